@@ -3,14 +3,64 @@
 // Only compiles when the "recast" feature is enabled.
 // Uses thirdparty/recastnavigation/ (self-contained within RustCode/)
 // to ensure binary compatibility with the C++ MoveMapGen output.
+//
+// When the library is available the `recast_available` cfg is set; the code
+// gates the FFI navmesh builder on that cfg rather than on the feature, so a
+// failed C++ build can fall back to the pure-Rust pipeline.
+//
+// Environment overrides (useful when cross-compiling, e.g. aarch64 or musl):
+//   RECAST_LIB_DIR        - link a pre-built librecastdetour.a from this directory
+//                           instead of compiling the C++ sources
+//   RECAST_FFI_BINDINGS   - path to pre-generated Rust bindings replacing
+//                           src/recast_ffi.rs (must match recast_wrapper.h)
+//   RECAST_STATIC_STDCXX  - 1 to link the C++ standard library statically
+//                           (default for musl targets)
+//   RECAST_REQUIRED       - 1 to fail the build instead of falling back when
+//                           the C++ toolchain is unavailable
+// The C++ compiler itself is chosen by the cc crate (CXX_<target>, CXXFLAGS_<target>).
 
 fn main() {
+    println!("cargo::rustc-check-cfg=cfg(recast_available)");
+    println!("cargo::rustc-check-cfg=cfg(recast_external_bindings)");
+
     #[cfg(feature = "recast")]
     build_recast_detour();
 }
 
+#[cfg(feature = "recast")]
+fn env_flag(name: &str) -> bool {
+    println!("cargo:rerun-if-env-changed={}", name);
+    std::env::var(name).is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
 #[cfg(feature = "recast")]
 fn build_recast_detour() {
+    let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let target_env = std::env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+    let target = std::env::var("TARGET").unwrap_or_default();
+    let host = std::env::var("HOST").unwrap_or_default();
+
+    let static_stdcxx = env_flag("RECAST_STATIC_STDCXX") || target_env == "musl";
+    let required = env_flag("RECAST_REQUIRED");
+
+    println!("cargo:rerun-if-env-changed=RECAST_FFI_BINDINGS");
+    if let Ok(bindings) = std::env::var("RECAST_FFI_BINDINGS") {
+        let path = std::fs::canonicalize(&bindings)
+            .unwrap_or_else(|e| panic!("RECAST_FFI_BINDINGS={}: {}", bindings, e));
+        println!("cargo:rustc-env=RECAST_FFI_BINDINGS={}", path.display());
+        println!("cargo:rustc-cfg=recast_external_bindings");
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    println!("cargo:rerun-if-env-changed=RECAST_LIB_DIR");
+    if let Ok(lib_dir) = std::env::var("RECAST_LIB_DIR") {
+        println!("cargo:rustc-link-search=native={}", lib_dir);
+        println!("cargo:rustc-link-lib=static=recastdetour");
+        link_cpp_stdlib(&target_os, &target_env, static_stdcxx);
+        println!("cargo:rustc-cfg=recast_available");
+        return;
+    }
+
     // Path relative to this crate's Cargo.toml (crates/extractors/)
     // Points to RustCode/thirdparty/recastnavigation/
     let recast_dir = std::path::Path::new("../../thirdparty/recastnavigation");
@@ -48,9 +98,14 @@ fn build_recast_detour() {
         .cpp(true)
         .std("c++14")
         .warnings(false)
+        .target(&target)
+        .host(&host)
         .include(recast_dir.join("Recast/Include"))
         .include(recast_dir.join("Detour/Include"));
 
+    // The C++ runtime is linked below so musl/static builds can pick the static variant
+    build.cpp_link_stdlib(None);
+
     // Add Recast sources
     for src in &recast_sources {
         build.file(recast_src.join(src));
@@ -67,11 +122,39 @@ fn build_recast_detour() {
     // Include the wrapper header directory
     build.include(".");
 
-    // Compile
-    build.compile("recastdetour");
-
     // Re-run if any source changes
     println!("cargo:rerun-if-changed=recast_wrapper.cpp");
     println!("cargo:rerun-if-changed=recast_wrapper.h");
     println!("cargo:rerun-if-changed=build.rs");
+
+    // Compile
+    match build.try_compile("recastdetour") {
+        Ok(()) => {
+            link_cpp_stdlib(&target_os, &target_env, static_stdcxx);
+            println!("cargo:rustc-cfg=recast_available");
+        }
+        Err(e) if !required => {
+            println!(
+                "cargo:warning=Recast/Detour could not be compiled for {} ({}); \
+                 falling back to the pure-Rust pipeline, navmesh tiles will not be built. \
+                 Set RECAST_LIB_DIR to link a pre-built library or RECAST_REQUIRED=1 to fail instead.",
+                target, e
+            );
+        }
+        Err(e) => panic!("Failed to compile Recast/Detour for {}: {}", target, e),
+    }
+}
+
+#[cfg(feature = "recast")]
+fn link_cpp_stdlib(target_os: &str, target_env: &str, static_stdcxx: bool) {
+    println!("cargo:rerun-if-env-changed=CXXSTDLIB");
+    let stdlib = match std::env::var("CXXSTDLIB") {
+        Ok(name) if name.is_empty() => return,
+        Ok(name) => name,
+        Err(_) if target_env == "msvc" => return,
+        Err(_) if matches!(target_os, "macos" | "ios" | "freebsd" | "openbsd") => "c++".to_string(),
+        Err(_) => "stdc++".to_string(),
+    };
+    let kind = if static_stdcxx { "static" } else { "dylib" };
+    println!("cargo:rustc-link-lib={}={}", kind, stdlib);
 }
@@ -5,22 +5,30 @@
 // - VMap assembler (contrib/vmap_assembler/vmap_assembler.cpp)
 // - MoveMapGen (contrib/mmap/src/generator.cpp)
 
+use std::process::ExitCode;
+
 use clap::{Args, Parser, Subcommand};
 
-mod dbc;
 mod map_dbc;
 #[allow(dead_code, unused_variables)]
 mod movemap_gen;
 mod mpq;
-#[cfg(feature = "recast")]
+#[cfg(all(recast_available, not(recast_external_bindings)))]
 #[allow(dead_code)]
 mod recast_ffi;
+#[cfg(all(recast_available, recast_external_bindings))]
+#[allow(dead_code, non_camel_case_types)]
+mod recast_ffi {
+    include!(env!("RECAST_FFI_BINDINGS"));
+}
+mod serve;
 #[allow(dead_code, unused_variables)]
 mod vmap_assemble;
 #[allow(dead_code, unused_variables)]
 mod vmap_extract;
 
-use mangos_shared::log::{initialize_logging, map_log_level};
+use mangos_shared::error::ExitStatus;
+use mangos_shared::log::{initialize_logging, map_log_level, LogOptions, LoggingHandle};
 
 /// Extractor selection bitmask
 const EXTRACT_MAP: u8 = 1;
@@ -51,6 +59,8 @@ enum Command {
     VmapAssemble(VmapAssembleArgs),
     /// MoveMap generator (C++: MoveMapGen)
     MoveMapGen(MoveMapGenArgs),
+    /// Watch extracted data and serve a validation status endpoint
+    Serve(ServeArgs),
 }
 
 #[derive(Args, Debug)]
@@ -105,6 +115,10 @@ struct VmapExtractArgs {
     /// Number of threads to use
     #[arg(long = "threads")]
     threads: Option<usize>,
+
+    /// Also write a JSON catalog of WMO group names, IDs, flags and liquid types
+    #[arg(long = "wmo-catalog", value_name = "FILE")]
+    wmo_catalog: Option<String>,
 }
 
 #[derive(Args, Debug)]
@@ -154,6 +168,10 @@ struct MoveMapGenArgs {
     #[arg(long = "skipLiquid")]
     skip_liquid: bool,
 
+    /// Include deep (ocean) water as swimmable navmesh, flagged separately from shallow water
+    #[arg(long = "includeDeepWater", conflicts_with = "skip_liquid")]
+    include_deep_water: bool,
+
     /// Skip continents
     #[arg(long = "skipContinents")]
     skip_continents: bool,
@@ -205,11 +223,30 @@ struct MoveMapGenArgs {
     /// Custom path to mmaps output directory (overrides workdir/mmaps)
     #[arg(long = "mmapsDir")]
     mmaps_dir: Option<String>,
+
+    /// Warn when a tile has more polygons than this (0 = no limit)
+    #[arg(long = "polyBudget", default_value_t = 16384)]
+    poly_budget: u32,
 }
 
-fn init_logging(log_level: Option<i32>) {
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Data directory containing maps/, vmaps/ and mmaps/
+    #[arg(long = "watch", default_value = ".")]
+    watch: String,
+
+    /// Address for the HTTP status endpoint
+    #[arg(long = "bind", default_value = "127.0.0.1:8095")]
+    bind: String,
+
+    /// Seconds between directory scans
+    #[arg(long = "interval", default_value_t = 5)]
+    interval: u64,
+}
+
+fn init_logging(log_level: Option<i32>) -> LoggingHandle {
     let console_level = map_log_level(log_level.unwrap_or(2));
-    initialize_logging(None, console_level, None);
+    initialize_logging(None, console_level, None, &LogOptions::default())
 }
 
 #[allow(dead_code)]
@@ -249,6 +286,10 @@ fn run_movemap_gen(args: MoveMapGenArgs) -> anyhow::Result<()> {
         .threads
         .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
 
+    if !cfg!(recast_available) {
+        tracing::warn!("MoveMapGen: built without Recast/Detour, using the pure-Rust pipeline (navmesh tiles are not generated)");
+    }
+
     tracing::info!(
         "MoveMapGen: workdir='{}' tile={:?} maps={:?} threads={} debug={} silent={} build_game_objects={}",
         args.workdir,
@@ -263,15 +304,32 @@ fn run_movemap_gen(args: MoveMapGenArgs) -> anyhow::Result<()> {
     movemap_gen::run_movemap_gen(&args)
 }
 
-fn main() -> anyhow::Result<()> {
+/// Exit codes follow mangos_shared::error::ExitStatus; a run that completes
+/// but logged warnings (skipped maps, models, tiles...) exits with PartialSuccess
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
-    init_logging(cli.log_level);
+    let logging = init_logging(cli.log_level);
 
-    match cli.command {
+    let result = match cli.command {
         Command::MapDbc(args) => run_map_dbc(args),
         Command::VmapExtract(args) => run_vmap_extract(args),
         Command::VmapAssemble(args) => run_vmap_assemble(args),
         Command::MoveMapGen(args) => run_movemap_gen(args),
-    }
+        Command::Serve(args) => serve::run_serve(args),
+    };
+
+    let status = match result {
+        Ok(()) if logging.warnings() > 0 => {
+            tracing::warn!("Finished with {} warning(s)", logging.warnings());
+            ExitStatus::PartialSuccess
+        }
+        Ok(()) => ExitStatus::Success,
+        Err(e) => {
+            tracing::error!("{:#}", e);
+            ExitStatus::from_error(&e)
+        }
+    };
+    logging.shutdown();
+    status.into()
 }
@@ -3,12 +3,13 @@ use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use byteorder::{LittleEndian, WriteBytesExt};
+use mangos_shared::dbc::DbcFile;
+use mangos_shared::error::MangosError;
 use rayon::prelude::*;
 use wow_adt::chunks::mh2o::VertexDataArray;
 use wow_adt::{parse_adt, ParsedAdt};
 use wow_wdt::{version::WowVersion, WdtReader};
 
-use crate::dbc::DbcFile;
 use crate::mpq::{build_path, MpqManager};
 use crate::MapDbcArgs;
 
@@ -37,8 +38,8 @@ const ADT_CELL_SIZE: usize = 8;
 const ADT_GRID_SIZE: usize = ADT_CELLS_PER_GRID * ADT_CELL_SIZE;
 const WDT_MAP_SIZE: usize = 64;
 
-const MAP_MAGIC: u32 = u32::from_le_bytes(*b"MAPS");
-const MAP_VERSION_MAGIC: u32 = u32::from_le_bytes(*b"s1.4");
+pub(crate) const MAP_MAGIC: u32 = u32::from_le_bytes(*b"MAPS");
+pub(crate) const MAP_VERSION_MAGIC: u32 = u32::from_le_bytes(*b"s1.4");
 const MAP_AREA_MAGIC: u32 = u32::from_le_bytes(*b"AREA");
 const MAP_HEIGHT_MAGIC: u32 = u32::from_le_bytes(*b"MHGT");
 const MAP_LIQUID_MAGIC: u32 = u32::from_le_bytes(*b"MLIQ");
@@ -68,7 +69,7 @@ const CONF_FLOAT_TO_INT16_LIMIT: f32 = 2048.0;
 const CONF_FLAT_HEIGHT_DELTA_LIMIT: f32 = 0.005;
 const CONF_FLAT_LIQUID_DELTA_LIMIT: f32 = 0.001;
 
-const GRID_MAP_FILE_HEADER_SIZE: u32 = 40;
+pub(crate) const GRID_MAP_FILE_HEADER_SIZE: u32 = 40;
 const GRID_MAP_AREA_HEADER_SIZE: u32 = 8;
 const GRID_MAP_HEIGHT_HEADER_SIZE: u32 = 16;
 const GRID_MAP_LIQUID_HEADER_SIZE: u32 = 16;
@@ -94,12 +95,12 @@ fn ensure_dir(path: &Path) -> anyhow::Result<()> {
 
 pub fn run_map_dbc(args: MapDbcArgs, threads: usize) -> anyhow::Result<()> {
     if args.extract_mask == 0 || args.extract_mask > (EXTRACT_MAP | EXTRACT_DBC | EXTRACT_CAMERA) {
-        anyhow::bail!("Invalid extract mask: {}", args.extract_mask);
+        anyhow::bail!(MangosError::Usage(format!("Invalid extract mask: {}", args.extract_mask)));
     }
 
     let input_path = Path::new(&args.input_path);
     if !input_path.exists() {
-        anyhow::bail!("Input path does not exist: {}", args.input_path);
+        anyhow::bail!(MangosError::Data(format!("Input path does not exist: {}", args.input_path)));
     }
 
     let output_path = Path::new(&args.output_path);
@@ -11,7 +11,9 @@ use std::sync::Arc;
 
 use anyhow::{bail, Context};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-#[cfg(feature = "recast")]
+use mangos_shared::error::MangosError;
+use mangos_shared::geometry::{grid_bounds, MAP_HALFSIZE, SIZE_OF_GRIDS};
+#[cfg(recast_available)]
 use crate::recast_ffi;
 use tracing::{debug, error, info, warn};
 
@@ -23,7 +25,7 @@ use tracing::{debug, error, info, warn};
 const BASE_UNIT_DIM: f32 = 0.266_666_6;
 
 /// Grid size in world units (one ADT tile)
-const GRID_SIZE: f32 = 533.333_3;
+const GRID_SIZE: f32 = SIZE_OF_GRIDS;
 
 /// Grid part size (one V8 cell)
 const GRID_PART_SIZE: f32 = GRID_SIZE / V8_SIZE as f32;
@@ -75,10 +77,16 @@ const NAV_AREA_MIN_VALUE: u8 = NAV_AREA_MAGMA_SLIME;
 const NAV_AREA_ALL_MASK: u8 = 0x3F;
 
 const NAV_GROUND: u16 = 1 << (NAV_AREA_MAX_VALUE - NAV_AREA_GROUND);
+const NAV_WATER: u16 = 1 << (NAV_AREA_MAX_VALUE - NAV_AREA_WATER);
+/// Extra polygon flag for deep (ocean) water surfaces, on top of NAV_WATER
+const NAV_DEEP_WATER: u16 = 1 << 4;
+/// Build-time area for deep water; written out as NAV_AREA_WATER + NAV_DEEP_WATER.
+/// Kept distinct during the build so deep and shallow water never share a region.
+const NAV_AREA_DEEP_WATER: u8 = NAV_AREA_MAX_VALUE + 1;
 
 // MMAP file format
-const MMAP_MAGIC: u32 = 0x4d4d_4150; // 'MMAP'
-const MMAP_VERSION: u32 = 8;
+pub(crate) const MMAP_MAGIC: u32 = 0x4d4d_4150; // 'MMAP'
+pub(crate) const MMAP_VERSION: u32 = 8;
 
 // Hole lookup tables
 const HOLETAB_H: [u16; 4] = [0x1111, 0x2222, 0x4444, 0x8888];
@@ -155,8 +163,11 @@ struct VmapLiquidData {
 // TerrainBuilder
 // ============================================================================
 
+#[derive(Clone)]
 struct TerrainBuilder {
     skip_liquid: bool,
+    /// Keep deep water surfaces (swimmable ocean) instead of dropping them
+    include_deep_water: bool,
     maps_dir: PathBuf,
     vmaps_dir: PathBuf,
 }
@@ -165,6 +176,7 @@ impl TerrainBuilder {
     fn new(skip_liquid: bool, maps_dir: &Path, vmaps_dir: &Path) -> Self {
         Self {
             skip_liquid,
+            include_deep_water: false,
             maps_dir: maps_dir.to_path_buf(),
             vmaps_dir: vmaps_dir.to_path_buf(),
         }
@@ -300,8 +312,9 @@ impl TerrainBuilder {
             }
 
             let count = (mesh_data.solid_verts.len() / 3) as i32;
-            let xoffset = (tile_x as f32 - 32.0) * GRID_SIZE;
-            let yoffset = (tile_y as f32 - 32.0) * GRID_SIZE;
+            // Height maps start at the grid's upper corner
+            let xoffset = -grid_bounds(tile_x).1;
+            let yoffset = -grid_bounds(tile_y).1;
 
             // V9 vertices
             for i in 0..V9_SIZE_SQ {
@@ -387,8 +400,8 @@ impl TerrainBuilder {
             }
 
             let count = (mesh_data.liquid_verts.len() / 3) as i32;
-            let xoffset = (tile_x as f32 - 32.0) * GRID_SIZE;
-            let yoffset = (tile_y as f32 - 32.0) * GRID_SIZE;
+            let xoffset = -grid_bounds(tile_x).1;
+            let yoffset = -grid_bounds(tile_y).1;
 
             // Generate liquid vertices
             if let Some(ref lmap) = liquid_map {
@@ -476,8 +489,13 @@ impl TerrainBuilder {
                 } else {
                     liquid_type_val = get_liquid_type(i, &liquid_flags);
                     if (liquid_type_val & MAP_LIQUID_TYPE_DEEP_WATER) != 0 {
+                        // The sea floor below deep water is never reachable
                         use_terrain = false;
-                        use_liquid = false;
+                        if self.include_deep_water {
+                            liquid_type_val = NAV_AREA_DEEP_WATER;
+                        } else {
+                            use_liquid = false;
+                        }
                     } else if (liquid_type_val
                         & (MAP_LIQUID_TYPE_WATER | MAP_LIQUID_TYPE_OCEAN))
                         != 0
@@ -672,8 +690,8 @@ impl TerrainBuilder {
                 std::f32::consts::PI * spawn.rot[1] / -180.0,
             );
             let mut position = spawn.pos;
-            position[0] -= 32.0 * GRID_SIZE;
-            position[1] -= 32.0 * GRID_SIZE;
+            position[0] -= MAP_HALFSIZE;
+            position[1] -= MAP_HALFSIZE;
 
             for group in &world_model.groups {
                 // Transform and add solid mesh
@@ -989,7 +1007,7 @@ struct RcConfig {
 const DT_VERTS_PER_POLYGON: u32 = 6;
 
 /// DT_NAVMESH_VERSION - matches the version from the bundled Detour
-const DT_NAVMESH_VERSION_CONST: u32 = 7;
+pub(crate) const DT_NAVMESH_VERSION_CONST: u32 = 7;
 
 /// DT_POLY_BITS
 const DT_POLY_BITS: u32 = 20;
@@ -1012,8 +1030,22 @@ struct MapBuilder {
     config: Option<serde_json::Value>,
     map_done: BTreeSet<u32>,
     threads: usize,
+    /// Tiles with more polygons than this are reported (0 = no limit)
+    poly_budget: u32,
 }
 
+/// Size and geometry statistics of one written .mmtile
+struct TileStats {
+    tile_x: u32,
+    tile_y: u32,
+    bytes: u64,
+    polys: u32,
+    verts: u32,
+}
+
+/// Number of largest tiles listed in the per-map report
+const REPORT_LARGEST_TILES: usize = 5;
+
 impl MapBuilder {
     #[allow(clippy::too_many_arguments)]
     fn new(
@@ -1051,6 +1083,7 @@ impl MapBuilder {
             config,
             map_done: BTreeSet::new(),
             threads,
+            poly_budget: 0,
         };
 
         builder.discover_tiles();
@@ -1198,11 +1231,7 @@ impl MapBuilder {
         // Build tiles using thread pool
         let tile_count = tiles.len() as u32;
         let mmaps_dir = self.mmaps_dir.clone();
-        let terrain_builder = Arc::new(TerrainBuilder::new(
-            self.terrain_builder.skip_liquid,
-            &self.maps_dir,
-            &self.vmaps_dir,
-        ));
+        let terrain_builder = Arc::new(self.terrain_builder.clone());
         let off_mesh_path = self.off_mesh_file_path.clone();
         let debug = self.debug;
         let config_json = self.config.clone();
@@ -1246,6 +1275,69 @@ impl MapBuilder {
                 }
             }
         }
+
+        self.report_map_output(map_id);
+    }
+
+    /// Summarize the .mmtile files written for a map and flag oversized tiles
+    fn report_map_output(&self, map_id: u32) {
+        let prefix = format!("{:03}", map_id);
+        let Ok(entries) = fs::read_dir(&self.mmaps_dir) else {
+            return;
+        };
+
+        let mut stats: Vec<TileStats> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let coords = name.strip_prefix(&prefix)?.strip_suffix(".mmtile")?;
+                if coords.len() != 4 {
+                    return None;
+                }
+                let tile_y = coords[0..2].parse().ok()?;
+                let tile_x = coords[2..4].parse().ok()?;
+                read_tile_stats(&entry.path(), tile_x, tile_y)
+            })
+            .collect();
+
+        if stats.is_empty() {
+            info!("[Map {:03}] No tiles written.", map_id);
+            return;
+        }
+
+        let total_bytes: u64 = stats.iter().map(|t| t.bytes).sum();
+        let total_polys: u64 = stats.iter().map(|t| t.polys as u64).sum();
+        let total_verts: u64 = stats.iter().map(|t| t.verts as u64).sum();
+        info!(
+            "[Map {:03}] Output: {} tiles, {:.2} MB, {} polygons, {} vertices",
+            map_id,
+            stats.len(),
+            total_bytes as f64 / (1024.0 * 1024.0),
+            total_polys,
+            total_verts
+        );
+
+        if self.poly_budget > 0 {
+            for tile in stats.iter().filter(|t| t.polys > self.poly_budget) {
+                warn!(
+                    "[Map {:03}] Tile [{:02},{:02}] has {} polygons (budget {}), check for degenerate input geometry",
+                    map_id, tile.tile_x, tile.tile_y, tile.polys, self.poly_budget
+                );
+            }
+        }
+
+        stats.sort_by_key(|t| std::cmp::Reverse(t.bytes));
+        for tile in stats.iter().take(REPORT_LARGEST_TILES) {
+            info!(
+                "[Map {:03}]   largest: tile [{:02},{:02}] {} KB, {} polygons, {} vertices",
+                map_id,
+                tile.tile_x,
+                tile.tile_y,
+                tile.bytes / 1024,
+                tile.polys,
+                tile.verts
+            );
+        }
     }
 
     /// Build tile (single-threaded path used for build_single_tile)
@@ -1258,11 +1350,7 @@ impl MapBuilder {
         cur_tile: u32,
         tile_count: u32,
     ) {
-        let tb = TerrainBuilder::new(
-            self.terrain_builder.skip_liquid,
-            &self.maps_dir,
-            &self.vmaps_dir,
-        );
+        let tb = self.terrain_builder.clone();
         build_tile_worker(
             map_id, tile_x, tile_y, nav_mesh_params, cur_tile, tile_count,
             &self.mmaps_dir, &tb, self.off_mesh_file_path.as_deref(),
@@ -1463,6 +1551,27 @@ struct NavMeshParams {
     max_polys: i32,
 }
 
+/// Read polygon/vertex counts from a written .mmtile
+/// Layout: MmapTileHeader (20 bytes) followed by dtMeshHeader, whose
+/// polyCount and vertCount are the 7th and 8th 32-bit fields.
+fn read_tile_stats(path: &Path, tile_x: u32, tile_y: u32) -> Option<TileStats> {
+    let mut file = fs::File::open(path).ok()?;
+    let bytes = file.metadata().ok()?.len();
+    let mut header = [0u8; 20 + 32];
+    file.read_exact(&mut header).ok()?;
+    let word = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+    if word(0) != MMAP_MAGIC {
+        return None;
+    }
+    Some(TileStats {
+        tile_x,
+        tile_y,
+        bytes,
+        polys: word(20 + 24),
+        verts: word(20 + 28),
+    })
+}
+
 fn write_nav_mesh_params(path: &Path, params: &NavMeshParams) -> anyhow::Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
@@ -1594,7 +1703,7 @@ fn build_move_map_tile(
     // This is where we'd call the actual Recast FFI functions.
     // For now, we build the navmesh data using safe abstractions over the FFI.
 
-    #[cfg(feature = "recast")]
+    #[cfg(recast_available)]
     unsafe {
         build_move_map_tile_unsafe(
             &tile_string,
@@ -1608,10 +1717,11 @@ fn build_move_map_tile(
         );
     }
 
-    #[cfg(not(feature = "recast"))]
+    #[cfg(not(recast_available))]
     {
         warn!(
-            "{}: Recast FFI not available (build with --features recast). \
+            "{}: Recast FFI not available (build with --features recast and a working C++ toolchain, \
+             or set RECAST_LIB_DIR). \
              Terrain data loaded ({} solid verts, {} liquid verts) but navmesh not built.",
             tile_string,
             mesh_data.solid_verts.len() / 3,
@@ -1620,7 +1730,7 @@ fn build_move_map_tile(
     }
 }
 
-#[cfg(feature = "recast")]
+#[cfg(recast_available)]
 /// Core Recast/Detour tile building - requires unsafe for FFI
 #[allow(clippy::too_many_arguments)]
 unsafe fn build_move_map_tile_unsafe(
@@ -1751,7 +1861,10 @@ unsafe fn build_move_map_tile_unsafe(
     for i in 0..pm_data.npolys as usize {
         let area = pm_data.areas.add(i).read() & NAV_AREA_ALL_MASK;
         if area != 0 {
-            if area >= NAV_AREA_MIN_VALUE {
+            if area == NAV_AREA_DEEP_WATER {
+                pm_data.areas.add(i).write(NAV_AREA_WATER);
+                pm_data.flags.add(i).write(NAV_WATER | NAV_DEEP_WATER);
+            } else if area >= NAV_AREA_MIN_VALUE {
                 pm_data.flags.add(i).write(
                     1u16 << (NAV_AREA_MAX_VALUE - area),
                 );
@@ -1885,7 +1998,7 @@ unsafe fn build_move_map_tile_unsafe(
     } // unsafe
 }
 
-#[cfg(feature = "recast")]
+#[cfg(recast_available)]
 /// Build a common tile using the Recast pipeline (unsafe FFI)
 #[allow(clippy::too_many_arguments)]
 unsafe fn build_common_tile_recast(
@@ -2082,7 +2195,7 @@ unsafe fn build_common_tile_recast(
 // Helper functions
 // ============================================================================
 
-#[cfg(feature = "recast")]
+#[cfg(recast_available)]
 /// Mark triangles with slopes between 50-60 degrees as steep
 fn rc_mod_almost_unwalkable_triangles(
     walkable_slope_angle: f32,
@@ -2141,7 +2254,7 @@ fn rc_mod_almost_unwalkable_triangles(
     }
 }
 
-#[cfg(feature = "recast")]
+#[cfg(recast_available)]
 /// Create a C-compatible rcConfig struct from our config
 fn create_rc_config_c(config: &RcConfig) -> recast_ffi::RcConfigC {
     recast_ffi::RcConfigC {
@@ -2311,10 +2424,8 @@ fn get_tile_bounds(tile_x: u32, tile_y: u32, verts: &[f32], vert_count: usize) -
     }
 
     // Width and depth from tile coordinates
-    bmax[0] = (32 - tile_x as i32) as f32 * GRID_SIZE;
-    bmax[2] = (32 - tile_y as i32) as f32 * GRID_SIZE;
-    bmin[0] = bmax[0] - GRID_SIZE;
-    bmin[2] = bmax[2] - GRID_SIZE;
+    (bmin[0], bmax[0]) = grid_bounds(tile_x);
+    (bmin[2], bmax[2]) = grid_bounds(tile_y);
 
     (bmin, bmax)
 }
@@ -2631,10 +2742,10 @@ pub fn run_movemap_gen(args: &super::MoveMapGenArgs) -> anyhow::Result<()> {
 
     // Validate input directories exist
     if !maps_dir.exists() {
-        bail!("Maps directory does not exist: {}", maps_dir.display());
+        bail!(MangosError::Data(format!("Maps directory does not exist: {}", maps_dir.display())));
     }
     if !vmaps_dir.exists() {
-        bail!("VMaps directory does not exist: {}", vmaps_dir.display());
+        bail!(MangosError::Data(format!("VMaps directory does not exist: {}", vmaps_dir.display())));
     }
 
     // Ensure mmaps output directory exists
@@ -2668,13 +2779,18 @@ pub fn run_movemap_gen(args: &super::MoveMapGenArgs) -> anyhow::Result<()> {
         &vmaps_dir,
         &mmaps_dir,
     );
+    builder.poly_budget = args.poly_budget;
+    builder.terrain_builder.include_deep_water = args.include_deep_water;
+    if args.include_deep_water {
+        info!("Including deep water surfaces as swimmable NAV_AREA_WATER (flag NAV_DEEP_WATER)");
+    }
 
     if let Some(ref tile) = args.tile {
         if let Some(&map_id) = args.map_ids.first() {
             info!("Building single tile: map={}, tile={},{}", map_id, tile.x, tile.y);
             builder.build_single_tile(map_id, tile.x as u32, tile.y as u32);
         } else {
-            bail!("Map ID required for --tile option");
+            bail!(MangosError::Usage("Map ID required for --tile option".into()));
         }
     } else {
         let map_ids: Vec<u32> = args.map_ids.clone();
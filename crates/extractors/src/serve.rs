@@ -0,0 +1,397 @@
+// serve.rs - Long-running data validation service
+//
+// Watches the extracted maps/vmaps/mmaps output directories, re-verifies any
+// file whose size or modification time changed, and exposes a small HTTP status
+// endpoint summarizing data health. Aimed at hosts that rsync data updates onto
+// live servers and want immediate validation.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use mangos_shared::error::MangosError;
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use crate::map_dbc::{GRID_MAP_FILE_HEADER_SIZE, MAP_MAGIC, MAP_VERSION_MAGIC};
+use crate::movemap_gen::{DT_NAVMESH_VERSION_CONST, MMAP_MAGIC, MMAP_VERSION};
+use crate::vmap_assemble::VMAP_MAGIC;
+use crate::ServeArgs;
+
+/// Output subdirectories watched below the data directory
+const WATCHED_DIRS: [&str; 3] = ["maps", "vmaps", "mmaps"];
+
+/// Maximum number of invalid files listed per category in the status output
+const MAX_REPORTED_INVALID: usize = 50;
+
+const MAP_HEADER_SIZE: u64 = GRID_MAP_FILE_HEADER_SIZE as u64;
+/// Magic, Detour version, mmap version, data size and flags ahead of a tile
+const MMAP_TILE_HEADER_SIZE: u64 = 20;
+/// dtNavMeshParams as written to .mmap files
+const MMAP_PARAMS_SIZE: u64 = 28;
+
+/// Result of verifying a single file
+#[derive(Debug, Clone, PartialEq)]
+enum FileStatus {
+    Valid,
+    Invalid(String),
+}
+
+/// Last known state of a watched file
+struct FileRecord {
+    modified: SystemTime,
+    len: u64,
+    status: FileStatus,
+}
+
+/// Health summary for one output category
+#[derive(Debug, Default, Clone, Serialize)]
+struct CategoryStatus {
+    total: usize,
+    valid: usize,
+    invalid: usize,
+    invalid_files: Vec<InvalidFile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InvalidFile {
+    path: String,
+    reason: String,
+}
+
+/// Snapshot served by the status endpoint
+#[derive(Debug, Default, Clone, Serialize)]
+struct DataStatus {
+    data_dir: String,
+    healthy: bool,
+    last_scan: u64,
+    scans: u64,
+    categories: BTreeMap<String, CategoryStatus>,
+}
+
+pub fn run_serve(args: ServeArgs) -> anyhow::Result<()> {
+    let data_dir = PathBuf::from(&args.watch);
+    if !data_dir.is_dir() {
+        anyhow::bail!(MangosError::Data(format!("Data directory {} does not exist", data_dir.display())));
+    }
+
+    let status = Arc::new(Mutex::new(DataStatus {
+        data_dir: data_dir.display().to_string(),
+        ..DataStatus::default()
+    }));
+
+    let listener = TcpListener::bind(&args.bind)?;
+    info!("Serve: watching '{}' every {}s, status endpoint on http://{}/status",
+        data_dir.display(), args.interval, args.bind);
+
+    let http_status = status.clone();
+    std::thread::spawn(move || serve_http(listener, http_status));
+
+    let mut records: HashMap<PathBuf, FileRecord> = HashMap::new();
+    let interval = Duration::from_secs(args.interval.max(1));
+
+    loop {
+        scan(&data_dir, &mut records);
+
+        let summary = summarize(&data_dir, &records);
+        {
+            let mut current = status.lock().unwrap();
+            let scans = current.scans + 1;
+            *current = DataStatus {
+                scans,
+                ..summary
+            };
+        }
+
+        std::thread::sleep(interval);
+    }
+}
+
+/// Walk the watched directories, re-verifying new or changed files
+fn scan(data_dir: &Path, records: &mut HashMap<PathBuf, FileRecord>) {
+    let mut seen = HashSet::new();
+
+    for dir in WATCHED_DIRS {
+        let path = data_dir.join(dir);
+        let entries = match std::fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let file_path = entry.path();
+            let meta = match entry.metadata() {
+                Ok(meta) if meta.is_file() => meta,
+                _ => continue,
+            };
+            if category_of(&file_path).is_none() {
+                continue;
+            }
+
+            let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+            let len = meta.len();
+            seen.insert(file_path.clone());
+
+            let unchanged = records
+                .get(&file_path)
+                .is_some_and(|r| r.modified == modified && r.len == len);
+            if unchanged {
+                continue;
+            }
+
+            let status = match validate_file(&file_path) {
+                Ok(()) => FileStatus::Valid,
+                Err(reason) => FileStatus::Invalid(reason),
+            };
+
+            match &status {
+                FileStatus::Valid => debug!("Serve: {} verified", file_path.display()),
+                FileStatus::Invalid(reason) => warn!("Serve: {} is invalid: {}", file_path.display(), reason),
+            }
+
+            records.insert(file_path, FileRecord { modified, len, status });
+        }
+    }
+
+    let before = records.len();
+    records.retain(|path, _| seen.contains(path));
+    if records.len() != before {
+        info!("Serve: {} file(s) removed since last scan", before - records.len());
+    }
+}
+
+/// Build the health summary from the current records
+fn summarize(data_dir: &Path, records: &HashMap<PathBuf, FileRecord>) -> DataStatus {
+    let mut categories: BTreeMap<String, CategoryStatus> = WATCHED_DIRS
+        .iter()
+        .map(|d| (d.to_string(), CategoryStatus::default()))
+        .collect();
+
+    for (path, record) in records {
+        let Some(category) = category_of(path) else {
+            continue;
+        };
+        let entry = categories.entry(category.to_string()).or_default();
+        entry.total += 1;
+        match &record.status {
+            FileStatus::Valid => entry.valid += 1,
+            FileStatus::Invalid(reason) => {
+                entry.invalid += 1;
+                if entry.invalid_files.len() < MAX_REPORTED_INVALID {
+                    entry.invalid_files.push(InvalidFile {
+                        path: path.display().to_string(),
+                        reason: reason.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    for category in categories.values_mut() {
+        category.invalid_files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    DataStatus {
+        data_dir: data_dir.display().to_string(),
+        healthy: categories.values().all(|c| c.invalid == 0),
+        last_scan: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        scans: 0,
+        categories,
+    }
+}
+
+/// Map a file to its output category by extension
+fn category_of(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()? {
+        "map" => Some("maps"),
+        "vmtree" | "vmtile" | "vmo" => Some("vmaps"),
+        "mmap" | "mmtile" => Some("mmaps"),
+        _ => None,
+    }
+}
+
+/// Verify a single output file's header and size consistency
+fn validate_file(path: &Path) -> Result<(), String> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let mut file = std::fs::File::open(path).map_err(|e| format!("cannot open: {}", e))?;
+    let len = file.metadata().map_err(|e| format!("cannot stat: {}", e))?.len();
+
+    match ext {
+        "map" => {
+            if len < MAP_HEADER_SIZE {
+                return Err(format!("truncated header ({} bytes)", len));
+            }
+            let mut header = [0u8; MAP_HEADER_SIZE as usize];
+            file.read_exact(&mut header).map_err(|e| e.to_string())?;
+            let word = |i: usize| u32::from_le_bytes(header[i * 4..i * 4 + 4].try_into().unwrap());
+            if word(0) != MAP_MAGIC || word(1) != MAP_VERSION_MAGIC {
+                return Err("bad map magic/version".to_string());
+            }
+            // (offset, size) pairs for area, height, liquid and holes sections
+            for section in 0..4 {
+                let base = 8 + section * 8;
+                let offset = u32::from_le_bytes(header[base..base + 4].try_into().unwrap()) as u64;
+                let size = u32::from_le_bytes(header[base + 4..base + 8].try_into().unwrap()) as u64;
+                if offset > 0 && offset + size > len {
+                    return Err(format!("section {} exceeds file size", section));
+                }
+            }
+            Ok(())
+        }
+        "vmtree" | "vmtile" | "vmo" => {
+            let mut magic = [0u8; 8];
+            file.read_exact(&mut magic).map_err(|_| "truncated header".to_string())?;
+            if &magic[..] != VMAP_MAGIC.as_bytes() {
+                return Err("bad vmap magic".to_string());
+            }
+            Ok(())
+        }
+        "mmap" => {
+            if len != MMAP_PARAMS_SIZE {
+                return Err(format!("expected {} bytes of navmesh params, found {}", MMAP_PARAMS_SIZE, len));
+            }
+            Ok(())
+        }
+        "mmtile" => {
+            if len < MMAP_TILE_HEADER_SIZE {
+                return Err(format!("truncated header ({} bytes)", len));
+            }
+            let mut header = [0u8; MMAP_TILE_HEADER_SIZE as usize];
+            file.read_exact(&mut header).map_err(|e| e.to_string())?;
+            let word = |i: usize| u32::from_le_bytes(header[i * 4..i * 4 + 4].try_into().unwrap());
+            if word(0) != MMAP_MAGIC {
+                return Err("bad mmtile magic".to_string());
+            }
+            if word(1) != DT_NAVMESH_VERSION_CONST || word(2) != MMAP_VERSION {
+                return Err(format!("version mismatch (detour {}, mmap {})", word(1), word(2)));
+            }
+            if MMAP_TILE_HEADER_SIZE + word(3) as u64 != len {
+                return Err(format!("size field {} does not match file size {}", word(3), len));
+            }
+            Ok(())
+        }
+        _ => Err("unknown file type".to_string()),
+    }
+}
+
+/// Minimal HTTP/1.1 responder for the status endpoint
+fn serve_http(listener: TcpListener, status: Arc<Mutex<DataStatus>>) {
+    for stream in listener.incoming().flatten() {
+        if let Err(e) = handle_http(stream, &status) {
+            debug!("Serve: status request failed: {}", e);
+        }
+    }
+}
+
+fn handle_http(mut stream: TcpStream, status: &Arc<Mutex<DataStatus>>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+
+    let snapshot = status.lock().unwrap().clone();
+    let (code, body) = match path {
+        "/status" | "/" => ("200 OK", serde_json::to_string_pretty(&snapshot).unwrap_or_default()),
+        "/health" if snapshot.healthy => ("200 OK", "{\"healthy\":true}".to_string()),
+        "/health" => ("503 Service Unavailable", "{\"healthy\":false}".to_string()),
+        _ => ("404 Not Found", "{\"error\":\"not found\"}".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("extractors_serve_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    fn check(dir: &Path, name: &str, data: &[u8]) -> Result<(), String> {
+        let path = dir.join(name);
+        std::fs::write(&path, data).unwrap();
+        validate_file(&path)
+    }
+
+    #[test]
+    fn test_validate_file() {
+        let dir = temp_dir("validate");
+
+        // Header with the area section right behind it
+        let mut map = words(&[MAP_MAGIC, MAP_VERSION_MAGIC, 40, 8, 0, 0, 0, 0, 0, 0]);
+        map.extend_from_slice(&[0; 8]);
+        assert_eq!(check(&dir, "0003231.map", &map), Ok(()));
+        assert_eq!(check(&dir, "0003231.map", &map[..map.len() - 1]), Err("section 0 exceeds file size".into()));
+        assert_eq!(check(&dir, "0003231.map", &map[..20]), Err("truncated header (20 bytes)".into()));
+        map[4..8].copy_from_slice(b"s1.3");
+        assert_eq!(check(&dir, "0003231.map", &map), Err("bad map magic/version".into()));
+
+        assert_eq!(check(&dir, "000.vmtree", b"VMAP_7.0rest"), Ok(()));
+        assert_eq!(check(&dir, "000_32_31.vmtile", b"VMAP_6.0rest"), Err("bad vmap magic".into()));
+        assert_eq!(check(&dir, "000_32_31.vmtile", b"VMAP"), Err("truncated header".into()));
+
+        assert_eq!(check(&dir, "000.mmap", &[0; 28]), Ok(()));
+        assert!(check(&dir, "000.mmap", &[0; 27]).is_err());
+
+        let mut tile = words(&[MMAP_MAGIC, DT_NAVMESH_VERSION_CONST, MMAP_VERSION, 4, 0]);
+        tile.extend_from_slice(&[0; 4]);
+        assert_eq!(check(&dir, "0003231.mmtile", &tile), Ok(()));
+        assert_eq!(
+            check(&dir, "0003231.mmtile", &tile[..22]),
+            Err("size field 4 does not match file size 22".into())
+        );
+        tile[8..12].copy_from_slice(&(MMAP_VERSION - 1).to_le_bytes());
+        assert_eq!(
+            check(&dir, "0003231.mmtile", &tile),
+            Err(format!("version mismatch (detour {}, mmap {})", DT_NAVMESH_VERSION_CONST, MMAP_VERSION - 1))
+        );
+        tile[0] ^= 1;
+        assert_eq!(check(&dir, "0003231.mmtile", &tile), Err("bad mmtile magic".into()));
+
+        assert_eq!(check(&dir, "notes.txt", b""), Err("unknown file type".into()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_scan_tracks_changes_and_removals() {
+        let dir = temp_dir("scan");
+        std::fs::create_dir_all(dir.join("mmaps")).unwrap();
+        std::fs::write(dir.join("mmaps/000.mmap"), [0; 28]).unwrap();
+        std::fs::write(dir.join("mmaps/001.mmap"), [0; 28]).unwrap();
+        std::fs::write(dir.join("mmaps/readme.txt"), "not data").unwrap();
+
+        let mut records = HashMap::new();
+        scan(&dir, &mut records);
+        assert_eq!(records.len(), 2);
+        assert!(summarize(&dir, &records).healthy);
+
+        std::fs::write(dir.join("mmaps/000.mmap"), [0; 20]).unwrap();
+        std::fs::remove_file(dir.join("mmaps/001.mmap")).unwrap();
+        scan(&dir, &mut records);
+        assert_eq!(records.len(), 1);
+        let status = summarize(&dir, &records);
+        assert!(!status.healthy);
+        assert_eq!(status.categories["mmaps"].invalid, 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -5,11 +5,13 @@ use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use mangos_shared::error::MangosError;
+use mangos_shared::geometry::MAP_HALFSIZE;
 use rayon::prelude::*;
 
 use crate::VmapAssembleArgs;
 
-const VMAP_MAGIC: &str = "VMAP_7.0";
+pub(crate) const VMAP_MAGIC: &str = "VMAP_7.0";
 const RAW_VMAP_MAGIC: &str = "VMAPs05";
 const GAMEOBJECT_MODELS: &str = "temp_gameobject_models";
 
@@ -17,7 +19,7 @@ const MOD_M2: u32 = 1;
 const MOD_WORLDSPAWN: u32 = 1 << 1;
 const MOD_HAS_BOUND: u32 = 1 << 2;
 
-const WORLDSPAWN_OFFSET: f32 = 533.333_3 * 32.0;
+const WORLDSPAWN_OFFSET: f32 = MAP_HALFSIZE;
 
 #[derive(Clone, Copy, Debug, Default)]
 struct Vec3 {
@@ -223,7 +225,7 @@ pub fn run_vmap_assemble(args: VmapAssembleArgs, threads: usize) -> anyhow::Resu
 
     let raw_dir = Path::new(&args.raw_data_dir);
     if !raw_dir.exists() {
-        anyhow::bail!("Raw data directory does not exist: {}", args.raw_data_dir);
+        anyhow::bail!(MangosError::Data(format!("Raw data directory does not exist: {}", args.raw_data_dir)));
     }
 
     let output_dir = PathBuf::from(&args.output_dir);
@@ -576,11 +578,11 @@ fn read_raw_model(path: &Path) -> anyhow::Result<RawModel> {
 
 fn parse_raw_model_with_header(data: &[u8], header_len: usize) -> anyhow::Result<RawModel> {
     if data.len() < header_len + 12 {
-        anyhow::bail!("Raw vmap file too small");
+        anyhow::bail!(MangosError::Data("Raw vmap file too small".into()));
     }
 
     if &data[..7] != RAW_VMAP_MAGIC.as_bytes() {
-        anyhow::bail!("Invalid raw vmap magic");
+        anyhow::bail!(MangosError::Data("Invalid raw vmap magic".into()));
     }
 
     let mut cursor = std::io::Cursor::new(data);
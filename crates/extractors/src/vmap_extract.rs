@@ -5,10 +5,12 @@ use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use mangos_shared::dbc::DbcFile;
+use mangos_shared::error::MangosError;
+use mangos_shared::geometry::MAP_HALFSIZE;
 use wow_adt::{parse_adt, ParsedAdt};
 use wow_wdt::{version::WowVersion, WdtReader};
 
-use crate::dbc::DbcFile;
 use crate::mpq::{build_path, MpqManager};
 use crate::VmapExtractArgs;
 
@@ -164,12 +166,37 @@ struct VmapContext {
     wmo_doodads: HashMap<String, WmoDoodadData>,
     failed_paths: HashSet<String>,
     all_files: std::collections::BTreeSet<String>,
+    /// Collected only when a WMO catalog was requested
+    wmo_catalog: Option<Vec<WmoCatalogEntry>>,
+}
+
+/// One WMO root model in the group catalog
+#[derive(Debug, serde::Serialize)]
+struct WmoCatalogEntry {
+    model: String,
+    root_wmo_id: u32,
+    groups: Vec<WmoGroupCatalogEntry>,
+}
+
+/// One WMO group as read from MOGP, with its MOGN names resolved
+#[derive(Debug, serde::Serialize)]
+struct WmoGroupCatalogEntry {
+    index: u32,
+    group_wmo_id: u32,
+    name: Option<String>,
+    description: Option<String>,
+    flags: u32,
+    indoor: bool,
+    liquid_type: u32,
+    has_liquid: bool,
+    /// Not written to the vmap (antiportal or unreachable group)
+    skipped: bool,
 }
 
 pub fn run_vmap_extract(args: VmapExtractArgs, _threads: usize) -> anyhow::Result<()> {
     let data_path = Path::new(&args.data_path);
     if !data_path.exists() {
-        anyhow::bail!("Data path does not exist: {}", args.data_path);
+        anyhow::bail!(MangosError::Data(format!("Data path does not exist: {}", args.data_path)));
     }
 
     let output_root = PathBuf::from(&args.output_path);
@@ -181,7 +208,9 @@ pub fn run_vmap_extract(args: VmapExtractArgs, _threads: usize) -> anyhow::Resul
     let dirty_dir = buildings_dir.join("dir");
     let dirty_dir_bin = buildings_dir.join(DIR_BIN);
     if dirty_dir.exists() || dirty_dir_bin.exists() {
-        anyhow::bail!("Your output directory seems to be polluted, please use an empty directory!");
+        anyhow::bail!(MangosError::Usage(
+            "Your output directory seems to be polluted, please use an empty directory!".into()
+        ));
     }
 
     if !buildings_dir.exists() {
@@ -198,10 +227,10 @@ pub fn run_vmap_extract(args: VmapExtractArgs, _threads: usize) -> anyhow::Resul
     }
 
     if mpq.list_files().is_empty() {
-        anyhow::bail!(
+        anyhow::bail!(MangosError::Data(format!(
             "FATAL ERROR: None MPQ archive found by path '{}'. Use -d option with proper path.",
             args.data_path
-        );
+        )));
     }
 
     let all_files = mpq.list_files();
@@ -215,6 +244,7 @@ pub fn run_vmap_extract(args: VmapExtractArgs, _threads: usize) -> anyhow::Resul
         wmo_doodads: HashMap::new(),
         failed_paths: HashSet::new(),
         all_files,
+        wmo_catalog: args.wmo_catalog.as_ref().map(|_| Vec::new()),
     };
 
     tracing::info!("Extract for VMAPs05. Beginning work ....");
@@ -226,6 +256,14 @@ pub fn run_vmap_extract(args: VmapExtractArgs, _threads: usize) -> anyhow::Resul
 
     extract_gameobject_models(&mut context)?;
 
+    if let (Some(path), Some(mut catalog)) = (&args.wmo_catalog, context.wmo_catalog.take()) {
+        catalog.sort_by(|a, b| a.model.cmp(&b.model));
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Cannot create WMO catalog {}", path))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &catalog)?;
+        tracing::info!("Wrote WMO catalog with {} models to {}", catalog.len(), path);
+    }
+
     if !context.failed_paths.is_empty() {
         tracing::warn!("Some models could not be extracted:");
         for path in &context.failed_paths {
@@ -783,6 +821,7 @@ fn extract_single_wmo(context: &mut VmapContext, fname: &mut str) -> anyhow::Res
 
     let mut total_triangles = 0u32;
     let mut real_groups = root.n_groups;
+    let mut catalog_groups = Vec::new();
 
     for idx in 0..root.n_groups {
         let mut group_name = fname.to_owned();
@@ -797,7 +836,12 @@ fn extract_single_wmo(context: &mut VmapContext, fname: &mut str) -> anyhow::Res
             continue;
         };
 
-        if group.should_skip(&root) {
+        let skipped = group.should_skip(&root);
+        if context.wmo_catalog.is_some() {
+            catalog_groups.push(group.catalog_entry(idx, &root, skipped));
+        }
+
+        if skipped {
             real_groups = real_groups.saturating_sub(1);
             continue;
         }
@@ -826,6 +870,14 @@ fn extract_single_wmo(context: &mut VmapContext, fname: &mut str) -> anyhow::Res
     output.write_u32::<LittleEndian>(real_groups)?;
     output.flush()?;
 
+    if let Some(catalog) = context.wmo_catalog.as_mut() {
+        catalog.push(WmoCatalogEntry {
+            model: fixed.clone(),
+            root_wmo_id: root.root_wmo_id,
+            groups: catalog_groups,
+        });
+    }
+
     context.wmo_doodads.insert(fixed, doodads);
 
     Ok(true)
@@ -1125,6 +1177,26 @@ impl WmoGroup {
         Ok(Some(group))
     }
 
+    fn catalog_entry(&self, index: u32, root: &WmoRoot, skipped: bool) -> WmoGroupCatalogEntry {
+        let name_at = |offset: i32| {
+            usize::try_from(offset)
+                .ok()
+                .and_then(|offset| read_cstring(&root.group_names, offset))
+                .filter(|name| !name.is_empty())
+        };
+        WmoGroupCatalogEntry {
+            index,
+            group_wmo_id: self.group_wmo_id,
+            name: name_at(self.group_name),
+            description: name_at(self.desc_group_name),
+            flags: self.mogp_flags as u32,
+            indoor: (self.mogp_flags & 0x2000) != 0,
+            liquid_type: self.liquid_type,
+            has_liquid: self.liquid_header.is_some(),
+            skipped,
+        }
+    }
+
     fn should_skip(&self, root: &WmoRoot) -> bool {
         if (self.mogp_flags & 0x80) != 0 {
             return true;
@@ -1674,8 +1746,8 @@ fn write_wmo_instance(
 
     let mut position = inst.position;
     if position.x == 0.0 && position.z == 0.0 {
-        position.x = 533.333_3 * 32.0;
-        position.z = 533.333_3 * 32.0;
+        position.x = MAP_HALFSIZE;
+        position.z = MAP_HALFSIZE;
     }
 
     let position = fix_coords(position);
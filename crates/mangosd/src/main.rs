@@ -0,0 +1,202 @@
+// mangosd - CMaNGOS TBC World Server
+// Rust rewrite of src/mangosd/Main.cpp
+//
+// Only the first step of the world server exists so far:
+// - Accepting game clients sent here by the realm list
+// - The session handshake, checked against the session key stored by realmd
+// - Header encryption of the authenticated connection
+// - The realm's uptime row and population in the login database
+
+mod response_codes;
+mod world_socket;
+
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+use mangos_shared::auth::session_key;
+use mangos_shared::config::get_config;
+use mangos_shared::database::Database;
+use mangos_shared::error::{ExitStatus, MangosError};
+use mangos_shared::log::{initialize_logging, map_log_level, LogOptions, LOG_TARGET_DB_ERROR};
+use mangos_shared::network::{bind_listeners, normalize_addr, parse_bind_addresses};
+use mangos_shared::world::UptimeReporter;
+
+use world_socket::WorldContext;
+
+/// Default world server port
+const DEFAULT_WORLDSERVER_PORT: i32 = 8085;
+
+/// Default config file name
+const DEFAULT_CONFIG: &str = "mangosd.conf";
+
+/// CLI arguments
+#[derive(Parser, Debug)]
+#[command(name = "mangosd")]
+#[command(about = "CMaNGOS TBC World Server (Rust)")]
+#[command(version)]
+struct Args {
+    /// Configuration file path
+    #[arg(short, long, default_value = DEFAULT_CONFIG)]
+    config: String,
+
+    /// Console log level override (0=Minimum, 1=Error, 2=Detail, 3=Full/Debug, 4=Trace)
+    /// Overrides the LogLevel setting from the config file.
+    #[arg(short, long, value_name = "LEVEL")]
+    log_level: Option<i32>,
+}
+
+/// Exit codes follow mangos_shared::error::ExitStatus
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: cannot start the async runtime: {}", e);
+            return ExitStatus::Failure.into();
+        }
+    };
+    match runtime.block_on(run(args)) {
+        Ok(()) => ExitStatus::Success.into(),
+        Err(e) => {
+            let status = ExitStatus::from_error(&e);
+            eprintln!("Error: {:#}", e);
+            status.into()
+        }
+    }
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    // Load configuration
+    {
+        let mut config = get_config().lock();
+        if !config.set_source(&args.config, "Mangosd_") {
+            eprintln!("Could not find configuration file {}.", args.config);
+            anyhow::bail!(MangosError::Config(format!("Configuration file {} not found", args.config)));
+        }
+    }
+
+    // Initialize logging, same levels as realmd
+    let (log_dir, console_level_str, file_level_str, log_options) = {
+        let config = get_config().lock();
+        let dir = config.get_string_default("LogsDir", "");
+        let log_dir = if dir.is_empty() { None } else { Some(dir) };
+
+        let console_level_int = args.log_level.unwrap_or_else(|| config.get_int_default("LogLevel", 2));
+        let file_level_int = config.get_int_default("LogFileLevel", console_level_int);
+
+        let console_str = map_log_level(console_level_int).to_string();
+        let file_str = map_log_level(file_level_int).to_string();
+
+        (log_dir, console_str, file_str, LogOptions::from_config(&config, "mangosd"))
+    };
+    let logging = initialize_logging(
+        log_dir.as_deref(),
+        &console_level_str,
+        Some(&file_level_str),
+        &log_options,
+    );
+
+    tracing::debug!("Console log level: {} | File log level: {}", console_level_str, file_level_str);
+    tracing::info!("CMaNGOS TBC World Server (Rust) v{}", env!("CARGO_PKG_VERSION"));
+    tracing::info!("Using configuration file: {}", args.config);
+    tracing::info!("<Ctrl-C> to stop.");
+
+    if let Err(e) = session_key::initialize(&get_config().lock()) {
+        anyhow::bail!(MangosError::Config(format!("{:#}", e)));
+    }
+
+    // The login database holds the accounts and their session keys
+    let mut login_db = Database::new("Login");
+    let db_string = get_config().lock().get_string("LoginDatabaseInfo");
+    if db_string.is_empty() {
+        tracing::error!("Database not specified in configuration");
+        anyhow::bail!(MangosError::Config("LoginDatabaseInfo not specified".into()));
+    }
+    if let Err(e) = login_db.initialize(&db_string).await {
+        tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot connect to database: {}", e);
+        anyhow::bail!(MangosError::Database(format!("Cannot connect to login database: {}", e)));
+    }
+
+    let (realm_id, player_limit, uptime_interval) = {
+        let config = get_config().lock();
+        (
+            config.get_int_default("RealmID", 1).max(0) as u32,
+            config.get_int_default("PlayerLimit", 100).max(0) as u32,
+            Duration::from_secs(config.get_int_default("UpdateUptimeInterval", 10).max(1) as u64 * 60),
+        )
+    };
+    let uptime = match UptimeReporter::start(login_db.clone(), realm_id, player_limit).await {
+        Ok(uptime) => uptime,
+        Err(e) => {
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot record the start of realm {}: {:#}", realm_id, e);
+            anyhow::bail!(MangosError::Database(format!("Cannot update the uptime table: {:#}", e)));
+        }
+    };
+
+    let (bind_ip, port, ctx) = {
+        let config = get_config().lock();
+        let ctx = WorldContext {
+            db: login_db,
+            uptime: uptime.clone(),
+            expansion: config.get_int_default("Expansion", 1).clamp(0, 1) as u8,
+            map_v4: config.get_bool_default("MapIPv4MappedAddresses", true),
+            socket_timeout: Duration::from_millis(config.get_int_default("SocketTimeOutTime", 900_000).max(1000) as u64),
+        };
+        (
+            config.get_string_default("BindIP", "0.0.0.0"),
+            config.get_int_default("WorldServerPort", DEFAULT_WORLDSERVER_PORT),
+            Arc::new(ctx),
+        )
+    };
+
+    let bind_addresses = parse_bind_addresses(&bind_ip, port as u16).map_err(|e| MangosError::Config(format!("BindIP: {:#}", e)))?;
+    let listeners = bind_listeners(&bind_addresses).map_err(|e| MangosError::Network(format!("{:#}", e)))?;
+    for listener in &listeners {
+        tracing::info!("Listening on {}", listener.local_addr()?);
+    }
+
+    // Setup Ctrl-C handler
+    let (stop_tx, stop_rx) = watch::channel(false);
+    ctrlc::set_handler(move || {
+        tracing::info!("Received shutdown signal");
+        stop_tx.send_replace(true);
+    })?;
+    let uptime_task = uptime.spawn(uptime_interval, stop_rx.clone());
+
+    let accept_loops: Vec<_> = listeners
+        .into_iter()
+        .map(|listener| tokio::spawn(accept_loop(listener, ctx.clone(), stop_rx.clone())))
+        .collect();
+    for accept_loop in accept_loops {
+        let _ = accept_loop.await;
+    }
+    // Sets the realm offline
+    let _ = uptime_task.await;
+
+    tracing::info!("Halting process...");
+    logging.shutdown();
+    Ok(())
+}
+
+/// Accept world connections on `listener` until the stop signal
+async fn accept_loop(listener: TcpListener, ctx: Arc<WorldContext>, mut stop: watch::Receiver<bool>) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = stop.wait_for(|stopped| *stopped) => return,
+        };
+        match accepted {
+            Ok((stream, peer)) => {
+                let peer = normalize_addr(peer, ctx.map_v4);
+                tokio::spawn(world_socket::handle_connection(stream, peer, ctx.clone()));
+            }
+            Err(e) => tracing::error!("Failed to accept connection: {}", e),
+        }
+    }
+}
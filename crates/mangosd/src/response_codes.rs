@@ -0,0 +1,14 @@
+// ResponseCodes - Result codes of SMSG_AUTH_RESPONSE (TBC 2.4.3)
+// Rust equivalent of the parts of SharedDefines.h the session handshake needs
+
+/// Result codes of SMSG_AUTH_RESPONSE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ResponseCode {
+    Ok = 0x0C,
+    Failed = 0x0D,
+    SystemError = 0x11,
+    UnknownAccount = 0x15,
+    SessionExpired = 0x17,
+    Banned = 0x1C,
+}
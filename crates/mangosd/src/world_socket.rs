@@ -0,0 +1,254 @@
+// WorldSocket - Client connection to the world server
+// Rust equivalent of WorldSocket.cpp (TBC)
+//
+// The server opens with SMSG_AUTH_CHALLENGE carrying a random seed. The client
+// answers with CMSG_AUTH_SESSION, proving it knows the session key K realmd
+// stored for the account:
+//   digest = SHA1(account, 0u32, client seed, server seed, K)
+// Once the digest matches, both sides encrypt packet headers with AuthCrypt
+// (keyed from K) for the rest of the connection; SMSG_AUTH_RESPONSE is the
+// first encrypted packet. Refusals are sent in the clear and close the socket.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::net::TcpStream;
+use zeroize::Zeroizing;
+
+use mangos_shared::auth::{constant_time_eq, session_key, AuthCrypt, BigNumber, Sha1Hash};
+use mangos_shared::database::{Database, FieldExt};
+use mangos_shared::network::{ip_matches, read_exact_timeout, read_frame, write_all_timeout};
+use mangos_shared::util::unix_now;
+use mangos_shared::world::{parse_client_header, Opcode, UptimeReporter, WorldPacket, CLIENT_HEADER_LEN};
+
+use crate::response_codes::ResponseCode;
+
+/// Longest a client may take to answer the auth challenge
+const AUTH_SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest client packet body accepted, addon data included
+const MAX_CLIENT_PACKET: usize = 10240;
+
+/// Settings shared by all world connections
+pub struct WorldContext {
+    pub db: Database,
+    /// Counts the players in the world for the population
+    pub uptime: Arc<UptimeReporter>,
+    /// Highest expansion the server offers (Expansion)
+    pub expansion: u8,
+    pub map_v4: bool,
+    /// Idle time after which an authenticated session is dropped (SocketTimeOutTime)
+    pub socket_timeout: Duration,
+}
+
+/// An account that passed CMSG_AUTH_SESSION
+struct AuthedAccount {
+    id: u32,
+    name: String,
+    expansion: u8,
+}
+
+struct WorldSocket {
+    stream: TcpStream,
+    peer: SocketAddr,
+    crypt: AuthCrypt,
+}
+
+impl WorldSocket {
+    async fn send_packet(&mut self, packet: &WorldPacket) -> anyhow::Result<()> {
+        let mut header = packet.server_header()?;
+        self.crypt.encrypt_send(&mut header);
+
+        let mut data = Vec::with_capacity(header.len() + packet.size());
+        data.extend_from_slice(&header);
+        data.extend_from_slice(packet.contents());
+        tracing::trace!("[{}] Sending {}", self.peer, packet);
+        write_all_timeout(&mut self.stream, &data, WRITE_TIMEOUT).await
+    }
+
+    async fn read_packet(&mut self, timeout: Duration) -> anyhow::Result<WorldPacket> {
+        let mut header = [0u8; CLIENT_HEADER_LEN];
+        read_exact_timeout(&mut self.stream, &mut header, timeout).await?;
+        self.crypt.decrypt_recv(&mut header);
+
+        let (len, opcode) = parse_client_header(&header)?;
+        let opcode = u16::try_from(opcode).map_err(|_| anyhow::anyhow!("client sent opcode {:#x}", opcode))?;
+        let body = read_frame(&mut self.stream, len, MAX_CLIENT_PACKET, timeout).await?;
+        let packet = WorldPacket::from_parts(opcode, body);
+        tracing::trace!("[{}] Received {}", self.peer, packet);
+        Ok(packet)
+    }
+
+    /// Refuse the session with `code`; the header goes out unencrypted
+    async fn refuse(&mut self, code: ResponseCode) -> anyhow::Result<()> {
+        let mut packet = WorldPacket::with_capacity(Opcode::SMSG_AUTH_RESPONSE, 1);
+        packet.write_u8(code as u8);
+        self.send_packet(&packet).await
+    }
+}
+
+/// Run one client connection until it closes
+pub async fn handle_connection(stream: TcpStream, peer: SocketAddr, ctx: Arc<WorldContext>) {
+    tracing::debug!("[{}] Accepted world connection", peer);
+    let mut socket = WorldSocket { stream, peer, crypt: AuthCrypt::new() };
+    match run_session(&mut socket, &ctx).await {
+        Ok(()) => tracing::debug!("[{}] Connection closed", peer),
+        Err(e) => tracing::debug!("[{}] Connection closed: {:#}", peer, e),
+    }
+}
+
+async fn run_session(socket: &mut WorldSocket, ctx: &WorldContext) -> anyhow::Result<()> {
+    let server_seed: u32 = rand::thread_rng().r#gen();
+    let mut challenge = WorldPacket::with_capacity(Opcode::SMSG_AUTH_CHALLENGE, 4);
+    challenge.write_u32(server_seed);
+    socket.send_packet(&challenge).await?;
+
+    let packet = socket.read_packet(AUTH_SESSION_TIMEOUT).await?;
+    anyhow::ensure!(
+        packet.known_opcode() == Some(Opcode::CMSG_AUTH_SESSION),
+        "expected CMSG_AUTH_SESSION, got {}",
+        packet
+    );
+    let Some(account) = authenticate(socket, ctx, server_seed, packet).await? else {
+        return Ok(());
+    };
+
+    tracing::info!(
+        "[{}] Account {} (id {}) authenticated, expansion {}",
+        socket.peer, account.name, account.id, account.expansion
+    );
+    let mut response = WorldPacket::with_capacity(Opcode::SMSG_AUTH_RESPONSE, 11);
+    response.write_u8(ResponseCode::Ok as u8);
+    response.write_u32(0); // billing time remaining
+    response.write_u8(0); // billing flags
+    response.write_u32(0); // billing time rested
+    response.write_u8(account.expansion);
+    socket.send_packet(&response).await?;
+    let _online = OnlineGuard::new(&ctx.uptime);
+
+    // Character handling is not implemented yet; keep the session alive
+    loop {
+        let mut packet = socket.read_packet(ctx.socket_timeout).await?;
+        match packet.known_opcode() {
+            Some(Opcode::CMSG_PING) => {
+                let ping = packet.read_u32()?;
+                let latency = packet.read_u32()?;
+                tracing::trace!("[{}] Ping {} (latency {} ms)", socket.peer, ping, latency);
+                let mut pong = WorldPacket::with_capacity(Opcode::SMSG_PONG, 4);
+                pong.write_u32(ping);
+                socket.send_packet(&pong).await?;
+            }
+            _ => tracing::debug!("[{}] Unhandled {} from {}", socket.peer, packet, account.name),
+        }
+    }
+}
+
+/// Counts an authenticated session as online until dropped
+struct OnlineGuard<'a>(&'a UptimeReporter);
+
+impl<'a> OnlineGuard<'a> {
+    fn new(uptime: &'a UptimeReporter) -> Self {
+        uptime.player_joined();
+        OnlineGuard(uptime)
+    }
+}
+
+impl Drop for OnlineGuard<'_> {
+    fn drop(&mut self) {
+        self.0.player_left();
+    }
+}
+
+/// Check CMSG_AUTH_SESSION against the session key realmd stored; on success
+/// header encryption is switched on, otherwise the client was told why not
+async fn authenticate(
+    socket: &mut WorldSocket,
+    ctx: &WorldContext,
+    server_seed: u32,
+    mut pkt: WorldPacket,
+) -> anyhow::Result<Option<AuthedAccount>> {
+    let build = pkt.read_u32()?;
+    let _server_id = pkt.read_u32()?;
+    let name = pkt.read_cstring()?.to_uppercase();
+    let client_seed = pkt.read_u32()?;
+    let digest = pkt.read_bytes(20)?;
+    // The addon info that follows is not needed yet
+    tracing::debug!("[{}] CMSG_AUTH_SESSION from {} (build {})", socket.peer, name, build);
+
+    let sql = format!(
+        "SELECT id, CAST(sessionkey AS CHAR), lockedIp, CAST(locked AS SIGNED), CAST(expansion AS SIGNED) \
+         FROM account WHERE username = '{}'",
+        Database::escape_string(&name)
+    );
+    let row = match ctx.db.query_one(&sql).await {
+        Ok(row) => row,
+        Err(e) => {
+            tracing::error!("[{}] Cannot look up account {}: {}", socket.peer, name, e);
+            socket.refuse(ResponseCode::SystemError).await?;
+            return Ok(None);
+        }
+    };
+    let Some(row) = row else {
+        tracing::info!("[{}] Unknown account {} tried to enter the world", socket.peer, name);
+        socket.refuse(ResponseCode::UnknownAccount).await?;
+        return Ok(None);
+    };
+    let id = row.get_u32(0);
+
+    if row.get_i64(3) == 1 && !ip_matches(&row.get_string(2), socket.peer.ip(), ctx.map_v4) {
+        tracing::info!("[{}] Account {} is locked to another address", socket.peer, name);
+        socket.refuse(ResponseCode::Failed).await?;
+        return Ok(None);
+    }
+
+    let stored = Zeroizing::new(row.get_string(1));
+    let key_hex = match session_key::open(&name, &stored) {
+        Ok(key_hex) if !key_hex.is_empty() => key_hex,
+        Ok(_) => {
+            tracing::info!("[{}] Account {} has no session key", socket.peer, name);
+            socket.refuse(ResponseCode::SessionExpired).await?;
+            return Ok(None);
+        }
+        Err(e) => {
+            tracing::error!("[{}] Session key of {} unusable: {:#}", socket.peer, name, e);
+            socket.refuse(ResponseCode::SystemError).await?;
+            return Ok(None);
+        }
+    };
+    let mut k = BigNumber::new();
+    k.set_hex_str(&key_hex);
+
+    let mut sha = Sha1Hash::new();
+    sha.initialize();
+    sha.update_data(&name);
+    sha.update_data_bytes(&0u32.to_le_bytes());
+    sha.update_data_bytes(&client_seed.to_le_bytes());
+    sha.update_data_bytes(&server_seed.to_le_bytes());
+    sha.update_big_numbers(&[&k]);
+    sha.finalize();
+    if !constant_time_eq(sha.get_digest(), &digest) {
+        tracing::info!("[{}] Account {} sent a wrong session digest", socket.peer, name);
+        socket.refuse(ResponseCode::Failed).await?;
+        return Ok(None);
+    }
+
+    let ban_sql = format!(
+        "SELECT 1 FROM account_banned WHERE account_id = '{}' AND CAST(active AS SIGNED) = 1 \
+         AND (expires_at > '{}' OR expires_at = banned_at)",
+        id,
+        unix_now()
+    );
+    if ctx.db.query_one(&ban_sql).await?.is_some() {
+        tracing::info!("[{}] Banned account {} tried to enter the world", socket.peer, name);
+        socket.refuse(ResponseCode::Banned).await?;
+        return Ok(None);
+    }
+
+    socket.crypt.init(&k);
+    let expansion = (row.get_i64(4).clamp(0, u8::MAX as i64) as u8).min(ctx.expansion);
+    Ok(Some(AuthedAccount { id, name, expansion }))
+}
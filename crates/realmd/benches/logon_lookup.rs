@@ -0,0 +1,120 @@
+// Database cost of a logon challenge: IP ban, account and account ban lookup
+//
+// Runs the lookup of handle_logon_challenge (src/logon_lookup.rs, compiled in
+// here with the ip_ban module it uses) against a real login database: a
+// throwaway SQLite file by default, or the database at LOGON_BENCH_DATABASE
+// (e.g. a MySQL server with resources/sql/realmd.sql), where rows for the
+// account BENCH are added and removed again.
+//
+// Run with: cargo bench -p realmd --bench logon_lookup
+
+#[allow(dead_code)]
+#[path = "../src/ip_ban.rs"]
+mod ip_ban;
+#[path = "../src/logon_lookup.rs"]
+mod logon_lookup;
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::runtime::Runtime;
+
+use mangos_shared::database::Database;
+use mangos_shared::util::unix_now;
+
+const ACCOUNT: &str = "BENCH";
+const IP: &str = "203.0.113.7";
+
+/// Bans that don't match, so the IP lookup scans like on a live realm
+const OTHER_IP_BANS: u32 = 200;
+
+/// The default database, removed again after the run
+fn sqlite_path() -> PathBuf {
+    std::env::temp_dir().join(format!("realmd-bench-{}.db", std::process::id()))
+}
+
+/// Remove the rows added by open_database
+async fn remove_rows(db: &Database) {
+    for sql in [
+        format!("DELETE FROM account WHERE username = '{}'", ACCOUNT),
+        "DELETE FROM account_banned WHERE banned_by = 'bench'".to_string(),
+        "DELETE FROM ip_banned WHERE banned_by = 'bench'".to_string(),
+    ] {
+        db.execute(&sql).await.unwrap();
+    }
+}
+
+/// A login database with the bench account, an expired ban of it and unrelated IP bans
+async fn open_database() -> (Database, &'static str) {
+    let mut db = Database::new("Login");
+    let backend = match std::env::var("LOGON_BENCH_DATABASE") {
+        Ok(url) => {
+            db.initialize(&url).await.expect("cannot connect to LOGON_BENCH_DATABASE");
+            "database"
+        }
+        Err(_) => {
+            let path = sqlite_path();
+            let _ = std::fs::remove_file(&path);
+            db.initialize(&format!("sqlite://{}?mode=rwc", path.display())).await.unwrap();
+            for statement in mangos_test_harness::sqlite_schema() {
+                db.execute(&statement).await.unwrap();
+            }
+            "sqlite"
+        }
+    };
+
+    remove_rows(&db).await;
+    let now = unix_now();
+    db.execute(&format!(
+        "INSERT INTO account(username, v, s, joindate) VALUES('{}', '{}', '{}', '2006-04-25 10:18:56')",
+        ACCOUNT,
+        "AB".repeat(32),
+        "CD".repeat(32)
+    ))
+    .await
+    .unwrap();
+    db.execute(&format!(
+        "INSERT INTO account_banned(account_id, banned_at, expires_at, banned_by, reason, active) \
+         SELECT id, {}, {}, 'bench', 'expired', 1 FROM account WHERE username = '{}'",
+        now - 7200,
+        now - 3600,
+        ACCOUNT
+    ))
+    .await
+    .unwrap();
+    for i in 0..OTHER_IP_BANS {
+        db.execute(&format!(
+            "INSERT INTO ip_banned VALUES ('198.51.{}.{}', {}, {}, 'bench', '')",
+            i / 256,
+            i % 256,
+            now,
+            now + 3600
+        ))
+        .await
+        .unwrap();
+    }
+    (db, backend)
+}
+
+fn bench_logon_lookup(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let (db, backend) = runtime.block_on(open_database());
+    let ip: IpAddr = IP.parse().unwrap();
+
+    // The account exists, its ban expired and the address is not banned
+    let (ip_ban, account) = runtime.block_on(logon_lookup::lookup(&db, ip, true, ACCOUNT));
+    assert!(ip_ban.unwrap().is_none() && account.unwrap().is_some());
+
+    let mut group = c.benchmark_group(format!("logon_lookup_{}", backend));
+    group.bench_function("challenge", |b| {
+        b.iter(|| runtime.block_on(logon_lookup::lookup(&db, ip, true, ACCOUNT)))
+    });
+    group.finish();
+
+    runtime.block_on(remove_rows(&db));
+    let _ = std::fs::remove_file(sqlite_path());
+}
+
+criterion_group!(benches, bench_logon_lookup);
+criterion_main!(benches);
@@ -0,0 +1,29 @@
+// Fuzz the logon challenge parsers with arbitrary header and body bytes
+//
+// The parsers live in the realmd binary, so the modules are compiled in here.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/auth_codes.rs"]
+mod auth_codes;
+#[allow(dead_code)]
+#[path = "../../src/protocol.rs"]
+mod protocol;
+
+use protocol::{AuthLogonChallengeBody, AuthLogonChallengeHeader};
+
+fuzz_target!(|data: &[u8]| {
+    let (header, body) = data.split_at(data.len().min(AuthLogonChallengeHeader::SIZE));
+    if let Ok(header) = AuthLogonChallengeHeader::from_bytes(header)
+        && let Ok(size) = header.body_size()
+    {
+        // realmd reads exactly the announced size
+        if let Ok(parsed) = AuthLogonChallengeBody::from_bytes(&body[..size.min(body.len())]) {
+            assert!(parsed.username.len() <= auth_codes::AUTH_LOGON_MAX_NAME);
+        }
+    }
+    let _ = AuthLogonChallengeBody::from_bytes(data);
+});
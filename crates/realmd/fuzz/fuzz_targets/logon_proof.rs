@@ -0,0 +1,26 @@
+// Fuzz the logon proof parser with every combination of PIN and matrix card data
+//
+// The parsers live in the realmd binary, so the modules are compiled in here.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/auth_codes.rs"]
+mod auth_codes;
+#[allow(dead_code)]
+#[path = "../../src/protocol.rs"]
+mod protocol;
+
+use protocol::AuthLogonProofClient;
+
+fuzz_target!(|data: &[u8]| {
+    for (with_pin, with_matrix) in [(false, false), (true, false), (false, true), (true, true)] {
+        if let Ok(proof) = AuthLogonProofClient::from_bytes(data, with_pin, with_matrix) {
+            assert_eq!(data.len(), AuthLogonProofClient::SIZE_WITHOUT_PIN + AuthLogonProofClient::extra_size(with_pin, with_matrix));
+            assert_eq!(proof.pin.is_some(), with_pin);
+            assert_eq!(proof.matrix_proof.is_some(), with_matrix);
+        }
+    }
+});
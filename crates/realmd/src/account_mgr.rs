@@ -0,0 +1,802 @@
+// AccountMgr - Administrative account operations
+// Rust equivalent of the account parts of AccountMgr.h/cpp
+//
+// Every administrative interface (CLI, SOAP, REST) must go through these
+// functions instead of issuing its own UPDATEs, so that sensitive changes are
+// audited in one place.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::{Rng, RngCore};
+
+use mangos_shared::auth::{constant_time_eq, BigNumber, MatrixCard, Sha1Hash, SRP6};
+use mangos_shared::config::{get_config, Config};
+use mangos_shared::database::{Database, FieldExt};
+use mangos_shared::log::{LOG_TARGET_AUDIT, LOG_TARGET_DB_ERROR};
+use mangos_shared::network::IpNetwork;
+use mangos_shared::util::local_datetime;
+use mangos_shared::{AccountTypes, SEC_ADMINISTRATOR};
+
+/// Administrative interface an account change originated from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeSource {
+    Cli,
+    Soap,
+    Rest,
+}
+
+impl ChangeSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeSource::Cli => "cli",
+            ChangeSource::Soap => "soap",
+            ChangeSource::Rest => "rest",
+        }
+    }
+}
+
+/// Longest account name and password the client accepts (C++ MAX_ACCOUNT_STR / MAX_PASSWORD_STR)
+pub const MAX_ACCOUNT_STR: usize = 16;
+pub const MAX_PASSWORD_STR: usize = 16;
+
+/// Highest expansion an account can be given (0 = Classic, 1 = TBC, 2 = WotLK)
+pub const MAX_EXPANSION: u8 = 2;
+
+/// Expansion of new accounts when none is given (TBC)
+pub const DEFAULT_EXPANSION: u8 = 1;
+
+/// Outcome of a gmlevel change request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GmLevelChange {
+    /// The new level was written to the account
+    Applied { old_level: AccountTypes },
+    /// Elevation to administrator needs a second administrator to confirm the token
+    PendingConfirmation { token: String, expires_at: u64 },
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Calculate the SHA pass hash the SRP6 verifier is derived from.
+/// Matches C++ AccountMgr::CalculateShaPassHash:
+///   SHA1(UPPER(username) + ":" + UPPER(password))
+/// Returns the hex-encoded digest string.
+pub fn calculate_sha_pass_hash(username: &str, password: &str) -> String {
+    let mut sha = Sha1Hash::new();
+    sha.initialize();
+    sha.update_data(&username.to_uppercase());
+    sha.update_data(":");
+    sha.update_data(&password.to_uppercase());
+    sha.finalize();
+
+    // Hex-encode the digest
+    sha.get_digest()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<String>()
+}
+
+/// Fresh salt and verifier (hex) for an account's password
+fn make_verifier(username: &str, password: &str) -> anyhow::Result<(String, String)> {
+    let mut srp = SRP6::new();
+    if !srp.calculate_verifier_random(&calculate_sha_pass_hash(username, password)) {
+        anyhow::bail!("Failed to generate SRP6 verifier");
+    }
+    Ok((srp.get_salt().as_hex_str(), srp.get_verifier().as_hex_str()))
+}
+
+/// Give an imported account that only has the legacy sha_pass_hash column a
+/// salt and verifier, derived the way account creation does
+///
+/// Returns the account's (v, s), or None when it has no usable hash. Fails when
+/// the column does not exist. The unsalted hash is cleared once converted.
+pub async fn migrate_sha_pass_hash(db: &Database, account_id: u32) -> anyhow::Result<Option<(String, String)>> {
+    let Some(row) = db
+        .query_one(&format!("SELECT sha_pass_hash FROM account WHERE id = '{}'", account_id))
+        .await?
+    else {
+        return Ok(None);
+    };
+    let hash = row.get_string(0);
+    if !is_sha_pass_hash(&hash) {
+        return Ok(None);
+    }
+
+    if let Some((verifier, salt)) = convert_sha_pass_hash(db, account_id, hash.trim()).await? {
+        tracing::info!("Converted the sha_pass_hash of account {} to an SRP6 verifier", account_id);
+        return Ok(Some((verifier, salt)));
+    }
+
+    // A concurrent login converted it first, use its verifier
+    Ok(db
+        .query_one(&format!("SELECT v, s FROM account WHERE id = '{}'", account_id))
+        .await?
+        .map(|row| (row.get_string(0), row.get_string(1))))
+}
+
+/// Whether `hash` looks like a SHA1(USERNAME:PASSWORD) digest
+fn is_sha_pass_hash(hash: &str) -> bool {
+    let hash = hash.trim();
+    hash.len() == 2 * Sha1Hash::DIGEST_LENGTH && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Store a verifier derived from `hash` unless sha_pass_hash changed meanwhile; returns (v, s) when stored
+async fn convert_sha_pass_hash(db: &Database, account_id: u32, hash: &str) -> anyhow::Result<Option<(String, String)>> {
+    let mut srp = SRP6::new();
+    if !srp.calculate_verifier_random(hash) {
+        anyhow::bail!("Failed to generate SRP6 verifier");
+    }
+    let (salt, verifier) = (srp.get_salt().as_hex_str(), srp.get_verifier().as_hex_str());
+    let converted = db
+        .execute(&format!(
+            "UPDATE account SET v = '{}', s = '{}', sha_pass_hash = '' WHERE id = '{}' AND sha_pass_hash = '{}'",
+            verifier,
+            salt,
+            account_id,
+            Database::escape_string(hash)
+        ))
+        .await?;
+    Ok((converted > 0).then_some((verifier, salt)))
+}
+
+/// Progress of [`migrate_all_sha_pass_hashes`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SrpMigration {
+    /// Accounts looked at so far
+    pub checked: u64,
+    /// Accounts with a missing or broken verifier given one from sha_pass_hash
+    /// (would be, on a dry run)
+    pub converted: u64,
+    /// Accounts with a missing or broken verifier and no usable sha_pass_hash
+    pub unusable: u64,
+}
+
+/// Give every account with a missing or broken salt/verifier one derived from
+/// its sha_pass_hash, `batch_size` accounts at a time
+///
+/// `progress` is called after every batch. With `dry_run` nothing is written.
+/// Accounts without a usable hash are logged and left alone.
+pub async fn migrate_all_sha_pass_hashes(
+    db: &Database,
+    dry_run: bool,
+    batch_size: u32,
+    mut progress: impl FnMut(&SrpMigration),
+) -> anyhow::Result<SrpMigration> {
+    let mut migration = SrpMigration::default();
+    let mut last_id = 0;
+    loop {
+        let rows = db
+            .query(&format!(
+                "SELECT id, username, v, s, sha_pass_hash FROM account WHERE id > '{}' ORDER BY id LIMIT {}",
+                last_id,
+                batch_size.max(1)
+            ))
+            .await
+            .map_err(|e| e.context("Cannot read the account table, does it have a sha_pass_hash column?"))?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_id = last.get_u32(0);
+
+        for row in &rows {
+            migration.checked += 1;
+            let mut srp = SRP6::new();
+            if srp.set_verifier(&row.get_string(2)) && srp.set_salt(&row.get_string(3)) {
+                continue;
+            }
+
+            let (account_id, username, hash) = (row.get_u32(0), row.get_string(1), row.get_string(4));
+            if !is_sha_pass_hash(&hash) {
+                tracing::warn!("Account {} (id {}) has no verifier and no usable sha_pass_hash", username, account_id);
+                migration.unusable += 1;
+            } else if dry_run || convert_sha_pass_hash(db, account_id, hash.trim()).await?.is_some() {
+                migration.converted += 1;
+            }
+        }
+        progress(&migration);
+    }
+
+    if !dry_run && migration.converted > 0 {
+        tracing::info!(target: LOG_TARGET_AUDIT, "{} sha_pass_hash value(s) converted to SRP6 verifiers", migration.converted);
+    }
+    Ok(migration)
+}
+
+fn validate_password(password: &str) -> anyhow::Result<()> {
+    if password.is_empty() || password.chars().count() > MAX_PASSWORD_STR {
+        anyhow::bail!("Password must be 1 to {} characters", MAX_PASSWORD_STR);
+    }
+    Ok(())
+}
+
+/// Check a password against the account's stored verifier (C++ AccountMgr::CheckPassword)
+pub async fn check_password(db: &Database, account_id: u32, password: &str) -> anyhow::Result<bool> {
+    let Some(row) = db
+        .query_one(&format!("SELECT username, s, v FROM account WHERE id = '{}'", account_id))
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    let mut srp = SRP6::new();
+    if !srp.calculate_verifier(&calculate_sha_pass_hash(&row.get_string(0), password), &row.get_string(1)) {
+        return Ok(false);
+    }
+    let mut stored = BigNumber::new();
+    stored.set_hex_str(&row.get_string(2));
+    Ok(!stored.is_zero() && constant_time_eq(&stored.as_byte_array(32), &srp.get_verifier().as_byte_array(32)))
+}
+
+/// Id of the account named `username` (case-insensitive)
+pub async fn find_account_id(db: &Database, username: &str) -> anyhow::Result<Option<u32>> {
+    Ok(db
+        .query_one(&format!(
+            "SELECT id FROM account WHERE username = '{}'",
+            Database::escape_string(&username.to_uppercase())
+        ))
+        .await?
+        .map(|row| row.get_u32(0)))
+}
+
+/// Set once a failed account_access query was reported
+static ACCOUNT_ACCESS_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// GM levels an account holds on single realms (account_access table)
+#[derive(Debug, Clone, Default)]
+pub struct RealmAccess {
+    /// Level on every realm (RealmID -1)
+    all_realms: Option<AccountTypes>,
+    by_realm: HashMap<u32, AccountTypes>,
+}
+
+impl RealmAccess {
+    /// Whether account.gmlevel applies to every realm
+    pub fn is_empty(&self) -> bool {
+        self.all_realms.is_none() && self.by_realm.is_empty()
+    }
+
+    /// Level on `realm_id` for an account whose account.gmlevel is `base`;
+    /// account_access only ever raises it
+    pub fn level(&self, realm_id: u32, base: AccountTypes) -> AccountTypes {
+        let granted = self.by_realm.get(&realm_id).copied().max(self.all_realms).unwrap_or(base);
+        base.max(granted).min(SEC_ADMINISTRATOR)
+    }
+}
+
+/// Per-realm GM levels of an account; empty when account_access is missing
+pub async fn load_realm_access(db: &Database, account_id: u32) -> RealmAccess {
+    let sql = format!(
+        "SELECT RealmID, CAST(gmlevel AS SIGNED) FROM account_access WHERE id = '{}'",
+        account_id
+    );
+    let rows = match db.query(&sql).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            if !ACCOUNT_ACCESS_WARNED.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    target: LOG_TARGET_DB_ERROR,
+                    "Cannot read account_access, using account.gmlevel on every realm: {}",
+                    e
+                );
+            }
+            return RealmAccess::default();
+        }
+    };
+    ACCOUNT_ACCESS_WARNED.store(false, Ordering::Relaxed);
+
+    let mut access = RealmAccess::default();
+    for row in &rows {
+        let level = row.get_i32(1).clamp(0, i32::from(SEC_ADMINISTRATOR)) as AccountTypes;
+        match u32::try_from(row.get_i32(0)) {
+            Ok(realm_id) => {
+                access.by_realm.insert(realm_id, level);
+            }
+            Err(_) => access.all_realms = Some(access.all_realms.map_or(level, |all| all.max(level))),
+        }
+    }
+    access
+}
+
+/// Create an account (C++ AccountMgr::CreateAccount); returns the new account id
+pub async fn create_account(
+    db: &Database,
+    username: &str,
+    password: &str,
+    expansion: u8,
+    actor: &str,
+    source: ChangeSource,
+) -> anyhow::Result<u32> {
+    let username = username.to_uppercase();
+    if username.is_empty()
+        || username.chars().count() > MAX_ACCOUNT_STR
+        || !username.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        anyhow::bail!("Account name must be 1 to {} letters or digits", MAX_ACCOUNT_STR);
+    }
+    validate_password(password)?;
+    if expansion > MAX_EXPANSION {
+        anyhow::bail!("Invalid expansion {} (maximum is {})", expansion, MAX_EXPANSION);
+    }
+    if find_account_id(db, &username).await?.is_some() {
+        anyhow::bail!("Account {} already exists", username);
+    }
+
+    let (s_hex, v_hex) = make_verifier(&username, password)?;
+    db.execute(&format!(
+        "INSERT INTO account(username, v, s, expansion, joindate) VALUES('{}', '{}', '{}', '{}', '{}')",
+        username, v_hex, s_hex, expansion, local_datetime()
+    ))
+    .await?;
+
+    let account_id = find_account_id(db, &username)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Account {} was not created", username))?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "account {} ({}) created by '{}' via {}",
+        account_id, username, actor, source.as_str()
+    );
+    Ok(account_id)
+}
+
+/// Rules for accounts created on their first login (AutoCreateAccounts)
+pub struct AutoCreatePolicy {
+    pub gm_level: AccountTypes,
+    pub expansion: u8,
+    /// Addresses allowed to create accounts; empty = any
+    pub allowed_networks: Vec<IpNetwork>,
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Characters allowed in names besides ASCII letters and digits
+    pub extra_chars: String,
+    /// Refused names, upper case; a trailing '*' matches every name starting with the rest
+    pub reserved_names: Vec<String>,
+    /// Give new accounts a random password instead of their name
+    pub random_password: bool,
+}
+
+impl AutoCreatePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        let allowed_ips = config.get_string("AutoCreateAccounts.AllowedIPs");
+        let allowed_networks = allowed_ips
+            .split_whitespace()
+            .filter_map(|entry| {
+                let network = IpNetwork::parse(entry);
+                if network.is_none() {
+                    tracing::warn!("AutoCreateAccounts.AllowedIPs: ignoring invalid entry '{}'", entry);
+                }
+                network
+            })
+            .collect();
+        AutoCreatePolicy {
+            gm_level: config.get_int_default("AutoCreateAccounts.GmLevel", 0).clamp(0, SEC_ADMINISTRATOR.into()) as AccountTypes,
+            expansion: config.get_int_default("AutoCreateAccounts.Expansion", DEFAULT_EXPANSION.into()).clamp(0, MAX_EXPANSION.into()) as u8,
+            allowed_networks,
+            min_length: config.get_int_default("AutoCreateAccounts.MinLength", 1).max(1) as usize,
+            max_length: (config.get_int_default("AutoCreateAccounts.MaxLength", MAX_ACCOUNT_STR as i32).max(1) as usize).min(MAX_ACCOUNT_STR),
+            extra_chars: config.get_string("AutoCreateAccounts.NameChars"),
+            reserved_names: config
+                .get_string("AutoCreateAccounts.ReservedNames")
+                .split_whitespace()
+                .map(str::to_uppercase)
+                .collect(),
+            random_password: config.get_bool_default("AutoCreateAccounts.RandomPassword", false),
+        }
+    }
+
+    /// Why `username` may not be created for a client at `ip`, None when it may
+    pub fn refusal(&self, username: &str, ip: IpAddr) -> Option<&'static str> {
+        if !self.allowed_networks.is_empty() && !self.allowed_networks.iter().any(|network| network.contains(ip)) {
+            return Some("address not in AutoCreateAccounts.AllowedIPs");
+        }
+        let length = username.chars().count();
+        if length < self.min_length || length > self.max_length {
+            return Some("name length outside AutoCreateAccounts.MinLength/MaxLength");
+        }
+        if !username.chars().all(|c| c.is_ascii_alphanumeric() || self.extra_chars.contains(c)) {
+            return Some("name has characters outside AutoCreateAccounts.NameChars");
+        }
+        let upper = username.to_uppercase();
+        let reserved = self.reserved_names.iter().any(|reserved| match reserved.strip_suffix('*') {
+            Some(prefix) => upper.starts_with(prefix),
+            None => upper == *reserved,
+        });
+        if reserved {
+            return Some("name in AutoCreateAccounts.ReservedNames");
+        }
+        None
+    }
+}
+
+/// Create the account a client tried to log in with, as `policy` says
+///
+/// The password is the account name, or a random one nobody knows when
+/// AutoCreateAccounts.RandomPassword is set: the account then has to be given
+/// a password through an admin interface before it can be used.
+pub async fn auto_create_account(db: &Database, username: &str, policy: &AutoCreatePolicy) -> anyhow::Result<u32> {
+    let password = if policy.random_password {
+        rand::thread_rng()
+            .sample_iter(rand::distributions::Alphanumeric)
+            .take(MAX_PASSWORD_STR)
+            .map(char::from)
+            .collect()
+    } else {
+        username.to_string()
+    };
+    let (s_hex, v_hex) = make_verifier(username, &password)?;
+    let safe_username = Database::escape_string(username);
+    db.execute(&format!(
+        "INSERT INTO account(username, v, s, gmlevel, expansion, joindate) VALUES('{}', '{}', '{}', '{}', '{}', '{}')",
+        safe_username, v_hex, s_hex, policy.gm_level, policy.expansion, local_datetime()
+    ))
+    .await?;
+
+    let account_id = find_account_id(db, username)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Account {} was not created", username))?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "account {} ({}) auto-created on first login: gmlevel {}, expansion {}, {} password",
+        account_id, username, policy.gm_level, policy.expansion,
+        if policy.random_password { "random" } else { "name as" }
+    );
+    Ok(account_id)
+}
+
+/// Set a new password, recomputing the salt and verifier (C++ AccountMgr::ChangePassword)
+pub async fn set_password(
+    db: &Database,
+    account_id: u32,
+    password: &str,
+    actor: &str,
+    source: ChangeSource,
+) -> anyhow::Result<()> {
+    validate_password(password)?;
+    let username = db
+        .query_one(&format!("SELECT username FROM account WHERE id = '{}'", account_id))
+        .await?
+        .map(|row| row.get_string(0))
+        .ok_or_else(|| anyhow::anyhow!("Account {} does not exist", account_id))?;
+
+    let (s_hex, v_hex) = make_verifier(&username, password)?;
+    db.execute(&format!(
+        "UPDATE account SET v = '{}', s = '{}' WHERE id = '{}'",
+        v_hex, s_hex, account_id
+    ))
+    .await?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "password of account {} changed by '{}' via {}",
+        account_id, actor, source.as_str()
+    );
+    Ok(())
+}
+
+/// Change the expansion an account may play
+pub async fn set_expansion(
+    db: &Database,
+    account_id: u32,
+    expansion: u8,
+    actor: &str,
+    source: ChangeSource,
+) -> anyhow::Result<()> {
+    if expansion > MAX_EXPANSION {
+        anyhow::bail!("Invalid expansion {} (maximum is {})", expansion, MAX_EXPANSION);
+    }
+    if !account_exists(db, account_id).await? {
+        anyhow::bail!("Account {} does not exist", account_id);
+    }
+    db.execute(&format!("UPDATE account SET expansion = '{}' WHERE id = '{}'", expansion, account_id))
+        .await?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "expansion of account {} set to {} by '{}' via {}",
+        account_id, expansion, actor, source.as_str()
+    );
+    Ok(())
+}
+
+async fn account_exists(db: &Database, account_id: u32) -> anyhow::Result<bool> {
+    Ok(db
+        .query_one(&format!("SELECT id FROM account WHERE id = '{}'", account_id))
+        .await?
+        .is_some())
+}
+
+/// Delete an account and its login-side data (C++ AccountMgr::DeleteAccount).
+///
+/// Characters live in the realms' character databases, which realmd cannot
+/// reach; they have to be removed there. Ban history and audit records are kept.
+pub async fn delete_account(db: &Database, account_id: u32, actor: &str, source: ChangeSource) -> anyhow::Result<()> {
+    let username = db
+        .query_one(&format!("SELECT username FROM account WHERE id = '{}'", account_id))
+        .await?
+        .map(|row| row.get_string(0))
+        .ok_or_else(|| anyhow::anyhow!("Account {} does not exist", account_id))?;
+
+    db.execute(&format!("DELETE FROM account WHERE id = '{}'", account_id)).await?;
+    db.execute(&format!("DELETE FROM realmcharacters WHERE acctid = '{}'", account_id)).await?;
+    db.execute(&format!("DELETE FROM account_gmlevel_pending WHERE account_id = '{}'", account_id))
+        .await?;
+    db.execute(&format!("DELETE FROM account_matrix_card WHERE account_id = '{}'", account_id))
+        .await?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "account {} ({}) deleted by '{}' via {}",
+        account_id, username, actor, source.as_str()
+    );
+    Ok(())
+}
+
+/// Store a new matrix card for an account, replacing any previous one
+pub async fn set_matrix_card(
+    db: &Database,
+    account_id: u32,
+    card: &MatrixCard,
+    actor: &str,
+    source: ChangeSource,
+) -> anyhow::Result<()> {
+    if !account_exists(db, account_id).await? {
+        anyhow::bail!("Account {} does not exist", account_id);
+    }
+    db.execute(&format!(
+        "REPLACE INTO account_matrix_card(account_id, width, height, digit_count, digits) VALUES('{}', '{}', '{}', '{}', '{}')",
+        account_id,
+        card.width,
+        card.height,
+        card.digit_count,
+        card.to_digit_string()
+    ))
+    .await?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "matrix card of account {} replaced by '{}' via {}",
+        account_id, actor, source.as_str()
+    );
+    Ok(())
+}
+
+/// Ban an account for `duration` seconds (0 = permanent)
+pub async fn ban_account(
+    db: &Database,
+    account_id: u32,
+    duration: u64,
+    reason: &str,
+    actor: &str,
+    source: ChangeSource,
+) -> anyhow::Result<()> {
+    if !account_exists(db, account_id).await? {
+        anyhow::bail!("Account {} does not exist", account_id);
+    }
+    let now = unix_now();
+    db.execute(&format!(
+        "INSERT INTO account_banned(account_id, banned_at, expires_at, banned_by, reason, active) \
+         VALUES('{}', '{}', '{}', '{}', '{}', 1)",
+        account_id,
+        now,
+        now + duration,
+        Database::escape_string(actor),
+        Database::escape_string(reason)
+    ))
+    .await?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "account {} banned by '{}' via {} for {} ({})",
+        account_id,
+        actor,
+        source.as_str(),
+        if duration == 0 { "ever".to_string() } else { format!("{}s", duration) },
+        reason
+    );
+    Ok(())
+}
+
+/// Whether the account has an active ban that has not expired
+pub async fn is_banned(db: &Database, account_id: u32) -> anyhow::Result<bool> {
+    Ok(db
+        .query_one(&format!(
+            "SELECT 1 FROM account_banned WHERE account_id = '{}' AND CAST(active AS SIGNED) = 1 \
+             AND (expires_at > {} OR expires_at = banned_at)",
+            account_id,
+            unix_now()
+        ))
+        .await?
+        .is_some())
+}
+
+/// Lift the active bans of an account; returns the number of bans lifted
+pub async fn unban_account(db: &Database, account_id: u32, actor: &str, source: ChangeSource) -> anyhow::Result<u64> {
+    let lifted = db
+        .execute(&format!(
+            "UPDATE account_banned SET active = 0, unbanned_at = '{}', unbanned_by = '{}' \
+             WHERE account_id = '{}' AND active = 1",
+            unix_now(),
+            Database::escape_string(actor),
+            account_id
+        ))
+        .await?;
+
+    if lifted > 0 {
+        tracing::warn!(
+            target: LOG_TARGET_AUDIT,
+            "account {} unbanned by '{}' via {}",
+            account_id, actor, source.as_str()
+        );
+    }
+    Ok(lifted)
+}
+
+/// Change an account's gmlevel.
+///
+/// Writes an audit record for every applied change. When `GmLevel.DualControl`
+/// is enabled, elevation to SEC_ADMINISTRATOR is not applied directly; instead a
+/// one-time confirmation token is returned which a *different* administrator must
+/// pass to [`confirm_gm_level`] before it expires.
+pub async fn set_gm_level(
+    db: &Database,
+    account_id: u32,
+    new_level: AccountTypes,
+    actor: &str,
+    source: ChangeSource,
+) -> anyhow::Result<GmLevelChange> {
+    if new_level > SEC_ADMINISTRATOR {
+        anyhow::bail!("Invalid gmlevel {} (maximum is {})", new_level, SEC_ADMINISTRATOR);
+    }
+
+    let old_level = current_gm_level(db, account_id).await?;
+
+    let (dual_control, confirm_timeout) = {
+        let config = get_config().lock();
+        (
+            config.get_bool_default("GmLevel.DualControl", false),
+            config.get_int_default("GmLevel.ConfirmationTimeout", 600).max(1) as u64,
+        )
+    };
+
+    if dual_control && new_level >= SEC_ADMINISTRATOR && old_level < SEC_ADMINISTRATOR {
+        let mut raw = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut raw);
+        let token: String = raw.iter().map(|b| format!("{:02X}", b)).collect();
+        let now = unix_now();
+        let expires_at = now + confirm_timeout;
+
+        db.execute(&format!(
+            "INSERT INTO account_gmlevel_pending(token, account_id, gmlevel, requested_by, source, requested_at, expires_at) \
+             VALUES('{}', '{}', '{}', '{}', '{}', '{}', '{}')",
+            token,
+            account_id,
+            new_level,
+            Database::escape_string(actor),
+            source.as_str(),
+            now,
+            expires_at
+        ))
+        .await?;
+
+        tracing::warn!(
+            target: LOG_TARGET_AUDIT,
+            "gmlevel elevation of account {} to {} requested by '{}' via {}, awaiting second administrator",
+            account_id, new_level, actor, source.as_str()
+        );
+
+        return Ok(GmLevelChange::PendingConfirmation { token, expires_at });
+    }
+
+    apply_gm_level(db, account_id, old_level, new_level, actor, None, source).await?;
+    Ok(GmLevelChange::Applied { old_level })
+}
+
+/// Confirm a pending administrator elevation created by [`set_gm_level`].
+///
+/// The confirming administrator must differ from the requester.
+pub async fn confirm_gm_level(
+    db: &Database,
+    token: &str,
+    actor: &str,
+    source: ChangeSource,
+) -> anyhow::Result<GmLevelChange> {
+    let safe_token = Database::escape_string(token);
+    let row = db
+        .query_one(&format!(
+            "SELECT account_id, CAST(gmlevel AS SIGNED) AS gmlevel, requested_by, expires_at \
+             FROM account_gmlevel_pending WHERE token = '{}'",
+            safe_token
+        ))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Unknown or already used confirmation token"))?;
+
+    let account_id = row.get_u32(0);
+    let new_level = row.get_u8(1);
+    let requested_by = row.get_string(2);
+    let expires_at = row.get_u64(3);
+
+    if expires_at < unix_now() {
+        db.execute(&format!("DELETE FROM account_gmlevel_pending WHERE token = '{}'", safe_token))
+            .await?;
+        anyhow::bail!("Confirmation token expired");
+    }
+
+    if requested_by.eq_ignore_ascii_case(actor) {
+        anyhow::bail!("Elevation must be confirmed by a different administrator");
+    }
+
+    // Consume the token before applying so it can never be replayed
+    if db
+        .execute(&format!("DELETE FROM account_gmlevel_pending WHERE token = '{}'", safe_token))
+        .await?
+        == 0
+    {
+        anyhow::bail!("Confirmation token already used");
+    }
+
+    let old_level = current_gm_level(db, account_id).await?;
+    apply_gm_level(db, account_id, old_level, new_level, &requested_by, Some(actor), source).await?;
+    Ok(GmLevelChange::Applied { old_level })
+}
+
+/// The account's gmlevel (C++ AccountMgr::GetSecurity)
+pub async fn current_gm_level(db: &Database, account_id: u32) -> anyhow::Result<AccountTypes> {
+    db.query_one(&format!(
+        "SELECT CAST(gmlevel AS SIGNED) AS gmlevel FROM account WHERE id = '{}'",
+        account_id
+    ))
+    .await?
+    .map(|row| row.get_u8(0))
+    .ok_or_else(|| anyhow::anyhow!("Account {} does not exist", account_id))
+}
+
+/// Write the new level and its audit record
+async fn apply_gm_level(
+    db: &Database,
+    account_id: u32,
+    old_level: AccountTypes,
+    new_level: AccountTypes,
+    actor: &str,
+    confirmed_by: Option<&str>,
+    source: ChangeSource,
+) -> anyhow::Result<()> {
+    db.execute(&format!(
+        "UPDATE account SET gmlevel = '{}' WHERE id = '{}'",
+        new_level, account_id
+    ))
+    .await?;
+
+    db.execute(&format!(
+        "INSERT INTO account_gmlevel_audit(account_id, old_gmlevel, new_gmlevel, changed_by, confirmed_by, source, changed_at) \
+         VALUES('{}', '{}', '{}', '{}', '{}', '{}', '{}')",
+        account_id,
+        old_level,
+        new_level,
+        Database::escape_string(actor),
+        Database::escape_string(confirmed_by.unwrap_or("")),
+        source.as_str(),
+        unix_now()
+    ))
+    .await?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "gmlevel of account {} changed {} -> {} by '{}'{} via {}",
+        account_id,
+        old_level,
+        new_level,
+        actor,
+        confirmed_by.map(|c| format!(" (confirmed by '{}')", c)).unwrap_or_default(),
+        source.as_str()
+    );
+
+    Ok(())
+}
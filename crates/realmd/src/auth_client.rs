@@ -0,0 +1,182 @@
+// AuthClient - The client side of a login
+//
+// Performs the exchange a game client makes with realmd: logon challenge,
+// SRP6 proof computed with SRP6Client and a realm list request, and reads the
+// realm list back. Used by `realmd test-login` and `realmd stress` to check a
+// running realmd from the outside. It sends no client hash, so a target with
+// StrictVersionCheck refuses it, and cannot answer authenticator, PIN or
+// matrix card prompts.
+
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use mangos_shared::auth::SRP6Client;
+use mangos_shared::util::ByteBuffer;
+use mangos_shared::RealmFlags;
+
+use crate::auth_codes::{AuthCmd, AuthLogonResult};
+use crate::realm_list::RealmBuildInfo;
+
+/// Longest a login may take before it counts as failed
+pub const LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A realm as the realm list shows it to the client
+#[derive(Debug, Clone)]
+pub struct RealmEntry {
+    pub name: String,
+    pub address: String,
+    pub icon: u8,
+    pub locked: bool,
+    pub flags: u8,
+    pub population: f32,
+    pub characters: u8,
+    pub category: u8,
+    /// Version and build for realms flagged REALM_FLAG_SPECIFYBUILD
+    pub version: Option<(u8, u8, u8, u16)>,
+}
+
+/// Realms from the realm list packet body `body` sent to a `build` client
+pub fn parse_realm_list(build: u16, body: &[u8]) -> io::Result<Vec<RealmEntry>> {
+    let mut pkt = ByteBuffer::from(body);
+    pkt.read_u32()?; // unused
+    let legacy = matches!(build, 5875 | 6005 | 6141);
+    let count = if legacy { pkt.read_u8()? as usize } else { pkt.read_u16()? as usize };
+
+    let mut realms = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (icon, locked) = if legacy {
+            (pkt.read_u32()? as u8, false)
+        } else {
+            (pkt.read_u8()?, pkt.read_u8()? != 0)
+        };
+        let flags = pkt.read_u8()?;
+        let name = pkt.read_cstring()?;
+        let address = pkt.read_cstring()?;
+        let population = pkt.read_f32()?;
+        let characters = pkt.read_u8()?;
+        let category = pkt.read_u8()?;
+        pkt.read_u8()?; // unknown
+        let version = if !legacy && flags & RealmFlags::REALM_FLAG_SPECIFYBUILD != 0 {
+            Some((pkt.read_u8()?, pkt.read_u8()?, pkt.read_u8()?, pkt.read_u16()?))
+        } else {
+            None
+        };
+        realms.push(RealmEntry { name, address, icon, locked, flags, population, characters, category, version });
+    }
+    Ok(realms)
+}
+
+/// Four character code as the client sends it: reversed and zero padded
+fn four_cc(code: &str) -> [u8; 4] {
+    let mut bytes = [0u8; 4];
+    for (slot, byte) in bytes.iter_mut().zip(code.bytes().rev()) {
+        *slot = byte;
+    }
+    bytes
+}
+
+fn result_name(result: u8) -> String {
+    match AuthLogonResult::from_u8(result) {
+        Some(result) => format!("{:?}", result),
+        None => format!("result 0x{:02X}", result),
+    }
+}
+
+async fn read_bytes(stream: &mut TcpStream, len: usize) -> Result<Vec<u8>, String> {
+    let mut buf = vec![0u8; len];
+    stream
+        .read_exact(&mut buf)
+        .await
+        .map_err(|e| format!("connection: {}", e.kind()))?;
+    Ok(buf)
+}
+
+/// Log in as `name` at `target` and request the realm list; the body of the
+/// realm list packet, or the failure reason
+pub async fn login(target: &str, info: &RealmBuildInfo, name: &str, password: &str) -> Result<Vec<u8>, String> {
+    let mut stream = TcpStream::connect(target)
+        .await
+        .map_err(|e| format!("connect: {}", e.kind()))?;
+    let account = name.to_uppercase();
+
+    // Logon challenge
+    let mut body = ByteBuffer::new();
+    body.append(b"WoW\0");
+    body.write_u8(info.major_version);
+    body.write_u8(info.minor_version);
+    body.write_u8(info.bugfix_version);
+    body.write_u16(info.build);
+    body.append(&four_cc("x86"));
+    body.append(&four_cc("Win"));
+    body.append(&four_cc("enUS"));
+    body.write_u32(0); // timezone bias
+    body.append(&[127, 0, 0, 1]);
+    body.write_u8(account.len() as u8);
+    body.append(account.as_bytes());
+    let mut packet = ByteBuffer::new();
+    packet.write_u8(AuthCmd::LogonChallenge as u8);
+    packet.write_u8(8);
+    packet.write_u16(body.contents().len() as u16);
+    packet.append(body.contents());
+    stream
+        .write_all(packet.contents())
+        .await
+        .map_err(|e| format!("connection: {}", e.kind()))?;
+
+    let header = read_bytes(&mut stream, 3).await?;
+    if header[2] != AuthLogonResult::Success as u8 {
+        return Err(format!("challenge: {}", result_name(header[2])));
+    }
+    let b = read_bytes(&mut stream, 32).await?;
+    let g_len = read_bytes(&mut stream, 1).await?[0] as usize;
+    let g = read_bytes(&mut stream, g_len).await?;
+    let n_len = read_bytes(&mut stream, 1).await?[0] as usize;
+    let n = read_bytes(&mut stream, n_len).await?;
+    let salt = read_bytes(&mut stream, 32).await?;
+    let security = read_bytes(&mut stream, 17).await?[16];
+    if security != 0 {
+        return Err("account needs an authenticator, PIN or matrix card".to_string());
+    }
+
+    // Logon proof
+    let mut client = SRP6Client::new(name, password);
+    if !client.process_challenge(&b, &g, &n, &salt) {
+        return Err("invalid challenge".to_string());
+    }
+    let mut packet = ByteBuffer::new();
+    packet.write_u8(AuthCmd::LogonProof as u8);
+    packet.append(&client.get_client_public_ephemeral().as_byte_array(32));
+    packet.append(&client.get_proof().as_byte_array(20));
+    packet.append(&[0u8; 20]); // client hash
+    packet.write_u8(0); // number of keys
+    packet.write_u8(0); // security flags
+    stream
+        .write_all(packet.contents())
+        .await
+        .map_err(|e| format!("connection: {}", e.kind()))?;
+
+    let header = read_bytes(&mut stream, 2).await?;
+    if header[1] != AuthLogonResult::Success as u8 {
+        return Err(format!("proof: {}", result_name(header[1])));
+    }
+    let m2 = read_bytes(&mut stream, 20).await?;
+    let trailer = if matches!(info.build, 5875 | 6005 | 6141) { 4 } else { 10 };
+    read_bytes(&mut stream, trailer).await?;
+    if !client.verify_server_proof(&m2) {
+        return Err("server proof mismatch".to_string());
+    }
+
+    // Realm list
+    stream
+        .write_all(&[AuthCmd::RealmList as u8, 0, 0, 0, 0])
+        .await
+        .map_err(|e| format!("connection: {}", e.kind()))?;
+    let header = read_bytes(&mut stream, 3).await?;
+    if header[0] != AuthCmd::RealmList as u8 {
+        return Err("unexpected realm list reply".to_string());
+    }
+    read_bytes(&mut stream, u16::from_le_bytes([header[1], header[2]]) as usize).await
+}
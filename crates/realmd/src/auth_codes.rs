@@ -61,6 +61,33 @@ pub enum AuthLogonResult {
     FailedUseBnet = 0x12,
 }
 
+impl AuthLogonResult {
+    pub fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            0x00 => Some(AuthLogonResult::Success),
+            0x01 => Some(AuthLogonResult::FailedUnknown0),
+            0x02 => Some(AuthLogonResult::FailedUnknown1),
+            0x03 => Some(AuthLogonResult::FailedBanned),
+            0x04 => Some(AuthLogonResult::FailedUnknownAccount),
+            0x05 => Some(AuthLogonResult::FailedIncorrectPassword),
+            0x06 => Some(AuthLogonResult::FailedAlreadyOnline),
+            0x07 => Some(AuthLogonResult::FailedNoTime),
+            0x08 => Some(AuthLogonResult::FailedDbBusy),
+            0x09 => Some(AuthLogonResult::FailedVersionInvalid),
+            0x0A => Some(AuthLogonResult::FailedVersionUpdate),
+            0x0B => Some(AuthLogonResult::FailedInvalidServer),
+            0x0C => Some(AuthLogonResult::FailedSuspended),
+            0x0D => Some(AuthLogonResult::FailedFailNoaccess),
+            0x0E => Some(AuthLogonResult::SuccessSurvey),
+            0x0F => Some(AuthLogonResult::FailedParentcontrol),
+            0x10 => Some(AuthLogonResult::FailedLockedEnforced),
+            0x11 => Some(AuthLogonResult::FailedTrialEnded),
+            0x12 => Some(AuthLogonResult::FailedUseBnet),
+            _ => None,
+        }
+    }
+}
+
 /// Account flags
 #[derive(Debug, Clone, Copy)]
 #[repr(u32)]
@@ -78,7 +105,7 @@ pub enum AccountFlags {
 pub enum SecurityFlags {
     None = 0x00,
     Pin = 0x01,
-    Unk = 0x02,
+    MatrixCard = 0x02,
     Authenticator = 0x04,
 }
 
@@ -0,0 +1,167 @@
+// AuthLog - Structured record of authentication decisions
+//
+// Every accepted or rejected login attempt is logged as one event on the
+// "authlog" target with its account, address, build, result code and reason,
+// so a sink with LogSink.authlog.Format = "json" gives a machine-readable trail
+// for compliance and abuse investigations. With AuthLog.Database enabled the
+// same records are batched into the auth_log table by a background task, and
+// rows older than AuthLog.RetentionDays are removed hourly. On shutdown the
+// queued records are flushed before the process exits.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+use tokio::sync::mpsc;
+
+use mangos_shared::database::Database;
+use mangos_shared::log::{LOG_TARGET_AUTH_LOG, LOG_TARGET_DB_ERROR};
+use mangos_shared::util::unix_now;
+use mangos_shared::DAY;
+
+use crate::auth_codes::AuthLogonResult;
+
+/// Records waiting for the database before new ones are dropped
+const QUEUE_SIZE: usize = 4096;
+
+/// Most records written by one INSERT
+const BATCH_SIZE: usize = 100;
+
+/// Time between retention cleanups
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Step of the login the decision was taken in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthStage {
+    Challenge,
+    Proof,
+    Reconnect,
+}
+
+impl AuthStage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuthStage::Challenge => "challenge",
+            AuthStage::Proof => "proof",
+            AuthStage::Reconnect => "reconnect",
+        }
+    }
+}
+
+struct AuthDecision {
+    time: u64,
+    stage: AuthStage,
+    account: String,
+    ip: IpAddr,
+    build: u16,
+    result: AuthLogonResult,
+    reason: &'static str,
+}
+
+static QUEUE: OnceCell<mpsc::Sender<AuthDecision>> = OnceCell::new();
+
+/// Records queued or being written
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Record one authentication decision
+pub fn record(stage: AuthStage, account: &str, ip: IpAddr, build: u16, result: AuthLogonResult, reason: &'static str) {
+    tracing::info!(
+        target: LOG_TARGET_AUTH_LOG,
+        stage = stage.as_str(),
+        account,
+        ip = %ip,
+        build,
+        result = result as u8,
+        result_name = ?result,
+        reason,
+        "auth decision"
+    );
+
+    if let Some(queue) = QUEUE.get() {
+        let decision = AuthDecision {
+            time: unix_now(),
+            stage,
+            account: account.to_string(),
+            ip,
+            build,
+            result,
+            reason,
+        };
+        PENDING.fetch_add(1, Ordering::SeqCst);
+        if queue.try_send(decision).is_err() {
+            PENDING.fetch_sub(1, Ordering::SeqCst);
+            tracing::warn!("Auth log queue full, a decision was not written to auth_log");
+        }
+    }
+}
+
+/// Start writing decisions to the auth_log table and pruning rows older than
+/// `retention_days` (0 = keep forever)
+pub fn start(db: Arc<Database>, retention_days: u32) {
+    let (queue, decisions) = mpsc::channel(QUEUE_SIZE);
+    if QUEUE.set(queue).is_err() {
+        return;
+    }
+    tokio::spawn(write_decisions(db.clone(), decisions));
+    if retention_days > 0 {
+        tokio::spawn(cleanup(db, retention_days));
+    }
+}
+
+/// Wait up to `timeout` for the queued records to reach the database; returns
+/// the number still unwritten
+pub async fn flush(timeout: Duration) -> usize {
+    let deadline = Instant::now() + timeout;
+    while PENDING.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    PENDING.load(Ordering::SeqCst)
+}
+
+async fn write_decisions(db: Arc<Database>, mut decisions: mpsc::Receiver<AuthDecision>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while decisions.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        let count = batch.len();
+        let values: Vec<String> = batch
+            .drain(..)
+            .map(|d| {
+                format!(
+                    "('{}', '{}', '{}', '{}', '{}', '{}', '{}')",
+                    d.time,
+                    d.stage.as_str(),
+                    Database::escape_string(&d.account),
+                    d.ip,
+                    d.build,
+                    d.result as u8,
+                    d.reason
+                )
+            })
+            .collect();
+        let sql = format!(
+            "INSERT INTO auth_log(time, stage, account, ip, build, result, reason) VALUES {}",
+            values.join(", ")
+        );
+        if let Err(e) = db.execute(&sql).await {
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot write {} auth_log row(s): {:#}", count, e);
+        }
+        PENDING.fetch_sub(count, Ordering::SeqCst);
+    }
+}
+
+async fn cleanup(db: Arc<Database>, retention_days: u32) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let sql = format!(
+            "DELETE FROM auth_log WHERE time < {}",
+            unix_now().saturating_sub(u64::from(retention_days) * u64::from(DAY))
+        );
+        match db.execute(&sql).await {
+            Ok(0) => {}
+            Ok(removed) => tracing::debug!("Removed {} auth_log row(s) older than {} days", removed, retention_days),
+            Err(e) => tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot clean up auth_log: {:#}", e),
+        }
+    }
+}
@@ -9,91 +9,344 @@
 // 1. ReconnectChallenge -> random proof
 // 2. ReconnectProof -> verify session
 
+use std::cell::Cell;
+use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use chrono::Timelike;
+use once_cell::sync::{Lazy, OnceCell};
+use tokio::io::AsyncReadExt;
 use tokio::net::TcpStream;
-use tokio::time::{timeout, Duration};
-
-use mangos_shared::auth::{BigNumber, Sha1Hash, SRP6, base32_decode};
-use mangos_shared::auth::hmac_sha1::hmac_sha1;
-use mangos_shared::config::get_config;
+use tokio::time::{timeout, Duration, Instant};
+use zeroize::Zeroizing;
+use tracing::Instrument;
+
+use mangos_shared::auth::{BigNumber, MatrixCard, Sha1Hash, SRP6, constant_time_eq};
+use mangos_shared::auth::session_key;
+use mangos_shared::auth::totp::{self, TotpParams};
+use mangos_shared::config::{get_config, Config};
 use mangos_shared::database::{Database, FieldExt};
-use mangos_shared::util::ByteBuffer;
+use mangos_shared::log::{dump_packet, packet_dump_enabled, LOG_TARGET_AUDIT, LOG_TARGET_DB_ERROR};
+use mangos_shared::network::{
+    ip_matches, normalize_ip, parse_country_list, read_exact_timeout, read_frame, write_all_timeout, GeoIp, IpRateLimiter,
+    ThrottledReader,
+};
+use mangos_shared::util::{local_datetime, unix_now, ByteBuffer};
 use mangos_shared::{SEC_ADMINISTRATOR, SEC_PLAYER, AccountTypes, RealmFlags, LOGIN_TYPE_REALMD};
 
+use crate::account_mgr::{self, AutoCreatePolicy, RealmAccess};
 use crate::auth_codes::*;
+use crate::auth_log::{self, AuthStage};
+use crate::client_stats;
+use crate::ip_reputation;
+use crate::logon_lookup;
+use crate::maintenance;
+use crate::patcher::{self, PatchInfo};
 use crate::protocol::*;
-use crate::realm_list::{self, RealmList, find_build_info, get_realm_category_id};
+use crate::realm_list::{self, RealmList, RealmListPacket, find_build_info, get_realm_category_id};
+use crate::shared_state::{self, SCOPE_CHALLENGE};
+use crate::shutdown;
+use crate::strikes;
+use crate::tarpit;
+use crate::web_token;
+use crate::webhook::{self, SecurityEvent};
+
+/// Client connection; reads are rate limited until the client has authenticated
+type ClientStream = ThrottledReader<TcpStream>;
 
 /// Read exactly `buf.len()` bytes with a timeout.
 /// Returns an error if the read times out or fails.
-async fn read_with_timeout(stream: &mut TcpStream, buf: &mut [u8], dur: Duration) -> anyhow::Result<()> {
-    timeout(dur, stream.read_exact(buf))
-        .await
-        .map_err(|_| anyhow::anyhow!("read timeout"))??;
+async fn read_with_timeout(stream: &mut ClientStream, buf: &mut [u8], dur: Duration) -> anyhow::Result<()> {
+    read_exact_timeout(stream, buf, dur).await?;
+    trace_packet(stream, "RECV", buf);
     Ok(())
 }
 
+/// Read a packet body of `len` bytes announced by its header, refusing anything above `max_len`
+async fn read_body_with_timeout(stream: &mut ClientStream, len: usize, max_len: usize, dur: Duration) -> anyhow::Result<Vec<u8>> {
+    let body = read_frame(stream, len, max_len, dur).await?;
+    trace_packet(stream, "RECV", &body);
+    Ok(body)
+}
+
+tokio::task_local! {
+    /// Earliest time the response to the current logon request may be sent
+    static RESPONSE_NOT_BEFORE: Cell<Option<Instant>>;
+}
+
+/// Country database for GeoIp.DenyCountries and account.lock_country, if loaded
+static GEOIP: OnceCell<GeoIp> = OnceCell::new();
+
+/// Install the country database loaded at startup
+pub fn set_geoip(geoip: GeoIp) {
+    let _ = GEOIP.set(geoip);
+}
+
+/// Random per-process secret used to derive stable decoy salts
+static DECOY_SALT_SECRET: Lazy<[u8; 32]> = Lazy::new(|| {
+    let mut secret = [0u8; 32];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut secret);
+    secret
+});
+
+/// Hold back the next response until `min_time` has passed since now.
+/// Used so failing logon requests take as long as succeeding ones (Auth.MinResponseTime).
+fn delay_next_response(min_time: Duration) {
+    if !min_time.is_zero() {
+        let _ = RESPONSE_NOT_BEFORE.try_with(|t| t.set(Some(Instant::now() + min_time)));
+    }
+}
+
 /// Write all bytes with a timeout.
 /// Returns an error if the write times out or fails.
-async fn write_with_timeout(stream: &mut TcpStream, data: &[u8], dur: Duration) -> anyhow::Result<()> {
-    timeout(dur, stream.write_all(data))
-        .await
-        .map_err(|_| anyhow::anyhow!("write timeout"))??;
-    Ok(())
+async fn write_with_timeout(stream: &mut ClientStream, data: &[u8], dur: Duration) -> anyhow::Result<()> {
+    if let Some(not_before) = RESPONSE_NOT_BEFORE.try_with(|t| t.take()).ok().flatten() {
+        tokio::time::sleep_until(not_before).await;
+    }
+    trace_packet(stream, "SEND", data);
+    write_all_timeout(stream, data, dur).await
+}
+
+/// Hex-dump packet data when Log.PacketDump is enabled
+fn trace_packet(stream: &ClientStream, direction: &str, data: &[u8]) {
+    if packet_dump_enabled() {
+        let peer = stream.get_ref().peer_addr().map_or_else(|_| "?".to_string(), |a| a.to_string());
+        dump_packet(direction, peer, data);
+    }
+}
+
+/// Run a future (typically database work) unless the client disconnects first.
+///
+/// Dropping the inner future cancels any in-flight query and releases its pool
+/// connection, so abandoned sessions stop consuming DB slots during login floods.
+/// The outer error means the client went away; the inner value is the future's output.
+async fn until_disconnect<F: Future>(stream: &ClientStream, fut: F) -> anyhow::Result<F::Output> {
+    tokio::select! {
+        output = fut => Ok(output),
+        _ = wait_for_disconnect(stream) => Err(anyhow::anyhow!("client disconnected, abandoning pending work")),
+    }
+}
+
+/// Resolve once the peer has closed (or reset) the connection.
+/// If the client has already sent more data it is clearly alive, so never resolve.
+async fn wait_for_disconnect(stream: &ClientStream) {
+    let mut probe = [0u8; 1];
+    match stream.get_ref().peek(&mut probe).await {
+        Ok(0) | Err(_) => {}
+        Ok(_) => std::future::pending::<()>().await,
+    }
+}
+
+/// What the client told about itself in its (reconnect) challenge
+struct ClientInfo {
+    login: String,
+    /// `login` escaped for SQL
+    safe_login: String,
+    build: u16,
+    /// Escaped for SQL
+    os: String,
+    platform: String,
+    locale: String,
+    /// `locale` escaped for SQL
+    safe_locale: String,
+}
+
+/// PIN prompt sent with a logon challenge (builds <= 6141)
+struct PinChallenge {
+    grid_seed: u32,
+    server_security_salt: BigNumber,
 }
 
-/// Session status state machine
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum SessionStatus {
+/// Logon challenge answered, waiting for the client's proof
+struct LogonProofState {
+    client: ClientInfo,
+    srp: SRP6,
+    /// Authenticator secret, or the PIN digits for builds <= 6141
+    token: Zeroizing<String>,
+    account_security_level: AccountTypes,
+    pin: Option<PinChallenge>,
+    matrix_challenge: Option<MatrixChallenge>,
+    /// The account does not exist, the proof fails whatever the client sends
+    decoy: bool,
+}
+
+/// Reconnect challenge answered, waiting for the client's proof
+struct ReconnectProofState {
+    client: ClientInfo,
+    account_security_level: AccountTypes,
+    /// Session key of the previous login
+    session_key: Zeroizing<BigNumber>,
+    reconnect_proof: Zeroizing<BigNumber>,
+}
+
+/// Logged in (or reconnected), may ask for the realm list
+struct AuthedState {
+    client: ClientInfo,
+    account_security_level: AccountTypes,
+}
+
+/// Session state machine
+///
+/// Each state owns only the data valid in it. A handler takes over the state
+/// its command is accepted in and returns the next one, so a command in the
+/// wrong state has nothing to work with and the session ends.
+enum Session {
+    /// Waiting for a logon or reconnect challenge
     Challenge,
-    LogonProof,
-    ReconProof,
-    Patch,
-    Authed,
+    LogonProof(Box<LogonProofState>),
+    ReconnectProof(Box<ReconnectProofState>),
+    /// Client build refused, downloading this patch
+    Patch(PatchInfo),
+    Authed(AuthedState),
     Closed,
 }
 
+impl Session {
+    fn name(&self) -> &'static str {
+        match self {
+            Session::Challenge => "Challenge",
+            Session::LogonProof(_) => "LogonProof",
+            Session::ReconnectProof(_) => "ReconProof",
+            Session::Patch(_) => "Patch",
+            Session::Authed(_) => "Authed",
+            Session::Closed => "Closed",
+        }
+    }
+}
+
 /// Handle a single authentication session
+///
+/// Everything logged by the session runs inside a "session" span carrying the
+/// peer address; the account name and client build are added once the
+/// challenge has been read.
 pub async fn handle_client(
-    mut stream: TcpStream,
+    stream: TcpStream,
     addr: SocketAddr,
     db: Arc<Database>,
-    realm_list: Arc<tokio::sync::RwLock<RealmList>>,
-    timeout_secs: u64,
+    realm_list: Arc<RealmList>,
+    challenge_limiter: Arc<IpRateLimiter>,
+    timeouts: SessionTimeouts,
 ) {
-    tracing::debug!("[{}] New connection accepted", addr);
+    let span = tracing::info_span!(
+        "session",
+        peer = %addr,
+        account = tracing::field::Empty,
+        build = tracing::field::Empty
+    );
+    RESPONSE_NOT_BEFORE
+        .scope(Cell::new(None), run_session(stream, addr, db, realm_list, challenge_limiter, timeouts))
+        .instrument(span)
+        .await;
+}
 
-    let mut status = SessionStatus::Challenge;
-    let mut srp = SRP6::new();
-    let mut reconnect_proof = BigNumber::new();
-    let mut login = String::new();
-    let mut safe_login = String::new();
-    let mut token = String::new();
-    let mut os = String::new();
-    let mut platform = String::new();
-    let mut locale = String::new();
-    let mut safe_locale = String::new();
-    let mut build: u16 = 0;
-    let mut account_security_level: AccountTypes = SEC_PLAYER;
-    let mut server_security_salt = BigNumber::new();
-    let mut grid_seed: u32 = 0;
-    let mut prompt_pin = false;
-
-    // Configurable connection timeout for all I/O operations
-    let timeout_duration = Duration::from_secs(timeout_secs);
+/// Time limits of a client session
+#[derive(Debug, Clone, Copy)]
+pub struct SessionTimeouts {
+    /// Idle time allowed until the client authenticated
+    pub pre_auth: Duration,
+    /// Idle time allowed between realm list requests once it authenticated
+    pub authed: Duration,
+    /// Time after which the session is closed in any state (zero = unlimited)
+    pub lifetime: Duration,
+}
+
+impl SessionTimeouts {
+    pub fn from_config(config: &Config) -> Self {
+        let secs = |key: &str, default: i32| Duration::from_secs(config.get_int_default(key, default).max(0) as u64);
+        SessionTimeouts {
+            pre_auth: secs("PreAuthTimeout", 10).max(Duration::from_secs(1)),
+            authed: secs("ConnectionTimeout", 30).max(Duration::from_secs(1)),
+            lifetime: secs("SessionLifetime", 0),
+        }
+    }
+
+    /// Idle time allowed in `session`
+    fn idle(&self, session: &Session) -> Duration {
+        match session {
+            Session::Authed(_) => self.authed,
+            _ => self.pre_auth,
+        }
+    }
+}
+
+/// Resolves at `deadline`, never without one
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Attach the account name and client build to the current session span
+fn record_session_account(login: &str, build: u16) {
+    let span = tracing::Span::current();
+    span.record("account", login);
+    span.record("build", build);
+}
+
+async fn run_session(
+    stream: TcpStream,
+    addr: SocketAddr,
+    db: Arc<Database>,
+    realm_list: Arc<RealmList>,
+    challenge_limiter: Arc<IpRateLimiter>,
+    timeouts: SessionTimeouts,
+) {
+    tracing::debug!("New connection accepted");
+
+    let mut session = Session::Challenge;
+
+    // The session ends at this point whatever state it is in
+    let deadline = (!timeouts.lifetime.is_zero()).then(|| Instant::now() + timeouts.lifetime);
+
+    // Minimum time before answering a logon challenge/proof, hides which check failed
+    let min_response_time = {
+        let config = get_config().lock();
+        Duration::from_millis(config.get_int_default("Auth.MinResponseTime", 0).max(0) as u64)
+    };
+
+    // Until the client has authenticated, cap how fast it can feed us bytes
+    let mut stream = {
+        let config = get_config().lock();
+        match config.get_int_default("Network.PreAuthReadRate", 4096) {
+            rate if rate > 0 => {
+                let burst = config.get_int_default("Network.PreAuthReadBurst", 1024).max(1);
+                ThrottledReader::new(stream, rate as u32, burst as u32)
+            }
+            _ => ThrottledReader::unlimited(stream),
+        }
+    };
 
     loop {
-        // Read the command byte
-        let cmd_byte = match timeout(timeout_duration, stream.read_u8()).await {
-            Ok(Ok(byte)) => byte,
+        // Idle limit for all I/O until the next command, shorter before authentication
+        let timeout_duration = timeouts.idle(&session);
+
+        // Read the command byte, unless the server is closing the remaining sessions
+        let read = tokio::select! {
+            read = timeout(timeout_duration, stream.read_u8()) => read,
+            _ = sleep_until_deadline(deadline) => {
+                tracing::debug!("Closing session in state {} after {}s (SessionLifetime)", session.name(), timeouts.lifetime.as_secs());
+                return;
+            }
+            _ = shutdown::sessions_closing() => {
+                tracing::debug!("Closing session in state {} for shutdown", session.name());
+                if let Err(e) = send_shutdown_notice(&mut stream, &addr, &session, timeout_duration).await {
+                    tracing::debug!("Cannot send shutdown notice: {:#}", e);
+                }
+                return;
+            }
+        };
+        let cmd_byte = match read {
+            Ok(Ok(byte)) => {
+                dump_packet("RECV", addr, &[byte]);
+                byte
+            }
             Ok(Err(e)) => {
-                tracing::debug!("[{}] Connection closed: {}", addr, e);
+                tracing::debug!("Connection closed: {}", e);
                 return;
             }
             Err(_) => {
-                tracing::debug!("[{}] Connection timeout after {}s of inactivity", addr, timeout_duration.as_secs());
+                tracing::debug!("Connection timeout after {}s of inactivity", timeout_duration.as_secs());
                 return;
             }
         };
@@ -101,328 +354,370 @@ pub async fn handle_client(
         let cmd = match AuthCmd::from_u8(cmd_byte) {
             Some(cmd) => cmd,
             None => {
-                tracing::debug!("[{}] Received unknown command byte: 0x{:02X}", addr, cmd_byte);
+                strikes::record(addr.ip(), &format!("unknown command byte 0x{:02X}", cmd_byte));
                 return;
             }
         };
 
-        tracing::debug!("[{}] Received command {:?} (0x{:02X}) in state {:?}", addr, cmd, cmd_byte, status);
-
-        // Check if the command is valid for the current status
-        let expected_status = match cmd {
-            AuthCmd::LogonChallenge => SessionStatus::Challenge,
-            AuthCmd::LogonProof => SessionStatus::LogonProof,
-            AuthCmd::ReconnectChallenge => SessionStatus::Challenge,
-            AuthCmd::ReconnectProof => SessionStatus::ReconProof,
-            AuthCmd::RealmList => SessionStatus::Authed,
-            AuthCmd::XferAccept | AuthCmd::XferResume | AuthCmd::XferCancel => SessionStatus::Patch,
-            _ => SessionStatus::Closed,
-        };
+        tracing::debug!("Received command {:?} (0x{:02X}) in state {}", cmd, cmd_byte, session.name());
 
-        if expected_status != status {
-            tracing::debug!(
-                "[{}] Unauthorized command {:?} in state {:?} (expected {:?}), disconnecting",
-                addr, cmd, status, expected_status
-            );
-            return;
-        }
-
-        let result = match cmd {
-            AuthCmd::LogonChallenge => {
-                handle_logon_challenge(
-                    &mut stream,
-                    &addr,
-                    &db,
-                    &mut status,
-                    &mut srp,
-                    &mut login,
-                    &mut safe_login,
-                    &mut token,
-                    &mut os,
-                    &mut platform,
-                    &mut locale,
-                    &mut safe_locale,
-                    &mut build,
-                    &mut account_security_level,
-                    &mut server_security_salt,
-                    &mut grid_seed,
-                    &mut prompt_pin,
-                    timeout_duration,
-                )
-                .await
+        // Only the state expecting the command can handle it
+        let result = match (cmd, std::mem::replace(&mut session, Session::Closed)) {
+            (AuthCmd::LogonChallenge, Session::Challenge) => {
+                // Stall and drop clients sending logon challenges too fast (password spraying)
+                if !shared_state::check_rate(&challenge_limiter, SCOPE_CHALLENGE, addr.ip()).await {
+                    tracing::info!(target: LOG_TARGET_AUDIT, "Too many logon attempts from {}, disconnecting", addr.ip());
+                    auth_log::record(AuthStage::Challenge, "", addr.ip(), 0, AuthLogonResult::FailedFailNoaccess, "rate limited");
+                    tokio::time::sleep(challenge_limiter.tarpit()).await;
+                    return;
+                }
+                delay_next_response(min_response_time);
+                handle_logon_challenge(&mut stream, &addr, &db, timeout_duration)
+                    .instrument(tracing::info_span!("logon_challenge"))
+                    .await
             }
-            AuthCmd::LogonProof => {
-                handle_logon_proof(
-                    &mut stream,
-                    &addr,
-                    &db,
-                    &mut status,
-                    &mut srp,
-                    &login,
-                    &safe_login,
-                    &safe_locale,
-                    &token,
-                    &os,
-                    &platform,
-                    build,
-                    prompt_pin,
-                    &server_security_salt,
-                    grid_seed,
-                    &mut account_security_level,
-                    timeout_duration,
-                )
-                .await
+            (AuthCmd::LogonProof, Session::LogonProof(state)) => {
+                // Recent failures for this account or address slow the answer down further
+                let tarpit_delay = tarpit::delay_for(addr.ip(), &state.client.login).await;
+                delay_next_response(min_response_time.max(tarpit_delay));
+                handle_logon_proof(&mut stream, &addr, &db, *state, timeout_duration)
+                    .instrument(tracing::info_span!("logon_proof"))
+                    .await
             }
-            AuthCmd::ReconnectChallenge => {
-                handle_reconnect_challenge(
-                    &mut stream,
-                    &addr,
-                    &db,
-                    &mut status,
-                    &mut srp,
-                    &mut login,
-                    &mut safe_login,
-                    &mut build,
-                    &mut reconnect_proof,
-                    timeout_duration,
-                )
-                .await
+            (AuthCmd::ReconnectChallenge, Session::Challenge) => {
+                handle_reconnect_challenge(&mut stream, &addr, &db, timeout_duration)
+                    .instrument(tracing::info_span!("reconnect_challenge"))
+                    .await
             }
-            AuthCmd::ReconnectProof => {
-                handle_reconnect_proof(
-                    &mut stream,
-                    &addr,
-                    &db,
-                    &mut status,
-                    &srp,
-                    &login,
-                    &reconnect_proof,
-                    build,
-                    &os,
-                    timeout_duration,
-                )
-                .await
+            (AuthCmd::ReconnectProof, Session::ReconnectProof(state)) => {
+                handle_reconnect_proof(&mut stream, &addr, *state, timeout_duration)
+                    .instrument(tracing::info_span!("reconnect_proof"))
+                    .await
             }
-            AuthCmd::RealmList => {
-                handle_realm_list(
-                    &mut stream,
-                    &addr,
-                    &db,
-                    &realm_list,
-                    &safe_login,
-                    &login,
-                    build,
-                    account_security_level,
-                    timeout_duration,
-                )
-                .await
+            (AuthCmd::RealmList, Session::Authed(state)) => {
+                handle_realm_list(&mut stream, &addr, &db, &realm_list, &state, timeout_duration)
+                    .instrument(tracing::info_span!("realm_list"))
+                    .await
+                    .map(|()| Session::Authed(state))
             }
-            AuthCmd::XferResume => {
-                tracing::debug!("[{}] XferResume - skipping 8 bytes", addr);
-                let mut buf = [0u8; 8];
-                if read_with_timeout(&mut stream, &mut buf, timeout_duration).await.is_err() {
-                    tracing::debug!("[{}] XferResume read timeout/error", addr);
-                    return;
-                }
-                Ok(())
+            (AuthCmd::XferResume, Session::Patch(patch)) => {
+                handle_xfer_resume(&mut stream, &patch, timeout_duration)
+                    .instrument(tracing::info_span!("xfer"))
+                    .await
+                    .map(|()| Session::Patch(patch))
+            }
+            (AuthCmd::XferAccept, Session::Patch(patch)) => {
+                send_patch(&mut stream, &patch, 0, timeout_duration)
+                    .instrument(tracing::info_span!("xfer"))
+                    .await
+                    .map(|()| Session::Patch(patch))
             }
-            AuthCmd::XferCancel => {
-                tracing::debug!("[{}] XferCancel - disconnecting", addr);
+            (AuthCmd::XferCancel, Session::Patch(_)) => {
+                tracing::debug!("XferCancel - disconnecting");
                 return;
             }
-            AuthCmd::XferAccept => {
-                tracing::debug!("[{}] XferAccept received", addr);
-                Ok(())
+            (cmd, state) => {
+                strikes::record(addr.ip(), &format!("command {:?} in state {}", cmd, state.name()));
+                return;
             }
-            _ => {
-                tracing::debug!("[{}] Unhandled command {:?}", addr, cmd);
+        };
+
+        session = match result {
+            Ok(next) => next,
+            Err(e) => {
+                // Packets that fail to parse count against the address, I/O errors don't
+                match e.downcast_ref::<ParseError>() {
+                    Some(parse_error) => strikes::record(addr.ip(), &parse_error.to_string()),
+                    None => tracing::debug!("Handler error for {:?}: {}", cmd, e),
+                }
                 return;
             }
         };
 
-        if let Err(e) = result {
-            tracing::debug!("[{}] Handler error for {:?}: {}", addr, cmd, e);
-            return;
+        match session {
+            Session::Closed => {
+                tracing::debug!("Session closed, disconnecting");
+                return;
+            }
+            Session::Authed(_) if stream.is_limited() => stream.set_unlimited(),
+            _ => {}
         }
 
-        if status == SessionStatus::Closed {
-            tracing::debug!("[{}] Session closed, disconnecting", addr);
-            return;
-        }
+        tracing::trace!("Command {:?} completed, new state: {}", cmd, session.name());
+    }
+}
+
+/// Handle CMD_XFER_RESUME: continue a patch download from the offset the client already has
+async fn handle_xfer_resume(stream: &mut ClientStream, patch: &PatchInfo, timeout_duration: Duration) -> Result<(), anyhow::Error> {
+    let mut buf = [0u8; XferResume::SIZE];
+    read_with_timeout(stream, &mut buf, timeout_duration).await?;
+    let resume = XferResume::from_bytes(&buf)?;
+
+    if resume.offset >= patch.size {
+        anyhow::bail!("XferResume offset {} beyond patch size {}", resume.offset, patch.size);
+    }
+    send_patch(stream, patch, resume.offset, timeout_duration).await
+}
+
+/// Stream a patch to the client as CMD_XFER_DATA chunks, starting at `offset`
+///
+/// Stops early when the client sends anything (XferCancel, XferResume or a
+/// disconnect), leaving it for the session loop to handle.
+async fn send_patch(stream: &mut ClientStream, patch: &PatchInfo, offset: u64, timeout_duration: Duration) -> Result<(), anyhow::Error> {
+    use tokio::io::AsyncSeekExt;
 
-        tracing::trace!("[{}] Command {:?} completed, new state: {:?}", addr, cmd, status);
+    let mut file = tokio::fs::File::open(&patch.path).await?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    tracing::debug!("Sending patch {} from offset {}", patch.path.display(), offset);
+
+    let mut chunk = vec![0u8; patcher::XFER_CHUNK_SIZE];
+    let mut sent = offset;
+    loop {
+        if has_pending_input(stream) {
+            tracing::debug!("Patch transfer interrupted by client at offset {}", sent);
+            return Ok(());
+        }
+        let read = file.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        write_with_timeout(stream, &XferData::to_bytes(&chunk[..read]), timeout_duration).await?;
+        sent += read as u64;
     }
+
+    tracing::debug!("Patch {} sent ({} bytes)", patch.path.display(), sent - offset);
+    Ok(())
+}
+
+/// True when the client has sent data (or closed the connection) that hasn't been read yet
+fn has_pending_input(stream: &ClientStream) -> bool {
+    let mut probe = [0u8; 1];
+    let mut buf = tokio::io::ReadBuf::new(&mut probe);
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    stream.get_ref().poll_peek(&mut cx, &mut buf).is_ready()
 }
 
 /// Handle CMD_AUTH_LOGON_CHALLENGE
-#[allow(clippy::too_many_arguments)]
 async fn handle_logon_challenge(
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
     addr: &SocketAddr,
     db: &Database,
-    status: &mut SessionStatus,
-    srp: &mut SRP6,
-    login: &mut String,
-    safe_login: &mut String,
-    token: &mut String,
-    os: &mut String,
-    platform: &mut String,
-    locale: &mut String,
-    safe_locale: &mut String,
-    build: &mut u16,
-    _account_security_level: &mut AccountTypes,
-    server_security_salt: &mut BigNumber,
-    grid_seed: &mut u32,
-    prompt_pin: &mut bool,
     timeout_duration: Duration,
-) -> Result<(), anyhow::Error> {
+) -> Result<Session, anyhow::Error> {
     // Read header (3 bytes: error + size)
     let mut header_buf = [0u8; AuthLogonChallengeHeader::SIZE];
     read_with_timeout(stream, &mut header_buf, timeout_duration).await?;
 
-    let header = AuthLogonChallengeHeader::from_bytes(&header_buf)
-        .ok_or_else(|| anyhow::anyhow!("Invalid logon challenge header"))?;
-
-    let remaining = header.size as usize;
-    tracing::trace!("[{}] LogonChallenge header: size={}", addr, remaining);
-
-    if remaining < AuthLogonChallengeBody::MIN_SIZE - AUTH_LOGON_MAX_NAME {
-        tracing::debug!("[{}] LogonChallenge body too small: {} bytes", addr, remaining);
-        return Err(anyhow::anyhow!("Logon challenge body too small"));
-    }
-
-    // Session is closed unless overridden
-    *status = SessionStatus::Closed;
+    let header = AuthLogonChallengeHeader::from_bytes(&header_buf)?;
+    let remaining = header.body_size()?;
+    tracing::trace!("LogonChallenge header: size={}", remaining);
 
     // Read the body
-    let mut body_buf = vec![0u8; remaining];
-    read_with_timeout(stream, &mut body_buf, timeout_duration).await?;
+    let body_buf = read_body_with_timeout(stream, remaining, AuthLogonChallengeBody::MAX_SIZE, timeout_duration).await?;
 
-    let body = AuthLogonChallengeBody::from_bytes(&body_buf)
-        .ok_or_else(|| anyhow::anyhow!("Invalid logon challenge body"))?;
-
-    if body.username_len as usize > AUTH_LOGON_MAX_NAME {
-        tracing::debug!("[{}] Username too long: {} chars", addr, body.username_len);
-        return Err(anyhow::anyhow!("Username too long"));
-    }
-
-    // Store client info
-    *login = body.username_string();
-    *build = body.build;
-    *os = body.os_string();
-    *platform = body.platform_string();
-    *locale = body.locale_string();
+    let body = AuthLogonChallengeBody::from_bytes(&body_buf)?;
 
+    record_session_account(&body.username, body.build);
     tracing::debug!(
-        "[{}] LogonChallenge: account='{}' build={} os='{}' platform='{}' locale='{}'",
-        addr, login, build, os, platform, locale
+        "LogonChallenge: account='{}' build={} os='{}' platform='{}' locale='{}'",
+        body.username, body.build, body.os, body.platform, body.locale
     );
 
+    if let Some(reason) = maintenance::reason() {
+        tracing::debug!("Answering '{}' with server busy ({})", body.username, reason);
+        auth_log::record(AuthStage::Challenge, &body.username, addr.ip(), body.build, AuthLogonResult::FailedDbBusy, reason);
+        let response = [AuthCmd::LogonChallenge as u8, 0x00, AuthLogonResult::FailedDbBusy as u8];
+        write_with_timeout(stream, &response, timeout_duration).await?;
+        return Ok(Session::Closed);
+    }
+
     // Escape for SQL safety
-    *safe_login = Database::escape_string(login);
-    *safe_locale = Database::escape_string(locale);
-    let escaped_os = Database::escape_string(os);
-    *os = escaped_os;
+    let client = ClientInfo {
+        safe_login: Database::escape_string(&body.username),
+        safe_locale: Database::escape_string(&body.locale),
+        os: Database::escape_string(&body.os),
+        login: body.username,
+        build: body.build,
+        platform: body.platform,
+        locale: body.locale,
+    };
+    let (login, safe_login, build) = (client.login.as_str(), client.safe_login.as_str(), client.build);
+
+    // Filled in once the challenge is sent; the session is closed otherwise
+    let mut srp = SRP6::new();
+    let mut token = Zeroizing::new(String::new());
+    let mut pin = None;
+    let mut matrix_challenge = None;
+    let mut decoy = false;
+    let mut accepted_security_level: Option<AccountTypes> = None;
 
     let mut pkt = ByteBuffer::new();
     pkt.write_u8(AuthCmd::LogonChallenge as u8);
     pkt.write_u8(0x00);
 
-    // Check IP ban
+    // Check IP and subnet bans
+    let map_v4 = get_config().lock().get_bool_default("MapIPv4MappedAddresses", true);
     let ip_str = addr.ip().to_string();
-    let ip_ban_sql = format!(
-        "SELECT expires_at FROM ip_banned \
-         WHERE (expires_at = banned_at OR expires_at > UNIX_TIMESTAMP()) AND ip = '{}'",
-        Database::escape_string(&ip_str)
-    );
 
-    tracing::trace!("[{}] Checking IP ban for {}", addr, ip_str);
+    // Check the IP reputation providers, cheaper than the database
+    if let Some((provider, reason)) = until_disconnect(stream, ip_reputation::check(normalize_ip(addr.ip(), map_v4))).await? {
+        pkt.write_u8(AuthLogonResult::FailedFailNoaccess as u8);
+        tracing::info!(target: LOG_TARGET_AUDIT, "IP {} refused by {}: {}", ip_str, provider, reason);
+        auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedFailNoaccess, "ip reputation");
+        write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
+        return Ok(Session::Closed);
+    }
+
+    // The account with its active ban, alongside the IP ban, in a single round trip
+    tracing::trace!("Checking IP ban for {} and looking up account '{}'", ip_str, login);
+    let (ip_banned, account) = until_disconnect(stream, logon_lookup::lookup(db, addr.ip(), map_v4, safe_login)).await?;
 
-    if let Ok(Some(_)) = db.query_one(&ip_ban_sql).await {
+    if let Ok(Some(ban)) = ip_banned {
         pkt.write_u8(AuthLogonResult::FailedFailNoaccess as u8);
-        tracing::info!("[{}] Banned IP {} tried to login", addr, ip_str);
+        tracing::info!(target: LOG_TARGET_AUDIT, "Banned IP {} tried to login (ban on {})", ip_str, ban);
+        auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedFailNoaccess, "ip banned");
         write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-        return Ok(());
+        return Ok(Session::Closed);
     }
 
-    // Get account details
-    let account_sql = format!(
-        "SELECT id, CAST(locked AS SIGNED) AS locked, lockedIp, \
-         CAST(gmlevel AS SIGNED) AS gmlevel, \
-         CAST(v AS CHAR) AS v, CAST(s AS CHAR) AS s, \
-         CAST(token AS CHAR) AS token \
-         FROM account WHERE username = '{}'",
-        safe_login
-    );
+    // Check country ban
+    let country = GEOIP.get().and_then(|geoip| geoip.country(addr.ip()));
+    if let Some(country) = &country {
+        tracing::trace!("Client {} resolved to country {}", ip_str, country);
+        let denied = parse_country_list(&get_config().lock().get_string("GeoIp.DenyCountries"));
+        if denied.contains(country) {
+            pkt.write_u8(AuthLogonResult::FailedBanned as u8);
+            tracing::info!(target: LOG_TARGET_AUDIT, "Account '{}' tried to login from denied country {} (IP {})", login, country, ip_str);
+            auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedBanned, "country denied");
+            write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
+            return Ok(Session::Closed);
+        }
+    }
 
-    tracing::trace!("[{}] Looking up account '{}'", addr, login);
+    // Every supported build is listed or newer than the listed ones, so other
+    // clients are outdated. They are told to update right away unless there is a
+    // patch for them, which the client only accepts in answer to its proof.
+    if find_build_info(build).is_none() && find_patch(&client).await?.is_none() {
+        pkt.write_u8(AuthLogonResult::FailedVersionUpdate as u8);
+        tracing::info!("Account '{}' tried to login with outdated build {}", login, build);
+        auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedVersionUpdate, "outdated build");
+        write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
+        return Ok(Session::Closed);
+    }
 
-    match db.query_one(&account_sql).await? {
+    let account = match account {
+        Ok(account) => account,
+        Err(e) => {
+            // Tell the client to come back later instead of dropping it
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot look up account '{}': {:#}", login, e);
+            auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedDbBusy, "database error");
+            pkt.write_u8(AuthLogonResult::FailedDbBusy as u8);
+            write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
+            return Ok(Session::Closed);
+        }
+    };
+    match account {
         Some(row) => {
             let account_id: u32 = row.get_u32(0);
             let locked: u8 = row.get_u8(1);
 
             tracing::debug!(
-                "[{}] Account '{}' found: id={} locked={} gmlevel={}",
-                addr, login, account_id, locked, row.get_u8(3)
+                "Account '{}' found: id={} locked={} gmlevel={}",
+                login, account_id, locked, row.get_u8(3)
             );
 
             // Check IP lock
             if locked == 1 {
                 let locked_ip: String = row.get_string(2);
-                tracing::debug!("[{}] Account '{}' is locked to IP '{}'", addr, login, locked_ip);
-                if locked_ip != ip_str {
-                    tracing::info!("[{}] Account '{}' IP lock mismatch: expected='{}' got='{}'", addr, login, locked_ip, ip_str);
+                tracing::debug!("Account '{}' is locked to IP '{}'", login, locked_ip);
+                if !ip_matches(&locked_ip, addr.ip(), map_v4) {
+                    tracing::info!("Account '{}' IP lock mismatch: expected='{}' got='{}'", login, locked_ip, ip_str);
                     pkt.write_u8(AuthLogonResult::FailedSuspended as u8);
+                    auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedSuspended, "ip lock mismatch");
                     write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-                    return Ok(());
+                    return Ok(Session::Closed);
                 }
-                tracing::trace!("[{}] Account '{}' IP lock verified", addr, login);
+                tracing::trace!("Account '{}' IP lock verified", login);
             }
 
-            let database_v: String = row.get_string(4);
-            let database_s: String = row.get_string(5);
+            // Check country lock ("00" = not locked); addresses without a known country pass
+            let lock_country = row.get_string(7);
+            if !lock_country.is_empty()
+                && lock_country != "00"
+                && let Some(country) = &country
+                && !lock_country.eq_ignore_ascii_case(country)
+            {
+                tracing::info!(
+                    target: LOG_TARGET_AUDIT,
+                    "Account '{}' is locked to country {}, login attempt from {} (IP {})",
+                    login, lock_country, country, ip_str
+                );
+                pkt.write_u8(AuthLogonResult::FailedLockedEnforced as u8);
+                auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedLockedEnforced, "country lock mismatch");
+                write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
+                return Ok(Session::Closed);
+            }
 
-            tracing::trace!("[{}] SRP6 verifier length: {} salt length: {}", addr, database_v.len(), database_s.len());
+            let mut database_v = Zeroizing::new(row.get_string(4));
+            let mut database_s = Zeroizing::new(row.get_string(5));
+
+            // Accounts imported with only the legacy sha_pass_hash get their verifier now
+            if database_v.trim().is_empty() || database_s.trim().is_empty() {
+                match until_disconnect(stream, account_mgr::migrate_sha_pass_hash(db, account_id)).await? {
+                    Ok(Some((v, s))) => {
+                        *database_v = v;
+                        *database_s = s;
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::debug!("No legacy sha_pass_hash for account '{}': {:#}", login, e),
+                }
+            }
+
+            tracing::trace!("SRP6 verifier length: {} salt length: {}", database_v.len(), database_s.len());
 
             // Set SRP6 verifier and salt
             if !srp.set_verifier(&database_v) || !srp.set_salt(&database_s) {
                 pkt.write_u8(AuthLogonResult::FailedFailNoaccess as u8);
-                tracing::warn!("[{}] Broken v/s values for account '{}'", addr, login);
+                tracing::warn!("Broken v/s values for account '{}'", login);
+                auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedFailNoaccess, "broken verifier");
                 write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-                return Ok(());
+                return Ok(Session::Closed);
             }
 
             // Check account ban
-            let ban_sql = format!(
-                "SELECT banned_at, expires_at FROM account_banned \
-                 WHERE account_id = {} AND CAST(active AS SIGNED) = 1 AND \
-                 (expires_at > UNIX_TIMESTAMP() OR expires_at = banned_at)",
-                account_id
-            );
-
-            tracing::trace!("[{}] Checking account ban for id={}", addr, account_id);
-
-            if let Ok(Some(ban_row)) = db.query_one(&ban_sql).await {
-                let banned_at: u64 = ban_row.get_u64(0);
-                let expires_at: u64 = ban_row.get_u64(1);
+            if row.get_u32(8) != 0 {
+                let banned_at: u64 = row.get_u64(9);
+                let expires_at: u64 = row.get_u64(10);
 
                 if banned_at == expires_at {
                     pkt.write_u8(AuthLogonResult::FailedBanned as u8);
-                    tracing::info!("[{}] Permanently banned account '{}' (id={}) tried to login", addr, login, account_id);
+                    tracing::info!(target: LOG_TARGET_AUDIT, "Permanently banned account '{}' (id={}) tried to login", login, account_id);
+                    auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedBanned, "account banned");
                 } else {
                     pkt.write_u8(AuthLogonResult::FailedSuspended as u8);
                     tracing::info!(
-                        "[{}] Temporarily banned account '{}' (id={}) tried to login (expires at {})",
-                        addr, login, account_id, expires_at
+                        target: LOG_TARGET_AUDIT,
+                        "Temporarily banned account '{}' (id={}) tried to login (expires at {})",
+                        login, account_id, expires_at
                     );
+                    auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedSuspended, "account suspended");
                 }
                 write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-                return Ok(());
+                return Ok(Session::Closed);
+            }
+
+            // Check parental control playtime hours
+            if let Some(wait) = until_disconnect(stream, playtime_restriction(db, account_id)).await? {
+                pkt.write_u8(AuthLogonResult::FailedParentcontrol as u8);
+                let minutes = wait.as_secs().div_ceil(60);
+                tracing::info!("Account '{}' (id={}) tried to login outside its allowed hours ({} min left)", login, account_id, minutes);
+                auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedParentcontrol, "outside allowed hours");
+                write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
+                return Ok(Session::Closed);
             }
 
             // Generate SRP6 challenge
-            tracing::trace!("[{}] Generating SRP6 challenge for '{}'", addr, login);
+            tracing::trace!("Generating SRP6 challenge for '{}'", login);
             srp.calculate_host_public_ephemeral();
 
             pkt.write_u8(AuthLogonResult::Success as u8);
@@ -450,34 +745,41 @@ async fn handle_logon_challenge(
             *token = row.get_string(6);
             let mut security_flags: u8 = 0;
 
-            if !token.is_empty() && *build >= 8606 {
+            if !token.is_empty() && build >= 8606 {
                 // Authenticator was added in 2.4.3
                 security_flags = SecurityFlags::Authenticator as u8;
-                tracing::debug!("[{}] Account '{}' has authenticator token (build {})", addr, login, build);
+                tracing::debug!("Account '{}' has authenticator token (build {})", login, build);
             }
 
-            if !token.is_empty() && *build <= 6141 {
+            if !token.is_empty() && build <= 6141 {
                 security_flags = SecurityFlags::Pin as u8;
-                tracing::debug!("[{}] Account '{}' using PIN mode (build {})", addr, login, build);
+                tracing::debug!("Account '{}' using PIN mode (build {})", login, build);
+            }
+
+            matrix_challenge = until_disconnect(stream, load_matrix_challenge(db, account_id)).await?;
+            if matrix_challenge.is_some() {
+                security_flags |= SecurityFlags::MatrixCard as u8;
+                tracing::debug!("Account '{}' has a matrix card", login);
             }
 
             pkt.write_u8(security_flags);
 
             if security_flags & SecurityFlags::Pin as u8 != 0 {
-                *grid_seed = 0;
-                pkt.write_u32(*grid_seed);
+                let grid_seed = rand::random::<u32>();
+                pkt.write_u32(grid_seed);
+                let mut server_security_salt = BigNumber::new();
                 server_security_salt.set_rand(16 * 8);
                 pkt.append(&server_security_salt.as_byte_array(16)[..16]);
-                *prompt_pin = true;
-                tracing::trace!("[{}] PIN challenge generated for '{}'", addr, login);
+                pin = Some(PinChallenge { grid_seed, server_security_salt });
+                tracing::trace!("PIN challenge generated for '{}'", login);
             }
 
-            if security_flags & SecurityFlags::Unk as u8 != 0 {
-                pkt.write_u8(0);
-                pkt.write_u8(0);
-                pkt.write_u8(0);
-                pkt.write_u8(0);
-                pkt.write_u64(0);
+            if let Some(challenge) = matrix_challenge.as_ref() {
+                pkt.write_u8(challenge.card.width);
+                pkt.write_u8(challenge.card.height);
+                pkt.write_u8(challenge.card.digit_count);
+                pkt.write_u8(challenge.challenge_count);
+                pkt.write_u64(challenge.seed);
             }
 
             if security_flags & SecurityFlags::Authenticator as u8 != 0 {
@@ -485,255 +787,614 @@ async fn handle_logon_challenge(
             }
 
             let sec_level: u8 = row.get_u8(3);
-            *_account_security_level = if sec_level <= SEC_ADMINISTRATOR {
-                sec_level
-            } else {
-                SEC_ADMINISTRATOR
-            };
-
-            *status = SessionStatus::LogonProof;
+            accepted_security_level = Some(sec_level.min(SEC_ADMINISTRATOR));
             tracing::debug!(
-                "[{}] LogonChallenge SUCCESS for '{}': security_flags=0x{:02X} response_size={} bytes",
-                addr, login, security_flags, pkt.size()
+                "LogonChallenge SUCCESS for '{}': security_flags=0x{:02X} response_size={} bytes",
+                login, security_flags, pkt.size()
             );
         }
         None => {
-            // Check if auto-create is enabled
+            // Check if auto-create is enabled and allowed for this name and address;
+            // a refused name is answered like any other unknown account
             let auto_create = {
                 let config = get_config().lock();
-                config.get_bool_default("AutoCreateAccounts", false)
-            };
+                config
+                    .get_bool_default("AutoCreateAccounts", false)
+                    .then(|| AutoCreatePolicy::from_config(&config))
+            }
+            .filter(|policy| match policy.refusal(login, addr.ip()) {
+                Some(reason) => {
+                    tracing::info!("Account '{}' not auto-created for {}: {}", login, ip_str, reason);
+                    false
+                }
+                None => true,
+            });
 
-            if auto_create {
+            if let Some(policy) = auto_create {
                 tracing::info!(
-                    "[{}] Account '{}' not found, auto-creating (AutoCreateAccounts enabled)",
-                    addr, login
+                    "Account '{}' not found, auto-creating (AutoCreateAccounts enabled)",
+                    login
                 );
 
-                match auto_create_account(db, login, safe_login).await {
-                    Ok(()) => {
-                        tracing::info!("[{}] Account '{}' auto-created successfully (password = username)", addr, login);
+                match until_disconnect(stream, account_mgr::auto_create_account(db, login, &policy)).await? {
+                    Ok(account_id) => {
+                        tracing::info!("Account '{}' auto-created successfully (id={})", login, account_id);
 
                         // Re-query the freshly created account and proceed with challenge
-                        match db.query_one(&account_sql).await? {
+                        match until_disconnect(stream, db.query_one(&logon_lookup::account_sql(safe_login))).await?? {
                             Some(row) => {
-                                let database_v: String = row.get_string(4);
-                                let database_s: String = row.get_string(5);
+                                let database_v = Zeroizing::new(row.get_string(4));
+                                let database_s = Zeroizing::new(row.get_string(5));
 
                                 if !srp.set_verifier(&database_v) || !srp.set_salt(&database_s) {
                                     pkt.write_u8(AuthLogonResult::FailedFailNoaccess as u8);
-                                    tracing::error!("[{}] Auto-created account '{}' has broken v/s values", addr, login);
+                                    tracing::error!("Auto-created account '{}' has broken v/s values", login);
+                                    auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedFailNoaccess, "broken verifier");
                                     write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-                                    return Ok(());
+                                    return Ok(Session::Closed);
                                 }
 
                                 // Generate SRP6 challenge
-                                srp.calculate_host_public_ephemeral();
-
-                                pkt.write_u8(AuthLogonResult::Success as u8);
-                                pkt.append(&srp.get_host_public_ephemeral().as_byte_array(32));
-                                pkt.write_u8(1);
-                                pkt.append(&srp.get_generator_modulo().as_byte_array(0));
-                                pkt.write_u8(32);
-                                pkt.append(&srp.get_prime().as_byte_array(32));
-
-                                let mut salt_bn = BigNumber::new();
-                                salt_bn.set_hex_str(&database_s);
-                                pkt.append(&salt_bn.as_byte_array(0));
-                                pkt.append(&VERSION_CHALLENGE);
-
                                 // No authenticator/PIN for auto-created accounts
-                                pkt.write_u8(0);
+                                write_plain_challenge(&mut pkt, &mut srp, &database_s);
 
                                 let sec_level: u8 = row.get_u8(3);
-                                *_account_security_level = if sec_level <= SEC_ADMINISTRATOR {
-                                    sec_level
-                                } else {
-                                    SEC_ADMINISTRATOR
-                                };
-
-                                *status = SessionStatus::LogonProof;
+                                accepted_security_level = Some(sec_level.min(SEC_ADMINISTRATOR));
                                 tracing::debug!(
-                                    "[{}] LogonChallenge SUCCESS for auto-created '{}': response_size={} bytes",
-                                    addr, login, pkt.size()
+                                    "LogonChallenge SUCCESS for auto-created '{}': response_size={} bytes",
+                                    login, pkt.size()
                                 );
                             }
                             None => {
                                 pkt.write_u8(AuthLogonResult::FailedUnknownAccount as u8);
-                                tracing::error!("[{}] Auto-created account '{}' not found after insert", addr, login);
+                                tracing::error!("Auto-created account '{}' not found after insert", login);
+                                auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedUnknownAccount, "auto-create failed");
                             }
                         }
                     }
                     Err(e) => {
                         pkt.write_u8(AuthLogonResult::FailedUnknownAccount as u8);
-                        tracing::error!("[{}] Failed to auto-create account '{}': {}", addr, login, e);
+                        tracing::error!("Failed to auto-create account '{}': {}", login, e);
+                        auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedUnknownAccount, "auto-create failed");
                     }
                 }
+            } else if get_config().lock().get_bool_default("Auth.ConcealUnknownAccounts", false) {
+                // Answer like an existing account; the proof then fails exactly as a wrong password does
+                let salt = decoy_salt(login);
+                let mut verifier = BigNumber::new();
+                verifier.set_rand(32 * 8);
+                if srp.set_verifier(&verifier.as_hex_str()) && srp.set_salt(&salt) {
+                    write_plain_challenge(&mut pkt, &mut srp, &salt);
+                    decoy = true;
+                    accepted_security_level = Some(SEC_PLAYER);
+                } else {
+                    pkt.write_u8(AuthLogonResult::FailedUnknownAccount as u8);
+                    auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedUnknownAccount, "unknown account");
+                }
+                tracing::info!("Unknown account '{}' tried to login (decoy challenge sent)", login);
             } else {
                 pkt.write_u8(AuthLogonResult::FailedUnknownAccount as u8);
-                tracing::info!("[{}] Unknown account '{}' tried to login", addr, login);
+                tracing::info!("Unknown account '{}' tried to login", login);
+                auth_log::record(AuthStage::Challenge, login, addr.ip(), build, AuthLogonResult::FailedUnknownAccount, "unknown account");
             }
         }
     }
 
     write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-    Ok(())
+    let Some(account_security_level) = accepted_security_level else {
+        return Ok(Session::Closed);
+    };
+    Ok(Session::LogonProof(Box::new(LogonProofState {
+        client,
+        srp,
+        token,
+        account_security_level,
+        pin,
+        matrix_challenge,
+        decoy,
+    })))
+}
+
+/// Matrix card prompt sent with a logon challenge
+struct MatrixChallenge {
+    card: MatrixCard,
+    challenge_count: u8,
+    seed: u64,
+}
+
+/// Matrix card prompt for the account, if Auth.MatrixCard is on and it has a card
+async fn load_matrix_challenge(db: &Database, account_id: u32) -> Option<MatrixChallenge> {
+    let challenge_count = {
+        let config = get_config().lock();
+        if !config.get_bool_default("Auth.MatrixCard", false) {
+            return None;
+        }
+        config.get_int_default("Auth.MatrixCardChallenges", 3).clamp(1, 255) as usize
+    };
+
+    let row = match db
+        .query_one(&format!(
+            "SELECT CAST(width AS SIGNED), CAST(height AS SIGNED), CAST(digit_count AS SIGNED), digits \
+             FROM account_matrix_card WHERE account_id = '{}'",
+            account_id
+        ))
+        .await
+    {
+        Ok(row) => row?,
+        Err(e) => {
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot load matrix card of account {}: {:#}", account_id, e);
+            return None;
+        }
+    };
+    let Some(card) = MatrixCard::from_digit_string(row.get_u8(0), row.get_u8(1), row.get_u8(2), &row.get_string(3)) else {
+        tracing::warn!("Account {} has a malformed matrix card, not prompting for it", account_id);
+        return None;
+    };
+    Some(MatrixChallenge {
+        challenge_count: challenge_count.min(card.cells()) as u8,
+        card,
+        seed: rand::random(),
+    })
+}
+
+/// Time until the account may log in again, when account_playtime restricts it now
+///
+/// The window runs from allowed_start to allowed_end in server local time and
+/// may span midnight; equal times allow the whole day. The logon result has no
+/// field for the time left, so it only goes to the log.
+async fn playtime_restriction(db: &Database, account_id: u32) -> Option<Duration> {
+    if !get_config().lock().get_bool_default("Auth.ParentalControl", false) {
+        return None;
+    }
+
+    let row = match db
+        .query_one(&format!(
+            "SELECT CAST(TIME_TO_SEC(allowed_start) AS SIGNED), CAST(TIME_TO_SEC(allowed_end) AS SIGNED) \
+             FROM account_playtime WHERE account_id = '{}'",
+            account_id
+        ))
+        .await
+    {
+        Ok(row) => row?,
+        Err(e) => {
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot load allowed hours of account {}: {:#}", account_id, e);
+            return None;
+        }
+    };
+    const DAY: i64 = 24 * 3600;
+    let (start, end) = (row.get_i64(0).rem_euclid(DAY), row.get_i64(1).rem_euclid(DAY));
+    let now = i64::from(chrono::Local::now().num_seconds_from_midnight());
+
+    let allowed = match start.cmp(&end) {
+        std::cmp::Ordering::Equal => true,
+        std::cmp::Ordering::Less => (start..end).contains(&now),
+        std::cmp::Ordering::Greater => now >= start || now < end,
+    };
+    (!allowed).then(|| Duration::from_secs((start - now).rem_euclid(DAY) as u64))
+}
+
+/// Append a successful SRP6 challenge without PIN/authenticator to `pkt`
+fn write_plain_challenge(pkt: &mut ByteBuffer, srp: &mut SRP6, salt_hex: &str) {
+    srp.calculate_host_public_ephemeral();
+
+    pkt.write_u8(AuthLogonResult::Success as u8);
+    pkt.append(&srp.get_host_public_ephemeral().as_byte_array(32));
+    pkt.write_u8(1);
+    pkt.append(&srp.get_generator_modulo().as_byte_array(0));
+    pkt.write_u8(32);
+    pkt.append(&srp.get_prime().as_byte_array(32));
+
+    let mut salt_bn = BigNumber::new();
+    salt_bn.set_hex_str(salt_hex);
+    pkt.append(&salt_bn.as_byte_array(0));
+    pkt.append(&VERSION_CHALLENGE);
+
+    pkt.write_u8(0);
+}
+
+/// Salt for a decoy challenge: random-looking but stable per username,
+/// so repeated challenges cannot tell a decoy from a real account
+fn decoy_salt(login: &str) -> String {
+    let mut bytes = Vec::with_capacity(40);
+    for round in [b'0', b'1'] {
+        let mut sha = Sha1Hash::new();
+        sha.update_data_bytes(&*DECOY_SALT_SECRET);
+        sha.update_data_bytes(&[round]);
+        sha.update_data(&login.to_uppercase());
+        sha.finalize();
+        bytes.extend_from_slice(sha.get_digest());
+    }
+    // Keep the top byte non-zero so the salt always serializes to 32 bytes
+    bytes[31] |= 0x80;
+    let mut salt = BigNumber::new();
+    salt.set_binary(&bytes[..32]);
+    salt.as_hex_str()
+}
+
+/// The patch from PatchesDir for the client's build and locale
+async fn find_patch(client: &ClientInfo) -> Result<Option<PatchInfo>, anyhow::Error> {
+    let (build, locale) = (client.build, client.safe_locale.clone());
+    Ok(tokio::task::spawn_blocking(move || patcher::find_patch(build, &locale)).await?)
 }
 
 /// Handle CMD_AUTH_LOGON_PROOF
-#[allow(clippy::too_many_arguments)]
 async fn handle_logon_proof(
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
     addr: &SocketAddr,
     db: &Database,
-    status: &mut SessionStatus,
-    srp: &mut SRP6,
-    login: &str,
-    safe_login: &str,
-    safe_locale: &str,
-    token: &str,
-    os: &str,
-    platform: &str,
-    build: u16,
-    prompt_pin: bool,
-    _server_security_salt: &BigNumber,
-    _grid_seed: u32,
-    _account_security_level: &mut AccountTypes,
+    state: LogonProofState,
     timeout_duration: Duration,
-) -> Result<(), anyhow::Error> {
-    // Read the proof data
-    let proof_size = if prompt_pin {
-        AuthLogonProofClient::SIZE_WITH_PIN
-    } else {
-        AuthLogonProofClient::SIZE_WITHOUT_PIN
-    };
-
-    tracing::trace!("[{}] Reading LogonProof: {} bytes (pin={})", addr, proof_size, prompt_pin);
+) -> Result<Session, anyhow::Error> {
+    let LogonProofState { client, mut srp, token, account_security_level, pin, matrix_challenge, decoy } = state;
+    let (login, safe_login, build) = (client.login.as_str(), client.safe_login.as_str(), client.build);
+
+    // Read the proof data; the PIN and matrix card data follow only if the client flags them
+    tracing::trace!(
+        "Reading LogonProof: {} bytes (pin prompted={} matrix prompted={})",
+        AuthLogonProofClient::SIZE_WITHOUT_PIN, pin.is_some(), matrix_challenge.is_some()
+    );
 
-    let mut proof_buf = vec![0u8; proof_size];
+    let mut proof_buf = vec![0u8; AuthLogonProofClient::SIZE_WITHOUT_PIN];
     read_with_timeout(stream, &mut proof_buf, timeout_duration).await?;
 
-    let proof = AuthLogonProofClient::from_bytes(&proof_buf, prompt_pin)
-        .ok_or_else(|| anyhow::anyhow!("Invalid logon proof"))?;
+    let client_flags = proof_buf[AuthLogonProofClient::SIZE_WITHOUT_PIN - 1];
+    let with_pin = pin.is_some() && client_flags & SecurityFlags::Pin as u8 != 0;
+    let with_matrix = matrix_challenge.is_some() && client_flags & SecurityFlags::MatrixCard as u8 != 0;
+    let extra_size = AuthLogonProofClient::extra_size(with_pin, with_matrix);
+    if extra_size > 0 {
+        proof_buf.resize(AuthLogonProofClient::SIZE_WITHOUT_PIN + extra_size, 0);
+        read_with_timeout(stream, &mut proof_buf[AuthLogonProofClient::SIZE_WITHOUT_PIN..], timeout_duration).await?;
+    }
 
-    *status = SessionStatus::Closed;
+    let proof = AuthLogonProofClient::from_bytes(&proof_buf, with_pin, with_matrix)?;
 
-    // Check build validity
+    // Builds without a patch were turned away at the challenge, so offer the patch
     if find_build_info(build).is_none() {
-        let mut pkt = ByteBuffer::new();
-        pkt.write_u8(AuthCmd::LogonChallenge as u8);
-        pkt.write_u8(0x00);
-        pkt.write_u8(AuthLogonResult::FailedVersionInvalid as u8);
-        tracing::info!("[{}] Account '{}' tried to login with unsupported build {}", addr, login, build);
-        write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-        return Ok(());
+        if let Some(found) = find_patch(&client).await? {
+            tracing::info!("Account '{}' has build {}, offering patch {}", login, build, found.path.display());
+            auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedVersionUpdate, "patch offered");
+            let mut pkt = ByteBuffer::new();
+            pkt.write_u8(AuthCmd::LogonProof as u8);
+            pkt.write_u8(AuthLogonResult::FailedVersionUpdate as u8);
+            pkt.append(&XferInit { file_size: found.size, md5: found.md5 }.to_bytes());
+            write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
+            return Ok(Session::Patch(found));
+        }
+
+        // The patch was removed since the challenge
+        tracing::info!("Account '{}' tried to login with outdated build {}", login, build);
+        auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedVersionUpdate, "outdated build");
+        send_logon_proof_result(stream, build, AuthLogonResult::FailedVersionUpdate, timeout_duration).await?;
+        return Ok(Session::Closed);
     }
 
     // Calculate session key
-    tracing::trace!("[{}] Calculating SRP6 session key for '{}'", addr, login);
+    tracing::trace!("Calculating SRP6 session key for '{}'", login);
     if !srp.calculate_session_key(&proof.a) {
-        tracing::warn!("[{}] SRP6 session key calculation failed for '{}' (invalid A value)", addr, login);
-        return Ok(());
+        tracing::warn!("SRP6 session key calculation failed for '{}' (invalid A value)", login);
+        return Ok(Session::Closed);
     }
 
     srp.hash_session_key();
     srp.calculate_proof(login);
 
-    tracing::trace!("[{}] Verifying SRP6 proof for '{}'", addr, login);
+    tracing::trace!("Verifying SRP6 proof for '{}'", login);
 
     // Check if proof matches (password correct)
-    // srp.proof() returns true when client M1 matches our computed M = password correct
-    if !srp.proof(&proof.m1) {
+    // srp.proof() returns true when client M1 matches our computed M = password correct.
+    // A decoy challenge for an unknown account never succeeds, but still does the same work.
+    if !srp.proof(&proof.m1) || decoy {
         // Proof did NOT match = wrong password
         send_logon_proof_error(stream, build, timeout_duration).await?;
-        tracing::info!("[{}] Account '{}' login failed: wrong password", addr, login);
+        if decoy {
+            tracing::info!(target: LOG_TARGET_AUDIT, "Unknown account '{}' login failed", login);
+            auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedUnknownAccount, "unknown account");
+        } else {
+            tracing::info!(target: LOG_TARGET_AUDIT, "Account '{}' login failed: wrong password", login);
+            auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedIncorrectPassword, "wrong password");
+        }
 
-        // Handle failed login counting
+        // Handle failed login counting. Deliberately not cancelled on disconnect:
+        // otherwise a client could dodge the autoban by hanging up after the error.
+        tarpit::record_failure(addr.ip(), login).await;
+        webhook::record_failed_login(addr.ip(), login);
         handle_failed_login(db, login, safe_login, addr).await;
-        return Ok(());
+        return Ok(Session::Closed);
     }
 
     // Proof matched = password correct
-    tracing::debug!("[{}] SRP6 proof verified for '{}', password correct", addr, login);
+    tracing::debug!("SRP6 proof verified for '{}', password correct", login);
+
+    // Check the PIN for builds <= 6141; the account token holds the PIN digits
+    if let Some(challenge) = &pin {
+        let verified = match (&proof.pin, token.trim().parse::<u32>()) {
+            (Some(pin_data), Ok(digits)) => verify_pin_data(digits, challenge.grid_seed, &challenge.server_security_salt, pin_data),
+            _ => false,
+        };
+        if !verified {
+            tracing::info!(target: LOG_TARGET_AUDIT, "Account '{}' login failed: wrong PIN", login);
+            auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedIncorrectPassword, "wrong pin");
+            send_logon_proof_error(stream, build, timeout_duration).await?;
+            tarpit::record_failure(addr.ip(), login).await;
+            webhook::record_failed_login(addr.ip(), login);
+            handle_failed_login(db, login, safe_login, addr).await;
+            return Ok(Session::Closed);
+        }
+
+        tracing::debug!("PIN verified for '{}'", login);
+    }
+
+    if let Some(challenge) = &matrix_challenge {
+        let session_key = srp.get_strong_session_key().as_byte_array(40);
+        let verified = proof.matrix_proof.as_ref().is_some_and(|matrix_proof| {
+            challenge.card.verify(&session_key, challenge.challenge_count, challenge.seed, matrix_proof)
+        });
+        if !verified {
+            tracing::info!(target: LOG_TARGET_AUDIT, "Account '{}' login failed: wrong matrix card digits", login);
+            auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedIncorrectPassword, "wrong matrix card");
+            send_logon_proof_error(stream, build, timeout_duration).await?;
+            tarpit::record_failure(addr.ip(), login).await;
+            webhook::record_failed_login(addr.ip(), login);
+            handle_failed_login(db, login, safe_login, addr).await;
+            return Ok(Session::Closed);
+        }
+
+        tracing::debug!("Matrix card verified for '{}'", login);
+    }
 
     // Handle authenticator token for builds > 6141
+    let mut authenticator_verified = false;
     if build > 6141 && (proof.security_flags & SecurityFlags::Authenticator as u8 != 0 || !token.is_empty()) {
-        tracing::debug!("[{}] Reading authenticator token for '{}'", addr, login);
+        tracing::debug!("Reading authenticator token for '{}'", login);
         // Read authenticator token
         let mut pin_count_buf = [0u8; 1];
         if read_with_timeout(stream, &mut pin_count_buf, timeout_duration).await.is_err() {
-            tracing::debug!("[{}] Failed to read authenticator token length for '{}'", addr, login);
+            tracing::debug!("Failed to read authenticator token length for '{}'", login);
             send_logon_proof_error(stream, build, timeout_duration).await?;
-            return Ok(());
+            return Ok(Session::Closed);
         }
         let pin_count = pin_count_buf[0];
 
         if pin_count > 16 {
-            tracing::debug!("[{}] Invalid authenticator token length {} for '{}'", addr, pin_count, login);
+            tracing::debug!("Invalid authenticator token length {} for '{}'", pin_count, login);
             send_logon_proof_error(stream, build, timeout_duration).await?;
-            return Ok(());
+            return Ok(Session::Closed);
         }
 
-        let mut keys = vec![0u8; pin_count as usize];
+        let mut keys = Zeroizing::new(vec![0u8; pin_count as usize]);
         if read_with_timeout(stream, &mut keys, timeout_duration).await.is_err() {
-            tracing::debug!("[{}] Failed to read authenticator token data for '{}'", addr, login);
+            tracing::debug!("Failed to read authenticator token data for '{}'", login);
             send_logon_proof_error(stream, build, timeout_duration).await?;
-            return Ok(());
+            return Ok(Session::Closed);
         }
 
-        let client_token: i32 = String::from_utf8_lossy(&keys)
-            .parse()
-            .unwrap_or(-1);
-        let server_token = generate_token(token);
+        // Codes are not even checked while the authenticator is locked
+        if until_disconnect(stream, authenticator_locked(db, safe_login)).await? {
+            tracing::info!(target: LOG_TARGET_AUDIT, "Account '{}' login refused: authenticator locked after failed codes", login);
+            auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedSuspended, "authenticator locked");
+            send_logon_proof_result(stream, build, AuthLogonResult::FailedSuspended, timeout_duration).await?;
+            return Ok(Session::Closed);
+        }
 
-        tracing::trace!("[{}] Authenticator: client={} server={}", addr, client_token, server_token);
+        let client_token = String::from_utf8_lossy(&keys);
+        let params = TotpParams::from_config(&get_config().lock());
 
-        if server_token != client_token {
-            tracing::info!(
-                "[{}] Account '{}' authenticator mismatch: client={} expected={}",
-                addr, login, client_token, server_token
-            );
+        if !totp::verify_code(&token, &client_token, &params) {
+            tracing::info!("Account '{}' authenticator mismatch", login);
+            auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedIncorrectPassword, "wrong authenticator code");
             send_logon_proof_error(stream, build, timeout_duration).await?;
-            return Ok(());
+            tarpit::record_failure(addr.ip(), login).await;
+            webhook::record_failed_login(addr.ip(), login);
+            record_authenticator_failure(db, login, safe_login).await;
+            return Ok(Session::Closed);
         }
 
-        tracing::debug!("[{}] Authenticator verified for '{}'", addr, login);
+        tracing::debug!("Authenticator verified for '{}'", login);
+        // A code checked against no secret proves nothing
+        authenticator_verified = !token.is_empty();
+        until_disconnect(stream, reset_authenticator_failures(db, safe_login)).await?;
+    }
+
+    // Elevated accounts may be required to prove an authenticator code as well
+    let required_level = get_config().lock().get_int_default("Security.RequireTotpGmLevel", 0);
+    if required_level > 0 && i32::from(account_security_level) >= required_level && !authenticator_verified {
+        tracing::warn!(
+            target: LOG_TARGET_AUDIT,
+            "Account '{}' (gmlevel {}) refused: Security.RequireTotpGmLevel needs a verified authenticator{}",
+            login,
+            account_security_level,
+            if token.is_empty() { ", none is set" } else if build < 8606 { ", the client cannot send one" } else { "" }
+        );
+        auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedFailNoaccess, "authenticator required");
+        send_logon_proof_result(stream, build, AuthLogonResult::FailedFailNoaccess, timeout_duration).await?;
+        return Ok(Session::Closed);
     }
 
     // Password (and optional authenticator) verified, finalize login
-    verify_and_finalize(stream, addr, db, status, srp, login, safe_login, safe_locale, os, platform, build, &proof, timeout_duration).await?;
-    Ok(())
+    verify_and_finalize(stream, addr, db, &srp, client, account_security_level, &proof, timeout_duration).await
+}
+
+/// Check the PIN hash the client sent against the account PIN
+///
+/// The client shows the digits 0-9 on a grid shuffled by the grid seed and
+/// hashes the grid positions the player clicked, not the digits themselves:
+/// hash = SHA1(client_salt || SHA1(server_salt || positions as ASCII))
+fn verify_pin_data(pin: u32, grid_seed: u32, server_security_salt: &BigNumber, pin_data: &AuthLogonPinData) -> bool {
+    // Shuffle the grid the way the client does
+    let mut grid: Vec<u8> = (0..10).collect();
+    let mut remapped_grid = Vec::with_capacity(grid.len());
+    let mut seed = grid_seed;
+    for i in (1..=grid.len() as u32).rev() {
+        remapped_grid.push(grid.remove((seed % i) as usize));
+        seed /= i;
+    }
+
+    // PIN digits, most significant first
+    let digits = pin.to_string();
+    if !(4..=10).contains(&digits.len()) {
+        return false;
+    }
+
+    // Position of each digit on the shuffled grid, as ASCII
+    let positions: Zeroizing<Vec<u8>> = Zeroizing::new(
+        digits
+            .bytes()
+            .map(|d| {
+                let index = remapped_grid.iter().position(|&g| g == d - b'0').unwrap_or(0);
+                b'0' + index as u8
+            })
+            .collect(),
+    );
+
+    let mut sha = Sha1Hash::new();
+    sha.update_data_bytes(&server_security_salt.as_byte_array(16)[..16]);
+    sha.update_data_bytes(&positions);
+    sha.finalize();
+    let inner = *sha.get_digest();
+
+    sha.initialize();
+    sha.update_data_bytes(&pin_data.salt);
+    sha.update_data_bytes(&inner);
+    sha.finalize();
+
+    constant_time_eq(sha.get_digest(), &pin_data.hash)
 }
 
 /// Send an error response for logon proof
-async fn send_logon_proof_error(stream: &mut TcpStream, build: u16, timeout_duration: Duration) -> Result<(), anyhow::Error> {
+async fn send_logon_proof_error(stream: &mut ClientStream, build: u16, timeout_duration: Duration) -> Result<(), anyhow::Error> {
+    send_logon_proof_result(stream, build, AuthLogonResult::FailedUnknownAccount, timeout_duration).await
+}
+
+/// Send a failed logon proof with `result`
+async fn send_logon_proof_result(
+    stream: &mut ClientStream,
+    build: u16,
+    result: AuthLogonResult,
+    timeout_duration: Duration,
+) -> Result<(), anyhow::Error> {
     if build > 6005 {
-        let response: [u8; 4] = [
-            AuthCmd::LogonProof as u8,
-            AuthLogonResult::FailedUnknownAccount as u8,
-            0,
-            0,
-        ];
+        let response: [u8; 4] = [AuthCmd::LogonProof as u8, result as u8, 0, 0];
         write_with_timeout(stream, &response, timeout_duration).await?;
     } else {
-        let response: [u8; 2] = [
-            AuthCmd::LogonProof as u8,
-            AuthLogonResult::FailedUnknownAccount as u8,
-        ];
+        let response: [u8; 2] = [AuthCmd::LogonProof as u8, result as u8];
         write_with_timeout(stream, &response, timeout_duration).await?;
     }
     Ok(())
 }
 
+/// Answer a proof the client is about to send with "server busy" before the
+/// session is closed for shutdown; in other states the client just disconnects
+async fn send_shutdown_notice(
+    stream: &mut ClientStream,
+    addr: &SocketAddr,
+    session: &Session,
+    timeout_duration: Duration,
+) -> Result<(), anyhow::Error> {
+    let (cmd, stage, client) = match session {
+        Session::LogonProof(state) => (AuthCmd::LogonProof, AuthStage::Proof, &state.client),
+        Session::ReconnectProof(state) => (AuthCmd::ReconnectProof, AuthStage::Reconnect, &state.client),
+        _ => return Ok(()),
+    };
+    let build = client.build;
+    auth_log::record(stage, &client.login, addr.ip(), build, AuthLogonResult::FailedDbBusy, "server shutting down");
+
+    let mut pkt = ByteBuffer::new();
+    pkt.write_u8(cmd as u8);
+    pkt.write_u8(AuthLogonResult::FailedDbBusy as u8);
+    if build > 6005 {
+        pkt.write_u16(0x00);
+    }
+    write_with_timeout(stream, pkt.contents(), timeout_duration).await
+}
+
+/// Whether too many wrong authenticator codes locked the account's authenticator
+///
+/// Counts as locked when the lock cannot be read, so a database error never
+/// lets codes be guessed past Auth.TotpMaxFailures.
+async fn authenticator_locked(db: &Database, safe_login: &str) -> bool {
+    let sql = format!(
+        "SELECT 1 FROM account WHERE username = '{}' AND totp_locked_until > {}",
+        safe_login,
+        unix_now()
+    );
+    match db.query_one(&sql).await {
+        Ok(row) => row.is_some(),
+        Err(e) => {
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot read the authenticator lock of '{}': {:#}", safe_login, e);
+            true
+        }
+    }
+}
+
+/// Count a wrong authenticator code, apart from failed_logins; locks the
+/// authenticator for Auth.TotpLockTime seconds at Auth.TotpMaxFailures
+async fn record_authenticator_failure(db: &Database, login: &str, safe_login: &str) {
+    let (max_failures, lock_time) = {
+        let config = get_config().lock();
+        (
+            config.get_int_default("Auth.TotpMaxFailures", 5).max(0),
+            config.get_int_default("Auth.TotpLockTime", 900).max(1),
+        )
+    };
+    if max_failures == 0 {
+        return;
+    }
+
+    // One statement, so concurrent wrong codes cannot slip past the limit.
+    // MySQL assigns left to right with the new values, so the lock is set
+    // before the counter changes; other databases use the old values anyway.
+    let locked_until = unix_now() + lock_time as u64;
+    let update = db
+        .execute(&format!(
+            "UPDATE account SET \
+             totp_locked_until = CASE WHEN totp_failures + 1 >= {max} THEN '{until}' ELSE totp_locked_until END, \
+             totp_failures = CASE WHEN totp_failures + 1 >= {max} THEN 0 ELSE totp_failures + 1 END \
+             WHERE username = '{}'",
+            safe_login,
+            max = max_failures,
+            until = locked_until
+        ))
+        .await;
+    if let Err(e) = update {
+        tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot count the authenticator failure of '{}': {:#}", login, e);
+        return;
+    }
+
+    // Only decides whether to report the lock
+    let locked = db
+        .query_one(&format!(
+            "SELECT 1 FROM account WHERE username = '{}' AND totp_failures = 0 AND totp_locked_until = '{}'",
+            safe_login, locked_until
+        ))
+        .await;
+    if let Ok(Some(_)) = locked {
+        tracing::warn!(
+            target: LOG_TARGET_AUDIT,
+            "Authenticator of account '{}' locked for {}s after {} wrong codes",
+            login, lock_time, max_failures
+        );
+    }
+}
+
+/// Forget wrong authenticator codes after a correct one
+async fn reset_authenticator_failures(db: &Database, safe_login: &str) {
+    if let Err(e) = db
+        .execute(&format!("UPDATE account SET totp_failures = 0 WHERE username = '{}' AND totp_failures > 0", safe_login))
+        .await
+    {
+        tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot reset the authenticator failures of '{}': {:#}", safe_login, e);
+    }
+}
+
 /// Handle failed login attempt counting and auto-banning
-async fn handle_failed_login(db: &Database, login: &str, safe_login: &str, addr: &SocketAddr) {
+pub async fn handle_failed_login(db: &Database, login: &str, safe_login: &str, addr: &SocketAddr) {
     let max_wrong = {
         let config = get_config().lock();
         config.get_int_default("WrongPass.MaxCount", 0) as u32
     };
 
     if max_wrong == 0 {
-        tracing::trace!("[{}] WrongPass.MaxCount=0, failed login counting disabled", addr);
+        tracing::trace!("WrongPass.MaxCount=0, failed login counting disabled");
         return;
     }
 
@@ -751,9 +1412,10 @@ async fn handle_failed_login(db: &Database, login: &str, safe_login: &str, addr:
 
     if let Ok(Some(row)) = db.query_one(&sql).await {
         let failed_logins: u32 = row.get_u32(1);
-        tracing::debug!("[{}] Account '{}' failed login count: {}/{}", addr, login, failed_logins, max_wrong);
+        tracing::debug!("Account '{}' failed login count: {}/{}", login, failed_logins, max_wrong);
 
         if failed_logins >= max_wrong {
+            let now = unix_now();
             let (ban_time, ban_type) = {
                 let config = get_config().lock();
                 (
@@ -767,26 +1429,42 @@ async fn handle_failed_login(db: &Database, login: &str, safe_login: &str, addr:
                 let _ = db
                     .execute(&format!(
                         "INSERT INTO account_banned(account_id, banned_at, expires_at, banned_by, reason, active) \
-                         VALUES ('{}', UNIX_TIMESTAMP(), UNIX_TIMESTAMP()+'{}', 'MaNGOS realmd', 'Failed login autoban', 1)",
-                        acc_id, ban_time
+                         VALUES ('{}', '{}', '{}', 'MaNGOS realmd', 'Failed login autoban', 1)",
+                        acc_id, now, now + u64::from(ban_time)
                     ))
                     .await;
                 tracing::warn!(
-                    "[{}] Account '{}' (id={}) auto-banned for {}s ({} failed attempts)",
-                    addr, login, acc_id, ban_time, failed_logins
+                    target: LOG_TARGET_AUDIT,
+                    "Account '{}' (id={}) auto-banned for {}s ({} failed attempts)",
+                    login, acc_id, ban_time, failed_logins
                 );
+                webhook::notify(SecurityEvent::Autoban {
+                    target: format!("Account '{}'", login),
+                    account: login.to_string(),
+                    ip: addr.ip(),
+                    ban_time,
+                    failures: failed_logins,
+                });
             } else {
                 let ip = Database::escape_string(&addr.ip().to_string());
                 let _ = db
                     .execute(&format!(
-                        "INSERT INTO ip_banned VALUES ('{}', UNIX_TIMESTAMP(), UNIX_TIMESTAMP()+'{}', 'MaNGOS realmd', 'Failed login autoban')",
-                        ip, ban_time
+                        "INSERT INTO ip_banned VALUES ('{}', '{}', '{}', 'MaNGOS realmd', 'Failed login autoban')",
+                        ip, now, now + u64::from(ban_time)
                     ))
                     .await;
                 tracing::warn!(
-                    "[{}] IP {} auto-banned for {}s (account '{}', {} failed attempts)",
-                    addr, addr.ip(), ban_time, login, failed_logins
+                    target: LOG_TARGET_AUDIT,
+                    "IP {} auto-banned for {}s (account '{}', {} failed attempts)",
+                    addr.ip(), ban_time, login, failed_logins
                 );
+                webhook::notify(SecurityEvent::Autoban {
+                    target: format!("IP {}", addr.ip()),
+                    account: login.to_string(),
+                    ip: addr.ip(),
+                    ban_time,
+                    failures: failed_logins,
+                });
             }
         }
     }
@@ -795,82 +1473,105 @@ async fn handle_failed_login(db: &Database, login: &str, safe_login: &str, addr:
 /// Verify client version and finalize authentication
 #[allow(clippy::too_many_arguments)]
 async fn verify_and_finalize(
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
     addr: &SocketAddr,
     db: &Database,
-    status: &mut SessionStatus,
-    srp: &mut SRP6,
-    login: &str,
-    safe_login: &str,
-    safe_locale: &str,
-    os: &str,
-    platform: &str,
-    build: u16,
+    srp: &SRP6,
+    client: ClientInfo,
+    account_security_level: AccountTypes,
     proof: &AuthLogonProofClient,
     timeout_duration: Duration,
-) -> Result<(), anyhow::Error> {
+) -> Result<Session, anyhow::Error> {
+    let ClientInfo { login, safe_login, build, os, platform, safe_locale, .. } = &client;
+    let build = *build;
+    client_stats::record(build, os, platform, safe_locale);
+
     // Verify version
-    tracing::trace!("[{}] Verifying client version for '{}' (build={} os='{}')", addr, login, build, os);
+    tracing::trace!("Verifying client version for '{}' (build={} os='{}')", login, build, os);
 
     if !verify_version(build, os, &proof.a, &proof.crc_hash, false) {
-        tracing::info!("[{}] Account '{}' rejected: modified client detected (build={})", addr, login, build);
+        tracing::info!("Account '{}' rejected: modified client detected (build={})", login, build);
+        auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::FailedVersionInvalid, "modified client");
         let response: [u8; 2] = [
             AuthCmd::LogonProof as u8,
             AuthLogonResult::FailedVersionInvalid as u8,
         ];
         write_with_timeout(stream, &response, timeout_duration).await?;
-        return Ok(());
+        return Ok(Session::Closed);
     }
 
-    tracing::info!("[{}] User '{}' successfully authenticated (build={} os='{}' platform='{}')", addr, login, build, os, platform);
+    tracing::info!(target: LOG_TARGET_AUDIT, "User '{}' successfully authenticated (build={} os='{}' platform='{}')", login, build, os, platform);
+    auth_log::record(AuthStage::Proof, login, addr.ip(), build, AuthLogonResult::Success, "authenticated");
 
     // Update session in database
-    let k_hex = srp.get_strong_session_key().as_hex_str();
-    tracing::trace!("[{}] Storing session key for '{}' (length={})", addr, login, k_hex.len());
-
-    let _ = db
-        .execute(&format!(
-            "UPDATE account SET sessionkey = '{}', locale = '{}', failed_logins = 0, os = '{}', platform = '{}' \
-             WHERE username = '{}'",
-            k_hex, safe_locale, os, platform, safe_login
-        ))
-        .await;
+    let k_hex = Zeroizing::new(srp.get_strong_session_key().as_hex_str());
+    tracing::trace!("Storing session key for '{}' (length={})", login, k_hex.len());
+    let k_hex = session_key::seal(login, &k_hex);
+
+    let session_sql = Zeroizing::new(format!(
+        "UPDATE account SET sessionkey = '{}', locale = '{}', failed_logins = 0, os = '{}', platform = '{}' \
+         WHERE username = '{}'",
+        *k_hex, safe_locale, os, platform, safe_login
+    ));
+    let _ = until_disconnect(stream, db.execute(&session_sql)).await?;
+    tarpit::clear_account(login).await;
 
     // Log the login
-    if let Ok(Some(row)) = db
-        .query_one(&format!(
-            "SELECT id FROM account WHERE username = '{}'",
+    let mut account_flags: u32 = 0;
+    if let Ok(Some(row)) = until_disconnect(
+        stream,
+        db.query_one(&format!(
+            "SELECT id, CAST(gmlevel AS SIGNED) AS gmlevel, flags FROM account WHERE username = '{}'",
             safe_login
-        ))
-        .await
+        )),
+    )
+    .await?
     {
         let account_id: u32 = row.get_u32(0);
+        let gmlevel: AccountTypes = row.get_u8(1);
+        account_flags = row.get_u32(2);
+        if gmlevel > SEC_PLAYER {
+            webhook::notify(SecurityEvent::GmLogin { account: login.to_string(), gmlevel, ip: addr.ip() });
+        }
         let ip = Database::escape_string(&addr.ip().to_string());
         let _ = db
             .execute(&format!(
                 "INSERT INTO account_logons(accountId, ip, loginTime, loginSource) \
-                 VALUES('{}', '{}', NOW(), '{}')",
-                account_id, ip, LOGIN_TYPE_REALMD
+                 VALUES('{}', '{}', '{}', '{}')",
+                account_id, ip, local_datetime(), LOGIN_TYPE_REALMD
             ))
             .await;
-        tracing::debug!("[{}] Login recorded: account_id={} ip={}", addr, account_id, ip);
+        tracing::debug!("Login recorded: account_id={} ip={}", account_id, ip);
+        web_token::issue(db, account_id, addr.ip()).await;
     }
 
     // Send proof to client
     let mut sha = Sha1Hash::new();
     srp.finalize(&mut sha);
-    send_proof(stream, build, &sha, timeout_duration).await?;
+    send_proof(stream, build, &sha, account_flags, timeout_duration).await?;
 
-    *status = SessionStatus::Authed;
-    tracing::debug!("[{}] '{}' -> state Authed, ready for realm list", addr, login);
-    Ok(())
+    tracing::debug!("'{}' -> state Authed, ready for realm list", login);
+    Ok(Session::Authed(AuthedState { client, account_security_level }))
+}
+
+/// Parse a flags value written in decimal or as 0x-prefixed hex
+fn parse_flags(text: &str) -> Option<u32> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
 }
 
 /// Send the logon proof response to the client
+///
+/// The account flags are Auth.AccountFlags combined with the account's own
+/// `flags` column; the survey id comes from Auth.SurveyId.
 async fn send_proof(
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
     build: u16,
     sha: &Sha1Hash,
+    account_flags: u32,
     timeout_duration: Duration,
 ) -> Result<(), anyhow::Error> {
     match build {
@@ -886,14 +1587,27 @@ async fn send_proof(
             write_with_timeout(stream, &proof.to_bytes(), timeout_duration).await?;
         }
         _ => {
-            // 2.x+ client
-            tracing::trace!("Sending standard (2.x+) LogonProof response");
+            // 2.x and 3.x client (the same proof through 3.3.5a)
+            let (default_flags, survey_id) = {
+                let config = get_config().lock();
+                let flags = config.get_string_default("Auth.AccountFlags", "0x00800000");
+                let default_flags = parse_flags(&flags).unwrap_or_else(|| {
+                    tracing::warn!("Invalid Auth.AccountFlags '{}', using ProPass", flags);
+                    AccountFlags::ProPass as u32
+                });
+                (default_flags, config.get_int_default("Auth.SurveyId", 0).max(0) as u32)
+            };
+            let account_flags = default_flags | account_flags;
+            tracing::trace!(
+                "Sending standard (2.x+) LogonProof response (account_flags=0x{:08X} survey_id={})",
+                account_flags, survey_id
+            );
             let proof = AuthLogonProofServer {
                 cmd: AuthCmd::LogonProof as u8,
                 error: 0,
                 m2: *sha.get_digest(),
-                account_flags: AccountFlags::ProPass as u32,
-                survey_id: 0,
+                account_flags,
+                survey_id,
                 unk_flags: 0,
             };
             write_with_timeout(stream, &proof.to_bytes(), timeout_duration).await?;
@@ -903,110 +1617,127 @@ async fn send_proof(
 }
 
 /// Handle CMD_AUTH_RECONNECT_CHALLENGE
-#[allow(clippy::too_many_arguments)]
 async fn handle_reconnect_challenge(
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
     addr: &SocketAddr,
     db: &Database,
-    status: &mut SessionStatus,
-    srp: &mut SRP6,
-    login: &mut String,
-    safe_login: &mut String,
-    build: &mut u16,
-    reconnect_proof: &mut BigNumber,
     timeout_duration: Duration,
-) -> Result<(), anyhow::Error> {
+) -> Result<Session, anyhow::Error> {
     // Read header
     let mut header_buf = [0u8; AuthLogonChallengeHeader::SIZE];
     read_with_timeout(stream, &mut header_buf, timeout_duration).await?;
 
-    let header = AuthLogonChallengeHeader::from_bytes(&header_buf)
-        .ok_or_else(|| anyhow::anyhow!("Invalid reconnect challenge header"))?;
-
-    let remaining = header.size as usize;
-    tracing::trace!("[{}] ReconnectChallenge header: size={}", addr, remaining);
-
-    *status = SessionStatus::Closed;
+    let header = AuthLogonChallengeHeader::from_bytes(&header_buf)?;
+    let remaining = header.body_size()?;
+    tracing::trace!("ReconnectChallenge header: size={}", remaining);
 
     // Read body
-    let mut body_buf = vec![0u8; remaining];
-    read_with_timeout(stream, &mut body_buf, timeout_duration).await?;
+    let body_buf = read_body_with_timeout(stream, remaining, AuthLogonChallengeBody::MAX_SIZE, timeout_duration).await?;
 
-    let body = AuthLogonChallengeBody::from_bytes(&body_buf)
-        .ok_or_else(|| anyhow::anyhow!("Invalid reconnect challenge body"))?;
+    let body = AuthLogonChallengeBody::from_bytes(&body_buf)?;
 
-    if body.username_len > 10 {
-        tracing::debug!("[{}] ReconnectChallenge username too long: {}", addr, body.username_len);
+    if body.username.len() > 10 {
+        tracing::debug!("ReconnectChallenge username too long: {}", body.username.len());
         return Err(anyhow::anyhow!("Username too long for reconnect"));
     }
 
-    *login = body.username_string();
-    *safe_login = Database::escape_string(login);
-    *build = body.build;
+    let login = body.username;
+    let safe_login = Database::escape_string(&login);
+    let build = body.build;
+    record_session_account(&login, build);
+
+    tracing::debug!("ReconnectChallenge: account='{}' build={}", login, build);
 
-    tracing::debug!("[{}] ReconnectChallenge: account='{}' build={}", addr, login, build);
+    if let Some(reason) = maintenance::reason() {
+        tracing::debug!("Answering reconnect of '{}' with server busy ({})", login, reason);
+        auth_log::record(AuthStage::Reconnect, &login, addr.ip(), build, AuthLogonResult::FailedDbBusy, reason);
+        let response = [AuthCmd::ReconnectChallenge as u8, AuthLogonResult::FailedDbBusy as u8];
+        write_with_timeout(stream, &response, timeout_duration).await?;
+        return Ok(Session::Closed);
+    }
 
-    // Look up session key
+    // Look up the session key, and the client the session was opened with for the version check
     let sql = format!(
-        "SELECT CAST(sessionkey AS CHAR) AS sessionkey FROM account WHERE username = '{}'",
+        "SELECT CAST(sessionkey AS CHAR) AS sessionkey, os, platform, CAST(gmlevel AS SIGNED) AS gmlevel \
+         FROM account WHERE username = '{}'",
         safe_login
     );
 
-    match db.query_one(&sql).await? {
-        Some(row) => {
-            let session_key: String = row.get_string(0);
-            tracing::trace!("[{}] Session key found for '{}' (length={})", addr, login, session_key.len());
-            srp.set_strong_session_key(&session_key);
-        }
-        None => {
-            tracing::info!("[{}] Reconnect failed: no session key for '{}'", addr, login);
-            return Err(anyhow::anyhow!("No session key"));
+    let Some(row) = until_disconnect(stream, db.query_one(&sql)).await?? else {
+        tracing::info!("Reconnect failed: no session key for '{}'", login);
+        auth_log::record(AuthStage::Reconnect, &login, addr.ip(), build, AuthLogonResult::FailedUnknownAccount, "no session key");
+        return Err(anyhow::anyhow!("No session key"));
+    };
+
+    let stored_key = Zeroizing::new(row.get_string(0));
+    let session_key_hex = match session_key::open(&login, &stored_key) {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::warn!("Reconnect failed: cannot read the session key of '{}': {:#}", login, e);
+            auth_log::record(AuthStage::Reconnect, &login, addr.ip(), build, AuthLogonResult::FailedUnknownAccount, "unreadable session key");
+            return Err(anyhow::anyhow!("Unreadable session key"));
         }
-    }
+    };
+    tracing::trace!("Session key found for '{}' (length={})", login, session_key_hex.len());
+    let mut session_key = Zeroizing::new(BigNumber::new());
+    session_key.set_hex_str(&session_key_hex);
 
-    *status = SessionStatus::ReconProof;
+    // Accounts that never logged in through this realmd have the column default "0"
+    let (stored_os, stored_platform) = (row.get_string(1), row.get_string(2));
+    let known = |value: &str| !value.is_empty() && value != "0";
+    let os = if known(&stored_os) { stored_os } else { Database::escape_string(&body.os) };
+    let platform = if known(&stored_platform) { stored_platform } else { body.platform };
+    tracing::trace!("Reconnecting client of '{}': os='{}' platform='{}'", login, os, platform);
 
     // Send response
     let mut pkt = ByteBuffer::new();
     pkt.write_u8(AuthCmd::ReconnectChallenge as u8);
     pkt.write_u8(0x00);
 
+    let mut reconnect_proof = Zeroizing::new(BigNumber::new());
     reconnect_proof.set_rand(16 * 8);
     pkt.append(&reconnect_proof.as_byte_array(16)[..16]);
     pkt.append(&VERSION_CHALLENGE);
 
-    tracing::debug!("[{}] ReconnectChallenge SUCCESS for '{}' -> state ReconProof", addr, login);
+    tracing::debug!("ReconnectChallenge SUCCESS for '{}' -> state ReconProof", login);
     write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-    Ok(())
+
+    let client = ClientInfo {
+        login,
+        safe_login,
+        build,
+        os,
+        platform,
+        safe_locale: Database::escape_string(&body.locale),
+        locale: body.locale,
+    };
+    Ok(Session::ReconnectProof(Box::new(ReconnectProofState {
+        client,
+        account_security_level: row.get_u8(3).min(SEC_ADMINISTRATOR),
+        session_key,
+        reconnect_proof,
+    })))
 }
 
 /// Handle CMD_AUTH_RECONNECT_PROOF
-#[allow(clippy::too_many_arguments)]
 async fn handle_reconnect_proof(
-    stream: &mut TcpStream,
-    _addr: &SocketAddr,
-    _db: &Database,
-    status: &mut SessionStatus,
-    srp: &SRP6,
-    login: &str,
-    reconnect_proof: &BigNumber,
-    build: u16,
-    os: &str,
+    stream: &mut ClientStream,
+    addr: &SocketAddr,
+    state: ReconnectProofState,
     timeout_duration: Duration,
-) -> Result<(), anyhow::Error> {
+) -> Result<Session, anyhow::Error> {
     let mut proof_buf = [0u8; AuthReconnectProofClient::SIZE];
     read_with_timeout(stream, &mut proof_buf, timeout_duration).await?;
 
-    let proof = AuthReconnectProofClient::from_bytes(&proof_buf)
-        .ok_or_else(|| anyhow::anyhow!("Invalid reconnect proof"))?;
-
-    *status = SessionStatus::Closed;
+    let proof = AuthReconnectProofClient::from_bytes(&proof_buf)?;
 
-    let k = srp.get_strong_session_key();
+    let ReconnectProofState { client, account_security_level, session_key, reconnect_proof } = state;
+    let (login, build, os) = (client.login.as_str(), client.build, client.os.as_str());
+    let k: &BigNumber = &session_key;
     if login.is_empty() || reconnect_proof.get_num_bytes() == 0 || k.get_num_bytes() == 0 {
-        tracing::debug!("[{}] ReconnectProof: missing data (login='{}' proof_len={} key_len={})",
-            _addr, login, reconnect_proof.get_num_bytes(), k.get_num_bytes());
-        return Ok(());
+        tracing::debug!("ReconnectProof: missing data (login='{}' proof_len={} key_len={})",
+            login, reconnect_proof.get_num_bytes(), k.get_num_bytes());
+        return Ok(Session::Closed);
     }
 
     let mut t1 = BigNumber::new();
@@ -1015,20 +1746,21 @@ async fn handle_reconnect_proof(
     let mut sha = Sha1Hash::new();
     sha.initialize();
     sha.update_data(login);
-    sha.update_big_numbers(&[&t1, reconnect_proof, k]);
+    sha.update_big_numbers(&[&t1, &reconnect_proof, k]);
     sha.finalize();
 
-    tracing::trace!("[{}] Verifying reconnect proof for '{}'", _addr, login);
+    tracing::trace!("Verifying reconnect proof for '{}'", login);
 
-    if sha.get_digest()[..] == proof.r2[..] {
+    if constant_time_eq(&sha.get_digest()[..], &proof.r2) {
         // Verify version
         if !verify_version(build, os, &proof.r1, &proof.r3, true) {
-            tracing::info!("[{}] Reconnect failed for '{}': modified client (build={})", _addr, login, build);
+            tracing::info!("Reconnect failed for '{}': modified client (build={})", login, build);
+            auth_log::record(AuthStage::Reconnect, login, addr.ip(), build, AuthLogonResult::FailedVersionInvalid, "modified client");
             let mut pkt = ByteBuffer::new();
             pkt.write_u8(AuthCmd::ReconnectProof as u8);
             pkt.write_u8(AuthLogonResult::FailedVersionInvalid as u8);
             write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
-            return Ok(());
+            return Ok(Session::Closed);
         }
 
         let mut pkt = ByteBuffer::new();
@@ -1037,33 +1769,33 @@ async fn handle_reconnect_proof(
         pkt.write_u16(0x00);
         write_with_timeout(stream, pkt.contents(), timeout_duration).await?;
 
-        *status = SessionStatus::Authed;
-        tracing::info!("[{}] User '{}' successfully reconnected (build={})", _addr, login, build);
-    } else {
-        tracing::info!("[{}] Reconnect proof mismatch for '{}': session invalid", _addr, login);
+        tracing::info!(target: LOG_TARGET_AUDIT, "User '{}' successfully reconnected (build={})", login, build);
+        auth_log::record(AuthStage::Reconnect, login, addr.ip(), build, AuthLogonResult::Success, "reconnected");
+        return Ok(Session::Authed(AuthedState { client, account_security_level }));
     }
 
-    Ok(())
+    tracing::info!("Reconnect proof mismatch for '{}': session invalid", login);
+    auth_log::record(AuthStage::Reconnect, login, addr.ip(), build, AuthLogonResult::FailedUnknownAccount, "session invalid");
+    Ok(Session::Closed)
 }
 
 /// Handle CMD_REALM_LIST
-#[allow(clippy::too_many_arguments)]
 async fn handle_realm_list(
-    stream: &mut TcpStream,
-    _addr: &SocketAddr,
+    stream: &mut ClientStream,
+    addr: &SocketAddr,
     db: &Database,
-    realm_list: &Arc<tokio::sync::RwLock<RealmList>>,
-    safe_login: &str,
-    login: &str,
-    build: u16,
-    account_security_level: AccountTypes,
+    realm_list: &Arc<RealmList>,
+    session: &AuthedState,
     timeout_duration: Duration,
 ) -> Result<(), anyhow::Error> {
+    let ClientInfo { login, safe_login, build, locale, .. } = &session.client;
+    let (build, account_security_level) = (*build, session.account_security_level);
+
     // Skip 4 bytes of padding from client
     let mut skip_buf = [0u8; 4];
     read_with_timeout(stream, &mut skip_buf, timeout_duration).await?;
 
-    tracing::debug!("[{}] RealmList request from '{}' (build={})", _addr, login, build);
+    tracing::debug!("RealmList request from '{}' (build={})", login, build);
 
     // Get account ID and GM level
     let sql = format!(
@@ -1071,60 +1803,115 @@ async fn handle_realm_list(
         safe_login
     );
 
-    let (account_id, security_level) = match db.query_one(&sql).await? {
+    let (account_id, security_level) = match until_disconnect(stream, db.query_one(&sql)).await?? {
         Some(row) => (row.get_u32(0), row.get_u8(1)),
         None => {
-            tracing::error!("[{}] User '{}' not found for realm list", _addr, login);
+            tracing::error!("User '{}' not found for realm list", login);
             return Err(anyhow::anyhow!("Account not found"));
         }
     };
 
-    // Update realm list if needed
-    {
-        let mut rl = realm_list.write().await;
-        rl.update_if_needed(db).await;
-    }
+    let access = until_disconnect(stream, account_mgr::load_realm_access(db, account_id)).await?;
+    let viewer = RealmListViewer {
+        build,
+        security_level,
+        account_security_level,
+        access: &access,
+        announcement: realm_list.announcement(),
+    };
 
-    // Build realm list packet - clone realm data to avoid holding lock across await
-    let realms_snapshot = {
-        let rl = realm_list.read().await;
-        rl.realms().clone()
+    until_disconnect(stream, realm_list.resolve_addresses()).await?;
+    let local = realm_list.is_local(addr.ip());
+    let build_packet = |realms: &_, locale: &_, local| build_realm_list_packet(realms, &viewer, locale, local);
+    let packet = if access.is_empty() {
+        // Serialized realm list for this kind of client, shared until the next refresh
+        realm_list.packet((build, security_level, account_security_level), locale, local, build_packet)
+    } else {
+        realm_list.account_packet(locale, local, build_packet)
     };
+    let char_counts = realm_list.char_counts();
+    let char_counts = until_disconnect(stream, char_counts.counts(db, account_id, &packet.realm_ids())).await?;
 
     tracing::debug!(
-        "[{}] Sending {} realm(s) to '{}' (account_id={} gmlevel={})",
-        _addr, realms_snapshot.len(), login, account_id, security_level
+        "Sending {} realm(s) to '{}' (account_id={} gmlevel={} chars={:?})",
+        packet.char_count_offsets.len(), login, account_id, security_level, char_counts
     );
 
+    let response = packet.with_char_counts(&char_counts);
+    tracing::trace!("RealmList response: {} bytes total", response.len());
+    write_with_timeout(stream, &response, timeout_duration).await?;
+    Ok(())
+}
+
+/// Account a realm list is built for
+struct RealmListViewer<'a> {
+    build: u16,
+    /// account.gmlevel when the list is requested
+    security_level: AccountTypes,
+    /// Level the session logged in with
+    account_security_level: AccountTypes,
+    /// Per-realm levels raising both of the above
+    access: &'a RealmAccess,
+    /// Name of an offline entry listed after the realms
+    announcement: Option<&'a str>,
+}
+
+impl RealmListViewer<'_> {
+    /// Whether `realm` is listed at all; players do not see GM realms
+    fn sees(&self, realm: &realm_list::Realm) -> bool {
+        self.access.level(realm.id, self.security_level) > SEC_PLAYER || realm.allowed_security_level == SEC_PLAYER
+    }
+
+    /// Whether `realm` is listed but cannot be entered
+    fn locked_out(&self, realm: &realm_list::Realm) -> bool {
+        realm.allowed_security_level > self.access.level(realm.id, self.account_security_level)
+    }
+}
+
+/// Serialize the realm list response (header included) with zero character counts
+fn build_realm_list_packet(
+    realms: &std::collections::BTreeMap<String, realm_list::Realm>,
+    viewer: &RealmListViewer,
+    locale: &str,
+    local: bool,
+) -> RealmListPacket {
     let mut pkt = ByteBuffer::new();
-    load_realm_list(&mut pkt, &realms_snapshot, account_id, security_level, build, account_security_level, db).await;
+    let char_count_offsets = load_realm_list(&mut pkt, realms, viewer, locale, local);
 
-    // Send header + realm list
     let mut hdr = ByteBuffer::new();
     hdr.write_u8(AuthCmd::RealmList as u8);
     hdr.write_u16(pkt.size() as u16);
+    let header_len = hdr.size();
     hdr.append(pkt.contents());
 
-    tracing::trace!("[{}] RealmList response: {} bytes total", _addr, hdr.size());
-    write_with_timeout(stream, hdr.contents(), timeout_duration).await?;
-    Ok(())
+    RealmListPacket {
+        bytes: hdr.contents().to_vec(),
+        char_count_offsets: char_count_offsets
+            .into_iter()
+            .map(|(offset, realm_id)| (offset + header_len, realm_id))
+            .collect(),
+    }
 }
 
-/// Build the realm list packet
-async fn load_realm_list(
+/// Build the realm list packet; returns the offset of each realm's character count byte
+///
+/// Realm names are translated for `locale` where realmlist_locale has one;
+/// `local` clients get the realmlist_local addresses.
+fn load_realm_list(
     pkt: &mut ByteBuffer,
     realms: &std::collections::BTreeMap<String, realm_list::Realm>,
-    account_id: u32,
-    security_level: u8,
-    build: u16,
-    account_security_level: AccountTypes,
-    db: &Database,
-) {
-    // Count eligible realms
-    let eligible_count = realms
-        .values()
-        .filter(|r| r.allowed_security_level <= security_level)
-        .count();
+    viewer: &RealmListViewer,
+    locale: &str,
+    local: bool,
+) -> Vec<(usize, u32)> {
+    let mut char_count_offsets = Vec::new();
+    let build = viewer.build;
+
+    // Count eligible realms, skipped by the same check as below
+    let eligible_count = realms.values().filter(|r| viewer.sees(r)).count() + usize::from(viewer.announcement.is_some());
+    // The announcement goes in the tab of the first listed realm
+    let first_timezone = realms.values().find(|r| viewer.sees(r)).map_or(1, |r| r.timezone);
+    let announcement_category = get_realm_category_id(build, first_timezone);
 
     match build {
         5875 | 6005 | 6141 => {
@@ -1134,23 +1921,21 @@ async fn load_realm_list(
 
             for (name, realm) in realms {
                 // Skip realms that require higher security
-                if security_level == 0 && realm.allowed_security_level > 0 {
+                if !viewer.sees(realm) {
                     tracing::trace!("Skipping realm '{}' (requires security level {})", name, realm.allowed_security_level);
                     continue;
                 }
 
-                // Get character count
-                let char_count = get_char_count(db, realm.id, account_id).await;
-
                 let ok_build = realm.realm_builds.contains(&(build as u32));
                 let build_info = if ok_build {
-                    find_build_info(build)
+                    realm.build_info(build)
                 } else {
                     None
                 };
-                let build_info = build_info.unwrap_or(&realm.realm_build_info);
+                let build_info = build_info.as_ref().unwrap_or(&realm.realm_build_info);
 
                 let mut realm_flags = realm.realm_flags;
+                let name = realm.localized_name(name, locale);
 
                 // Append version to name for SPECIFYBUILD flag (1.x doesn't support it natively)
                 let display_name = if realm_flags & RealmFlags::REALM_FLAG_SPECIFYBUILD != 0 {
@@ -1162,58 +1947,66 @@ async fn load_realm_list(
                         build_info.bugfix_version
                     )
                 } else {
-                    name.clone()
+                    name.to_string()
                 };
 
-                if !ok_build || realm.allowed_security_level > account_security_level {
+                if !ok_build || viewer.locked_out(realm) {
                     realm_flags |= RealmFlags::REALM_FLAG_OFFLINE;
                 }
 
                 let category_id = get_realm_category_id(build, realm.timezone);
+                let address = realm.address_for(local);
 
                 tracing::trace!(
-                    "Realm '{}': id={} addr='{}' flags=0x{:02X} chars={} population={:.1}",
-                    display_name, realm.id, realm.address, realm_flags, char_count, realm.population_level
+                    "Realm '{}': id={} addr='{}' flags=0x{:02X} population={:.1}",
+                    display_name, realm.id, address, realm_flags, realm.population_level
                 );
 
                 pkt.write_u32(realm.icon as u32);
                 pkt.write_u8(realm_flags);
                 pkt.write_string(&display_name);
-                pkt.write_string(&realm.address);
+                pkt.write_string(address);
                 pkt.write_f32(realm.population_level);
-                pkt.write_u8(char_count);
+                char_count_offsets.push((pkt.size(), realm.id));
+                pkt.write_u8(0); // character count, patched in per account
                 pkt.write_u8(category_id);
                 pkt.write_u8(0x00);
             }
 
+            if let Some(announcement) = viewer.announcement {
+                pkt.write_u32(0);
+                pkt.write_u8(RealmFlags::REALM_FLAG_OFFLINE);
+                pkt.write_string(announcement);
+                pkt.write_string("0.0.0.0:0");
+                pkt.write_f32(0.0);
+                pkt.write_u8(0);
+                pkt.write_u8(announcement_category);
+                pkt.write_u8(0x00);
+            }
+
             pkt.write_u16(0x0002);
         }
         _ => {
-            // 2.x+ client format
+            // 2.x and 3.x client format
             pkt.write_u32(0); // unused
             pkt.write_u16(eligible_count as u16);
 
             for (name, realm) in realms {
-                if security_level == 0 && realm.allowed_security_level > 0 {
+                if !viewer.sees(realm) {
                     tracing::trace!("Skipping realm '{}' (requires security level {})", name, realm.allowed_security_level);
                     continue;
                 }
 
-                let char_count = get_char_count(db, realm.id, account_id).await;
                 let ok_build = realm.realm_builds.contains(&(build as u32));
 
                 let build_info = if ok_build {
-                    find_build_info(build)
+                    realm.build_info(build)
                 } else {
                     None
                 };
-                let build_info_ref = build_info.unwrap_or(&realm.realm_build_info);
+                let build_info_ref = build_info.as_ref().unwrap_or(&realm.realm_build_info);
 
-                let lock: u8 = if realm.allowed_security_level > account_security_level {
-                    1
-                } else {
-                    0
-                };
+                let lock: u8 = if viewer.locked_out(realm) { 1 } else { 0 };
 
                 let mut realm_flags = realm.realm_flags;
                 if !ok_build {
@@ -1224,19 +2017,22 @@ async fn load_realm_list(
                 }
 
                 let category_id = get_realm_category_id(build, realm.timezone);
+                let address = realm.address_for(local);
+                let name = realm.localized_name(name, locale);
 
                 tracing::trace!(
-                    "Realm '{}': id={} addr='{}' flags=0x{:02X} lock={} chars={} population={:.1}",
-                    name, realm.id, realm.address, realm_flags, lock, char_count, realm.population_level
+                    "Realm '{}': id={} addr='{}' flags=0x{:02X} lock={} population={:.1}",
+                    name, realm.id, address, realm_flags, lock, realm.population_level
                 );
 
                 pkt.write_u8(realm.icon);
                 pkt.write_u8(lock);
                 pkt.write_u8(realm_flags);
                 pkt.write_string(name);
-                pkt.write_string(&realm.address);
+                pkt.write_string(address);
                 pkt.write_f32(realm.population_level);
-                pkt.write_u8(char_count);
+                char_count_offsets.push((pkt.size(), realm.id));
+                pkt.write_u8(0); // character count, patched in per account
                 pkt.write_u8(category_id);
                 pkt.write_u8(0x2C);
 
@@ -1248,22 +2044,23 @@ async fn load_realm_list(
                 }
             }
 
+            if let Some(announcement) = viewer.announcement {
+                pkt.write_u8(0);
+                pkt.write_u8(0); // lock
+                pkt.write_u8(RealmFlags::REALM_FLAG_OFFLINE);
+                pkt.write_string(announcement);
+                pkt.write_string("0.0.0.0:0");
+                pkt.write_f32(0.0);
+                pkt.write_u8(0);
+                pkt.write_u8(announcement_category);
+                pkt.write_u8(0x2C);
+            }
+
             pkt.write_u16(0x0010);
         }
     }
-}
-
-/// Get the character count for an account on a realm
-async fn get_char_count(db: &Database, realm_id: u32, account_id: u32) -> u8 {
-    let sql = format!(
-        "SELECT CAST(numchars AS SIGNED) AS numchars FROM realmcharacters WHERE realmid = '{}' AND acctid = '{}'",
-        realm_id, account_id
-    );
 
-    match db.query_one(&sql).await {
-        Ok(Some(row)) => row.get_u8(0),
-        _ => 0,
-    }
+    char_count_offsets
 }
 
 /// Verify client version hash
@@ -1279,133 +2076,40 @@ fn verify_version(build: u16, os: &str, a: &[u8], version_proof: &[u8], is_recon
 
     let zeros = [0u8; 20];
 
-    let version_hash: &[u8; 20] = if !is_reconnect {
-        let build_info = match find_build_info(build) {
-            Some(info) => info,
-            None => {
-                tracing::trace!("No build info for build {}", build);
-                return false;
-            }
-        };
-
-        let hash = match os {
-            "Win" => &build_info.windows_hash,
-            "OSX" => &build_info.mac_hash,
-            _ => {
-                tracing::trace!("Unknown OS '{}' for version check", os);
-                return false;
-            }
-        };
+    // Build and OS are checked on reconnects too
+    let build_info = match find_build_info(build) {
+        Some(info) => info,
+        None => {
+            tracing::trace!("No build info for build {}", build);
+            return false;
+        }
+    };
 
-        if *hash == zeros {
-            tracing::trace!("No version hash stored server-side for build={} os='{}', accepting", build, os);
-            return true; // not filled serverside
+    let hash = match os {
+        "Win" => build_info.windows_hash,
+        "OSX" => build_info.mac_hash,
+        _ => {
+            tracing::trace!("Unknown OS '{}' for version check", os);
+            return false;
         }
+    };
 
-        hash
+    // A reconnecting client proves its version over zeros instead of the executable hash
+    let version_hash: [u8; 20] = if is_reconnect {
+        zeros
+    } else if hash == zeros {
+        tracing::trace!("No version hash stored server-side for build={} os='{}', accepting", build, os);
+        return true; // not filled serverside
     } else {
-        &zeros
+        hash
     };
 
     let mut sha = Sha1Hash::new();
     sha.update_data_bytes(a);
-    sha.update_data_bytes(version_hash);
+    sha.update_data_bytes(&version_hash);
     sha.finalize();
 
-    let result = sha.get_digest()[..] == version_proof[..20.min(version_proof.len())];
+    let result = constant_time_eq(&sha.get_digest()[..], &version_proof[..20.min(version_proof.len())]);
     tracing::trace!("Version check result: {}", if result { "PASS" } else { "FAIL" });
     result
 }
-
-/// Calculate the SHA pass hash for account creation.
-/// Matches C++ AccountMgr::CalculateShaPassHash:
-///   SHA1(UPPER(username) + ":" + UPPER(password))
-/// Returns the hex-encoded digest string.
-fn calculate_sha_pass_hash(username: &str, password: &str) -> String {
-    let mut sha = Sha1Hash::new();
-    sha.initialize();
-    sha.update_data(&username.to_uppercase());
-    sha.update_data(":");
-    sha.update_data(&password.to_uppercase());
-    sha.finalize();
-
-    // Hex-encode the digest
-    sha.get_digest()
-        .iter()
-        .map(|b| format!("{:02X}", b))
-        .collect::<String>()
-}
-
-/// Auto-create an account when it doesn't exist.
-/// The password is set to be the same as the username (development convenience).
-/// Uses the same SRP6 verifier generation as C++ AccountMgr::CreateAccount.
-async fn auto_create_account(
-    db: &Database,
-    login: &str,
-    safe_login: &str,
-) -> Result<(), anyhow::Error> {
-    // Password = username (standard dev convention)
-    let ri = calculate_sha_pass_hash(login, login);
-
-    let mut srp = SRP6::new();
-    if !srp.calculate_verifier_random(&ri) {
-        return Err(anyhow::anyhow!("Failed to generate SRP6 verifier"));
-    }
-
-    let s_hex = srp.get_salt().as_hex_str();
-    let v_hex = srp.get_verifier().as_hex_str();
-
-    let expansion = {
-        let config = get_config().lock();
-        config.get_int_default("AutoCreateAccounts.Expansion", 1) as u32
-    };
-
-    tracing::debug!(
-        "Auto-creating account '{}': expansion={} salt_len={} verifier_len={}",
-        login, expansion, s_hex.len(), v_hex.len()
-    );
-
-    let sql = format!(
-        "INSERT INTO account(username, v, s, expansion, joindate) \
-         VALUES('{}', '{}', '{}', '{}', NOW())",
-        safe_login, v_hex, s_hex, expansion
-    );
-
-    db.execute(&sql).await?;
-
-    Ok(())
-}
-
-/// Generate a TOTP token from a base32 key
-/// Matches the C++ generateToken function
-pub fn generate_token(b32key: &str) -> i32 {
-    let decoded = match base32_decode(b32key) {
-        Ok(d) => d,
-        Err(_) => return -1,
-    };
-
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-        / 30;
-
-    let mut challenge = [0u8; 8];
-    let mut ts = timestamp;
-    for i in (0..8).rev() {
-        challenge[i] = (ts & 0xFF) as u8;
-        ts >>= 8;
-    }
-
-    let hmac_result = hmac_sha1(&decoded, &challenge);
-
-    let offset = (hmac_result[19] & 0x0F) as usize;
-    let trunc_hash = ((hmac_result[offset] as u32) << 24)
-        | ((hmac_result[offset + 1] as u32) << 16)
-        | ((hmac_result[offset + 2] as u32) << 8)
-        | (hmac_result[offset + 3] as u32);
-
-    let trunc_hash = trunc_hash & 0x7FFFFFFF;
-
-    (trunc_hash % 1_000_000) as i32
-}
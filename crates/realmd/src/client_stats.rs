@@ -0,0 +1,103 @@
+// ClientStats - Daily counts of the clients players log in with
+//
+// Every login with a correct password is counted by day (UTC), build, OS,
+// platform and locale. The counts are kept in memory and added to the
+// client_stats table every ClientStats.FlushInterval seconds and on shutdown,
+// so operators can see which builds and locales are still in use before
+// enabling StrictVersionCheck or dropping a build.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+
+use mangos_shared::database::Database;
+use mangos_shared::log::LOG_TARGET_DB_ERROR;
+
+/// Day (YYYY-MM-DD), build, OS, platform and locale of a client
+type ClientKey = (String, u16, String, String, String);
+
+struct ClientStats {
+    db: Arc<Database>,
+    counts: Mutex<HashMap<ClientKey, u32>>,
+}
+
+static STATS: OnceCell<ClientStats> = OnceCell::new();
+
+/// Count a login of this client
+pub fn record(build: u16, os: &str, platform: &str, locale: &str) {
+    let Some(stats) = STATS.get() else {
+        return;
+    };
+    let day = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    *stats
+        .counts
+        .lock()
+        .entry((day, build, os.to_string(), platform.to_string(), locale.to_string()))
+        .or_insert(0) += 1;
+}
+
+/// Start adding the counts to client_stats every `interval`
+pub fn start(db: Arc<Database>, interval: Duration) {
+    if STATS.set(ClientStats { db, counts: Mutex::new(HashMap::new()) }).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            flush().await;
+        }
+    });
+}
+
+/// Write the counts collected so far; they are kept for the next try when the database fails
+pub async fn flush() {
+    let Some(stats) = STATS.get() else {
+        return;
+    };
+    let counts = std::mem::take(&mut *stats.counts.lock());
+    if counts.is_empty() {
+        return;
+    }
+
+    let mut failed = Vec::new();
+    for (key, logins) in counts {
+        if let Err(e) = add_logins(&stats.db, &key, logins).await {
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot write client_stats row for build {}: {:#}", key.1, e);
+            failed.push((key, logins));
+        }
+    }
+    if !failed.is_empty() {
+        let mut pending = stats.counts.lock();
+        for (key, logins) in failed {
+            *pending.entry(key).or_insert(0) += logins;
+        }
+    }
+}
+
+/// Add `logins` to the row of `key`, creating it on the first flush of the day
+///
+/// An UPDATE and, when no row matched, an INSERT, so it runs on every
+/// database backend; an INSERT losing the race to another flush falls back
+/// to the UPDATE.
+async fn add_logins(db: &Database, key: &ClientKey, logins: u32) -> anyhow::Result<()> {
+    let (day, build, os, platform, locale) = key;
+    let (os, platform, locale) =
+        (Database::escape_string(os), Database::escape_string(platform), Database::escape_string(locale));
+    let update = format!(
+        "UPDATE client_stats SET logins = logins + {logins} \
+         WHERE day = '{day}' AND build = '{build}' AND os = '{os}' AND platform = '{platform}' AND locale = '{locale}'"
+    );
+    let insert = format!(
+        "INSERT INTO client_stats(day, build, os, platform, locale, logins) \
+         VALUES('{day}', '{build}', '{os}', '{platform}', '{locale}', '{logins}')"
+    );
+    if db.execute(&update).await? == 0 && db.execute(&insert).await.is_err() {
+        db.execute(&update).await?;
+    }
+    Ok(())
+}
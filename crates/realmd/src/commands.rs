@@ -0,0 +1,201 @@
+// Commands - Console-style account and ban commands
+// Rust equivalent of the account and ban handlers in Level3.cpp
+//
+// Accepts the command lines the C++ core takes on its console and over SOAP
+// ("account create NAME PASS", "ban account NAME 1d Reason", ...) and answers
+// with the same texts, so tools written against the C++ server keep working.
+// Only the commands that make sense on the login server are supported.
+
+use mangos_shared::database::Database;
+use mangos_shared::network::IpNetwork;
+use mangos_shared::util::{secs_to_time_string, time_string_to_secs};
+
+use crate::account_mgr::{self, ChangeSource, GmLevelChange};
+use crate::ip_ban;
+use crate::maintenance;
+use crate::realm_list::{self, RealmBuildInfo};
+use crate::shutdown::{self, ShutdownMode};
+
+/// Output of a command; Err when the command failed
+pub type CommandResult = Result<String, String>;
+
+/// Run one command line as `actor`
+pub async fn execute(db: &Database, line: &str, actor: &str, source: ChangeSource) -> CommandResult {
+    let mut args = line.split_whitespace();
+    let words: Vec<String> = args.by_ref().take(2).map(str::to_ascii_lowercase).collect();
+    let rest: Vec<&str> = args.collect();
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    let result = match (words.as_slice(), rest.as_slice()) {
+        (["account", "create"], [name, password, expansion @ ..]) => {
+            let expansion = match expansion.first() {
+                Some(expansion) => expansion.parse().map_err(|_| "Invalid expansion".to_string())?,
+                None => account_mgr::DEFAULT_EXPANSION,
+            };
+            account_mgr::create_account(db, name, password, expansion, actor, source)
+                .await
+                .map(|_| format!("Account created: {}", name.to_uppercase()))
+        }
+        (["account", "delete"], [name]) => {
+            let account_id = find_account(db, name).await?;
+            account_mgr::delete_account(db, account_id, actor, source)
+                .await
+                .map(|_| format!("Account deleted: {}", name.to_uppercase()))
+        }
+        (["account", "set"], ["password", name, password, confirm]) => {
+            if password != confirm {
+                return Err("The new passwords do not match".to_string());
+            }
+            let account_id = find_account(db, name).await?;
+            account_mgr::set_password(db, account_id, password, actor, source)
+                .await
+                .map(|_| "The password was changed".to_string())
+        }
+        (["account", "set"], ["gmlevel", name, level]) => {
+            let level = level.parse().map_err(|_| "Invalid gmlevel".to_string())?;
+            let account_id = find_account(db, name).await?;
+            account_mgr::set_gm_level(db, account_id, level, actor, source)
+                .await
+                .map(|change| match change {
+                    GmLevelChange::Applied { .. } => {
+                        format!("You change security level of {} to {}.", name.to_uppercase(), level)
+                    }
+                    GmLevelChange::PendingConfirmation { token, .. } => format!(
+                        "Security level {} for {} awaits confirmation by another administrator: account confirm gmlevel {}",
+                        level,
+                        name.to_uppercase(),
+                        token
+                    ),
+                })
+        }
+        (["account", "confirm"], ["gmlevel", token]) => account_mgr::confirm_gm_level(db, token, actor, source)
+            .await
+            .map(|_| "Security level change confirmed.".to_string()),
+        (["account", "set"], ["addon", name, expansion]) => {
+            let expansion = expansion.parse().map_err(|_| "Invalid expansion".to_string())?;
+            let account_id = find_account(db, name).await?;
+            account_mgr::set_expansion(db, account_id, expansion, actor, source)
+                .await
+                .map(|_| {
+                    format!(
+                        "Account {} (Id: {}) have up to {} expansion allowed.",
+                        name.to_uppercase(),
+                        account_id,
+                        expansion
+                    )
+                })
+        }
+        (["ban", "account"], [name, duration, reason @ ..]) if !reason.is_empty() => {
+            let duration = parse_ban_time(duration)?;
+            let account_id = find_account(db, name).await?;
+            let reason = reason.join(" ");
+            account_mgr::ban_account(db, account_id, duration, &reason, actor, source)
+                .await
+                .map(|_| ban_message(&name.to_uppercase(), duration, &reason))
+        }
+        (["ban", "ip"], [ip, duration, reason @ ..]) if !reason.is_empty() => {
+            let network = parse_network(ip)?;
+            let duration = parse_ban_time(duration)?;
+            let reason = reason.join(" ");
+            ip_ban::ban(db, network, duration, actor, &reason)
+                .await
+                .map(|_| ban_message(&network.canonical().to_string(), duration, &reason))
+        }
+        (["unban", "account"], [name]) => {
+            let account_id = find_account(db, name).await?;
+            match account_mgr::unban_account(db, account_id, actor, source).await {
+                Ok(0) => return Err(format!("There was an error removing the ban on {}.", name.to_uppercase())),
+                result => result.map(|_| format!("{} unbanned.", name.to_uppercase())),
+            }
+        }
+        (["unban", "ip"], [ip]) => {
+            let network = parse_network(ip)?;
+            match ip_ban::unban(db, network, actor).await {
+                Ok(0) => return Err(format!("There was an error removing the ban on {}.", network.canonical())),
+                result => result.map(|_| format!("{} unbanned.", network.canonical())),
+            }
+        }
+        (["realmbuild", "add"], [realm_id, build, version @ ..]) => {
+            let realm_id = parse_realm_id(realm_id)?;
+            let info = match version {
+                [] if realm_id == 0 => return Err("Builds for every realm (realm 0) need a version".to_string()),
+                [] => RealmBuildInfo::unknown(build.parse().map_err(|_| format!("Invalid build '{}'", build))?),
+                _ => realm_list::parse_build_info(&format!("{}:{}", build, version.join(":")))
+                    .ok_or_else(|| "Usage: realmbuild add REALMID BUILD [MAJOR.MINOR.BUGFIX[HOTFIX] [WINHASH [MACHASH]]]".to_string())?,
+            };
+            realm_list::add_build(db, realm_id, &info, actor)
+                .await
+                .map(|_| format!("Build {} added to {}, active after the next realm list refresh.", info.build, realm_label(realm_id)))
+        }
+        (["realmbuild", "remove"], [realm_id, build]) => {
+            let realm_id = parse_realm_id(realm_id)?;
+            let build: u16 = build.parse().map_err(|_| format!("Invalid build '{}'", build))?;
+            match realm_list::remove_build(db, realm_id, build, actor).await {
+                Ok(0) => return Err(format!("Build {} is not listed for {}.", build, realm_label(realm_id))),
+                result => result.map(|_| format!("Build {} removed from {}.", build, realm_label(realm_id))),
+            }
+        }
+        (["server", "restart"], []) => {
+            tracing::info!("Restart requested by {}", actor);
+            shutdown::request(ShutdownMode::Restart);
+            Ok("Server will restart once the current sessions finish.".to_string())
+        }
+        (["server", "maintenance"], [state]) => {
+            let enabled = match state.to_ascii_lowercase().as_str() {
+                "on" => true,
+                "off" => false,
+                _ => return Err("Use 'server maintenance on' or 'server maintenance off'".to_string()),
+            };
+            maintenance::set(enabled, actor);
+            Ok(if enabled {
+                "Maintenance mode enabled, logins are answered with server busy.".to_string()
+            } else {
+                "Maintenance mode disabled.".to_string()
+            })
+        }
+        _ => return Err("There is no such command, or the syntax is wrong".to_string()),
+    };
+    result.map_err(|e| format!("{:#}", e))
+}
+
+async fn find_account(db: &Database, name: &str) -> Result<u32, String> {
+    match account_mgr::find_account_id(db, name).await {
+        Ok(Some(account_id)) => Ok(account_id),
+        Ok(None) => Err(format!("Account {} does not exist", name.to_uppercase())),
+        Err(e) => Err(format!("{:#}", e)),
+    }
+}
+
+/// Ban time like "1d12h", or -1 for permanent (returned as 0)
+pub fn parse_ban_time(text: &str) -> Result<u64, String> {
+    if text == "-1" {
+        return Ok(0);
+    }
+    match time_string_to_secs(text) {
+        0 => Err(format!("Wrong ban time '{}' (use e.g. 1d12h, or -1 for permanent)", text)),
+        secs => Ok(secs),
+    }
+}
+
+fn parse_realm_id(text: &str) -> Result<u32, String> {
+    text.parse().map_err(|_| format!("Invalid realm id '{}'", text))
+}
+
+fn realm_label(realm_id: u32) -> String {
+    match realm_id {
+        0 => "every realm".to_string(),
+        id => format!("realm {}", id),
+    }
+}
+
+fn parse_network(text: &str) -> Result<IpNetwork, String> {
+    IpNetwork::parse(text).ok_or_else(|| format!("Invalid address or CIDR range '{}'", text))
+}
+
+fn ban_message(target: &str, duration: u64, reason: &str) -> String {
+    if duration == 0 {
+        format!("{} is banned permanently for {}.", target, reason)
+    } else {
+        format!("{} is banned for {}. Reason: {}.", target, secs_to_time_string(duration), reason)
+    }
+}
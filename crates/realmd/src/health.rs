@@ -0,0 +1,130 @@
+// Health - Liveness and readiness endpoints
+//
+// A small HTTP server for container orchestrators (Health.Enabled, Health.IP,
+// Health.Port), answering without authentication:
+//   GET /health  200 while the login database answers, 503 once it stops
+//   GET /ready   200 when the database answers and at least one realm is loaded
+// Both return the details as JSON. The database is pinged every
+// Health.CheckInterval seconds and the endpoints report the last result, so a
+// probe never waits on a dead connection.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::net::{TcpListener, TcpStream};
+
+use mangos_shared::database::Database;
+use mangos_shared::log::LOG_TARGET_DB_ERROR;
+
+use crate::http;
+use crate::realm_list::RealmList;
+
+/// Result of the latest database ping
+struct DbStatus {
+    connected: bool,
+    /// Unix time of the latest ping
+    checked_at: u64,
+    error: Option<String>,
+}
+
+/// Shared state of the health server
+pub struct Health {
+    db: Arc<Database>,
+    realm_list: Arc<RealmList>,
+    /// Auth listeners bound at startup
+    listeners: usize,
+    db_status: Mutex<DbStatus>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Health {
+    /// The database was just connected, so it starts out healthy
+    pub fn new(db: Arc<Database>, realm_list: Arc<RealmList>, listeners: usize) -> Self {
+        Health {
+            db,
+            realm_list,
+            listeners,
+            db_status: Mutex::new(DbStatus { connected: true, checked_at: unix_now(), error: None }),
+        }
+    }
+
+    /// Ping the database every `interval` and record the result
+    pub async fn monitor(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let result = self.db.ping().await;
+            let mut status = self.db_status.lock();
+            match result {
+                Ok(()) => {
+                    if !status.connected {
+                        tracing::info!("Health: login database reachable again");
+                    }
+                    *status = DbStatus { connected: true, checked_at: unix_now(), error: None };
+                }
+                Err(e) => {
+                    if status.connected {
+                        tracing::error!(target: LOG_TARGET_DB_ERROR, "Health: login database ping failed: {:#}", e);
+                    }
+                    *status = DbStatus { connected: false, checked_at: unix_now(), error: Some(format!("{:#}", e)) };
+                }
+            }
+        }
+    }
+
+    /// Answer probes until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let health = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = health.handle_connection(stream, peer).await {
+                            tracing::debug!("Health: request from {} failed: {:#}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    tracing::error!("Health: failed to accept connection: {}", e);
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream, peer: SocketAddr) -> anyhow::Result<()> {
+        let request = http::read_request(&mut stream).await?;
+
+        let (db_ok, db) = {
+            let status = self.db_status.lock();
+            (
+                status.connected,
+                json!({ "connected": status.connected, "checked_at": status.checked_at, "error": status.error }),
+            )
+        };
+        let realms = self.realm_list.size();
+        let ok = match request.path.as_str() {
+            "/health" => db_ok,
+            "/ready" => db_ok && self.listeners > 0 && realms > 0,
+            _ => return http::write_response(&mut stream, "404 Not Found", "application/json", "{\"error\":\"not found\"}").await,
+        };
+
+        let body = json!({
+            "status": if ok { "ok" } else { "unavailable" },
+            "listeners": self.listeners,
+            "database": db,
+            "realms": realms,
+        });
+        tracing::trace!("Health: {} from {} -> {}", request.path, peer, ok);
+        let status = if ok { "200 OK" } else { "503 Service Unavailable" };
+        http::write_response(&mut stream, status, "application/json", &body.to_string()).await
+    }
+}
@@ -0,0 +1,272 @@
+// Http - Minimal HTTP/1.1 plumbing for the admin interfaces
+//
+// Just enough HTTP for the REST API and the SOAP endpoint: one request per
+// connection with a size-limited head and a Content-Length body, answered
+// with "Connection: close", over plain TCP or a TLS stream. The webhook notifier posts and the IP reputation
+// feeds are fetched through the same plumbing, over TLS for https:// URLs.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{crypto, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// Largest request head and body accepted
+const MAX_HEAD_SIZE: u64 = 8 * 1024;
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Time a client gets to send its whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed HTTP request
+pub struct HttpRequest {
+    /// Upper-cased method
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Value of a header, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Read one request, giving up after REQUEST_TIMEOUT
+pub async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> anyhow::Result<HttpRequest> {
+    match tokio::time::timeout(REQUEST_TIMEOUT, read_request_inner(stream)).await {
+        Ok(request) => request,
+        Err(_) => anyhow::bail!("timed out reading the request"),
+    }
+}
+
+async fn read_request_inner<S: AsyncRead + Unpin>(stream: &mut S) -> anyhow::Result<HttpRequest> {
+    let mut head = BufReader::new(stream).take(MAX_HEAD_SIZE);
+
+    let mut line = String::new();
+    head.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        anyhow::bail!("malformed request line");
+    };
+    let method = method.to_ascii_uppercase();
+    let path = path.split('?').next().unwrap_or("").to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 {
+            anyhow::bail!("request head too large or truncated");
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            anyhow::bail!("malformed header");
+        };
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = HttpRequest { method, path, headers, body: Vec::new() };
+    let content_length: usize = match request.header("Content-Length") {
+        Some(value) => value.parse()?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_SIZE {
+        anyhow::bail!("body larger than {} bytes", MAX_BODY_SIZE);
+    }
+
+    // Bytes past the head may already sit in the buffer
+    let mut reader = head.into_inner();
+    request.body = vec![0u8; content_length];
+    reader.read_exact(&mut request.body).await?;
+    Ok(request)
+}
+
+/// Send a complete response and close the connection
+pub async fn write_response<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> anyhow::Result<()> {
+    write_response_with_headers(stream, status, &[], content_type, body).await
+}
+
+/// Send a complete response with extra headers and close the connection
+pub async fn write_response_with_headers<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    status: &str,
+    headers: &[(&str, &str)],
+    content_type: &str,
+    body: &str,
+) -> anyhow::Result<()> {
+    let headers: String = headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect();
+    let message = format!(
+        "HTTP/1.1 {}\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        headers,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(message.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// TLS settings for https:// requests, trusting the Mozilla root certificates
+static TLS_CLIENT: Lazy<Option<Arc<ClientConfig>>> = Lazy::new(|| {
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    match ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider())).with_safe_default_protocol_versions() {
+        Ok(builder) => Some(Arc::new(builder.with_root_certificates(roots).with_no_client_auth())),
+        Err(e) => {
+            tracing::error!("Cannot set up the TLS client: {}", e);
+            None
+        }
+    }
+});
+
+/// Parts of an http:// or https:// URL
+struct Url<'a> {
+    tls: bool,
+    host: &'a str,
+    port: u16,
+    /// Path and query, starting with '/'
+    target: &'a str,
+}
+
+fn parse_url(url: &str) -> anyhow::Result<Url<'_>> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        anyhow::bail!("unsupported URL '{}'", url);
+    };
+    let (authority, target) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+    // [v6]:port, host:port or a bare host
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(v6) => {
+            let (host, after) = v6.split_once(']').ok_or_else(|| anyhow::anyhow!("malformed URL '{}'", url))?;
+            (host, after.strip_prefix(':'))
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port.parse()?,
+        None if tls => 443,
+        None => 80,
+    };
+    if host.is_empty() {
+        anyhow::bail!("malformed URL '{}'", url);
+    }
+    Ok(Url { tls, host, port, target })
+}
+
+/// POST `body` to `url` and return the response status code
+pub async fn post(url: &str, content_type: &str, body: &str) -> anyhow::Result<u16> {
+    let url = parse_url(url)?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: realmd/{}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        url.target,
+        url.host,
+        env!("CARGO_PKG_VERSION"),
+        content_type,
+        body.len(),
+        body
+    );
+
+    let (status, _) = send(&url, &request, None, REQUEST_TIMEOUT).await?;
+    Ok(status)
+}
+
+/// GET `url`; returns the status code and a body of at most `max_body` bytes
+///
+/// Asks for HTTP/1.0 so the body comes without chunked encoding.
+pub async fn get(url: &str, max_body: usize, timeout: Duration) -> anyhow::Result<(u16, Vec<u8>)> {
+    let url = parse_url(url)?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: realmd/{}\r\nConnection: close\r\n\r\n",
+        url.target,
+        url.host,
+        env!("CARGO_PKG_VERSION")
+    );
+    send(&url, &request, Some(max_body), timeout).await
+}
+
+/// Connect to `url`, send `request` and read the response, its body only when `max_body` is set
+async fn send(url: &Url<'_>, request: &str, max_body: Option<usize>, timeout: Duration) -> anyhow::Result<(u16, Vec<u8>)> {
+    match tokio::time::timeout(timeout, async {
+        let stream = TcpStream::connect((url.host, url.port)).await?;
+        if url.tls {
+            let config = TLS_CLIENT.clone().ok_or_else(|| anyhow::anyhow!("TLS client unavailable"))?;
+            let name = ServerName::try_from(url.host.to_string())?;
+            let stream = TlsConnector::from(config).connect(name, stream).await?;
+            exchange(stream, request, max_body).await
+        } else {
+            exchange(stream, request, max_body).await
+        }
+    })
+    .await
+    {
+        Ok(response) => response,
+        Err(_) => anyhow::bail!("timed out waiting for {}", url.host),
+    }
+}
+
+/// Send a request and read the status code of the response, then the body when `max_body` is set
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, request: &str, max_body: Option<usize>) -> anyhow::Result<(u16, Vec<u8>)> {
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    (&mut reader).take(MAX_HEAD_SIZE).read_line(&mut line).await?;
+    // HTTP/1.1 204 No Content
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("malformed response"))?;
+    let Some(max_body) = max_body else {
+        return Ok((status, Vec::new()));
+    };
+
+    // Skip the headers up to the empty line
+    let mut head = (&mut reader).take(MAX_HEAD_SIZE);
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 {
+            anyhow::bail!("response ended in the headers");
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut body = Vec::new();
+    reader.take(max_body as u64 + 1).read_to_end(&mut body).await?;
+    if body.len() > max_body {
+        anyhow::bail!("response body larger than {} bytes", max_body);
+    }
+    Ok((status, body))
+}
@@ -0,0 +1,78 @@
+// IpBan - Address and subnet bans (ip_banned)
+//
+// ip_banned.ip holds either a single address ("203.0.113.7") or a CIDR
+// network ("203.0.113.0/24", "2001:db8::/32"). Single addresses are matched
+// by the database, networks are loaded and matched here, so a ban on a
+// subnet applies to every client inside it.
+
+use std::net::IpAddr;
+
+use mangos_shared::database::{Database, FieldExt};
+use mangos_shared::log::LOG_TARGET_AUDIT;
+use mangos_shared::network::{ip_text_forms, normalize_ip, IpNetwork};
+use mangos_shared::util::unix_now;
+
+/// Condition selecting bans that have not expired (expires_at = banned_at is permanent)
+fn active_ban() -> String {
+    format!("(expires_at = banned_at OR expires_at > {})", unix_now())
+}
+
+/// The active ban covering `ip`, as stored in ip_banned.ip
+pub async fn find_ban(db: &Database, ip: IpAddr, map_v4: bool) -> anyhow::Result<Option<String>> {
+    // A ban may be stored in plain or v4-mapped form
+    let ip_forms = ip_text_forms(ip, map_v4)
+        .iter()
+        .map(|ip| format!("'{}'", Database::escape_string(ip)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let rows = db
+        .query(&format!(
+            "SELECT ip FROM ip_banned WHERE {} AND (ip IN ({}) OR ip LIKE '%/%')",
+            active_ban(), ip_forms
+        ))
+        .await?;
+
+    let ip = normalize_ip(ip, map_v4);
+    Ok(rows.iter().map(|row| row.get_string(0)).find(|stored| {
+        !stored.contains('/')
+            || IpNetwork::parse(stored.trim()).is_some_and(|network| network.normalize(map_v4).contains(ip))
+    }))
+}
+
+/// Ban an address or network for `duration` seconds (0 = permanent)
+pub async fn ban(db: &Database, network: IpNetwork, duration: u64, banned_by: &str, reason: &str) -> anyhow::Result<()> {
+    let network = network.canonical();
+    let now = unix_now();
+    db.execute(&format!(
+        "INSERT INTO ip_banned VALUES ('{}', '{}', '{}', '{}', '{}')",
+        network,
+        now,
+        now + duration,
+        Database::escape_string(banned_by),
+        Database::escape_string(reason)
+    ))
+    .await?;
+
+    tracing::warn!(
+        target: LOG_TARGET_AUDIT,
+        "{} banned by '{}' for {} ({})",
+        network,
+        banned_by,
+        if duration == 0 { "ever".to_string() } else { format!("{}s", duration) },
+        reason
+    );
+    Ok(())
+}
+
+/// Lift every ban on exactly this address or network; returns the number of bans removed
+pub async fn unban(db: &Database, network: IpNetwork, unbanned_by: &str) -> anyhow::Result<u64> {
+    let network = network.canonical();
+    let removed = db
+        .execute(&format!("DELETE FROM ip_banned WHERE ip = '{}'", network))
+        .await?;
+
+    if removed > 0 {
+        tracing::warn!(target: LOG_TARGET_AUDIT, "{} unbanned by '{}'", network, unbanned_by);
+    }
+    Ok(removed)
+}
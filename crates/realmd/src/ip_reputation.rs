@@ -0,0 +1,306 @@
+// IpReputation - Deny lists consulted before ip_banned
+//
+// Providers tell whether a client address is known bad: static files and HTTP
+// feeds of addresses and CIDR networks (one per line, as in the Spamhaus DROP
+// or FireHOL lists), reloaded every IpReputation.RefreshInterval seconds, and
+// DNS block lists queried per address. The logon challenge asks them in turn
+// before looking at ip_banned, so listed clients are refused without a
+// database query.
+//
+// Every provider caches its answers for IpReputation.CacheTime seconds and
+// counts lookups, cache hits, listed addresses and errors, shown by
+// GET /ip-reputation on the REST API. A provider that fails to answer lets the
+// client through.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+use parking_lot::{Mutex, RwLock};
+use serde_json::{json, Value};
+
+use mangos_shared::config::Config;
+use mangos_shared::network::{dnsbl_query_name, parse_network_list, IpNetwork};
+
+use crate::http;
+
+/// Largest feed accepted
+const MAX_FEED_SIZE: usize = 16 * 1024 * 1024;
+
+/// Time a feed download may take
+const FEED_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Cached answers per provider before expired ones are swept out
+const SWEEP_THRESHOLD: usize = 16384;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A source of known bad addresses
+pub trait ReputationProvider: Send + Sync {
+    /// Name shown in logs and metrics
+    fn name(&self) -> &str;
+
+    /// Why `ip` is listed, None when it is not
+    fn check(&self, ip: IpAddr) -> BoxFuture<'_, anyhow::Result<Option<String>>>;
+
+    /// Reload the list, for providers that keep one
+    fn refresh(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Where a network list comes from
+enum ListSource {
+    File(PathBuf),
+    Feed(String),
+}
+
+/// Addresses and networks from a file or an HTTP feed
+struct NetworkList {
+    name: String,
+    source: ListSource,
+    networks: RwLock<Arc<Vec<IpNetwork>>>,
+}
+
+impl NetworkList {
+    fn new(source: ListSource) -> Self {
+        let name = match &source {
+            ListSource::File(path) => format!("file:{}", path.display()),
+            ListSource::Feed(url) => format!("feed:{}", url),
+        };
+        NetworkList { name, source, networks: RwLock::new(Arc::new(Vec::new())) }
+    }
+
+    async fn load(&self) -> anyhow::Result<String> {
+        match &self.source {
+            ListSource::File(path) => Ok(tokio::fs::read_to_string(path).await?),
+            ListSource::Feed(url) => match http::get(url, MAX_FEED_SIZE, FEED_TIMEOUT).await? {
+                (200, body) => Ok(String::from_utf8_lossy(&body).into_owned()),
+                (status, _) => anyhow::bail!("HTTP {}", status),
+            },
+        }
+    }
+}
+
+impl ReputationProvider for NetworkList {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, ip: IpAddr) -> BoxFuture<'_, anyhow::Result<Option<String>>> {
+        let networks = self.networks.read().clone();
+        Box::pin(async move {
+            Ok(networks
+                .iter()
+                .find(|network| network.contains(ip))
+                .map(|network| format!("{} is listed", network)))
+        })
+    }
+
+    fn refresh(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            let (networks, invalid) = parse_network_list(&self.load().await?);
+            if invalid > 0 {
+                tracing::warn!("IpReputation: {} has {} invalid line(s)", self.name, invalid);
+            }
+            tracing::debug!("IpReputation: loaded {} network(s) from {}", networks.len(), self.name);
+            *self.networks.write() = Arc::new(networks);
+            Ok(())
+        })
+    }
+}
+
+/// A DNS block list: listed addresses resolve under its zone
+struct Dnsbl {
+    name: String,
+    zone: String,
+    timeout: Duration,
+}
+
+impl ReputationProvider for Dnsbl {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, ip: IpAddr) -> BoxFuture<'_, anyhow::Result<Option<String>>> {
+        Box::pin(async move {
+            let query = dnsbl_query_name(ip, &self.zone);
+            match tokio::time::timeout(self.timeout, tokio::net::lookup_host((query.as_str(), 0))).await {
+                // Block lists answer with 127.0.0.x; anything else is a broken or hijacked resolver
+                Ok(Ok(mut answers)) => Ok(answers
+                    .find(|answer| matches!(answer.ip(), IpAddr::V4(v4) if v4.is_loopback()))
+                    .map(|answer| format!("listed on {} ({})", self.zone, answer.ip()))),
+                // NXDOMAIN: not listed
+                Ok(Err(_)) => Ok(None),
+                Err(_) => anyhow::bail!("no answer for {} within {}ms", query, self.timeout.as_millis()),
+            }
+        })
+    }
+}
+
+/// Counters of one provider
+#[derive(Default)]
+struct ProviderMetrics {
+    lookups: AtomicU64,
+    cache_hits: AtomicU64,
+    listed: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// A provider with its answer cache and counters
+struct Provider {
+    inner: Box<dyn ReputationProvider>,
+    cache: Mutex<HashMap<IpAddr, (Option<String>, Instant)>>,
+    metrics: ProviderMetrics,
+}
+
+impl Provider {
+    async fn check(&self, ip: IpAddr, cache_time: Duration) -> Option<String> {
+        self.metrics.lookups.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        if let Some((verdict, at)) = self.cache.lock().get(&ip)
+            && now.saturating_duration_since(*at) < cache_time
+        {
+            self.metrics.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return verdict.clone();
+        }
+
+        let verdict = match self.inner.check(ip).await {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("IpReputation: {} failed for {}: {:#}", self.inner.name(), ip, e);
+                return None;
+            }
+        };
+        if verdict.is_some() {
+            self.metrics.listed.fetch_add(1, Ordering::Relaxed);
+        }
+        if !cache_time.is_zero() {
+            let mut cache = self.cache.lock();
+            if cache.len() >= SWEEP_THRESHOLD {
+                cache.retain(|_, (_, at)| now.saturating_duration_since(*at) < cache_time);
+            }
+            cache.insert(ip, (verdict.clone(), now));
+        }
+        verdict
+    }
+
+    fn metrics(&self) -> Value {
+        let metrics = &self.metrics;
+        json!({
+            "name": self.inner.name(),
+            "lookups": metrics.lookups.load(Ordering::Relaxed),
+            "cache_hits": metrics.cache_hits.load(Ordering::Relaxed),
+            "listed": metrics.listed.load(Ordering::Relaxed),
+            "errors": metrics.errors.load(Ordering::Relaxed),
+            "cached": self.cache.lock().len(),
+        })
+    }
+}
+
+/// IP reputation settings read from the config file
+pub struct ReputationSettings {
+    pub files: Vec<String>,
+    pub feeds: Vec<String>,
+    pub dnsbl_zones: Vec<String>,
+    pub dnsbl_timeout: Duration,
+    pub cache_time: Duration,
+    /// How often files and feeds are reloaded; zero = only at startup
+    pub refresh_interval: Duration,
+}
+
+impl ReputationSettings {
+    pub fn from_config(config: &Config) -> Self {
+        let list = |key: &str| config.get_string(key).split_whitespace().map(str::to_string).collect();
+        ReputationSettings {
+            files: list("IpReputation.Files"),
+            feeds: list("IpReputation.Feeds"),
+            dnsbl_zones: list("IpReputation.Dnsbl"),
+            dnsbl_timeout: Duration::from_millis(config.get_int_default("IpReputation.DnsblTimeout", 500).max(1) as u64),
+            cache_time: Duration::from_secs(config.get_int_default("IpReputation.CacheTime", 3600).max(0) as u64),
+            refresh_interval: Duration::from_secs(config.get_int_default("IpReputation.RefreshInterval", 3600).max(0) as u64),
+        }
+    }
+}
+
+struct Reputation {
+    providers: Vec<Arc<Provider>>,
+    cache_time: Duration,
+}
+
+static REPUTATION: OnceCell<Reputation> = OnceCell::new();
+
+/// Load the configured providers and keep their lists fresh; without any the check stays off
+pub async fn start(settings: ReputationSettings) {
+    let mut providers: Vec<Box<dyn ReputationProvider>> = Vec::new();
+    for path in settings.files {
+        providers.push(Box::new(NetworkList::new(ListSource::File(path.into()))));
+    }
+    for url in settings.feeds {
+        providers.push(Box::new(NetworkList::new(ListSource::Feed(url))));
+    }
+    for zone in settings.dnsbl_zones {
+        providers.push(Box::new(Dnsbl { name: format!("dnsbl:{}", zone), zone, timeout: settings.dnsbl_timeout }));
+    }
+    if providers.is_empty() {
+        return;
+    }
+
+    let providers: Vec<Arc<Provider>> = providers
+        .into_iter()
+        .map(|inner| Arc::new(Provider { inner, cache: Mutex::new(HashMap::new()), metrics: ProviderMetrics::default() }))
+        .collect();
+    refresh_all(&providers).await;
+    tracing::info!("Checking client addresses against {} IP reputation provider(s)", providers.len());
+
+    if !settings.refresh_interval.is_zero() {
+        let providers = providers.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(settings.refresh_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                refresh_all(&providers).await;
+            }
+        });
+    }
+    let _ = REPUTATION.set(Reputation { providers, cache_time: settings.cache_time });
+}
+
+/// Reload every list; a list that fails keeps its previous entries
+async fn refresh_all(providers: &[Arc<Provider>]) {
+    for provider in providers {
+        match provider.inner.refresh().await {
+            // Old answers may no longer hold
+            Ok(()) => provider.cache.lock().clear(),
+            Err(e) => tracing::warn!("IpReputation: cannot load {}: {:#}", provider.inner.name(), e),
+        }
+    }
+}
+
+/// The first provider listing `ip` and its reason
+pub async fn check(ip: IpAddr) -> Option<(String, String)> {
+    let reputation = REPUTATION.get()?;
+    for provider in &reputation.providers {
+        if let Some(reason) = provider.check(ip, reputation.cache_time).await {
+            return Some((provider.inner.name().to_string(), reason));
+        }
+    }
+    None
+}
+
+/// Counters of every provider, for the REST API
+pub fn metrics() -> Value {
+    let providers: Vec<Value> = REPUTATION
+        .get()
+        .map(|reputation| reputation.providers.iter().map(|provider| provider.metrics()).collect())
+        .unwrap_or_default();
+    json!({ "providers": providers })
+}
@@ -0,0 +1,47 @@
+// LogonLookup - Database lookups of a logon challenge
+//
+// The account row comes with its active ban (permanent bans first) from a
+// single joined query, run alongside the IP ban lookup, so a logon challenge
+// waits for one round trip instead of three. Only ip_ban and mangos-shared
+// are used here, so benches/logon_lookup.rs compiles this module in and
+// measures the queries realmd runs.
+
+use std::net::IpAddr;
+
+use sqlx::any::AnyRow;
+
+use mangos_shared::database::Database;
+use mangos_shared::util::unix_now;
+
+use crate::ip_ban;
+
+/// The account `safe_login` (escaped) with its active ban, if any
+///
+/// Columns: id, locked, lockedIp, gmlevel, v, s, token, lock_country,
+/// ban_id (0 without a ban), banned_at, expires_at
+pub fn account_sql(safe_login: &str) -> String {
+    format!(
+        "SELECT a.id, CAST(a.locked AS SIGNED) AS locked, a.lockedIp, \
+         CAST(a.gmlevel AS SIGNED) AS gmlevel, \
+         CAST(a.v AS CHAR) AS v, CAST(a.s AS CHAR) AS s, \
+         CAST(a.token AS CHAR) AS token, a.lock_country, \
+         CAST(COALESCE(b.id, 0) AS SIGNED) AS ban_id, b.banned_at, b.expires_at \
+         FROM account a LEFT JOIN account_banned b ON b.account_id = a.id AND CAST(b.active AS SIGNED) = 1 \
+         AND (b.expires_at > {now} OR b.expires_at = b.banned_at) \
+         WHERE a.username = '{}' \
+         ORDER BY (b.expires_at = b.banned_at) DESC, b.expires_at DESC LIMIT 1",
+        safe_login,
+        now = unix_now()
+    )
+}
+
+/// The IP ban covering `ip` and the account row of [`account_sql`], queried concurrently
+pub async fn lookup(
+    db: &Database,
+    ip: IpAddr,
+    map_v4: bool,
+    safe_login: &str,
+) -> (anyhow::Result<Option<String>>, anyhow::Result<Option<AnyRow>>) {
+    let account_sql = account_sql(safe_login);
+    tokio::join!(ip_ban::find_ban(db, ip, map_v4), db.query_one(&account_sql))
+}
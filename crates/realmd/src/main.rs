@@ -7,26 +7,69 @@
 // - Account banning/locking
 // - Session key management
 
+mod account_mgr;
+mod auth_client;
 mod auth_codes;
+mod auth_log;
 mod auth_socket;
+mod client_stats;
+mod commands;
+mod health;
+mod http;
+mod ip_ban;
+mod ip_reputation;
+mod logon_lookup;
+mod maintenance;
+mod maintenance_window;
+mod patcher;
 mod protocol;
+mod realm_address;
 mod realm_list;
+mod realm_status;
+mod rest_api;
+mod service;
+mod shared_state;
+mod shutdown;
+mod strikes;
+mod stress;
+mod soap;
+mod tarpit;
+mod web_token;
+mod webhook;
 
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::process::ExitCode;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use parking_lot::Mutex;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 
-use mangos_shared::config::get_config;
+use mangos_shared::auth::session_key;
+use mangos_shared::auth::totp::{self, TotpParams};
+use mangos_shared::auth::MatrixCard;
+use mangos_shared::config::{get_config, redact_value};
+use mangos_shared::daemon::{self, PidFile};
 use mangos_shared::database::Database;
-use mangos_shared::log::{initialize_logging, map_log_level};
-use mangos_shared::MINUTE;
+use mangos_shared::error::{ExitStatus, MangosError};
+use mangos_shared::log::{initialize_logging, map_log_level, set_packet_dump, LogOptions, LOG_TARGET_DB_ERROR};
+use mangos_shared::network::{bind_listeners, normalize_addr, parse_bind_addresses, GeoIp, IpNetwork, IpRateLimiter, ProxyProtocol, SocketOptions, TlsAcceptor};
+use mangos_shared::{RealmFlags, MINUTE};
+use mangos_shared::util::{secs_to_time_string, unix_now};
 
-use realm_list::RealmList;
+use account_mgr::{ChangeSource, GmLevelChange};
+
+use health::Health;
+use auth_socket::SessionTimeouts;
+use realm_list::{RealmList, RealmListSettings};
+use realm_status::RealmStatus;
+use rest_api::RestApi;
+use shutdown::ShutdownMode;
+use soap::SoapServer;
 
 /// Tracks active connections per-IP and total, enforcing configurable limits.
 struct ConnectionTracker {
@@ -46,13 +89,20 @@ impl ConnectionTracker {
         }
     }
 
-    /// Try to register a new connection from `ip`.
-    /// Returns `false` if the connection would exceed per-IP or total limits.
-    fn try_add(&mut self, ip: IpAddr) -> bool {
+    /// Try to register a new connection against the total limit.
+    /// Returns `false` if the server is already at its limit.
+    fn try_reserve(&mut self) -> bool {
         // Check total limit (0 = unlimited)
         if self.max_total > 0 && self.total >= self.max_total {
             return false;
         }
+        self.total += 1;
+        true
+    }
+
+    /// Try to register a reserved connection as coming from `ip`.
+    /// Returns `false` if the connection would exceed the per-IP limit.
+    fn try_add_ip(&mut self, ip: IpAddr) -> bool {
         // Check per-IP limit (0 = unlimited)
         if self.max_per_ip > 0 {
             let count = self.per_ip.entry(ip).or_insert(0);
@@ -61,13 +111,14 @@ impl ConnectionTracker {
             }
             *count += 1;
         }
-        self.total += 1;
         true
     }
 
-    /// Unregister a connection from `ip`. Called when the connection drops.
-    fn remove(&mut self, ip: IpAddr) {
-        if let std::collections::hash_map::Entry::Occupied(mut entry) = self.per_ip.entry(ip) {
+    /// Unregister a connection, and its `ip` if it was added. Called when the connection drops.
+    fn release(&mut self, ip: Option<IpAddr>) {
+        if let Some(ip) = ip
+            && let std::collections::hash_map::Entry::Occupied(mut entry) = self.per_ip.entry(ip)
+        {
             let count = entry.get_mut();
             if *count <= 1 {
                 entry.remove();
@@ -79,19 +130,124 @@ impl ConnectionTracker {
     }
 }
 
-/// RAII guard that automatically calls `ConnectionTracker::remove()` on drop.
+/// RAII guard that automatically calls `ConnectionTracker::release()` on drop.
 /// Ensures connection tracking cleanup even on panics or early returns.
 struct ConnectionGuard {
     tracker: Arc<Mutex<ConnectionTracker>>,
-    ip: IpAddr,
+    ip: Option<IpAddr>,
+}
+
+impl ConnectionGuard {
+    /// Take one of the total connection slots; None when the server is full
+    fn reserve(tracker: &Arc<Mutex<ConnectionTracker>>) -> Option<Self> {
+        tracker.lock().try_reserve().then(|| ConnectionGuard {
+            tracker: tracker.clone(),
+            ip: None,
+        })
+    }
+
+    /// Count the connection against `ip` once the client address is known
+    fn try_bind(&mut self, ip: IpAddr) -> bool {
+        let added = self.tracker.lock().try_add_ip(ip);
+        if added {
+            self.ip = Some(ip);
+        }
+        added
+    }
 }
 
 impl Drop for ConnectionGuard {
     fn drop(&mut self) {
-        self.tracker.lock().remove(self.ip);
+        self.tracker.lock().release(self.ip);
     }
 }
 
+/// Shared state handed to every accepted connection
+struct AcceptContext {
+    db: Arc<Database>,
+    realm_list: Arc<RealmList>,
+    tracker: Arc<Mutex<ConnectionTracker>>,
+    challenge_limiter: Arc<IpRateLimiter>,
+    proxy_protocol: Option<ProxyProtocol>,
+    socket_options: SocketOptions,
+    map_v4: bool,
+    timeouts: SessionTimeouts,
+}
+
+/// Accept connections on one listener until a stop or restart is requested,
+/// then hand the listener back
+async fn accept_loop(listener: TcpListener, ctx: Arc<AcceptContext>) -> TcpListener {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown::wait() => return listener,
+        };
+        match accepted {
+            Ok((stream, peer)) => {
+                // Enforce the total limit before spawning, so a flood of sockets
+                // is closed right away instead of piling up tasks
+                let Some(guard) = ConnectionGuard::reserve(&ctx.tracker) else {
+                    tracing::warn!(
+                        "[{}] Connection rejected: server full (max_total={})",
+                        peer, ctx.tracker.lock().max_total
+                    );
+                    continue;
+                };
+                tokio::spawn(handle_connection(stream, peer, guard, ctx.clone()));
+            }
+            Err(e) => {
+                tracing::error!("Failed to accept connection: {}", e);
+            }
+        }
+    }
+}
+
+/// Resolve the client address, apply the connection limits and run the session
+async fn handle_connection(mut stream: TcpStream, peer: SocketAddr, mut guard: ConnectionGuard, ctx: Arc<AcceptContext>) {
+    if let Err(e) = ctx.socket_options.apply(&stream) {
+        tracing::debug!("[{}] Cannot set socket options: {}", peer, e);
+    }
+
+    // Behind a load balancer the real client address comes from its PROXY header
+    let addr = match &ctx.proxy_protocol {
+        Some(proxy) => match proxy.resolve_peer(&mut stream, peer).await {
+            Ok(addr) => addr,
+            Err(e) => {
+                tracing::debug!("[{}] Rejected proxied connection: {}", peer, e);
+                return;
+            }
+        },
+        None => peer,
+    };
+    let addr = normalize_addr(addr, ctx.map_v4);
+    let ip = addr.ip();
+
+    // Addresses blocked for protocol violations are dropped before any work is done
+    if strikes::is_blocked(ip) {
+        tracing::debug!("[{}] Connection rejected: blocked for protocol violations", addr);
+        return;
+    }
+
+    // Enforce the per-IP limit; the guard releases the slot on any exit
+    if !guard.try_bind(ip) {
+        tracing::warn!(
+            "[{}] Connection rejected: limit exceeded (per_ip={})",
+            addr, ctx.tracker.lock().max_per_ip
+        );
+        // Drop `stream` immediately
+        return;
+    }
+    auth_socket::handle_client(
+        stream,
+        addr,
+        ctx.db.clone(),
+        ctx.realm_list.clone(),
+        ctx.challenge_limiter.clone(),
+        ctx.timeouts,
+    )
+    .await;
+}
+
 /// Default realm server port
 const DEFAULT_REALMSERVER_PORT: i32 = 3724;
 
@@ -108,33 +264,531 @@ struct Args {
     #[arg(short, long, default_value = DEFAULT_CONFIG)]
     config: String,
 
+    /// Write the process id to FILE (overrides PidFile from the config file)
+    #[arg(short, long, value_name = "FILE")]
+    pidfile: Option<String>,
+
+    /// Detach from the terminal and run in the background (Unix only)
+    #[arg(short, long)]
+    daemon: bool,
+
+    /// Install or uninstall the Windows service; "run" is used by the service control manager
+    #[arg(short, long, value_enum, value_name = "ACTION")]
+    service: Option<ServiceAction>,
+
     /// Console log level override (0=Minimum, 1=Error, 2=Detail, 3=Full/Debug, 4=Trace)
     /// Overrides the LogLevel setting from the config file.
     #[arg(short, long, value_name = "LEVEL")]
     log_level: Option<i32>,
+
+    /// Print every effective configuration value (secrets redacted) and its source, then exit
+    #[arg(long)]
+    dump_config: bool,
+
+    /// Generate an authenticator secret for ACCOUNT, print its otpauth:// URI (the QR code payload), then exit
+    #[arg(long, value_name = "ACCOUNT")]
+    totp_enroll: Option<String>,
+
+    /// Issuer name shown in authenticator apps (with --totp-enroll)
+    #[arg(long, value_name = "NAME", default_value = "CMaNGOS")]
+    totp_issuer: String,
+
+    /// Ban an address or CIDR range (e.g. 203.0.113.0/24) in ip_banned, then exit
+    #[arg(long, value_name = "ADDR[/PREFIX]", conflicts_with = "unban_ip")]
+    ban_ip: Option<String>,
+
+    /// Ban duration in seconds for --ban-ip (0 = permanent)
+    #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+    ban_time: u64,
+
+    /// Reason recorded with --ban-ip
+    #[arg(long, value_name = "TEXT", default_value = "Console ban")]
+    ban_reason: String,
+
+    /// Remove the ban on exactly this address or CIDR range, then exit
+    #[arg(long, value_name = "ADDR[/PREFIX]")]
+    unban_ip: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Windows service actions (-s)
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ServiceAction {
+    Install,
+    Uninstall,
+    Run,
+}
+
+/// One-shot administrative commands; realmd exits after running them
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage accounts in the login database
+    Account {
+        /// Administrator the change is audited under, "[Console]" when omitted;
+        /// required to confirm an elevation
+        #[arg(long, value_name = "NAME", global = true)]
+        actor: Option<String>,
+        #[command(subcommand)]
+        action: AccountCommand,
+    },
+    /// Compute the SRP6 salt and verifier of accounts that lack one from their legacy sha_pass_hash
+    #[command(name = "srp-migrate")]
+    SrpMigrate {
+        /// Only count the accounts that would be converted
+        #[arg(long)]
+        dry_run: bool,
+        /// Accounts read per query
+        #[arg(long, default_value_t = 1000)]
+        batch_size: u32,
+    },
+    /// Load-test a running realmd with real logins and report latencies
+    Stress {
+        /// File with one name:password per line
+        #[arg(long, value_name = "FILE")]
+        accounts: String,
+        /// Logins in flight at once
+        #[arg(long, default_value_t = 10)]
+        concurrency: usize,
+        /// Logins to make in total (default: each account once)
+        #[arg(long)]
+        logins: Option<usize>,
+        #[arg(long, value_name = "HOST:PORT", default_value = "127.0.0.1:3724")]
+        target: String,
+        /// Client build to log in as
+        #[arg(long, default_value_t = 8606)]
+        build: u16,
+    },
+    /// Log in to a running realmd once and print the realm list it sends
+    #[command(name = "test-login")]
+    TestLogin {
+        /// Address of the realmd, port 3724 when omitted
+        #[arg(long, value_name = "HOST[:PORT]", default_value = "127.0.0.1")]
+        host: String,
+        #[arg(long)]
+        account: String,
+        #[arg(long)]
+        password: String,
+        /// Client build to log in as
+        #[arg(long, default_value_t = 8606)]
+        build: u16,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AccountCommand {
+    /// Create an account
+    Create {
+        name: String,
+        password: String,
+        /// Expansion the account may play (0 = classic, 1 = TBC, 2 = WotLK)
+        #[arg(long, default_value_t = account_mgr::DEFAULT_EXPANSION)]
+        expansion: u8,
+    },
+    /// Set a new password
+    #[command(name = "setpassword")]
+    SetPassword { name: String, password: String },
+    /// Set the gmlevel (0-3)
+    #[command(name = "setgm")]
+    SetGm { name: String, level: u8 },
+    /// Ban an account
+    Ban {
+        name: String,
+        /// Ban time like 1d12h, or -1 for permanent
+        #[arg(long, value_name = "TIME", default_value = "-1", allow_hyphen_values = true)]
+        time: String,
+        #[arg(long, value_name = "TEXT", default_value = "Console ban")]
+        reason: String,
+    },
+    /// Lift the active bans of an account
+    Unban { name: String },
+    /// Delete an account (characters must be removed from the character databases)
+    Delete { name: String },
+    /// Confirm a change awaiting a second administrator (GmLevel.DualControl)
+    Confirm {
+        #[command(subcommand)]
+        change: ConfirmCommand,
+    },
+    /// Issue a new matrix card and print it
+    #[command(name = "matrixcard")]
+    MatrixCard {
+        name: String,
+        #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(1..=26))]
+        width: u8,
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(1..=99))]
+        height: u8,
+        /// Digits per cell
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=8))]
+        digits: u8,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfirmCommand {
+    /// Apply the gmlevel elevation requested with TOKEN
+    #[command(name = "gmlevel")]
+    GmLevel { token: String },
+}
+
+/// Actor recorded in the audit log for command line changes without --actor
+const CONSOLE_ACTOR: &str = "[Console]";
+
+async fn find_account(db: &Database, name: &str) -> anyhow::Result<u32> {
+    account_mgr::find_account_id(db, name)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Account {} does not exist", name.to_uppercase()))
+}
+
+/// Handle `realmd account ...` against the login database
+async fn run_account_command(db: &Database, actor: Option<&str>, action: &AccountCommand) -> anyhow::Result<()> {
+    let source = ChangeSource::Cli;
+    // Upper-cased like the account names SOAP administrators are audited under
+    let named_actor = actor.map(str::to_uppercase);
+    let actor = named_actor.as_deref().unwrap_or(CONSOLE_ACTOR);
+
+    match action {
+        AccountCommand::Create { name, password, expansion } => {
+            let account_id = account_mgr::create_account(db, name, password, *expansion, actor, source).await?;
+            println!("Account created: {} (Id: {})", name.to_uppercase(), account_id);
+        }
+        AccountCommand::SetPassword { name, password } => {
+            let account_id = find_account(db, name).await?;
+            account_mgr::set_password(db, account_id, password, actor, source).await?;
+            println!("The password of {} was changed", name.to_uppercase());
+        }
+        AccountCommand::SetGm { name, level } => {
+            let account_id = find_account(db, name).await?;
+            match account_mgr::set_gm_level(db, account_id, *level, actor, source).await? {
+                GmLevelChange::Applied { .. } => {
+                    println!("Security level of {} set to {}", name.to_uppercase(), level);
+                }
+                GmLevelChange::PendingConfirmation { token, .. } => {
+                    println!(
+                        "Security level {} for {} awaits confirmation by another administrator (token {})",
+                        level,
+                        name.to_uppercase(),
+                        token
+                    );
+                }
+            }
+        }
+        AccountCommand::Ban { name, time, reason } => {
+            let duration = commands::parse_ban_time(time).map_err(MangosError::Config)?;
+            let account_id = find_account(db, name).await?;
+            account_mgr::ban_account(db, account_id, duration, reason, actor, source).await?;
+            if duration == 0 {
+                println!("{} is banned permanently", name.to_uppercase());
+            } else {
+                println!("{} is banned for {}", name.to_uppercase(), secs_to_time_string(duration));
+            }
+        }
+        AccountCommand::Unban { name } => {
+            let account_id = find_account(db, name).await?;
+            match account_mgr::unban_account(db, account_id, actor, source).await? {
+                0 => println!("{} is not banned", name.to_uppercase()),
+                _ => println!("{} unbanned", name.to_uppercase()),
+            }
+        }
+        AccountCommand::Delete { name } => {
+            let account_id = find_account(db, name).await?;
+            account_mgr::delete_account(db, account_id, actor, source).await?;
+            println!("Account deleted: {} (Id: {})", name.to_uppercase(), account_id);
+        }
+        AccountCommand::Confirm { change: ConfirmCommand::GmLevel { token } } => {
+            // "[Console]" cannot stand for a second administrator
+            let Some(confirmer) = named_actor.as_deref() else {
+                anyhow::bail!(MangosError::Config("Name the confirming administrator with --actor".into()));
+            };
+            account_mgr::confirm_gm_level(db, token, confirmer, source).await?;
+            println!("Security level change confirmed by {}", confirmer);
+        }
+        AccountCommand::MatrixCard { name, width, height, digits } => {
+            let account_id = find_account(db, name).await?;
+            let card = MatrixCard::generate(*width, *height, *digits);
+            account_mgr::set_matrix_card(db, account_id, &card, actor, source).await?;
+            println!("Matrix card for {} (prompted when Auth.MatrixCard = 1):", name.to_uppercase());
+            print_matrix_card(&card);
+        }
+    }
+    Ok(())
+}
+
+/// Handle `realmd srp-migrate` against the login database
+async fn run_srp_migrate(db: &Database, dry_run: bool, batch_size: u32) -> anyhow::Result<()> {
+    let migration = account_mgr::migrate_all_sha_pass_hashes(db, dry_run, batch_size, |progress| {
+        println!(
+            "Checked {} account(s): {} {}, {} without a usable sha_pass_hash",
+            progress.checked,
+            progress.converted,
+            if dry_run { "to convert" } else { "converted" },
+            progress.unusable
+        );
+    })
+    .await?;
+    if dry_run {
+        println!("Dry run: {} of {} account(s) would get a new verifier", migration.converted, migration.checked);
+    } else {
+        println!("{} of {} account(s) got a new verifier", migration.converted, migration.checked);
+    }
+    Ok(())
+}
+
+/// Handle `realmd stress`; needs no configuration or database
+async fn run_stress(command: &Command) -> anyhow::Result<()> {
+    let Command::Stress { accounts, concurrency, logins, target, build } = command else {
+        return Ok(());
+    };
+    let accounts = stress::read_accounts(Path::new(accounts))?;
+    let options = stress::StressOptions {
+        target: target.clone(),
+        concurrency: *concurrency,
+        logins: logins.unwrap_or(accounts.len()),
+        build: *build,
+    };
+    println!(
+        "Logging in {} time(s) with {} account(s) at {}, {} at once",
+        options.logins,
+        accounts.len(),
+        options.target,
+        options.concurrency
+    );
+    let report = stress::run(&options, accounts).await?;
+
+    let succeeded = report.latencies.len();
+    println!(
+        "{} succeeded, {} failed in {:.1}s ({:.1} logins/s)",
+        succeeded,
+        report.failed(),
+        report.elapsed.as_secs_f64(),
+        succeeded as f64 / report.elapsed.as_secs_f64().max(0.001)
+    );
+    for (reason, count) in &report.failures {
+        println!("  {:>6}  {}", count, reason);
+    }
+    if let (Some(p50), Some(p90), Some(p99), Some(max)) =
+        (report.percentile(0.5), report.percentile(0.9), report.percentile(0.99), report.percentile(1.0))
+    {
+        println!(
+            "Latency p50 {:.1}ms, p90 {:.1}ms, p99 {:.1}ms, max {:.1}ms",
+            p50.as_secs_f64() * 1000.0,
+            p90.as_secs_f64() * 1000.0,
+            p99.as_secs_f64() * 1000.0,
+            max.as_secs_f64() * 1000.0
+        );
+    }
+    Ok(())
+}
+
+/// Handle `realmd test-login`; fails unless the login succeeds
+async fn run_test_login(command: &Command) -> anyhow::Result<()> {
+    let Command::TestLogin { host, account, password, build } = command else {
+        return Ok(());
+    };
+    let info = realm_list::find_build_info(*build)
+        .filter(|info| info.build == *build)
+        .ok_or_else(|| anyhow::anyhow!("Unknown client build {}", build))?;
+    let target = if host.parse::<SocketAddr>().is_ok() || host.rsplit_once(':').is_some_and(|(name, _)| !name.contains(':')) {
+        host.clone()
+    } else if host.parse::<std::net::Ipv6Addr>().is_ok() {
+        format!("[{}]:3724", host)
+    } else {
+        format!("{}:3724", host)
+    };
+
+    let started = std::time::Instant::now();
+    let body = match tokio::time::timeout(auth_client::LOGIN_TIMEOUT, auth_client::login(&target, &info, account, password)).await {
+        Ok(Ok(body)) => body,
+        Ok(Err(reason)) => anyhow::bail!("Login of {} at {} failed: {}", account.to_uppercase(), target, reason),
+        Err(_) => anyhow::bail!("Login of {} at {} timed out", account.to_uppercase(), target),
+    };
+    let realms = auth_client::parse_realm_list(*build, &body).map_err(|e| anyhow::anyhow!("Malformed realm list: {}", e))?;
+    println!(
+        "Logged in as {} at {} in {:.1}ms, {} realm(s):",
+        account.to_uppercase(),
+        target,
+        started.elapsed().as_secs_f64() * 1000.0,
+        realms.len()
+    );
+    for realm in &realms {
+        let mut state = Vec::new();
+        if realm.flags & RealmFlags::REALM_FLAG_OFFLINE != 0 {
+            state.push("offline".to_string());
+        }
+        if realm.locked {
+            state.push("locked".to_string());
+        }
+        if realm.flags & RealmFlags::REALM_FLAG_RECOMMENDED != 0 {
+            state.push("recommended".to_string());
+        }
+        if realm.flags & RealmFlags::REALM_FLAG_NEW_PLAYERS != 0 {
+            state.push("new players".to_string());
+        }
+        if let Some((major, minor, bugfix, build)) = realm.version {
+            state.push(format!("{}.{}.{} ({})", major, minor, bugfix, build));
+        }
+        println!(
+            "  {:<24} {:<22} type {:<2} category {:<2} population {:.2}, {} character(s){}",
+            realm.name,
+            realm.address,
+            realm.icon,
+            realm.category,
+            realm.population,
+            realm.characters,
+            if state.is_empty() { String::new() } else { format!(", {}", state.join(", ")) }
+        );
+    }
+    Ok(())
+}
+
+/// Handle --ban-ip / --unban-ip against the login database
+async fn run_ban_command(db: &Database, args: &Args) -> anyhow::Result<()> {
+    let (target, banning) = match (&args.ban_ip, &args.unban_ip) {
+        (Some(target), _) => (target, true),
+        (None, Some(target)) => (target, false),
+        (None, None) => return Ok(()),
+    };
+    let Some(network) = IpNetwork::parse(target.trim()) else {
+        anyhow::bail!(MangosError::Config(format!("Invalid address or CIDR range '{}'", target)));
+    };
+
+    if banning {
+        ip_ban::ban(db, network, args.ban_time, CONSOLE_ACTOR, &args.ban_reason).await?;
+        println!("Banned {}", network.canonical());
+    } else {
+        match ip_ban::unban(db, network, CONSOLE_ACTOR).await? {
+            0 => println!("No ban on {}", network.canonical()),
+            removed => println!("Removed {} ban(s) on {}", removed, network.canonical()),
+        }
+    }
+    Ok(())
+}
+
+/// Print a matrix card as a grid with lettered columns and numbered rows
+fn print_matrix_card(card: &MatrixCard) {
+    let cell_width = card.digit_count as usize;
+    print!("   ");
+    for x in 0..card.width {
+        print!(" {:>cell_width$}", char::from(b'A' + x));
+    }
+    println!();
+    for y in 0..card.height {
+        print!("{:>3}", y + 1);
+        for x in 0..card.width {
+            let digits: String = card.cell(x, y).iter().map(|d| char::from(b'0' + d)).collect();
+            print!(" {}", digits);
+        }
+        println!();
+    }
+}
+
+/// Print the effective configuration to stdout (used by --dump-config)
+fn dump_config() {
+    let config = get_config().lock();
+    let entries = config.entries();
+    let key_width = entries.iter().map(|e| e.key.len()).max().unwrap_or(0);
+
+    println!("# Effective configuration from {}", config.filename());
+    for entry in &entries {
+        println!(
+            "{:<width$} = {:<40} [{}]",
+            entry.key,
+            redact_value(&entry.key, &entry.value),
+            entry.source,
+            width = key_width
+        );
+    }
+}
+
+/// Print a new authenticator secret and its provisioning URI (used by --totp-enroll)
+fn print_totp_enrollment(account: &str, issuer: &str) {
+    let account = account.to_uppercase();
+    let params = TotpParams::from_config(&get_config().lock());
+    let secret = totp::generate_secret();
+    println!("Secret:  {}", secret);
+    println!("URI:     {}", totp::provisioning_uri(issuer, &account, &secret, &params));
+    println!();
+    println!("Encode the URI as a QR code (e.g. qrencode -t ansiutf8 '<URI>') and scan it with the authenticator app,");
+    println!("then store the secret for the account:");
+    println!("  UPDATE account SET token = '{}' WHERE username = '{}';", secret, Database::escape_string(&account));
 }
 
+/// Time closed sessions get to send their last answer, and auth_log to flush
+const SESSION_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Global stop signal
 static STOP_EVENT: AtomicBool = AtomicBool::new(false);
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+/// Exit codes follow mangos_shared::error::ExitStatus
+fn main() -> ExitCode {
     let args = Args::parse();
 
+    if let Some(action) = args.service {
+        return match service::execute(action, args) {
+            Ok(()) => ExitStatus::Success.into(),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                ExitStatus::from_error(&e).into()
+            }
+        };
+    }
+
+    // Fork before the runtime starts its worker threads
+    if args.daemon
+        && let Err(e) = daemon::daemonize()
+    {
+        eprintln!("Error: {:#}", e);
+        return ExitStatus::from_error(&e).into();
+    }
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: cannot start the async runtime: {}", e);
+            return ExitStatus::Failure.into();
+        }
+    };
+    match runtime.block_on(run(args)) {
+        Ok(()) => ExitStatus::Success.into(),
+        Err(e) => {
+            let status = ExitStatus::from_error(&e);
+            eprintln!("Error: {:#}", e);
+            status.into()
+        }
+    }
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    match &args.command {
+        Some(command @ Command::Stress { .. }) => return run_stress(command).await,
+        Some(command @ Command::TestLogin { .. }) => return run_test_login(command).await,
+        _ => {}
+    }
+
     // Load configuration
     {
         let mut config = get_config().lock();
         if !config.set_source(&args.config, "Realmd_") {
             eprintln!("Could not find configuration file {}.", args.config);
-            return Err(anyhow::anyhow!("Configuration file not found"));
+            anyhow::bail!(MangosError::Config(format!("Configuration file {} not found", args.config)));
         }
     }
 
+    if args.dump_config {
+        dump_config();
+        return Ok(());
+    }
+
+    if let Some(account) = &args.totp_enroll {
+        print_totp_enrollment(account, &args.totp_issuer);
+        return Ok(());
+    }
+
     // Initialize logging
     // LogLevel: console log level (0=Minimum/Error, 1=Warn, 2=Detail/Info, 3=Full/Debug, 4=Trace)
     // LogFileLevel: file log level (same scale, defaults to LogLevel)
     // CLI --log-level overrides config LogLevel
-    let (log_dir, console_level_str, file_level_str) = {
+    let (log_dir, console_level_str, file_level_str, log_options) = {
         let config = get_config().lock();
         let dir = config.get_string_default("LogsDir", "");
         let log_dir = if dir.is_empty() { None } else { Some(dir) };
@@ -145,12 +799,13 @@ async fn main() -> anyhow::Result<()> {
         let console_str = map_log_level(console_level_int).to_string();
         let file_str = map_log_level(file_level_int).to_string();
 
-        (log_dir, console_str, file_str)
+        (log_dir, console_str, file_str, LogOptions::from_config(&config, "realmd"))
     };
-    initialize_logging(
+    let logging = initialize_logging(
         log_dir.as_deref(),
         &console_level_str,
         Some(&file_level_str),
+        &log_options,
     );
 
     // Print banner
@@ -170,6 +825,10 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Using configuration file: {}", args.config);
     tracing::info!("<Ctrl-C> to stop.");
 
+    if let Err(e) = session_key::initialize(&get_config().lock()) {
+        anyhow::bail!(MangosError::Config(format!("{:#}", e)));
+    }
+
     // Initialize database
     let mut login_db = Database::new("Login");
     let db_string = {
@@ -179,77 +838,301 @@ async fn main() -> anyhow::Result<()> {
 
     if db_string.is_empty() {
         tracing::error!("Database not specified in configuration");
-        return Err(anyhow::anyhow!("Database not specified"));
+        anyhow::bail!(MangosError::Config("LoginDatabaseInfo not specified".into()));
     }
 
     tracing::info!("Login Database total connections: 2");
 
     if let Err(e) = login_db.initialize(&db_string).await {
-        tracing::error!("Cannot connect to database: {}", e);
-        return Err(anyhow::anyhow!("Database connection failed"));
+        tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot connect to database: {}", e);
+        anyhow::bail!(MangosError::Database(format!("Cannot connect to login database: {}", e)));
+    }
+
+    if args.ban_ip.is_some() || args.unban_ip.is_some() {
+        return run_ban_command(&login_db, &args).await;
     }
 
+    match &args.command {
+        Some(Command::Account { actor, action }) => return run_account_command(&login_db, actor.as_deref(), action).await,
+        Some(Command::SrpMigrate { dry_run, batch_size }) => return run_srp_migrate(&login_db, *dry_run, *batch_size).await,
+        Some(Command::Stress { .. } | Command::TestLogin { .. }) | None => {}
+    }
+
+    // Removed again when run() returns
+    let pid_file_path = args.pidfile.clone().unwrap_or_else(|| get_config().lock().get_string("PidFile"));
+    let _pid_file = if pid_file_path.is_empty() {
+        None
+    } else {
+        let pid_file = PidFile::create(&pid_file_path)?;
+        tracing::info!("Daemon PID: {} (written to {})", std::process::id(), pid_file.path().display());
+        Some(pid_file)
+    };
+
     let db = Arc::new(login_db);
 
     // Initialize realm list
-    let (update_interval, stale_timeout) = {
+    let settings = RealmListSettings::from_config(&get_config().lock());
+    let mut realm_list = RealmList::new();
+    realm_list.initialize(settings, &db).await;
+
+    if realm_list.size() == 0 {
+        tracing::error!("No valid realms specified.");
+        anyhow::bail!(MangosError::Config("No realms configured in the realmlist table".into()));
+    }
+
+    let realm_list = Arc::new(realm_list);
+
+    // Refresh the realm list in the background; requests only read the latest snapshot
+    let refresh_interval = realm_list.update_interval();
+    if refresh_interval > 0 {
+        let realm_list = realm_list.clone();
+        let db = db.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(refresh_interval as u64));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick completes at once, the list was just loaded
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                realm_list.refresh(&db).await;
+            }
+        });
+    }
+
+    // Admin REST API on its own address
+    let (rest_enabled, rest_bind, rest_port, rest_token) = {
         let config = get_config().lock();
         (
-            config.get_int_default("RealmsStateUpdateDelay", 20) as u32,
-            config.get_int_default("RealmStaleTimeout", 60) as i64,
+            config.get_bool_default("RestApi.Enable", false),
+            config.get_string_default("RestApi.BindIP", "127.0.0.1"),
+            config.get_int_default("RestApi.Port", 8086),
+            config.get_string("RestApi.Token"),
         )
     };
+    if rest_enabled {
+        if rest_token.is_empty() {
+            anyhow::bail!(MangosError::Config("RestApi.Token must be set when RestApi.Enable is on".into()));
+        }
+        let rest_tls = TlsAcceptor::from_config(&get_config().lock(), "RestApi")
+            .map_err(|e| MangosError::Config(format!("RestApi TLS: {:#}", e)))?;
+        // The bearer token opens every account, so it only goes in clear text over loopback
+        let loopback = rest_bind == "localhost" || rest_bind.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+        if rest_tls.is_none() && !loopback {
+            anyhow::bail!(MangosError::Config(format!(
+                "RestApi.BindIP {} is not a loopback address, set RestApi.TlsEnabled or bind to 127.0.0.1",
+                rest_bind
+            )));
+        }
+        let listener = TcpListener::bind((rest_bind.as_str(), rest_port as u16))
+            .await
+            .map_err(|e| MangosError::Network(format!("Cannot bind REST API to {}:{}: {}", rest_bind, rest_port, e)))?;
+        tracing::info!(
+            "Admin REST API listening on {}:{}{}",
+            rest_bind, rest_port, if rest_tls.is_some() { " (TLS)" } else { "" }
+        );
+        let api = Arc::new(RestApi::new(db.clone(), realm_list.clone(), rest_token, rest_tls));
+        tokio::spawn(api.serve(listener));
+    }
 
-    let mut realm_list = RealmList::new();
-    realm_list.initialize(update_interval, stale_timeout, &db).await;
+    // Load the country database for GeoIp.DenyCountries and account country locks
+    let geoip_path = get_config().lock().get_string("GeoIp.Database");
+    if !geoip_path.is_empty() {
+        let geoip = GeoIp::open(Path::new(&geoip_path)).map_err(|e| MangosError::Config(format!("GeoIp.Database: {:#}", e)))?;
+        tracing::info!("Loaded GeoIP database {}", geoip_path);
+        auth_socket::set_geoip(geoip);
+    }
 
-    if realm_list.size() == 0 {
-        tracing::error!("No valid realms specified.");
-        return Err(anyhow::anyhow!("No realms configured"));
+    // Authentication decisions in the auth_log table
+    let (auth_log_db, auth_log_retention) = {
+        let config = get_config().lock();
+        (
+            config.get_bool_default("AuthLog.Database", false),
+            config.get_int_default("AuthLog.RetentionDays", 90).max(0) as u32,
+        )
+    };
+    if auth_log_db {
+        auth_log::start(db.clone(), auth_log_retention);
     }
 
-    let realm_list = Arc::new(tokio::sync::RwLock::new(realm_list));
+    // Daily counts of client builds, OS, platform and locale in client_stats
+    let (client_stats, client_stats_interval) = {
+        let config = get_config().lock();
+        (
+            config.get_bool_default("ClientStats.Enabled", false),
+            Duration::from_secs(config.get_int_default("ClientStats.FlushInterval", 60).max(1) as u64),
+        )
+    };
+    if client_stats {
+        client_stats::start(db.clone(), client_stats_interval);
+    }
+
+    // Answer logins with "server busy" in maintenance mode or while the database is down
+    let (maintenance_enabled, maintenance_check_interval) = {
+        let config = get_config().lock();
+        (
+            config.get_bool_default("Maintenance.Enabled", false),
+            config.get_int_default("Maintenance.DbCheckInterval", 10).max(0) as u64,
+        )
+    };
+    if maintenance_enabled {
+        maintenance::set(true, "Maintenance.Enabled");
+    }
+    if maintenance_check_interval > 0 {
+        maintenance::monitor(db.clone(), Duration::from_secs(maintenance_check_interval));
+    }
+
+    // Show realms offline during their scheduled maintenance windows
+    let maintenance_windows = maintenance_window::parse_windows(&get_config().lock().get_string("MaintenanceWindows"));
+    if !maintenance_windows.is_empty() {
+        tracing::info!("{} realm maintenance window(s) scheduled", maintenance_windows.len());
+        maintenance_window::schedule(db.clone(), realm_list.clone(), maintenance_windows);
+    }
+
+    // Tarpit and rate limit counters shared with other instances
+    let shared_state_backend = get_config().lock().get_string_default("SharedState.Backend", "memory");
+    match shared_state_backend.to_ascii_lowercase().as_str() {
+        "memory" => {}
+        "database" => {
+            shared_state::start(db.clone());
+            tracing::info!("Sharing tarpit and rate limit counters through the login database");
+        }
+        other => anyhow::bail!(MangosError::Config(format!("SharedState.Backend: unknown backend '{}'", other))),
+    }
+
+    // Security event notifications
+    webhook::start(webhook::WebhookSettings::from_config(&get_config().lock()));
+
+    // Deny lists checked before ip_banned
+    let reputation_settings = ip_reputation::ReputationSettings::from_config(&get_config().lock());
+    ip_reputation::start(reputation_settings).await;
+
+    // Hash the client patches up front so offering one doesn't stall a login
+    let patches = patcher::load_patches();
+    if patches > 0 {
+        tracing::info!("Loaded {} client patch(es)", patches);
+    }
 
     // Cleanup expired bans
+    let now = unix_now();
     let _ = db
-        .execute("UPDATE account_banned SET active = 0 WHERE expires_at <= UNIX_TIMESTAMP() AND expires_at <> banned_at")
+        .execute(&format!("UPDATE account_banned SET active = 0 WHERE expires_at <= {} AND expires_at <> banned_at", now))
         .await;
     let _ = db
-        .execute("DELETE FROM ip_banned WHERE expires_at <= UNIX_TIMESTAMP() AND expires_at <> banned_at")
+        .execute(&format!("DELETE FROM ip_banned WHERE expires_at <= {} AND expires_at <> banned_at", now))
         .await;
 
     // Read connection security settings
-    let (connection_timeout, max_per_ip, max_total) = {
+    let (timeouts, max_per_ip, max_total) = {
         let config = get_config().lock();
         (
-            config.get_int_default("ConnectionTimeout", 30) as u64,
+            SessionTimeouts::from_config(&config),
             config.get_int_default("MaxConnectionsPerIP", 10) as u32,
-            config.get_int_default("MaxConnections", 1000) as u32,
+            // MaxConnections is the older name of MaxTotalConnections
+            config.get_int_default("MaxTotalConnections", config.get_int_default("MaxConnections", 1000)) as u32,
         )
     };
 
     tracing::info!(
-        "Connection limits: timeout={}s max_per_ip={} max_total={} (0=unlimited)",
-        connection_timeout,
+        "Connection limits: timeout={}s (pre-auth {}s) lifetime={}s max_per_ip={} max_total={} (0=unlimited)",
+        timeouts.authed.as_secs(),
+        timeouts.pre_auth.as_secs(),
+        timeouts.lifetime.as_secs(),
         max_per_ip,
         max_total
     );
 
     let tracker = Arc::new(Mutex::new(ConnectionTracker::new(max_per_ip, max_total)));
 
-    // Start the TCP listener
-    let bind_ip = {
+    let challenge_limiter = Arc::new(IpRateLimiter::from_config(&get_config().lock(), "LogonChallenge"));
+    if challenge_limiter.is_enabled() {
+        tracing::info!(
+            "Logon challenges limited to {} per {}s per IP",
+            challenge_limiter.max_attempts(),
+            challenge_limiter.window().as_secs()
+        );
+    }
+
+    // CMaNGOS-compatible SOAP endpoint; its logins count against the same limits
+    let (soap_enabled, soap_ip, soap_port) = {
+        let config = get_config().lock();
+        (
+            config.get_bool_default("SOAP.Enabled", false),
+            config.get_string_default("SOAP.IP", "127.0.0.1"),
+            config.get_int_default("SOAP.Port", 7878),
+        )
+    };
+    if soap_enabled {
+        let listener = TcpListener::bind((soap_ip.as_str(), soap_port as u16))
+            .await
+            .map_err(|e| MangosError::Network(format!("Cannot bind SOAP to {}:{}: {}", soap_ip, soap_port, e)))?;
+        tracing::info!("SOAP listening on {}:{}", soap_ip, soap_port);
+        tokio::spawn(Arc::new(SoapServer::new(db.clone(), challenge_limiter.clone())).serve(listener));
+    }
+
+    set_packet_dump(get_config().lock().get_bool_default("Log.PacketDump", false));
+
+    let proxy_protocol = ProxyProtocol::from_config(&get_config().lock());
+    let socket_options = SocketOptions::from_config(&get_config().lock());
+    if let Some(proxy) = &proxy_protocol {
+        if proxy.trusted.is_empty() {
+            tracing::warn!("ProxyProtocol.Enabled is set but ProxyProtocol.TrustedProxies is empty; no PROXY headers will be accepted");
+        } else {
+            tracing::info!("Accepting PROXY protocol headers from {} trusted network(s)", proxy.trusted.len());
+        }
+    }
+
+    // Start the TCP listeners
+    let (bind_ip, port, map_v4) = {
         let config = get_config().lock();
-        config.get_string_default("BindIP", "0.0.0.0")
+        (
+            config.get_string_default("BindIP", "0.0.0.0"),
+            config.get_int_default("RealmServerPort", DEFAULT_REALMSERVER_PORT),
+            config.get_bool_default("MapIPv4MappedAddresses", true),
+        )
     };
-    let port = {
+
+    let bind_addresses = parse_bind_addresses(&bind_ip, port as u16).map_err(|e| MangosError::Config(format!("BindIP: {:#}", e)))?;
+    let listeners = open_listeners(&bind_addresses).map_err(|e| MangosError::Network(format!("{:#}", e)))?;
+    for listener in &listeners {
+        tracing::info!("Listening on {}", listener.local_addr()?);
+    }
+
+    // Liveness and readiness probes
+    let (health_enabled, health_ip, health_port, health_interval) = {
         let config = get_config().lock();
-        config.get_int_default("RealmServerPort", DEFAULT_REALMSERVER_PORT)
+        (
+            config.get_bool_default("Health.Enabled", false),
+            config.get_string_default("Health.IP", "127.0.0.1"),
+            config.get_int_default("Health.Port", 8087),
+            config.get_int_default("Health.CheckInterval", 10).max(1) as u64,
+        )
     };
+    if health_enabled {
+        let listener = TcpListener::bind((health_ip.as_str(), health_port as u16))
+            .await
+            .map_err(|e| MangosError::Network(format!("Cannot bind health endpoint to {}:{}: {}", health_ip, health_port, e)))?;
+        tracing::info!("Health endpoints listening on {}:{}", health_ip, health_port);
+        let health = Arc::new(Health::new(db.clone(), realm_list.clone(), listeners.len()));
+        tokio::spawn(health.clone().monitor(Duration::from_secs(health_interval)));
+        tokio::spawn(health.serve(listener));
+    }
 
-    let bind_addr = format!("{}:{}", bind_ip, port);
-    let listener = TcpListener::bind(&bind_addr).await?;
-    tracing::info!("Listening on {}", bind_addr);
+    // Public realm status for websites
+    let (status_enabled, status_ip, status_port) = {
+        let config = get_config().lock();
+        (
+            config.get_bool_default("RealmStatus.Enabled", false),
+            config.get_string_default("RealmStatus.IP", "0.0.0.0"),
+            config.get_int_default("RealmStatus.Port", 8088),
+        )
+    };
+    if status_enabled {
+        let listener = TcpListener::bind((status_ip.as_str(), status_port as u16))
+            .await
+            .map_err(|e| MangosError::Network(format!("Cannot bind realm status endpoint to {}:{}: {}", status_ip, status_port, e)))?;
+        tracing::info!("Realm status endpoint listening on {}:{}", status_ip, status_port);
+        tokio::spawn(Arc::new(RealmStatus::new(realm_list.clone())).serve(listener));
+    }
 
     // Setup Ctrl-C handler
     let stop_event = Arc::new(AtomicBool::new(false));
@@ -259,7 +1142,10 @@ async fn main() -> anyhow::Result<()> {
         tracing::info!("Received shutdown signal");
         stop_clone.store(true, Ordering::SeqCst);
         STOP_EVENT.store(true, Ordering::SeqCst);
+        shutdown::request(ShutdownMode::Stop);
     })?;
+    #[cfg(unix)]
+    shutdown::listen_for_signals()?;
 
     // Database ping interval
     let ping_interval = {
@@ -268,67 +1154,121 @@ async fn main() -> anyhow::Result<()> {
     };
     let ping_interval_secs = ping_interval * MINUTE as u64;
 
+    // Under a systemd watchdog every successful ping doubles as the heartbeat,
+    // so a wedged runtime or a lost database gets realmd restarted
+    let watchdog = daemon::watchdog_interval();
+    let ping_period = match watchdog {
+        Some(watchdog) => {
+            tracing::info!("systemd watchdog enabled ({}s)", watchdog.as_secs());
+            Duration::from_secs(ping_interval_secs).min(watchdog / 2)
+        }
+        None => Duration::from_secs(ping_interval_secs),
+    };
+
     // Spawn database ping task
     let db_ping = db.clone();
     let stop_ping = stop_event.clone();
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(ping_interval_secs));
+        let mut interval = tokio::time::interval(ping_period);
         loop {
             interval.tick().await;
             if stop_ping.load(Ordering::SeqCst) {
                 break;
             }
             tracing::debug!("Ping database to keep connection alive");
-            if let Err(e) = db_ping.ping().await {
-                tracing::error!("Database ping failed: {}", e);
+            match db_ping.ping().await {
+                Ok(()) if watchdog.is_some() => notify_systemd("WATCHDOG=1"),
+                Ok(()) => {}
+                Err(e) => tracing::error!(target: LOG_TARGET_DB_ERROR, "Database ping failed: {}", e),
             }
         }
     });
 
-    // Main accept loop
-    loop {
-        tokio::select! {
-            result = listener.accept() => {
-                match result {
-                    Ok((stream, addr)) => {
-                        let ip = addr.ip();
-
-                        // Enforce connection limits
-                        let allowed = tracker.lock().try_add(ip);
-                        if !allowed {
-                            tracing::warn!(
-                                "[{}] Connection rejected: limit exceeded (per_ip={} total={})",
-                                addr, max_per_ip, max_total
-                            );
-                            // Drop `stream` immediately by not spawning
-                            continue;
-                        }
-
-                        let db = db.clone();
-                        let realm_list = realm_list.clone();
-                        let tracker_clone = tracker.clone();
-
-                        tokio::spawn(async move {
-                            // RAII guard ensures tracker.remove(ip) on any exit
-                            let _guard = ConnectionGuard {
-                                tracker: tracker_clone,
-                                ip,
-                            };
-                            auth_socket::handle_client(stream, addr, db, realm_list, connection_timeout).await;
-                        });
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to accept connection: {}", e);
-                    }
-                }
-            }
-            _ = tokio::signal::ctrl_c() => {
-                tracing::info!("Shutting down...");
-                break;
-            }
+    let ctx = Arc::new(AcceptContext {
+        db,
+        realm_list,
+        tracker,
+        challenge_limiter,
+        proxy_protocol,
+        socket_options,
+        map_v4,
+        timeouts,
+    });
+    let accept_loops: Vec<_> = listeners
+        .into_iter()
+        .map(|listener| tokio::spawn(accept_loop(listener, ctx.clone())))
+        .collect();
+    notify_systemd(&format!("READY=1\nMAINPID={}\nSTATUS=Accepting connections", std::process::id()));
+
+    let mode = shutdown::wait().await;
+    tracing::info!("Shutting down...");
+    notify_systemd(match mode {
+        ShutdownMode::Stop => "STOPPING=1\nSTATUS=Draining sessions",
+        ShutdownMode::Restart => "RELOADING=1\nSTATUS=Draining sessions for restart",
+    });
+    let mut listeners = Vec::with_capacity(accept_loops.len());
+    for accept_loop in accept_loops {
+        if let Ok(listener) = accept_loop.await {
+            listeners.push(listener);
         }
     }
+    // Keep the port open while draining only when the listeners are passed on
+    if mode == ShutdownMode::Stop || cfg!(not(unix)) {
+        listeners.clear();
+    }
+
+    // Let the sessions in flight finish, then close the rest
+    let grace_period = get_config().lock().get_int_default("Shutdown.GracePeriod", 30).max(0) as u64;
+    let sessions = || ctx.tracker.lock().total;
+    if shutdown::drain(sessions, Duration::from_secs(grace_period)).await > 0 {
+        shutdown::close_sessions();
+        let remaining = shutdown::drain(sessions, SESSION_CLOSE_TIMEOUT).await;
+        if remaining > 0 {
+            tracing::warn!("Abandoning {} session(s) still open after {}s", remaining, grace_period);
+        }
+    }
+    let unwritten = auth_log::flush(SESSION_CLOSE_TIMEOUT).await;
+    if unwritten > 0 {
+        tracing::warn!("{} auth_log row(s) were not written before exiting", unwritten);
+    }
+    client_stats::flush().await;
 
+    if mode == ShutdownMode::Restart {
+        tracing::info!("Restarting realmd...");
+        logging.shutdown();
+        return shutdown::restart(listeners);
+    }
     tracing::info!("Halting process...");
+    logging.shutdown();
     Ok(())
 }
+
+/// Tell systemd about a state change; a failure is only worth a warning
+fn notify_systemd(state: &str) {
+    if let Err(e) = daemon::sd_notify(state) {
+        tracing::warn!("Cannot notify systemd: {:#}", e);
+    }
+}
+
+/// Listeners for `addresses`, reusing those handed over by a restart
+fn open_listeners(addresses: &[SocketAddr]) -> anyhow::Result<Vec<TcpListener>> {
+    #[cfg(unix)]
+    let mut inherited = mangos_shared::network::take_inherited_listeners();
+    #[cfg(not(unix))]
+    let mut inherited: Vec<TcpListener> = Vec::new();
+
+    let mut listeners = Vec::with_capacity(addresses.len());
+    let mut missing = Vec::new();
+    for addr in addresses {
+        match inherited.iter().position(|l| l.local_addr().is_ok_and(|local| local == *addr)) {
+            Some(index) => {
+                tracing::info!("Reusing listener on {} from the previous process", addr);
+                listeners.push(inherited.swap_remove(index));
+            }
+            None => missing.push(*addr),
+        }
+    }
+    // Listeners no longer configured are closed when `inherited` drops
+    listeners.extend(bind_listeners(&missing)?);
+    Ok(listeners)
+}
@@ -0,0 +1,80 @@
+// Maintenance - Turning logins away with "server busy"
+//
+// While realmd is in maintenance mode it answers every logon and reconnect
+// challenge with the busy result, which the client shows as a message,
+// instead of dropping the connection. The mode is switched with
+// Maintenance.Enabled, the "server maintenance on|off" command (console
+// syntax, also over SOAP) or PUT /maintenance on the REST API; a switch at
+// runtime lasts until the next start.
+//
+// realmd also enters it on its own while the login database does not answer:
+// with Maintenance.DbCheckInterval set the database is pinged that often and
+// logins resume with the first ping that succeeds.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use mangos_shared::database::Database;
+use mangos_shared::log::LOG_TARGET_DB_ERROR;
+
+/// Switched on by config or an administrator
+static MANUAL: AtomicBool = AtomicBool::new(false);
+
+/// Set while the login database does not answer
+static DB_UNREACHABLE: AtomicBool = AtomicBool::new(false);
+
+/// Why logins are turned away, None outside maintenance
+pub fn reason() -> Option<&'static str> {
+    if MANUAL.load(Ordering::Relaxed) {
+        Some("maintenance")
+    } else if DB_UNREACHABLE.load(Ordering::Relaxed) {
+        Some("database unreachable")
+    } else {
+        None
+    }
+}
+
+/// Whether an administrator switched maintenance mode on
+pub fn enabled() -> bool {
+    MANUAL.load(Ordering::Relaxed)
+}
+
+/// Whether the mode was entered because the login database stopped answering
+pub fn database_unreachable() -> bool {
+    DB_UNREACHABLE.load(Ordering::Relaxed)
+}
+
+/// Switch maintenance mode on or off for `actor`
+pub fn set(enabled: bool, actor: &str) {
+    if MANUAL.swap(enabled, Ordering::Relaxed) != enabled {
+        tracing::warn!("Maintenance mode {} by {}", if enabled { "enabled" } else { "disabled" }, actor);
+    }
+}
+
+/// Ping the login database every `interval` and turn logins away while it fails
+pub fn monitor(db: Arc<Database>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            match db.ping().await {
+                Ok(()) => {
+                    if DB_UNREACHABLE.swap(false, Ordering::Relaxed) {
+                        tracing::warn!("Login database reachable again, accepting logins");
+                    }
+                }
+                Err(e) => {
+                    if !DB_UNREACHABLE.swap(true, Ordering::Relaxed) {
+                        tracing::error!(
+                            target: LOG_TARGET_DB_ERROR,
+                            "Login database unreachable, answering logins with server busy: {:#}",
+                            e
+                        );
+                    }
+                }
+            }
+        }
+    });
+}
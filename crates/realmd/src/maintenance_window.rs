@@ -0,0 +1,235 @@
+// MaintenanceWindow - Recurring realm downtime from the config
+//
+// MaintenanceWindows lists weekly windows during which realms are shown
+// offline, e.g. "Tue 04:00-06:00 1,2; Daily 03:00-03:15", separated by ';':
+//   <days> <start>-<end> [<realm ids>]
+// with days as "Daily" or a comma separated list of day names, times in
+// server local time (an end before the start runs into the next day) and no
+// realm ids meaning every realm.
+//
+// When a window opens realmd sets REALM_FLAG_OFFLINE in realmlist.realmflags
+// of the affected realms that are online, and keeps it set should a world
+// server come up during the window. When it closes the flag is cleared again
+// on those realms only, so a realm that was already offline stays so. A
+// realmd restarted after a window closed does not know which flags it set and
+// leaves them to the world servers.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Weekday};
+
+use mangos_shared::database::Database;
+use mangos_shared::log::LOG_TARGET_DB_ERROR;
+use mangos_shared::RealmFlags;
+
+use crate::realm_list::RealmList;
+
+/// How often the windows are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// One recurring maintenance window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    /// Days the window opens on, by Weekday::num_days_from_monday
+    days: [bool; 7],
+    start: NaiveTime,
+    end: NaiveTime,
+    /// Affected realms, empty for every realm
+    realms: Vec<u32>,
+}
+
+impl MaintenanceWindow {
+    /// Parse a MaintenanceWindows entry: "<days> <HH:MM>-<HH:MM> [<realm id>,...]"
+    pub fn parse(entry: &str) -> Option<Self> {
+        let mut fields = entry.split_whitespace();
+        let (days_field, times) = (fields.next()?, fields.next()?);
+        let realms = match fields.next() {
+            Some(ids) => ids.split(',').map(|id| id.trim().parse().ok()).collect::<Option<Vec<u32>>>()?,
+            None => Vec::new(),
+        };
+        if fields.next().is_some() {
+            return None;
+        }
+
+        let mut days = [false; 7];
+        if days_field.eq_ignore_ascii_case("daily") {
+            days = [true; 7];
+        } else {
+            for day in days_field.split(',') {
+                days[day.trim().parse::<Weekday>().ok()?.num_days_from_monday() as usize] = true;
+            }
+        }
+
+        let (start, end) = times.split_once('-')?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+        Some(MaintenanceWindow { days, start, end, realms })
+    }
+
+    fn opens_on(&self, day: Weekday) -> bool {
+        self.days[day.num_days_from_monday() as usize]
+    }
+
+    /// Whether the window is open at local time `now`
+    pub fn is_open(&self, now: NaiveDateTime) -> bool {
+        let (day, time) = (now.weekday(), now.time());
+        if self.start < self.end {
+            self.opens_on(day) && time >= self.start && time < self.end
+        } else {
+            // Runs past midnight; equal times make a whole day
+            (self.opens_on(day) && time >= self.start) || (self.opens_on(day.pred()) && time < self.end)
+        }
+    }
+
+    /// Whether the window covers `realm_id`
+    fn covers(&self, realm_id: u32) -> bool {
+        self.realms.is_empty() || self.realms.contains(&realm_id)
+    }
+}
+
+/// Parse MaintenanceWindows; invalid entries are logged and skipped
+pub fn parse_windows(value: &str) -> Vec<MaintenanceWindow> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let window = MaintenanceWindow::parse(entry);
+            if window.is_none() {
+                tracing::warn!("Ignoring invalid MaintenanceWindows entry '{}'", entry);
+            }
+            window
+        })
+        .collect()
+}
+
+/// Set and clear the offline flag of realms as `windows` open and close
+pub fn schedule(db: Arc<Database>, realm_list: Arc<RealmList>, windows: Vec<MaintenanceWindow>) {
+    tokio::spawn(async move {
+        // Realms whose offline flag was set here
+        let mut flagged: BTreeSet<u32> = BTreeSet::new();
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let now = Local::now().naive_local();
+            let open: BTreeSet<u32> = realm_list
+                .realms()
+                .values()
+                .map(|realm| realm.id)
+                .filter(|&id| windows.iter().any(|window| window.is_open(now) && window.covers(id)))
+                .collect();
+
+            let mut changed = false;
+            for &realm_id in &open {
+                let sql = format!(
+                    "UPDATE realmlist SET realmflags = realmflags | {0} WHERE id = '{1}' AND (realmflags & {0}) = 0",
+                    RealmFlags::REALM_FLAG_OFFLINE, realm_id
+                );
+                match db.execute(&sql).await {
+                    Ok(0) => {}
+                    Ok(_) => {
+                        tracing::warn!("Maintenance window open, realm {} set offline", realm_id);
+                        flagged.insert(realm_id);
+                        changed = true;
+                    }
+                    Err(e) => tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot set realm {} offline for maintenance: {:#}", realm_id, e),
+                }
+            }
+            let closed: Vec<u32> = flagged.difference(&open).copied().collect();
+            for realm_id in closed {
+                let sql = format!(
+                    "UPDATE realmlist SET realmflags = realmflags & ~{} WHERE id = '{}'",
+                    RealmFlags::REALM_FLAG_OFFLINE, realm_id
+                );
+                match db.execute(&sql).await {
+                    Ok(_) => {
+                        tracing::warn!("Maintenance window closed, realm {} online again", realm_id);
+                        flagged.remove(&realm_id);
+                        changed = true;
+                    }
+                    Err(e) => tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot end maintenance of realm {}: {:#}", realm_id, e),
+                }
+            }
+
+            // Clients see the change with the next realm list, not the next refresh
+            if changed {
+                realm_list.refresh(&db).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// Local time on the day of the week `day` (2026-10-12 is a Monday)
+    fn at(day: Weekday, time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, 12 + day.num_days_from_monday())
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+    }
+
+    #[test]
+    fn test_parse() {
+        let window = MaintenanceWindow::parse("Tue,thu 04:00-06:30 1,2").unwrap();
+        assert_eq!(window.days, [false, true, false, true, false, false, false]);
+        assert_eq!((window.start.to_string().as_str(), window.end.to_string().as_str()), ("04:00:00", "06:30:00"));
+        assert_eq!(window.realms, [1, 2]);
+        assert_eq!(MaintenanceWindow::parse("daily 03:00-03:15").unwrap().days, [true; 7]);
+
+        let invalid_entries = [
+            "",
+            "Tue",
+            "Tue 04:00",
+            "Tue 4-6",
+            "Tue 25:00-26:00",
+            "Someday 04:00-06:00",
+            "Tue 04:00-06:00 1,x",
+            "Tue 04:00-06:00 1 2",
+        ];
+        for invalid in invalid_entries {
+            assert_eq!(MaintenanceWindow::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_windows() {
+        let windows = parse_windows(" Tue 04:00-06:00 1; bogus ;; Daily 03:00-03:15 ");
+        assert_eq!(windows.len(), 2);
+        assert!(windows[0].covers(1) && !windows[0].covers(2));
+        assert!(windows[1].covers(2));
+    }
+
+    #[test]
+    fn test_is_open() {
+        let window = MaintenanceWindow::parse("Tue 04:00-06:00").unwrap();
+        assert!(window.is_open(at(Weekday::Tue, "04:00")));
+        assert!(window.is_open(at(Weekday::Tue, "05:59")));
+        assert!(!window.is_open(at(Weekday::Tue, "06:00")));
+        assert!(!window.is_open(at(Weekday::Tue, "03:59")));
+        assert!(!window.is_open(at(Weekday::Wed, "05:00")));
+    }
+
+    #[test]
+    fn test_is_open_past_midnight() {
+        // Opens Sunday night and closes on Monday morning
+        let window = MaintenanceWindow::parse("Sun 23:00-01:00").unwrap();
+        assert!(window.is_open(at(Weekday::Sun, "23:30")));
+        assert!(window.is_open(at(Weekday::Mon, "00:30")));
+        assert!(!window.is_open(at(Weekday::Mon, "01:00")));
+        assert!(!window.is_open(at(Weekday::Mon, "23:30")));
+        assert!(!window.is_open(at(Weekday::Sat, "00:30")));
+
+        // Equal times make a whole day from the start
+        let window = MaintenanceWindow::parse("Wed 12:00-12:00").unwrap();
+        assert!(window.is_open(at(Weekday::Wed, "12:00")));
+        assert!(window.is_open(at(Weekday::Thu, "11:59")));
+        assert!(!window.is_open(at(Weekday::Thu, "12:00")));
+        assert!(!window.is_open(at(Weekday::Wed, "11:59")));
+    }
+}
@@ -0,0 +1,140 @@
+// Patcher - Client patch transfer (CMD_XFER_*)
+// Rust equivalent of PatchCache and PatcherRunnable in AuthSocket.cpp
+//
+// Clients with an unsupported build get the patch for their build and locale
+// if one exists under PatchesDir, named like the C++ server expects:
+// <build><locale>.mpq, e.g. "8606enGB.mpq". The MD5 of every patch is
+// computed once at startup; patches dropped in while the server runs are
+// hashed on first use.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use mangos_shared::auth::Md5Hash;
+use mangos_shared::config::get_config;
+
+/// Bytes of patch data per CMD_XFER_DATA packet (C++ ChunkSize)
+pub const XFER_CHUNK_SIZE: usize = 2048;
+
+/// MD5 of every known patch, keyed by path
+static PATCH_CACHE: Lazy<RwLock<HashMap<PathBuf, [u8; 16]>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// A patch file offered to one client
+#[derive(Debug, Clone)]
+pub struct PatchInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    pub md5: [u8; 16],
+}
+
+/// Directory patches are served from (PatchesDir)
+fn patches_dir() -> PathBuf {
+    PathBuf::from(get_config().lock().get_string_default("PatchesDir", "./patches"))
+}
+
+/// Hash every *.mpq in PatchesDir; returns the number of patches found
+pub fn load_patches() -> usize {
+    let dir = patches_dir();
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::debug!("No patches loaded from {}: {}", dir.display(), e);
+            return 0;
+        }
+    };
+
+    let mut cache = HashMap::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mpq")) {
+            continue;
+        }
+        match file_md5(&path) {
+            Ok(md5) => {
+                tracing::debug!("Loaded patch {}", path.display());
+                cache.insert(path, md5);
+            }
+            Err(e) => tracing::error!("Cannot hash patch {}: {:#}", path.display(), e),
+        }
+    }
+
+    let count = cache.len();
+    *PATCH_CACHE.write() = cache;
+    count
+}
+
+/// Find the patch for a client build and locale, hashing it if it is new
+pub fn find_patch(build: u16, locale: &str) -> Option<PatchInfo> {
+    find_patch_in(&patches_dir(), build, locale)
+}
+
+fn find_patch_in(dir: &Path, build: u16, locale: &str) -> Option<PatchInfo> {
+    // The locale ends up in a path, so only accept what clients send ("enGB")
+    if locale.len() != 4 || !locale.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let path = dir.join(format!("{}{}.mpq", build, locale));
+    let size = std::fs::metadata(&path).ok().filter(|m| m.is_file())?.len();
+
+    let cached = PATCH_CACHE.read().get(&path).copied();
+    let md5 = match cached {
+        Some(md5) => md5,
+        None => match file_md5(&path) {
+            Ok(md5) => {
+                PATCH_CACHE.write().insert(path.clone(), md5);
+                md5
+            }
+            Err(e) => {
+                tracing::error!("Cannot hash patch {}: {:#}", path.display(), e);
+                return None;
+            }
+        },
+    };
+
+    Some(PatchInfo { path, size, md5 })
+}
+
+/// MD5 of a whole file
+fn file_md5(path: &Path) -> anyhow::Result<[u8; 16]> {
+    let mut file = std::fs::File::open(path).context("open failed")?;
+    let mut md5 = Md5Hash::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf).context("read failed")?;
+        if read == 0 {
+            break;
+        }
+        md5.update_data_bytes(&buf[..read]);
+    }
+    md5.finalize();
+    Ok(*md5.get_digest())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_patch() {
+        let dir = std::env::temp_dir().join(format!("realmd_patcher_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("8606enGB.mpq.d")).unwrap();
+        std::fs::write(dir.join("8606enGB.mpq"), b"abc").unwrap();
+
+        let patch = find_patch_in(&dir, 8606, "enGB").unwrap();
+        assert_eq!(patch.size, 3);
+        // MD5 of "abc"
+        assert_eq!(data_encoding::HEXLOWER.encode(&patch.md5), "900150983cd24fb0d6963f7d28e17f72");
+
+        assert!(find_patch_in(&dir, 8606, "deDE").is_none());
+        assert!(find_patch_in(&dir, 8607, "enGB").is_none());
+        // Locales that would leave the directory or name something else
+        assert!(find_patch_in(&dir, 8606, "../x").is_none());
+        assert!(find_patch_in(&dir, 8606, "enGB.mpq.d").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
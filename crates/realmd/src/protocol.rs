@@ -3,9 +3,59 @@
 //
 // These represent the binary packet formats exchanged between
 // the WoW client and the authentication server.
+//
+// Client packets are parsed strictly: every packet has to be exactly as long
+// as its fields say, announced sizes are checked against the largest valid
+// packet before anything is read, and text fields have to be valid UTF-8
+// (username) or plain letters and digits (platform, OS, locale).
 
 use mangos_shared::util::ByteBuffer;
 
+use crate::auth_codes::{AuthCmd, AUTH_LOGON_MAX_NAME};
+
+/// Why a client packet was rejected
+///
+/// Parsing never panics and never allocates more than the packet it is given,
+/// so any byte sequence can be fed to the `from_bytes` functions.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    #[error("{packet}: {len} bytes, expected {expected}")]
+    Size { packet: &'static str, len: usize, expected: usize },
+    #[error("{packet}: announced size {size} outside {min}..={max}")]
+    AnnouncedSize { packet: &'static str, size: usize, min: usize, max: usize },
+    #[error("{packet}: {field} of {len} bytes exceeds {max}")]
+    FieldTooLong { packet: &'static str, field: &'static str, len: usize, max: usize },
+    #[error("{packet}: {field} is not valid text")]
+    InvalidText { packet: &'static str, field: &'static str },
+}
+
+/// Buffer over a received packet, which has to be exactly `expected` bytes long
+fn packet_buffer(packet: &'static str, data: &[u8], expected: usize) -> Result<ByteBuffer, ParseError> {
+    let buf = ByteBuffer::from(data);
+    buf.check_exact(expected)
+        .map_err(|_| ParseError::Size { packet, len: data.len(), expected })?;
+    Ok(buf)
+}
+
+/// A four character code sent as a reversed, NUL padded little-endian
+/// integer ("68x\0" is x86); letters and digits only
+fn four_cc(packet: &'static str, field: &'static str, bytes: [u8; 4]) -> Result<String, ParseError> {
+    let code: String = bytes.iter().rev().skip_while(|&&b| b == 0).map(|&b| b as char).collect();
+    if !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(ParseError::InvalidText { packet, field });
+    }
+    Ok(code)
+}
+
+/// UTF-8 text without control characters
+fn text(packet: &'static str, field: &'static str, bytes: &[u8]) -> Result<String, ParseError> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .filter(|text| !text.chars().any(char::is_control))
+        .map(str::to_string)
+        .ok_or(ParseError::InvalidText { packet, field })
+}
+
 /// Logon Challenge header (received from client)
 /// Packed struct: cmd (1) + error (1) + size (2)
 #[derive(Debug, Clone)]
@@ -18,15 +68,26 @@ pub struct AuthLogonChallengeHeader {
 impl AuthLogonChallengeHeader {
     pub const SIZE: usize = 3; // error (1) + size (2), cmd already read
 
-    pub fn from_bytes(data: &[u8]) -> Option<Self> {
-        if data.len() < Self::SIZE {
-            return None;
-        }
-        Some(AuthLogonChallengeHeader {
-            error: data[0],
-            size: u16::from_le_bytes([data[1], data[2]]),
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ParseError> {
+        const PACKET: &str = "challenge header";
+        let mut buf = packet_buffer(PACKET, data, Self::SIZE)?;
+        let short = |_: std::io::Error| ParseError::Size { packet: PACKET, len: data.len(), expected: Self::SIZE };
+        Ok(AuthLogonChallengeHeader {
+            error: buf.read_u8().map_err(short)?,
+            size: buf.read_u16().map_err(short)?,
         })
     }
+
+    /// Size of the body that follows, refused before anything is read when no
+    /// valid body can have it
+    pub fn body_size(&self) -> Result<usize, ParseError> {
+        let size = self.size as usize;
+        let (min, max) = (AuthLogonChallengeBody::MIN_SIZE, AuthLogonChallengeBody::MAX_SIZE);
+        if !(min..=max).contains(&size) {
+            return Err(ParseError::AnnouncedSize { packet: "challenge header", size, min, max });
+        }
+        Ok(size)
+    }
 }
 
 /// Logon Challenge body (received from client)
@@ -39,53 +100,52 @@ pub struct AuthLogonChallengeBody {
     pub version2: u8,
     pub version3: u8,
     pub build: u16,
-    pub platform: [u8; 4],
-    pub os: [u8; 4],
-    pub country: [u8; 4],
+    /// Platform (x86, PPC) in reading order
+    pub platform: String,
+    /// OS (Win, OSX) in reading order
+    pub os: String,
+    /// Locale (enUS, deDE) in reading order
+    pub locale: String,
     pub timezone_bias: u32,
     pub ip: u32,
-    pub username_len: u8,
-    pub username: Vec<u8>,
+    pub username: String,
 }
 
 impl AuthLogonChallengeBody {
     /// Minimum size without the variable-length username
     pub const MIN_SIZE: usize = 4 + 1 + 1 + 1 + 2 + 4 + 4 + 4 + 4 + 4 + 1; // = 30
-
-    pub fn from_bytes(data: &[u8]) -> Option<Self> {
-        if data.len() < Self::MIN_SIZE {
-            return None;
-        }
-
-        let mut gamename = [0u8; 4];
-        gamename.copy_from_slice(&data[0..4]);
-
-        let version1 = data[4];
-        let version2 = data[5];
-        let version3 = data[6];
-        let build = u16::from_le_bytes([data[7], data[8]]);
-
-        let mut platform = [0u8; 4];
-        platform.copy_from_slice(&data[9..13]);
-
-        let mut os = [0u8; 4];
-        os.copy_from_slice(&data[13..17]);
-
-        let mut country = [0u8; 4];
-        country.copy_from_slice(&data[17..21]);
-
-        let timezone_bias = u32::from_le_bytes([data[21], data[22], data[23], data[24]]);
-        let ip = u32::from_le_bytes([data[25], data[26], data[27], data[28]]);
-        let username_len = data[29];
-
-        let username_end = 30 + username_len as usize;
-        if data.len() < username_end {
-            return None;
+    /// Largest valid body, with a username of AUTH_LOGON_MAX_NAME characters
+    pub const MAX_SIZE: usize = Self::MIN_SIZE + AUTH_LOGON_MAX_NAME;
+
+    /// Parse a body; its size has to match the username length it announces
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ParseError> {
+        const PACKET: &str = "challenge body";
+        let buf = ByteBuffer::from(data);
+        buf.check_remaining(Self::MIN_SIZE)
+            .map_err(|_| ParseError::Size { packet: PACKET, len: data.len(), expected: Self::MIN_SIZE })?;
+        let username_len = data[Self::MIN_SIZE - 1] as usize;
+        if username_len > AUTH_LOGON_MAX_NAME {
+            return Err(ParseError::FieldTooLong { packet: PACKET, field: "username", len: username_len, max: AUTH_LOGON_MAX_NAME });
         }
-
-        let username = data[30..username_end].to_vec();
-
-        Some(AuthLogonChallengeBody {
+        let expected = Self::MIN_SIZE + username_len;
+        let mut buf = packet_buffer(PACKET, data, expected)?;
+        let short = |_: std::io::Error| ParseError::Size { packet: PACKET, len: data.len(), expected };
+
+        let gamename = buf.read_array().map_err(short)?;
+        let version1 = buf.read_u8().map_err(short)?;
+        let version2 = buf.read_u8().map_err(short)?;
+        let version3 = buf.read_u8().map_err(short)?;
+        let build = buf.read_u16().map_err(short)?;
+        let platform = four_cc(PACKET, "platform", buf.read_array().map_err(short)?)?;
+        let os = four_cc(PACKET, "os", buf.read_array().map_err(short)?)?;
+        let locale = four_cc(PACKET, "locale", buf.read_array().map_err(short)?)?;
+        let timezone_bias = buf.read_u32().map_err(short)?;
+        let ip = buf.read_u32().map_err(short)?;
+        // The username length, checked above
+        buf.read_skip(1);
+        let username = text(PACKET, "username", &buf.read_bytes(username_len).map_err(short)?)?;
+
+        Ok(AuthLogonChallengeBody {
             gamename,
             version1,
             version2,
@@ -93,48 +153,12 @@ impl AuthLogonChallengeBody {
             build,
             platform,
             os,
-            country,
+            locale,
             timezone_bias,
             ip,
-            username_len,
             username,
         })
     }
-
-    /// Get the OS as a string (reversed byte order)
-    pub fn os_string(&self) -> String {
-        let mut os = self.os;
-        os[3] = 0;
-        let s: String = os.iter()
-            .take_while(|&&b| b != 0)
-            .map(|&b| b as char)
-            .collect();
-        s.chars().rev().collect()
-    }
-
-    /// Get the platform as a string (reversed byte order)
-    pub fn platform_string(&self) -> String {
-        let mut platform = self.platform;
-        platform[3] = 0;
-        let s: String = platform.iter()
-            .take_while(|&&b| b != 0)
-            .map(|&b| b as char)
-            .collect();
-        s.chars().rev().collect()
-    }
-
-    /// Get the locale as a string (reversed byte order)
-    pub fn locale_string(&self) -> String {
-        let s: String = self.country.iter()
-            .map(|&b| b as char)
-            .collect();
-        s.chars().rev().collect()
-    }
-
-    /// Get the username as a string
-    pub fn username_string(&self) -> String {
-        String::from_utf8_lossy(&self.username).to_string()
-    }
 }
 
 /// Logon Proof received from client (CMD_AUTH_LOGON_PROOF)
@@ -146,42 +170,57 @@ pub struct AuthLogonProofClient {
     pub crc_hash: [u8; 20],  // Client version proof
     pub number_of_keys: u8,
     pub security_flags: u8,
+    pub pin: Option<AuthLogonPinData>, // Present when the client answered a PIN prompt
+    pub matrix_proof: Option<[u8; 20]>, // Present when the client answered a matrix card prompt
 }
 
 impl AuthLogonProofClient {
     pub const SIZE_WITHOUT_PIN: usize = 32 + 20 + 20 + 1 + 1; // = 74
-    pub const PIN_DATA_SIZE: usize = 16 + 20; // salt(16) + hash(20) = 36
-    pub const SIZE_WITH_PIN: usize = Self::SIZE_WITHOUT_PIN + Self::PIN_DATA_SIZE;
+    pub const PIN_DATA_SIZE: usize = AuthLogonPinData::SIZE; // salt(16) + hash(20) = 36
+    pub const MATRIX_PROOF_SIZE: usize = 20;
+
+    /// Bytes following the fixed part for the optional PIN and matrix card data
+    pub fn extra_size(with_pin: bool, with_matrix: bool) -> usize {
+        let mut size = 0;
+        if with_pin {
+            size += Self::PIN_DATA_SIZE;
+        }
+        if with_matrix {
+            size += Self::MATRIX_PROOF_SIZE;
+        }
+        size
+    }
 
-    pub fn from_bytes(data: &[u8], with_pin: bool) -> Option<Self> {
-        let expected_size = if with_pin {
-            Self::SIZE_WITH_PIN
+    pub fn from_bytes(data: &[u8], with_pin: bool, with_matrix: bool) -> Result<Self, ParseError> {
+        const PACKET: &str = "logon proof";
+        let expected = Self::SIZE_WITHOUT_PIN + Self::extra_size(with_pin, with_matrix);
+        let mut buf = packet_buffer(PACKET, data, expected)?;
+        let short = |_: std::io::Error| ParseError::Size { packet: PACKET, len: data.len(), expected };
+
+        let a = buf.read_array().map_err(short)?;
+        let m1 = buf.read_array().map_err(short)?;
+        let crc_hash = buf.read_array().map_err(short)?;
+        let number_of_keys = buf.read_u8().map_err(short)?;
+        let security_flags = buf.read_u8().map_err(short)?;
+        let pin = if with_pin {
+            Some(AuthLogonPinData {
+                salt: buf.read_array().map_err(short)?,
+                hash: buf.read_array().map_err(short)?,
+            })
         } else {
-            Self::SIZE_WITHOUT_PIN
+            None
         };
+        // The matrix card proof follows the PIN data
+        let matrix_proof = if with_matrix { Some(buf.read_array().map_err(short)?) } else { None };
 
-        if data.len() < expected_size {
-            return None;
-        }
-
-        let mut a = [0u8; 32];
-        a.copy_from_slice(&data[0..32]);
-
-        let mut m1 = [0u8; 20];
-        m1.copy_from_slice(&data[32..52]);
-
-        let mut crc_hash = [0u8; 20];
-        crc_hash.copy_from_slice(&data[52..72]);
-
-        let number_of_keys = data[72];
-        let security_flags = data[73];
-
-        Some(AuthLogonProofClient {
+        Ok(AuthLogonProofClient {
             a,
             m1,
             crc_hash,
             number_of_keys,
             security_flags,
+            pin,
+            matrix_proof,
         })
     }
 }
@@ -195,6 +234,7 @@ pub struct AuthLogonPinData {
 }
 
 impl AuthLogonPinData {
+    pub const SIZE: usize = 16 + 20; // = 36
 }
 
 /// Logon Proof sent to client (post-2.x builds)
@@ -258,27 +298,157 @@ pub struct AuthReconnectProofClient {
 impl AuthReconnectProofClient {
     pub const SIZE: usize = 16 + 20 + 20 + 1; // = 57 (cmd already read)
 
-    pub fn from_bytes(data: &[u8]) -> Option<Self> {
-        if data.len() < Self::SIZE {
-            return None;
-        }
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ParseError> {
+        const PACKET: &str = "reconnect proof";
+        let mut buf = packet_buffer(PACKET, data, Self::SIZE)?;
+        let short = |_: std::io::Error| ParseError::Size { packet: PACKET, len: data.len(), expected: Self::SIZE };
+        Ok(AuthReconnectProofClient {
+            r1: buf.read_array().map_err(short)?,
+            r2: buf.read_array().map_err(short)?,
+            r3: buf.read_array().map_err(short)?,
+            number_of_keys: buf.read_u8().map_err(short)?,
+        })
+    }
+}
+
+/// Patch transfer offer sent to client (CMD_XFER_INITIATE)
+#[derive(Debug, Clone)]
+pub struct XferInit {
+    pub file_size: u64,
+    pub md5: [u8; 16],
+}
+
+impl XferInit {
+    /// The client only accepts "Patch" as the file name
+    pub const FILE_NAME: &'static [u8] = b"Patch";
+    pub const SIZE: usize = 1 + 1 + 5 + 8 + 16; // = 31
 
-        let mut r1 = [0u8; 16];
-        r1.copy_from_slice(&data[0..16]);
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = ByteBuffer::with_capacity(Self::SIZE);
+        buf.write_u8(AuthCmd::XferInitiate as u8);
+        buf.write_u8(Self::FILE_NAME.len() as u8);
+        buf.append(Self::FILE_NAME);
+        buf.write_u64(self.file_size);
+        buf.append(&self.md5);
+        buf.contents().to_vec()
+    }
+}
 
-        let mut r2 = [0u8; 20];
-        r2.copy_from_slice(&data[16..36]);
+/// Patch data chunk sent to client (CMD_XFER_DATA)
+pub struct XferData;
 
-        let mut r3 = [0u8; 20];
-        r3.copy_from_slice(&data[36..56]);
+impl XferData {
+    pub const HEADER_SIZE: usize = 1 + 2; // cmd + data_size
 
-        let number_of_keys = data[56];
+    /// Frame one chunk of patch data
+    pub fn to_bytes(data: &[u8]) -> Vec<u8> {
+        let mut buf = ByteBuffer::with_capacity(Self::HEADER_SIZE + data.len());
+        buf.write_u8(AuthCmd::XferData as u8);
+        buf.write_u16(data.len() as u16);
+        buf.append(data);
+        buf.contents().to_vec()
+    }
+}
 
-        Some(AuthReconnectProofClient {
-            r1,
-            r2,
-            r3,
-            number_of_keys,
+/// Resume offset received from client (CMD_XFER_RESUME)
+#[derive(Debug, Clone)]
+pub struct XferResume {
+    pub offset: u64,
+}
+
+impl XferResume {
+    pub const SIZE: usize = 8; // cmd already read
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ParseError> {
+        const PACKET: &str = "xfer resume";
+        let mut buf = packet_buffer(PACKET, data, Self::SIZE)?;
+        Ok(XferResume {
+            offset: buf
+                .read_u64()
+                .map_err(|_| ParseError::Size { packet: PACKET, len: data.len(), expected: Self::SIZE })?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Challenge body of a 2.4.3 client on Windows for `username`
+    fn challenge_body(username: &[u8]) -> Vec<u8> {
+        let mut buf = ByteBuffer::new();
+        buf.append(b"WoW\0");
+        buf.append(&[2, 4, 3]);
+        buf.write_u16(8606);
+        buf.append(b"68x\0");
+        buf.append(b"niW\0");
+        buf.append(b"SUne");
+        buf.write_u32(60);
+        buf.write_u32(0x0100007F);
+        buf.write_u8(username.len() as u8);
+        buf.append(username);
+        buf.contents().to_vec()
+    }
+
+    #[test]
+    fn test_challenge_body() {
+        let body = AuthLogonChallengeBody::from_bytes(&challenge_body(b"ALICE")).unwrap();
+        assert_eq!(body.build, 8606);
+        assert_eq!((body.platform.as_str(), body.os.as_str(), body.locale.as_str()), ("x86", "Win", "enUS"));
+        assert_eq!(body.username, "ALICE");
+    }
+
+    #[test]
+    fn test_announced_size_too_large() {
+        let mut header = vec![0u8];
+        header.extend_from_slice(&(AuthLogonChallengeBody::MAX_SIZE as u16 + 1).to_le_bytes());
+        let header = AuthLogonChallengeHeader::from_bytes(&header).unwrap();
+        let size = AuthLogonChallengeBody::MAX_SIZE + 1;
+        assert!(matches!(header.body_size(), Err(ParseError::AnnouncedSize { size: announced, .. }) if announced == size));
+    }
+
+    #[test]
+    fn test_username_len_mismatch() {
+        // One byte more and one byte less than the username length says
+        let mut body = challenge_body(b"ALICE");
+        body.push(b'X');
+        let result = AuthLogonChallengeBody::from_bytes(&body);
+        assert!(matches!(result, Err(ParseError::Size { len, expected, .. }) if len == expected + 1), "{:?}", result);
+        body.truncate(body.len() - 2);
+        let result = AuthLogonChallengeBody::from_bytes(&body);
+        assert!(matches!(result, Err(ParseError::Size { len, expected, .. }) if len + 1 == expected), "{:?}", result);
+    }
+
+    #[test]
+    fn test_non_utf8_username() {
+        let result = AuthLogonChallengeBody::from_bytes(&challenge_body(&[b'A', 0xFF, 0xFE]));
+        assert_eq!(result.unwrap_err(), ParseError::InvalidText { packet: "challenge body", field: "username" });
+    }
+
+    #[test]
+    fn test_username_too_long() {
+        let name = vec![b'A'; AUTH_LOGON_MAX_NAME + 1];
+        let result = AuthLogonChallengeBody::from_bytes(&challenge_body(&name));
+        assert_eq!(
+            result.unwrap_err(),
+            ParseError::FieldTooLong {
+                packet: "challenge body",
+                field: "username",
+                len: AUTH_LOGON_MAX_NAME + 1,
+                max: AUTH_LOGON_MAX_NAME
+            }
+        );
+    }
+
+    #[test]
+    fn test_logon_proof_sizes() {
+        let plain = [0u8; AuthLogonProofClient::SIZE_WITHOUT_PIN];
+        assert!(AuthLogonProofClient::from_bytes(&plain, false, false).is_ok());
+        // The PIN data is missing
+        assert!(matches!(AuthLogonProofClient::from_bytes(&plain, true, false), Err(ParseError::Size { .. })));
+
+        let with_matrix = [0u8; AuthLogonProofClient::SIZE_WITHOUT_PIN + AuthLogonProofClient::MATRIX_PROOF_SIZE];
+        let proof = AuthLogonProofClient::from_bytes(&with_matrix, false, true).unwrap();
+        assert!(proof.pin.is_none() && proof.matrix_proof.is_some());
+    }
+}
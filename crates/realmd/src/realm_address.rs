@@ -0,0 +1,112 @@
+// RealmAddress - Host names in realmlist.address
+//
+// realmlist.address and realmlist_local.address may hold a host name instead
+// of an IP address, for servers on a dynamic-DNS name. realmd looks the name
+// up when it sends a realm list and the last answer is older than
+// RealmAddressCacheTime seconds, and gives clients the IPv4 address it got
+// (IPv6 when there is none). When a lookup fails the last address that
+// resolved is kept, so a DNS outage does not take the realms offline.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Time a lookup may take before the last address is used
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Host name and port of a "host:port" realm address, None when the host is an IP address
+fn host_name(address: &str) -> Option<(&str, &str)> {
+    let (host, port) = address.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    (!host.is_empty() && host.parse::<IpAddr>().is_err()).then_some((host, port))
+}
+
+/// Whether a "host:port" realm address has to be looked up
+pub fn is_host_name(address: &str) -> bool {
+    host_name(address).is_some()
+}
+
+/// Last answer for a host name
+struct Lookup {
+    /// Last address the name resolved to, None until it resolved once
+    ip: Option<IpAddr>,
+    checked: Instant,
+}
+
+/// Cache of realm host name lookups
+pub struct AddressResolver {
+    /// How long an answer is reused (zero = look up on every realm list)
+    ttl: Duration,
+    lookups: Mutex<HashMap<String, Lookup>>,
+}
+
+impl AddressResolver {
+    pub fn new(ttl: Duration) -> Self {
+        AddressResolver { ttl, lookups: Mutex::new(HashMap::new()) }
+    }
+
+    /// Look up the host names among `addresses` whose answer expired; true when one
+    /// resolved to another address than before
+    pub async fn refresh<'a>(&self, addresses: impl IntoIterator<Item = &'a str>) -> bool {
+        let mut changed = false;
+        for (host, _) in addresses.into_iter().filter_map(host_name) {
+            let now = Instant::now();
+            {
+                let mut lookups = self.lookups.lock();
+                let lookup = lookups.entry(host.to_string()).or_insert(Lookup { ip: None, checked: now });
+                if lookup.ip.is_some() && now.saturating_duration_since(lookup.checked) < self.ttl {
+                    continue;
+                }
+                // Requests arriving during the lookup keep using the last address
+                lookup.checked = now;
+            }
+
+            let ip = match tokio::time::timeout(LOOKUP_TIMEOUT, tokio::net::lookup_host((host, 0))).await {
+                Ok(Ok(answers)) => {
+                    let answers: Vec<IpAddr> = answers.map(|answer| answer.ip()).collect();
+                    answers.iter().find(|ip| ip.is_ipv4()).or(answers.first()).copied()
+                }
+                Ok(Err(e)) => {
+                    tracing::debug!("Cannot resolve realm address {}: {}", host, e);
+                    None
+                }
+                Err(_) => {
+                    tracing::debug!("No answer for realm address {} within {}ms", host, LOOKUP_TIMEOUT.as_millis());
+                    None
+                }
+            };
+
+            let mut lookups = self.lookups.lock();
+            let Some(lookup) = lookups.get_mut(host) else {
+                continue;
+            };
+            match ip {
+                Some(ip) if lookup.ip != Some(ip) => {
+                    tracing::info!("Realm address {} now resolves to {}", host, ip);
+                    lookup.ip = Some(ip);
+                    changed = true;
+                }
+                Some(_) => {}
+                None => match lookup.ip {
+                    Some(last) => tracing::warn!("Cannot resolve realm address {}, keeping {}", host, last),
+                    None => tracing::warn!("Cannot resolve realm address {}, sending the name", host),
+                },
+            }
+        }
+        changed
+    }
+
+    /// `address` with its host name replaced by the address it last resolved to
+    pub fn resolve(&self, address: &str) -> String {
+        let Some((host, port)) = host_name(address) else {
+            return address.to_string();
+        };
+        match self.lookups.lock().get(host).and_then(|lookup| lookup.ip) {
+            Some(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+            Some(ip) => format!("{}:{}", ip, port),
+            None => address.to_string(),
+        }
+    }
+}
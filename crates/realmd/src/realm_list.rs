@@ -1,13 +1,37 @@
 // RealmList - Server realm management
 // Rust equivalent of RealmList.h/cpp
+//
+// Client builds come from the built-in list, AllowedBuilds and the realmbuilds
+// table. realmbuilds rows with realmid 0 are accepted on every realm; rows for
+// a realm add to the builds it serves (next to the older realmlist.realmbuilds
+// column) and may carry the version shown for that realm. The table is read
+// again on every realm list refresh, so builds change without a restart.
+//
+// The optional realmlist_locale table holds translated realm names. The realm
+// list sent to a client uses the name for the locale it reported in its logon
+// challenge ("deDE", "frFR", ...) and the realmlist name otherwise.
+//
+// Clients connecting from LocalNetworks get the realmlist_local address of a
+// realm instead of realmlist.address. Either may be a host name, looked up
+// when the realm list is sent (see realm_address).
 
+use data_encoding::HEXUPPER;
+use mangos_shared::config::{get_config, Config};
 use mangos_shared::database::{Database, FieldExt};
+use mangos_shared::log::{LOG_TARGET_AUDIT, LOG_TARGET_DB_ERROR};
+use mangos_shared::network::IpNetwork;
+
+use crate::realm_address::{self, AddressResolver};
 use mangos_shared::{AccountTypes, SEC_ADMINISTRATOR, MAX_REALM_ZONES, RealmFlags};
-use parking_lot::RwLock;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Build information for supported client versions
 #[derive(Debug, Clone)]
@@ -22,100 +46,335 @@ pub struct RealmBuildInfo {
     pub mac_hash: [u8; 20],
 }
 
-/// Supported client builds (matching ExpectedRealmdClientBuilds in RealmList.cpp)
-pub static EXPECTED_BUILDS: once_cell::sync::Lazy<Vec<RealmBuildInfo>> =
-    once_cell::sync::Lazy::new(|| {
-        vec![
-            RealmBuildInfo {
-                build: 13930, major_version: 3, minor_version: 3, bugfix_version: 5,
-                hotfix_version: 'a', windows_hash: [0; 20], mac_hash: [0; 20],
-            },
-            RealmBuildInfo {
-                build: 12340, major_version: 3, minor_version: 3, bugfix_version: 5,
-                hotfix_version: 'a',
-                windows_hash: [
-                    0xCD, 0xCB, 0xBD, 0x51, 0x88, 0x31, 0x5E, 0x6B, 0x4D, 0x19,
-                    0x44, 0x9D, 0x49, 0x2D, 0xBC, 0xFA, 0xF1, 0x56, 0xA3, 0x47,
-                ],
-                mac_hash: [
-                    0xB7, 0x06, 0xD1, 0x3F, 0xF2, 0xF4, 0x01, 0x88, 0x39, 0x72,
-                    0x94, 0x61, 0xE3, 0xF8, 0xA0, 0xE2, 0xB5, 0xFD, 0xC0, 0x34,
-                ],
-            },
-            RealmBuildInfo {
-                build: 11723, major_version: 3, minor_version: 3, bugfix_version: 3,
-                hotfix_version: 'a', windows_hash: [0; 20], mac_hash: [0; 20],
-            },
-            RealmBuildInfo {
-                build: 11403, major_version: 3, minor_version: 3, bugfix_version: 2,
-                hotfix_version: ' ', windows_hash: [0; 20], mac_hash: [0; 20],
-            },
-            RealmBuildInfo {
-                build: 11159, major_version: 3, minor_version: 3, bugfix_version: 0,
-                hotfix_version: 'a', windows_hash: [0; 20], mac_hash: [0; 20],
-            },
-            RealmBuildInfo {
-                build: 10505, major_version: 3, minor_version: 2, bugfix_version: 2,
-                hotfix_version: 'a', windows_hash: [0; 20], mac_hash: [0; 20],
-            },
-            RealmBuildInfo {
-                build: 9947, major_version: 3, minor_version: 1, bugfix_version: 3,
-                hotfix_version: ' ', windows_hash: [0; 20], mac_hash: [0; 20],
-            },
-            RealmBuildInfo {
-                build: 8606, major_version: 2, minor_version: 4, bugfix_version: 3,
-                hotfix_version: ' ',
-                windows_hash: [
-                    0x31, 0x9A, 0xFA, 0xA3, 0xF2, 0x55, 0x96, 0x82, 0xF9, 0xFF,
-                    0x65, 0x8B, 0xE0, 0x14, 0x56, 0x25, 0x5F, 0x45, 0x6F, 0xB1,
-                ],
-                mac_hash: [
-                    0xD8, 0xB0, 0xEC, 0xFE, 0x53, 0x4B, 0xC1, 0x13, 0x1E, 0x19,
-                    0xBA, 0xD1, 0xD4, 0xC0, 0xE8, 0x13, 0xEE, 0xE4, 0x99, 0x4F,
-                ],
-            },
-            RealmBuildInfo {
-                build: 6141, major_version: 1, minor_version: 12, bugfix_version: 3,
-                hotfix_version: ' ',
-                windows_hash: [
-                    0xEB, 0x88, 0x24, 0x3E, 0x94, 0x26, 0xC9, 0xD6, 0x8C, 0x81,
-                    0x87, 0xF7, 0xDA, 0xE2, 0x25, 0xEA, 0xF3, 0x88, 0xD8, 0xAF,
-                ],
-                mac_hash: [0; 20],
-            },
-            RealmBuildInfo {
-                build: 6005, major_version: 1, minor_version: 12, bugfix_version: 2,
-                hotfix_version: ' ',
-                windows_hash: [
-                    0x06, 0x97, 0x32, 0x38, 0x76, 0x56, 0x96, 0x41, 0x48, 0x79,
-                    0x28, 0xFD, 0xC7, 0xC9, 0xE3, 0x3B, 0x44, 0x70, 0xC8, 0x80,
-                ],
-                mac_hash: [0; 20],
-            },
-            RealmBuildInfo {
-                build: 5875, major_version: 1, minor_version: 12, bugfix_version: 1,
-                hotfix_version: ' ',
-                windows_hash: [
-                    0x95, 0xED, 0xB2, 0x7C, 0x78, 0x23, 0xB3, 0x63, 0xCB, 0xDD,
-                    0xAB, 0x56, 0xA3, 0x92, 0xE7, 0xCB, 0x73, 0xFC, 0xCA, 0x20,
-                ],
-                mac_hash: [
-                    0x8D, 0x17, 0x3C, 0xC3, 0x81, 0x96, 0x1E, 0xEB, 0xAB, 0xF3,
-                    0x36, 0xF5, 0xE6, 0x67, 0x5B, 0x10, 0x1B, 0xB5, 0x13, 0xE5,
-                ],
-            },
-        ]
-    });
+impl RealmBuildInfo {
+    /// A build without a known version or hashes
+    pub fn unknown(build: u16) -> Self {
+        RealmBuildInfo {
+            build,
+            major_version: 0,
+            minor_version: 0,
+            bugfix_version: 0,
+            hotfix_version: ' ',
+            windows_hash: [0; 20],
+            mac_hash: [0; 20],
+        }
+    }
+}
+
+/// Supported client builds from the built-in list and AllowedBuilds
+static CONFIGURED_BUILDS: Lazy<Vec<RealmBuildInfo>> = Lazy::new(|| {
+    let mut builds = default_builds();
+    let allowed = get_config().lock().get_string_default("AllowedBuilds", "");
+    for entry in allowed.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let Some(info) = parse_build_info(entry) else {
+            tracing::error!("AllowedBuilds: ignoring invalid entry '{}'", entry);
+            continue;
+        };
+        tracing::info!("Accepting client build {} from AllowedBuilds", info.build);
+        merge_build(&mut builds, info);
+    }
+    builds
+});
+
+/// Supported client builds: the configured ones plus the realmbuilds rows for
+/// every realm (realmid 0), replaced on each realm list refresh
+static EXPECTED_BUILDS: Lazy<RwLock<Arc<Vec<RealmBuildInfo>>>> = Lazy::new(|| RwLock::new(Arc::new(CONFIGURED_BUILDS.clone())));
+
+/// Set once a failed realmbuilds query was reported, so refreshes don't repeat it
+static REALMBUILDS_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Set once a failed realmlist_locale query was reported
+static REALMLIST_LOCALE_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Set once a failed realmlist_local query was reported
+static REALMLIST_LOCAL_WARNED: AtomicBool = AtomicBool::new(false);
+
+fn merge_build(builds: &mut Vec<RealmBuildInfo>, info: RealmBuildInfo) {
+    match builds.iter_mut().find(|b| b.build == info.build) {
+        Some(existing) => *existing = info,
+        None => builds.push(info),
+    }
+}
+
+/// Parse an AllowedBuilds entry: `build:major.minor.bugfix[hotfix][:windows_hash[:mac_hash]]`,
+/// hashes as 40 hex digits (omitted or empty = not checked)
+pub fn parse_build_info(entry: &str) -> Option<RealmBuildInfo> {
+    let mut fields = entry.split(':').map(str::trim);
+    let build = fields.next()?.parse().ok()?;
+
+    let mut version = fields.next()?.split('.');
+    let major_version = version.next()?.parse().ok()?;
+    let minor_version = version.next()?.parse().ok()?;
+    let bugfix = version.next()?;
+    if version.next().is_some() {
+        return None;
+    }
+    let (bugfix_version, hotfix_version) = match bugfix.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, c)) if i + c.len_utf8() == bugfix.len() && c.is_ascii_lowercase() => (bugfix[..i].parse().ok()?, c),
+        Some(_) => return None,
+        None => (bugfix.parse().ok()?, ' '),
+    };
+
+    let windows_hash = parse_version_hash(fields.next().unwrap_or(""))?;
+    let mac_hash = parse_version_hash(fields.next().unwrap_or(""))?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(RealmBuildInfo { build, major_version, minor_version, bugfix_version, hotfix_version, windows_hash, mac_hash })
+}
+
+/// 40 hex digits to a version hash; empty means no hash
+fn parse_version_hash(hex: &str) -> Option<[u8; 20]> {
+    let mut hash = [0u8; 20];
+    if hex.is_empty() {
+        return Some(hash);
+    }
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    for (byte, digits) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// Built-in client builds (matching ExpectedRealmdClientBuilds in RealmList.cpp)
+fn default_builds() -> Vec<RealmBuildInfo> {
+    vec![
+        RealmBuildInfo {
+            build: 13930, major_version: 3, minor_version: 3, bugfix_version: 5,
+            hotfix_version: 'a', windows_hash: [0; 20], mac_hash: [0; 20],
+        },
+        RealmBuildInfo {
+            build: 12340, major_version: 3, minor_version: 3, bugfix_version: 5,
+            hotfix_version: 'a',
+            windows_hash: [
+                0xCD, 0xCB, 0xBD, 0x51, 0x88, 0x31, 0x5E, 0x6B, 0x4D, 0x19,
+                0x44, 0x9D, 0x49, 0x2D, 0xBC, 0xFA, 0xF1, 0x56, 0xA3, 0x47,
+            ],
+            mac_hash: [
+                0xB7, 0x06, 0xD1, 0x3F, 0xF2, 0xF4, 0x01, 0x88, 0x39, 0x72,
+                0x94, 0x61, 0xE3, 0xF8, 0xA0, 0xE2, 0xB5, 0xFD, 0xC0, 0x34,
+            ],
+        },
+        RealmBuildInfo {
+            build: 11723, major_version: 3, minor_version: 3, bugfix_version: 3,
+            hotfix_version: 'a', windows_hash: [0; 20], mac_hash: [0; 20],
+        },
+        RealmBuildInfo {
+            build: 11403, major_version: 3, minor_version: 3, bugfix_version: 2,
+            hotfix_version: ' ', windows_hash: [0; 20], mac_hash: [0; 20],
+        },
+        RealmBuildInfo {
+            build: 11159, major_version: 3, minor_version: 3, bugfix_version: 0,
+            hotfix_version: 'a', windows_hash: [0; 20], mac_hash: [0; 20],
+        },
+        RealmBuildInfo {
+            build: 10505, major_version: 3, minor_version: 2, bugfix_version: 2,
+            hotfix_version: 'a', windows_hash: [0; 20], mac_hash: [0; 20],
+        },
+        RealmBuildInfo {
+            build: 9947, major_version: 3, minor_version: 1, bugfix_version: 3,
+            hotfix_version: ' ', windows_hash: [0; 20], mac_hash: [0; 20],
+        },
+        RealmBuildInfo {
+            build: 8606, major_version: 2, minor_version: 4, bugfix_version: 3,
+            hotfix_version: ' ',
+            windows_hash: [
+                0x31, 0x9A, 0xFA, 0xA3, 0xF2, 0x55, 0x96, 0x82, 0xF9, 0xFF,
+                0x65, 0x8B, 0xE0, 0x14, 0x56, 0x25, 0x5F, 0x45, 0x6F, 0xB1,
+            ],
+            mac_hash: [
+                0xD8, 0xB0, 0xEC, 0xFE, 0x53, 0x4B, 0xC1, 0x13, 0x1E, 0x19,
+                0xBA, 0xD1, 0xD4, 0xC0, 0xE8, 0x13, 0xEE, 0xE4, 0x99, 0x4F,
+            ],
+        },
+        RealmBuildInfo {
+            build: 6141, major_version: 1, minor_version: 12, bugfix_version: 3,
+            hotfix_version: ' ',
+            windows_hash: [
+                0xEB, 0x88, 0x24, 0x3E, 0x94, 0x26, 0xC9, 0xD6, 0x8C, 0x81,
+                0x87, 0xF7, 0xDA, 0xE2, 0x25, 0xEA, 0xF3, 0x88, 0xD8, 0xAF,
+            ],
+            mac_hash: [0; 20],
+        },
+        RealmBuildInfo {
+            build: 6005, major_version: 1, minor_version: 12, bugfix_version: 2,
+            hotfix_version: ' ',
+            windows_hash: [
+                0x06, 0x97, 0x32, 0x38, 0x76, 0x56, 0x96, 0x41, 0x48, 0x79,
+                0x28, 0xFD, 0xC7, 0xC9, 0xE3, 0x3B, 0x44, 0x70, 0xC8, 0x80,
+            ],
+            mac_hash: [0; 20],
+        },
+        RealmBuildInfo {
+            build: 5875, major_version: 1, minor_version: 12, bugfix_version: 1,
+            hotfix_version: ' ',
+            windows_hash: [
+                0x95, 0xED, 0xB2, 0x7C, 0x78, 0x23, 0xB3, 0x63, 0xCB, 0xDD,
+                0xAB, 0x56, 0xA3, 0x92, 0xE7, 0xCB, 0x73, 0xFC, 0xCA, 0x20,
+            ],
+            mac_hash: [
+                0x8D, 0x17, 0x3C, 0xC3, 0x81, 0x96, 0x1E, 0xEB, 0xAB, 0xF3,
+                0x36, 0xF5, 0xE6, 0x67, 0x5B, 0x10, 0x1B, 0xB5, 0x13, 0xE5,
+            ],
+        },
+    ]
+}
 
 /// Find build info for a given client build number
-pub fn find_build_info(build: u16) -> Option<&'static RealmBuildInfo> {
+pub fn find_build_info(build: u16) -> Option<RealmBuildInfo> {
+    let builds = EXPECTED_BUILDS.read().clone();
+    // Explicit equal check first, so builds added above the range keep their own info
+    if let Some(info) = builds.iter().find(|b| b.build == build) {
+        return Some(info.clone());
+    }
+
     // First build is low bound of always accepted range
-    if build >= EXPECTED_BUILDS[0].build {
-        return Some(&EXPECTED_BUILDS[0]);
+    (build >= builds[0].build).then(|| builds[0].clone())
+}
+
+/// Load the realmbuilds table, by realm id; None when it cannot be read
+async fn load_realm_builds(db: &Database) -> Option<HashMap<u32, Vec<RealmBuildInfo>>> {
+    let sql = "SELECT realmid, build, \
+               CAST(major_version AS SIGNED), CAST(minor_version AS SIGNED), CAST(bugfix_version AS SIGNED), \
+               hotfix_version, windows_hash, mac_hash \
+               FROM realmbuilds ORDER BY realmid, build";
+    let rows = match db.query(sql).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            if !REALMBUILDS_WARNED.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    target: LOG_TARGET_DB_ERROR,
+                    "Cannot read realmbuilds, using realmlist.realmbuilds and AllowedBuilds only: {}",
+                    e
+                );
+            }
+            return None;
+        }
+    };
+    REALMBUILDS_WARNED.store(false, Ordering::Relaxed);
+
+    let mut builds: HashMap<u32, Vec<RealmBuildInfo>> = HashMap::new();
+    for row in &rows {
+        let realm_id = row.get_u32(0);
+        let Ok(build) = u16::try_from(row.get_u32(1)) else {
+            tracing::error!("realmbuilds: ignoring invalid build {} for realm {}", row.get_u32(1), realm_id);
+            continue;
+        };
+        let hashes = (parse_version_hash(row.get_string(6).trim()), parse_version_hash(row.get_string(7).trim()));
+        let (Some(windows_hash), Some(mac_hash)) = hashes else {
+            tracing::error!("realmbuilds: ignoring build {} for realm {}, hashes must be 40 hex digits", build, realm_id);
+            continue;
+        };
+        builds.entry(realm_id).or_default().push(RealmBuildInfo {
+            build,
+            major_version: row.get_u8(2),
+            minor_version: row.get_u8(3),
+            bugfix_version: row.get_u8(4),
+            hotfix_version: row.get_string(5).chars().next().unwrap_or(' '),
+            windows_hash,
+            mac_hash,
+        });
+    }
+    Some(builds)
+}
+
+/// Load the realmlist_locale table: translated names by realm id, then locale;
+/// None when it cannot be read
+async fn load_realm_names(db: &Database) -> Option<HashMap<u32, HashMap<String, String>>> {
+    let sql = "SELECT realmid, locale, name FROM realmlist_locale";
+    let rows = match db.query(sql).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            if !REALMLIST_LOCALE_WARNED.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    target: LOG_TARGET_DB_ERROR,
+                    "Cannot read realmlist_locale, realm names are not translated: {}",
+                    e
+                );
+            }
+            return None;
+        }
+    };
+    REALMLIST_LOCALE_WARNED.store(false, Ordering::Relaxed);
+
+    let mut names: HashMap<u32, HashMap<String, String>> = HashMap::new();
+    for row in &rows {
+        let realm_id = row.get_u32(0);
+        let locale = row.get_string(1).trim().to_string();
+        let name = row.get_string(2);
+        if locale.is_empty() || name.is_empty() {
+            tracing::error!("realmlist_locale: ignoring empty locale or name for realm {}", realm_id);
+            continue;
+        }
+        names.entry(realm_id).or_default().insert(locale, name);
     }
+    Some(names)
+}
+
+/// Load the realmlist_local table: "host:port" for LAN clients by realm id,
+/// the realm's own port when none is set; None when it cannot be read
+async fn load_local_addresses(db: &Database) -> Option<HashMap<u32, (String, u32)>> {
+    let sql = "SELECT realmid, address, port FROM realmlist_local";
+    let rows = match db.query(sql).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            if !REALMLIST_LOCAL_WARNED.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    target: LOG_TARGET_DB_ERROR,
+                    "Cannot read realmlist_local, every client gets the public realm address: {}",
+                    e
+                );
+            }
+            return None;
+        }
+    };
+    REALMLIST_LOCAL_WARNED.store(false, Ordering::Relaxed);
+
+    let mut addresses = HashMap::new();
+    for row in &rows {
+        let realm_id = row.get_u32(0);
+        let address = row.get_string(1).trim().to_string();
+        if address.is_empty() {
+            tracing::error!("realmlist_local: ignoring empty address for realm {}", realm_id);
+            continue;
+        }
+        addresses.insert(realm_id, (address, row.get_u32(2)));
+    }
+    Some(addresses)
+}
 
-    // Continue from 1 with explicit equal check
-    EXPECTED_BUILDS.iter().skip(1).find(|b| b.build == build)
+/// Accept `info.build` on realm `realm_id` (0 = every realm), replacing an existing entry
+pub async fn add_build(db: &Database, realm_id: u32, info: &RealmBuildInfo, actor: &str) -> anyhow::Result<()> {
+    let hash = |hash: &[u8; 20]| if *hash == [0; 20] { String::new() } else { HEXUPPER.encode(hash) };
+    db.execute(&format!(
+        "REPLACE INTO realmbuilds (realmid, build, major_version, minor_version, bugfix_version, hotfix_version, windows_hash, mac_hash) \
+         VALUES ('{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}')",
+        realm_id,
+        info.build,
+        info.major_version,
+        info.minor_version,
+        info.bugfix_version,
+        Database::escape_string(info.hotfix_version.to_string().trim()),
+        hash(&info.windows_hash),
+        hash(&info.mac_hash)
+    ))
+    .await?;
+    tracing::info!(target: LOG_TARGET_AUDIT, "Build {} added to realm {} by '{}'", info.build, realm_id, actor);
+    Ok(())
+}
+
+/// Stop accepting `build` on realm `realm_id`; returns the number of rows removed
+pub async fn remove_build(db: &Database, realm_id: u32, build: u16, actor: &str) -> anyhow::Result<u64> {
+    let removed = db
+        .execute(&format!("DELETE FROM realmbuilds WHERE realmid = '{}' AND build = '{}'", realm_id, build))
+        .await?;
+    if removed > 0 {
+        tracing::info!(target: LOG_TARGET_AUDIT, "Build {} removed from realm {} by '{}'", build, realm_id, actor);
+    }
+    Ok(removed)
 }
 
 /// Realm category ID mapping tables by version and zone
@@ -130,7 +389,39 @@ static REALM_CATEGORY_IDS: [[u8; MAX_REALM_ZONES]; 4] = [
     [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37],
 ];
 
+/// Categories from RealmCategories, by (client major version or None for all, timezone)
+static CATEGORY_OVERRIDES: Lazy<HashMap<(Option<u8>, u8), u8>> = Lazy::new(|| {
+    let mut overrides = HashMap::new();
+    let configured = get_config().lock().get_string_default("RealmCategories", "");
+    for entry in configured.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        match parse_category_entry(entry) {
+            Some((key, category)) => {
+                overrides.insert(key, category);
+            }
+            None => tracing::error!("RealmCategories: ignoring invalid entry '{}'", entry),
+        }
+    }
+    overrides
+});
+
+/// Parse a RealmCategories entry, "[version:]timezone=category"
+fn parse_category_entry(entry: &str) -> Option<((Option<u8>, u8), u8)> {
+    let (zone, category) = entry.split_once('=')?;
+    let (version, zone) = match zone.split_once(':') {
+        Some((version, zone)) => (Some(version.trim().parse().ok()?), zone),
+        None => (None, zone),
+    };
+    let zone: u8 = zone.trim().parse().ok()?;
+    if zone as usize >= MAX_REALM_ZONES {
+        return None;
+    }
+    Some(((version, zone), category.trim().parse().ok()?))
+}
+
 /// Get the realm category ID for a given build and timezone
+///
+/// RealmCategories entries for the client's major version win over entries
+/// for every version, which win over the built-in tables.
 pub fn get_realm_category_id(build: u16, timezone: u8) -> u8 {
     let zone = if (timezone as usize) >= MAX_REALM_ZONES {
         1 // REALM_ZONE_DEVELOPMENT
@@ -138,8 +429,16 @@ pub fn get_realm_category_id(build: u16, timezone: u8) -> u8 {
         timezone as usize
     };
 
-    match find_build_info(build) {
-        Some(info) => REALM_CATEGORY_IDS[info.major_version as usize][zone],
+    let major_version = find_build_info(build).map(|info| info.major_version);
+    let configured = major_version
+        .and_then(|version| CATEGORY_OVERRIDES.get(&(Some(version), zone as u8)))
+        .or_else(|| CATEGORY_OVERRIDES.get(&(None, zone as u8)));
+    if let Some(&category) = configured {
+        return category;
+    }
+
+    match major_version.and_then(|version| REALM_CATEGORY_IDS.get(version as usize)) {
+        Some(categories) => categories[zone],
         None => zone as u8,
     }
 }
@@ -149,6 +448,8 @@ pub fn get_realm_category_id(build: u16, timezone: u8) -> u8 {
 pub struct Realm {
     pub id: u32,
     pub address: String,  // "host:port"
+    /// "host:port" given to clients from LocalNetworks instead of `address`
+    pub local_address: Option<String>,
     pub icon: u8,
     pub realm_flags: u8,
     pub timezone: u8,
@@ -156,6 +457,10 @@ pub struct Realm {
     pub population_level: f32,
     pub realm_builds: BTreeSet<u32>,
     pub realm_build_info: RealmBuildInfo,
+    /// Versions from realmbuilds shown for this realm instead of the global ones, by build
+    pub build_versions: BTreeMap<u16, RealmBuildInfo>,
+    /// Translated names from realmlist_locale, by client locale
+    pub localized_names: HashMap<String, String>,
     /// In-memory only: unix timestamp when realm DB data last changed (heartbeat)
     pub last_seen_alive: i64,
     /// In-memory only: population from previous poll (for change detection)
@@ -164,71 +469,281 @@ pub struct Realm {
     pub prev_realm_flags: u8,
 }
 
+impl Realm {
+    /// Version info for `build` on this realm: its own realmbuilds entry, else the global one
+    pub fn build_info(&self, build: u16) -> Option<RealmBuildInfo> {
+        self.build_versions.get(&build).cloned().or_else(|| find_build_info(build))
+    }
+
+    /// Name shown to a client with `locale`, `name` when there is no translation
+    pub fn localized_name<'a>(&'a self, name: &'a str, locale: &str) -> &'a str {
+        self.localized_names.get(locale).map_or(name, String::as_str)
+    }
+
+    /// Address a client connects to, the LAN one for `local` clients when there is one
+    pub fn address_for(&self, local: bool) -> &str {
+        match &self.local_address {
+            Some(address) if local => address,
+            _ => &self.address,
+        }
+    }
+}
+
+/// Short-lived cache of realmcharacters counts per (account, realm)
+///
+/// A realm list request needs the count for every realm; on a miss all counts
+/// of the account are fetched with one query. Cleared on every realm list refresh.
+pub struct CharCountCache {
+    /// How long a count is reused (zero = always query)
+    ttl: Duration,
+    counts: Mutex<HashMap<(u32, u32), (u8, Instant)>>,
+}
+
+impl CharCountCache {
+    pub fn new(ttl: Duration) -> Self {
+        CharCountCache { ttl, counts: Mutex::new(HashMap::new()) }
+    }
+
+    /// Character counts of `account_id` on each of `realm_ids`
+    pub async fn counts(&self, db: &Database, account_id: u32, realm_ids: &[u32]) -> HashMap<u32, u8> {
+        let now = Instant::now();
+        if !self.ttl.is_zero() {
+            let counts = self.counts.lock();
+            let cached: Option<HashMap<u32, u8>> = realm_ids
+                .iter()
+                .map(|&realm_id| {
+                    counts
+                        .get(&(account_id, realm_id))
+                        .filter(|(_, at)| now.saturating_duration_since(*at) < self.ttl)
+                        .map(|&(count, _)| (realm_id, count))
+                })
+                .collect();
+            if let Some(cached) = cached {
+                return cached;
+            }
+        }
+
+        let sql = format!(
+            "SELECT realmid, CAST(numchars AS SIGNED) AS numchars FROM realmcharacters WHERE acctid = '{}'",
+            account_id
+        );
+        let stored: HashMap<u32, u8> = match db.query(&sql).await {
+            Ok(rows) => rows.iter().map(|row| (row.get_u32(0), row.get_u8(1))).collect(),
+            Err(e) => {
+                tracing::error!(target: LOG_TARGET_DB_ERROR, "Failed to query character counts: {}", e);
+                return realm_ids.iter().map(|&realm_id| (realm_id, 0)).collect();
+            }
+        };
+
+        let result: HashMap<u32, u8> = realm_ids
+            .iter()
+            .map(|&realm_id| (realm_id, stored.get(&realm_id).copied().unwrap_or(0)))
+            .collect();
+        if !self.ttl.is_zero() {
+            let mut counts = self.counts.lock();
+            counts.extend(result.iter().map(|(&realm_id, &count)| ((account_id, realm_id), (count, now))));
+        }
+        result
+    }
+
+    /// Forget all cached counts
+    pub fn clear(&self) {
+        self.counts.lock().clear();
+    }
+}
+
+/// A serialized realm list response, with the character count bytes left at 0
+#[derive(Debug, Default)]
+pub struct RealmListPacket {
+    pub bytes: Vec<u8>,
+    /// Offset of each realm's character count byte, with the realm id
+    pub char_count_offsets: Vec<(usize, u32)>,
+}
+
+impl RealmListPacket {
+    /// Ids of the realms in the packet
+    pub fn realm_ids(&self) -> Vec<u32> {
+        self.char_count_offsets.iter().map(|&(_, realm_id)| realm_id).collect()
+    }
+
+    /// The packet bytes with one account's character counts patched in
+    pub fn with_char_counts(&self, counts: &HashMap<u32, u8>) -> Vec<u8> {
+        let mut bytes = self.bytes.clone();
+        for &(offset, realm_id) in &self.char_count_offsets {
+            bytes[offset] = counts.get(&realm_id).copied().unwrap_or(0);
+        }
+        bytes
+    }
+}
+
+/// Kind of client a serialized realm list is for: build, gmlevel, session
+/// security level, locale and whether it connects from LocalNetworks
+type PacketKey = (u16, u8, AccountTypes, String, bool);
+
+/// One loaded realm list, with the packets serialized from it
+struct RealmSnapshot {
+    realms: BTreeMap<String, Realm>,
+    /// Locales with a translated name for at least one realm
+    locales: BTreeSet<String>,
+    /// Whether any realm has a LAN address
+    has_local_addresses: bool,
+    /// Realm addresses holding a host name
+    host_names: Vec<String>,
+    /// Serialized realm lists per kind of client
+    packets: Mutex<HashMap<PacketKey, Arc<RealmListPacket>>>,
+}
+
+impl RealmSnapshot {
+    fn new(realms: BTreeMap<String, Realm>) -> Self {
+        let locales = realms.values().flat_map(|r| r.localized_names.keys().cloned()).collect();
+        let has_local_addresses = realms.values().any(|r| r.local_address.is_some());
+        let host_names = realms
+            .values()
+            .flat_map(|r| std::iter::once(&r.address).chain(r.local_address.as_ref()))
+            .filter(|address| realm_address::is_host_name(address))
+            .cloned()
+            .collect();
+        RealmSnapshot { realms, locales, has_local_addresses, host_names, packets: Mutex::new(HashMap::new()) }
+    }
+}
+
+/// Private, loopback and link-local networks, which get the realmlist_local addresses
+const DEFAULT_LOCAL_NETWORKS: &str = "127.0.0.0/8 10.0.0.0/8 172.16.0.0/12 192.168.0.0/16 169.254.0.0/16 ::1 fc00::/7 fe80::/10";
+
+/// Realm list settings read from the config file
+pub struct RealmListSettings {
+    /// Seconds between refreshes from the database (0 = never)
+    pub update_interval: u32,
+    pub stale_timeout: i64,
+    pub heartbeat_timeout: i64,
+    pub char_count_cache_time: Duration,
+    pub local_networks: Vec<IpNetwork>,
+    /// How long a realm host name lookup is reused
+    pub address_cache_time: Duration,
+    /// Name of an offline entry added to every realm list, None for no entry
+    pub announcement: Option<String>,
+}
+
+impl RealmListSettings {
+    pub fn from_config(config: &Config) -> Self {
+        let local_networks = config
+            .get_string_default("LocalNetworks", DEFAULT_LOCAL_NETWORKS)
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let network = IpNetwork::parse(entry);
+                if network.is_none() {
+                    tracing::warn!("Ignoring invalid LocalNetworks entry '{}'", entry);
+                }
+                network
+            })
+            .collect();
+        RealmListSettings {
+            update_interval: config.get_int_default("RealmsStateUpdateDelay", 20) as u32,
+            stale_timeout: config.get_int_default("RealmStaleTimeout", 60) as i64,
+            heartbeat_timeout: config.get_int_default("RealmHeartbeatTimeout", 1500).max(0) as i64,
+            char_count_cache_time: Duration::from_secs(config.get_int_default("CharacterCountCacheTime", 10).max(0) as u64),
+            local_networks,
+            address_cache_time: Duration::from_secs(config.get_int_default("RealmAddressCacheTime", 300).max(0) as u64),
+            announcement: Some(config.get_string("AnnouncementRealm").trim().to_string()).filter(|name| !name.is_empty()),
+        }
+    }
+}
+
 /// The realm list manager
-/// Thread-safe singleton managing the collection of available realms
+/// Thread-safe singleton managing the collection of available realms. A background
+/// task refreshes it every update interval by swapping in a new snapshot, so
+/// requests never wait for the database.
 pub struct RealmList {
-    realms: Arc<RwLock<BTreeMap<String, Realm>>>,
+    snapshot: RwLock<Arc<RealmSnapshot>>,
     update_interval: u32,
-    next_update_time: i64,
     /// Seconds without DB changes before a realm is considered stale (0 = disabled)
     stale_timeout: i64,
+    /// Seconds without an uptime table update before a realm is shown offline (0 = disabled)
+    heartbeat_timeout: i64,
+    char_counts: Arc<CharCountCache>,
+    /// Client networks given the realmlist_local addresses
+    local_networks: Vec<IpNetwork>,
+    resolver: AddressResolver,
+    announcement: Option<String>,
 }
 
 impl RealmList {
     pub fn new() -> Self {
         RealmList {
-            realms: Arc::new(RwLock::new(BTreeMap::new())),
+            snapshot: RwLock::new(Arc::new(RealmSnapshot::new(BTreeMap::new()))),
             update_interval: 0,
-            next_update_time: 0,
             stale_timeout: 0,
+            heartbeat_timeout: 0,
+            char_counts: Arc::new(CharCountCache::new(Duration::ZERO)),
+            local_networks: Vec::new(),
+            resolver: AddressResolver::new(Duration::ZERO),
+            announcement: None,
         }
     }
 
     /// Initialize the realm list with periodic update interval
-    pub async fn initialize(&mut self, update_interval: u32, stale_timeout: i64, db: &Database) {
+    pub async fn initialize(&mut self, settings: RealmListSettings, db: &Database) {
+        let RealmListSettings {
+            update_interval,
+            stale_timeout,
+            heartbeat_timeout,
+            char_count_cache_time,
+            local_networks,
+            address_cache_time,
+            announcement,
+        } = settings;
         tracing::debug!(
-            "Initializing realm list (update interval: {}s, stale timeout: {}s{})",
+            "Initializing realm list (update interval: {}s, stale timeout: {}s{}, heartbeat timeout: {}s{})",
             update_interval,
             stale_timeout,
-            if stale_timeout == 0 { " (disabled)" } else { "" }
+            if stale_timeout == 0 { " (disabled)" } else { "" },
+            heartbeat_timeout,
+            if heartbeat_timeout == 0 { " (disabled)" } else { "" }
         );
         self.update_interval = update_interval;
         self.stale_timeout = stale_timeout;
-        let empty = BTreeMap::new();
-        self.update_realms(db, true, &empty).await;
-    }
-
-    /// Update the realm list if the update interval has passed
-    pub async fn update_if_needed(&mut self, db: &Database) {
-        if self.update_interval == 0 {
-            return;
+        self.heartbeat_timeout = heartbeat_timeout;
+        self.char_counts = Arc::new(CharCountCache::new(char_count_cache_time));
+        self.local_networks = local_networks;
+        self.resolver = AddressResolver::new(address_cache_time);
+        self.announcement = announcement;
+        if let Some(realms) = self.update_realms(db, true, &BTreeMap::new()).await {
+            *self.snapshot.get_mut() = Arc::new(RealmSnapshot::new(realms));
         }
+    }
 
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+    /// Seconds between background refreshes (0 = never refreshed)
+    pub fn update_interval(&self) -> u32 {
+        self.update_interval
+    }
 
-        if self.next_update_time > now {
-            return;
-        }
+    /// Name of the offline entry listed after the realms (AnnouncementRealm)
+    pub fn announcement(&self) -> Option<&str> {
+        self.announcement.as_deref()
+    }
 
+    /// Reload the realms from the database and swap in the new list
+    ///
+    /// On a database error the current list is kept.
+    pub async fn refresh(&self, db: &Database) {
         tracing::debug!("Realm list update interval expired, refreshing from database");
-        self.next_update_time = now + self.update_interval as i64;
 
-        // Snapshot old realm heartbeat data before clearing
-        let old_realms = self.realms.read().clone();
-        self.realms.write().clear();
-        self.update_realms(db, false, &old_realms).await;
+        // The old snapshot carries the heartbeat data
+        let old = self.snapshot.read().clone();
+        if let Some(realms) = self.update_realms(db, false, &old.realms).await {
+            *self.snapshot.write() = Arc::new(RealmSnapshot::new(realms));
+            self.char_counts.clear();
+        }
     }
 
     /// Load realms from the database
     async fn update_realms(
-        &mut self,
+        &self,
         db: &Database,
         init: bool,
         old_realms: &BTreeMap<String, Realm>,
-    ) {
+    ) -> Option<BTreeMap<String, Realm>> {
         tracing::debug!("Updating Realm List...");
 
         let now = SystemTime::now()
@@ -241,9 +756,24 @@ impl RealmList {
                    CAST(realmflags AS SIGNED) AS realmflags, \
                    CAST(timezone AS SIGNED) AS timezone, \
                    CAST(allowedSecurityLevel AS SIGNED) AS allowedSecurityLevel, \
-                   population, realmbuilds \
+                   population, realmbuilds, \
+                   CAST(COALESCE((SELECT MAX(starttime + uptime) FROM uptime \
+                   WHERE uptime.realmid = realmlist.id), 0) AS SIGNED) AS heartbeat \
                    FROM realmlist WHERE (realmflags & 1) = 0 ORDER BY name";
 
+        // Builds for every realm replace the previous ones; on a read error the
+        // previous global list stays and realms fall back to realmlist.realmbuilds
+        let mut db_builds = load_realm_builds(db).await.unwrap_or_default();
+        let mut global_builds = CONFIGURED_BUILDS.clone();
+        for info in db_builds.remove(&0).unwrap_or_default() {
+            merge_build(&mut global_builds, info);
+        }
+        *EXPECTED_BUILDS.write() = Arc::new(global_builds);
+
+        let mut db_names = load_realm_names(db).await.unwrap_or_default();
+        let mut db_local_addresses = load_local_addresses(db).await.unwrap_or_default();
+
+        let mut realms = BTreeMap::new();
         match db.query(sql).await {
             Ok(rows) => {
                 tracing::debug!("Realm query returned {} row(s)", rows.len());
@@ -258,6 +788,8 @@ impl RealmList {
                     let allowed_security_level: u8 = row.get_u8(7);
                     let population: f32 = row.get_f32(8);
                     let builds_str: String = row.get_string(9);
+                    // Last uptime table update by the worldserver, 0 if it never wrote one
+                    let heartbeat: i64 = row.get_i64(10);
 
                     if id == 0 {
                         tracing::error!("Realm ID must be > 0 for {}", name);
@@ -296,32 +828,22 @@ impl RealmList {
                         }
                     }
 
-                    // Get build info for the first supported build
-                    let first_build = realm_builds.iter().next().copied().unwrap_or(0);
-                    let build_info = if first_build > 0 {
-                        find_build_info(first_build as u16)
-                            .filter(|b| b.build == first_build as u16)
-                            .cloned()
-                            .unwrap_or(RealmBuildInfo {
-                                build: first_build as u16,
-                                major_version: 0,
-                                minor_version: 0,
-                                bugfix_version: 0,
-                                hotfix_version: ' ',
-                                windows_hash: [0; 20],
-                                mac_hash: [0; 20],
-                            })
-                    } else {
-                        RealmBuildInfo {
-                            build: 0,
-                            major_version: 0,
-                            minor_version: 0,
-                            bugfix_version: 0,
-                            hotfix_version: ' ',
-                            windows_hash: [0; 20],
-                            mac_hash: [0; 20],
+                    // realmbuilds rows add builds, with a version to show when one is set
+                    let mut build_versions = BTreeMap::new();
+                    for info in db_builds.remove(&id).unwrap_or_default() {
+                        realm_builds.insert(u32::from(info.build));
+                        if info.major_version > 0 {
+                            build_versions.insert(info.build, info);
                         }
-                    };
+                    }
+
+                    // Get build info for the first supported build
+                    let first_build = realm_builds.iter().next().copied().unwrap_or(0) as u16;
+                    let build_info = build_versions
+                        .get(&first_build)
+                        .cloned()
+                        .or_else(|| find_build_info(first_build).filter(|b| b.build == first_build))
+                        .unwrap_or_else(|| RealmBuildInfo::unknown(first_build));
 
                     // Heartbeat: detect if any DB data changed since last poll
                     let (prev_pop, prev_flags, prev_alive) = match old_realms.get(&name) {
@@ -344,12 +866,28 @@ impl RealmList {
                         realm_flags |= RealmFlags::REALM_FLAG_OFFLINE;
                     }
 
+                    // Heartbeat override: the worldserver stopped updating its uptime row
+                    if self.heartbeat_timeout > 0
+                        && heartbeat > 0
+                        && realm_flags & RealmFlags::REALM_FLAG_OFFLINE == 0
+                        && now - heartbeat > self.heartbeat_timeout
+                    {
+                        tracing::warn!(
+                            "Realm '{}' (id {}) missed its heartbeat (uptime updated {}s ago, timeout {}s), showing as offline",
+                            name, id, now - heartbeat, self.heartbeat_timeout
+                        );
+                        realm_flags |= RealmFlags::REALM_FLAG_OFFLINE;
+                    }
+
                     let full_address = format!("{}:{}", address, port);
+                    let local_address = db_local_addresses
+                        .remove(&id)
+                        .map(|(local, local_port)| format!("{}:{}", local, if local_port > 0 { local_port } else { port }));
 
                     tracing::debug!(
-                        "Realm '{}': id={} address='{}' icon={} flags=0x{:02X} timezone={} \
+                        "Realm '{}': id={} address='{}' local_address='{}' icon={} flags=0x{:02X} timezone={} \
                          security={} population={:.1} builds='{}' alive={}s_ago",
-                        name, id, full_address, icon, realm_flags, timezone,
+                        name, id, full_address, local_address.as_deref().unwrap_or(""), icon, realm_flags, timezone,
                         security_level, population, builds_str,
                         now - last_seen_alive
                     );
@@ -357,6 +895,7 @@ impl RealmList {
                     let realm = Realm {
                         id,
                         address: full_address,
+                        local_address,
                         icon,
                         realm_flags,
                         timezone,
@@ -364,6 +903,8 @@ impl RealmList {
                         population_level: population,
                         realm_builds,
                         realm_build_info: build_info,
+                        build_versions,
+                        localized_names: db_names.remove(&id).unwrap_or_default(),
                         last_seen_alive,
                         prev_population: population,
                         prev_realm_flags: raw_realm_flags,
@@ -373,23 +914,109 @@ impl RealmList {
                         tracing::info!("Added realm id {}, name '{}'", id, name);
                     }
 
-                    self.realms.write().insert(name, realm);
+                    realms.insert(name, realm);
                 }
             }
             Err(e) => {
-                tracing::error!("Failed to query realm list: {}", e);
+                tracing::error!(target: LOG_TARGET_DB_ERROR, "Failed to query realm list: {}", e);
+                return None;
             }
         }
+        Some(realms)
+    }
+
+    /// Realms of the current snapshot, by name
+    pub fn realms(&self) -> BTreeMap<String, Realm> {
+        self.snapshot.read().realms.clone()
+    }
+
+    /// Character count cache, shared with in-flight realm list requests
+    pub fn char_counts(&self) -> Arc<CharCountCache> {
+        self.char_counts.clone()
+    }
+
+    /// The serialized realm list for one kind of client, built by `build_packet` on first use
+    /// after every refresh
+    ///
+    /// `build_packet` gets the client's locale, or "" when no realm has a name
+    /// translated for it, so clients without translations share one packet.
+    pub fn packet(
+        &self,
+        (build, security_level, account_security_level): (u16, u8, AccountTypes),
+        locale: &str,
+        local: bool,
+        build_packet: impl FnOnce(&BTreeMap<String, Realm>, &str, bool) -> RealmListPacket,
+    ) -> Arc<RealmListPacket> {
+        let snapshot = self.snapshot.read().clone();
+        let locale = if snapshot.locales.contains(locale) { locale } else { "" };
+        // LAN clients share the public list unless a realm has a LAN address
+        let local = local && snapshot.has_local_addresses;
+        let key: PacketKey = (build, security_level, account_security_level, locale.to_string(), local);
+        if let Some(packet) = snapshot.packets.lock().get(&key) {
+            return packet.clone();
+        }
+        let packet = Arc::new(self.build_from(&snapshot, locale, local, build_packet));
+        snapshot.packets.lock().insert(key, packet.clone());
+        packet
     }
 
-    /// Get a read lock on the realms
-    pub fn realms(&self) -> parking_lot::RwLockReadGuard<'_, BTreeMap<String, Realm>> {
-        self.realms.read()
+    /// The realm list for one account only, built by `build_packet` on every call
+    ///
+    /// For accounts whose view depends on more than their security level, such
+    /// as per-realm GM levels; `build_packet` gets the same arguments as for
+    /// [`RealmList::packet`].
+    pub fn account_packet(
+        &self,
+        locale: &str,
+        local: bool,
+        build_packet: impl FnOnce(&BTreeMap<String, Realm>, &str, bool) -> RealmListPacket,
+    ) -> Arc<RealmListPacket> {
+        let snapshot = self.snapshot.read().clone();
+        let locale = if snapshot.locales.contains(locale) { locale } else { "" };
+        let local = local && snapshot.has_local_addresses;
+        Arc::new(self.build_from(&snapshot, locale, local, build_packet))
+    }
+
+    fn build_from(
+        &self,
+        snapshot: &RealmSnapshot,
+        locale: &str,
+        local: bool,
+        build_packet: impl FnOnce(&BTreeMap<String, Realm>, &str, bool) -> RealmListPacket,
+    ) -> RealmListPacket {
+        if snapshot.host_names.is_empty() {
+            return build_packet(&snapshot.realms, locale, local);
+        }
+        // Clients get the addresses the host names last resolved to
+        let mut realms = snapshot.realms.clone();
+        for realm in realms.values_mut() {
+            realm.address = self.resolver.resolve(&realm.address);
+            realm.local_address = realm.local_address.as_deref().map(|address| self.resolver.resolve(address));
+        }
+        build_packet(&realms, locale, local)
+    }
+
+    /// Look up the realm host names whose answer expired; packets built with an
+    /// address that changed are dropped
+    pub async fn resolve_addresses(&self) {
+        let snapshot = self.snapshot.read().clone();
+        if snapshot.host_names.is_empty() {
+            return;
+        }
+        if self.resolver.refresh(snapshot.host_names.iter().map(String::as_str)).await {
+            snapshot.packets.lock().clear();
+        }
+    }
+
+    /// Whether a client at `ip` is on LocalNetworks and gets the LAN realm addresses
+    pub fn is_local(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.local_networks.iter().any(|network| network.contains(ip))
     }
 
     /// Get the number of realms
     pub fn size(&self) -> usize {
-        self.realms.read().len()
+        self.snapshot.read().realms.len()
     }
 }
 
@@ -398,3 +1025,33 @@ impl Default for RealmList {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_category_entry() {
+        assert_eq!(parse_category_entry("1=5"), Some(((None, 1), 5)));
+        assert_eq!(parse_category_entry(" 2:8 = 3 "), Some(((Some(2), 8), 3)));
+        let out_of_range = format!("{}=1", MAX_REALM_ZONES);
+        for invalid in ["1", "=1", "1=", "x=1", "1=x", "x:1=1", "1=256", out_of_range.as_str()] {
+            assert_eq!(parse_category_entry(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_realm_category_id() {
+        // Built-in tables by the client's major version (no RealmCategories set)
+        assert_eq!(get_realm_category_id(8606, 1), 1);
+        assert_eq!(get_realm_category_id(8606, 10), 10);
+        assert_eq!(get_realm_category_id(8606, 31), 0);
+        assert_eq!(get_realm_category_id(5875, 3), 5);
+        assert_eq!(get_realm_category_id(5875, 9), 2);
+        assert_eq!(get_realm_category_id(12340, 37), 37);
+        // Unknown timezones count as development realms
+        assert_eq!(get_realm_category_id(8606, MAX_REALM_ZONES as u8), 1);
+        // Builds without a table keep the timezone
+        assert_eq!(get_realm_category_id(4000, 7), 7);
+    }
+}
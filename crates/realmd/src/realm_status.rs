@@ -0,0 +1,108 @@
+// RealmStatus - Public realm status for server websites
+//
+// A read-only HTTP server (RealmStatus.Enabled, RealmStatus.IP,
+// RealmStatus.Port) answering GET /realms without authentication, so a
+// website can render a status widget without querying the database. It
+// returns the name, type, flags, population level and online state of every
+// realm open to players; addresses, GM-only realms and anything about
+// accounts are left out. Responses allow any origin, so browsers can fetch
+// them directly, and may be cached until the next realm list refresh.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+
+use mangos_shared::{RealmFlags, SEC_PLAYER};
+
+use crate::http;
+use crate::realm_list::{Realm, RealmList};
+
+/// Realm type shown by the client for an icon value
+fn realm_type(icon: u8) -> &'static str {
+    match icon {
+        1 => "pvp",
+        6 => "rp",
+        8 => "rppvp",
+        _ => "normal",
+    }
+}
+
+/// Population as the client labels it
+fn population_label(population: f32) -> &'static str {
+    if population >= 2.0 {
+        "high"
+    } else if population >= 1.0 {
+        "medium"
+    } else {
+        "low"
+    }
+}
+
+fn realm_json(name: &str, realm: &Realm) -> Value {
+    let flag = |flag: u8| realm.realm_flags & flag != 0;
+    json!({
+        "id": realm.id,
+        "name": name,
+        "type": realm_type(realm.icon),
+        "timezone": realm.timezone,
+        "flags": realm.realm_flags,
+        "online": !flag(RealmFlags::REALM_FLAG_OFFLINE),
+        "recommended": flag(RealmFlags::REALM_FLAG_RECOMMENDED),
+        "new_players": flag(RealmFlags::REALM_FLAG_NEW_PLAYERS),
+        "population": realm.population_level,
+        "population_level": population_label(realm.population_level),
+    })
+}
+
+/// Shared state of the status server
+pub struct RealmStatus {
+    realm_list: Arc<RealmList>,
+}
+
+impl RealmStatus {
+    pub fn new(realm_list: Arc<RealmList>) -> Self {
+        RealmStatus { realm_list }
+    }
+
+    /// Answer requests until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let status = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = status.handle_connection(stream, peer).await {
+                            tracing::debug!("RealmStatus: request from {} failed: {:#}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    tracing::error!("RealmStatus: failed to accept connection: {}", e);
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream, peer: SocketAddr) -> anyhow::Result<()> {
+        let request = http::read_request(&mut stream).await?;
+        if request.method != "GET" || request.path != "/realms" {
+            return http::write_response(&mut stream, "404 Not Found", "application/json", "{\"error\":\"not found\"}").await;
+        }
+
+        let realms: Vec<Value> = self
+            .realm_list
+            .realms()
+            .iter()
+            .filter(|(_, realm)| realm.allowed_security_level == SEC_PLAYER)
+            .map(|(name, realm)| realm_json(name, realm))
+            .collect();
+        tracing::trace!("RealmStatus: {} realm(s) to {}", realms.len(), peer);
+
+        let cache_control = format!("public, max-age={}", self.realm_list.update_interval());
+        let headers = [("Access-Control-Allow-Origin", "*"), ("Cache-Control", cache_control.as_str())];
+        let body = json!({ "realms": realms });
+        http::write_response_with_headers(&mut stream, "200 OK", &headers, "application/json", &body.to_string()).await
+    }
+}
@@ -0,0 +1,356 @@
+// RestApi - Admin HTTP API for accounts, bans and realms
+//
+// An optional plain HTTP/1.1 server on its own address (RestApi.BindIP and
+// RestApi.Port), so operators can manage accounts without running SQL by hand.
+// Every request needs "Authorization: Bearer <RestApi.Token>". Changes go
+// through AccountMgr and ip_ban and are audited under the name sent in the
+// X-Admin header.
+//
+// That name is whatever the client sends and the token is shared by every
+// administrator, so it cannot tell two of them apart: elevations requested
+// here under GmLevel.DualControl are confirmed with "account confirm gmlevel"
+// over SOAP or with `realmd account --actor <name> confirm gmlevel`, never
+// through this API.
+//
+// Endpoints, with JSON bodies and responses:
+//   GET    /realms
+//   POST   /accounts                    {"username", "password", "expansion"?}
+//   PUT    /accounts/<name>/password    {"password"}
+//   PUT    /accounts/<name>/gmlevel     {"gmlevel"}
+//   PUT    /accounts/<name>/expansion   {"expansion"}
+//   POST   /accounts/<name>/ban         {"duration"?, "reason"?}
+//   DELETE /accounts/<name>/ban
+//   POST   /ip-bans                     {"ip", "duration"?, "reason"?}
+//   DELETE /ip-bans                     {"ip"}
+//   GET    /ip-reputation
+//   GET    /maintenance
+//   PUT    /maintenance                 {"enabled"}
+//   POST   /restart
+//
+// With RestApi.TlsEnabled the API is served over TLS (RestApi.TlsCertificate,
+// RestApi.TlsPrivateKey). Without it the token travels in clear text, so
+// realmd refuses to start unless RestApi.BindIP is a loopback address.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+
+use mangos_shared::auth::constant_time_eq;
+use mangos_shared::database::Database;
+use mangos_shared::network::{IpNetwork, TlsAcceptor};
+use mangos_shared::RealmFlags;
+
+use crate::account_mgr::{self, ChangeSource, GmLevelChange};
+use crate::http::{self, HttpRequest};
+use crate::ip_ban;
+use crate::ip_reputation;
+use crate::maintenance;
+use crate::realm_list::RealmList;
+use crate::shutdown::{self, ShutdownMode};
+
+/// Actor recorded for requests without an X-Admin header
+const DEFAULT_ACTOR: &str = "[REST]";
+
+/// Shared state of the API server
+pub struct RestApi {
+    db: Arc<Database>,
+    realm_list: Arc<RealmList>,
+    token: String,
+    /// None when the API is served in plain HTTP on a loopback address
+    tls: Option<TlsAcceptor>,
+}
+
+/// An API request with its JSON body
+struct Request {
+    method: String,
+    path: String,
+    token: Option<String>,
+    actor: String,
+    body: Value,
+}
+
+impl Request {
+    fn parse(request: HttpRequest) -> anyhow::Result<Self> {
+        let token = request
+            .header("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        let actor = request
+            .header("X-Admin")
+            .filter(|actor| !actor.is_empty())
+            .unwrap_or(DEFAULT_ACTOR)
+            .to_string();
+        let body = if request.body.is_empty() { Value::Null } else { serde_json::from_slice(&request.body)? };
+        Ok(Request { method: request.method, path: request.path, token, actor, body })
+    }
+}
+
+/// Status line and JSON body of a response
+struct Response {
+    status: &'static str,
+    body: Value,
+}
+
+impl Response {
+    fn new(status: &'static str, body: Value) -> Self {
+        Response { status, body }
+    }
+
+    fn error(status: &'static str, message: impl std::fmt::Display) -> Self {
+        Response::new(status, json!({ "error": message.to_string() }))
+    }
+
+    /// 500 for database failures, 400 for everything the request got wrong
+    fn from_error(e: anyhow::Error) -> Self {
+        if e.downcast_ref::<sqlx::Error>().is_some() {
+            tracing::error!("RestApi: database error: {:#}", e);
+            Response::error("500 Internal Server Error", "database error")
+        } else {
+            Response::error("400 Bad Request", format!("{:#}", e))
+        }
+    }
+}
+
+impl RestApi {
+    pub fn new(db: Arc<Database>, realm_list: Arc<RealmList>, token: String, tls: Option<TlsAcceptor>) -> Self {
+        RestApi { db, realm_list, token, tls }
+    }
+
+    /// Accept API clients until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let api = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = api.handle_connection(stream, peer).await {
+                            tracing::debug!("RestApi: request from {} failed: {:#}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    tracing::error!("RestApi: failed to accept connection: {}", e);
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream, peer: SocketAddr) -> anyhow::Result<()> {
+        match &self.tls {
+            Some(tls) => self.handle_stream(tls.accept(stream).await?, peer).await,
+            None => self.handle_stream(stream, peer).await,
+        }
+    }
+
+    async fn handle_stream<S: AsyncRead + AsyncWrite + Unpin>(&self, mut stream: S, peer: SocketAddr) -> anyhow::Result<()> {
+        let request = match http::read_request(&mut stream).await.and_then(Request::parse) {
+            Ok(request) => request,
+            Err(e) => return write_response(&mut stream, Response::error("400 Bad Request", format!("{:#}", e))).await,
+        };
+
+        let authorized = request
+            .token
+            .as_deref()
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), self.token.as_bytes()));
+        let response = if authorized {
+            self.route(&request).await
+        } else {
+            tracing::warn!("RestApi: unauthorized {} {} from {}", request.method, request.path, peer);
+            Response::error("401 Unauthorized", "missing or wrong bearer token")
+        };
+
+        tracing::info!(
+            "RestApi: {} {} by '{}' from {} -> {}",
+            request.method, request.path, request.actor, peer, response.status
+        );
+        write_response(&mut stream, response).await
+    }
+
+    async fn route(&self, req: &Request) -> Response {
+        let segments: Vec<&str> = req.path.trim_matches('/').split('/').collect();
+        let result = match (req.method.as_str(), segments.as_slice()) {
+            ("GET", ["realms"]) => Ok(self.list_realms()),
+            ("POST", ["accounts"]) => self.create_account(req).await,
+            ("POST", ["gmlevel", "confirm"]) => {
+                return Response::error("403 Forbidden", "confirm elevations over SOAP or with realmd account confirm gmlevel");
+            }
+            ("POST", ["ip-bans"]) => self.ban_ip(req).await,
+            ("DELETE", ["ip-bans"]) => self.unban_ip(req).await,
+            ("GET", ["ip-reputation"]) => Ok(Response::new("200 OK", ip_reputation::metrics())),
+            ("GET", ["maintenance"]) => Ok(maintenance_response()),
+            ("PUT", ["maintenance"]) => bool_field(&req.body, "enabled").map(|enabled| {
+                maintenance::set(enabled, &req.actor);
+                maintenance_response()
+            }),
+            ("POST", ["restart"]) => {
+                tracing::info!("Restart requested by {}", req.actor);
+                shutdown::request(ShutdownMode::Restart);
+                Ok(Response::new("202 Accepted", json!({ "restarting": true })))
+            }
+            (_, ["accounts", name, action]) => match account_mgr::find_account_id(&self.db, name).await {
+                Ok(Some(account_id)) => self.account_action(req, account_id, action).await,
+                Ok(None) => return Response::error("404 Not Found", format!("no account named {}", name)),
+                Err(e) => Err(e),
+            },
+            _ => return Response::error("404 Not Found", "unknown endpoint"),
+        };
+        result.unwrap_or_else(Response::from_error)
+    }
+
+    /// Endpoints under /accounts/<name>/
+    async fn account_action(&self, req: &Request, account_id: u32, action: &str) -> anyhow::Result<Response> {
+        let (db, actor, body) = (&*self.db, req.actor.as_str(), &req.body);
+        match (req.method.as_str(), action) {
+            ("PUT", "password") => {
+                account_mgr::set_password(db, account_id, str_field(body, "password")?, actor, ChangeSource::Rest).await?;
+                Ok(Response::new("200 OK", json!({ "id": account_id })))
+            }
+            ("PUT", "gmlevel") => {
+                let gmlevel = u8::try_from(u64_field(body, "gmlevel")?)?;
+                let change = account_mgr::set_gm_level(db, account_id, gmlevel, actor, ChangeSource::Rest).await?;
+                Ok(gm_level_response(account_id, gmlevel, change))
+            }
+            ("PUT", "expansion") => {
+                let expansion = u8::try_from(u64_field(body, "expansion")?)?;
+                account_mgr::set_expansion(db, account_id, expansion, actor, ChangeSource::Rest).await?;
+                Ok(Response::new("200 OK", json!({ "id": account_id, "expansion": expansion })))
+            }
+            ("POST", "ban") => {
+                let duration = opt_u64_field(body, "duration")?.unwrap_or(0);
+                let reason = opt_str_field(body, "reason")?.unwrap_or("");
+                account_mgr::ban_account(db, account_id, duration, reason, actor, ChangeSource::Rest).await?;
+                Ok(Response::new("200 OK", json!({ "id": account_id, "duration": duration })))
+            }
+            ("DELETE", "ban") => {
+                let lifted = account_mgr::unban_account(db, account_id, actor, ChangeSource::Rest).await?;
+                Ok(Response::new("200 OK", json!({ "id": account_id, "lifted": lifted })))
+            }
+            _ => Ok(Response::error("404 Not Found", "unknown endpoint")),
+        }
+    }
+
+    fn list_realms(&self) -> Response {
+        let realms: Vec<Value> = self
+            .realm_list
+            .realms()
+            .iter()
+            .map(|(name, realm)| {
+                json!({
+                    "id": realm.id,
+                    "name": name,
+                    "address": realm.address,
+                    "icon": realm.icon,
+                    "flags": realm.realm_flags,
+                    "online": realm.realm_flags & RealmFlags::REALM_FLAG_OFFLINE == 0,
+                    "timezone": realm.timezone,
+                    "security_level": realm.allowed_security_level,
+                    "population": realm.population_level,
+                    "builds": realm.realm_builds,
+                })
+            })
+            .collect();
+        Response::new("200 OK", json!({ "realms": realms }))
+    }
+
+    async fn create_account(&self, req: &Request) -> anyhow::Result<Response> {
+        let expansion = match opt_u64_field(&req.body, "expansion")? {
+            Some(expansion) => u8::try_from(expansion)?,
+            None => account_mgr::DEFAULT_EXPANSION,
+        };
+        let account_id = account_mgr::create_account(
+            &self.db,
+            str_field(&req.body, "username")?,
+            str_field(&req.body, "password")?,
+            expansion,
+            &req.actor,
+            ChangeSource::Rest,
+        )
+        .await?;
+        Ok(Response::new("201 Created", json!({ "id": account_id })))
+    }
+
+    async fn ban_ip(&self, req: &Request) -> anyhow::Result<Response> {
+        let network = network_field(&req.body)?;
+        let duration = opt_u64_field(&req.body, "duration")?.unwrap_or(0);
+        let reason = opt_str_field(&req.body, "reason")?.unwrap_or("");
+        ip_ban::ban(&self.db, network, duration, &req.actor, reason).await?;
+        Ok(Response::new("200 OK", json!({ "ip": network.canonical().to_string(), "duration": duration })))
+    }
+
+    async fn unban_ip(&self, req: &Request) -> anyhow::Result<Response> {
+        let network = network_field(&req.body)?;
+        let removed = ip_ban::unban(&self.db, network, &req.actor).await?;
+        Ok(Response::new("200 OK", json!({ "ip": network.canonical().to_string(), "removed": removed })))
+    }
+}
+
+fn maintenance_response() -> Response {
+    Response::new(
+        "200 OK",
+        json!({
+            "enabled": maintenance::enabled(),
+            "database_unreachable": maintenance::database_unreachable(),
+            "refusing_logins": maintenance::reason().is_some(),
+        }),
+    )
+}
+
+fn gm_level_response(account_id: u32, gmlevel: u8, change: GmLevelChange) -> Response {
+    match change {
+        GmLevelChange::Applied { old_level } => Response::new(
+            "200 OK",
+            json!({ "id": account_id, "gmlevel": gmlevel, "old_gmlevel": old_level }),
+        ),
+        GmLevelChange::PendingConfirmation { token, expires_at } => Response::new(
+            "202 Accepted",
+            json!({ "id": account_id, "gmlevel": gmlevel, "token": token, "expires_at": expires_at }),
+        ),
+    }
+}
+
+fn opt_str_field<'a>(body: &'a Value, key: &str) -> anyhow::Result<Option<&'a str>> {
+    match body.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("\"{}\" must be a string", key)),
+    }
+}
+
+fn str_field<'a>(body: &'a Value, key: &str) -> anyhow::Result<&'a str> {
+    opt_str_field(body, key)?.ok_or_else(|| anyhow::anyhow!("\"{}\" is required", key))
+}
+
+fn opt_u64_field(body: &Value, key: &str) -> anyhow::Result<Option<u64>> {
+    match body.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("\"{}\" must be a non-negative integer", key)),
+    }
+}
+
+fn u64_field(body: &Value, key: &str) -> anyhow::Result<u64> {
+    opt_u64_field(body, key)?.ok_or_else(|| anyhow::anyhow!("\"{}\" is required", key))
+}
+
+fn bool_field(body: &Value, key: &str) -> anyhow::Result<bool> {
+    match body.get(key) {
+        None | Some(Value::Null) => anyhow::bail!("\"{}\" is required", key),
+        Some(value) => value.as_bool().ok_or_else(|| anyhow::anyhow!("\"{}\" must be true or false", key)),
+    }
+}
+
+fn network_field(body: &Value) -> anyhow::Result<IpNetwork> {
+    let ip = str_field(body, "ip")?;
+    IpNetwork::parse(ip.trim()).ok_or_else(|| anyhow::anyhow!("invalid address or CIDR range '{}'", ip))
+}
+
+async fn write_response<S: AsyncWrite + Unpin>(stream: &mut S, response: Response) -> anyhow::Result<()> {
+    http::write_response(stream, response.status, "application/json", &response.body.to_string()).await
+}
@@ -0,0 +1,174 @@
+// Service - Windows service integration
+// Rust equivalent of ServiceWin32.cpp in the C++ core
+//
+// "realmd -s install" registers realmd with the service control manager,
+// started as "realmd -c <config> -s run"; "-s uninstall" stops and removes it.
+// Under the SCM, "-s run" hands the main thread to the service dispatcher and
+// runs the server on the service thread. Stop and system shutdown requests go
+// through the same graceful shutdown as Ctrl-C, reporting StopPending while
+// sessions drain.
+
+/// Register, remove or run realmd as a Windows service
+#[cfg(not(windows))]
+pub fn execute(_action: crate::ServiceAction, _args: crate::Args) -> anyhow::Result<()> {
+    use mangos_shared::error::MangosError;
+
+    anyhow::bail!(MangosError::Usage("Windows services are only supported on Windows, use --daemon instead".into()))
+}
+
+#[cfg(windows)]
+pub use windows::execute;
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsString;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use windows_service::service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode, ServiceInfo, ServiceStartType,
+        ServiceState, ServiceStatus, ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle};
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    use mangos_shared::config::get_config;
+    use mangos_shared::error::{ExitStatus, MangosError};
+
+    use crate::shutdown::{self, ShutdownMode};
+    use crate::{Args, ServiceAction};
+
+    const SERVICE_NAME: &str = "realmd";
+    const SERVICE_DISPLAY_NAME: &str = "MaNGOS realmd service";
+    const SERVICE_DESCRIPTION: &str = "Massive Network Game Object Server";
+    const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+    /// Time the SCM is told a start or stop may take beyond the grace period
+    const PENDING_WAIT_HINT: Duration = Duration::from_secs(30);
+
+    /// Arguments for the service thread, which the dispatcher starts without them
+    static SERVICE_ARGS: Mutex<Option<Args>> = Mutex::new(None);
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn execute(action: ServiceAction, args: Args) -> anyhow::Result<()> {
+        match action {
+            ServiceAction::Install => install(&args),
+            ServiceAction::Uninstall => uninstall(),
+            ServiceAction::Run => {
+                *SERVICE_ARGS.lock().unwrap_or_else(|e| e.into_inner()) = Some(args);
+                // Blocks until the service has stopped
+                service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(|e| {
+                    anyhow::Error::new(e).context(MangosError::Usage("-s run only works when started by the service control manager".into()))
+                })
+            }
+        }
+    }
+
+    fn install(args: &Args) -> anyhow::Result<()> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)?;
+        // Services start in the system directory, so the config path has to be absolute
+        let config = std::path::absolute(&args.config)?;
+        let info = ServiceInfo {
+            name: OsString::from(SERVICE_NAME),
+            display_name: OsString::from(SERVICE_DISPLAY_NAME),
+            service_type: SERVICE_TYPE,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: std::env::current_exe()?,
+            launch_arguments: vec!["-c".into(), config.into_os_string(), "-s".into(), "run".into()],
+            dependencies: Vec::new(),
+            account_name: None,
+            account_password: None,
+        };
+        let service = manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+        service.set_description(SERVICE_DESCRIPTION)?;
+        println!("Service {} installed", SERVICE_NAME);
+        Ok(())
+    }
+
+    fn uninstall() -> anyhow::Result<()> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+        let service = manager.open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)?;
+        if service.query_status()?.current_state != ServiceState::Stopped {
+            service.stop()?;
+        }
+        // Removed by the SCM once the last handle is closed
+        service.delete()?;
+        println!("Service {} uninstalled", SERVICE_NAME);
+        Ok(())
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let args = SERVICE_ARGS.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(args) = args
+            && let Err(e) = run_service(args)
+        {
+            tracing::error!("Service stopped with an error: {:#}", e);
+        }
+    }
+
+    fn run_service(args: Args) -> anyhow::Result<()> {
+        let status_handle = service_control_handler::register(SERVICE_NAME, |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                shutdown::request(ShutdownMode::Stop);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+        set_state(status_handle, ServiceState::StartPending, ServiceExitCode::Win32(0), PENDING_WAIT_HINT)?;
+
+        // Relative paths in the config (LogsDir, PatchesDir) are relative to the binary
+        if let Some(dir) = std::env::current_exe()?.parent() {
+            std::env::set_current_dir(dir)?;
+        }
+
+        let result = tokio::runtime::Runtime::new()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| {
+                set_state(status_handle, ServiceState::Running, ServiceExitCode::Win32(0), Duration::ZERO)?;
+                runtime.block_on(async {
+                    let server = crate::run(args);
+                    tokio::pin!(server);
+                    tokio::select! {
+                        result = &mut server => return result,
+                        _ = shutdown::wait() => {}
+                    }
+                    let grace_period = get_config().lock().get_int_default("Shutdown.GracePeriod", 30).max(0) as u64;
+                    let wait_hint = Duration::from_secs(grace_period) + PENDING_WAIT_HINT;
+                    set_state(status_handle, ServiceState::StopPending, ServiceExitCode::Win32(0), wait_hint)?;
+                    server.await
+                })
+            });
+
+        let exit_code = match &result {
+            Ok(()) => ServiceExitCode::Win32(0),
+            Err(e) => ServiceExitCode::ServiceSpecific(ExitStatus::from_error(e) as u32),
+        };
+        set_state(status_handle, ServiceState::Stopped, exit_code, Duration::ZERO)?;
+        result
+    }
+
+    fn set_state(
+        status_handle: ServiceStatusHandle,
+        state: ServiceState,
+        exit_code: ServiceExitCode,
+        wait_hint: Duration,
+    ) -> windows_service::Result<()> {
+        let controls_accepted = match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+        status_handle.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: state,
+            controls_accepted,
+            exit_code,
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        })
+    }
+}
@@ -0,0 +1,151 @@
+// SharedState - Counters shared by realmd instances behind a load balancer
+//
+// The WrongPass autoban already counts in account.failed_logins, but the
+// tarpit's failure counts and the LogonChallenge rate limit live in process
+// memory, so every instance behind a load balancer applies them on its own.
+// With SharedState.Backend = "database" they are kept as rows of the
+// realmd_counters table in the login database instead and apply to the
+// cluster as a whole.
+//
+// A counter the database cannot answer for falls back to the in-memory one
+// of this instance, so an outage never blocks logins. Rows idle for a day are
+// removed hourly.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+
+use mangos_shared::database::{Database, FieldExt};
+use mangos_shared::log::LOG_TARGET_DB_ERROR;
+use mangos_shared::network::IpRateLimiter;
+use mangos_shared::util::unix_now;
+use mangos_shared::DAY;
+
+/// Time between removals of idle counters
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Failed logon proofs per address, for the tarpit
+pub const SCOPE_TARPIT_IP: &str = "tarpit_ip";
+/// Failed logon proofs per account, for the tarpit
+pub const SCOPE_TARPIT_ACCOUNT: &str = "tarpit_account";
+/// Logon challenges per address, for LogonChallenge.RateLimit
+pub const SCOPE_CHALLENGE: &str = "challenge";
+
+/// When a counter starts over
+#[derive(Debug, Clone, Copy)]
+pub enum Expiry {
+    /// After this long without an increment
+    Idle(Duration),
+    /// This long after its first increment
+    Window(Duration),
+}
+
+static DATABASE: OnceCell<Arc<Database>> = OnceCell::new();
+
+/// Keep the counters in the login database from now on
+pub fn start(db: Arc<Database>) {
+    if DATABASE.set(db.clone()).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let sql = format!("DELETE FROM realmd_counters WHERE last_time < {}", unix_now().saturating_sub(DAY.into()));
+            if let Err(e) = db.execute(&sql).await {
+                tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot remove idle realmd_counters rows: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Whether counters are shared with other instances
+pub fn enabled() -> bool {
+    DATABASE.get().is_some()
+}
+
+/// Current value of a counter, unless it expired after `idle` without an increment
+///
+/// None without a shared backend or when the database cannot be read.
+pub async fn count(scope: &str, key: &str, idle: Duration) -> Option<u32> {
+    let db = DATABASE.get()?;
+    let sql = format!(
+        "SELECT count FROM realmd_counters WHERE scope = '{}' AND name = '{}' AND last_time > {}",
+        scope,
+        Database::escape_string(key),
+        unix_now().saturating_sub(idle.as_secs())
+    );
+    match db.query_one(&sql).await {
+        Ok(row) => Some(row.map_or(0, |row| row.get_u32(0))),
+        Err(e) => {
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot read counter {}:{}: {:#}", scope, key, e);
+            None
+        }
+    }
+}
+
+/// Add one to a counter and return its new value
+///
+/// None without a shared backend or when the database cannot be written.
+pub async fn increment(scope: &str, key: &str, expiry: Expiry) -> Option<u32> {
+    let db = DATABASE.get()?;
+    let key = Database::escape_string(key);
+    let (since, time) = match expiry {
+        Expiry::Idle(idle) => ("last_time", idle),
+        Expiry::Window(window) => ("first_time", window),
+    };
+    let now = unix_now();
+    let cutoff = now.saturating_sub(time.as_secs());
+    // MySQL assigns left to right, so no expression reads a column set before it
+    let update = format!(
+        "UPDATE realmd_counters SET count = CASE WHEN {since} > {cutoff} THEN count + 1 ELSE 1 END, \
+         first_time = CASE WHEN {since} > {cutoff} THEN first_time ELSE {now} END, last_time = {now} \
+         WHERE scope = '{scope}' AND name = '{key}'"
+    );
+    let insert = format!(
+        "INSERT INTO realmd_counters(scope, name, count, first_time, last_time) \
+         VALUES('{scope}', '{key}', 1, {now}, {now})"
+    );
+    let result = async {
+        if db.execute(&update).await? == 0 && db.execute(&insert).await.is_err() {
+            // Another instance inserted the row first
+            db.execute(&update).await?;
+        }
+        let sql = format!("SELECT count FROM realmd_counters WHERE scope = '{}' AND name = '{}'", scope, key);
+        Ok::<_, anyhow::Error>(db.query_one(&sql).await?.map_or(1, |row| row.get_u32(0)))
+    }
+    .await;
+    result
+        .inspect_err(|e| tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot update counter {}:{}: {:#}", scope, key, e))
+        .ok()
+}
+
+/// Drop a counter
+pub async fn remove(scope: &str, key: &str) {
+    let Some(db) = DATABASE.get() else {
+        return;
+    };
+    let sql = format!(
+        "DELETE FROM realmd_counters WHERE scope = '{}' AND name = '{}'",
+        scope,
+        Database::escape_string(key)
+    );
+    if let Err(e) = db.execute(&sql).await {
+        tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot remove counter {}:{}: {:#}", scope, key, e);
+    }
+}
+
+/// `limiter.check(ip)` over the attempts seen by every instance
+///
+/// The shared count uses fixed windows starting at the first attempt instead
+/// of the sliding window of the in-memory limiter.
+pub async fn check_rate(limiter: &IpRateLimiter, scope: &str, ip: IpAddr) -> bool {
+    if limiter.is_enabled()
+        && let Some(attempts) = increment(scope, &ip.to_string(), Expiry::Window(limiter.window())).await
+    {
+        return attempts as usize <= limiter.max_attempts();
+    }
+    limiter.check(ip)
+}
@@ -0,0 +1,152 @@
+// Shutdown - Stop and graceful restart
+//
+// A stop is requested with Ctrl-C or SIGTERM, a restart with SIGUSR2, the
+// "server restart" console command or POST /restart on the REST API. Either
+// way the accept loops stop taking connections and hand their listeners back
+// to main, which gives the sessions in flight up to Shutdown.GracePeriod
+// seconds to finish. Sessions still open after that are told to close: those
+// waiting for a logon or reconnect proof answer it with a "server busy" result
+// first. Queued auth_log rows are flushed before the process exits.
+//
+// A restart then re-executes the realmd binary with the same arguments. On Unix the listening sockets stay open across exec() and
+// are adopted by the new process, so clients connecting meanwhile wait in the
+// accept queue instead of being refused.
+
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+/// How often draining checks for sessions left
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What the process does once it stops accepting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownMode {
+    Stop,
+    Restart,
+}
+
+static REQUESTED: Lazy<watch::Sender<Option<ShutdownMode>>> = Lazy::new(|| watch::channel(None).0);
+
+/// Set once the grace period is over and open sessions must end
+static CLOSING: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+/// Ask the process to stop or restart; a stop overrides a pending restart
+pub fn request(mode: ShutdownMode) {
+    REQUESTED.send_if_modified(|requested| match *requested {
+        Some(ShutdownMode::Stop) => false,
+        Some(current) if current == mode => false,
+        _ => {
+            tracing::info!("{:?} requested", mode);
+            *requested = Some(mode);
+            true
+        }
+    });
+}
+
+/// Wait until a stop or restart is requested
+pub async fn wait() -> ShutdownMode {
+    let mut requested = REQUESTED.subscribe();
+    let mode = match requested.wait_for(Option::is_some).await {
+        Ok(mode) => *mode,
+        // The sender lives in a static and is never dropped
+        Err(_) => None,
+    };
+    mode.unwrap_or(ShutdownMode::Stop)
+}
+
+/// Tell the sessions still open to wrap up
+pub fn close_sessions() {
+    CLOSING.send_replace(true);
+}
+
+/// Resolves once open sessions are told to close
+pub async fn sessions_closing() {
+    let mut closing = CLOSING.subscribe();
+    // The sender lives in a static and is never dropped
+    let _ = closing.wait_for(|closing| *closing).await;
+}
+
+/// Turn SIGTERM into a stop and SIGUSR2 into a restart request
+#[cfg(unix)]
+pub fn listen_for_signals() -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    for (kind, name, mode) in [
+        (SignalKind::terminate(), "SIGTERM", ShutdownMode::Stop),
+        (SignalKind::user_defined2(), "SIGUSR2", ShutdownMode::Restart),
+    ] {
+        let mut signals = signal(kind)?;
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                tracing::info!("Received {}", name);
+                request(mode);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Wait up to `timeout` for `active()` to reach zero; returns the sessions left
+pub async fn drain(active: impl Fn() -> u32, timeout: Duration) -> u32 {
+    let deadline = Instant::now() + timeout;
+    let mut remaining = active();
+    if remaining > 0 {
+        tracing::info!("Waiting up to {}s for {} session(s) to finish", timeout.as_secs(), remaining);
+    }
+    while remaining > 0 && Instant::now() < deadline {
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        remaining = active();
+    }
+    remaining
+}
+
+/// Start a new realmd with the same arguments in place of this one
+///
+/// On Unix the process image is replaced and the listeners are passed on, so
+/// this only returns on failure. Elsewhere a new process is spawned and the
+/// caller exits; the listeners must be closed before so it can bind the port.
+pub fn restart(listeners: Vec<TcpListener>) -> anyhow::Result<()> {
+    let mut command = Command::new(current_exe()?);
+    command.args(std::env::args_os().skip(1));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        use mangos_shared::network::{export_listeners, LISTEN_FDS_ENV};
+
+        match export_listeners(&listeners) {
+            Ok(fds) => {
+                command.env(LISTEN_FDS_ENV, fds);
+            }
+            Err(e) => {
+                eprintln!("Cannot pass the listeners on, the new process binds its own: {:#}", e);
+                drop(listeners);
+                command.env_remove(LISTEN_FDS_ENV);
+            }
+        }
+        Err(anyhow::Error::new(command.exec()).context("Cannot re-execute realmd"))
+    }
+
+    #[cfg(not(unix))]
+    {
+        drop(listeners);
+        command.spawn().map_err(|e| anyhow::Error::new(e).context("Cannot start a new realmd"))?;
+        Ok(())
+    }
+}
+
+/// Path of the running binary, or of its replacement after an upgrade
+fn current_exe() -> anyhow::Result<std::path::PathBuf> {
+    let exe = std::env::current_exe()?;
+    if exe.exists() {
+        return Ok(exe);
+    }
+    // Linux reports "<path> (deleted)" once the binary was replaced on disk
+    let name = exe.to_string_lossy();
+    Ok(name.strip_suffix(" (deleted)").unwrap_or(&name).into())
+}
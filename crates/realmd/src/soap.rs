@@ -0,0 +1,319 @@
+// Soap - CMaNGOS-compatible SOAP endpoint
+// Rust equivalent of MaNGOSsoap.cpp
+//
+// Speaks the same protocol as the C++ core: an executeCommand call in the
+// urn:MaNGOS namespace carrying a console command, authenticated with HTTP
+// Basic auth by an account of gmlevel SEC_ADMINISTRATOR or higher. Web
+// registration panels and account tools written for the C++ server can point
+// at realmd unchanged (for the commands in commands.rs).
+//
+// Every request logs in, so it goes through the same checks as a game login:
+// the LogonChallenge rate limit, IP and account bans, the tarpit, and the
+// WrongPass autoban for wrong passwords. Basic auth cannot carry an
+// authenticator code, so accounts held to Security.RequireTotpGmLevel are
+// refused.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use data_encoding::BASE64;
+use tokio::net::{TcpListener, TcpStream};
+
+use mangos_shared::config::get_config;
+use mangos_shared::database::Database;
+use mangos_shared::log::{LOG_TARGET_AUDIT, LOG_TARGET_DB_ERROR};
+use mangos_shared::network::IpRateLimiter;
+use mangos_shared::SEC_ADMINISTRATOR;
+
+use crate::account_mgr::{self, ChangeSource};
+use crate::auth_socket;
+use crate::commands;
+use crate::http::{self, HttpRequest};
+use crate::ip_ban;
+use crate::shared_state::{self, SCOPE_CHALLENGE};
+use crate::tarpit;
+use crate::webhook;
+
+const ENVELOPE_START: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+<SOAP-ENV:Envelope xmlns:SOAP-ENV=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+xmlns:SOAP-ENC=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" \
+xmlns:ns1=\"urn:MaNGOS\"><SOAP-ENV:Body>";
+const ENVELOPE_END: &str = "</SOAP-ENV:Body></SOAP-ENV:Envelope>";
+
+/// Shared state of the SOAP server
+pub struct SoapServer {
+    db: Arc<Database>,
+    /// The LogonChallenge limiter of the game logins
+    limiter: Arc<IpRateLimiter>,
+}
+
+impl SoapServer {
+    pub fn new(db: Arc<Database>, limiter: Arc<IpRateLimiter>) -> Self {
+        SoapServer { db, limiter }
+    }
+
+    /// Accept SOAP clients until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let server = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = server.handle_connection(stream, peer).await {
+                            tracing::debug!("SOAP: request from {} failed: {:#}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    tracing::error!("SOAP: failed to accept connection: {}", e);
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream, peer: SocketAddr) -> anyhow::Result<()> {
+        let request = match http::read_request(&mut stream).await {
+            Ok(request) => request,
+            Err(e) => return write_fault(&mut stream, "400 Bad Request", &format!("{:#}", e)).await,
+        };
+
+        if !shared_state::check_rate(&self.limiter, SCOPE_CHALLENGE, peer.ip()).await {
+            tracing::info!(target: LOG_TARGET_AUDIT, "SOAP: too many requests from {}", peer.ip());
+            tokio::time::sleep(self.limiter.tarpit()).await;
+            return http::write_response(&mut stream, "429 Too Many Requests", "text/plain", "Too Many Requests").await;
+        }
+        let map_v4 = get_config().lock().get_bool_default("MapIPv4MappedAddresses", true);
+        match ip_ban::find_ban(&self.db, peer.ip(), map_v4).await {
+            Ok(None) => {}
+            Ok(Some(ban)) => {
+                tracing::info!(target: LOG_TARGET_AUDIT, "SOAP: banned IP {} refused (ban on {})", peer.ip(), ban);
+                return write_forbidden(&mut stream).await;
+            }
+            Err(e) => {
+                tracing::error!(target: LOG_TARGET_DB_ERROR, "SOAP: cannot check the bans of {}: {:#}", peer.ip(), e);
+                return write_fault(&mut stream, "500 Internal Server Error", "database error").await;
+            }
+        }
+
+        let Some((user, password)) = basic_credentials(&request) else {
+            tracing::debug!("SOAP: request from {} without authentication", peer);
+            return write_unauthorized(&mut stream).await;
+        };
+        let login = user.to_uppercase();
+        // Recent failures slow the answer down, as for a game client's logon proof
+        tokio::time::sleep(tarpit::delay_for(peer.ip(), &login).await).await;
+        let account_id = match self.authenticate(&user, &password).await {
+            Ok(Some(account_id)) => account_id,
+            Ok(None) => {
+                tracing::warn!(target: LOG_TARGET_AUDIT, "SOAP: failed login for '{}' from {}", user, peer);
+                tarpit::record_failure(peer.ip(), &login).await;
+                webhook::record_failed_login(peer.ip(), &login);
+                auth_socket::handle_failed_login(&self.db, &login, &Database::escape_string(&login), &peer).await;
+                return write_unauthorized(&mut stream).await;
+            }
+            Err(e) => {
+                tracing::error!("SOAP: cannot check login for '{}': {:#}", user, e);
+                return write_fault(&mut stream, "500 Internal Server Error", "database error").await;
+            }
+        };
+        let (banned, level) = tokio::join!(
+            account_mgr::is_banned(&self.db, account_id),
+            account_mgr::current_gm_level(&self.db, account_id)
+        );
+        if !matches!(banned, Ok(false)) {
+            tracing::info!(target: LOG_TARGET_AUDIT, "SOAP: banned account '{}' refused (from {})", user, peer);
+            return write_forbidden(&mut stream).await;
+        }
+        let level = match level {
+            Ok(level) if level >= SEC_ADMINISTRATOR => level,
+            _ => {
+                tracing::warn!("SOAP: {}'s gmlevel is too low (from {})", user, peer);
+                return write_forbidden(&mut stream).await;
+            }
+        };
+        let required_level = get_config().lock().get_int_default("Security.RequireTotpGmLevel", 0);
+        if required_level > 0 && i32::from(level) >= required_level {
+            tracing::warn!(
+                target: LOG_TARGET_AUDIT,
+                "SOAP: '{}' (gmlevel {}) refused: Security.RequireTotpGmLevel needs an authenticator code",
+                user, level
+            );
+            return write_forbidden(&mut stream).await;
+        }
+        self.reset_failures(account_id, &login).await;
+
+        let body = String::from_utf8_lossy(&request.body);
+        let Some(command) = element_text(&body, "command").filter(|c| !c.trim().is_empty()) else {
+            return write_fault(&mut stream, "500 Internal Server Error", "Command mandatory").await;
+        };
+
+        tracing::info!("SOAP: '{}' from {} runs: {}", user, peer, command);
+        match commands::execute(&self.db, &command, &user.to_uppercase(), ChangeSource::Soap).await {
+            Ok(output) => {
+                let body = format!(
+                    "{}<ns1:executeCommandResponse><result>{}</result></ns1:executeCommandResponse>{}",
+                    ENVELOPE_START,
+                    xml_escape(&output),
+                    ENVELOPE_END
+                );
+                http::write_response(&mut stream, "200 OK", "text/xml; charset=utf-8", &body).await
+            }
+            Err(output) => write_fault(&mut stream, "500 Internal Server Error", &output).await,
+        }
+    }
+
+    /// Clear the failed logins of an account after a successful login
+    async fn reset_failures(&self, account_id: u32, login: &str) {
+        if let Err(e) = self
+            .db
+            .execute(&format!("UPDATE account SET failed_logins = 0 WHERE id = '{}'", account_id))
+            .await
+        {
+            tracing::error!(target: LOG_TARGET_DB_ERROR, "SOAP: cannot reset the failed logins of '{}': {:#}", login, e);
+        }
+        tarpit::clear_account(login).await;
+    }
+
+    /// Account id for valid credentials
+    async fn authenticate(&self, user: &str, password: &str) -> anyhow::Result<Option<u32>> {
+        let Some(account_id) = account_mgr::find_account_id(&self.db, user).await? else {
+            return Ok(None);
+        };
+        Ok(account_mgr::check_password(&self.db, account_id, password)
+            .await?
+            .then_some(account_id))
+    }
+}
+
+/// User and password from an "Authorization: Basic" header
+fn basic_credentials(request: &HttpRequest) -> Option<(String, String)> {
+    let encoded = request.header("Authorization")?.strip_prefix("Basic ")?;
+    let decoded = BASE64.decode(encoded.trim().as_bytes()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Unescaped text of the first element named `name`, with or without a namespace prefix
+fn element_text(xml: &str, name: &str) -> Option<String> {
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = rest.find('>')?;
+        let tag = &rest[..end];
+        let tag_name = tag.split_whitespace().next().unwrap_or("").trim_end_matches('/');
+        let local = tag_name.rsplit(':').next().unwrap_or(tag_name);
+        rest = &rest[end + 1..];
+        if local != name {
+            continue;
+        }
+        if tag.ends_with('/') {
+            return Some(String::new());
+        }
+        let close = rest.find("</")?;
+        return Some(xml_unescape(&rest[..close]));
+    }
+    None
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse()))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+async fn write_forbidden(stream: &mut TcpStream) -> anyhow::Result<()> {
+    http::write_response(stream, "403 Forbidden", "text/plain", "Forbidden").await
+}
+
+async fn write_unauthorized(stream: &mut TcpStream) -> anyhow::Result<()> {
+    // Clients expect the challenge before they send credentials
+    http::write_response_with_headers(
+        stream,
+        "401 Unauthorized",
+        &[("WWW-Authenticate", "Basic realm=\"gSOAP Web Service\"")],
+        "text/plain",
+        "HTTP authentication required",
+    )
+    .await
+}
+
+async fn write_fault(stream: &mut TcpStream, status: &str, message: &str) -> anyhow::Result<()> {
+    let message = xml_escape(message);
+    let body = format!(
+        "{}<SOAP-ENV:Fault><faultcode>SOAP-ENV:Client</faultcode><faultstring>{}</faultstring>\
+         <detail>{}</detail></SOAP-ENV:Fault>{}",
+        ENVELOPE_START, message, message, ENVELOPE_END
+    );
+    http::write_response(stream, status, "text/xml; charset=utf-8", &body).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_element_text() {
+        let body = "<SOAP-ENV:Body><ns1:executeCommand xmlns:ns1=\"urn:MaNGOS\">\
+                    <command>account create bob x&amp;y</command></ns1:executeCommand></SOAP-ENV:Body>";
+        assert_eq!(element_text(body, "command").as_deref(), Some("account create bob x&y"));
+        assert_eq!(element_text("<ns1:command>server info</ns1:command>", "command").as_deref(), Some("server info"));
+        assert_eq!(element_text("<command/>", "command").as_deref(), Some(""));
+        // Only the element name counts, not a longer one starting with it
+        assert_eq!(element_text("<commands>x</commands>", "command"), None);
+        assert_eq!(element_text("<command>unterminated", "command"), None);
+        assert_eq!(element_text("<command", "command"), None);
+    }
+
+    #[test]
+    fn test_xml_unescape() {
+        assert_eq!(xml_unescape("&lt;a&gt; &amp; &quot;b&quot; &apos;c&apos;"), "<a> & \"b\" 'c'");
+        assert_eq!(xml_unescape("&#65;&#x42;&#x1F600;"), "AB\u{1F600}");
+        // Unknown or broken entities are kept as they are
+        assert_eq!(xml_unescape("&nbsp; &#xZZ; &#1114112; a & b"), "&nbsp; &#xZZ; &#1114112; a & b");
+        assert_eq!(xml_escape("<a & 'b'>"), "&lt;a &amp; &apos;b&apos;&gt;");
+        assert_eq!(xml_unescape(&xml_escape("<x y=\"1\">&</x>")), "<x y=\"1\">&</x>");
+    }
+}
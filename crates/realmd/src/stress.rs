@@ -0,0 +1,116 @@
+// Stress - Load test of a running realmd
+//
+// `realmd stress --accounts <file> --concurrency <n>` logs in to --target the
+// way a game client does (see auth_client) over up to <n> connections at once.
+// The accounts file has one "name:password" per line ('#' starts a comment);
+// accounts are used in turn until --logins logins were made. It reports how
+// many logins succeeded, why the others failed and the latency percentiles of
+// the whole handshake.
+//
+// The target counts the tester like any client: LogonChallenge.RateLimit,
+// WrongPass.MaxCount and the tarpit all apply to its address.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::auth_client;
+use crate::realm_list::find_build_info;
+
+pub struct StressOptions {
+    /// Address of the realmd under test, host:port
+    pub target: String,
+    pub concurrency: usize,
+    /// Logins to make in total
+    pub logins: usize,
+    pub build: u16,
+}
+
+/// Outcome of a stress run
+#[derive(Default)]
+pub struct StressReport {
+    /// Handshake times of the successful logins, sorted
+    pub latencies: Vec<Duration>,
+    /// Failed logins by reason
+    pub failures: BTreeMap<String, usize>,
+    pub elapsed: Duration,
+}
+
+impl StressReport {
+    /// Latency below which `fraction` of the successful logins completed
+    pub fn percentile(&self, fraction: f64) -> Option<Duration> {
+        let last = self.latencies.len().checked_sub(1)?;
+        Some(self.latencies[((last as f64) * fraction).round() as usize])
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failures.values().sum()
+    }
+}
+
+/// Read "name:password" lines from `path`
+pub fn read_accounts(path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let text = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path.display(), e))?;
+    let mut accounts = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((name, password)) = line.split_once(':') else {
+            anyhow::bail!("{}:{}: expected name:password", path.display(), number + 1);
+        };
+        accounts.push((name.trim().to_string(), password.to_string()));
+    }
+    anyhow::ensure!(!accounts.is_empty(), "{} lists no accounts", path.display());
+    Ok(accounts)
+}
+
+/// Run `options.logins` logins with `accounts` against the target
+pub async fn run(options: &StressOptions, accounts: Vec<(String, String)>) -> anyhow::Result<StressReport> {
+    let info = find_build_info(options.build)
+        .filter(|info| info.build == options.build)
+        .ok_or_else(|| anyhow::anyhow!("Unknown client build {}", options.build))?;
+    let accounts = Arc::new(accounts);
+    let next = Arc::new(AtomicUsize::new(0));
+
+    let started = Instant::now();
+    let mut workers = Vec::new();
+    for _ in 0..options.concurrency.clamp(1, options.logins.max(1)) {
+        let (accounts, next, info) = (accounts.clone(), next.clone(), info.clone());
+        let (target, logins) = (options.target.clone(), options.logins);
+        workers.push(tokio::spawn(async move {
+            let mut outcomes = Vec::new();
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= logins {
+                    break;
+                }
+                let (name, password) = &accounts[index % accounts.len()];
+                let begin = Instant::now();
+                let outcome = match tokio::time::timeout(auth_client::LOGIN_TIMEOUT, auth_client::login(&target, &info, name, password)).await {
+                    Ok(Ok(_)) => Ok(begin.elapsed()),
+                    Ok(Err(reason)) => Err(reason),
+                    Err(_) => Err("timed out".to_string()),
+                };
+                outcomes.push(outcome);
+            }
+            outcomes
+        }));
+    }
+
+    let mut report = StressReport::default();
+    for worker in workers {
+        for outcome in worker.await? {
+            match outcome {
+                Ok(latency) => report.latencies.push(latency),
+                Err(reason) => *report.failures.entry(reason).or_default() += 1,
+            }
+        }
+    }
+    report.elapsed = started.elapsed();
+    report.latencies.sort();
+    Ok(report)
+}
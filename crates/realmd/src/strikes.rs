@@ -0,0 +1,95 @@
+// Strikes - Temporary blocks after protocol violations
+//
+// Real clients never send unknown commands, commands out of order or packets
+// that fail to parse; bots and scanners do, and reconnect at once when the
+// connection is closed. Every such violation is a strike against the client
+// address. ProtocolStrikes.MaxCount strikes without a ProtocolStrikes.Window
+// seconds pause block the address for ProtocolStrikes.BlockTime seconds: its
+// connections are closed as soon as they are accepted.
+//
+// A block is logged on the audit target as
+//   "Blocking <ip> for <n>s after <n> protocol violations (<last violation>)"
+// so fail2ban can extend it to the firewall. Strikes and blocks are kept in
+// memory only.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use mangos_shared::config::get_config;
+use mangos_shared::log::LOG_TARGET_AUDIT;
+
+/// Addresses tracked before forgotten ones are swept out
+const SWEEP_THRESHOLD: usize = 4096;
+
+static STRIKES: Lazy<Mutex<Strikes>> = Lazy::new(|| {
+    let config = get_config().lock();
+    Mutex::new(Strikes {
+        max_count: config.get_int_default("ProtocolStrikes.MaxCount", 5).max(0) as u32,
+        window: Duration::from_secs(config.get_int_default("ProtocolStrikes.Window", 60).max(1) as u64),
+        block_time: Duration::from_secs(config.get_int_default("ProtocolStrikes.BlockTime", 600).max(1) as u64),
+        by_ip: HashMap::new(),
+    })
+});
+
+/// Strikes against one address
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    count: u32,
+    last: Instant,
+    blocked_until: Option<Instant>,
+}
+
+struct Strikes {
+    /// Strikes that block an address; 0 disables blocking
+    max_count: u32,
+    /// How long strikes are remembered without a new one
+    window: Duration,
+    block_time: Duration,
+    by_ip: HashMap<IpAddr, Record>,
+}
+
+/// Count a protocol violation by `ip`, described by `violation`
+pub fn record(ip: IpAddr, violation: &str) {
+    let mut strikes = STRIKES.lock();
+    tracing::debug!("Protocol violation from {}: {}", ip, violation);
+    if strikes.max_count == 0 {
+        return;
+    }
+
+    let now = Instant::now();
+    let (window, max_count, block_time) = (strikes.window, strikes.max_count, strikes.block_time);
+    if strikes.by_ip.len() >= SWEEP_THRESHOLD {
+        strikes.by_ip.retain(|_, record| {
+            now.saturating_duration_since(record.last) < window || record.blocked_until.is_some_and(|until| until > now)
+        });
+    }
+    let record = strikes.by_ip.entry(ip).or_insert(Record { count: 0, last: now, blocked_until: None });
+    if now.saturating_duration_since(record.last) >= window {
+        record.count = 0;
+    }
+    record.count = record.count.saturating_add(1);
+    record.last = now;
+
+    if record.count >= max_count && record.blocked_until.is_none_or(|until| until <= now) {
+        record.blocked_until = Some(now + block_time);
+        tracing::warn!(
+            target: LOG_TARGET_AUDIT,
+            "Blocking {} for {}s after {} protocol violations ({})",
+            ip, block_time.as_secs(), record.count, violation
+        );
+    }
+}
+
+/// Whether connections from `ip` are refused for protocol violations
+pub fn is_blocked(ip: IpAddr) -> bool {
+    let strikes = STRIKES.lock();
+    strikes
+        .by_ip
+        .get(&ip)
+        .and_then(|record| record.blocked_until)
+        .is_some_and(|until| until > Instant::now())
+}
@@ -0,0 +1,133 @@
+// Tarpit - Progressive delay after failed logins
+//
+// Remembers recent failed logon proofs per account and per IP and holds back
+// the next LogonProof response, doubling the delay with every failure up to
+// WrongPass.TarpitMax. Guessing gets slower long before WrongPass.MaxCount
+// bans anyone, and it also works with the autoban disabled.
+//
+// Failures are forgotten after WrongPass.TarpitReset seconds without a new
+// one, or for the account when it logs in successfully. They are kept in
+// memory, and also in the login database when SharedState.Backend shares them
+// with the other realmd instances.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use mangos_shared::config::get_config;
+
+use crate::shared_state::{self, Expiry, SCOPE_TARPIT_ACCOUNT, SCOPE_TARPIT_IP};
+
+/// Keys tracked per map before forgotten ones are swept out
+const SWEEP_THRESHOLD: usize = 4096;
+
+static TARPIT: Lazy<Mutex<LoginTarpit>> = Lazy::new(|| {
+    let config = get_config().lock();
+    Mutex::new(LoginTarpit {
+        base: Duration::from_millis(config.get_int_default("WrongPass.Tarpit", 0).max(0) as u64),
+        max: Duration::from_millis(config.get_int_default("WrongPass.TarpitMax", 10000).max(0) as u64),
+        reset: Duration::from_secs(config.get_int_default("WrongPass.TarpitReset", 900).max(1) as u64),
+        by_ip: HashMap::new(),
+        by_account: HashMap::new(),
+    })
+});
+
+/// Failure count and time of the latest failure
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+struct LoginTarpit {
+    /// Delay after the first failure; 0 disables the tarpit
+    base: Duration,
+    max: Duration,
+    /// How long failures are remembered
+    reset: Duration,
+    by_ip: HashMap<IpAddr, Failures>,
+    by_account: HashMap<String, Failures>,
+}
+
+impl LoginTarpit {
+    fn failures<K: Eq + Hash>(&self, map: &HashMap<K, Failures>, key: &K, now: Instant) -> u32 {
+        map.get(key)
+            .filter(|f| now.saturating_duration_since(f.last) < self.reset)
+            .map_or(0, |f| f.count)
+    }
+
+    fn record<K: Eq + Hash>(map: &mut HashMap<K, Failures>, key: K, reset: Duration, now: Instant) {
+        if map.len() >= SWEEP_THRESHOLD {
+            map.retain(|_, f| now.saturating_duration_since(f.last) < reset);
+        }
+        let entry = map.entry(key).or_insert(Failures { count: 0, last: now });
+        if now.saturating_duration_since(entry.last) >= reset {
+            entry.count = 0;
+        }
+        entry.count = entry.count.saturating_add(1);
+        entry.last = now;
+    }
+}
+
+/// Delay before answering the next logon proof from `ip` for `account`
+pub async fn delay_for(ip: IpAddr, account: &str) -> Duration {
+    let (base, max, reset) = {
+        let tarpit = TARPIT.lock();
+        (tarpit.base, tarpit.max, tarpit.reset)
+    };
+    if base.is_zero() {
+        return Duration::ZERO;
+    }
+
+    let shared = match shared_state::count(SCOPE_TARPIT_IP, &ip.to_string(), reset).await {
+        Some(by_ip) => shared_state::count(SCOPE_TARPIT_ACCOUNT, account, reset).await.map(|by_account| by_ip.max(by_account)),
+        None => None,
+    };
+    let failures = shared.unwrap_or_else(|| {
+        let tarpit = TARPIT.lock();
+        let now = Instant::now();
+        tarpit
+            .failures(&tarpit.by_ip, &ip, now)
+            .max(tarpit.failures(&tarpit.by_account, &account.to_string(), now))
+    });
+    if failures == 0 {
+        return Duration::ZERO;
+    }
+
+    // base, 2 * base, 4 * base, ... up to max
+    base.saturating_mul(1u32 << (failures - 1).min(16)).min(max)
+}
+
+/// Count a failed logon proof against `ip` and `account`
+pub async fn record_failure(ip: IpAddr, account: &str) {
+    let reset = {
+        let mut tarpit = TARPIT.lock();
+        if tarpit.base.is_zero() {
+            return;
+        }
+
+        let now = Instant::now();
+        let reset = tarpit.reset;
+        LoginTarpit::record(&mut tarpit.by_ip, ip, reset, now);
+        LoginTarpit::record(&mut tarpit.by_account, account.to_string(), reset, now);
+        reset
+    };
+
+    if shared_state::enabled() {
+        shared_state::increment(SCOPE_TARPIT_IP, &ip.to_string(), Expiry::Idle(reset)).await;
+        shared_state::increment(SCOPE_TARPIT_ACCOUNT, account, Expiry::Idle(reset)).await;
+    }
+}
+
+/// Forget the failures of an account after it logged in successfully
+///
+/// The address keeps its count, so one good login doesn't clear the record
+/// of a host spraying many accounts.
+pub async fn clear_account(account: &str) {
+    TARPIT.lock().by_account.remove(account);
+    shared_state::remove(SCOPE_TARPIT_ACCOUNT, account).await;
+}
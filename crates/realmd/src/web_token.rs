@@ -0,0 +1,86 @@
+// WebToken - Sign-in tokens for companion web services
+//
+// With WebToken.Enabled every successful logon proof stores a token in the
+// account_web_token table, valid for WebToken.Lifetime seconds, so a website
+// can let the player manage the account without typing the password again,
+// e.g. by taking the newest token issued to the account from the visitor's
+// address. A token reads
+//   <account id>.<expiry unix time>.<nonce>.<signature>
+// where the signature is the hex HMAC-SHA1 of everything before it under
+// WebToken.Secret. Services knowing the secret can check a token without the
+// database; without a secret a random one is used and only the table counts.
+
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data_encoding::HEXLOWER;
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use mangos_shared::auth::hmac_sha1::hmac_sha1;
+use mangos_shared::config::get_config;
+use mangos_shared::database::Database;
+use mangos_shared::log::LOG_TARGET_DB_ERROR;
+
+struct WebTokenSettings {
+    enabled: bool,
+    /// Seconds a token is valid
+    lifetime: u64,
+    secret: Zeroizing<Vec<u8>>,
+}
+
+static SETTINGS: Lazy<WebTokenSettings> = Lazy::new(|| {
+    let config = get_config().lock();
+    let configured = config.get_string("WebToken.Secret");
+    let secret = if configured.is_empty() {
+        let mut secret = vec![0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        secret
+    } else {
+        configured.into_bytes()
+    };
+    WebTokenSettings {
+        enabled: config.get_bool_default("WebToken.Enabled", false),
+        lifetime: config.get_int_default("WebToken.Lifetime", 300).max(1) as u64,
+        secret: Zeroizing::new(secret),
+    }
+});
+
+/// Signed token for `account_id` expiring at `expires_at`
+fn sign(secret: &[u8], account_id: u32, expires_at: u64, nonce: &[u8]) -> String {
+    let payload = format!("{}.{}.{}", account_id, expires_at, HEXLOWER.encode(nonce));
+    let signature = hmac_sha1(secret, payload.as_bytes());
+    format!("{}.{}", payload, HEXLOWER.encode(&signature))
+}
+
+/// Store a new token for an account that just logged in from `ip`
+pub async fn issue(db: &Database, account_id: u32, ip: IpAddr) {
+    let settings = &*SETTINGS;
+    if !settings.enabled {
+        return;
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let expires_at = now + settings.lifetime;
+    let mut nonce = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let token = Zeroizing::new(sign(&settings.secret, account_id, expires_at, &nonce));
+
+    // Expired tokens are of no use to anyone
+    if let Err(e) = db.execute(&format!("DELETE FROM account_web_token WHERE expires_at < {}", now)).await {
+        tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot remove expired web tokens: {:#}", e);
+    }
+    let sql = Zeroizing::new(format!(
+        "INSERT INTO account_web_token(token, account_id, ip, issued_at, expires_at) VALUES('{}', '{}', '{}', '{}', '{}')",
+        *token,
+        account_id,
+        Database::escape_string(&ip.to_string()),
+        now,
+        expires_at
+    ));
+    match db.execute(&sql).await {
+        Ok(_) => tracing::debug!("Web token issued for account {} (valid {}s)", account_id, settings.lifetime),
+        Err(e) => tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot store web token for account {}: {:#}", account_id, e),
+    }
+}
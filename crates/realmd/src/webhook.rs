@@ -0,0 +1,258 @@
+// Webhook - Security event notifications
+//
+// Posts a JSON message to every URL in Webhook.Urls when an account or address
+// is autobanned, a GM account logs in, or an address keeps failing logins. The
+// payload carries the text both as "content" (Discord) and "text" (Slack), next
+// to the event's fields for custom receivers.
+//
+// Delivery runs on a background task so a slow receiver never holds up a login.
+// At most Webhook.RateLimit messages go out per minute; events over the limit
+// are counted and mentioned in the next message. Failed posts are retried
+// Webhook.Retries times with a doubling delay.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use mangos_shared::config::Config;
+use mangos_shared::util::secs_to_time_string;
+use mangos_shared::AccountTypes;
+
+use crate::http;
+
+/// Events waiting for delivery before new ones are dropped
+const QUEUE_SIZE: usize = 256;
+
+/// Delay before the first retry
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Addresses tracked for failed logins before stale ones are swept out
+const SWEEP_THRESHOLD: usize = 4096;
+
+/// A security event worth telling the admins about
+#[derive(Debug, Clone)]
+pub enum SecurityEvent {
+    /// WrongPass.MaxCount was reached; `target` is the banned account or address
+    Autoban { target: String, account: String, ip: IpAddr, ban_time: u32, failures: u32 },
+    /// An account with a gmlevel above SEC_PLAYER logged in
+    GmLogin { account: String, gmlevel: AccountTypes, ip: IpAddr },
+    /// An address reached Webhook.FailedLoginThreshold failures
+    FailedLogins { ip: IpAddr, account: String, failures: u32, window: u64 },
+}
+
+impl SecurityEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            SecurityEvent::Autoban { .. } => "autoban",
+            SecurityEvent::GmLogin { .. } => "gm_login",
+            SecurityEvent::FailedLogins { .. } => "failed_logins",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            SecurityEvent::Autoban { target, account, ip, ban_time, failures } => format!(
+                "{} auto-banned for {} after {} failed logins (account '{}' from {})",
+                target,
+                secs_to_time_string(u64::from(*ban_time)),
+                failures,
+                account,
+                ip
+            ),
+            SecurityEvent::GmLogin { account, gmlevel, ip } => {
+                format!("GM account '{}' (gmlevel {}) logged in from {}", account, gmlevel, ip)
+            }
+            SecurityEvent::FailedLogins { ip, account, failures, window } => format!(
+                "{} failed logins from {} within {}s (latest account '{}')",
+                failures, ip, window, account
+            ),
+        }
+    }
+
+    fn fields(&self) -> Value {
+        match self {
+            SecurityEvent::Autoban { target, account, ip, ban_time, failures } => json!({
+                "target": target, "account": account, "ip": ip.to_string(), "ban_time": ban_time, "failures": failures,
+            }),
+            SecurityEvent::GmLogin { account, gmlevel, ip } => json!({
+                "account": account, "gmlevel": gmlevel, "ip": ip.to_string(),
+            }),
+            SecurityEvent::FailedLogins { ip, account, failures, window } => json!({
+                "account": account, "ip": ip.to_string(), "failures": failures, "window": window,
+            }),
+        }
+    }
+}
+
+/// Webhook settings read from the config file
+pub struct WebhookSettings {
+    pub urls: Vec<String>,
+    /// Messages per minute; 0 = unlimited
+    pub rate_limit: u32,
+    pub retries: u32,
+    pub gm_logins: bool,
+    /// Failures from one address that trigger a notification; 0 = off
+    pub failed_login_threshold: u32,
+    pub failed_login_window: Duration,
+}
+
+impl WebhookSettings {
+    pub fn from_config(config: &Config) -> Self {
+        WebhookSettings {
+            urls: config
+                .get_string("Webhook.Urls")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            rate_limit: config.get_int_default("Webhook.RateLimit", 10).max(0) as u32,
+            retries: config.get_int_default("Webhook.Retries", 3).max(0) as u32,
+            gm_logins: config.get_bool_default("Webhook.GmLogins", true),
+            failed_login_threshold: config.get_int_default("Webhook.FailedLoginThreshold", 10).max(0) as u32,
+            failed_login_window: Duration::from_secs(config.get_int_default("Webhook.FailedLoginWindow", 300).max(1) as u64),
+        }
+    }
+}
+
+struct Notifier {
+    queue: mpsc::Sender<SecurityEvent>,
+    gm_logins: bool,
+    failed_login_threshold: u32,
+    failed_login_window: Duration,
+    /// Failure count and start of the counting window per address
+    failures: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+}
+
+static NOTIFIER: OnceCell<Notifier> = OnceCell::new();
+
+/// Start the delivery task; without URLs notifications stay off
+pub fn start(settings: WebhookSettings) {
+    if settings.urls.is_empty() {
+        return;
+    }
+    let (queue, events) = mpsc::channel(QUEUE_SIZE);
+    let notifier = Notifier {
+        queue,
+        gm_logins: settings.gm_logins,
+        failed_login_threshold: settings.failed_login_threshold,
+        failed_login_window: settings.failed_login_window,
+        failures: Mutex::new(HashMap::new()),
+    };
+    if NOTIFIER.set(notifier).is_err() {
+        return;
+    }
+    tracing::info!("Posting security events to {} webhook(s)", settings.urls.len());
+    tokio::spawn(deliver(events, settings.urls, settings.rate_limit, settings.retries));
+}
+
+/// Queue an event for delivery
+pub fn notify(event: SecurityEvent) {
+    let Some(notifier) = NOTIFIER.get() else {
+        return;
+    };
+    if let SecurityEvent::GmLogin { .. } = event
+        && !notifier.gm_logins
+    {
+        return;
+    }
+    if notifier.queue.try_send(event).is_err() {
+        tracing::warn!("Webhook queue full, dropping a security event");
+    }
+}
+
+/// Count a failed login from `ip`, notifying once the threshold is reached
+pub fn record_failed_login(ip: IpAddr, account: &str) {
+    let Some(notifier) = NOTIFIER.get() else {
+        return;
+    };
+    if notifier.failed_login_threshold == 0 {
+        return;
+    }
+
+    let now = Instant::now();
+    let window = notifier.failed_login_window;
+    let failures = {
+        let mut failures = notifier.failures.lock();
+        if failures.len() >= SWEEP_THRESHOLD {
+            failures.retain(|_, (_, start)| now.saturating_duration_since(*start) < window);
+        }
+        let entry = failures.entry(ip).or_insert((0, now));
+        if now.saturating_duration_since(entry.1) >= window {
+            *entry = (0, now);
+        }
+        entry.0 += 1;
+        entry.0
+    };
+
+    // Once per window, not for every failure past the threshold
+    if failures == notifier.failed_login_threshold {
+        notify(SecurityEvent::FailedLogins {
+            ip,
+            account: account.to_string(),
+            failures,
+            window: window.as_secs(),
+        });
+    }
+}
+
+async fn deliver(mut events: mpsc::Receiver<SecurityEvent>, urls: Vec<String>, rate_limit: u32, retries: u32) {
+    let mut window_start = Instant::now();
+    let mut sent = 0;
+    let mut suppressed = 0;
+
+    while let Some(event) = events.recv().await {
+        if window_start.elapsed() >= Duration::from_secs(60) {
+            window_start = Instant::now();
+            sent = 0;
+        }
+        if rate_limit > 0 && sent >= rate_limit {
+            suppressed += 1;
+            continue;
+        }
+        sent += 1;
+
+        let mut message = event.message();
+        if suppressed > 0 {
+            message.push_str(&format!(" ({} more event(s) suppressed by the rate limit)", suppressed));
+            suppressed = 0;
+        }
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let body = json!({
+            "content": message,
+            "text": message,
+            "event": event.kind(),
+            "time": time,
+            "details": event.fields(),
+        })
+        .to_string();
+
+        for url in &urls {
+            post_with_retry(url, &body, retries).await;
+        }
+    }
+}
+
+async fn post_with_retry(url: &str, body: &str, retries: u32) {
+    let mut delay = RETRY_DELAY;
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+        match http::post(url, "application/json", body).await {
+            Ok(status) if (200..300).contains(&status) => return,
+            // Client errors other than rate limiting won't go away by retrying
+            Ok(status) if status != 429 && (400..500).contains(&status) => {
+                tracing::warn!("Webhook {} rejected the event: HTTP {}", url, status);
+                return;
+            }
+            Ok(status) => tracing::debug!("Webhook {} answered HTTP {} (attempt {})", url, status, attempt + 1),
+            Err(e) => tracing::debug!("Webhook {} failed: {:#} (attempt {})", url, e, attempt + 1),
+        }
+    }
+    tracing::warn!("Giving up on webhook {} after {} attempt(s)", url, retries + 1);
+}
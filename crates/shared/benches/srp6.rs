@@ -0,0 +1,57 @@
+// Server-side SRP6 cost of one login: challenge (B) and proof verification
+//
+// Run with: cargo bench -p mangos-shared --bench srp6
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use mangos_shared::auth::{SRP6, SRP6Client, Sha1Hash};
+
+/// SHA1(USERNAME:PASSWORD) as stored in account.sha_pass_hash
+fn sha_pass_hash(username: &str, password: &str) -> String {
+    let mut sha = Sha1Hash::new();
+    sha.update_data(&format!("{}:{}", username, password));
+    sha.finalize();
+    sha.get_digest().iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn bench_login(c: &mut Criterion) {
+    let mut account = SRP6::new();
+    account.calculate_verifier_random(&sha_pass_hash("BENCH", "BENCH"));
+    let verifier = account.get_verifier().as_hex_str();
+    let salt = account.get_salt().as_hex_str();
+
+    c.bench_function("srp6_server_login", |b| {
+        b.iter_batched(
+            || {
+                // The client half is not part of the server's cost
+                let mut server = SRP6::new();
+                server.set_verifier(&verifier);
+                server.set_salt(&salt);
+                server.calculate_host_public_ephemeral();
+                let mut client = SRP6Client::new("BENCH", "BENCH");
+                client.process_challenge(
+                    &server.get_host_public_ephemeral().as_byte_array(32),
+                    &server.get_generator_modulo().as_byte_array(1),
+                    &server.get_prime().as_byte_array(32),
+                    &server.get_salt().as_byte_array(32),
+                );
+                (client.get_client_public_ephemeral().as_byte_array(32), client.get_proof().as_byte_array(20))
+            },
+            |(a, m1)| {
+                let mut server = SRP6::new();
+                server.set_verifier(&verifier);
+                server.set_salt(&salt);
+                server.calculate_host_public_ephemeral();
+                server.calculate_session_key(&a);
+                server.hash_session_key();
+                server.calculate_proof("BENCH");
+                // The client proof was made against another B, so it won't match
+                server.proof(&m1)
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_login);
+criterion_main!(benches);
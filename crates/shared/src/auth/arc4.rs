@@ -0,0 +1,85 @@
+// ARC4 - RC4 stream cipher
+// Rust equivalent of ARC4.h/cpp
+//
+// Used by Warden for its packet streams and to wrap modules. RC4 is broken
+// as a cipher; it is only here because the client speaks it.
+
+use zeroize::Zeroize;
+
+/// RC4 keystream state
+#[derive(Clone)]
+pub struct Arc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Arc4 {
+    /// Keyed cipher; `key` must not be empty
+    pub fn new(key: &[u8]) -> Self {
+        assert!(!key.is_empty(), "ARC4 key must not be empty");
+        let mut state = [0u8; 256];
+        for (i, byte) in state.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let mut j = 0u8;
+        for i in 0..256 {
+            j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+            state.swap(i, j as usize);
+        }
+        Arc4 { state, i: 0, j: 0 }
+    }
+
+    /// Encrypt or decrypt `data` in place, continuing the keystream
+    pub fn update(&mut self, data: &mut [u8]) {
+        for byte in data {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.state[self.i as usize]);
+            self.state.swap(self.i as usize, self.j as usize);
+            let k = self.state[self.state[self.i as usize].wrapping_add(self.state[self.j as usize]) as usize];
+            *byte ^= k;
+        }
+    }
+}
+
+impl Drop for Arc4 {
+    fn drop(&mut self) {
+        self.state.zeroize();
+    }
+}
+
+impl std::fmt::Debug for Arc4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Arc4 { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_vectors() {
+        // Test vectors from the original RC4 description
+        let mut data = *b"Plaintext";
+        Arc4::new(b"Key").update(&mut data);
+        assert_eq!(data, [0xBB, 0xF3, 0x16, 0xE8, 0xD9, 0x40, 0xAF, 0x0A, 0xD3]);
+
+        let mut data = *b"pedia";
+        Arc4::new(b"Wiki").update(&mut data);
+        assert_eq!(data, [0x10, 0x21, 0xBF, 0x04, 0x20]);
+    }
+
+    #[test]
+    fn test_stream_continues() {
+        let mut whole = *b"Attack at dawn";
+        Arc4::new(b"Secret").update(&mut whole);
+
+        let mut split = *b"Attack at dawn";
+        let mut cipher = Arc4::new(b"Secret");
+        let (head, tail) = split.split_at_mut(5);
+        cipher.update(head);
+        cipher.update(tail);
+        assert_eq!(split, whole);
+    }
+}
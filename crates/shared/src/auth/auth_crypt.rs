@@ -0,0 +1,121 @@
+// AuthCrypt - World packet header encryption
+// Rust equivalent of AuthCrypt.h/cpp (TBC)
+//
+// Once a world session is authenticated both sides encrypt the packet
+// headers (not the bodies) with a key derived from the session key K:
+// HMAC-SHA1 of K under a fixed seed. Each header byte is mixed with the
+// previous ciphertext byte, so the streams of both directions must see every
+// header exactly once and in order.
+
+use super::big_number::BigNumber;
+use super::hmac_sha1::HmacSha1;
+
+/// HMAC key the TBC client derives the header key with
+const SEED_KEY: [u8; 16] = [0x38, 0xA7, 0x83, 0x15, 0xF8, 0x92, 0x25, 0x30, 0x71, 0x98, 0x67, 0xB1, 0x8C, 0x04, 0xE2, 0xAA];
+
+/// Header cipher of one world connection
+#[derive(Debug, Clone, Default)]
+pub struct AuthCrypt {
+    key: [u8; HmacSha1::DIGEST_LENGTH],
+    send_i: usize,
+    send_j: u8,
+    recv_i: usize,
+    recv_j: u8,
+    initialized: bool,
+}
+
+impl AuthCrypt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Derive the header key from the 40 byte session key `k`
+    pub fn init(&mut self, k: &BigNumber) {
+        let mut hmac = HmacSha1::new(&SEED_KEY);
+        hmac.update_data(&k.as_byte_array(40));
+        hmac.finalize();
+        self.key = *hmac.get_digest();
+        self.send_i = 0;
+        self.send_j = 0;
+        self.recv_i = 0;
+        self.recv_j = 0;
+        self.initialized = true;
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// Decrypt a received header in place; no-op before init
+    pub fn decrypt_recv(&mut self, data: &mut [u8]) {
+        if !self.initialized {
+            return;
+        }
+        for byte in data {
+            self.recv_i %= self.key.len();
+            let x = byte.wrapping_sub(self.recv_j) ^ self.key[self.recv_i];
+            self.recv_i += 1;
+            self.recv_j = *byte;
+            *byte = x;
+        }
+    }
+
+    /// Encrypt a header to send in place; no-op before init
+    pub fn encrypt_send(&mut self, data: &mut [u8]) {
+        if !self.initialized {
+            return;
+        }
+        for byte in data {
+            self.send_i %= self.key.len();
+            let x = (*byte ^ self.key[self.send_i]).wrapping_add(self.send_j);
+            self.send_i += 1;
+            self.send_j = x;
+            *byte = x;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_key() -> BigNumber {
+        let mut k = BigNumber::new();
+        k.set_hex_str("4F8E2C61B7D8A3F912E5C7B8D9A0E1F2C3D4E5F6A7B8C9D0E1F2A3B4C5D6E7F8091A2B3C4D5E6F70");
+        k
+    }
+
+    #[test]
+    fn test_uninitialized_is_plain() {
+        let mut crypt = AuthCrypt::new();
+        let mut header = [0x00, 0x06, 0xEC, 0x01];
+        crypt.encrypt_send(&mut header);
+        assert_eq!(header, [0x00, 0x06, 0xEC, 0x01]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // The server's send stream is the client's receive stream
+        let (mut server, mut client) = (AuthCrypt::new(), AuthCrypt::new());
+        server.init(&session_key());
+        client.init(&session_key());
+        for header in [[0x00u8, 0x06, 0xEE, 0x01], [0x00, 0x0A, 0xDD, 0x01], [0x00, 0x06, 0xEE, 0x01]] {
+            let mut data = header;
+            server.encrypt_send(&mut data);
+            assert_ne!(data, header);
+            client.decrypt_recv(&mut data);
+            assert_eq!(data, header);
+        }
+    }
+
+    #[test]
+    fn test_stream_state() {
+        // The same header encrypts differently the second time
+        let mut crypt = AuthCrypt::new();
+        crypt.init(&session_key());
+        let (mut first, mut second) = ([0u8; 4], [0u8; 4]);
+        crypt.encrypt_send(&mut first);
+        crypt.encrypt_send(&mut second);
+        assert_ne!(first, second);
+    }
+}
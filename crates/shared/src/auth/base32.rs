@@ -1,4 +1,4 @@
-// Base32 decoding
+// Base32 encoding/decoding
 // Rust equivalent of base32.h/cpp
 // Uses data-encoding crate for RFC 4648 Base32
 
@@ -35,6 +35,11 @@ pub fn base32_decode(input: &str) -> Result<Vec<u8>, String> {
         .map_err(|e| format!("base32 decode error: {}", e))
 }
 
+/// Encode bytes as unpadded upper-case base32, the form authenticator apps expect
+pub fn base32_encode(data: &[u8]) -> String {
+    data_encoding::BASE32_NOPAD.encode(data)
+}
+
 /// Decode base32 into a pre-allocated buffer (matching C++ API)
 /// Returns the number of bytes decoded, or -1 on error
 pub fn base32_decode_into(input: &str, output: &mut [u8]) -> i32 {
@@ -54,14 +59,21 @@ mod tests {
 
     #[test]
     fn test_base32_decode() {
-        // "JBSWY3DPEHPK3PXP" encodes "Hello!"
+        // "JBSWY3DPEHPK3PXP" encodes "Hello!" followed by 0xDEADBEEF
         let result = base32_decode("JBSWY3DPEHPK3PXP").unwrap();
-        assert_eq!(result, b"Hello!");
+        assert_eq!(result, b"Hello!\xDE\xAD\xBE\xEF");
     }
 
     #[test]
     fn test_base32_with_whitespace() {
         let result = base32_decode("JBSW Y3DP EHPK 3PXP").unwrap();
-        assert_eq!(result, b"Hello!");
+        assert_eq!(result, b"Hello!\xDE\xAD\xBE\xEF");
+    }
+
+    #[test]
+    fn test_base32_encode_round_trip() {
+        assert_eq!(base32_encode(b"Hello!\xDE\xAD\xBE\xEF"), "JBSWY3DPEHPK3PXP");
+        assert_eq!(base32_encode(b"Hello!"), "JBSWY3DPEE");
+        assert_eq!(base32_decode(&base32_encode(b"Hello!")).unwrap(), b"Hello!");
     }
 }
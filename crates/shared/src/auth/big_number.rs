@@ -2,8 +2,9 @@
 // Rust equivalent of BigNumber.h/cpp using num-bigint
 
 use num_bigint::{BigUint, RandBigInt};
-use num_traits::Zero;
+use num_traits::{One, Zero};
 use rand::thread_rng;
+use zeroize::Zeroize;
 
 /// BigNumber wraps num-bigint's BigUint for cryptographic operations.
 /// Mirrors the C++ BigNumber class that wraps OpenSSL's BIGNUM.
@@ -164,6 +165,89 @@ impl BigNumber {
     }
 }
 
+/// Precomputed powers of a fixed base for repeated `base^exp mod m`
+///
+/// Holds base^(d * 16^i) mod m for every 4-bit digit d of an exponent up to
+/// `max_bits` long, so an exponentiation is one modular multiplication per
+/// digit and no squarings. Worth it when the base and modulus never change,
+/// like g and N in SRP6.
+#[derive(Debug, Clone)]
+pub struct FixedBaseExp {
+    modulus: BigUint,
+    base: BigUint,
+    table: Vec<[BigUint; 16]>,
+}
+
+impl FixedBaseExp {
+    const WINDOW_BITS: u64 = 4;
+
+    /// Build the table for exponents of up to `max_bits` bits
+    pub fn new(base: &BigNumber, modulus: &BigNumber, max_bits: u64) -> Self {
+        let m = &modulus.bn;
+        let windows = max_bits.div_ceil(Self::WINDOW_BITS) as usize;
+        let mut table = Vec::with_capacity(windows);
+        let mut power = &base.bn % m;
+        for _ in 0..windows {
+            let mut row: [BigUint; 16] = std::array::from_fn(|_| BigUint::one() % m);
+            for d in 1..16 {
+                row[d] = (&row[d - 1] * &power) % m;
+            }
+            // base^(16^(i + 1)) = base^(15 * 16^i) * base^(16^i)
+            power = (&row[15] * &power) % m;
+            table.push(row);
+        }
+
+        FixedBaseExp {
+            modulus: m.clone(),
+            base: base.bn.clone(),
+            table,
+        }
+    }
+
+    /// base^exp mod modulus
+    ///
+    /// Every digit costs one multiplication, zero digits included, so the
+    /// work done doesn't depend on the exponent's value. Exponents longer
+    /// than the table fall back to a regular modpow.
+    pub fn mod_exp(&self, exp: &BigNumber) -> BigNumber {
+        if exp.bn.bits() > self.table.len() as u64 * Self::WINDOW_BITS {
+            return BigNumber {
+                bn: self.base.modpow(&exp.bn, &self.modulus),
+            };
+        }
+
+        let mut digits = exp.bn.to_bytes_le();
+        digits.resize(self.table.len().div_ceil(2), 0);
+        let mut result = BigUint::one();
+        for (i, row) in self.table.iter().enumerate() {
+            let d = (digits[i / 2] >> ((i % 2) * 4)) & 0x0F;
+            result = (result * &row[d as usize]) % &self.modulus;
+        }
+        digits.zeroize();
+
+        BigNumber { bn: result }
+    }
+}
+
+/// Wipes the digits in place, for numbers holding key material
+///
+/// num-bigint has no mutable access to its digit buffer, so the buffer is
+/// overwritten through assign_from_slice with a value of the same length
+/// (all zero digits but the top one, which keeps the length and therefore
+/// the allocation). Temporaries created during arithmetic are not covered.
+impl Zeroize for BigNumber {
+    fn zeroize(&mut self) {
+        let digits = self.bn.iter_u64_digits().len();
+        if digits > 0 {
+            let mut wipe = vec![0u32; digits * 2];
+            wipe[digits * 2 - 1] = 1;
+            self.bn.assign_from_slice(&wipe);
+            std::hint::black_box(&self.bn);
+        }
+        self.bn = BigUint::zero();
+    }
+}
+
 // Arithmetic operator implementations
 
 impl std::ops::Add for &BigNumber {
@@ -259,6 +343,33 @@ mod tests {
         assert_eq!(sum.as_dword(), 15);
     }
 
+    #[test]
+    fn test_zeroize() {
+        let mut bn = BigNumber::new();
+        bn.set_hex_str("894B645E89E1535BBDAD5B8B290650530801B18EBFBF5E8FAB3C82872A3E9BB7");
+        bn.zeroize();
+        assert!(bn.is_zero());
+
+        let mut zero = BigNumber::new();
+        zero.zeroize();
+        assert!(zero.is_zero());
+    }
+
+    #[test]
+    fn test_fixed_base_exp() {
+        let mut n = BigNumber::new();
+        n.set_hex_str("894B645E89E1535BBDAD5B8B290650530801B18EBFBF5E8FAB3C82872A3E9BB7");
+        let g = BigNumber::from_u32(7);
+        let table = FixedBaseExp::new(&g, &n, 160);
+
+        for bits in [1, 8, 152, 160, 256] {
+            let mut exp = BigNumber::new();
+            exp.set_rand(bits);
+            assert_eq!(table.mod_exp(&exp), g.mod_exp(&exp, &n), "{} bit exponent", bits);
+        }
+        assert_eq!(table.mod_exp(&BigNumber::new()).as_dword(), 1);
+    }
+
     #[test]
     fn test_hex_roundtrip() {
         let mut bn = BigNumber::new();
@@ -0,0 +1,33 @@
+// Constant-time comparison for secret-derived values
+//
+// A plain == on byte slices returns at the first differing byte, so the time
+// it takes tells an attacker how many leading bytes of a guessed proof were
+// right. Proofs, digests and tokens are compared with this instead.
+
+use std::hint::black_box;
+
+/// Compare two byte slices in time independent of their contents
+///
+/// Only the lengths may leak, which are public for every value compared in
+/// the auth path (fixed-size digests).
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | black_box(x ^ y));
+    black_box(diff) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!constant_time_eq(&[0, 2, 3], &[1, 2, 3]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2]));
+    }
+}
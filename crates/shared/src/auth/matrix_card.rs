@@ -0,0 +1,144 @@
+// Matrix card (security flag 0x02) generation and verification
+//
+// A matrix card is a grid of cells holding a few digits each, handed to the
+// player on paper. The logon challenge sends the card size, the number of
+// cells to ask for and a random seed; the client derives the cells from the
+// seed, asks the player for their digits and answers with
+// HMAC-SHA1(key = MD5(session key), digits of every asked cell in order).
+//
+// Cells are picked the way the PIN grid is shuffled: each pick takes
+// `seed % cells left` among the cells not asked yet (row-major order), then
+// divides the seed by that count.
+
+use rand::Rng;
+
+use super::constant_time::constant_time_eq;
+use super::crypto_hash::Md5Hash;
+use super::hmac_sha1::HmacSha1;
+
+/// A matrix card: `width` x `height` cells of `digit_count` digits each
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatrixCard {
+    pub width: u8,
+    pub height: u8,
+    pub digit_count: u8,
+    /// Digit values (0-9), row by row, `digit_count` per cell
+    digits: Vec<u8>,
+}
+
+impl MatrixCard {
+    /// Random card of the given size
+    pub fn generate(width: u8, height: u8, digit_count: u8) -> Self {
+        let mut rng = rand::thread_rng();
+        let len = width as usize * height as usize * digit_count as usize;
+        MatrixCard {
+            width,
+            height,
+            digit_count,
+            digits: (0..len).map(|_| rng.gen_range(0..10)).collect(),
+        }
+    }
+
+    /// Card from its stored form, a string of all digits row by row
+    pub fn from_digit_string(width: u8, height: u8, digit_count: u8, text: &str) -> Option<Self> {
+        let digits: Vec<u8> = text
+            .trim()
+            .chars()
+            .map(|c| c.to_digit(10).map(|d| d as u8))
+            .collect::<Option<_>>()?;
+        let cells = width as usize * height as usize;
+        if cells == 0 || digit_count == 0 || digits.len() != cells * digit_count as usize {
+            return None;
+        }
+        Some(MatrixCard { width, height, digit_count, digits })
+    }
+
+    /// Stored form of the card (see [`MatrixCard::from_digit_string`])
+    pub fn to_digit_string(&self) -> String {
+        self.digits.iter().map(|d| char::from(b'0' + d)).collect()
+    }
+
+    /// Digits of the cell in column `x`, row `y`
+    pub fn cell(&self, x: u8, y: u8) -> &[u8] {
+        let start = (y as usize * self.width as usize + x as usize) * self.digit_count as usize;
+        &self.digits[start..start + self.digit_count as usize]
+    }
+
+    /// Number of cells on the card
+    pub fn cells(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// Proof the client sends for `challenge_count` cells picked by `seed`
+    pub fn proof(&self, session_key: &[u8], challenge_count: u8, seed: u64) -> [u8; 20] {
+        let mut md5 = Md5Hash::new();
+        md5.update_data_bytes(session_key);
+        md5.finalize();
+
+        let mut hmac = HmacSha1::new(md5.get_digest());
+        for (x, y) in challenge_coordinates(self.width, self.height, challenge_count, seed) {
+            hmac.update_data(self.cell(x, y));
+        }
+        hmac.finalize();
+        *hmac.get_digest()
+    }
+
+    /// Check a client's matrix card proof
+    pub fn verify(&self, session_key: &[u8], challenge_count: u8, seed: u64, client_proof: &[u8; 20]) -> bool {
+        constant_time_eq(&self.proof(session_key, challenge_count, seed), client_proof)
+    }
+}
+
+/// Cells (column, row) the client asks for, in order
+pub fn challenge_coordinates(width: u8, height: u8, challenge_count: u8, seed: u64) -> Vec<(u8, u8)> {
+    let width = width as u32;
+    let mut remaining: Vec<u32> = (0..width * height as u32).collect();
+    let mut seed = seed;
+    let mut coordinates = Vec::with_capacity(challenge_count as usize);
+    for _ in 0..challenge_count {
+        if remaining.is_empty() {
+            break;
+        }
+        let count = remaining.len() as u64;
+        let cell = remaining.remove((seed % count) as usize);
+        seed /= count;
+        coordinates.push(((cell % width) as u8, (cell / width) as u8));
+    }
+    coordinates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_coordinates() {
+        // Seed 0 always takes the first cell left
+        assert_eq!(challenge_coordinates(8, 10, 3, 0), vec![(0, 0), (1, 0), (2, 0)]);
+
+        // 83 = 3 + 80 * 1: cell 3, then the second of the 79 cells left
+        assert_eq!(challenge_coordinates(8, 10, 2, 83), vec![(3, 0), (1, 0)]);
+
+        let coordinates = challenge_coordinates(4, 3, 12, u64::MAX);
+        let mut unique = coordinates.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 12);
+        assert!(coordinates.iter().all(|&(x, y)| x < 4 && y < 3));
+    }
+
+    #[test]
+    fn test_matrix_card_proof() {
+        let card = MatrixCard::generate(8, 10, 2);
+        let stored = card.to_digit_string();
+        assert_eq!(stored.len(), 160);
+        assert_eq!(MatrixCard::from_digit_string(8, 10, 2, &stored), Some(card.clone()));
+        assert_eq!(MatrixCard::from_digit_string(8, 10, 3, &stored), None);
+
+        let session_key = [0x5Au8; 40];
+        let proof = card.proof(&session_key, 3, 123_456_789);
+        assert!(card.verify(&session_key, 3, 123_456_789, &proof));
+        assert!(!card.verify(&session_key, 3, 987_654_321, &proof));
+        assert!(!card.verify(&[0u8; 40], 3, 123_456_789, &proof));
+    }
+}
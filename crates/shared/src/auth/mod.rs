@@ -1,13 +1,27 @@
 // Auth module - cryptographic primitives and authentication protocols
 
+pub mod arc4;
+pub mod auth_crypt;
 pub mod big_number;
+pub mod constant_time;
 pub mod crypto_hash;
 pub mod hmac_sha1;
+pub mod matrix_card;
 pub mod srp6;
+pub mod srp6_client;
 pub mod base32;
+pub mod session_key;
+pub mod totp;
+pub mod warden;
 
-pub use big_number::BigNumber;
+pub use arc4::Arc4;
+pub use auth_crypt::AuthCrypt;
+pub use big_number::{BigNumber, FixedBaseExp};
+pub use constant_time::constant_time_eq;
 pub use crypto_hash::{Sha1Hash, Md5Hash};
 pub use hmac_sha1::HmacSha1;
+pub use matrix_card::MatrixCard;
 pub use srp6::SRP6;
-pub use base32::base32_decode;
+pub use srp6_client::SRP6Client;
+pub use base32::{base32_decode, base32_encode};
+pub use warden::{WardenCrypt, WardenKeyGenerator, WardenModule, WardenModuleCache};
@@ -0,0 +1,150 @@
+// SessionKey - Encryption of account.sessionkey at rest
+//
+// With SessionKey.EncryptionKey set (64 hex digits, an AES-256 key) realmd
+// stores session keys as
+//   GCM:<hex nonce, ciphertext and tag>
+// sealed with AES-GCM and bound to the account name, so a database dump or a
+// copied row does not hand out live sessions. Reconnects decrypt the key
+// again, and world servers are given the same SessionKey.EncryptionKey.
+// Nothing hands the opened key out over the network.
+//
+// Keys stored in plain hex keep working, so the option can be switched on
+// while players are online; switching it off again logs out everyone whose
+// key is encrypted.
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use data_encoding::HEXUPPER;
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use crate::config::Config;
+
+/// Prefix of encrypted session keys
+const SEALED_PREFIX: &str = "GCM:";
+
+const NONCE_LEN: usize = 12;
+
+/// Cipher from SessionKey.EncryptionKey, None when keys are stored in plain hex
+static CIPHER: OnceCell<Option<Aes256Gcm>> = OnceCell::new();
+
+/// Read SessionKey.EncryptionKey; fails when it is set but not a 256 bit hex key
+pub fn initialize(config: &Config) -> anyhow::Result<()> {
+    let configured = Zeroizing::new(config.get_string("SessionKey.EncryptionKey"));
+    let cipher = if configured.trim().is_empty() {
+        None
+    } else {
+        let key = Zeroizing::new(
+            HEXUPPER
+                .decode(configured.trim().to_uppercase().as_bytes())
+                .map_err(|_| anyhow::anyhow!("SessionKey.EncryptionKey must be hex digits"))?,
+        );
+        anyhow::ensure!(key.len() == 32, "SessionKey.EncryptionKey must be 64 hex digits, not {}", configured.trim().len());
+        tracing::info!("Session keys are stored encrypted");
+        Some(Aes256Gcm::new_from_slice(&key)?)
+    };
+    let _ = CIPHER.set(cipher);
+    Ok(())
+}
+
+fn cipher() -> Option<&'static Aes256Gcm> {
+    CIPHER.get().and_then(Option::as_ref)
+}
+
+/// Value to store in account.sessionkey of `login` for the hex session key `key_hex`
+pub fn seal(login: &str, key_hex: &str) -> Zeroizing<String> {
+    seal_with(cipher(), login, key_hex)
+}
+
+fn seal_with(cipher: Option<&Aes256Gcm>, login: &str, key_hex: &str) -> Zeroizing<String> {
+    let Some(cipher) = cipher else {
+        return Zeroizing::new(key_hex.to_string());
+    };
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let account = login.to_uppercase();
+    let payload = Payload { msg: key_hex.as_bytes(), aad: account.as_bytes() };
+    // Encryption only fails for messages far larger than a session key
+    let sealed = cipher.encrypt(Nonce::from_slice(&nonce), payload).expect("session key too large to encrypt");
+    let mut bytes = Zeroizing::new(nonce.to_vec());
+    bytes.extend_from_slice(&sealed);
+    Zeroizing::new(format!("{}{}", SEALED_PREFIX, HEXUPPER.encode(&bytes)))
+}
+
+/// Hex session key from the account.sessionkey value of `login`
+pub fn open(login: &str, stored: &str) -> anyhow::Result<Zeroizing<String>> {
+    open_with(cipher(), login, stored)
+}
+
+fn open_with(cipher: Option<&Aes256Gcm>, login: &str, stored: &str) -> anyhow::Result<Zeroizing<String>> {
+    let Some(sealed) = stored.strip_prefix(SEALED_PREFIX) else {
+        return Ok(Zeroizing::new(stored.to_string()));
+    };
+    let cipher = cipher.ok_or_else(|| anyhow::anyhow!("session key is encrypted but SessionKey.EncryptionKey is not set"))?;
+    let bytes = HEXUPPER
+        .decode(sealed.as_bytes())
+        .map_err(|_| anyhow::anyhow!("encrypted session key is not hex"))?;
+    anyhow::ensure!(bytes.len() > NONCE_LEN, "encrypted session key is truncated");
+    let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+    let account = login.to_uppercase();
+    let key = cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad: account.as_bytes() })
+        .map_err(|_| anyhow::anyhow!("session key does not decrypt, wrong SessionKey.EncryptionKey or account"))?;
+    Ok(Zeroizing::new(String::from_utf8(key)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF";
+
+    fn test_cipher() -> Aes256Gcm {
+        Aes256Gcm::new_from_slice(&[7u8; 32]).unwrap()
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let cipher = test_cipher();
+        let sealed = seal_with(Some(&cipher), "alice", KEY);
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(!sealed.contains(KEY));
+        // Account names are matched like logins, whatever their case
+        assert_eq!(open_with(Some(&cipher), "ALICE", &sealed).unwrap().as_str(), KEY);
+        // A fresh nonce every time
+        assert_ne!(*seal_with(Some(&cipher), "alice", KEY), *sealed);
+    }
+
+    #[test]
+    fn test_open_refuses_other_accounts_and_tampering() {
+        let cipher = test_cipher();
+        let sealed = seal_with(Some(&cipher), "alice", KEY);
+        // A row copied to another account does not decrypt
+        assert!(open_with(Some(&cipher), "bob", &sealed).is_err());
+
+        let mut tampered = sealed.to_string();
+        let last = tampered.pop().unwrap();
+        tampered.push(if last == '0' { '1' } else { '0' });
+        assert!(open_with(Some(&cipher), "alice", &tampered).is_err());
+        assert!(open_with(Some(&cipher), "alice", "GCM:00AA").is_err());
+        assert!(open_with(Some(&cipher), "alice", "GCM:not hex").is_err());
+
+        let other = Aes256Gcm::new_from_slice(&[8u8; 32]).unwrap();
+        assert!(open_with(Some(&other), "alice", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_plain_keys() {
+        // Without a cipher keys are stored as they are
+        assert_eq!(seal_with(None, "alice", KEY).as_str(), KEY);
+        assert_eq!(open_with(None, "alice", KEY).unwrap().as_str(), KEY);
+        // Keys stored before encryption was switched on keep working
+        assert_eq!(open_with(Some(&test_cipher()), "alice", KEY).unwrap().as_str(), KEY);
+
+        // A sealed key cannot be read once the cipher is gone
+        let sealed = seal_with(Some(&test_cipher()), "alice", KEY);
+        let error = open_with(None, "alice", &sealed).unwrap_err();
+        assert!(error.to_string().contains("SessionKey.EncryptionKey is not set"), "{}", error);
+    }
+}
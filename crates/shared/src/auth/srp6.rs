@@ -2,19 +2,46 @@
 // Rust equivalent of SRP6.h/cpp
 //
 // This implements the WoW-specific SRP6 authentication protocol.
-// The protocol constants (N, g) are specific to the WoW client.
+// The protocol constants (N, g) are specific to the WoW client. They, H(N) xor
+// H(g) and a table of powers of g are computed once and shared by every
+// login, so a handshake only pays for its own ephemeral values.
 
-use super::big_number::BigNumber;
+use once_cell::sync::Lazy;
+
+use super::big_number::{BigNumber, FixedBaseExp};
+use super::constant_time::constant_time_eq;
 use super::crypto_hash::Sha1Hash;
+use zeroize::Zeroize;
 
-/// SRP6 protocol state
-/// Implements the server side of the SRP6 authentication handshake.
-pub struct SRP6 {
-    // Protocol parameters
+/// Constants shared by every SRP6 session
+struct SrpConstants {
     /// Safe prime (N) - the large prime modulus
     n: BigNumber,
     /// Generator modulo (g)
     g: BigNumber,
+    /// H(N) XOR H(g), the first part of every proof
+    ng_hash: [u8; Sha1Hash::DIGEST_LENGTH],
+    /// Powers of g for g^b (b is 152 bits) and g^x (x is 160 bits)
+    g_powers: FixedBaseExp,
+}
+
+static CONSTANTS: Lazy<SrpConstants> = Lazy::new(|| {
+    let mut n = BigNumber::new();
+    n.set_hex_str("894B645E89E1535BBDAD5B8B290650530801B18EBFBF5E8FAB3C82872A3E9BB7");
+    let g = BigNumber::from_u32(7);
+
+    SrpConstants {
+        ng_hash: ng_hash(&n, &g),
+        g_powers: FixedBaseExp::new(&g, &n, Sha1Hash::DIGEST_LENGTH as u64 * 8),
+        n,
+        g,
+    }
+});
+
+/// SRP6 protocol state
+/// Implements the server side of the SRP6 authentication handshake.
+pub struct SRP6 {
+    // Protocol parameters
     /// Salt (s) - random per-account
     s: BigNumber,
     /// Password verifier (v) - stored in database
@@ -45,20 +72,25 @@ impl Default for SRP6 {
     }
 }
 
+/// Wipe the private ephemeral, verifier and session keys when the session ends
+impl Drop for SRP6 {
+    fn drop(&mut self) {
+        self.v.zeroize();
+        self.b.zeroize();
+        self.u.zeroize();
+        self.big_s.zeroize();
+        self.k.zeroize();
+        self.m.zeroize();
+    }
+}
+
 impl SRP6 {
     /// Salt byte size
     pub const S_BYTE_SIZE: usize = 32;
 
     /// Create a new SRP6 instance with the WoW-specific prime and generator
     pub fn new() -> Self {
-        let mut n = BigNumber::new();
-        n.set_hex_str("894B645E89E1535BBDAD5B8B290650530801B18EBFBF5E8FAB3C82872A3E9BB7");
-
-        let g = BigNumber::from_u32(7);
-
         SRP6 {
-            n,
-            g,
             s: BigNumber::new(),
             v: BigNumber::new(),
             b: BigNumber::new(),
@@ -76,10 +108,10 @@ impl SRP6 {
     /// B = (v * 3 + g^b mod N) mod N
     pub fn calculate_host_public_ephemeral(&mut self) {
         self.b.set_rand(19 * 8);
-        let g_mod = self.g.mod_exp(&self.b, &self.n);
+        let g_mod = CONSTANTS.g_powers.mod_exp(&self.b);
         let v_times_3 = &self.v * 3u32;
         let sum = &v_times_3 + &g_mod;
-        self.big_b = &sum % &self.n;
+        self.big_b = &sum % &CONSTANTS.n;
 
         assert!(g_mod.get_num_bytes() <= 32);
     }
@@ -87,36 +119,7 @@ impl SRP6 {
     /// Calculate proof (M) of the strong session key (K)
     /// M = SHA1(H(N) XOR H(g) || H(username) || s || A || B || K)
     pub fn calculate_proof(&mut self, username: &str) {
-        // H(N)
-        let mut sha = Sha1Hash::new();
-        sha.update_big_numbers(&[&self.n]);
-        sha.finalize();
-        let mut hash = *sha.get_digest();
-
-        // H(g)
-        sha.initialize();
-        sha.update_big_numbers(&[&self.g]);
-        sha.finalize();
-
-        // H(N) XOR H(g)
-        for (i, byte) in hash.iter_mut().enumerate().take(20) {
-            *byte ^= sha.get_digest()[i];
-        }
-
-        // H(username)
-        sha.initialize();
-        sha.update_data(username);
-        sha.finalize();
-        let t4 = *sha.get_digest();
-
-        // M = SHA1(H(N) XOR H(g) || H(username) || s || A || B || K)
-        sha.initialize();
-        sha.update_data_bytes(&hash);
-        sha.update_data_bytes(&t4);
-        sha.update_big_numbers(&[&self.s, &self.big_a, &self.big_b, &self.k]);
-        sha.finalize();
-
-        self.m.set_binary(sha.get_digest());
+        self.m = session_proof(&CONSTANTS.ng_hash, username, &self.s, &self.big_a, &self.big_b, &self.k);
     }
 
     /// Calculate the session key (S) based on client public ephemeral (A)
@@ -132,7 +135,7 @@ impl SRP6 {
         }
 
         // SRP safeguard: abort if A % N == 0
-        let a_mod_n = &self.big_a % &self.n;
+        let a_mod_n = &self.big_a % &CONSTANTS.n;
         if a_mod_n.is_zero() {
             return false;
         }
@@ -144,9 +147,9 @@ impl SRP6 {
         self.u.set_binary(sha.get_digest());
 
         // S = (A * v^u mod N)^b mod N
-        let v_mod = self.v.mod_exp(&self.u, &self.n);
+        let v_mod = self.v.mod_exp(&self.u, &CONSTANTS.n);
         let a_times_v = &self.big_a * &v_mod;
-        self.big_s = a_times_v.mod_exp(&self.b, &self.n);
+        self.big_s = a_times_v.mod_exp(&self.b, &CONSTANTS.n);
 
         true
     }
@@ -189,7 +192,7 @@ impl SRP6 {
         x.set_binary(sha.get_digest());
 
         // v = g^x mod N
-        self.v = self.g.mod_exp(&x, &self.n);
+        self.v = CONSTANTS.g_powers.mod_exp(&x);
 
         true
     }
@@ -197,34 +200,7 @@ impl SRP6 {
     /// Generate the strong session key (K) from session key (S)
     /// K is derived by interleaving SHA1 hashes of even/odd bytes of S
     pub fn hash_session_key(&mut self) {
-        let t = self.big_s.as_byte_array(32);
-        let mut t1 = [0u8; 16];
-        let mut vk = [0u8; 40];
-
-        // Hash even bytes
-        for i in 0..16 {
-            t1[i] = t[i * 2];
-        }
-        let mut sha = Sha1Hash::new();
-        sha.initialize();
-        sha.update_data_bytes(&t1);
-        sha.finalize();
-        for i in 0..20 {
-            vk[i * 2] = sha.get_digest()[i];
-        }
-
-        // Hash odd bytes
-        for i in 0..16 {
-            t1[i] = t[i * 2 + 1];
-        }
-        sha.initialize();
-        sha.update_data_bytes(&t1);
-        sha.finalize();
-        for i in 0..20 {
-            vk[i * 2 + 1] = sha.get_digest()[i];
-        }
-
-        self.k.set_binary(&vk);
+        self.k = interleave_session_key(&self.big_s);
     }
 
     /// Verify client proof (M)
@@ -237,13 +213,13 @@ impl SRP6 {
     /// `if !srp.proof(...)` for the failure branch.
     pub fn proof(&self, client_m: &[u8]) -> bool {
         let our_m = self.m.as_byte_array(client_m.len());
-        our_m[..client_m.len()] == client_m[..client_m.len()]
+        constant_time_eq(&our_m[..client_m.len()], client_m)
     }
 
     /// Verify password verifier (v) against a database value
     pub fn proof_verifier(&self, vc: &str) -> bool {
         let v_hex = self.v.as_hex_str();
-        vc == v_hex
+        constant_time_eq(vc.as_bytes(), v_hex.as_bytes())
     }
 
     /// Generate server proof hash for client verification
@@ -260,11 +236,11 @@ impl SRP6 {
     }
 
     pub fn get_generator_modulo(&self) -> &BigNumber {
-        &self.g
+        &CONSTANTS.g
     }
 
     pub fn get_prime(&self) -> &BigNumber {
-        &self.n
+        &CONSTANTS.n
     }
 
     pub fn get_proof(&self) -> &BigNumber {
@@ -303,6 +279,85 @@ impl SRP6 {
     }
 }
 
+/// H(N) XOR H(g)
+pub(super) fn ng_hash(n: &BigNumber, g: &BigNumber) -> [u8; Sha1Hash::DIGEST_LENGTH] {
+    // H(N)
+    let mut sha = Sha1Hash::new();
+    sha.update_big_numbers(&[n]);
+    sha.finalize();
+    let mut hash = *sha.get_digest();
+
+    // H(g)
+    sha.initialize();
+    sha.update_big_numbers(&[g]);
+    sha.finalize();
+
+    for (byte, g_byte) in hash.iter_mut().zip(sha.get_digest()) {
+        *byte ^= g_byte;
+    }
+    hash
+}
+
+/// M = SHA1(H(N) XOR H(g) || H(username) || s || A || B || K), shared with the client side
+pub(super) fn session_proof(
+    ng_hash: &[u8; Sha1Hash::DIGEST_LENGTH],
+    username: &str,
+    s: &BigNumber,
+    big_a: &BigNumber,
+    big_b: &BigNumber,
+    k: &BigNumber,
+) -> BigNumber {
+    // H(username)
+    let mut sha = Sha1Hash::new();
+    sha.update_data(username);
+    sha.finalize();
+    let t4 = *sha.get_digest();
+
+    sha.initialize();
+    sha.update_data_bytes(ng_hash);
+    sha.update_data_bytes(&t4);
+    sha.update_big_numbers(&[s, big_a, big_b, k]);
+    sha.finalize();
+
+    let mut m = BigNumber::new();
+    m.set_binary(sha.get_digest());
+    m
+}
+
+/// K = interleaved SHA1 hashes of the even and odd bytes of S
+pub(super) fn interleave_session_key(big_s: &BigNumber) -> BigNumber {
+    let t = big_s.as_byte_array(32);
+    let mut t1 = [0u8; 16];
+    let mut vk = [0u8; 40];
+
+    // Hash even bytes
+    for i in 0..16 {
+        t1[i] = t[i * 2];
+    }
+    let mut sha = Sha1Hash::new();
+    sha.initialize();
+    sha.update_data_bytes(&t1);
+    sha.finalize();
+    for i in 0..20 {
+        vk[i * 2] = sha.get_digest()[i];
+    }
+
+    // Hash odd bytes
+    for i in 0..16 {
+        t1[i] = t[i * 2 + 1];
+    }
+    sha.initialize();
+    sha.update_data_bytes(&t1);
+    sha.finalize();
+    for i in 0..20 {
+        vk[i * 2 + 1] = sha.get_digest()[i];
+    }
+
+    let mut k = BigNumber::new();
+    k.set_binary(&vk);
+    k
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -310,8 +365,8 @@ mod tests {
     #[test]
     fn test_srp6_init() {
         let srp = SRP6::new();
-        assert!(!srp.n.is_zero());
-        assert_eq!(srp.g.as_dword(), 7);
+        assert!(!srp.get_prime().is_zero());
+        assert_eq!(srp.get_generator_modulo().as_dword(), 7);
     }
 
     #[test]
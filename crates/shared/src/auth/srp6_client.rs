@@ -0,0 +1,209 @@
+// SRP6Client - Client side of the WoW SRP6 handshake
+//
+// Computes what the game client sends during login (A, M1) from the server's
+// logon challenge (B, g, N, salt) and the account credentials, so a login can
+// be driven from Rust: integration tests, the load tester, tooling.
+
+use super::big_number::BigNumber;
+use super::constant_time::constant_time_eq;
+use super::crypto_hash::Sha1Hash;
+use super::srp6::{interleave_session_key, ng_hash, session_proof};
+use zeroize::Zeroize;
+
+/// SRP6 client state for one login attempt
+pub struct SRP6Client {
+    /// Upper-cased account name, as the client sends it
+    username: String,
+    /// SHA1(USERNAME:PASSWORD)
+    credentials_hash: [u8; Sha1Hash::DIGEST_LENGTH],
+
+    /// Client private ephemeral (a)
+    a: BigNumber,
+    /// Client public ephemeral (A) - sent to server
+    big_a: BigNumber,
+    /// Strong session key (K)
+    k: BigNumber,
+    /// Client proof (M1) - sent to server
+    m1: BigNumber,
+    /// Expected server proof (M2) = SHA1(A || M1 || K)
+    m2: [u8; Sha1Hash::DIGEST_LENGTH],
+}
+
+/// Wipe the credentials hash, private ephemeral and session keys
+impl Drop for SRP6Client {
+    fn drop(&mut self) {
+        self.credentials_hash.zeroize();
+        self.a.zeroize();
+        self.k.zeroize();
+        self.m1.zeroize();
+        self.m2.zeroize();
+    }
+}
+
+impl SRP6Client {
+    /// Start a login for `username`/`password` with a random private ephemeral
+    pub fn new(username: &str, password: &str) -> Self {
+        let username = username.to_uppercase();
+
+        let mut sha = Sha1Hash::new();
+        sha.update_data(&username);
+        sha.update_data(":");
+        sha.update_data(&password.to_uppercase());
+        sha.finalize();
+
+        let mut a = BigNumber::new();
+        a.set_rand(19 * 8);
+
+        SRP6Client {
+            username,
+            credentials_hash: *sha.get_digest(),
+            a,
+            big_a: BigNumber::new(),
+            k: BigNumber::new(),
+            m1: BigNumber::new(),
+            m2: [0u8; Sha1Hash::DIGEST_LENGTH],
+        }
+    }
+
+    /// Use a fixed private ephemeral (a) instead of a random one, for reproducible tests
+    pub fn set_private_ephemeral(&mut self, a: &BigNumber) {
+        self.a = a.clone();
+    }
+
+    /// Process the server's logon challenge and compute A, K, M1
+    /// All values are in wire (little-endian) byte order.
+    ///
+    /// Returns false if the challenge is invalid (B % N == 0 or u == 0)
+    pub fn process_challenge(&mut self, server_b: &[u8], g: &[u8], n: &[u8], salt: &[u8]) -> bool {
+        let mut big_b = BigNumber::new();
+        big_b.set_binary(server_b);
+        let mut big_g = BigNumber::new();
+        big_g.set_binary(g);
+        let mut big_n = BigNumber::new();
+        big_n.set_binary(n);
+        let mut s = BigNumber::new();
+        s.set_binary(salt);
+
+        if big_n.is_zero() || (&big_b % &big_n).is_zero() {
+            return false;
+        }
+
+        // A = g^a mod N
+        self.big_a = big_g.mod_exp(&self.a, &big_n);
+
+        // u = SHA1(A || B)
+        let mut sha = Sha1Hash::new();
+        sha.update_big_numbers(&[&self.big_a, &big_b]);
+        sha.finalize();
+        let mut u = BigNumber::new();
+        u.set_binary(sha.get_digest());
+        if u.is_zero() {
+            return false;
+        }
+
+        // x = SHA1(s || SHA1(USERNAME:PASSWORD))
+        sha.initialize();
+        sha.update_data_bytes(&s.as_byte_array(0));
+        sha.update_data_bytes(&self.credentials_hash);
+        sha.finalize();
+        let mut x = BigNumber::new();
+        x.set_binary(sha.get_digest());
+
+        // S = (B - 3 * g^x)^(a + u * x) mod N
+        let kgx = &(&big_g.mod_exp(&x, &big_n) * 3u32) % &big_n;
+        let base = &(&(&(&big_b % &big_n) + &big_n) - &kgx) % &big_n;
+        let mut exponent = &self.a + &(&u * &x);
+        let mut big_s = base.mod_exp(&exponent, &big_n);
+
+        self.k = interleave_session_key(&big_s);
+        self.m1 = session_proof(&ng_hash(&big_n, &big_g), &self.username, &s, &self.big_a, &big_b, &self.k);
+
+        // M2 = SHA1(A || M1 || K)
+        sha.initialize();
+        sha.update_big_numbers(&[&self.big_a, &self.m1, &self.k]);
+        sha.finalize();
+        self.m2 = *sha.get_digest();
+
+        x.zeroize();
+        big_s.zeroize();
+        exponent.zeroize();
+
+        true
+    }
+
+    /// Verify the server proof (M2) from the logon proof response
+    pub fn verify_server_proof(&self, server_m2: &[u8]) -> bool {
+        constant_time_eq(&self.m2, server_m2)
+    }
+
+    // Getters
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn get_client_public_ephemeral(&self) -> &BigNumber {
+        &self.big_a
+    }
+
+    pub fn get_proof(&self) -> &BigNumber {
+        &self.m1
+    }
+
+    pub fn get_strong_session_key(&self) -> &BigNumber {
+        &self.k
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::SRP6;
+
+    fn sha_pass_hash(username: &str, password: &str) -> String {
+        let mut sha = Sha1Hash::new();
+        sha.update_data(&format!("{}:{}", username.to_uppercase(), password.to_uppercase()));
+        sha.finalize();
+        sha.get_digest().iter().map(|b| format!("{:02X}", b)).collect()
+    }
+
+    /// Run the server and client halves against each other
+    fn login(stored_password: &str, entered_password: &str) -> (bool, bool) {
+        let mut server = SRP6::new();
+        server.calculate_verifier_random(&sha_pass_hash("Tester", stored_password));
+        server.calculate_host_public_ephemeral();
+
+        let mut client = SRP6Client::new("Tester", entered_password);
+        assert!(client.process_challenge(
+            &server.get_host_public_ephemeral().as_byte_array(32),
+            &server.get_generator_modulo().as_byte_array(1),
+            &server.get_prime().as_byte_array(32),
+            &server.get_salt().as_byte_array(32),
+        ));
+
+        assert!(server.calculate_session_key(&client.get_client_public_ephemeral().as_byte_array(32)));
+        server.hash_session_key();
+        server.calculate_proof(client.get_username());
+        let server_accepted = server.proof(&client.get_proof().as_byte_array(20));
+
+        let mut sha = Sha1Hash::new();
+        server.finalize(&mut sha);
+        let client_accepted = client.verify_server_proof(sha.get_digest());
+        if server_accepted {
+            assert_eq!(client.get_strong_session_key(), server.get_strong_session_key());
+        }
+        (server_accepted, client_accepted)
+    }
+
+    #[test]
+    fn test_client_server_login() {
+        assert_eq!(login("secret", "SECRET"), (true, true));
+        assert_eq!(login("secret", "wrong"), (false, false));
+    }
+
+    #[test]
+    fn test_rejects_invalid_challenge() {
+        let mut client = SRP6Client::new("Tester", "secret");
+        let n = SRP6::new().get_prime().as_byte_array(32);
+        assert!(!client.process_challenge(&n, &[7], &n, &[1; 32]));
+    }
+}
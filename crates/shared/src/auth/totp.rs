@@ -0,0 +1,175 @@
+// TOTP authenticator enrollment and verification
+//
+// Creates the shared secret stored in account.token and the otpauth:// URI
+// that authenticator apps (Google Authenticator, Authy, ...) import, usually
+// by scanning it as a QR code.
+//
+// Codes are checked against the current time step and `window` steps either
+// side of it (RFC 6238 section 5.2), so clients whose clock is slightly off
+// are still accepted.
+//
+// URI format: https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+
+use rand::RngCore;
+
+use super::base32::{base32_decode, base32_encode};
+use super::constant_time::constant_time_eq;
+use super::hmac_sha1::hmac_sha1;
+use crate::config::Config;
+
+/// Secret length in bytes; RFC 4226 recommends 160 bits for HMAC-SHA1
+pub const TOTP_SECRET_LENGTH: usize = 20;
+
+/// Code length, step length and accepted clock drift
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TotpParams {
+    /// Number of digits in a code (6-8)
+    pub digits: u32,
+    /// Seconds per time step
+    pub period: u64,
+    /// Time steps accepted before and after the current one
+    pub window: u64,
+}
+
+impl Default for TotpParams {
+    fn default() -> Self {
+        TotpParams {
+            digits: 6,
+            period: 30,
+            window: 1,
+        }
+    }
+}
+
+impl TotpParams {
+    /// Read `Auth.TotpDigits`, `Auth.TotpPeriod` (seconds) and `Auth.TotpWindow` (steps)
+    pub fn from_config(config: &Config) -> Self {
+        TotpParams {
+            digits: config.get_int_default("Auth.TotpDigits", 6).clamp(6, 8) as u32,
+            period: config.get_int_default("Auth.TotpPeriod", 30).max(1) as u64,
+            window: config.get_int_default("Auth.TotpWindow", 1).max(0) as u64,
+        }
+    }
+}
+
+/// HOTP value (RFC 4226) of `secret` for time step `step`
+pub fn totp_code(secret: &[u8], step: u64, digits: u32) -> u32 {
+    let hmac_result = hmac_sha1(secret, &step.to_be_bytes());
+
+    // Dynamic truncation
+    let offset = (hmac_result[19] & 0x0F) as usize;
+    let trunc_hash = u32::from_be_bytes([
+        hmac_result[offset],
+        hmac_result[offset + 1],
+        hmac_result[offset + 2],
+        hmac_result[offset + 3],
+    ]) & 0x7FFF_FFFF;
+
+    trunc_hash % 10u32.pow(digits)
+}
+
+/// Check a code the client typed against a base32 secret at the current time
+///
+/// An empty secret never matches: anyone can compute the codes of an empty
+/// HMAC key.
+pub fn verify_code(secret: &str, code: &str, params: &TotpParams) -> bool {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    match (base32_decode(secret), code.trim().parse::<u32>()) {
+        (Ok(secret), Ok(code)) if !secret.is_empty() => verify_code_at(&secret, code, now, params),
+        _ => false,
+    }
+}
+
+/// Check `code` against every step in the window around `unix_time`
+pub fn verify_code_at(secret: &[u8], code: u32, unix_time: u64, params: &TotpParams) -> bool {
+    let current = unix_time / params.period;
+    let first = current.saturating_sub(params.window);
+    // Check every step, so timing doesn't reveal which one matched
+    (first..=current + params.window).fold(false, |matched, step| {
+        constant_time_eq(&totp_code(secret, step, params.digits).to_be_bytes(), &code.to_be_bytes()) | matched
+    })
+}
+
+/// Generate a random TOTP secret, base32 encoded as stored in account.token
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; TOTP_SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    base32_encode(&secret)
+}
+
+/// Build the otpauth:// provisioning URI for a base32 secret
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &str, params: &TotpParams) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret,
+        percent_encode(issuer),
+        params.digits,
+        params.period
+    )
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_secret() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+        assert_eq!(base32_decode(&secret).unwrap().len(), TOTP_SECRET_LENGTH);
+        assert_ne!(secret, generate_secret());
+    }
+
+    #[test]
+    fn test_totp_rfc6238_vectors() {
+        let secret = b"12345678901234567890";
+        assert_eq!(totp_code(secret, 59 / 30, 8), 94287082);
+        assert_eq!(totp_code(secret, 1111111109 / 30, 8), 7081804);
+        assert_eq!(totp_code(secret, 1234567890 / 30, 8), 89005924);
+        assert_eq!(totp_code(secret, 59 / 30, 6), 287082);
+    }
+
+    #[test]
+    fn test_verify_code_window() {
+        let secret = b"12345678901234567890";
+        let params = TotpParams::default();
+        let code = totp_code(secret, 1111111109 / 30, 6);
+
+        assert!(verify_code_at(secret, code, 1111111109, &params));
+        assert!(verify_code_at(secret, code, 1111111109 + 30, &params));
+        assert!(verify_code_at(secret, code, 1111111109 - 30, &params));
+        assert!(!verify_code_at(secret, code, 1111111109 + 60, &params));
+
+        let strict = TotpParams { window: 0, ..params };
+        assert!(!verify_code_at(secret, code, 1111111109 + 30, &strict));
+        assert!(!verify_code("not base32!", "123456", &params));
+        assert!(!verify_code("JBSWY3DPEHPK3PXP", "abc", &params));
+
+        // Codes of an empty key are public
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        assert!(!verify_code("", &totp_code(&[], now / 30, 6).to_string(), &params));
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        assert_eq!(
+            provisioning_uri("My Realm", "GM:Admin", "JBSWY3DPEHPK3PXP", &TotpParams::default()),
+            "otpauth://totp/My%20Realm:GM%3AAdmin?secret=JBSWY3DPEHPK3PXP&issuer=My%20Realm&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
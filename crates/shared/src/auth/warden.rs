@@ -0,0 +1,352 @@
+// Warden - Anti-cheat crypto plumbing
+// Rust equivalent of WardenKeyGeneration.h and the key handling of
+// Warden.cpp/WardenWin.cpp
+//
+// Warden packets (SMSG/CMSG_WARDEN_DATA) are RC4 encrypted in each direction.
+// The initial stream keys come from the session key K:
+//   o1 = SHA1(first half of K), o2 = SHA1(second half of K), o0 = 0^20
+//   o0 = SHA1(o1, o0, o2) each time the 20 bytes of o0 are used up
+// The first 16 generated bytes key what the server receives, the next 16
+// what it sends. Once the client has loaded a module the streams are rekeyed
+// with the seeds that come with that module.
+//
+// Modules are kept on disk as <ID>.bin (the RC4 encrypted module as sent to
+// the client, ID being its MD5 in hex) next to <ID>.key (the 16 byte RC4 key
+// the client decrypts it with). Decrypted, a module is a u32 uncompressed
+// size followed by zlib data and the module signature.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::Context;
+use data_encoding::HEXUPPER;
+use zeroize::Zeroizing;
+
+use super::arc4::Arc4;
+use super::big_number::BigNumber;
+use super::constant_time::constant_time_eq;
+use super::crypto_hash::{Md5Hash, Sha1Hash};
+
+/// Length of the RC4 keys of the Warden streams and modules
+pub const WARDEN_KEY_LENGTH: usize = 16;
+
+/// Length of a module ID (its MD5)
+pub const WARDEN_MODULE_ID_LENGTH: usize = 16;
+
+/// Largest module accepted when unpacking, uncompressed
+const MAX_MODULE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Key stream Warden derives its RC4 keys from (SHA1Randx in C++)
+pub struct WardenKeyGenerator {
+    o0: Zeroizing<[u8; Sha1Hash::DIGEST_LENGTH]>,
+    o1: Zeroizing<[u8; Sha1Hash::DIGEST_LENGTH]>,
+    o2: Zeroizing<[u8; Sha1Hash::DIGEST_LENGTH]>,
+    taken: usize,
+}
+
+impl WardenKeyGenerator {
+    pub fn new(seed: &[u8]) -> Self {
+        let half = seed.len() / 2;
+        let digest = |data: &[u8]| {
+            let mut sha = Sha1Hash::new();
+            sha.update_data_bytes(data);
+            sha.finalize();
+            Zeroizing::new(*sha.get_digest())
+        };
+        let mut generator = WardenKeyGenerator {
+            o0: Zeroizing::new([0u8; Sha1Hash::DIGEST_LENGTH]),
+            o1: digest(&seed[..half]),
+            o2: digest(&seed[half..]),
+            taken: 0,
+        };
+        generator.fill_up();
+        generator
+    }
+
+    /// Keys derived from the 40 byte session key `k`
+    pub fn from_session_key(k: &BigNumber) -> Self {
+        Self::new(&Zeroizing::new(k.as_byte_array(40)))
+    }
+
+    /// Fill `buf` with the next bytes of the stream
+    pub fn generate(&mut self, buf: &mut [u8]) {
+        for byte in buf {
+            if self.taken == Sha1Hash::DIGEST_LENGTH {
+                self.fill_up();
+            }
+            *byte = self.o0[self.taken];
+            self.taken += 1;
+        }
+    }
+
+    fn fill_up(&mut self) {
+        let mut sha = Sha1Hash::new();
+        sha.update_data_bytes(&*self.o1);
+        sha.update_data_bytes(&*self.o0);
+        sha.update_data_bytes(&*self.o2);
+        sha.finalize();
+        self.o0.copy_from_slice(sha.get_digest());
+        self.taken = 0;
+    }
+}
+
+/// The two RC4 streams of a Warden session, seen from the server
+#[derive(Debug)]
+pub struct WardenCrypt {
+    input: Arc4,
+    output: Arc4,
+}
+
+impl WardenCrypt {
+    /// Streams right after login, keyed from the session key `k`
+    pub fn new(k: &BigNumber) -> Self {
+        let mut generator = WardenKeyGenerator::from_session_key(k);
+        let mut input_key = Zeroizing::new([0u8; WARDEN_KEY_LENGTH]);
+        let mut output_key = Zeroizing::new([0u8; WARDEN_KEY_LENGTH]);
+        generator.generate(&mut *input_key);
+        generator.generate(&mut *output_key);
+        Self::with_keys(&input_key, &output_key)
+    }
+
+    /// Streams with explicit keys, e.g. the seeds of a loaded module
+    pub fn with_keys(input_key: &[u8; WARDEN_KEY_LENGTH], output_key: &[u8; WARDEN_KEY_LENGTH]) -> Self {
+        WardenCrypt { input: Arc4::new(input_key), output: Arc4::new(output_key) }
+    }
+
+    /// Decrypt a received CMSG_WARDEN_DATA payload in place
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        self.input.update(data);
+    }
+
+    /// Encrypt an SMSG_WARDEN_DATA payload in place
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        self.output.update(data);
+    }
+}
+
+/// A Warden module as sent to clients
+#[derive(Clone)]
+pub struct WardenModule {
+    /// MD5 of `data`
+    pub id: [u8; WARDEN_MODULE_ID_LENGTH],
+    /// RC4 key the client decrypts `data` with
+    pub key: Zeroizing<[u8; WARDEN_KEY_LENGTH]>,
+    /// The encrypted module
+    pub data: Vec<u8>,
+}
+
+impl WardenModule {
+    pub fn new(key: [u8; WARDEN_KEY_LENGTH], data: Vec<u8>) -> Self {
+        WardenModule { id: module_id(&data), key: Zeroizing::new(key), data }
+    }
+
+    /// Module ID as used in file names
+    pub fn id_hex(&self) -> String {
+        HEXUPPER.encode(&self.id)
+    }
+
+    /// Decrypt and inflate the module, e.g. to inspect or checksum it
+    pub fn unpack(&self) -> anyhow::Result<Vec<u8>> {
+        let mut data = self.data.clone();
+        Arc4::new(&*self.key).update(&mut data);
+        anyhow::ensure!(data.len() >= 4, "module {} is only {} bytes", self.id_hex(), data.len());
+        let size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        anyhow::ensure!(size <= MAX_MODULE_SIZE, "module {} claims {} bytes", self.id_hex(), size);
+
+        // The signature after the zlib stream is not read by the decoder
+        let mut module = Vec::with_capacity(size);
+        flate2::read::ZlibDecoder::new(&data[4..])
+            .take(size as u64 + 1)
+            .read_to_end(&mut module)
+            .with_context(|| format!("module {} does not inflate", self.id_hex()))?;
+        anyhow::ensure!(
+            module.len() == size,
+            "module {} inflates to {} bytes instead of {}",
+            self.id_hex(),
+            module.len(),
+            size
+        );
+        Ok(module)
+    }
+}
+
+impl std::fmt::Debug for WardenModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WardenModule").field("id", &self.id_hex()).field("size", &self.data.len()).finish()
+    }
+}
+
+fn module_id(data: &[u8]) -> [u8; WARDEN_MODULE_ID_LENGTH] {
+    let mut md5 = Md5Hash::new();
+    md5.update_data_bytes(data);
+    md5.finalize();
+    *md5.get_digest()
+}
+
+/// The Warden modules found in a directory, by ID
+#[derive(Debug, Default)]
+pub struct WardenModuleCache {
+    modules: BTreeMap<[u8; WARDEN_MODULE_ID_LENGTH], WardenModule>,
+}
+
+impl WardenModuleCache {
+    /// Load every <ID>.bin/<ID>.key pair in `dir`
+    ///
+    /// Files whose contents do not match their ID, or that lack a key, are
+    /// skipped with a warning so one bad module does not disable the rest.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let mut cache = WardenModuleCache::default();
+        let entries = std::fs::read_dir(dir).with_context(|| format!("cannot read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "bin") {
+                continue;
+            }
+            match load_module(&path) {
+                Ok(module) => {
+                    cache.modules.insert(module.id, module);
+                }
+                Err(e) => tracing::warn!("Skipping Warden module {}: {:#}", path.display(), e),
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Write `module` to `dir` and add it to the cache
+    pub fn store(&mut self, dir: &Path, module: WardenModule) -> anyhow::Result<()> {
+        let base = dir.join(module.id_hex());
+        std::fs::write(base.with_extension("bin"), &module.data)
+            .with_context(|| format!("cannot write {}.bin", base.display()))?;
+        std::fs::write(base.with_extension("key"), module.key.as_slice())
+            .with_context(|| format!("cannot write {}.key", base.display()))?;
+        self.modules.insert(module.id, module);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &[u8; WARDEN_MODULE_ID_LENGTH]) -> Option<&WardenModule> {
+        self.modules.get(id)
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &WardenModule> {
+        self.modules.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+fn load_module(path: &Path) -> anyhow::Result<WardenModule> {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let id: [u8; WARDEN_MODULE_ID_LENGTH] = HEXUPPER
+        .decode(stem.to_ascii_uppercase().as_bytes())
+        .ok()
+        .and_then(|id| id.try_into().ok())
+        .context("file name is not a 32 digit module ID")?;
+
+    let data = std::fs::read(path).context("cannot read module")?;
+    anyhow::ensure!(constant_time_eq(&module_id(&data), &id), "contents do not match the module ID");
+
+    let key = Zeroizing::new(std::fs::read(path.with_extension("key")).context("cannot read the .key file")?);
+    let key: [u8; WARDEN_KEY_LENGTH] = key.as_slice().try_into().map_err(|_| {
+        anyhow::anyhow!("the .key file holds {} bytes instead of {}", key.len(), WARDEN_KEY_LENGTH)
+    })?;
+    Ok(WardenModule { id, key: Zeroizing::new(key), data })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn session_key() -> BigNumber {
+        let mut k = BigNumber::new();
+        k.set_hex_str("4F8E2C61B7D8A3F912E5C7B8D9A0E1F2C3D4E5F6A7B8C9D0E1F2A3B4C5D6E7F8091A2B3C4D5E6F70");
+        k
+    }
+
+    #[test]
+    fn test_key_generator_blocks() {
+        // Reading in pieces gives the same stream as reading at once
+        let mut whole = [0u8; 48];
+        WardenKeyGenerator::new(b"seed").generate(&mut whole);
+
+        let mut generator = WardenKeyGenerator::new(b"seed");
+        let mut pieces = [0u8; 48];
+        for chunk in pieces.chunks_mut(7) {
+            generator.generate(chunk);
+        }
+        assert_eq!(pieces, whole);
+
+        let mut sha = Sha1Hash::new();
+        sha.update_data_bytes(&whole[..20]);
+        sha.finalize();
+        assert_ne!(&whole[20..40], sha.get_digest());
+    }
+
+    #[test]
+    fn test_crypt_matches_client() {
+        // The client keys its streams the other way round
+        let mut generator = WardenKeyGenerator::from_session_key(&session_key());
+        let (mut server_in, mut server_out) = ([0u8; 16], [0u8; 16]);
+        generator.generate(&mut server_in);
+        generator.generate(&mut server_out);
+        let mut client = WardenCrypt::with_keys(&server_out, &server_in);
+
+        let mut server = WardenCrypt::new(&session_key());
+        let mut packet = *b"\x00module request";
+        server.encrypt(&mut packet);
+        client.decrypt(&mut packet);
+        assert_eq!(&packet, b"\x00module request");
+
+        let mut answer = *b"\x02hash result";
+        client.encrypt(&mut answer);
+        server.decrypt(&mut answer);
+        assert_eq!(&answer, b"\x02hash result");
+    }
+
+    fn packed_module(key: &[u8; 16], module: &[u8]) -> Vec<u8> {
+        let mut data = (module.len() as u32).to_le_bytes().to_vec();
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(module).unwrap();
+        data.extend(zlib.finish().unwrap());
+        data.extend_from_slice(b"SIGN");
+        data.extend_from_slice(&[0xAB; 256]);
+        Arc4::new(key).update(&mut data);
+        data
+    }
+
+    #[test]
+    fn test_module_cache() {
+        let dir = std::env::temp_dir().join(format!("mangos_warden_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let key = [7u8; 16];
+        let module = WardenModule::new(key, packed_module(&key, b"module code"));
+        assert_eq!(module.unpack().unwrap(), b"module code");
+
+        let mut cache = WardenModuleCache::default();
+        cache.store(&dir, module.clone()).unwrap();
+
+        // A module renamed to the wrong ID is refused
+        std::fs::write(dir.join("00000000000000000000000000000000.bin"), &module.data).unwrap();
+        std::fs::write(dir.join("00000000000000000000000000000000.key"), key).unwrap();
+
+        let loaded = WardenModuleCache::load(&dir).unwrap();
+        assert_eq!(loaded.len(), 1);
+        let found = loaded.get(&module.id).unwrap();
+        assert_eq!(found.data, module.data);
+        assert_eq!(*found.key, key);
+
+        let wrong_key = WardenModule::new([8u8; 16], module.data.clone());
+        assert!(wrong_key.unpack().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
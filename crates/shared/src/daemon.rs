@@ -0,0 +1,233 @@
+// Daemon - Background mode and PID file
+// Rust equivalent of PosixDaemon.cpp and the PidFile handling in the C++ Main.cpp
+//
+// `daemonize` detaches the server from its terminal. It forks, so it has to
+// run before the async runtime starts its worker threads. `PidFile` records
+// the process id for init scripts: it refuses to start over the pid file of a
+// process that is still running, replaces stale ones and removes the file
+// again when the server exits.
+//
+// Under systemd (Type=notify) `sd_notify` reports readiness, stopping and
+// watchdog heartbeats over $NOTIFY_SOCKET; without that variable it does
+// nothing, so callers need not check how the server was started.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::error::MangosError;
+
+/// Set in the environment of a daemonized process, so a server re-executing
+/// itself (graceful restart) does not detach a second time
+pub const DAEMON_ENV: &str = "MANGOS_DAEMON";
+
+/// Detach from the terminal and keep running in the background
+///
+/// The parent exits with status 0 once the child is forked. The child starts a
+/// new session and points stdin, stdout and stderr at /dev/null, so only the
+/// log files show what happens from here on. Must be called while the process
+/// still has a single thread.
+#[cfg(unix)]
+pub fn daemonize() -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    if std::env::var_os(DAEMON_ENV).is_some() {
+        return Ok(());
+    }
+
+    // SAFETY: the process is single-threaded, so the child inherits a
+    // consistent copy of everything
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()).context("Cannot fork"),
+        0 => {}
+        _ => std::process::exit(0),
+    }
+
+    // SAFETY: plain system calls on descriptors this process owns
+    unsafe {
+        if libc::setsid() == -1 {
+            return Err(std::io::Error::last_os_error()).context("Cannot start a new session");
+        }
+        let null = fs::OpenOptions::new().read(true).write(true).open("/dev/null")?;
+        for fd in 0..=2 {
+            if libc::dup2(null.as_raw_fd(), fd) == -1 {
+                return Err(std::io::Error::last_os_error()).context("Cannot redirect standard streams");
+            }
+        }
+        // Still single-threaded, nothing reads the environment concurrently
+        std::env::set_var(DAEMON_ENV, "1");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemonize() -> Result<()> {
+    anyhow::bail!(MangosError::Usage("Daemon mode is only supported on Unix, run the server as a service instead".into()))
+}
+
+/// PID file of a running server, removed on drop
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    pid: u32,
+}
+
+impl PidFile {
+    /// Write the current process id to `path`
+    ///
+    /// Fails when the file names another process that is still running. A file
+    /// left behind by a crash is overwritten, as is one holding our own id
+    /// (the process re-executed itself).
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let pid = std::process::id();
+
+        if let Ok(text) = fs::read_to_string(path) {
+            match text.trim().parse::<u32>() {
+                Ok(old) if old != pid && process_alive(old) => anyhow::bail!(MangosError::Config(format!(
+                    "Another instance is already running with pid {} (pid file {})",
+                    old,
+                    path.display()
+                ))),
+                Ok(old) if old != pid => tracing::warn!("Replacing stale pid file {} (pid {} is gone)", path.display(), old),
+                Ok(_) => {}
+                Err(_) => tracing::warn!("Replacing unreadable pid file {}", path.display()),
+            }
+        }
+
+        fs::write(path, format!("{}\n", pid)).with_context(|| format!("Cannot write pid file {}", path.display()))?;
+        Ok(PidFile { path: path.to_path_buf(), pid })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Leave the file alone if a newer process already took it over
+        let ours = fs::read_to_string(&self.path).is_ok_and(|text| text.trim().parse() == Ok(self.pid));
+        if ours && let Err(e) = fs::remove_file(&self.path) {
+            tracing::warn!("Cannot remove pid file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Send a state change ("READY=1", "WATCHDOG=1", ...) to systemd
+///
+/// Returns false when the server was not started by systemd.
+#[cfg(unix)]
+pub fn sd_notify(state: &str) -> Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => notify_socket(&socket, state).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn sd_notify(_state: &str) -> Result<bool> {
+    Ok(false)
+}
+
+#[cfg(unix)]
+fn notify_socket(socket: &std::ffi::OsStr, state: &str) -> Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let sender = UnixDatagram::unbound()?;
+    // A leading '@' names a socket in the Linux abstract namespace
+    match socket.as_encoded_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sender.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => anyhow::bail!("Abstract NOTIFY_SOCKET addresses are only supported on Linux"),
+        None => {
+            sender.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+/// How often systemd expects "WATCHDOG=1" (WatchdogSec), when it watches this process
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // Set for the main process only; a forked daemon must not take it over
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse() != Ok(std::process::id())
+    {
+        return None;
+    }
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Whether a process with this id exists
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process exists
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    // The process exists but belongs to another user
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without a cheap check, assume the process that wrote the file is gone
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file() {
+        let path = std::env::temp_dir().join(format!("mangos-pidfile-test-{}.pid", std::process::id()));
+
+        // Stale file from a process that no longer exists
+        fs::write(&path, "2147483646\n").unwrap();
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", std::process::id()));
+
+        // Our own id after a re-exec is not another instance
+        let again = PidFile::create(&path).unwrap();
+        std::mem::forget(again);
+        drop(pid_file);
+        assert!(!path.exists());
+
+        // Someone else's running process
+        #[cfg(unix)]
+        {
+            fs::write(&path, "1\n").unwrap();
+            assert!(PidFile::create(&path).is_err());
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify_socket() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = std::env::temp_dir().join(format!("mangos-notify-test-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        notify_socket(path.as_os_str(), "READY=1\nSTATUS=Accepting connections").unwrap();
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=Accepting connections");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -7,6 +7,9 @@
 use sqlx::any::AnyRow;
 use sqlx::{AnyPool, Row};
 use anyhow::Result;
+use tracing::Instrument;
+
+use crate::log::LOG_TARGET_DB_ERROR;
 
 /// Database connection pool wrapper
 /// Equivalent to the C++ Database class with connection pooling
@@ -84,7 +87,7 @@ impl Database {
             anyhow::anyhow!("Database {} not initialized", self.name)
         })?;
 
-        let rows = sqlx::query(sql).fetch_all(pool).await?;
+        let rows = sqlx::query(sql).fetch_all(pool).instrument(self.query_span(sql)).await?;
         Ok(rows)
     }
 
@@ -94,7 +97,7 @@ impl Database {
             anyhow::anyhow!("Database {} not initialized", self.name)
         })?;
 
-        let row = sqlx::query(sql).fetch_optional(pool).await?;
+        let row = sqlx::query(sql).fetch_optional(pool).instrument(self.query_span(sql)).await?;
         Ok(row)
     }
 
@@ -104,7 +107,7 @@ impl Database {
             anyhow::anyhow!("Database {} not initialized", self.name)
         })?;
 
-        let result: sqlx::any::AnyQueryResult = sqlx::query(sql).execute(pool).await?;
+        let result: sqlx::any::AnyQueryResult = sqlx::query(sql).execute(pool).instrument(self.query_span(sql)).await?;
         Ok(result.rows_affected())
     }
 
@@ -134,6 +137,15 @@ impl Database {
         Ok(tx)
     }
 
+    /// Span covering one statement, so query latency shows up in exported traces
+    ///
+    /// Only the leading keyword is recorded; the statement text may carry
+    /// account data such as SRP6 verifiers.
+    fn query_span(&self, sql: &str) -> tracing::Span {
+        let operation = sql.split_whitespace().next().unwrap_or_default().to_ascii_uppercase();
+        tracing::info_span!("db.query", db = %self.name, operation = %operation)
+    }
+
     /// Escape a string for safe SQL insertion
     /// Note: With SQLx parameterized queries, this is less necessary,
     /// but provided for compatibility with the C++ codebase patterns.
@@ -155,6 +167,7 @@ impl Database {
             Ok(Some(_)) => Ok(true),
             Ok(None) => {
                 tracing::error!(
+                    target: LOG_TARGET_DB_ERROR,
                     "Database {} table '{}' missing required field '{}'",
                     self.name,
                     table,
@@ -163,7 +176,7 @@ impl Database {
                 Ok(false)
             }
             Err(e) => {
-                tracing::error!("Error checking required field: {}", e);
+                tracing::error!(target: LOG_TARGET_DB_ERROR, "Error checking required field: {}", e);
                 Ok(false)
             }
         }
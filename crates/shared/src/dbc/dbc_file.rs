@@ -1,4 +1,16 @@
+// DbcFile - Raw access to client DBC files
+// Rust equivalent of DBCFileLoader.h/cpp
+//
+// A DBC file is a "WDBC" header (record count, field count, record size,
+// string table size), the fixed size records and a string table that string
+// fields point into by offset.
+
 use std::io::{Cursor, Read};
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::error::MangosError;
 
 pub struct DbcFile {
     record_count: u32,
@@ -19,7 +31,7 @@ impl DbcFile {
         let mut magic = [0u8; 4];
         cursor.read_exact(&mut magic)?;
         if &magic != b"WDBC" {
-            anyhow::bail!("Invalid DBC magic");
+            anyhow::bail!(MangosError::Data("Invalid DBC magic".into()));
         }
 
         let record_count = read_u32(&mut cursor)?;
@@ -30,6 +42,10 @@ impl DbcFile {
         let data_size = record_count
             .checked_mul(record_size)
             .ok_or_else(|| anyhow::anyhow!("DBC data size overflow"))? as usize;
+        anyhow::ensure!(
+            data_size.saturating_add(string_size as usize) <= bytes.len(),
+            MangosError::Data("DBC file is shorter than its header says".into())
+        );
         let mut data = vec![0u8; data_size];
         cursor.read_exact(&mut data)?;
 
@@ -45,10 +61,24 @@ impl DbcFile {
         })
     }
 
+    /// Read and parse the DBC file at `path`
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("cannot parse {}", path.display()))
+    }
+
     pub fn record_count(&self) -> usize {
         self.record_count as usize
     }
 
+    pub fn field_count(&self) -> usize {
+        self.field_count as usize
+    }
+
+    pub fn record_size(&self) -> usize {
+        self.record_size as usize
+    }
+
     pub fn record(&self, index: usize) -> Option<DbcRecord<'_>> {
         if index >= self.record_count() {
             return None;
@@ -71,23 +101,24 @@ impl DbcFile {
 
     pub fn validate(&self) -> anyhow::Result<()> {
         if self.field_count * 4 != self.record_size {
-            anyhow::bail!("DBC header mismatch: field_count * 4 != record_size");
+            anyhow::bail!(MangosError::Data("DBC header mismatch: field_count * 4 != record_size".into()));
         }
         Ok(())
     }
+
+    /// String at `offset` in the string table; empty when out of range
+    fn string_at(&self, offset: usize) -> &str {
+        let Some(slice) = self.string_table.get(offset..) else {
+            return "";
+        };
+        let len = slice.iter().position(|&b| b == 0).unwrap_or(slice.len());
+        std::str::from_utf8(&slice[..len]).unwrap_or("")
+    }
 }
 
 impl<'a> DbcRecord<'a> {
     pub fn get_u32(&self, field: usize) -> Option<u32> {
-        let offset = field.checked_mul(4)?;
-        let start = self.index * self.file.record_size as usize + offset;
-        let end = start + 4;
-        if end > self.file.data.len() {
-            return None;
-        }
-        Some(u32::from_le_bytes(
-            self.file.data[start..end].try_into().ok()?,
-        ))
+        self.u32_at(field.checked_mul(4)?)
     }
 
     pub fn get_string(&self, field: usize) -> Option<String> {
@@ -100,6 +131,29 @@ impl<'a> DbcRecord<'a> {
         let bytes = &slice[..len];
         Some(String::from_utf8_lossy(bytes).to_string())
     }
+
+    /// Bytes of this record
+    pub fn bytes(&self) -> &'a [u8] {
+        let start = self.index * self.file.record_size as usize;
+        &self.file.data[start..start + self.file.record_size as usize]
+    }
+
+    /// u32 at byte `offset` of the record
+    pub fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.bytes().get(offset..offset.checked_add(4)?)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Byte at `offset` of the record
+    pub fn u8_at(&self, offset: usize) -> Option<u8> {
+        self.bytes().get(offset).copied()
+    }
+
+    /// String the u32 at byte `offset` points to; invalid UTF-8 reads as ""
+    pub fn string_at(&self, offset: usize) -> Option<&'a str> {
+        let file: &'a DbcFile = self.file;
+        Some(file.string_at(self.u32_at(offset)? as usize))
+    }
 }
 
 fn read_u32<R: Read>(reader: &mut R) -> anyhow::Result<u32> {
@@ -0,0 +1,261 @@
+// DbcStorage - Typed, indexed DBC tables
+// Rust equivalent of DBCStore.h
+//
+// Like the C++ loader, every entry type comes with a format string holding
+// one character per field of the file:
+//   n  u32 index, kept            d  u32 index, not kept
+//   i  u32                        f  float
+//   b  u8                         l  u32 read as bool
+//   s  string (offset into the string table)
+//   x  unused u32                 X  unused u8
+// The format has to cover every field and add up to the record size, so a
+// DBC from another client build is refused instead of read misaligned.
+// Entries are looked up by the index field, or by row without one.
+
+use std::path::Path;
+
+use anyhow::Context;
+
+use super::dbc_file::{DbcFile, DbcRecord};
+use crate::error::MangosError;
+
+/// Highest index accepted; keeps a corrupt file from allocating gigabytes
+const MAX_INDEX: u32 = 1 << 20;
+
+/// A DBC row type with its field format
+pub trait DbcEntry: Sized {
+    /// File name in the dbc directory, e.g. "Map.dbc"
+    const FILE_NAME: &'static str;
+    /// One format character per field, see the module comment
+    const FORMAT: &'static str;
+
+    fn from_row(row: &DbcRow<'_>) -> Self;
+}
+
+/// One record, read through its format
+pub struct DbcRow<'a> {
+    record: DbcRecord<'a>,
+    format: &'static [u8],
+    offsets: &'a [usize],
+}
+
+impl<'a> DbcRow<'a> {
+    fn offset(&self, field: usize, expected: &[u8]) -> usize {
+        debug_assert!(
+            expected.contains(&self.format[field]),
+            "field {} has format '{}'",
+            field,
+            self.format[field] as char
+        );
+        self.offsets[field]
+    }
+
+    pub fn u32(&self, field: usize) -> u32 {
+        self.record.u32_at(self.offset(field, b"nidx")).unwrap_or(0)
+    }
+
+    pub fn i32(&self, field: usize) -> i32 {
+        self.u32(field) as i32
+    }
+
+    pub fn f32(&self, field: usize) -> f32 {
+        f32::from_bits(self.record.u32_at(self.offset(field, b"f")).unwrap_or(0))
+    }
+
+    pub fn u8(&self, field: usize) -> u8 {
+        self.record.u8_at(self.offset(field, b"bX")).unwrap_or(0)
+    }
+
+    pub fn bool(&self, field: usize) -> bool {
+        self.record.u32_at(self.offset(field, b"l")).unwrap_or(0) != 0
+    }
+
+    pub fn str(&self, field: usize) -> &'a str {
+        self.record.string_at(self.offset(field, b"s")).unwrap_or("")
+    }
+
+    pub fn string(&self, field: usize) -> String {
+        self.str(field).to_string()
+    }
+
+    /// `N` consecutive string fields, e.g. the 16 locales of a name
+    pub fn strings<const N: usize>(&self, first: usize) -> [String; N] {
+        std::array::from_fn(|i| self.string(first + i))
+    }
+}
+
+/// Byte offset of every field, checked against the file's header
+fn field_offsets(format: &str, file: &DbcFile) -> anyhow::Result<Vec<usize>> {
+    if format.len() != file.field_count() {
+        anyhow::bail!(MangosError::Data(format!(
+            "format has {} fields, the file {}",
+            format.len(),
+            file.field_count()
+        )));
+    }
+    let mut offsets = Vec::with_capacity(format.len());
+    let mut offset = 0;
+    for c in format.bytes() {
+        offsets.push(offset);
+        offset += match c {
+            b'b' | b'X' => 1,
+            b'n' | b'd' | b'i' | b'f' | b'l' | b's' | b'x' => 4,
+            _ => anyhow::bail!(MangosError::Data(format!("unknown format character '{}'", c as char))),
+        };
+    }
+    if offset != file.record_size() {
+        anyhow::bail!(MangosError::Data(format!(
+            "format adds up to {} bytes per record, the file has {}",
+            offset,
+            file.record_size()
+        )));
+    }
+    Ok(offsets)
+}
+
+/// The entries of one DBC file, indexed like the C++ DBCStorage
+pub struct DbcStorage<T> {
+    index: Vec<Option<T>>,
+    count: usize,
+}
+
+impl<T: DbcEntry> DbcStorage<T> {
+    pub fn from_file(file: &DbcFile) -> anyhow::Result<Self> {
+        let offsets = field_offsets(T::FORMAT, file)?;
+        let format = T::FORMAT.as_bytes();
+        let index_field = format.iter().position(|&c| c == b'n' || c == b'd');
+
+        let mut index: Vec<Option<T>> = Vec::new();
+        for row in 0..file.record_count() {
+            let Some(record) = file.record(row) else {
+                continue;
+            };
+            let id = match index_field {
+                Some(field) => record.u32_at(offsets[field]).unwrap_or(0),
+                None => row as u32,
+            };
+            if id >= MAX_INDEX {
+                anyhow::bail!(MangosError::Data(format!("record {} has index {}", row, id)));
+            }
+            let id = id as usize;
+            if id >= index.len() {
+                index.resize_with(id + 1, || None);
+            }
+            index[id] = Some(T::from_row(&DbcRow { record, format, offsets: &offsets }));
+        }
+        Ok(DbcStorage { count: index.iter().flatten().count(), index })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_file(&DbcFile::from_bytes(bytes)?)
+    }
+
+    /// Load `T::FILE_NAME` from the dbc directory `dir`
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(T::FILE_NAME);
+        Self::from_file(&DbcFile::load(&path)?).with_context(|| format!("cannot load {}", path.display()))
+    }
+}
+
+impl<T> DbcStorage<T> {
+    pub fn lookup_entry(&self, id: u32) -> Option<&T> {
+        self.index.get(id as usize)?.as_ref()
+    }
+
+    /// Highest index + 1, like GetNumRows() in C++
+    pub fn num_rows(&self) -> u32 {
+        self.index.len() as u32
+    }
+
+    /// Number of entries loaded
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Entries with their index, in index order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
+        self.index.iter().enumerate().filter_map(|(id, entry)| Some((id as u32, entry.as_ref()?)))
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Build a DBC file from raw records and a string table
+    pub fn build_dbc(field_count: u32, record_size: u32, records: &[Vec<u8>], strings: &[u8]) -> Vec<u8> {
+        let mut bytes = b"WDBC".to_vec();
+        for value in [records.len() as u32, field_count, record_size, strings.len() as u32] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for record in records {
+            bytes.extend_from_slice(record);
+        }
+        bytes.extend_from_slice(strings);
+        bytes
+    }
+
+    struct TestEntry {
+        id: u32,
+        flag: u8,
+        scale: f32,
+        name: String,
+        enabled: bool,
+    }
+
+    impl DbcEntry for TestEntry {
+        const FILE_NAME: &'static str = "Test.dbc";
+        const FORMAT: &'static str = "nxbXfsl";
+
+        fn from_row(row: &DbcRow<'_>) -> Self {
+            TestEntry { id: row.u32(0), flag: row.u8(2), scale: row.f32(4), name: row.string(5), enabled: row.bool(6) }
+        }
+    }
+
+    fn record(id: u32, flag: u8, scale: f32, name: u32, enabled: u32) -> Vec<u8> {
+        let mut record = id.to_le_bytes().to_vec();
+        record.extend_from_slice(&99u32.to_le_bytes());
+        record.extend_from_slice(&[flag, 0xFF]);
+        record.extend_from_slice(&scale.to_le_bytes());
+        record.extend_from_slice(&name.to_le_bytes());
+        record.extend_from_slice(&enabled.to_le_bytes());
+        record
+    }
+
+    #[test]
+    fn test_typed_storage() {
+        let records = [record(5, 3, 1.5, 1, 1), record(2, 0, -2.0, 9, 0)];
+        let bytes = build_dbc(7, 22, &records, b"\0Eastern\0Kalimdor\0");
+        let storage = DbcStorage::<TestEntry>::from_bytes(&bytes).unwrap();
+
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.num_rows(), 6);
+        let entry = storage.lookup_entry(5).unwrap();
+        assert_eq!((entry.id, entry.flag, entry.scale, entry.name.as_str(), entry.enabled), (5, 3, 1.5, "Eastern", true));
+        assert_eq!(storage.lookup_entry(2).unwrap().name, "Kalimdor");
+        assert!(storage.lookup_entry(3).is_none());
+        assert!(storage.lookup_entry(100).is_none());
+        assert_eq!(storage.iter().map(|(id, _)| id).collect::<Vec<_>>(), [2, 5]);
+    }
+
+    #[test]
+    fn test_format_mismatch() {
+        // Wrong field count
+        let bytes = build_dbc(6, 22, &[record(1, 0, 0.0, 0, 0)], b"\0");
+        assert!(DbcStorage::<TestEntry>::from_bytes(&bytes).is_err());
+
+        // Right field count, wrong record size
+        let mut long = record(1, 0, 0.0, 0, 0);
+        long.extend_from_slice(&[0, 0]);
+        let bytes = build_dbc(7, 24, &[long], b"\0");
+        assert!(DbcStorage::<TestEntry>::from_bytes(&bytes).is_err());
+
+        // Truncated file
+        let bytes = build_dbc(7, 22, &[record(1, 0, 0.0, 0, 0)], b"\0");
+        assert!(DbcStorage::<TestEntry>::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+    }
+}
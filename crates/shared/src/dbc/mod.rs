@@ -0,0 +1,14 @@
+// DBC module - Client database (DBC) files
+// Rust equivalent of DBCFileLoader.h, DBCStore.h and DBCStructure.h
+//
+// DbcFile gives raw access to a file (used by the extractors); DbcStorage
+// loads it into typed entries through a format string, for the server and
+// tools that need Map.dbc, AreaTable.dbc or LiquidType.dbc.
+
+mod dbc_file;
+mod dbc_storage;
+mod structures;
+
+pub use dbc_file::{DbcFile, DbcRecord};
+pub use dbc_storage::{DbcEntry, DbcRow, DbcStorage};
+pub use structures::*;
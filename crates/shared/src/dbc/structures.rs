@@ -0,0 +1,184 @@
+// DBC structures - Entry types of the DBC files the server reads (TBC 2.4.3)
+// Rust equivalent of the matching parts of DBCStructure.h and DBCfmt.h
+//
+// Field numbers in the comments are the columns of the file; unused columns
+// are skipped by the format strings.
+
+use super::dbc_storage::{DbcEntry, DbcRow};
+
+/// Number of localized strings per text field
+pub const MAX_DBC_LOCALES: usize = 16;
+
+/// Map.dbc map_type values
+pub const MAP_COMMON: u32 = 0;
+pub const MAP_INSTANCE: u32 = 1;
+pub const MAP_RAID: u32 = 2;
+pub const MAP_BATTLEGROUND: u32 = 3;
+pub const MAP_ARENA: u32 = 4;
+
+/// Map.dbc
+#[derive(Debug, Clone)]
+pub struct MapEntry {
+    pub map_id: u32,                              // 0
+    pub internal_name: String,                    // 1 directory name in the client data
+    pub map_type: u32,                            // 2
+    // 3 is_pvp_zone
+    pub name: [String; MAX_DBC_LOCALES],          // 4-19
+    // 20 name flags
+    pub linked_zone: u32,                         // 21 common zone for instance and continent map
+    // 22-55 horde and alliance intro texts
+    pub multimap_id: u32,                         // 56
+    // 57-108 unused texts
+    pub ghost_entrance_map: i32,                  // 109 entrance map in ghost mode
+    pub ghost_entrance_x: f32,                    // 110
+    pub ghost_entrance_y: f32,                    // 111
+    pub reset_time_raid: u32,                     // 112
+    pub reset_time_heroic: u32,                   // 113
+    // 114-115 unknown
+    pub addon: u32,                               // 116 0 = original maps, 1 = TBC
+}
+
+impl MapEntry {
+    pub fn is_dungeon(&self) -> bool {
+        self.map_type == MAP_INSTANCE || self.map_type == MAP_RAID
+    }
+
+    pub fn is_raid(&self) -> bool {
+        self.map_type == MAP_RAID
+    }
+
+    pub fn is_battleground(&self) -> bool {
+        self.map_type == MAP_BATTLEGROUND
+    }
+
+    pub fn is_battle_arena(&self) -> bool {
+        self.map_type == MAP_ARENA
+    }
+
+    pub fn is_continent(&self) -> bool {
+        matches!(self.map_id, 0 | 1 | 530)
+    }
+}
+
+impl DbcEntry for MapEntry {
+    const FILE_NAME: &'static str = "Map.dbc";
+    const FORMAT: &'static str = concat!(
+        "nsix",
+        "ssssssssssssssss",
+        "xi",
+        "xxxxxxxxxxxxxxxxx",
+        "xxxxxxxxxxxxxxxxx",
+        "ix",
+        "xxxxxxxxxxxxxxxxx",
+        "xxxxxxxxxxxxxxxxx",
+        "xxxxxxxxxxxxxxxxx",
+        "iffiixxi",
+    );
+
+    fn from_row(row: &DbcRow<'_>) -> Self {
+        MapEntry {
+            map_id: row.u32(0),
+            internal_name: row.string(1),
+            map_type: row.u32(2),
+            name: row.strings(4),
+            linked_zone: row.u32(21),
+            multimap_id: row.u32(56),
+            ghost_entrance_map: row.i32(109),
+            ghost_entrance_x: row.f32(110),
+            ghost_entrance_y: row.f32(111),
+            reset_time_raid: row.u32(112),
+            reset_time_heroic: row.u32(113),
+            addon: row.u32(116),
+        }
+    }
+}
+
+/// AreaTable.dbc, indexed by the area flag like sAreaStore in C++
+#[derive(Debug, Clone)]
+pub struct AreaTableEntry {
+    pub id: u32,                                  // 0
+    pub map_id: u32,                              // 1
+    pub zone: u32,                                // 2 0 for zones, else the zone of this area
+    pub explore_flag: u32,                        // 3 area flag, the index
+    pub flags: u32,                               // 4
+    // 5-9 unused
+    pub area_level: i32,                          // 10
+    pub area_name: [String; MAX_DBC_LOCALES],     // 11-26
+    // 27 name flags
+    pub team: u32,                                // 28
+    // 29-32 unknown
+    pub liquid_type_override: u32,                // 33 liquid override by area
+    // 34-35 unknown
+}
+
+impl DbcEntry for AreaTableEntry {
+    const FILE_NAME: &'static str = "AreaTable.dbc";
+    const FORMAT: &'static str = "iiinixxxxxissssssssssssssssxixxxxixx";
+
+    fn from_row(row: &DbcRow<'_>) -> Self {
+        AreaTableEntry {
+            id: row.u32(0),
+            map_id: row.u32(1),
+            zone: row.u32(2),
+            explore_flag: row.u32(3),
+            flags: row.u32(4),
+            area_level: row.i32(10),
+            area_name: row.strings(11),
+            team: row.u32(28),
+            liquid_type_override: row.u32(33),
+        }
+    }
+}
+
+/// LiquidType.dbc
+#[derive(Debug, Clone)]
+pub struct LiquidTypeEntry {
+    pub id: u32,                                  // 0
+    // 1 name
+    // 2 flags
+    pub liquid_type: u32,                         // 3
+    pub spell_id: u32,                            // 4 aura applied while in the liquid
+}
+
+impl DbcEntry for LiquidTypeEntry {
+    const FILE_NAME: &'static str = "LiquidType.dbc";
+    const FORMAT: &'static str = "nxxii";
+
+    fn from_row(row: &DbcRow<'_>) -> Self {
+        LiquidTypeEntry { id: row.u32(0), liquid_type: row.u32(3), spell_id: row.u32(4) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbc::dbc_storage::tests::build_dbc;
+    use crate::dbc::DbcStorage;
+
+    #[test]
+    fn test_format_lengths() {
+        assert_eq!(MapEntry::FORMAT.len(), 117);
+        assert_eq!(AreaTableEntry::FORMAT.len(), 36);
+        assert_eq!(LiquidTypeEntry::FORMAT.len(), 5);
+    }
+
+    #[test]
+    fn test_area_table_index() {
+        let mut fields = [0u32; 36];
+        fields[0] = 12; // Elwynn Forest
+        fields[3] = 41; // area flag
+        fields[10] = 1;
+        fields[11] = 1;
+        fields[33] = 3;
+        let record: Vec<u8> = fields.iter().flat_map(|f| f.to_le_bytes()).collect();
+        let bytes = build_dbc(36, 144, &[record], b"\0Elwynn Forest\0");
+
+        let areas = DbcStorage::<AreaTableEntry>::from_bytes(&bytes).unwrap();
+        let area = areas.lookup_entry(41).unwrap();
+        assert_eq!(area.id, 12);
+        assert_eq!(area.area_name[0], "Elwynn Forest");
+        assert_eq!(area.area_name[1], "");
+        assert_eq!(area.liquid_type_override, 3);
+        assert!(areas.lookup_entry(12).is_none());
+    }
+}
@@ -0,0 +1,106 @@
+// Error taxonomy and process exit codes
+//
+// Every binary (realmd and each extractor subcommand) exits with one of the
+// codes below so wrapper scripts can tell a bad config from a dead database
+// or missing client data. Errors that should map to a specific code are
+// raised as a MangosError (usually via anyhow::bail!(MangosError::...)); any
+// other error ends up as a generic failure.
+//
+//   0  Success
+//   1  Failure         - unexpected/internal error
+//   2  Usage           - invalid command line arguments
+//   3  Config          - configuration file missing or invalid
+//   4  Database        - database unreachable or a required query failed
+//   5  Data            - input data (client files, maps, vmaps, DBCs) missing or corrupt
+//   6  Io              - reading or writing files failed
+//   7  Network         - cannot listen on the configured address
+//   8  PartialSuccess  - finished, but warnings were logged (items skipped)
+
+use std::process::ExitCode;
+
+use thiserror::Error;
+
+/// Process exit status, see the table at the top of this file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ExitStatus {
+    Success = 0,
+    Failure = 1,
+    Usage = 2,
+    Config = 3,
+    Database = 4,
+    Data = 5,
+    Io = 6,
+    Network = 7,
+    PartialSuccess = 8,
+}
+
+impl ExitStatus {
+    /// Pick the exit status for an error by walking its cause chain
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(e) = cause.downcast_ref::<MangosError>() {
+                return e.exit_status();
+            }
+            if cause.is::<std::io::Error>() {
+                return ExitStatus::Io;
+            }
+        }
+        ExitStatus::Failure
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status as u8)
+    }
+}
+
+/// Errors that map to a specific exit status
+#[derive(Debug, Error)]
+pub enum MangosError {
+    #[error("{0}")]
+    Usage(String),
+    #[error("{0}")]
+    Config(String),
+    #[error("{0}")]
+    Database(String),
+    #[error("{0}")]
+    Data(String),
+    #[error("{0}")]
+    Network(String),
+}
+
+impl MangosError {
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            MangosError::Usage(_) => ExitStatus::Usage,
+            MangosError::Config(_) => ExitStatus::Config,
+            MangosError::Database(_) => ExitStatus::Database,
+            MangosError::Data(_) => ExitStatus::Data,
+            MangosError::Network(_) => ExitStatus::Network,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_exit_status_from_error() {
+        let err = anyhow::anyhow!(MangosError::Data("Maps directory does not exist".into()));
+        assert_eq!(ExitStatus::from_error(&err), ExitStatus::Data);
+
+        let err = Err::<(), _>(MangosError::Database("gone".into()))
+            .context("Loading realms")
+            .unwrap_err();
+        assert_eq!(ExitStatus::from_error(&err), ExitStatus::Database);
+
+        let err = anyhow::Error::new(std::io::Error::other("disk full")).context("Writing 0000000.mmtile");
+        assert_eq!(ExitStatus::from_error(&err), ExitStatus::Io);
+
+        assert_eq!(ExitStatus::from_error(&anyhow::anyhow!("boom")), ExitStatus::Failure);
+    }
+}
@@ -0,0 +1,145 @@
+// Geometry - World, grid and cell coordinates of a map
+// Rust equivalent of GridDefines.h and NormalizeOrientation in MapManager.h
+//
+// A map is 64x64 grids of 533.33 yards centred on the world origin. The
+// server's grid and cell pairs count up with world x/y; the extracted .map,
+// .vmtile and .mmtile files number their grids the other way, from the
+// positive edge, so grid 0 holds the largest coordinates. Each grid is split
+// into 8x8 cells for visibility and object updates.
+
+use std::f32::consts::TAU;
+
+/// Side of a grid in yards
+pub const SIZE_OF_GRIDS: f32 = 533.333_3;
+/// Grids per map side
+pub const MAX_NUMBER_OF_GRIDS: u32 = 64;
+pub const CENTER_GRID_ID: u32 = MAX_NUMBER_OF_GRIDS / 2;
+pub const CENTER_GRID_OFFSET: f32 = SIZE_OF_GRIDS / 2.0;
+
+/// Cells per grid side
+pub const MAX_NUMBER_OF_CELLS: u32 = 8;
+pub const SIZE_OF_GRID_CELL: f32 = SIZE_OF_GRIDS / MAX_NUMBER_OF_CELLS as f32;
+pub const TOTAL_NUMBER_OF_CELLS_PER_MAP: u32 = MAX_NUMBER_OF_GRIDS * MAX_NUMBER_OF_CELLS;
+pub const CENTER_GRID_CELL_ID: u32 = TOTAL_NUMBER_OF_CELLS_PER_MAP / 2;
+pub const CENTER_GRID_CELL_OFFSET: f32 = SIZE_OF_GRID_CELL / 2.0;
+
+/// Side of a whole map in yards
+pub const MAP_SIZE: f32 = SIZE_OF_GRIDS * MAX_NUMBER_OF_GRIDS as f32;
+/// Distance from the centre to the edge of a map; the vmap and mmap
+/// extractors move world positions by this
+pub const MAP_HALFSIZE: f32 = MAP_SIZE / 2.0;
+
+/// Coordinate pair counting up with world x/y, None outside the map
+///
+/// Done in f64 like the C++ Compute template, so positions on a border land
+/// on the same side as there.
+fn compute(x: f32, y: f32, center_offset: f32, size: f32, center: u32, limit: u32) -> Option<(u32, u32)> {
+    let axis = |v: f32| {
+        let val = ((v as f64 - center_offset as f64) / size as f64 + center as f64 + 0.5).floor();
+        (0.0..limit as f64).contains(&val).then_some(val as u32)
+    };
+    Some((axis(x)?, axis(y)?))
+}
+
+/// Server grid (GridPair) of a world position
+pub fn compute_grid_pair(x: f32, y: f32) -> Option<(u32, u32)> {
+    compute(x, y, CENTER_GRID_OFFSET, SIZE_OF_GRIDS, CENTER_GRID_ID, MAX_NUMBER_OF_GRIDS)
+}
+
+/// Server cell (CellPair) of a world position
+pub fn compute_cell_pair(x: f32, y: f32) -> Option<(u32, u32)> {
+    compute(x, y, CENTER_GRID_CELL_OFFSET, SIZE_OF_GRID_CELL, CENTER_GRID_CELL_ID, TOTAL_NUMBER_OF_CELLS_PER_MAP)
+}
+
+/// Grid of a cell pair and the cell within that grid
+pub fn cell_to_grid(cell_x: u32, cell_y: u32) -> ((u32, u32), (u32, u32)) {
+    (
+        (cell_x / MAX_NUMBER_OF_CELLS, cell_y / MAX_NUMBER_OF_CELLS),
+        (cell_x % MAX_NUMBER_OF_CELLS, cell_y % MAX_NUMBER_OF_CELLS),
+    )
+}
+
+/// Grid of a world position as numbered in the extracted file names
+pub fn world_to_grid(x: f32, y: f32) -> Option<(u32, u32)> {
+    let (gx, gy) = compute_grid_pair(x, y)?;
+    Some((MAX_NUMBER_OF_GRIDS - 1 - gx, MAX_NUMBER_OF_GRIDS - 1 - gy))
+}
+
+/// Position along one axis in grids from the map's positive edge, fraction
+/// kept: file grid `g` covers g..g + 1
+pub fn grid_offset(v: f32) -> f32 {
+    CENTER_GRID_ID as f32 - v / SIZE_OF_GRIDS
+}
+
+/// World coordinates covered by file grid `g` along one axis, (min, max)
+pub fn grid_bounds(g: u32) -> (f32, f32) {
+    let max = (CENTER_GRID_ID as i32 - g as i32) as f32 * SIZE_OF_GRIDS;
+    (max - SIZE_OF_GRIDS, max)
+}
+
+/// Whether a coordinate is finite and inside the map
+pub fn is_valid_map_coord(c: f32) -> bool {
+    c.is_finite() && c.abs() <= MAP_HALFSIZE - 0.5
+}
+
+pub fn is_valid_map_position(x: f32, y: f32) -> bool {
+    is_valid_map_coord(x) && is_valid_map_coord(y)
+}
+
+/// Coordinate pulled back inside the map
+pub fn normalize_map_coord(c: f32) -> f32 {
+    c.clamp(-(MAP_HALFSIZE - 0.5), MAP_HALFSIZE - 0.5)
+}
+
+/// Orientation in [0, 2π)
+pub fn normalize_orientation(o: f32) -> f32 {
+    let o = o % TAU;
+    // -0.0 and values just below 0 must not end up as 2π
+    if o < 0.0 {
+        let o = o + TAU;
+        if o < TAU { o } else { 0.0 }
+    } else {
+        o
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_and_cell_coords() {
+        // Grids touch at the origin and count down from the positive edge
+        assert_eq!(world_to_grid(0.0, 0.0), Some((31, 31)));
+        assert_eq!(world_to_grid(-1.0, -1.0), Some((32, 32)));
+        assert_eq!(world_to_grid(1.0, 1.0), Some((31, 31)));
+        // Stormwind
+        assert_eq!(world_to_grid(-8913.0, 554.0), Some((48, 30)));
+        assert_eq!(world_to_grid(20000.0, 0.0), None);
+        assert_eq!(compute_grid_pair(-8913.0, 554.0), Some((15, 33)));
+
+        let (cx, cy) = compute_cell_pair(-8913.0, 554.0).unwrap();
+        assert_eq!((cx, cy), (122, 264));
+        assert_eq!(cell_to_grid(cx, cy), ((15, 33), (2, 0)));
+
+        assert_eq!(grid_bounds(31), (0.0, SIZE_OF_GRIDS));
+        assert_eq!(grid_bounds(32), (-SIZE_OF_GRIDS, 0.0));
+        let offset = grid_offset(-8913.0);
+        assert!((48.0..49.0).contains(&offset));
+    }
+
+    #[test]
+    fn test_map_coords_and_orientation() {
+        assert!(is_valid_map_position(-8913.0, 554.0));
+        assert!(!is_valid_map_position(MAP_HALFSIZE, 0.0));
+        assert!(!is_valid_map_coord(f32::NAN));
+        assert_eq!(normalize_map_coord(-20000.0), -(MAP_HALFSIZE - 0.5));
+        assert_eq!(normalize_map_coord(100.0), 100.0);
+
+        assert_eq!(normalize_orientation(1.0), 1.0);
+        assert!((normalize_orientation(-std::f32::consts::FRAC_PI_2) - 3.0 * std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!((normalize_orientation(TAU + 0.5) - 0.5).abs() < 1e-6);
+        assert_eq!(normalize_orientation(-1e-9), 0.0);
+        assert_eq!(normalize_orientation(TAU), 0.0);
+    }
+}
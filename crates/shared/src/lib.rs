@@ -3,10 +3,18 @@
 
 pub mod auth;
 pub mod config;
+pub mod daemon;
 pub mod database;
+pub mod dbc;
+pub mod error;
+pub mod geometry;
 pub mod log;
+pub mod mmap;
 pub mod network;
+pub mod terrain;
 pub mod util;
+pub mod vmap;
+pub mod world;
 
 /// Common type aliases matching the C++ codebase
 pub type AccountTypes = u8;
@@ -90,3 +98,9 @@ pub const MAX_REALM_ZONES: usize = 38;
 
 /// Minute in seconds
 pub const MINUTE: u32 = 60;
+/// Hour in seconds
+pub const HOUR: u32 = MINUTE * 60;
+/// Day in seconds
+pub const DAY: u32 = HOUR * 24;
+/// Week in seconds
+pub const WEEK: u32 = DAY * 7;
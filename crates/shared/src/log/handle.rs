@@ -0,0 +1,209 @@
+// Logging handle
+//
+// The file layers write through tracing_appender's non-blocking workers. A
+// worker only writes out its remaining queue when its WorkerGuard is dropped,
+// so the guards are kept here and the caller decides when logging ends. Queued
+// lines are counted on both sides of the worker so flush() can wait for them.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{Event, Subscriber};
+use tracing_appender::non_blocking::{ErrorCounter, NonBlocking, WorkerGuard};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// How long flush() waits for queued lines before giving up
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Lines handed to a worker versus lines it has written out
+#[derive(Default)]
+struct PendingLines {
+    queued: AtomicU64,
+    written: AtomicU64,
+}
+
+/// Keeps the file log workers alive; drop or shut down to flush them
+///
+/// Returned by [`initialize_logging`](super::initialize_logging). Callers must
+/// hold on to it for as long as they log, and should call
+/// [`shutdown`](LoggingHandle::shutdown) on the way out.
+#[must_use = "dropping the handle stops file logging"]
+#[derive(Default)]
+pub struct LoggingHandle {
+    guards: Vec<WorkerGuard>,
+    pending: Vec<(Arc<PendingLines>, ErrorCounter)>,
+    warnings: Arc<AtomicU64>,
+    #[cfg(feature = "otlp")]
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl LoggingHandle {
+    /// Start a non-blocking worker for `writer` and keep its guard
+    pub(super) fn spawn_writer<W: Write + Send + 'static>(&mut self, writer: W) -> TrackedWriter {
+        let pending = Arc::new(PendingLines::default());
+        let (inner, guard) = tracing_appender::non_blocking(CountingWriter {
+            inner: writer,
+            pending: pending.clone(),
+        });
+        self.guards.push(guard);
+        self.pending.push((pending.clone(), inner.error_counter()));
+        TrackedWriter { inner, pending }
+    }
+
+    /// Layer counting warnings and errors for [`warnings`](LoggingHandle::warnings)
+    pub(super) fn warning_counter(&self) -> WarningCounter {
+        WarningCounter {
+            warnings: self.warnings.clone(),
+        }
+    }
+
+    /// Number of warnings and errors logged so far
+    ///
+    /// The extractors use this to report partial success (ExitStatus::PartialSuccess).
+    pub fn warnings(&self) -> u64 {
+        self.warnings.load(Ordering::Relaxed)
+    }
+
+    /// Keep the OTLP tracer provider so its batch can be sent on shutdown
+    #[cfg(feature = "otlp")]
+    pub(super) fn set_tracer_provider(&mut self, provider: opentelemetry_sdk::trace::SdkTracerProvider) {
+        self.tracer_provider = Some(provider);
+    }
+
+    /// Wait until every line logged so far has been written to its file
+    ///
+    /// Returns false if the workers did not catch up within a couple of seconds.
+    /// Finished spans are handed to the OTLP exporter as well.
+    pub fn flush(&self) -> bool {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = &self.tracer_provider {
+            let _ = provider.force_flush();
+        }
+
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        let drained = |(pending, dropped): &(Arc<PendingLines>, ErrorCounter)| {
+            let done = pending.written.load(Ordering::Acquire) + dropped.dropped_lines() as u64;
+            done >= pending.queued.load(Ordering::Acquire)
+        };
+        while !self.pending.iter().all(drained) {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        true
+    }
+
+    /// Flush and stop the file log workers and the OTLP exporter
+    pub fn shutdown(self) {
+        self.flush();
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = &self.tracer_provider
+            && let Err(e) = provider.shutdown_with_timeout(FLUSH_TIMEOUT)
+        {
+            eprintln!("OTLP exporter shutdown failed: {}", e);
+        }
+        // Dropping the guards joins the workers
+    }
+}
+
+/// Counts WARN and ERROR events; install with a WARN level filter
+pub(super) struct WarningCounter {
+    warnings: Arc<AtomicU64>,
+}
+
+impl<S: Subscriber> Layer<S> for WarningCounter {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() <= tracing::Level::WARN {
+            self.warnings.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Writer used by the file layers; counts lines as they are queued
+#[derive(Clone)]
+pub(super) struct TrackedWriter {
+    inner: NonBlocking,
+    pending: Arc<PendingLines>,
+}
+
+impl Write for TrackedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.queued.fetch_add(1, Ordering::AcqRel);
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for TrackedWriter {
+    type Writer = TrackedWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Wraps the real file writer on the worker thread; counts lines written
+struct CountingWriter<W> {
+    inner: W,
+    pending: Arc<PendingLines>,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    // The worker hands over one queued line per write_all call
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let result = self.inner.write_all(buf);
+        self.pending.written.fetch_add(1, Ordering::AcqRel);
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_waits_for_queued_lines() {
+        let buf = SharedBuf::default();
+        let mut handle = LoggingHandle::default();
+        let writer = handle.spawn_writer(buf.clone());
+
+        for i in 0..100 {
+            writer.make_writer().write_all(format!("line {}\n", i).as_bytes()).unwrap();
+        }
+
+        assert!(handle.flush());
+        assert_eq!(buf.0.lock().unwrap().iter().filter(|&&b| b == b'\n').count(), 100);
+        handle.shutdown();
+    }
+}
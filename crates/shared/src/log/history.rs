@@ -0,0 +1,223 @@
+// In-memory log history
+//
+// Keeps the last N log records in a ring buffer so admin interfaces (RA
+// console, REST) can show recent log lines without reading log files.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::sync::Arc;
+
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use super::IpPrivacy;
+
+static LOG_HISTORY: OnceCell<Arc<LogHistory>> = OnceCell::new();
+
+/// The process-wide log history, if enabled in initialize_logging
+pub fn log_history() -> Option<Arc<LogHistory>> {
+    LOG_HISTORY.get().cloned()
+}
+
+/// A captured log record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub time: chrono::DateTime<chrono::Local>,
+    pub level: tracing::Level,
+    pub target: String,
+    /// Enclosing spans, root first, e.g. `session{peer=1.2.3.4:5678 account="BOB"}`
+    pub spans: String,
+    pub message: String,
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:>5} {}: ",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.level,
+            self.target
+        )?;
+        if !self.spans.is_empty() {
+            write!(f, "{}: ", self.spans)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// Fixed-size ring buffer of recent log records
+pub struct LogHistory {
+    capacity: usize,
+    records: Mutex<VecDeque<LogRecord>>,
+}
+
+impl LogHistory {
+    pub fn new(capacity: usize) -> Self {
+        LogHistory {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Register this history as the process-wide one returned by [`log_history`]
+    pub(super) fn install(self: &Arc<Self>) {
+        let _ = LOG_HISTORY.set(self.clone());
+    }
+
+    pub fn push(&self, record: LogRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// The newest `count` records, oldest first
+    pub fn recent(&self, count: usize) -> Vec<LogRecord> {
+        let records = self.records.lock();
+        records.iter().skip(records.len().saturating_sub(count)).cloned().collect()
+    }
+
+    /// The newest `count` records formatted as log lines, oldest first
+    pub fn recent_lines(&self, count: usize) -> Vec<String> {
+        self.recent(count).iter().map(ToString::to_string).collect()
+    }
+}
+
+/// Layer feeding every event into a [`LogHistory`]
+pub(super) struct HistoryLayer {
+    history: Arc<LogHistory>,
+    /// Applied to the message and span fields of every record
+    ip_privacy: IpPrivacy,
+}
+
+impl HistoryLayer {
+    pub(super) fn new(history: Arc<LogHistory>, ip_privacy: IpPrivacy) -> Self {
+        HistoryLayer { history, ip_privacy }
+    }
+}
+
+/// Span fields as recorded so far, stored in the span's extensions
+struct SpanFields(String);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for HistoryLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut visitor = MessageVisitor::default();
+            attrs.record(&mut visitor);
+            span.extensions_mut().insert(SpanFields(visitor.message));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if let Some(fields) = extensions.get_mut::<SpanFields>() {
+                let mut visitor = MessageVisitor {
+                    message: std::mem::take(&mut fields.0),
+                };
+                values.record(&mut visitor);
+                fields.0 = visitor.message;
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let mut spans = String::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if !spans.is_empty() {
+                    spans.push(':');
+                }
+                spans.push_str(span.name());
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    let _ = write!(spans, "{{{}}}", fields.0.trim_start());
+                }
+            }
+        }
+
+        self.history.push(LogRecord {
+            time: chrono::Local::now(),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            spans: self.ip_privacy.redact(&spans).into_owned(),
+            message: self.ip_privacy.redact(&visitor.message).into_owned(),
+        });
+    }
+}
+
+/// Collects the message plus any extra fields as `name=value`
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let fields = std::mem::take(&mut self.message);
+            let _ = write!(self.message, "{:?}", value);
+            self.message.push_str(&fields);
+        } else {
+            let _ = write!(self.message, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_history_keeps_last_records() {
+        let history = Arc::new(LogHistory::new(3));
+        let subscriber = tracing_subscriber::registry().with(HistoryLayer::new(history.clone(), IpPrivacy::Full));
+
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..5 {
+                tracing::info!(target: "test", "line {}", i);
+            }
+            tracing::warn!(target: "test", account = 7, "banned");
+        });
+
+        let records = history.recent(10);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].message, "line 3");
+        assert_eq!(records[2].message, "banned account=7");
+        assert_eq!(records[2].level, tracing::Level::WARN);
+        assert_eq!(history.recent(1)[0].target, "test");
+    }
+
+    #[test]
+    fn test_history_records_span_fields() {
+        let history = Arc::new(LogHistory::new(4));
+        let subscriber = tracing_subscriber::registry().with(HistoryLayer::new(history.clone(), IpPrivacy::Full));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("session", peer = "127.0.0.1:1234", account = tracing::field::Empty);
+            let _entered = span.enter();
+            tracing::info!(target: "test", "connected");
+            span.record("account", "BOB");
+            tracing::info!(target: "test", "challenge");
+        });
+
+        let records = history.recent(2);
+        assert_eq!(records[0].spans, "session{peer=\"127.0.0.1:1234\"}");
+        assert_eq!(records[1].spans, "session{peer=\"127.0.0.1:1234\" account=\"BOB\"}");
+        assert!(records[1].to_string().ends_with("test: session{peer=\"127.0.0.1:1234\" account=\"BOB\"}: challenge"));
+    }
+}
@@ -15,10 +15,126 @@
 //   3 = Full     -> DEBUG  (detailed activity)
 //   4 = Trace    -> TRACE  (packet-level debugging)
 
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
-use tracing_appender::rolling;
+mod handle;
+mod history;
+mod otlp;
+mod packet;
+mod privacy;
+mod rotation;
+mod system;
+
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter, Layer, Registry};
+use tracing_appender::rolling::{self, Rotation};
 use std::path::Path;
 
+use crate::config::Config;
+
+pub use handle::LoggingHandle;
+pub use history::{log_history, LogHistory, LogRecord};
+pub use otlp::OtlpExport;
+pub use packet::{dump_packet, hex_dump, packet_dump_enabled, set_packet_dump, LOG_TARGET_PACKETS};
+pub use privacy::{IpPrivacy, RedactingMakeWriter};
+pub use rotation::{LogRotation, SizeRotatingWriter};
+pub use system::{SystemLog, SystemLogBackend};
+
+/// Log target for database errors (the C++ core's dberror.log)
+pub const LOG_TARGET_DB_ERROR: &str = "dberror";
+
+/// Log target for authentication audit events
+pub const LOG_TARGET_AUDIT: &str = "audit";
+
+/// Log target for structured authentication decisions (one event per login attempt)
+pub const LOG_TARGET_AUTH_LOG: &str = "authlog";
+
+/// An additional named log file with its own filter
+///
+/// Mirrors the separate dberror.log / realmd.log files of the C++ core.
+/// The filter uses tracing directive syntax, so a sink can select events
+/// by target (e.g. "dberror=error") as well as by level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSink {
+    pub name: String,
+    pub file: String,
+    pub filter: String,
+    /// Write one JSON object per line instead of text
+    pub json: bool,
+}
+
+/// Optional logging features beyond the console/main file pair
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    /// Additional named log files with independent filters
+    pub sinks: Vec<LogSink>,
+    /// Rotation and retention applied to every log file
+    pub rotation: LogRotation,
+    /// Optional syslog/journald forwarding
+    pub system: Option<SystemLog>,
+    /// Number of recent records kept in memory (0 = disabled)
+    pub history_size: usize,
+    /// Tracing filter for the in-memory history
+    pub history_filter: String,
+    /// Optional OpenTelemetry span export
+    pub otlp: Option<OtlpExport>,
+    /// Redaction of client addresses outside the named sinks
+    pub ip_privacy: IpPrivacy,
+}
+
+impl LogOptions {
+    /// Read the logging options from the configuration
+    ///
+    /// `LogRotation.MaxFileSize` is in megabytes (0 = roll daily),
+    /// `LogRotation.MaxFiles` is the number of old files to keep (0 = all),
+    /// `LogRotation.Compress` gzips rotated files. `LogSystem.*` selects a
+    /// syslog/journald backend, identified as `identifier` unless overridden.
+    /// `LogHistory.Size` / `LogHistory.Filter` configure the in-memory history.
+    /// `LogOtlp.*` enables span export, reported as service `identifier` by default.
+    /// `LogPrivacy.Ip` redacts client addresses ("full", "truncate" or "hash").
+    pub fn from_config(config: &Config, identifier: &str) -> Self {
+        let ip_privacy = config.get_string("LogPrivacy.Ip");
+        let ip_privacy = IpPrivacy::parse(&ip_privacy).unwrap_or_else(|| {
+            eprintln!("Unknown LogPrivacy.Ip '{}', redacting addresses by hash", ip_privacy);
+            IpPrivacy::Hash
+        });
+        LogOptions {
+            sinks: load_log_sinks(config),
+            rotation: LogRotation {
+                max_file_size: config.get_int_default("LogRotation.MaxFileSize", 0).max(0) as u64 * 1024 * 1024,
+                max_files: config.get_int_default("LogRotation.MaxFiles", 0).max(0) as usize,
+                compress: config.get_bool_default("LogRotation.Compress", false),
+            },
+            system: SystemLog::from_config(config, identifier),
+            history_size: config.get_int_default("LogHistory.Size", 500).max(0) as usize,
+            history_filter: config.get_string_default("LogHistory.Filter", "info"),
+            otlp: OtlpExport::from_config(config, identifier),
+            ip_privacy,
+        }
+    }
+}
+
+/// Read the named log sinks from the configuration.
+///
+/// `LogSinks` is a comma/space separated list of sink names; each sink is then
+/// configured with `LogSink.<name>.File` (default "<name>.log") and
+/// `LogSink.<name>.Filter` (default "<name>=trace", i.e. everything logged
+/// with a target equal to the sink name). `LogSink.<name>.Format = "json"`
+/// writes one JSON object per event with its fields at the top level.
+pub fn load_log_sinks(config: &Config) -> Vec<LogSink> {
+    config
+        .get_string("LogSinks")
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+        .map(|name| LogSink {
+            name: name.to_string(),
+            file: config.get_string_default(&format!("LogSink.{}.File", name), &format!("{}.log", name)),
+            filter: config.get_string_default(&format!("LogSink.{}.Filter", name), &format!("{}=trace", name)),
+            json: config
+                .get_string_default(&format!("LogSink.{}.Format", name), "text")
+                .eq_ignore_ascii_case("json"),
+        })
+        .collect()
+}
+
 /// Map the C++ LogLevel integer (0-4) to a tracing filter string.
 ///
 /// C++ levels:
@@ -46,53 +162,134 @@ pub fn map_log_level(level: i32) -> &'static str {
 ///   log_dir       - Optional directory for log files
 ///   console_level - Tracing filter for console output (e.g., "info", "debug", "trace")
 ///   file_level    - Optional tracing filter for file output (defaults to console_level)
-pub fn initialize_logging(log_dir: Option<&str>, console_level: &str, file_level: Option<&str>) {
+///   options       - Named sinks (written to log_dir, or the current directory when
+///                   no log_dir is set), rotation settings, system log backend,
+///                   in-memory history and OTLP export
+///
+/// The returned handle owns the file writers; keep it alive for the lifetime of
+/// the program and call `shutdown()` before exiting so queued lines are written.
+pub fn initialize_logging(
+    log_dir: Option<&str>,
+    console_level: &str,
+    file_level: Option<&str>,
+    options: &LogOptions,
+) -> LoggingHandle {
     // RUST_LOG env var always takes precedence over config
     let console_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new(console_level));
 
+    let mut handle = LoggingHandle::default();
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+
+    layers.push(handle.warning_counter().with_filter(LevelFilter::WARN).boxed());
+
+    layers.push(
+        fmt::layer()
+            .with_writer(RedactingMakeWriter::new(std::io::stdout, options.ip_privacy))
+            .with_ansi(true)
+            .with_target(false)
+            .with_thread_ids(false)
+            .with_filter(console_filter)
+            .boxed(),
+    );
+
     if let Some(dir) = log_dir {
-        let path = Path::new(dir);
-        if !path.exists() {
-            let _ = std::fs::create_dir_all(path);
+        let file_filter_str = file_level.unwrap_or(console_level);
+        let filter = EnvFilter::new(file_filter_str);
+        layers.push(file_layer(&mut handle, dir, "realmd.log", filter, &options.rotation, false, options.ip_privacy));
+    }
+
+    for sink in &options.sinks {
+        let filter = match EnvFilter::try_new(&sink.filter) {
+            Ok(filter) => filter,
+            Err(e) => {
+                eprintln!("Invalid filter '{}' for log sink '{}': {}", sink.filter, sink.name, e);
+                continue;
+            }
+        };
+        let dir = log_dir.unwrap_or(".");
+        layers.push(file_layer(&mut handle, dir, &sink.file, filter, &options.rotation, sink.json, IpPrivacy::Full));
+    }
+
+    if let Some(layer) = options.system.as_ref().and_then(|system| system.layer(options.ip_privacy)) {
+        layers.push(layer);
+    }
+
+    if options.history_size > 0 {
+        match EnvFilter::try_new(&options.history_filter) {
+            Ok(filter) => {
+                let history = std::sync::Arc::new(LogHistory::new(options.history_size));
+                history.install();
+                layers.push(history::HistoryLayer::new(history, options.ip_privacy).with_filter(filter).boxed());
+            }
+            Err(e) => eprintln!("Invalid LogHistory.Filter '{}': {}", options.history_filter, e),
         }
+    }
 
-        let file_appender = rolling::daily(dir, "realmd.log");
-        let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+    if let Some(layer) = options.otlp.as_ref().and_then(|otlp| otlp.layer(&mut handle)) {
+        layers.push(layer);
+    }
 
-        // Keep the guard alive by leaking it (it lives for the program duration)
-        std::mem::forget(_guard);
+    tracing_subscriber::registry().with(layers).init();
+    handle
+}
 
-        let file_filter_str = file_level.unwrap_or(console_level);
-        let file_filter = EnvFilter::new(file_filter_str);
-
-        tracing_subscriber::registry()
-            .with(
-                fmt::layer()
-                    .with_ansi(true)
-                    .with_target(false)
-                    .with_thread_ids(false)
-                    .with_filter(console_filter),
-            )
-            .with(
-                fmt::layer()
-                    .with_writer(non_blocking)
-                    .with_ansi(false)
-                    .with_target(true)
-                    .with_filter(file_filter),
-            )
-            .init();
+/// Build a rotating, non-blocking file layer
+/// Rolls by size when a maximum size is configured, otherwise daily.
+fn file_layer(
+    handle: &mut LoggingHandle,
+    dir: &str,
+    file_name: &str,
+    filter: EnvFilter,
+    rotation: &LogRotation,
+    json: bool,
+    ip_privacy: IpPrivacy,
+) -> Box<dyn Layer<Registry> + Send + Sync> {
+    let path = Path::new(dir);
+    if !path.exists() {
+        let _ = std::fs::create_dir_all(path);
+    }
+
+    let writer = if rotation.max_file_size > 0 {
+        match SizeRotatingWriter::new(path, file_name, rotation.clone()) {
+            Ok(writer) => handle.spawn_writer(writer),
+            Err(e) => {
+                eprintln!("Cannot open log file {}/{}: {}", dir, file_name, e);
+                handle.spawn_writer(std::io::sink())
+            }
+        }
     } else {
-        tracing_subscriber::registry()
-            .with(console_filter)
-            .with(
-                fmt::layer()
-                    .with_ansi(true)
-                    .with_target(false)
-                    .with_thread_ids(false),
-            )
-            .init();
+        let mut builder = rolling::Builder::new()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(file_name);
+        if rotation.max_files > 0 {
+            builder = builder.max_log_files(rotation.max_files);
+        }
+        match builder.build(dir) {
+            Ok(appender) => handle.spawn_writer(appender),
+            Err(e) => {
+                eprintln!("Cannot open log file {}/{}: {}", dir, file_name, e);
+                handle.spawn_writer(std::io::sink())
+            }
+        }
+    };
+    let writer = RedactingMakeWriter::new(writer, ip_privacy);
+
+    if json {
+        return fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_writer(writer)
+            .with_target(true)
+            .with_filter(filter)
+            .boxed();
     }
+    fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_target(true)
+        .with_filter(filter)
+        .boxed()
 }
 
 /// Convenience macros that map to the C++ logging functions
@@ -116,3 +313,30 @@ macro_rules! debug_log {
 macro_rules! error_log {
     ($($arg:tt)*) => { tracing::error!($($arg)*) };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_log_sinks() {
+        let path = std::env::temp_dir().join(format!("mangos_log_sinks_{}.conf", std::process::id()));
+        std::fs::write(
+            &path,
+            "LogSinks = \"dberror, audit\"\nLogSink.audit.File = \"auth_audit.log\"\nLogSink.audit.Format = \"JSON\"\n",
+        )
+        .unwrap();
+
+        let mut config = Config::new();
+        assert!(config.set_source(path.to_str().unwrap(), ""));
+        let sinks = load_log_sinks(&config);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(sinks.len(), 2);
+        assert_eq!(sinks[0].file, "dberror.log");
+        assert_eq!(sinks[0].filter, "dberror=trace");
+        assert!(!sinks[0].json);
+        assert_eq!(sinks[1].file, "auth_audit.log");
+        assert!(sinks[1].json);
+    }
+}
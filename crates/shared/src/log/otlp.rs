@@ -0,0 +1,99 @@
+// OpenTelemetry export
+//
+// Sends tracing spans to an OTLP/HTTP collector (Jaeger, Grafana Tempo, ...)
+// when built with the "otlp" feature and LogOtlp.Endpoint is set. The auth
+// session, its commands and every database query run in spans, so a trace
+// shows where the time of a slow login went.
+
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use super::LoggingHandle;
+use crate::config::Config;
+
+/// OTLP span export settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtlpExport {
+    /// Collector traces URL, e.g. "http://localhost:4318/v1/traces"
+    pub endpoint: String,
+    /// service.name resource attribute
+    pub service_name: String,
+    /// Tracing directive filter selecting which spans are exported
+    pub filter: String,
+    /// Fraction of sessions traced, 0.0 - 1.0
+    pub sample_ratio: f64,
+}
+
+impl OtlpExport {
+    /// Read `LogOtlp.*` from the configuration; None when no endpoint is set
+    pub fn from_config(config: &Config, default_service_name: &str) -> Option<Self> {
+        let endpoint = config.get_string("LogOtlp.Endpoint");
+        if endpoint.is_empty() {
+            return None;
+        }
+
+        Some(OtlpExport {
+            endpoint,
+            service_name: config.get_string_default("LogOtlp.ServiceName", default_service_name),
+            filter: config.get_string_default("LogOtlp.Filter", "info"),
+            sample_ratio: config.get_float_default("LogOtlp.SampleRatio", 1.0).clamp(0.0, 1.0) as f64,
+        })
+    }
+
+    /// Build the export layer; the tracer provider is kept in `handle` so
+    /// pending spans are sent on shutdown
+    pub(super) fn layer(&self, handle: &mut LoggingHandle) -> Option<Box<dyn Layer<Registry> + Send + Sync>> {
+        let filter = match EnvFilter::try_new(&self.filter) {
+            Ok(filter) => filter,
+            Err(e) => {
+                eprintln!("Invalid LogOtlp.Filter '{}': {}", self.filter, e);
+                return None;
+            }
+        };
+        otlp_layer(self, filter, handle)
+    }
+}
+
+#[cfg(feature = "otlp")]
+fn otlp_layer(
+    settings: &OtlpExport,
+    filter: EnvFilter,
+    handle: &mut LoggingHandle,
+) -> Option<Box<dyn Layer<Registry> + Send + Sync>> {
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+    use opentelemetry_sdk::Resource;
+
+    let exporter = match opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(settings.endpoint.clone())
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            eprintln!("Cannot create OTLP exporter for '{}': {}", settings.endpoint, e);
+            return None;
+        }
+    };
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(settings.sample_ratio))))
+        .with_resource(Resource::builder().with_service_name(settings.service_name.clone()).build())
+        .build();
+    let tracer = provider.tracer("mangos");
+    handle.set_tracer_provider(provider);
+
+    Some(
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(filter)
+            .boxed(),
+    )
+}
+
+#[cfg(not(feature = "otlp"))]
+fn otlp_layer(_: &OtlpExport, _: EnvFilter, _: &mut LoggingHandle) -> Option<Box<dyn Layer<Registry> + Send + Sync>> {
+    eprintln!("LogOtlp.Endpoint is set, but this build lacks the \"otlp\" feature");
+    None
+}
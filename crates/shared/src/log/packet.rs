@@ -0,0 +1,86 @@
+// Packet dumping
+// Rust equivalent of the C++ sLog.outWorldPacketDump / LogRealmPackets output
+//
+// Buffers are printed 16 bytes per row as offset, hex bytes and printable ASCII:
+//   0000: 00 08 28 00 57 6F 57 00 02 04 03 F8 20 36 38 78  |..(.WoW..... 68x|
+
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Log target for packet dumps, so they can be routed to their own sink
+pub const LOG_TARGET_PACKETS: &str = "packets";
+
+const BYTES_PER_ROW: usize = 16;
+
+static PACKET_DUMP: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable packet dumping (Log.PacketDump)
+pub fn set_packet_dump(enabled: bool) {
+    PACKET_DUMP.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether packet dumps should be produced at all
+pub fn packet_dump_enabled() -> bool {
+    PACKET_DUMP.load(Ordering::Relaxed) && tracing::enabled!(target: LOG_TARGET_PACKETS, tracing::Level::TRACE)
+}
+
+/// Format a byte buffer as a hex+ASCII dump
+pub fn hex_dump(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(BYTES_PER_ROW) * 76);
+    for (row, chunk) in data.chunks(BYTES_PER_ROW).enumerate() {
+        if row > 0 {
+            out.push('\n');
+        }
+        let _ = write!(out, "{:04X}: ", row * BYTES_PER_ROW);
+        for i in 0..BYTES_PER_ROW {
+            match chunk.get(i) {
+                Some(b) => {
+                    let _ = write!(out, "{:02X} ", b);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str(" |");
+        out.extend(chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        out.push('|');
+    }
+    out
+}
+
+/// Dump a sent or received packet at trace level if packet dumping is enabled
+///
+/// `direction` is a short label such as "RECV" or "SEND".
+pub fn dump_packet(direction: &str, peer: impl Display, data: &[u8]) {
+    if !packet_dump_enabled() {
+        return;
+    }
+    tracing::trace!(
+        target: LOG_TARGET_PACKETS,
+        "[{}] {} {} bytes\n{}",
+        peer,
+        direction,
+        data.len(),
+        hex_dump(data)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump() {
+        assert_eq!(hex_dump(&[]), "");
+        assert_eq!(
+            hex_dump(b"WoW\x00\x01"),
+            "0000: 57 6F 57 00 01                                   |WoW..|"
+        );
+
+        let data: Vec<u8> = (0x41..0x41 + 17).collect();
+        let dump = hex_dump(&data);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("|ABCDEFGHIJKLMNOP|"));
+        assert!(lines[1].starts_with("0010: 51 "));
+    }
+}
@@ -0,0 +1,221 @@
+// IP address redaction in log output
+//
+// With LogPrivacy.Ip set to "truncate" or "hash", client addresses in the
+// console, the main log file, the system log and the in-memory history are
+// rewritten before they are written: truncated to their network (the last
+// IPv4 octet, all but the first 48 IPv6 bits) or replaced by a keyed hash that
+// stays the same until the process restarts, so lines of one client can still
+// be correlated. Named log sinks (e.g. the audit sink) keep full addresses.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use data_encoding::HEXLOWER;
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use tracing::Metadata;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::auth::hmac_sha1::hmac_sha1;
+
+/// Key of the address hashes, new for every process
+static HASH_KEY: Lazy<[u8; 16]> = Lazy::new(|| {
+    let mut key = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut key);
+    key
+});
+
+/// How client addresses appear in log output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpPrivacy {
+    /// Addresses are logged as they are
+    #[default]
+    Full,
+    /// Only the network: 203.0.113.0, 2001:db8:1::
+    Truncate,
+    /// A keyed hash: ip-1a2b3c4d
+    Hash,
+}
+
+impl IpPrivacy {
+    /// Parse LogPrivacy.Ip: "full" (or empty), "truncate" or "hash"
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "full" => Some(IpPrivacy::Full),
+            "truncate" => Some(IpPrivacy::Truncate),
+            "hash" => Some(IpPrivacy::Hash),
+            _ => None,
+        }
+    }
+
+    /// `ip` as it is logged
+    pub fn redact_ip(self, ip: IpAddr) -> String {
+        let ip = ip.to_canonical();
+        match self {
+            IpPrivacy::Full => ip.to_string(),
+            IpPrivacy::Truncate => match ip {
+                IpAddr::V4(ip) => {
+                    let [a, b, c, _] = ip.octets();
+                    Ipv4Addr::new(a, b, c, 0).to_string()
+                }
+                IpAddr::V6(ip) => {
+                    let [a, b, c, ..] = ip.segments();
+                    Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0).to_string()
+                }
+            },
+            IpPrivacy::Hash => {
+                let digest = hmac_sha1(&*HASH_KEY, ip.to_string().as_bytes());
+                format!("ip-{}", HEXLOWER.encode(&digest[..4]))
+            }
+        }
+    }
+
+    /// `text` with every client address redacted; loopback and unspecified
+    /// addresses (listen addresses) are left alone
+    pub fn redact(self, text: &str) -> Cow<'_, str> {
+        if self == IpPrivacy::Full {
+            return Cow::Borrowed(text);
+        }
+
+        let is_address_char = |c: char| c.is_ascii_hexdigit() || c == '.' || c == ':';
+        let mut out = String::new();
+        let mut copied = 0;
+        let mut rest = text;
+        let mut offset = 0;
+        while let Some(start) = rest.find(is_address_char) {
+            let len = rest[start..].find(|c: char| !is_address_char(c)).unwrap_or(rest.len() - start);
+            let token = &rest[start..start + len];
+            // A full stop after an address ends the sentence
+            let candidate = token.trim_end_matches('.');
+            if let Some((ip, port)) = parse_address(candidate)
+                && !ip.is_loopback()
+                && !ip.is_unspecified()
+            {
+                let token_start = offset + start;
+                out.push_str(&text[copied..token_start]);
+                out.push_str(&self.redact_ip(ip));
+                if let Some(port) = port {
+                    out.push(':');
+                    out.push_str(port);
+                }
+                copied = token_start + candidate.len();
+            }
+            offset += start + len;
+            rest = &rest[start + len..];
+        }
+
+        if copied == 0 {
+            return Cow::Borrowed(text);
+        }
+        out.push_str(&text[copied..]);
+        Cow::Owned(out)
+    }
+}
+
+/// An IP address, or an IPv4 address with its port
+fn parse_address(token: &str) -> Option<(IpAddr, Option<&str>)> {
+    if !token.contains(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    if let Ok(ip) = token.parse::<IpAddr>() {
+        return Some((ip, None));
+    }
+    let (ip, port) = token.rsplit_once(':')?;
+    let ip = ip.parse::<Ipv4Addr>().ok()?;
+    (!port.is_empty() && port.bytes().all(|b| b.is_ascii_digit())).then_some((IpAddr::V4(ip), Some(port)))
+}
+
+/// Redacts the addresses in everything written through `inner`
+///
+/// The fmt layer writes each event in one call, so an address is never split
+/// between two writes.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+    privacy: IpPrivacy,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M, privacy: IpPrivacy) -> Self {
+        RedactingMakeWriter { inner, privacy }
+    }
+}
+
+pub struct RedactingWriter<W> {
+    inner: W,
+    privacy: IpPrivacy,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(text) if self.privacy != IpPrivacy::Full => self.inner.write_all(self.privacy.redact(text).as_bytes())?,
+            _ => self.inner.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter { inner: self.inner.make_writer(), privacy: self.privacy }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        RedactingWriter { inner: self.inner.make_writer_for(meta), privacy: self.privacy }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(IpPrivacy::parse(""), Some(IpPrivacy::Full));
+        assert_eq!(IpPrivacy::parse("Truncate"), Some(IpPrivacy::Truncate));
+        assert_eq!(IpPrivacy::parse("hash"), Some(IpPrivacy::Hash));
+        assert_eq!(IpPrivacy::parse("mask"), None);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let privacy = IpPrivacy::Truncate;
+        assert_eq!(
+            privacy.redact("Banned IP 203.0.113.45 tried to login."),
+            "Banned IP 203.0.113.0 tried to login."
+        );
+        assert_eq!(privacy.redact("session{peer=198.51.100.7:51234}: done"), "session{peer=198.51.100.0:51234}: done");
+        assert_eq!(privacy.redact("from [2001:db8:1:2::5]:3724"), "from [2001:db8:1::]:3724");
+        assert_eq!(privacy.redact("from ::ffff:192.0.2.9"), "from 192.0.2.0");
+    }
+
+    #[test]
+    fn test_leaves_other_text_alone() {
+        let privacy = IpPrivacy::Truncate;
+        for text in [
+            "Listening on 0.0.0.0:3724",
+            "Reconnect from 127.0.0.1:50000",
+            "Client 2.4.3 (build=8606) at 12:30:45, hash 3738EC7E7C731FD4",
+            "Version 1.12.1.5875",
+        ] {
+            assert!(matches!(privacy.redact(text), Cow::Borrowed(_)), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        let privacy = IpPrivacy::Hash;
+        let first = privacy.redact("peer=203.0.113.45:1 ip=203.0.113.45").into_owned();
+        let hash = privacy.redact_ip("203.0.113.45".parse().unwrap());
+        assert!(hash.starts_with("ip-") && hash.len() == 11);
+        assert_eq!(first, format!("peer={}:1 ip={}", hash, hash));
+        assert_ne!(hash, privacy.redact_ip("203.0.113.46".parse().unwrap()));
+    }
+}
@@ -0,0 +1,253 @@
+// Size-based log rotation
+//
+// tracing_appender only rolls files by time. This writer rolls a log file once
+// it reaches a configured size, keeping a bounded number of older files
+// (`name.1`, `name.2`, ... or `name.1.gz`, ... when compression is enabled).
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+/// Log rotation and retention settings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogRotation {
+    /// Roll the file once it reaches this many bytes (0 = roll daily instead)
+    pub max_file_size: u64,
+    /// Number of rotated files to keep (0 = keep everything)
+    pub max_files: usize,
+    /// Gzip rotated files
+    pub compress: bool,
+}
+
+/// A file writer that rotates by size
+pub struct SizeRotatingWriter {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    rotation: LogRotation,
+    /// Compression worker, started by the first compressed rotation
+    compressor: Option<Compressor>,
+    /// Number of the next staging file
+    staged: u64,
+}
+
+/// Thread that shifts the `.N.gz` files and gzips staged logs, one rotation
+/// at a time and in the order they happened
+struct Compressor {
+    jobs: Option<mpsc::Sender<PathBuf>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Compressor {
+    fn start(path: PathBuf, rotation: LogRotation) -> Self {
+        let (jobs, queue) = mpsc::channel::<PathBuf>();
+        let thread = std::thread::spawn(move || {
+            for staging in queue {
+                let result = shift_rotated(&path, &rotation)
+                    .and_then(|()| compress_file(&staging, &rotated_path(&path, 1, true)));
+                if let Err(e) = result {
+                    eprintln!("Failed to compress rotated log {}: {}", staging.display(), e);
+                }
+            }
+        });
+        Compressor { jobs: Some(jobs), thread: Some(thread) }
+    }
+}
+
+impl Drop for Compressor {
+    /// Finish the queued rotations so no staged log is left behind
+    fn drop(&mut self) {
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Path of the n-th rotated file of `path`
+fn rotated_path(path: &Path, index: usize, compress: bool) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    if compress {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+/// Drop the oldest rotated file and shift the others up by one, freeing `.1`
+fn shift_rotated(path: &Path, rotation: &LogRotation) -> io::Result<()> {
+    let keep = rotation.max_files;
+    if keep > 0 {
+        let _ = fs::remove_file(rotated_path(path, keep, rotation.compress));
+    }
+
+    // Find the highest existing index when retention is unlimited
+    let highest = if keep > 0 {
+        keep.saturating_sub(1)
+    } else {
+        (1..).take_while(|i| rotated_path(path, *i, rotation.compress).exists()).last().unwrap_or(0)
+    };
+
+    for index in (1..=highest).rev() {
+        let from = rotated_path(path, index, rotation.compress);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1, rotation.compress))?;
+        }
+    }
+    Ok(())
+}
+
+impl SizeRotatingWriter {
+    /// Open (or create) `dir/file_name` for appending
+    pub fn new(dir: &Path, file_name: &str, rotation: LogRotation) -> io::Result<Self> {
+        let path = dir.join(file_name);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(SizeRotatingWriter {
+            path,
+            file,
+            written,
+            rotation,
+            compressor: None,
+            staged: 0,
+        })
+    }
+
+    /// Unused staging path for the file being rotated
+    fn staging_path(&mut self) -> PathBuf {
+        loop {
+            self.staged += 1;
+            let mut name = self.path.as_os_str().to_owned();
+            name.push(format!(".rotating.{}", self.staged));
+            let staging = PathBuf::from(name);
+            if !staging.exists() {
+                return staging;
+            }
+        }
+    }
+
+    /// Move the current file to `.1`, shifting older files up, and reopen
+    ///
+    /// With compression the file is only renamed to its own staging path here;
+    /// the shift and the gzip run on the compression thread, so a rotation
+    /// never overtakes the one before it.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.rotation.compress {
+            let staging = self.staging_path();
+            fs::rename(&self.path, &staging)?;
+            let (path, rotation) = (self.path.clone(), self.rotation.clone());
+            let compressor = self.compressor.get_or_insert_with(|| Compressor::start(path, rotation));
+            if let Some(jobs) = &compressor.jobs {
+                jobs.send(staging).map_err(|_| io::Error::other("log compression thread stopped"))?;
+            }
+        } else {
+            shift_rotated(&self.path, &self.rotation)?;
+            fs::rename(&self.path, rotated_path(&self.path, 1, false))?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.rotation.max_file_size > 0
+            && self.written > 0
+            && self.written + buf.len() as u64 > self.rotation.max_file_size
+        {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Gzip `from` into `to` and remove the source
+fn compress_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut input = File::open(from)?;
+    let output = File::create(to)?;
+    let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotates_by_size_with_retention() {
+        let dir = std::env::temp_dir().join(format!("mangos_rotation_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let rotation = LogRotation {
+            max_file_size: 16,
+            max_files: 2,
+            compress: false,
+        };
+        let mut writer = SizeRotatingWriter::new(&dir, "test.log", rotation).unwrap();
+        for _ in 0..5 {
+            writer.write_all(b"0123456789\n").unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert!(dir.join("test.log").exists());
+        assert!(dir.join("test.log.1").exists());
+        assert!(dir.join("test.log.2").exists());
+        assert!(!dir.join("test.log.3").exists());
+        assert_eq!(fs::read(dir.join("test.log")).unwrap().len(), 11);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_compressed_rotations_keep_every_line() {
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join(format!("mangos_rotation_gz_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let rotation = LogRotation {
+            max_file_size: 16,
+            max_files: 0,
+            compress: true,
+        };
+        let mut writer = SizeRotatingWriter::new(&dir, "test.log", rotation).unwrap();
+        // Back to back rotations, faster than the first can be compressed
+        for line in [b"first line\n", b"secnd line\n", b"third line\n"] {
+            writer.write_all(line).unwrap();
+        }
+        drop(writer);
+
+        let gunzip = |name: &str| {
+            let mut text = String::new();
+            flate2::read::GzDecoder::new(File::open(dir.join(name)).unwrap()).read_to_string(&mut text).unwrap();
+            text
+        };
+        assert_eq!(gunzip("test.log.2.gz"), "first line\n");
+        assert_eq!(gunzip("test.log.1.gz"), "secnd line\n");
+        assert_eq!(fs::read_to_string(dir.join("test.log")).unwrap(), "third line\n");
+        let leftovers: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.contains(".rotating"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
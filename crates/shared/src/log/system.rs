@@ -0,0 +1,224 @@
+// System log backends
+//
+// Forwards log records to the host's log infrastructure when running as a
+// service: classic syslog (feature "syslog") or systemd-journald (feature
+// "journald"). Selected with LogSystem.Backend in the configuration.
+
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::config::Config;
+
+use super::IpPrivacy;
+
+/// Where system log records are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemLogBackend {
+    Syslog,
+    Journald,
+}
+
+/// System log sink settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemLog {
+    pub backend: SystemLogBackend,
+    /// Tracing directive filter, e.g. "info" or "warn,audit=info"
+    pub filter: String,
+    /// Program name reported to syslog/journald
+    pub identifier: String,
+    /// Syslog facility name (syslog backend only)
+    pub facility: String,
+}
+
+impl SystemLog {
+    /// Read `LogSystem.*` from the configuration; None when disabled
+    pub fn from_config(config: &Config, default_identifier: &str) -> Option<Self> {
+        let backend = match config.get_string("LogSystem.Backend").to_ascii_lowercase().as_str() {
+            "syslog" => SystemLogBackend::Syslog,
+            "journald" | "journal" => SystemLogBackend::Journald,
+            "" | "none" => return None,
+            other => {
+                eprintln!("Unknown LogSystem.Backend '{}', system logging disabled", other);
+                return None;
+            }
+        };
+
+        Some(SystemLog {
+            backend,
+            filter: config.get_string_default("LogSystem.Filter", "info"),
+            identifier: config.get_string_default("LogSystem.Identifier", default_identifier),
+            facility: config.get_string_default("LogSystem.Facility", "daemon"),
+        })
+    }
+
+    /// Build the layer for this backend, or None if it is unavailable
+    /// Layer for the backend; syslog lines get client addresses redacted per `ip_privacy`,
+    /// journald fields are passed on as they are
+    pub(super) fn layer(&self, ip_privacy: IpPrivacy) -> Option<Box<dyn Layer<Registry> + Send + Sync>> {
+        let filter = match EnvFilter::try_new(&self.filter) {
+            Ok(filter) => filter,
+            Err(e) => {
+                eprintln!("Invalid LogSystem.Filter '{}': {}", self.filter, e);
+                return None;
+            }
+        };
+
+        match self.backend {
+            SystemLogBackend::Syslog => syslog_layer(self, filter, ip_privacy),
+            SystemLogBackend::Journald => journald_layer(self, filter),
+        }
+    }
+}
+
+#[cfg(feature = "syslog")]
+fn syslog_layer(settings: &SystemLog, filter: EnvFilter, ip_privacy: IpPrivacy) -> Option<Box<dyn Layer<Registry> + Send + Sync>> {
+    use tracing_subscriber::fmt;
+
+    use super::RedactingMakeWriter;
+
+    let facility = settings.facility.parse().unwrap_or_else(|_| {
+        eprintln!("Unknown LogSystem.Facility '{}', using daemon", settings.facility);
+        syslog::Facility::LOG_DAEMON
+    });
+    let formatter = syslog::Formatter3164 {
+        facility,
+        hostname: None,
+        process: settings.identifier.clone(),
+        pid: std::process::id(),
+    };
+
+    match syslog::unix(formatter) {
+        Ok(logger) => Some(
+            fmt::layer()
+                .with_writer(RedactingMakeWriter::new(writer::SyslogMakeWriter::new(logger), ip_privacy))
+                .with_ansi(false)
+                .without_time()
+                .with_level(false)
+                .with_target(true)
+                .with_filter(filter)
+                .boxed(),
+        ),
+        Err(e) => {
+            eprintln!("Cannot connect to syslog: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "syslog"))]
+fn syslog_layer(_: &SystemLog, _: EnvFilter, _: IpPrivacy) -> Option<Box<dyn Layer<Registry> + Send + Sync>> {
+    eprintln!("LogSystem.Backend = syslog, but this build lacks the \"syslog\" feature");
+    None
+}
+
+#[cfg(feature = "journald")]
+fn journald_layer(settings: &SystemLog, filter: EnvFilter) -> Option<Box<dyn Layer<Registry> + Send + Sync>> {
+    match tracing_journald::layer() {
+        Ok(layer) => Some(
+            layer
+                .with_syslog_identifier(settings.identifier.clone())
+                .with_filter(filter)
+                .boxed(),
+        ),
+        Err(e) => {
+            eprintln!("Cannot connect to journald: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "journald"))]
+fn journald_layer(_: &SystemLog, _: EnvFilter) -> Option<Box<dyn Layer<Registry> + Send + Sync>> {
+    eprintln!("LogSystem.Backend = journald, but this build lacks the \"journald\" feature");
+    None
+}
+
+#[cfg(feature = "syslog")]
+mod writer {
+    use std::io::{self, Write};
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+    use syslog::{Formatter3164, Logger, LoggerBackend};
+    use tracing::{Level, Metadata};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    type SyslogLogger = Logger<LoggerBackend, Formatter3164>;
+
+    /// Hands each formatted event to syslog at the event's severity
+    pub struct SyslogMakeWriter {
+        logger: Arc<Mutex<SyslogLogger>>,
+    }
+
+    impl SyslogMakeWriter {
+        pub fn new(logger: SyslogLogger) -> Self {
+            SyslogMakeWriter {
+                logger: Arc::new(Mutex::new(logger)),
+            }
+        }
+    }
+
+    pub struct SyslogWriter {
+        logger: Arc<Mutex<SyslogLogger>>,
+        level: Level,
+    }
+
+    impl Write for SyslogWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let line = String::from_utf8_lossy(buf);
+            let line = line.trim_end();
+            let mut logger = self.logger.lock();
+            let result = match self.level {
+                Level::ERROR => logger.err(line),
+                Level::WARN => logger.warning(line),
+                Level::INFO => logger.info(line),
+                _ => logger.debug(line),
+            };
+            result.map_err(|e| io::Error::other(e.to_string()))?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for SyslogMakeWriter {
+        type Writer = SyslogWriter;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            SyslogWriter {
+                logger: self.logger.clone(),
+                level: Level::INFO,
+            }
+        }
+
+        fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+            SyslogWriter {
+                logger: self.logger.clone(),
+                level: *meta.level(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_log_from_config() {
+        let path = std::env::temp_dir().join(format!("mangos_system_log_{}.conf", std::process::id()));
+        std::fs::write(&path, "LogSystem.Backend = \"Journald\"\nLogSystem.Filter = \"warn\"\n").unwrap();
+
+        let mut config = Config::new();
+        assert!(config.set_source(path.to_str().unwrap(), ""));
+        let system = SystemLog::from_config(&config, "realmd");
+        let _ = std::fs::remove_file(&path);
+
+        let system = system.expect("backend configured");
+        assert_eq!(system.backend, SystemLogBackend::Journald);
+        assert_eq!(system.filter, "warn");
+        assert_eq!(system.identifier, "realmd");
+        assert_eq!(system.facility, "daemon");
+    }
+}
@@ -0,0 +1,191 @@
+// Detour common - Vector and polygon helpers of the navigation mesh
+// Rust equivalent of DetourCommon.h/cpp
+//
+// Detour positions are [x, y, z] with y up; the "2D" helpers work on the
+// x/z plane. Polygons are wound so that tri_area_2d of consecutive
+// vertices is positive.
+
+/// Positions closer than this are the same point
+const EQUAL_THRESHOLD: f32 = (1.0 / 16384.0) * (1.0 / 16384.0);
+
+pub fn vadd(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn vsub(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn vlerp(a: &[f32; 3], b: &[f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+pub fn vlen_sqr(v: &[f32; 3]) -> f32 {
+    v[0] * v[0] + v[1] * v[1] + v[2] * v[2]
+}
+
+pub fn vdist(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    vlen_sqr(&vsub(a, b)).sqrt()
+}
+
+pub fn vequal(a: &[f32; 3], b: &[f32; 3]) -> bool {
+    vlen_sqr(&vsub(a, b)) < EQUAL_THRESHOLD
+}
+
+pub fn visfinite(v: &[f32; 3]) -> bool {
+    v.iter().all(|c| c.is_finite())
+}
+
+/// Twice the signed area of a triangle on the x/z plane
+pub fn tri_area_2d(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> f32 {
+    let abx = b[0] - a[0];
+    let abz = b[2] - a[2];
+    let acx = c[0] - a[0];
+    let acz = c[2] - a[2];
+    acx * abz - abx * acz
+}
+
+/// Squared x/z distance from a point to a segment, with the segment
+/// parameter of the nearest point
+pub fn distance_pt_seg_sqr_2d(pt: &[f32; 3], p: &[f32; 3], q: &[f32; 3]) -> (f32, f32) {
+    let pqx = q[0] - p[0];
+    let pqz = q[2] - p[2];
+    let d = pqx * pqx + pqz * pqz;
+    let mut t = pqx * (pt[0] - p[0]) + pqz * (pt[2] - p[2]);
+    if d > 0.0 {
+        t /= d;
+    }
+    let t = t.clamp(0.0, 1.0);
+    let dx = p[0] + t * pqx - pt[0];
+    let dz = p[2] + t * pqz - pt[2];
+    (dx * dx + dz * dz, t)
+}
+
+/// Height of a triangle below or above a point, if the point is inside
+/// the triangle on the x/z plane
+pub fn closest_height_point_triangle(p: &[f32; 3], a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> Option<f32> {
+    const EPS: f32 = 1e-6;
+    let v0 = vsub(c, a);
+    let v1 = vsub(b, a);
+    let v2 = vsub(p, a);
+
+    // Scaled barycentric coordinates
+    let mut denom = v0[0] * v1[2] - v0[2] * v1[0];
+    if denom.abs() < EPS {
+        return None;
+    }
+    let mut u = v1[2] * v2[0] - v1[0] * v2[2];
+    let mut v = v0[0] * v2[2] - v0[2] * v2[0];
+    if denom < 0.0 {
+        denom = -denom;
+        u = -u;
+        v = -v;
+    }
+
+    (u >= 0.0 && v >= 0.0 && u + v <= denom).then(|| a[1] + (v0[1] * u + v1[1] * v) / denom)
+}
+
+/// Whether a point is inside a polygon on the x/z plane
+pub fn point_in_polygon(pt: &[f32; 3], verts: &[[f32; 3]]) -> bool {
+    let mut inside = false;
+    let mut j = verts.len() - 1;
+    for (i, vi) in verts.iter().enumerate() {
+        let vj = &verts[j];
+        if (vi[2] > pt[2]) != (vj[2] > pt[2]) && pt[0] < (vj[0] - vi[0]) * (pt[2] - vi[2]) / (vj[2] - vi[2]) + vi[0] {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Whether a point is inside a polygon, filling the squared distance to
+/// and segment parameter on each edge (edge i runs from vertex i to i + 1)
+pub fn distance_pt_poly_edges_sqr(pt: &[f32; 3], verts: &[[f32; 3]], ed: &mut [f32], et: &mut [f32]) -> bool {
+    let mut inside = false;
+    let mut j = verts.len() - 1;
+    for (i, vi) in verts.iter().enumerate() {
+        let vj = &verts[j];
+        if (vi[2] > pt[2]) != (vj[2] > pt[2]) && pt[0] < (vj[0] - vi[0]) * (pt[2] - vi[2]) / (vj[2] - vi[2]) + vi[0] {
+            inside = !inside;
+        }
+        (ed[j], et[j]) = distance_pt_seg_sqr_2d(pt, vj, vi);
+        j = i;
+    }
+    inside
+}
+
+/// Point of a convex polygon picked from two random numbers in [0, 1)
+pub fn random_point_in_convex_poly(pts: &[[f32; 3]], s: f32, t: f32) -> [f32; 3] {
+    let npts = pts.len();
+    let mut areas = [0.0f32; super::nav_mesh::DT_VERTS_PER_POLYGON];
+    let mut areasum = 0.0;
+    for i in 2..npts {
+        areas[i] = tri_area_2d(&pts[0], &pts[i - 1], &pts[i]);
+        areasum += areas[i].max(0.001);
+    }
+
+    // Triangle weighted by area
+    let thr = s * areasum;
+    let mut acc = 0.0;
+    let mut u = 1.0;
+    let mut tri = npts - 1;
+    for (i, &dacc) in areas.iter().enumerate().take(npts).skip(2) {
+        if thr >= acc && thr < acc + dacc {
+            u = (thr - acc) / dacc;
+            tri = i;
+            break;
+        }
+        acc += dacc;
+    }
+
+    let v = t.sqrt();
+    let a = 1.0 - v;
+    let b = (1.0 - u) * v;
+    let c = u * v;
+    let (pa, pb, pc) = (&pts[0], &pts[tri - 1], &pts[tri]);
+    std::array::from_fn(|k| a * pa[k] + b * pb[k] + c * pc[k])
+}
+
+pub fn overlap_quant_bounds(amin: &[u16; 3], amax: &[u16; 3], bmin: &[u16; 3], bmax: &[u16; 3]) -> bool {
+    (0..3).all(|k| amin[k] <= bmax[k] && amax[k] >= bmin[k])
+}
+
+pub fn overlap_bounds(amin: &[f32; 3], amax: &[f32; 3], bmin: &[f32; 3], bmax: &[f32; 3]) -> bool {
+    (0..3).all(|k| amin[k] <= bmax[k] && amax[k] >= bmin[k])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Square of 10 on the x/z plane, wound the way Detour expects
+    const SQUARE: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [0.0, 0.0, 10.0], [10.0, 0.0, 10.0], [10.0, 0.0, 0.0]];
+
+    #[test]
+    fn test_polygon_helpers() {
+        assert!(tri_area_2d(&SQUARE[0], &SQUARE[1], &SQUARE[2]) > 0.0);
+        assert!(point_in_polygon(&[5.0, 3.0, 5.0], &SQUARE));
+        assert!(!point_in_polygon(&[15.0, 0.0, 5.0], &SQUARE));
+
+        let (d, t) = distance_pt_seg_sqr_2d(&[5.0, 0.0, 13.0], &SQUARE[1], &SQUARE[2]);
+        assert_eq!((d, t), (9.0, 0.5));
+
+        let mut ed = [0.0; 4];
+        let mut et = [0.0; 4];
+        assert!(!distance_pt_poly_edges_sqr(&[12.0, 0.0, 5.0], &SQUARE, &mut ed, &mut et));
+        assert_eq!((ed[2], et[2]), (4.0, 0.5));
+
+        // Slope rising along x
+        let a = [0.0, 0.0, 0.0];
+        let b = [0.0, 0.0, 10.0];
+        let c = [10.0, 5.0, 10.0];
+        assert_eq!(closest_height_point_triangle(&[4.0, 100.0, 8.0], &a, &b, &c), Some(2.0));
+        assert_eq!(closest_height_point_triangle(&[8.0, 0.0, 4.0], &a, &b, &c), None);
+
+        for (s, t) in [(0.0, 0.0), (0.3, 0.7), (0.99, 0.99)] {
+            let pt = random_point_in_convex_poly(&SQUARE, s, t);
+            assert!((0.0..=10.0).contains(&pt[0]) && (0.0..=10.0).contains(&pt[2]));
+        }
+    }
+}
@@ -0,0 +1,313 @@
+// MMap module - Pathfinding on the generated navigation meshes
+// Rust equivalent of MoveMap.h/cpp and the Detour library it drives
+//
+// MMapManager reads the output of `extractors move-map-gen` from
+// <DataDir>/mmaps: per map a .mmap with the dtNavMeshParams and per grid a
+// .mmtile, a 20 byte header ('MMAP', Detour version, MMAP_VERSION, data
+// size, liquid flag) followed by the Detour tile. Tiles are loaded and
+// unloaded with the grids and answer path and random point queries.
+//
+// Detour works in [y, z, x] of the world position, height second.
+
+mod common;
+mod nav_mesh;
+mod nav_mesh_query;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use byteorder::{LittleEndian, ReadBytesExt};
+use parking_lot::RwLock;
+
+use crate::error::MangosError;
+
+pub use nav_mesh::{
+    decode_poly_id, encode_poly_id, BvNode, Link, MeshHeader, MeshTile, NavMesh, NavMeshParams, OffMeshConnection,
+    Poly, PolyDetail, PolyRef, TileRef, DT_EXT_LINK, DT_MAX_AREAS, DT_NAVMESH_MAGIC, DT_NAVMESH_VERSION,
+    DT_OFFMESH_CON_BIDIR, DT_POLYTYPE_GROUND, DT_POLYTYPE_OFFMESH_CONNECTION, DT_VERTS_PER_POLYGON,
+};
+pub use nav_mesh_query::{
+    NavMeshQuery, PolyPath, QueryFilter, StraightPathPoint, DT_STRAIGHTPATH_END, DT_STRAIGHTPATH_OFFMESH_CONNECTION,
+    DT_STRAIGHTPATH_START,
+};
+
+/// 'MMAP'
+pub const MMAP_MAGIC: u32 = 0x4d4d_4150;
+pub const MMAP_VERSION: u32 = 8;
+/// Nodes a path search may visit
+pub const MMAP_MAX_NODES: usize = 1024;
+/// Longest polygon corridor and straight path of a search
+pub const MAX_PATH_LENGTH: usize = 74;
+pub const MAX_POINT_PATH_LENGTH: usize = 74;
+/// Box searched for the polygons at the ends of a path, Detour axes
+pub const NEAREST_POLY_EXTENTS: [f32; 3] = [3.0, 5.0, 3.0];
+
+pub fn map_file_name(map_id: u32) -> String {
+    format!("{:03}.mmap", map_id)
+}
+
+/// Tile file name, numbered like the .map grid it was built from
+pub fn tile_file_name(map_id: u32, gx: u32, gy: u32) -> String {
+    format!("{:03}{:02}{:02}.mmtile", map_id, gx, gy)
+}
+
+/// World position to Detour axes
+pub fn to_detour(x: f32, y: f32, z: f32) -> [f32; 3] {
+    [y, z, x]
+}
+
+/// Detour position back to world x, y, z
+pub fn from_detour(pos: &[f32; 3]) -> [f32; 3] {
+    [pos[2], pos[0], pos[1]]
+}
+
+/// Straight path of a move in world coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovePath {
+    pub points: Vec<[f32; 3]>,
+    /// The path stops short of the destination
+    pub partial: bool,
+}
+
+/// The navigation mesh of a map with its loaded tiles by grid
+struct MMapData {
+    nav_mesh: NavMesh,
+    loaded_tiles: HashMap<(u32, u32), TileRef>,
+}
+
+/// The navigation meshes of every map, loaded per grid
+pub struct MMapManager {
+    mmaps_dir: PathBuf,
+    maps: RwLock<HashMap<u32, MMapData>>,
+}
+
+impl MMapManager {
+    /// Manager for the navigation meshes in `<data_dir>/mmaps`
+    pub fn new(data_dir: &Path) -> Self {
+        MMapManager { mmaps_dir: data_dir.join("mmaps"), maps: RwLock::new(HashMap::new()) }
+    }
+
+    fn load_map_data(&self, map_id: u32) -> anyhow::Result<MMapData> {
+        let path = self.mmaps_dir.join(map_file_name(map_id));
+        let bytes = std::fs::read(&path).with_context(|| format!("cannot read {}", path.display()))?;
+        let params = NavMeshParams::read_from(&mut bytes.as_slice())
+            .and_then(NavMesh::new)
+            .with_context(|| format!("cannot parse {}", path.display()))?;
+        tracing::debug!("MMAP: loaded {}", path.display());
+        Ok(MMapData { nav_mesh: params, loaded_tiles: HashMap::new() })
+    }
+
+    /// Load the tile of a grid, and the map's .mmap with its first tile
+    pub fn load_map(&self, map_id: u32, gx: u32, gy: u32) -> anyhow::Result<()> {
+        let mut maps = self.maps.write();
+        let data = match maps.entry(map_id) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => entry.insert(self.load_map_data(map_id)?),
+        };
+        if data.loaded_tiles.contains_key(&(gx, gy)) {
+            return Ok(());
+        }
+
+        let path = self.mmaps_dir.join(tile_file_name(map_id, gx, gy));
+        let bytes = std::fs::read(&path).with_context(|| format!("cannot read {}", path.display()))?;
+        let tile_ref = (|| {
+            let mut reader = bytes.as_slice();
+            let magic = reader.read_u32::<LittleEndian>()?;
+            let dt_version = reader.read_u32::<LittleEndian>()?;
+            let mmap_version = reader.read_u32::<LittleEndian>()?;
+            let size = reader.read_u32::<LittleEndian>()? as usize;
+            let _uses_liquids = reader.read_u32::<LittleEndian>()?;
+            if magic != MMAP_MAGIC {
+                anyhow::bail!(MangosError::Data(format!("wrong magic {:#010x}", magic)));
+            }
+            if dt_version != DT_NAVMESH_VERSION || mmap_version != MMAP_VERSION {
+                anyhow::bail!(MangosError::Data(format!(
+                    "built for Detour {} / mmap {}, expected {} / {}; regenerate the mmaps",
+                    dt_version, mmap_version, DT_NAVMESH_VERSION, MMAP_VERSION
+                )));
+            }
+            let Some(tile_data) = reader.get(..size) else {
+                anyhow::bail!(MangosError::Data(format!("{} of {} bytes of tile data", reader.len(), size)));
+            };
+            data.nav_mesh.add_tile(tile_data)
+        })()
+        .with_context(|| format!("cannot load {}", path.display()))?;
+
+        data.loaded_tiles.insert((gx, gy), tile_ref);
+        tracing::debug!("MMAP: loaded tile {:03}[{:02},{:02}]", map_id, gx, gy);
+        Ok(())
+    }
+
+    /// Unload the tile of a grid, and the map with its last tile
+    pub fn unload_map_tile(&self, map_id: u32, gx: u32, gy: u32) {
+        let mut maps = self.maps.write();
+        let Some(data) = maps.get_mut(&map_id) else {
+            return;
+        };
+        let Some(tile_ref) = data.loaded_tiles.remove(&(gx, gy)) else {
+            tracing::error!("MMAP: trying to unload non-loaded tile {:03}[{:02},{:02}]", map_id, gx, gy);
+            return;
+        };
+        if let Err(e) = data.nav_mesh.remove_tile(tile_ref) {
+            tracing::error!("MMAP: cannot unload tile {:03}[{:02},{:02}]: {:#}", map_id, gx, gy, e);
+        }
+        if data.loaded_tiles.is_empty() {
+            maps.remove(&map_id);
+        }
+    }
+
+    pub fn unload_map(&self, map_id: u32) {
+        self.maps.write().remove(&map_id);
+    }
+
+    pub fn is_map_loaded(&self, map_id: u32) -> bool {
+        self.maps.read().contains_key(&map_id)
+    }
+
+    /// Number of tiles loaded over all maps
+    pub fn loaded_tiles_count(&self) -> usize {
+        self.maps.read().values().map(|data| data.loaded_tiles.len()).sum()
+    }
+
+    /// Run queries on the navigation mesh of a map, None if it is not loaded
+    pub fn with_query<R>(&self, map_id: u32, f: impl FnOnce(&NavMeshQuery) -> R) -> Option<R> {
+        let maps = self.maps.read();
+        let data = maps.get(&map_id)?;
+        Some(f(&NavMeshQuery::new(&data.nav_mesh, MMAP_MAX_NODES)))
+    }
+
+    /// Corners of a path between two world positions
+    ///
+    /// None when the map is not loaded or either end is not near the mesh.
+    /// The path is partial when the destination cannot be reached or the
+    /// way there is longer than MAX_PATH_LENGTH polygons.
+    pub fn find_path(&self, map_id: u32, from: [f32; 3], to: [f32; 3], filter: &QueryFilter) -> Option<MovePath> {
+        self.with_query(map_id, |query| {
+            let start = to_detour(from[0], from[1], from[2]);
+            let end = to_detour(to[0], to[1], to[2]);
+            let (start_ref, start_pos) = query.find_nearest_poly(&start, &NEAREST_POLY_EXTENTS, filter)?;
+            let (end_ref, end_pos) = query.find_nearest_poly(&end, &NEAREST_POLY_EXTENTS, filter)?;
+            let path = query.find_path(start_ref, end_ref, &start_pos, &end_pos, filter, MAX_PATH_LENGTH)?;
+            let partial = path.partial || path.polys.last() != Some(&end_ref);
+            let points = query.find_straight_path(&start_pos, &end_pos, &path.polys, MAX_POINT_PATH_LENGTH)?;
+            Some(MovePath { points: points.iter().map(|point| from_detour(&point.pos)).collect(), partial })
+        })
+        .flatten()
+    }
+
+    /// Random world position on the navigation mesh of a map
+    pub fn find_random_point<F: FnMut() -> f32>(&self, map_id: u32, filter: &QueryFilter, frand: F) -> Option<[f32; 3]> {
+        self.with_query(map_id, |query| query.find_random_point(filter, frand))
+            .flatten()
+            .map(|(_, pos)| from_detour(&pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nav_mesh::tests::{build_tile, test_params};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mangos_mmap_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("mmaps")).unwrap();
+        dir
+    }
+
+    fn write_params(dir: &Path, map_id: u32, params: &NavMeshParams) {
+        let mut out = Vec::new();
+        for v in params.orig.iter().chain([&params.tile_width, &params.tile_height]) {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out.extend_from_slice(&params.max_tiles.to_le_bytes());
+        out.extend_from_slice(&params.max_polys.to_le_bytes());
+        std::fs::write(dir.join("mmaps").join(map_file_name(map_id)), out).unwrap();
+    }
+
+    fn mmtile(tile: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for v in [MMAP_MAGIC, DT_NAVMESH_VERSION, MMAP_VERSION, tile.len() as u32, 0] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out.extend_from_slice(tile);
+        out
+    }
+
+    #[test]
+    fn test_coordinate_conversion() {
+        let pos = to_detour(1.0, 2.0, 3.0);
+        assert_eq!(pos, [2.0, 3.0, 1.0]);
+        assert_eq!(from_detour(&pos), [1.0, 2.0, 3.0]);
+        assert_eq!(tile_file_name(530, 3, 45), "5300345.mmtile");
+    }
+
+    #[test]
+    fn test_load_and_find_path() {
+        let dir = temp_dir("path");
+        write_params(&dir, 1, &test_params(4));
+        let mmaps = dir.join("mmaps");
+        std::fs::write(mmaps.join(tile_file_name(1, 32, 32)), mmtile(&build_tile(0, 0, 0.0, &[(1, 1)], true, &[])))
+            .unwrap();
+        std::fs::write(mmaps.join(tile_file_name(1, 32, 33)), mmtile(&build_tile(0, 1, 0.0, &[], true, &[]))).unwrap();
+
+        let manager = MMapManager::new(&dir);
+        manager.load_map(1, 32, 32).unwrap();
+        manager.load_map(1, 32, 33).unwrap();
+        manager.load_map(1, 32, 33).unwrap();
+        assert_eq!(manager.loaded_tiles_count(), 2);
+
+        // World x runs along Detour z
+        let filter = QueryFilter::new();
+        let path = manager.find_path(1, [5.0, 25.0, 0.0], [45.0, 5.0, 0.0], &filter).unwrap();
+        assert!(!path.partial);
+        assert_eq!(path.points.first(), Some(&[5.0, 25.0, 0.0]));
+        assert_eq!(path.points.last(), Some(&[45.0, 5.0, 0.0]));
+        // Around the hole in the first tile
+        assert!(path.points.len() > 2);
+
+        assert_eq!(manager.find_path(1, [5.0, 500.0, 0.0], [45.0, 5.0, 0.0], &filter), None);
+        assert_eq!(manager.find_path(2, [5.0, 25.0, 0.0], [45.0, 5.0, 0.0], &filter), None);
+
+        let mut n = 0.0;
+        let frand = || {
+            n = (n + 0.37) % 1.0;
+            n
+        };
+        let point = manager.find_random_point(1, &filter, frand).unwrap();
+        assert!((0.0..=60.0).contains(&point[0]) && (0.0..=30.0).contains(&point[1]));
+
+        manager.unload_map_tile(1, 32, 33);
+        assert_eq!(manager.find_path(1, [5.0, 25.0, 0.0], [45.0, 5.0, 0.0], &filter), None);
+        manager.unload_map_tile(1, 32, 32);
+        assert!(!manager.is_map_loaded(1));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_broken_files() {
+        let dir = temp_dir("broken");
+        let manager = MMapManager::new(&dir);
+        assert!(manager.load_map(3, 1, 1).is_err());
+
+        write_params(&dir, 3, &test_params(4));
+        let mmaps = dir.join("mmaps");
+        // No tile file
+        assert!(manager.load_map(3, 1, 1).is_err());
+
+        let mut old_version = mmtile(&build_tile(0, 0, 0.0, &[], false, &[]));
+        old_version[8] = 7;
+        std::fs::write(mmaps.join(tile_file_name(3, 1, 1)), old_version).unwrap();
+        assert!(manager.load_map(3, 1, 1).is_err());
+
+        let truncated = mmtile(&build_tile(0, 0, 0.0, &[], false, &[]));
+        std::fs::write(mmaps.join(tile_file_name(3, 1, 1)), &truncated[..truncated.len() - 1]).unwrap();
+        assert!(manager.load_map(3, 1, 1).is_err());
+        assert_eq!(manager.loaded_tiles_count(), 0);
+
+        // Unloading what is not loaded is harmless
+        manager.unload_map_tile(3, 1, 1);
+        manager.unload_map_tile(4, 1, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
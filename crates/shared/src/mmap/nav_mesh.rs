@@ -0,0 +1,1190 @@
+// NavMesh - The navigation mesh tiles of a map and the links between them
+// Rust equivalent of DetourNavMesh.h/cpp
+//
+// A tile is the dtCreateNavMeshData blob `extractors move-map-gen` writes
+// after the .mmtile header, each section padded to 4 bytes:
+//   dtMeshHeader     100 bytes ('DNAV', version 7, tile x/y/layer, counts,
+//                    walkable sizes, bounds, BV quantization factor)
+//   vertices         3 f32 each
+//   polygons         32 bytes: first link, 6 vertex and 6 neighbour
+//                    indices, flags, vertex count, area (6 bits) and type
+//   links            12 bytes each, rebuilt on load so skipped here
+//   detail meshes    12 bytes: vertex and triangle base, vertex and
+//                    triangle count
+//   detail vertices  3 f32 each
+//   detail triangles 3 vertex indices and the edge flags
+//   BV tree          16 bytes: quantized bounds and the polygon index, or
+//                    the negated escape offset of an interior node
+//   off-mesh links   36 bytes: end points, radius, polygon, flags, side
+// A neighbour index is 0 for a wall, the polygon index + 1 inside the tile
+// and DT_EXT_LINK | side on the tile border.
+//
+// Links are kept per polygon rather than in Detour's per tile free list.
+// Polygon references follow the DT_POLYREF64 layout: a 16 bit salt that
+// changes whenever a tile slot is reused, the 28 bit tile slot and the
+// 20 bit polygon index.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::error::MangosError;
+
+use super::common::{
+    closest_height_point_triangle, distance_pt_seg_sqr_2d, overlap_bounds, overlap_quant_bounds, point_in_polygon,
+    vlen_sqr, vlerp, vsub,
+};
+
+pub type PolyRef = u64;
+pub type TileRef = u64;
+
+/// 'DNAV'
+pub const DT_NAVMESH_MAGIC: u32 = u32::from_be_bytes(*b"DNAV");
+pub const DT_NAVMESH_VERSION: u32 = 7;
+pub const DT_VERTS_PER_POLYGON: usize = 6;
+/// Neighbour flag of an edge on the tile border, the side is in the low bits
+pub const DT_EXT_LINK: u16 = 0x8000;
+/// Off-mesh connection usable both ways
+pub const DT_OFFMESH_CON_BIDIR: u8 = 1;
+pub const DT_POLYTYPE_GROUND: u8 = 0;
+pub const DT_POLYTYPE_OFFMESH_CONNECTION: u8 = 1;
+pub const DT_MAX_AREAS: usize = 64;
+
+/// Detail triangle edge on the polygon boundary
+const DT_DETAIL_EDGE_BOUNDARY: u8 = 0x01;
+/// Side of links inside a tile
+const LINK_SIDE_NONE: u8 = 0xff;
+
+const SALT_BITS: u32 = 16;
+const TILE_BITS: u32 = 28;
+const POLY_BITS: u32 = 20;
+
+pub fn encode_poly_id(salt: u32, tile: u32, poly: u32) -> PolyRef {
+    ((salt as u64) << (TILE_BITS + POLY_BITS)) | ((tile as u64) << POLY_BITS) | poly as u64
+}
+
+/// Salt, tile slot and polygon index of a reference
+pub fn decode_poly_id(reference: PolyRef) -> (u32, u32, u32) {
+    let salt = (reference >> (TILE_BITS + POLY_BITS)) & ((1 << SALT_BITS) - 1);
+    let tile = (reference >> POLY_BITS) & ((1 << TILE_BITS) - 1);
+    let poly = reference & ((1 << POLY_BITS) - 1);
+    (salt as u32, tile as u32, poly as u32)
+}
+
+/// Tile of the opposite side, sides counting counter-clockwise from +x
+fn opposite_tile(side: u8) -> u8 {
+    (side + 4) & 7
+}
+
+fn read_vec3<R: Read>(reader: &mut R) -> std::io::Result<[f32; 3]> {
+    Ok([reader.read_f32::<LittleEndian>()?, reader.read_f32::<LittleEndian>()?, reader.read_f32::<LittleEndian>()?])
+}
+
+/// dtNavMeshParams, the content of a .mmap file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavMeshParams {
+    pub orig: [f32; 3],
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub max_tiles: i32,
+    pub max_polys: i32,
+}
+
+impl NavMeshParams {
+    pub fn read_from<R: Read>(reader: &mut R) -> anyhow::Result<Self> {
+        Ok(NavMeshParams {
+            orig: read_vec3(reader)?,
+            tile_width: reader.read_f32::<LittleEndian>()?,
+            tile_height: reader.read_f32::<LittleEndian>()?,
+            max_tiles: reader.read_i32::<LittleEndian>()?,
+            max_polys: reader.read_i32::<LittleEndian>()?,
+        })
+    }
+}
+
+/// The parts of dtMeshHeader not implied by the section lengths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshHeader {
+    pub x: i32,
+    pub y: i32,
+    pub layer: i32,
+    pub user_id: u32,
+    /// Index of the first off-mesh connection polygon
+    pub off_mesh_base: i32,
+    pub walkable_height: f32,
+    pub walkable_radius: f32,
+    pub walkable_climb: f32,
+    pub bmin: [f32; 3],
+    pub bmax: [f32; 3],
+    pub bv_quant_factor: f32,
+}
+
+/// A connection from an edge of a polygon to another polygon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+    pub reference: PolyRef,
+    /// Edge of the polygon, 0xff on the landing side of off-mesh links
+    pub edge: u8,
+    /// Neighbour tile side, 0xff inside the tile
+    pub side: u8,
+    /// Part of the edge the portal covers, in 1/255ths
+    pub bmin: u8,
+    pub bmax: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Poly {
+    pub verts: [u16; DT_VERTS_PER_POLYGON],
+    pub neis: [u16; DT_VERTS_PER_POLYGON],
+    pub flags: u16,
+    pub vert_count: u8,
+    area_and_type: u8,
+    links: Vec<Link>,
+}
+
+impl Poly {
+    pub fn area(&self) -> u8 {
+        self.area_and_type & 0x3f
+    }
+
+    pub fn poly_type(&self) -> u8 {
+        self.area_and_type >> 6
+    }
+
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolyDetail {
+    pub vert_base: u32,
+    pub tri_base: u32,
+    pub vert_count: u8,
+    pub tri_count: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvNode {
+    pub bmin: [u16; 3],
+    pub bmax: [u16; 3],
+    /// Polygon index of a leaf, negated escape offset of an interior node
+    pub i: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffMeshConnection {
+    /// Start and end point
+    pub pos: [[f32; 3]; 2],
+    pub rad: f32,
+    pub poly: u16,
+    pub flags: u8,
+    /// Tile side of the end point, 0xff inside the tile
+    pub side: u8,
+    pub user_id: u32,
+}
+
+/// One tile of navigation mesh
+#[derive(Debug, Clone)]
+pub struct MeshTile {
+    pub header: MeshHeader,
+    pub verts: Vec<[f32; 3]>,
+    pub polys: Vec<Poly>,
+    pub detail_meshes: Vec<PolyDetail>,
+    pub detail_verts: Vec<[f32; 3]>,
+    pub detail_tris: Vec<[u8; 4]>,
+    pub bv_tree: Vec<BvNode>,
+    pub off_mesh_cons: Vec<OffMeshConnection>,
+}
+
+impl MeshTile {
+    /// Parse and check the tile data of a .mmtile
+    pub fn read_from(data: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = data;
+        let reader = &mut reader;
+        let magic = reader.read_u32::<LittleEndian>()?;
+        if magic != DT_NAVMESH_MAGIC {
+            anyhow::bail!(MangosError::Data(format!("wrong navmesh magic {:#010x}", magic)));
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != DT_NAVMESH_VERSION {
+            anyhow::bail!(MangosError::Data(format!("navmesh version {}, expected {}", version, DT_NAVMESH_VERSION)));
+        }
+        let x = reader.read_i32::<LittleEndian>()?;
+        let y = reader.read_i32::<LittleEndian>()?;
+        let layer = reader.read_i32::<LittleEndian>()?;
+        let user_id = reader.read_u32::<LittleEndian>()?;
+        let mut counts = [0usize; 9];
+        for count in &mut counts {
+            let value = reader.read_i32::<LittleEndian>()?;
+            if value < 0 {
+                anyhow::bail!(MangosError::Data(format!("negative navmesh section size {}", value)));
+            }
+            *count = value as usize;
+        }
+        let [
+            poly_count,
+            vert_count,
+            max_link_count,
+            detail_mesh_count,
+            detail_vert_count,
+            detail_tri_count,
+            bv_node_count,
+            off_mesh_con_count,
+            off_mesh_base,
+        ] = counts;
+        let header = MeshHeader {
+            x,
+            y,
+            layer,
+            user_id,
+            off_mesh_base: off_mesh_base as i32,
+            walkable_height: reader.read_f32::<LittleEndian>()?,
+            walkable_radius: reader.read_f32::<LittleEndian>()?,
+            walkable_climb: reader.read_f32::<LittleEndian>()?,
+            bmin: read_vec3(reader)?,
+            bmax: read_vec3(reader)?,
+            bv_quant_factor: reader.read_f32::<LittleEndian>()?,
+        };
+
+        // Every section is a multiple of 4 bytes, so there is no padding
+        let needed = vert_count * 12
+            + poly_count * 32
+            + max_link_count * 12
+            + detail_mesh_count * 12
+            + detail_vert_count * 12
+            + detail_tri_count * 4
+            + bv_node_count * 16
+            + off_mesh_con_count * 36;
+        if reader.len() < needed {
+            anyhow::bail!(MangosError::Data(format!("navmesh tile truncated, {} of {} bytes", reader.len(), needed)));
+        }
+
+        let verts = (0..vert_count).map(|_| read_vec3(reader)).collect::<std::io::Result<Vec<_>>>()?;
+        let mut polys = Vec::with_capacity(poly_count);
+        for _ in 0..poly_count {
+            reader.read_u32::<LittleEndian>()?;
+            let mut poly = Poly {
+                verts: [0; DT_VERTS_PER_POLYGON],
+                neis: [0; DT_VERTS_PER_POLYGON],
+                flags: 0,
+                vert_count: 0,
+                area_and_type: 0,
+                links: Vec::new(),
+            };
+            reader.read_u16_into::<LittleEndian>(&mut poly.verts)?;
+            reader.read_u16_into::<LittleEndian>(&mut poly.neis)?;
+            poly.flags = reader.read_u16::<LittleEndian>()?;
+            poly.vert_count = reader.read_u8()?;
+            poly.area_and_type = reader.read_u8()?;
+            polys.push(poly);
+        }
+        let links: &[u8] = reader;
+        *reader = &links[max_link_count * 12..];
+        let mut detail_meshes = Vec::with_capacity(detail_mesh_count);
+        for _ in 0..detail_mesh_count {
+            detail_meshes.push(PolyDetail {
+                vert_base: reader.read_u32::<LittleEndian>()?,
+                tri_base: reader.read_u32::<LittleEndian>()?,
+                vert_count: reader.read_u8()?,
+                tri_count: reader.read_u8()?,
+            });
+            reader.read_u16::<LittleEndian>()?;
+        }
+        let detail_verts = (0..detail_vert_count).map(|_| read_vec3(reader)).collect::<std::io::Result<Vec<_>>>()?;
+        let mut detail_tris = vec![[0u8; 4]; detail_tri_count];
+        for tri in &mut detail_tris {
+            reader.read_exact(tri)?;
+        }
+        let mut bv_tree = Vec::with_capacity(bv_node_count);
+        for _ in 0..bv_node_count {
+            let mut bmin = [0u16; 3];
+            let mut bmax = [0u16; 3];
+            reader.read_u16_into::<LittleEndian>(&mut bmin)?;
+            reader.read_u16_into::<LittleEndian>(&mut bmax)?;
+            bv_tree.push(BvNode { bmin, bmax, i: reader.read_i32::<LittleEndian>()? });
+        }
+        let mut off_mesh_cons = Vec::with_capacity(off_mesh_con_count);
+        for _ in 0..off_mesh_con_count {
+            off_mesh_cons.push(OffMeshConnection {
+                pos: [read_vec3(reader)?, read_vec3(reader)?],
+                rad: reader.read_f32::<LittleEndian>()?,
+                poly: reader.read_u16::<LittleEndian>()?,
+                flags: reader.read_u8()?,
+                side: reader.read_u8()?,
+                user_id: reader.read_u32::<LittleEndian>()?,
+            });
+        }
+
+        let tile = MeshTile { header, verts, polys, detail_meshes, detail_verts, detail_tris, bv_tree, off_mesh_cons };
+        tile.validate()?;
+        Ok(tile)
+    }
+
+    /// Check every index so queries cannot go out of bounds
+    fn validate(&self) -> anyhow::Result<()> {
+        let bad = |what: String| anyhow::bail!(MangosError::Data(format!("navmesh tile {},{}: {}", self.header.x, self.header.y, what)));
+        if self.polys.len() >= 1 << POLY_BITS {
+            return bad(format!("{} polygons", self.polys.len()));
+        }
+        for (i, poly) in self.polys.iter().enumerate() {
+            let nv = poly.vert_count as usize;
+            let min_verts = if poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION { 2 } else { 3 };
+            if !(min_verts..=DT_VERTS_PER_POLYGON).contains(&nv) {
+                return bad(format!("polygon {} has {} vertices", i, nv));
+            }
+            if poly.verts[..nv].iter().any(|&v| v as usize >= self.verts.len()) {
+                return bad(format!("polygon {} uses a missing vertex", i));
+            }
+            if poly.neis[..nv].iter().any(|&n| n & DT_EXT_LINK == 0 && n as usize > self.polys.len()) {
+                return bad(format!("polygon {} has a missing neighbour", i));
+            }
+            if poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION {
+                continue;
+            }
+            let Some(detail) = self.detail_meshes.get(i) else {
+                return bad(format!("polygon {} has no detail mesh", i));
+            };
+            let tris = detail.tri_base as usize..detail.tri_base as usize + detail.tri_count as usize;
+            let Some(tris) = self.detail_tris.get(tris) else {
+                return bad(format!("detail mesh {} uses missing triangles", i));
+            };
+            let detail_verts = detail.vert_base as usize + detail.vert_count as usize;
+            let valid = |&index: &u8| {
+                (index as usize) < nv || (index as usize - nv < detail.vert_count as usize && detail_verts <= self.detail_verts.len())
+            };
+            if !tris.iter().all(|tri| tri[..3].iter().all(valid)) {
+                return bad(format!("detail mesh {} uses a missing vertex", i));
+            }
+        }
+        if self.bv_tree.iter().any(|node| node.i >= 0 && node.i as usize >= self.polys.len()) {
+            return bad("BV tree leaf of a missing polygon".into());
+        }
+        for con in &self.off_mesh_cons {
+            match self.polys.get(con.poly as usize) {
+                Some(poly) if poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION => {}
+                _ => return bad(format!("off-mesh connection {} without its polygon", con.user_id)),
+            }
+        }
+        Ok(())
+    }
+
+    fn poly_verts(&self, poly: &Poly) -> ([[f32; 3]; DT_VERTS_PER_POLYGON], usize) {
+        let mut verts = [[0.0; 3]; DT_VERTS_PER_POLYGON];
+        let nv = poly.vert_count as usize;
+        for (vert, &index) in verts.iter_mut().zip(&poly.verts[..nv]) {
+            *vert = self.verts[index as usize];
+        }
+        (verts, nv)
+    }
+
+    /// Vertices of a detail triangle of a polygon
+    fn detail_tri_verts(&self, poly: &Poly, detail: &PolyDetail, tri: &[u8; 4]) -> [[f32; 3]; 3] {
+        std::array::from_fn(|k| {
+            let index = tri[k] as usize;
+            if index < poly.vert_count as usize {
+                self.verts[poly.verts[index] as usize]
+            } else {
+                self.detail_verts[detail.vert_base as usize + index - poly.vert_count as usize]
+            }
+        })
+    }
+
+    /// Nearest point on the detail mesh edges, only boundary edges if asked
+    fn closest_point_on_detail_edges(&self, poly_index: usize, pos: &[f32; 3], only_boundary: bool) -> [f32; 3] {
+        const ANY_BOUNDARY_EDGE: u8 = DT_DETAIL_EDGE_BOUNDARY | (DT_DETAIL_EDGE_BOUNDARY << 2) | (DT_DETAIL_EDGE_BOUNDARY << 4);
+        let poly = &self.polys[poly_index];
+        let detail = &self.detail_meshes[poly_index];
+
+        let mut dmin = f32::MAX;
+        let mut closest = *pos;
+        let tris = &self.detail_tris[detail.tri_base as usize..detail.tri_base as usize + detail.tri_count as usize];
+        for tri in tris {
+            if only_boundary && tri[3] & ANY_BOUNDARY_EDGE == 0 {
+                continue;
+            }
+            let v = self.detail_tri_verts(poly, detail, tri);
+            let mut j = 2;
+            for k in 0..3 {
+                let boundary = (tri[3] >> (j * 2)) & 0x3 & DT_DETAIL_EDGE_BOUNDARY != 0;
+                // Inner edges are seen from both triangles
+                if boundary || (!only_boundary && tri[j] >= tri[k]) {
+                    let (d, t) = distance_pt_seg_sqr_2d(pos, &v[j], &v[k]);
+                    if d < dmin {
+                        dmin = d;
+                        closest = vlerp(&v[j], &v[k], t);
+                    }
+                }
+                j = k;
+            }
+        }
+        closest
+    }
+
+    /// Height of the detail mesh of a ground polygon above or below `pos`
+    pub fn get_poly_height(&self, poly_index: usize, pos: &[f32; 3]) -> Option<f32> {
+        let poly = self.polys.get(poly_index)?;
+        if poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION {
+            return None;
+        }
+        let (verts, nv) = self.poly_verts(poly);
+        if !point_in_polygon(pos, &verts[..nv]) {
+            return None;
+        }
+
+        let detail = &self.detail_meshes[poly_index];
+        let tris = &self.detail_tris[detail.tri_base as usize..detail.tri_base as usize + detail.tri_count as usize];
+        for tri in tris {
+            let [a, b, c] = self.detail_tri_verts(poly, detail, tri);
+            if let Some(height) = closest_height_point_triangle(pos, &a, &b, &c) {
+                return Some(height);
+            }
+        }
+        // Degenerate triangles or rounding put the point on an edge
+        Some(self.closest_point_on_detail_edges(poly_index, pos, false)[1])
+    }
+
+    /// Nearest point of a polygon and whether `pos` is above or below it
+    pub fn closest_point_on_poly(&self, poly_index: usize, pos: &[f32; 3]) -> ([f32; 3], bool) {
+        if let Some(height) = self.get_poly_height(poly_index, pos) {
+            return ([pos[0], height, pos[2]], true);
+        }
+        let poly = &self.polys[poly_index];
+        // Off-mesh connections have no detail mesh
+        if poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION {
+            let v0 = &self.verts[poly.verts[0] as usize];
+            let v1 = &self.verts[poly.verts[1] as usize];
+            let (_, t) = distance_pt_seg_sqr_2d(pos, v0, v1);
+            return (vlerp(v0, v1, t), false);
+        }
+        (self.closest_point_on_detail_edges(poly_index, pos, true), false)
+    }
+
+    /// Ground polygons whose bounds overlap the box
+    pub fn query_polygons(&self, qmin: &[f32; 3], qmax: &[f32; 3]) -> Vec<usize> {
+        let mut polys = Vec::new();
+        if !self.bv_tree.is_empty() {
+            let tbmin = &self.header.bmin;
+            let tbmax = &self.header.bmax;
+            let qfac = self.header.bv_quant_factor;
+            // Clamp the box to the tile and quantize it
+            let quantize = |v: &[f32; 3], k: usize| qfac * (v[k].clamp(tbmin[k], tbmax[k]) - tbmin[k]);
+            let bmin: [u16; 3] = std::array::from_fn(|k| quantize(qmin, k) as u16 & 0xfffe);
+            let bmax: [u16; 3] = std::array::from_fn(|k| (quantize(qmax, k) + 1.0) as u16 | 1);
+
+            let mut index = 0;
+            while let Some(node) = self.bv_tree.get(index) {
+                let overlap = overlap_quant_bounds(&bmin, &bmax, &node.bmin, &node.bmax);
+                let is_leaf = node.i >= 0;
+                if is_leaf && overlap {
+                    polys.push(node.i as usize);
+                }
+                if overlap || is_leaf {
+                    index += 1;
+                } else {
+                    index += node.i.unsigned_abs() as usize;
+                }
+            }
+            return polys;
+        }
+
+        for (i, poly) in self.polys.iter().enumerate() {
+            if poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION {
+                continue;
+            }
+            let (verts, nv) = self.poly_verts(poly);
+            let mut bmin = verts[0];
+            let mut bmax = verts[0];
+            for v in &verts[1..nv] {
+                for k in 0..3 {
+                    bmin[k] = bmin[k].min(v[k]);
+                    bmax[k] = bmax[k].max(v[k]);
+                }
+            }
+            if overlap_bounds(qmin, qmax, &bmin, &bmax) {
+                polys.push(i);
+            }
+        }
+        polys
+    }
+
+    /// Polygon nearest to `center` within the box, preferring polygons
+    /// within climb height above or below it, and its nearest point
+    pub fn find_nearest_poly(&self, center: &[f32; 3], half_extents: &[f32; 3]) -> Option<(usize, [f32; 3])> {
+        let bmin = vsub(center, half_extents);
+        let bmax = super::common::vadd(center, half_extents);
+        let mut nearest = None;
+        let mut nearest_distance_sqr = f32::MAX;
+        for poly_index in self.query_polygons(&bmin, &bmax) {
+            let (closest, over_poly) = self.closest_point_on_poly(poly_index, center);
+            let diff = vsub(center, &closest);
+            let d = if over_poly {
+                let d = diff[1].abs() - self.header.walkable_climb;
+                if d > 0.0 { d * d } else { 0.0 }
+            } else {
+                vlen_sqr(&diff)
+            };
+            if d < nearest_distance_sqr {
+                nearest_distance_sqr = d;
+                nearest = Some((poly_index, closest));
+            }
+        }
+        nearest
+    }
+}
+
+/// Slab coordinate of a border edge: x on sides 0/4, z on sides 2/6
+fn slab_coord(va: &[f32; 3], side: u8) -> f32 {
+    match side {
+        0 | 4 => va[0],
+        2 | 6 => va[2],
+        _ => 0.0,
+    }
+}
+
+/// End points of a border edge as (position along the border, height)
+fn slab_end_points(va: &[f32; 3], vb: &[f32; 3], side: u8) -> ([f32; 2], [f32; 2]) {
+    let axis = match side {
+        0 | 4 => 2,
+        2 | 6 => 0,
+        _ => return ([0.0; 2], [0.0; 2]),
+    };
+    if va[axis] < vb[axis] {
+        ([va[axis], va[1]], [vb[axis], vb[1]])
+    } else {
+        ([vb[axis], vb[1]], [va[axis], va[1]])
+    }
+}
+
+/// Whether two border edges share a stretch within `py` height of each other
+fn overlap_slabs(amin: &[f32; 2], amax: &[f32; 2], bmin: &[f32; 2], bmax: &[f32; 2], px: f32, py: f32) -> bool {
+    // Shrunk a little so edges touching at their ends do not connect
+    let minx = (amin[0] + px).max(bmin[0] + px);
+    let maxx = (amax[0] - px).min(bmax[0] - px);
+    if minx > maxx {
+        return false;
+    }
+
+    let ad = (amax[1] - amin[1]) / (amax[0] - amin[0]);
+    let ak = amin[1] - ad * amin[0];
+    let bd = (bmax[1] - bmin[1]) / (bmax[0] - bmin[0]);
+    let bk = bmin[1] - bd * bmin[0];
+    let dmin = (bd * minx + bk) - (ad * minx + ak);
+    let dmax = (bd * maxx + bk) - (ad * maxx + ak);
+    // Crossing edges always overlap
+    if dmin * dmax < 0.0 {
+        return true;
+    }
+    let thr = (py * 2.0) * (py * 2.0);
+    dmin * dmin <= thr || dmax * dmax <= thr
+}
+
+struct TileSlot {
+    salt: u32,
+    tile: Option<MeshTile>,
+}
+
+/// The loaded tiles of one map
+pub struct NavMesh {
+    params: NavMeshParams,
+    slots: Vec<TileSlot>,
+    free_slots: Vec<usize>,
+    /// Slots of the tiles (all layers) at each tile position
+    positions: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl NavMesh {
+    pub fn new(params: NavMeshParams) -> anyhow::Result<Self> {
+        if !(params.tile_width > 0.0 && params.tile_height > 0.0) || params.max_tiles <= 0 || params.max_tiles as u64 > 1 << TILE_BITS {
+            anyhow::bail!(MangosError::Data(format!("invalid navmesh parameters {:?}", params)));
+        }
+        Ok(NavMesh { params, slots: Vec::new(), free_slots: Vec::new(), positions: HashMap::new() })
+    }
+
+    pub fn params(&self) -> &NavMeshParams {
+        &self.params
+    }
+
+    /// Number of tiles loaded
+    pub fn tile_count(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.tile.is_some()).count()
+    }
+
+    /// Tile position holding a position
+    pub fn calc_tile_loc(&self, pos: &[f32; 3]) -> (i32, i32) {
+        (
+            ((pos[0] - self.params.orig[0]) / self.params.tile_width).floor() as i32,
+            ((pos[2] - self.params.orig[2]) / self.params.tile_height).floor() as i32,
+        )
+    }
+
+    fn slots_at(&self, x: i32, y: i32) -> &[usize] {
+        self.positions.get(&(x, y)).map_or(&[], Vec::as_slice)
+    }
+
+    fn tile(&self, slot: usize) -> &MeshTile {
+        self.slots[slot].tile.as_ref().expect("slot of a loaded tile")
+    }
+
+    fn tile_mut(&mut self, slot: usize) -> &mut MeshTile {
+        self.slots[slot].tile.as_mut().expect("slot of a loaded tile")
+    }
+
+    fn poly_ref_base(&self, slot: usize) -> PolyRef {
+        encode_poly_id(self.slots[slot].salt, slot as u32, 0)
+    }
+
+    /// Loaded tiles with their first polygon reference
+    pub fn tiles(&self) -> impl Iterator<Item = (PolyRef, &MeshTile)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, entry)| Some((encode_poly_id(entry.salt, slot as u32, 0), entry.tile.as_ref()?)))
+    }
+
+    /// Tiles at a tile position with their first polygon reference
+    pub fn tiles_at(&self, x: i32, y: i32) -> impl Iterator<Item = (PolyRef, &MeshTile)> {
+        self.slots_at(x, y).iter().map(|&slot| (self.poly_ref_base(slot), self.tile(slot)))
+    }
+
+    pub fn get_tile_at(&self, x: i32, y: i32, layer: i32) -> Option<&MeshTile> {
+        self.tiles_at(x, y).map(|(_, tile)| tile).find(|tile| tile.header.layer == layer)
+    }
+
+    pub fn get_tile_ref_at(&self, x: i32, y: i32, layer: i32) -> Option<TileRef> {
+        self.tiles_at(x, y).find(|(_, tile)| tile.header.layer == layer).map(|(base, _)| base)
+    }
+
+    /// Tile and polygon of a reference, None once its tile is gone
+    pub fn get_tile_and_poly_by_ref(&self, reference: PolyRef) -> Option<(&MeshTile, &Poly)> {
+        let (salt, slot, poly) = decode_poly_id(reference);
+        let entry = self.slots.get(slot as usize)?;
+        if entry.salt != salt {
+            return None;
+        }
+        let tile = entry.tile.as_ref()?;
+        Some((tile, tile.polys.get(poly as usize)?))
+    }
+
+    pub fn is_valid_poly_ref(&self, reference: PolyRef) -> bool {
+        self.get_tile_and_poly_by_ref(reference).is_some()
+    }
+
+    /// Nearest point of a polygon and whether `pos` is above or below it
+    pub fn closest_point_on_poly(&self, reference: PolyRef, pos: &[f32; 3]) -> Option<([f32; 3], bool)> {
+        let (tile, _) = self.get_tile_and_poly_by_ref(reference)?;
+        Some(tile.closest_point_on_poly(decode_poly_id(reference).2 as usize, pos))
+    }
+
+    /// Add the tile data of a .mmtile and link it to its neighbours
+    pub fn add_tile(&mut self, data: &[u8]) -> anyhow::Result<TileRef> {
+        let tile = MeshTile::read_from(data)?;
+        if tile.polys.len() > self.params.max_polys.max(0) as usize {
+            anyhow::bail!(MangosError::Data(format!(
+                "navmesh tile {},{} has {} polygons, at most {} allowed",
+                tile.header.x,
+                tile.header.y,
+                tile.polys.len(),
+                self.params.max_polys
+            )));
+        }
+        let (x, y, layer) = (tile.header.x, tile.header.y, tile.header.layer);
+        if self.get_tile_at(x, y, layer).is_some() {
+            anyhow::bail!(MangosError::Data(format!("navmesh tile {},{} layer {} is already loaded", x, y, layer)));
+        }
+
+        let slot = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None if self.slots.len() < self.params.max_tiles as usize => {
+                self.slots.push(TileSlot { salt: 1, tile: None });
+                self.slots.len() - 1
+            }
+            None => anyhow::bail!(MangosError::Data(format!("navmesh is full, {} tiles", self.params.max_tiles))),
+        };
+        self.slots[slot].tile = Some(tile);
+        self.positions.entry((x, y)).or_default().push(slot);
+
+        self.connect_int_links(slot);
+        // Off-mesh connections to their start polygons and within the tile
+        self.base_off_mesh_links(slot);
+        self.connect_ext_off_mesh_links(slot, slot, None);
+
+        // Other layers of the same position
+        let layers: Vec<usize> = self.slots_at(x, y).iter().copied().filter(|&other| other != slot).collect();
+        for other in layers {
+            self.connect_ext_links(slot, other, None);
+            self.connect_ext_links(other, slot, None);
+            self.connect_ext_off_mesh_links(slot, other, None);
+            self.connect_ext_off_mesh_links(other, slot, None);
+        }
+        for side in 0..8 {
+            let (nx, ny) = Self::neighbour_position(x, y, side);
+            for other in self.slots_at(nx, ny).to_vec() {
+                self.connect_ext_links(slot, other, Some(side));
+                self.connect_ext_links(other, slot, Some(opposite_tile(side)));
+                self.connect_ext_off_mesh_links(slot, other, Some(side));
+                self.connect_ext_off_mesh_links(other, slot, Some(opposite_tile(side)));
+            }
+        }
+        Ok(self.poly_ref_base(slot))
+    }
+
+    /// Remove a tile and the links of its neighbours into it
+    pub fn remove_tile(&mut self, tile_ref: TileRef) -> anyhow::Result<()> {
+        let (salt, slot, _) = decode_poly_id(tile_ref);
+        let slot = slot as usize;
+        let (x, y) = match self.slots.get(slot) {
+            Some(TileSlot { salt: current, tile: Some(tile) }) if *current == salt => (tile.header.x, tile.header.y),
+            _ => anyhow::bail!(MangosError::Data(format!("no navmesh tile {:#x}", tile_ref))),
+        };
+
+        let mut neighbours: Vec<usize> = self.slots_at(x, y).to_vec();
+        for side in 0..8 {
+            let (nx, ny) = Self::neighbour_position(x, y, side);
+            neighbours.extend_from_slice(self.slots_at(nx, ny));
+        }
+        for other in neighbours.into_iter().filter(|&other| other != slot) {
+            for poly in &mut self.tile_mut(other).polys {
+                poly.links.retain(|link| decode_poly_id(link.reference).1 as usize != slot);
+            }
+        }
+
+        if let Some(slots) = self.positions.get_mut(&(x, y)) {
+            slots.retain(|&other| other != slot);
+            if slots.is_empty() {
+                self.positions.remove(&(x, y));
+            }
+        }
+        let entry = &mut self.slots[slot];
+        entry.tile = None;
+        // Invalidate the references into the old tile
+        entry.salt = (entry.salt + 1) & ((1 << SALT_BITS) - 1);
+        if entry.salt == 0 {
+            entry.salt = 1;
+        }
+        self.free_slots.push(slot);
+        Ok(())
+    }
+
+    fn neighbour_position(x: i32, y: i32, side: u8) -> (i32, i32) {
+        match side {
+            0 => (x + 1, y),
+            1 => (x + 1, y + 1),
+            2 => (x, y + 1),
+            3 => (x - 1, y + 1),
+            4 => (x - 1, y),
+            5 => (x - 1, y - 1),
+            6 => (x, y - 1),
+            _ => (x + 1, y - 1),
+        }
+    }
+
+    fn connect_int_links(&mut self, slot: usize) {
+        let base = self.poly_ref_base(slot);
+        for poly in &mut self.tile_mut(slot).polys {
+            poly.links.clear();
+            if poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION {
+                continue;
+            }
+            for j in 0..poly.vert_count as usize {
+                let nei = poly.neis[j];
+                // Walls and tile borders
+                if nei == 0 || nei & DT_EXT_LINK != 0 {
+                    continue;
+                }
+                poly.links.push(Link {
+                    reference: base | (nei - 1) as PolyRef,
+                    edge: j as u8,
+                    side: LINK_SIDE_NONE,
+                    bmin: 0,
+                    bmax: 0,
+                });
+            }
+        }
+    }
+
+    /// Link off-mesh connections to the polygons their start lands on
+    fn base_off_mesh_links(&mut self, slot: usize) {
+        let base = self.poly_ref_base(slot);
+        let tile = self.tile(slot);
+        let landings: Vec<(usize, usize, [f32; 3])> = tile
+            .off_mesh_cons
+            .iter()
+            .filter_map(|con| {
+                let half_extents = [con.rad, tile.header.walkable_climb, con.rad];
+                let p = &con.pos[0];
+                let (land, nearest) = tile.find_nearest_poly(p, &half_extents)?;
+                // The nearest polygon may still be too far
+                if (nearest[0] - p[0]).powi(2) + (nearest[2] - p[2]).powi(2) > con.rad * con.rad {
+                    return None;
+                }
+                Some((con.poly as usize, land, nearest))
+            })
+            .collect();
+
+        let tile = self.tile_mut(slot);
+        for (con_poly, land, nearest) in landings {
+            // The connection starts on the mesh
+            let start = tile.polys[con_poly].verts[0] as usize;
+            tile.verts[start] = nearest;
+            tile.polys[con_poly].links.push(Link { reference: base | land as PolyRef, edge: 0, side: LINK_SIDE_NONE, bmin: 0, bmax: 0 });
+            // The start can always be used to enter the connection
+            tile.polys[land].links.push(Link {
+                reference: base | con_poly as PolyRef,
+                edge: 0xff,
+                side: LINK_SIDE_NONE,
+                bmin: 0,
+                bmax: 0,
+            });
+        }
+    }
+
+    /// Polygons of `target` with a border edge on `side` touching va-vb,
+    /// with the stretch of the border they share
+    fn find_connecting_polys(&self, va: &[f32; 3], vb: &[f32; 3], target: usize, side: u8) -> Vec<(PolyRef, f32, f32)> {
+        const MAX_CONNECTIONS: usize = 4;
+        let tile = self.tile(target);
+        let base = self.poly_ref_base(target);
+        let (amin, amax) = slab_end_points(va, vb, side);
+        let apos = slab_coord(va, side);
+        let m = DT_EXT_LINK | side as u16;
+
+        let mut connections = Vec::new();
+        for (i, poly) in tile.polys.iter().enumerate() {
+            let nv = poly.vert_count as usize;
+            for j in 0..nv {
+                if poly.neis[j] != m {
+                    continue;
+                }
+                let vc = &tile.verts[poly.verts[j] as usize];
+                let vd = &tile.verts[poly.verts[(j + 1) % nv] as usize];
+                if (apos - slab_coord(vc, side)).abs() > 0.01 {
+                    continue;
+                }
+                let (bmin, bmax) = slab_end_points(vc, vd, side);
+                if !overlap_slabs(&amin, &amax, &bmin, &bmax, 0.01, tile.header.walkable_climb) {
+                    continue;
+                }
+                if connections.len() < MAX_CONNECTIONS {
+                    connections.push((base | i as PolyRef, amin[0].max(bmin[0]), amax[0].min(bmax[0])));
+                }
+                break;
+            }
+        }
+        connections
+    }
+
+    /// Link the border edges of `slot` on `side` (all sides if None) to `target`
+    fn connect_ext_links(&mut self, slot: usize, target: usize, side: Option<u8>) {
+        let tile = self.tile(slot);
+        let mut new_links = Vec::new();
+        for (i, poly) in tile.polys.iter().enumerate() {
+            let nv = poly.vert_count as usize;
+            for j in 0..nv {
+                if poly.neis[j] & DT_EXT_LINK == 0 {
+                    continue;
+                }
+                let dir = (poly.neis[j] & 0xff) as u8;
+                if side.is_some_and(|side| side != dir) {
+                    continue;
+                }
+                let va = &tile.verts[poly.verts[j] as usize];
+                let vb = &tile.verts[poly.verts[(j + 1) % nv] as usize];
+                for (reference, cmin, cmax) in self.find_connecting_polys(va, vb, target, opposite_tile(dir)) {
+                    // Portal limits compressed to a byte
+                    let axis = match dir {
+                        0 | 4 => Some(2),
+                        2 | 6 => Some(0),
+                        _ => None,
+                    };
+                    let (bmin, bmax) = axis.map_or((0, 0), |axis| {
+                        let mut tmin = (cmin - va[axis]) / (vb[axis] - va[axis]);
+                        let mut tmax = (cmax - va[axis]) / (vb[axis] - va[axis]);
+                        if tmin > tmax {
+                            std::mem::swap(&mut tmin, &mut tmax);
+                        }
+                        ((tmin.clamp(0.0, 1.0) * 255.0).round() as u8, (tmax.clamp(0.0, 1.0) * 255.0).round() as u8)
+                    });
+                    new_links.push((i, Link { reference, edge: j as u8, side: dir, bmin, bmax }));
+                }
+            }
+        }
+        let tile = self.tile_mut(slot);
+        for (i, link) in new_links {
+            tile.polys[i].links.push(link);
+        }
+    }
+
+    /// Link the off-mesh connections of `target` landing in `slot`
+    fn connect_ext_off_mesh_links(&mut self, slot: usize, target: usize, side: Option<u8>) {
+        let opposite_side = side.map_or(LINK_SIDE_NONE, opposite_tile);
+        let tile = self.tile(slot);
+        let target_tile = self.tile(target);
+        let landings: Vec<(usize, usize, [f32; 3], bool)> = target_tile
+            .off_mesh_cons
+            .iter()
+            .filter(|con| con.side == opposite_side)
+            // Connections whose start is not on the mesh stay unlinked
+            .filter(|con| !target_tile.polys[con.poly as usize].links.is_empty())
+            .filter_map(|con| {
+                let half_extents = [con.rad, target_tile.header.walkable_climb, con.rad];
+                let p = &con.pos[1];
+                let (land, nearest) = tile.find_nearest_poly(p, &half_extents)?;
+                if (nearest[0] - p[0]).powi(2) + (nearest[2] - p[2]).powi(2) > con.rad * con.rad {
+                    return None;
+                }
+                Some((con.poly as usize, land, nearest, con.flags & DT_OFFMESH_CON_BIDIR != 0))
+            })
+            .collect();
+
+        let base = self.poly_ref_base(slot);
+        let target_base = self.poly_ref_base(target);
+        for (con_poly, land, nearest, bidirectional) in landings {
+            let target_tile = self.tile_mut(target);
+            // The connection ends on the mesh
+            let end = target_tile.polys[con_poly].verts[1] as usize;
+            target_tile.verts[end] = nearest;
+            target_tile.polys[con_poly].links.push(Link {
+                reference: base | land as PolyRef,
+                edge: 1,
+                side: opposite_side,
+                bmin: 0,
+                bmax: 0,
+            });
+            if bidirectional {
+                self.tile_mut(slot).polys[land].links.push(Link {
+                    reference: target_base | con_poly as PolyRef,
+                    edge: 0xff,
+                    side: side.unwrap_or(LINK_SIDE_NONE),
+                    bmin: 0,
+                    bmax: 0,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Side of the square polygons of the test tiles
+    pub const CELL: f32 = 10.0;
+    /// Cells per tile side
+    pub const CELLS: usize = 3;
+    pub const TILE_SIZE: f32 = CELL * CELLS as f32;
+
+    pub fn test_params(max_tiles: i32) -> NavMeshParams {
+        NavMeshParams { orig: [0.0; 3], tile_width: TILE_SIZE, tile_height: TILE_SIZE, max_tiles, max_polys: 1 << 10 }
+    }
+
+    pub struct TestOffMesh {
+        pub start: [f32; 3],
+        pub end: [f32; 3],
+        pub rad: f32,
+        pub bidirectional: bool,
+    }
+
+    /// A tile of CELLS x CELLS flat squares at `height`, leaving out the
+    /// cells in `holes`, with a BV tree if asked. Cell (i, j) spans x from
+    /// i * CELL and z from j * CELL within the tile.
+    pub fn build_tile(x: i32, y: i32, height: f32, holes: &[(usize, usize)], bv_tree: bool, off_mesh: &[TestOffMesh]) -> Vec<u8> {
+        let origin = [x as f32 * TILE_SIZE, y as f32 * TILE_SIZE];
+        let cells: Vec<(usize, usize)> =
+            (0..CELLS).flat_map(|j| (0..CELLS).map(move |i| (i, j))).filter(|cell| !holes.contains(cell)).collect();
+        let poly_of = |i: usize, j: usize| cells.iter().position(|&cell| cell == (i, j));
+
+        // (CELLS + 1)^2 grid of vertices, then two per off-mesh connection
+        let mut verts = Vec::new();
+        for j in 0..=CELLS {
+            for i in 0..=CELLS {
+                verts.push([origin[0] + i as f32 * CELL, height, origin[1] + j as f32 * CELL]);
+            }
+        }
+        let vert = |i: usize, j: usize| (j * (CELLS + 1) + i) as u16;
+
+        let mut polys: Vec<([u16; 6], [u16; 6], u8, u8)> = Vec::new();
+        for &(i, j) in &cells {
+            let nei = |ni: isize, nj: isize, side: u16| {
+                if ni < 0 || nj < 0 || ni >= CELLS as isize || nj >= CELLS as isize {
+                    DT_EXT_LINK | side
+                } else {
+                    poly_of(ni as usize, nj as usize).map_or(0, |p| p as u16 + 1)
+                }
+            };
+            let (ii, jj) = (i as isize, j as isize);
+            polys.push((
+                [vert(i, j), vert(i, j + 1), vert(i + 1, j + 1), vert(i + 1, j), 0, 0],
+                [nei(ii - 1, jj, 4), nei(ii, jj + 1, 2), nei(ii + 1, jj, 0), nei(ii, jj - 1, 6), 0, 0],
+                4,
+                1,
+            ));
+        }
+        let ground_polys = polys.len();
+        for con in off_mesh {
+            verts.push(con.start);
+            verts.push(con.end);
+            let v = verts.len() as u16;
+            polys.push(([v - 2, v - 1, 0, 0, 0, 0], [0; 6], 2, 1 | (DT_POLYTYPE_OFFMESH_CONNECTION << 6)));
+        }
+
+        let bmin = [origin[0], height - 1.0, origin[1]];
+        let bmax = [origin[0] + TILE_SIZE, height + 1.0, origin[1] + TILE_SIZE];
+        let mut bv_nodes: Vec<([u16; 3], [u16; 3], i32)> = Vec::new();
+        if bv_tree {
+            // One interior node over all leaves, quantized by 1 yard
+            bv_nodes.push(([0, 0, 0], [TILE_SIZE as u16, 2, TILE_SIZE as u16], -(ground_polys as i32 + 1)));
+            for (p, &(i, j)) in cells.iter().enumerate() {
+                let (i, j) = ((i as f32 * CELL) as u16, (j as f32 * CELL) as u16);
+                bv_nodes.push(([i, 0, j], [i + CELL as u16, 2, j + CELL as u16], p as i32));
+            }
+        }
+
+        let mut out = Vec::new();
+        let put_u32 = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+        let put_f32 = |out: &mut Vec<u8>, v: f32| out.extend_from_slice(&v.to_le_bytes());
+        put_u32(&mut out, DT_NAVMESH_MAGIC);
+        put_u32(&mut out, DT_NAVMESH_VERSION);
+        for v in [x, y, 0] {
+            put_u32(&mut out, v as u32);
+        }
+        put_u32(&mut out, 0);
+        let counts = [polys.len(), verts.len(), polys.len() * 4, ground_polys, 0, ground_polys * 2, bv_nodes.len(), off_mesh.len(), ground_polys];
+        for count in counts {
+            put_u32(&mut out, count as u32);
+        }
+        for v in [2.0, 0.6, 1.0] {
+            put_f32(&mut out, v);
+        }
+        for v in bmin.iter().chain(&bmax) {
+            put_f32(&mut out, *v);
+        }
+        put_f32(&mut out, 1.0);
+        for v in verts.iter().flatten() {
+            put_f32(&mut out, *v);
+        }
+        for (pverts, neis, vert_count, area_and_type) in &polys {
+            put_u32(&mut out, 0);
+            for v in pverts.iter().chain(neis) {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            out.extend_from_slice(&1u16.to_le_bytes());
+            out.push(*vert_count);
+            out.push(*area_and_type);
+        }
+        out.resize(out.len() + polys.len() * 4 * 12, 0);
+        // Detail meshes: the two triangles of each square, no extra vertices
+        for p in 0..ground_polys {
+            put_u32(&mut out, 0);
+            put_u32(&mut out, p as u32 * 2);
+            out.extend_from_slice(&[0, 2, 0, 0]);
+        }
+        for _ in 0..ground_polys {
+            out.extend_from_slice(&[0, 1, 2, 0b00_0101]);
+            out.extend_from_slice(&[0, 2, 3, 0b01_0100]);
+        }
+        for (bmin, bmax, i) in &bv_nodes {
+            for v in bmin.iter().chain(bmax) {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        for (n, con) in off_mesh.iter().enumerate() {
+            for v in con.start.iter().chain(&con.end) {
+                put_f32(&mut out, *v);
+            }
+            put_f32(&mut out, con.rad);
+            out.extend_from_slice(&((ground_polys + n) as u16).to_le_bytes());
+            out.push(con.bidirectional as u8);
+            out.push(0xff);
+            put_u32(&mut out, n as u32);
+        }
+        out
+    }
+
+    fn links_to_slot(mesh: &NavMesh, base: PolyRef, other: PolyRef) -> usize {
+        let (tile, _) = mesh.get_tile_and_poly_by_ref(base).unwrap();
+        let other_slot = decode_poly_id(other).1;
+        tile.polys.iter().flat_map(|poly| poly.links()).filter(|link| decode_poly_id(link.reference).1 == other_slot).count()
+    }
+
+    #[test]
+    fn test_poly_ref_encoding() {
+        let reference = encode_poly_id(3, 1000, 77);
+        assert_eq!(decode_poly_id(reference), (3, 1000, 77));
+        assert_eq!(decode_poly_id(encode_poly_id(0xffff, (1 << 28) - 1, (1 << 20) - 1)), (0xffff, (1 << 28) - 1, (1 << 20) - 1));
+    }
+
+    #[test]
+    fn test_tile_parse_and_links() {
+        let tile = MeshTile::read_from(&build_tile(0, 0, 0.0, &[(1, 1)], true, &[])).unwrap();
+        assert_eq!(tile.polys.len(), 8);
+        assert_eq!(tile.bv_tree.len(), 9);
+
+        let mut mesh = NavMesh::new(test_params(4)).unwrap();
+        let base = mesh.add_tile(&build_tile(0, 0, 0.0, &[(1, 1)], true, &[])).unwrap();
+        // Corner cell (0,0) touches (1,0) and (0,1); (1,0) has the hole above
+        let (_, corner) = mesh.get_tile_and_poly_by_ref(base).unwrap();
+        assert_eq!(corner.links().len(), 2);
+        let (_, bottom) = mesh.get_tile_and_poly_by_ref(base | 1).unwrap();
+        assert_eq!(bottom.links().len(), 2);
+
+        // Border edges link once the neighbour arrives
+        let east = mesh.add_tile(&build_tile(1, 0, 0.0, &[], false, &[])).unwrap();
+        assert_eq!(links_to_slot(&mesh, base, east), CELLS);
+        assert_eq!(links_to_slot(&mesh, east, base), CELLS);
+        assert_eq!(mesh.get_tile_at(1, 0, 0).map(|tile| tile.polys.len()), Some(9));
+        assert!(mesh.add_tile(&build_tile(1, 0, 0.0, &[], false, &[])).is_err());
+
+        // Too high to step onto
+        let north = mesh.add_tile(&build_tile(0, 1, 5.0, &[], false, &[])).unwrap();
+        assert_eq!(links_to_slot(&mesh, base, north), 0);
+
+        mesh.remove_tile(east).unwrap();
+        assert_eq!(links_to_slot(&mesh, base, east), 0);
+        assert!(!mesh.is_valid_poly_ref(east));
+        assert!(mesh.remove_tile(east).is_err());
+        // The slot is reused with a new salt
+        let again = mesh.add_tile(&build_tile(1, 0, 0.0, &[], false, &[])).unwrap();
+        assert_ne!(again, east);
+        assert_eq!(decode_poly_id(again).1, decode_poly_id(east).1);
+        assert_eq!(mesh.tile_count(), 3);
+    }
+
+    #[test]
+    fn test_bad_tiles() {
+        let data = build_tile(0, 0, 0.0, &[], true, &[]);
+        assert!(MeshTile::read_from(&data[..data.len() - 4]).is_err());
+        let mut wrong_magic = data.clone();
+        wrong_magic[0] ^= 1;
+        assert!(MeshTile::read_from(&wrong_magic).is_err());
+        // First polygon points at a vertex past the end
+        let mut bad_vertex = data.clone();
+        let first_poly = 100 + 16 * 12 + 4;
+        bad_vertex[first_poly..first_poly + 2].copy_from_slice(&100u16.to_le_bytes());
+        assert!(MeshTile::read_from(&bad_vertex).is_err());
+
+        let mut mesh = NavMesh::new(test_params(1)).unwrap();
+        mesh.add_tile(&data).unwrap();
+        assert!(mesh.add_tile(&build_tile(1, 0, 0.0, &[], false, &[])).is_err());
+        assert!(NavMesh::new(NavMeshParams { tile_width: 0.0, ..test_params(1) }).is_err());
+    }
+
+    #[test]
+    fn test_height_and_nearest() {
+        let tile = MeshTile::read_from(&build_tile(0, 0, 3.0, &[(1, 1)], true, &[])).unwrap();
+        assert_eq!(tile.get_poly_height(0, &[5.0, 0.0, 5.0]), Some(3.0));
+        assert_eq!(tile.get_poly_height(0, &[15.0, 0.0, 5.0]), None);
+
+        // Over the hole the nearest point is on an edge of a neighbour
+        let (poly, nearest) = tile.find_nearest_poly(&[15.0, 3.0, 12.0], &[5.0, 5.0, 5.0]).unwrap();
+        assert_eq!(nearest, [15.0, 3.0, 10.0]);
+        assert_eq!(tile.polys[poly].verts[0], 1);
+
+        // The BV tree clamps the box to the tile, polygon bounds do not
+        let tile = MeshTile::read_from(&build_tile(0, 0, 3.0, &[(1, 1)], false, &[])).unwrap();
+        assert_eq!(tile.find_nearest_poly(&[15.0, 3.0, 12.0], &[5.0, 5.0, 5.0]).unwrap().1, nearest);
+        assert_eq!(tile.find_nearest_poly(&[15.0, 30.0, 15.0], &[5.0, 5.0, 5.0]), None);
+    }
+}
@@ -0,0 +1,745 @@
+// NavMeshQuery - Path and position queries on a navigation mesh
+// Rust equivalent of DetourNavMeshQuery.h/cpp and DetourNode.h/cpp
+//
+// find_path is Detour's A* over polygons, from the midpoint of one portal
+// edge to the next, and find_straight_path pulls the resulting corridor
+// into corners (the funnel algorithm). Searches allocate their node pool
+// per call and stop expanding once it holds `max_nodes` nodes.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::common::{
+    distance_pt_poly_edges_sqr, distance_pt_seg_sqr_2d, random_point_in_convex_poly, tri_area_2d, vadd, vdist,
+    vequal, visfinite, vlen_sqr, vlerp, vsub,
+};
+use super::nav_mesh::{
+    decode_poly_id, MeshTile, NavMesh, Poly, PolyRef, DT_MAX_AREAS, DT_POLYTYPE_GROUND,
+    DT_POLYTYPE_OFFMESH_CONNECTION, DT_VERTS_PER_POLYGON,
+};
+
+/// Weight of the heuristic, slightly below 1 to favour exact costs
+const H_SCALE: f32 = 0.999;
+
+/// First point of a straight path
+pub const DT_STRAIGHTPATH_START: u8 = 0x01;
+/// Last point of a straight path
+pub const DT_STRAIGHTPATH_END: u8 = 0x02;
+/// Point where an off-mesh connection starts
+pub const DT_STRAIGHTPATH_OFFMESH_CONNECTION: u8 = 0x04;
+
+/// Which polygons a query may use and what crossing them costs
+#[derive(Debug, Clone)]
+pub struct QueryFilter {
+    area_cost: [f32; DT_MAX_AREAS],
+    include_flags: u16,
+    exclude_flags: u16,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        QueryFilter { area_cost: [1.0; DT_MAX_AREAS], include_flags: 0xffff, exclude_flags: 0 }
+    }
+}
+
+impl QueryFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Polygons need at least one of these flags
+    pub fn set_include_flags(&mut self, flags: u16) {
+        self.include_flags = flags;
+    }
+
+    pub fn include_flags(&self) -> u16 {
+        self.include_flags
+    }
+
+    /// Polygons with any of these flags are skipped
+    pub fn set_exclude_flags(&mut self, flags: u16) {
+        self.exclude_flags = flags;
+    }
+
+    pub fn exclude_flags(&self) -> u16 {
+        self.exclude_flags
+    }
+
+    /// Cost per yard of crossing polygons of an area
+    pub fn set_area_cost(&mut self, area: u8, cost: f32) {
+        if let Some(area_cost) = self.area_cost.get_mut(area as usize) {
+            *area_cost = cost;
+        }
+    }
+
+    pub fn area_cost(&self, area: u8) -> f32 {
+        self.area_cost.get(area as usize).copied().unwrap_or(1.0)
+    }
+
+    pub fn pass_filter(&self, poly: &Poly) -> bool {
+        poly.flags & self.include_flags != 0 && poly.flags & self.exclude_flags == 0
+    }
+
+    /// Cost of moving from `pa` to `pb` across `poly`
+    pub fn get_cost(&self, pa: &[f32; 3], pb: &[f32; 3], poly: &Poly) -> f32 {
+        vdist(pa, pb) * self.area_cost(poly.area())
+    }
+}
+
+/// Polygon corridor found by find_path
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolyPath {
+    pub polys: Vec<PolyRef>,
+    /// The end polygon was not reached, the path leads to the polygon nearest to it
+    pub partial: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StraightPathPoint {
+    pub pos: [f32; 3],
+    /// DT_STRAIGHTPATH_* flags
+    pub flags: u8,
+    /// Polygon entered at this point, 0 at the end
+    pub poly: PolyRef,
+}
+
+const NODE_OPEN: u8 = 0x01;
+const NODE_CLOSED: u8 = 0x02;
+
+struct Node {
+    pos: [f32; 3],
+    cost: f32,
+    total: f32,
+    parent: Option<usize>,
+    id: PolyRef,
+    flags: u8,
+}
+
+/// Open list entry; the heap pops the lowest total first
+struct OpenEntry {
+    total: f32,
+    node: usize,
+}
+
+impl PartialEq for OpenEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenEntry {}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.total.total_cmp(&self.total)
+    }
+}
+
+/// Queries on one navigation mesh
+pub struct NavMeshQuery<'a> {
+    nav: &'a NavMesh,
+    max_nodes: usize,
+}
+
+impl<'a> NavMeshQuery<'a> {
+    pub fn new(nav: &'a NavMesh, max_nodes: usize) -> Self {
+        NavMeshQuery { nav, max_nodes }
+    }
+
+    pub fn nav_mesh(&self) -> &'a NavMesh {
+        self.nav
+    }
+
+    fn tile_and_poly(&self, reference: PolyRef) -> Option<(&'a MeshTile, &'a Poly)> {
+        self.nav.get_tile_and_poly_by_ref(reference)
+    }
+
+    /// Polygon nearest to `center` within the box around it, and the
+    /// nearest point on it; polygons within climb height above or below
+    /// the center win over those beside it
+    pub fn find_nearest_poly(
+        &self,
+        center: &[f32; 3],
+        half_extents: &[f32; 3],
+        filter: &QueryFilter,
+    ) -> Option<(PolyRef, [f32; 3])> {
+        if !visfinite(center) || !visfinite(half_extents) {
+            return None;
+        }
+        let bmin = vsub(center, half_extents);
+        let bmax = vadd(center, half_extents);
+        let (minx, miny) = self.nav.calc_tile_loc(&bmin);
+        let (maxx, maxy) = self.nav.calc_tile_loc(&bmax);
+
+        let mut nearest = None;
+        let mut nearest_distance_sqr = f32::MAX;
+        for y in miny..=maxy {
+            for x in minx..=maxx {
+                for (base, tile) in self.nav.tiles_at(x, y) {
+                    for poly_index in tile.query_polygons(&bmin, &bmax) {
+                        if !filter.pass_filter(&tile.polys[poly_index]) {
+                            continue;
+                        }
+                        let (closest, over_poly) = tile.closest_point_on_poly(poly_index, center);
+                        let diff = vsub(center, &closest);
+                        let d = if over_poly {
+                            let d = diff[1].abs() - tile.header.walkable_climb;
+                            if d > 0.0 { d * d } else { 0.0 }
+                        } else {
+                            vlen_sqr(&diff)
+                        };
+                        if d < nearest_distance_sqr {
+                            nearest_distance_sqr = d;
+                            nearest = Some((base | poly_index as PolyRef, closest));
+                        }
+                    }
+                }
+            }
+        }
+        nearest
+    }
+
+    /// Nearest point of a polygon, using its detail mesh for the height
+    pub fn closest_point_on_poly(&self, reference: PolyRef, pos: &[f32; 3]) -> Option<([f32; 3], bool)> {
+        if !visfinite(pos) {
+            return None;
+        }
+        self.nav.closest_point_on_poly(reference, pos)
+    }
+
+    /// Nearest point on the outline of a polygon; `pos` itself when it is
+    /// inside on the x/z plane
+    pub fn closest_point_on_poly_boundary(&self, reference: PolyRef, pos: &[f32; 3]) -> Option<[f32; 3]> {
+        let (tile, poly) = self.tile_and_poly(reference)?;
+        if !visfinite(pos) {
+            return None;
+        }
+        let nv = poly.vert_count as usize;
+        let verts: Vec<[f32; 3]> = poly.verts[..nv].iter().map(|&v| tile.verts[v as usize]).collect();
+        let mut edged = [0.0; DT_VERTS_PER_POLYGON];
+        let mut edget = [0.0; DT_VERTS_PER_POLYGON];
+        if distance_pt_poly_edges_sqr(pos, &verts, &mut edged, &mut edget) {
+            return Some(*pos);
+        }
+        let imin = (0..nv).fold(0, |imin, i| if edged[i] < edged[imin] { i } else { imin });
+        Some(vlerp(&verts[imin], &verts[(imin + 1) % nv], edget[imin]))
+    }
+
+    /// Height of a polygon at a position inside it on the x/z plane
+    pub fn get_poly_height(&self, reference: PolyRef, pos: &[f32; 3]) -> Option<f32> {
+        let (tile, poly) = self.tile_and_poly(reference)?;
+        if !pos[0].is_finite() || !pos[2].is_finite() {
+            return None;
+        }
+        if poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION {
+            let v0 = &tile.verts[poly.verts[0] as usize];
+            let v1 = &tile.verts[poly.verts[1] as usize];
+            let (_, t) = distance_pt_seg_sqr_2d(pos, v0, v1);
+            return Some(v0[1] + (v1[1] - v0[1]) * t);
+        }
+        tile.get_poly_height(decode_poly_id(reference).2 as usize, pos)
+    }
+
+    /// Random point of the mesh: a tile picked uniformly, then a polygon
+    /// by its area, then a point inside it. `frand` returns values in [0, 1).
+    pub fn find_random_point<F: FnMut() -> f32>(&self, filter: &QueryFilter, mut frand: F) -> Option<(PolyRef, [f32; 3])> {
+        // Reservoir sampling, one pass over the tiles
+        let mut chosen_tile = None;
+        let mut tsum = 0.0;
+        for (base, tile) in self.nav.tiles() {
+            let area = 1.0;
+            tsum += area;
+            if frand() * tsum <= area {
+                chosen_tile = Some((base, tile));
+            }
+        }
+        let (base, tile) = chosen_tile?;
+
+        let mut chosen_poly = None;
+        let mut area_sum = 0.0;
+        for (i, poly) in tile.polys.iter().enumerate() {
+            if poly.poly_type() != DT_POLYTYPE_GROUND || !filter.pass_filter(poly) {
+                continue;
+            }
+            let va = &tile.verts[poly.verts[0] as usize];
+            let poly_area: f32 = (2..poly.vert_count as usize)
+                .map(|j| tri_area_2d(va, &tile.verts[poly.verts[j - 1] as usize], &tile.verts[poly.verts[j] as usize]))
+                .sum();
+            area_sum += poly_area;
+            if frand() * area_sum <= poly_area {
+                chosen_poly = Some((i, poly));
+            }
+        }
+        let (poly_index, poly) = chosen_poly?;
+
+        let verts: Vec<[f32; 3]> =
+            poly.verts[..poly.vert_count as usize].iter().map(|&v| tile.verts[v as usize]).collect();
+        let s = frand();
+        let t = frand();
+        let pt = random_point_in_convex_poly(&verts, s, t);
+        let (pt, _) = tile.closest_point_on_poly(poly_index, &pt);
+        Some((base | poly_index as PolyRef, pt))
+    }
+
+    /// Left and right end of the edge shared by two linked polygons
+    fn portal_points(
+        &self,
+        from: (&MeshTile, &Poly),
+        to_ref: PolyRef,
+        to: (&MeshTile, &Poly),
+        from_ref: PolyRef,
+    ) -> Option<([f32; 3], [f32; 3])> {
+        let (from_tile, from_poly) = from;
+        let (to_tile, to_poly) = to;
+        let link = from_poly.links().iter().find(|link| link.reference == to_ref)?;
+
+        // Off-mesh connections meet the mesh in one of their end points
+        if from_poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION {
+            let v = from_tile.verts[from_poly.verts[link.edge as usize] as usize];
+            return Some((v, v));
+        }
+        if to_poly.poly_type() == DT_POLYTYPE_OFFMESH_CONNECTION {
+            let link = to_poly.links().iter().find(|link| link.reference == from_ref)?;
+            let v = to_tile.verts[to_poly.verts[link.edge as usize] as usize];
+            return Some((v, v));
+        }
+
+        let edge = link.edge as usize;
+        let v0 = &from_tile.verts[from_poly.verts[edge] as usize];
+        let v1 = &from_tile.verts[from_poly.verts[(edge + 1) % from_poly.vert_count as usize] as usize];
+        // Tile borders may only share part of the edge
+        if link.side != 0xff && (link.bmin != 0 || link.bmax != 255) {
+            let s = 1.0 / 255.0;
+            return Some((vlerp(v0, v1, link.bmin as f32 * s), vlerp(v0, v1, link.bmax as f32 * s)));
+        }
+        Some((*v0, *v1))
+    }
+
+    /// Portal between two linked polygons and the type of the second one
+    pub fn get_portal_points(&self, from: PolyRef, to: PolyRef) -> Option<([f32; 3], [f32; 3], u8)> {
+        let from_poly = self.tile_and_poly(from)?;
+        let to_poly = self.tile_and_poly(to)?;
+        let (left, right) = self.portal_points(from_poly, to, to_poly, from)?;
+        Some((left, right, to_poly.1.poly_type()))
+    }
+
+    /// Polygons from `start_ref` to `end_ref`, at most `max_path` of them
+    ///
+    /// When the end cannot be reached the path leads to the polygon
+    /// closest to it and is marked partial. Costs are measured between
+    /// the midpoints of the portals, so the y values matter.
+    pub fn find_path(
+        &self,
+        start_ref: PolyRef,
+        end_ref: PolyRef,
+        start_pos: &[f32; 3],
+        end_pos: &[f32; 3],
+        filter: &QueryFilter,
+        max_path: usize,
+    ) -> Option<PolyPath> {
+        if !self.nav.is_valid_poly_ref(start_ref)
+            || !self.nav.is_valid_poly_ref(end_ref)
+            || !visfinite(start_pos)
+            || !visfinite(end_pos)
+            || max_path == 0
+        {
+            return None;
+        }
+        if start_ref == end_ref {
+            return Some(PolyPath { polys: vec![start_ref], partial: false });
+        }
+
+        let mut nodes = vec![Node {
+            pos: *start_pos,
+            cost: 0.0,
+            total: vdist(start_pos, end_pos) * H_SCALE,
+            parent: None,
+            id: start_ref,
+            flags: NODE_OPEN,
+        }];
+        // Nodes by polygon and the tile side it was entered from
+        let mut node_index: HashMap<(PolyRef, u8), usize> = HashMap::from([((start_ref, 0), 0)]);
+        let mut open = BinaryHeap::from([OpenEntry { total: nodes[0].total, node: 0 }]);
+        let mut last_best_node = 0;
+        let mut last_best_node_cost = nodes[0].total;
+
+        while let Some(OpenEntry { total, node: best }) = open.pop() {
+            // Entries left behind when a node got a better total
+            if nodes[best].flags & NODE_OPEN == 0 || total != nodes[best].total {
+                continue;
+            }
+            nodes[best].flags = (nodes[best].flags & !NODE_OPEN) | NODE_CLOSED;
+
+            let best_ref = nodes[best].id;
+            if best_ref == end_ref {
+                last_best_node = best;
+                break;
+            }
+            let best_tile_poly = self.tile_and_poly(best_ref)?;
+            let parent_ref = nodes[best].parent.map(|parent| nodes[parent].id);
+
+            for link in best_tile_poly.1.links() {
+                let neighbour_ref = link.reference;
+                // Do not go back where we came from
+                if neighbour_ref == 0 || Some(neighbour_ref) == parent_ref {
+                    continue;
+                }
+                let Some(neighbour_tile_poly) = self.tile_and_poly(neighbour_ref) else {
+                    continue;
+                };
+                if !filter.pass_filter(neighbour_tile_poly.1) {
+                    continue;
+                }
+
+                // Tile borders get their own node so both sides can be tried
+                let cross_side = if link.side != 0xff { link.side >> 1 } else { 0 };
+                let neighbour = match node_index.get(&(neighbour_ref, cross_side)) {
+                    Some(&neighbour) => neighbour,
+                    None if nodes.len() < self.max_nodes => {
+                        let Some((left, right)) =
+                            self.portal_points(best_tile_poly, neighbour_ref, neighbour_tile_poly, best_ref)
+                        else {
+                            continue;
+                        };
+                        nodes.push(Node {
+                            pos: vlerp(&left, &right, 0.5),
+                            cost: 0.0,
+                            total: 0.0,
+                            parent: None,
+                            id: neighbour_ref,
+                            flags: 0,
+                        });
+                        node_index.insert((neighbour_ref, cross_side), nodes.len() - 1);
+                        nodes.len() - 1
+                    }
+                    None => continue,
+                };
+
+                let cur_cost = filter.get_cost(&nodes[best].pos, &nodes[neighbour].pos, best_tile_poly.1);
+                let (cost, heuristic) = if neighbour_ref == end_ref {
+                    let end_cost = filter.get_cost(&nodes[neighbour].pos, end_pos, neighbour_tile_poly.1);
+                    (nodes[best].cost + cur_cost + end_cost, 0.0)
+                } else {
+                    (nodes[best].cost + cur_cost, vdist(&nodes[neighbour].pos, end_pos) * H_SCALE)
+                };
+                let total = cost + heuristic;
+
+                // Already open or visited with a better result
+                if nodes[neighbour].flags & (NODE_OPEN | NODE_CLOSED) != 0 && total >= nodes[neighbour].total {
+                    continue;
+                }
+                let node = &mut nodes[neighbour];
+                node.parent = Some(best);
+                node.flags = (node.flags & !NODE_CLOSED) | NODE_OPEN;
+                node.cost = cost;
+                node.total = total;
+                open.push(OpenEntry { total, node: neighbour });
+
+                if heuristic < last_best_node_cost {
+                    last_best_node_cost = heuristic;
+                    last_best_node = neighbour;
+                }
+            }
+        }
+
+        let mut polys = Vec::new();
+        let mut node = Some(last_best_node);
+        while let Some(index) = node {
+            polys.push(nodes[index].id);
+            node = nodes[index].parent;
+        }
+        polys.reverse();
+        // Keep the part from the start when the path does not fit
+        polys.truncate(max_path);
+        Some(PolyPath { polys, partial: nodes[last_best_node].id != end_ref })
+    }
+
+    /// Corners of the shortest way through a polygon corridor, at most
+    /// `max_straight_path` of them
+    ///
+    /// The start and end are clamped to the first and last polygon. A path
+    /// through a corridor that breaks off ends at the last reachable
+    /// polygon.
+    pub fn find_straight_path(
+        &self,
+        start_pos: &[f32; 3],
+        end_pos: &[f32; 3],
+        path: &[PolyRef],
+        max_straight_path: usize,
+    ) -> Option<Vec<StraightPathPoint>> {
+        if !visfinite(start_pos) || !visfinite(end_pos) || path.is_empty() || path[0] == 0 || max_straight_path == 0 {
+            return None;
+        }
+        let closest_start_pos = self.closest_point_on_poly_boundary(path[0], start_pos)?;
+        let mut closest_end_pos = self.closest_point_on_poly_boundary(*path.last()?, end_pos)?;
+
+        let mut points = Vec::new();
+        if !append_vertex(&mut points, closest_start_pos, DT_STRAIGHTPATH_START, path[0], max_straight_path) {
+            return Some(points);
+        }
+
+        if path.len() > 1 {
+            let mut portal_apex = closest_start_pos;
+            let mut portal_left = portal_apex;
+            let mut portal_right = portal_apex;
+            let mut left_index = 0;
+            let mut right_index = 0;
+            let mut left_poly_type = 0;
+            let mut right_poly_type = 0;
+            let mut left_poly_ref = path[0];
+            let mut right_poly_ref = path[0];
+
+            let mut i = 0;
+            while i < path.len() {
+                let (left, right, to_type) = if i + 1 < path.len() {
+                    let Some(portal) = self.get_portal_points(path[i], path[i + 1]) else {
+                        // path[i + 1] is gone, end at path[i]
+                        closest_end_pos = self.closest_point_on_poly_boundary(path[i], end_pos)?;
+                        append_vertex(&mut points, closest_end_pos, 0, path[i], max_straight_path);
+                        return Some(points);
+                    };
+                    // Starting right at the portal
+                    if i == 0 && distance_pt_seg_sqr_2d(&portal_apex, &portal.0, &portal.1).0 < 0.001 * 0.001 {
+                        i += 1;
+                        continue;
+                    }
+                    portal
+                } else {
+                    (closest_end_pos, closest_end_pos, DT_POLYTYPE_GROUND)
+                };
+
+                // Right vertex
+                if tri_area_2d(&portal_apex, &portal_right, &right) <= 0.0 {
+                    if vequal(&portal_apex, &portal_right) || tri_area_2d(&portal_apex, &portal_left, &right) > 0.0 {
+                        // Tighten the funnel
+                        portal_right = right;
+                        right_poly_ref = path.get(i + 1).copied().unwrap_or(0);
+                        right_poly_type = to_type;
+                        right_index = i;
+                    } else {
+                        // Right over left, the left point is a corner
+                        portal_apex = portal_left;
+                        let apex_index = left_index;
+                        let flags = if left_poly_ref == 0 {
+                            DT_STRAIGHTPATH_END
+                        } else if left_poly_type == DT_POLYTYPE_OFFMESH_CONNECTION {
+                            DT_STRAIGHTPATH_OFFMESH_CONNECTION
+                        } else {
+                            0
+                        };
+                        if !append_vertex(&mut points, portal_apex, flags, left_poly_ref, max_straight_path) {
+                            return Some(points);
+                        }
+                        portal_left = portal_apex;
+                        portal_right = portal_apex;
+                        left_index = apex_index;
+                        right_index = apex_index;
+                        // Restart from the new apex
+                        i = apex_index + 1;
+                        continue;
+                    }
+                }
+
+                // Left vertex
+                if tri_area_2d(&portal_apex, &portal_left, &left) >= 0.0 {
+                    if vequal(&portal_apex, &portal_left) || tri_area_2d(&portal_apex, &portal_right, &left) < 0.0 {
+                        portal_left = left;
+                        left_poly_ref = path.get(i + 1).copied().unwrap_or(0);
+                        left_poly_type = to_type;
+                        left_index = i;
+                    } else {
+                        portal_apex = portal_right;
+                        let apex_index = right_index;
+                        let flags = if right_poly_ref == 0 {
+                            DT_STRAIGHTPATH_END
+                        } else if right_poly_type == DT_POLYTYPE_OFFMESH_CONNECTION {
+                            DT_STRAIGHTPATH_OFFMESH_CONNECTION
+                        } else {
+                            0
+                        };
+                        if !append_vertex(&mut points, portal_apex, flags, right_poly_ref, max_straight_path) {
+                            return Some(points);
+                        }
+                        portal_left = portal_apex;
+                        portal_right = portal_apex;
+                        left_index = apex_index;
+                        right_index = apex_index;
+                        i = apex_index + 1;
+                        continue;
+                    }
+                }
+                i += 1;
+            }
+        }
+
+        append_vertex(&mut points, closest_end_pos, DT_STRAIGHTPATH_END, 0, max_straight_path);
+        Some(points)
+    }
+}
+
+/// Add a corner, or update the last one at the same spot; false once the
+/// path is full or has reached its end
+fn append_vertex(points: &mut Vec<StraightPathPoint>, pos: [f32; 3], flags: u8, poly: PolyRef, max: usize) -> bool {
+    if let Some(last) = points.last_mut().filter(|last| vequal(&last.pos, &pos)) {
+        last.flags = flags;
+        last.poly = poly;
+        return true;
+    }
+    if points.len() >= max {
+        return false;
+    }
+    points.push(StraightPathPoint { pos, flags, poly });
+    points.len() < max && flags != DT_STRAIGHTPATH_END
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmap::nav_mesh::tests::{build_tile, test_params, TestOffMesh};
+
+    const EXTENTS: [f32; 3] = [2.0, 4.0, 2.0];
+
+    fn corners(points: &[StraightPathPoint]) -> Vec<[f32; 3]> {
+        points.iter().map(|point| point.pos).collect()
+    }
+
+    #[test]
+    fn test_path_around_hole() {
+        let mut mesh = NavMesh::new(test_params(4)).unwrap();
+        mesh.add_tile(&build_tile(0, 0, 0.0, &[(1, 1)], true, &[])).unwrap();
+        let query = NavMeshQuery::new(&mesh, 256);
+        let filter = QueryFilter::new();
+
+        let start = [5.0, 0.5, 15.0];
+        let end = [25.0, 0.5, 15.0];
+        let (start_ref, start_pt) = query.find_nearest_poly(&start, &EXTENTS, &filter).unwrap();
+        let (end_ref, end_pt) = query.find_nearest_poly(&end, &EXTENTS, &filter).unwrap();
+        assert_eq!(start_pt, [5.0, 0.0, 15.0]);
+
+        let path = query.find_path(start_ref, end_ref, &start_pt, &end_pt, &filter, 64).unwrap();
+        assert!(!path.partial);
+        assert_eq!(path.polys.len(), 5);
+        assert_eq!(path.polys.first(), Some(&start_ref));
+        assert_eq!(path.polys.last(), Some(&end_ref));
+
+        // Two corners at the hole, on whichever side the search went
+        let points = query.find_straight_path(&start_pt, &end_pt, &path.polys, 16).unwrap();
+        assert_eq!(points.len(), 4);
+        let z = points[1].pos[2];
+        assert!(z == 10.0 || z == 20.0);
+        assert_eq!(corners(&points), [start_pt, [10.0, 0.0, z], [20.0, 0.0, z], end_pt]);
+        assert_eq!(points[0].flags, DT_STRAIGHTPATH_START);
+        assert_eq!(points[3].flags, DT_STRAIGHTPATH_END);
+        assert_eq!(points[3].poly, 0);
+
+        // Truncated outputs keep the start
+        let short = query.find_path(start_ref, end_ref, &start_pt, &end_pt, &filter, 2).unwrap();
+        assert_eq!(short.polys, path.polys[..2]);
+        assert_eq!(query.find_straight_path(&start_pt, &end_pt, &path.polys, 2).unwrap().len(), 2);
+
+        // In a straight line the path has no corners
+        let (end_ref, end_pt) = query.find_nearest_poly(&[5.0, 0.0, 28.0], &EXTENTS, &filter).unwrap();
+        let path = query.find_path(start_ref, end_ref, &start_pt, &end_pt, &filter, 64).unwrap();
+        let points = query.find_straight_path(&start_pt, &end_pt, &path.polys, 16).unwrap();
+        assert_eq!(corners(&points), [start_pt, end_pt]);
+    }
+
+    #[test]
+    fn test_path_across_tiles() {
+        let mut mesh = NavMesh::new(test_params(4)).unwrap();
+        mesh.add_tile(&build_tile(0, 0, 0.0, &[], false, &[])).unwrap();
+        let east = mesh.add_tile(&build_tile(1, 0, 0.5, &[], true, &[])).unwrap();
+        let filter = QueryFilter::new();
+
+        let start = [5.0, 0.0, 5.0];
+        let end = [55.0, 0.5, 5.0];
+        let (start_ref, end_ref) = {
+            let query = NavMeshQuery::new(&mesh, 256);
+            let (start_ref, _) = query.find_nearest_poly(&start, &EXTENTS, &filter).unwrap();
+            let (end_ref, _) = query.find_nearest_poly(&end, &EXTENTS, &filter).unwrap();
+            assert_eq!(query.get_poly_height(end_ref, &end), Some(0.5));
+
+            let path = query.find_path(start_ref, end_ref, &start, &end, &filter, 64).unwrap();
+            assert!(!path.partial);
+            assert_eq!(path.polys.len(), 6);
+            let points = query.find_straight_path(&start, &end, &path.polys, 16).unwrap();
+            assert_eq!(corners(&points), [start, end]);
+
+            // Too few nodes to get there
+            let path = NavMeshQuery::new(&mesh, 3).find_path(start_ref, end_ref, &start, &end, &filter, 64).unwrap();
+            assert!(path.partial);
+            (start_ref, end_ref)
+        };
+
+        // Without the east tile the end reference is stale
+        mesh.remove_tile(east).unwrap();
+        let query = NavMeshQuery::new(&mesh, 256);
+        assert_eq!(query.find_path(start_ref, end_ref, &start, &end, &filter, 64), None);
+        let (edge_ref, _) = query.find_nearest_poly(&[25.0, 0.0, 25.0], &EXTENTS, &filter).unwrap();
+        // Excluded polygons cannot be entered
+        let mut excluding = QueryFilter::new();
+        excluding.set_exclude_flags(1);
+        assert_eq!(query.find_nearest_poly(&start, &EXTENTS, &excluding), None);
+        let path = query.find_path(start_ref, edge_ref, &start, &end, &excluding, 64).unwrap();
+        assert_eq!(path, PolyPath { polys: vec![start_ref], partial: true });
+    }
+
+    #[test]
+    fn test_off_mesh_connection() {
+        // Two islands: the west column and the east column of one tile
+        let jump = TestOffMesh { start: [8.0, 0.0, 15.0], end: [22.0, 0.0, 15.0], rad: 1.0, bidirectional: false };
+        let holes = [(1, 0), (1, 1), (1, 2)];
+        let mut mesh = NavMesh::new(test_params(1)).unwrap();
+        mesh.add_tile(&build_tile(0, 0, 0.0, &holes, true, &[jump])).unwrap();
+        let query = NavMeshQuery::new(&mesh, 256);
+        let filter = QueryFilter::new();
+
+        let west = [5.0, 0.0, 5.0];
+        let east = [25.0, 0.0, 5.0];
+        let (west_ref, _) = query.find_nearest_poly(&west, &EXTENTS, &filter).unwrap();
+        let (east_ref, _) = query.find_nearest_poly(&east, &EXTENTS, &filter).unwrap();
+
+        let path = query.find_path(west_ref, east_ref, &west, &east, &filter, 64).unwrap();
+        assert!(!path.partial);
+        let points = query.find_straight_path(&west, &east, &path.polys, 16).unwrap();
+        assert_eq!(corners(&points), [west, [8.0, 0.0, 15.0], [22.0, 0.0, 15.0], east]);
+        assert_eq!(points[1].flags, DT_STRAIGHTPATH_OFFMESH_CONNECTION);
+
+        // One way only
+        let path = query.find_path(east_ref, west_ref, &east, &west, &filter, 64).unwrap();
+        assert!(path.partial);
+    }
+
+    #[test]
+    fn test_random_point() {
+        let mut mesh = NavMesh::new(test_params(4)).unwrap();
+        mesh.add_tile(&build_tile(0, 0, 2.0, &[(1, 1)], true, &[])).unwrap();
+        mesh.add_tile(&build_tile(0, 1, 2.0, &[], false, &[])).unwrap();
+        let query = NavMeshQuery::new(&mesh, 256);
+        let filter = QueryFilter::new();
+
+        let mut seed = 12345u32;
+        let mut frand = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32
+        };
+        for _ in 0..50 {
+            let (reference, pt) = query.find_random_point(&filter, &mut frand).unwrap();
+            assert_eq!(pt[1], 2.0);
+            assert!((0.0..=30.0).contains(&pt[0]) && (0.0..=60.0).contains(&pt[2]));
+            // Never in the hole
+            assert!(!((10.0..20.0).contains(&pt[0]) && (10.0..20.0).contains(&pt[2])));
+            assert_eq!(query.get_poly_height(reference, &pt), Some(2.0));
+        }
+
+        let mut excluding = QueryFilter::new();
+        excluding.set_include_flags(2);
+        assert_eq!(query.find_random_point(&excluding, frand), None);
+    }
+}
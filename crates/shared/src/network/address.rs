@@ -0,0 +1,213 @@
+// Client address normalization
+//
+// A dual-stack IPv6 listener reports IPv4 clients as v4-mapped addresses
+// (::ffff:203.0.113.7), while account.lockedIp and ip_banned.ip usually hold
+// the plain IPv4 text. Addresses are normalized before they are stored or
+// compared so both forms refer to the same client.
+//
+// IpNetwork covers both single addresses and CIDR networks, as used for
+// trusted proxies, subnet bans and deny lists.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+/// An address or CIDR network, e.g. "10.0.0.0/8" or "::1"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn parse(input: &str) -> Option<Self> {
+        let (addr, prefix) = match input.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse::<u8>().ok()?)),
+            None => (input.parse::<IpAddr>().ok()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(IpNetwork { addr, prefix })
+    }
+
+    /// Whether the network is a single address (/32 or /128)
+    pub fn is_host(&self) -> bool {
+        self.prefix == if self.addr.is_ipv4() { 32 } else { 128 }
+    }
+
+    /// The network with its host bits cleared, so "10.1.2.3/8" becomes "10.0.0.0/8"
+    pub fn canonical(&self) -> Self {
+        let addr = match self.addr {
+            IpAddr::V4(net) => IpAddr::V4((u32::from(net) & u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0)).into()),
+            IpAddr::V6(net) => IpAddr::V6((u128::from(net) & u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0)).into()),
+        };
+        IpNetwork { addr, prefix: self.prefix }
+    }
+
+    /// Turn a v4-mapped IPv6 network (::ffff:10.0.0.0/104) into plain IPv4 when `map_v4` is set
+    pub fn normalize(&self, map_v4: bool) -> Self {
+        match self.addr {
+            IpAddr::V6(v6) if map_v4 && self.prefix >= 96 => match v6.to_ipv4_mapped() {
+                Some(v4) => IpNetwork { addr: IpAddr::V4(v4), prefix: self.prefix - 96 },
+                None => *self,
+            },
+            _ => *self,
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Host addresses print without a prefix, networks as "addr/prefix"
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_host() {
+            write!(f, "{}", self.addr)
+        } else {
+            write!(f, "{}/{}", self.addr, self.prefix)
+        }
+    }
+}
+
+/// Turn v4-mapped IPv6 addresses into plain IPv4 when `map_v4` is set
+pub fn normalize_ip(ip: IpAddr, map_v4: bool) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) if map_v4 => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        _ => ip,
+    }
+}
+
+/// [`normalize_ip`] for a socket address, keeping the port
+pub fn normalize_addr(addr: SocketAddr, map_v4: bool) -> SocketAddr {
+    SocketAddr::new(normalize_ip(addr.ip(), map_v4), addr.port())
+}
+
+/// Whether an address stored as text matches a client address
+///
+/// The stored text is parsed, so "2001:DB8::1" and "2001:db8:0:0:0:0:0:1"
+/// match the same client. Unparseable text never matches.
+pub fn ip_matches(stored: &str, ip: IpAddr, map_v4: bool) -> bool {
+    stored
+        .trim()
+        .parse::<IpAddr>()
+        .is_ok_and(|stored| normalize_ip(stored, map_v4) == normalize_ip(ip, map_v4))
+}
+
+/// Text forms under which `ip` may be stored in the database, canonical form first
+///
+/// Used for lookups by string equality (ip_banned). With `map_v4` an IPv4
+/// client also matches rows written in v4-mapped form.
+pub fn ip_text_forms(ip: IpAddr, map_v4: bool) -> Vec<String> {
+    let ip = normalize_ip(ip, map_v4);
+    let mut forms = vec![ip.to_string()];
+    if let IpAddr::V4(v4) = ip
+        && map_v4
+    {
+        forms.push(v4.to_ipv6_mapped().to_string());
+    }
+    forms
+}
+
+/// Parse a deny list: one address or network per line, with "#" or ";"
+/// starting a comment (as in the Spamhaus DROP and FireHOL lists)
+///
+/// Returns the networks and the number of lines that could not be parsed.
+pub fn parse_network_list(text: &str) -> (Vec<IpNetwork>, usize) {
+    let mut networks = Vec::new();
+    let mut invalid = 0;
+    for line in text.lines() {
+        let entry = line.split(['#', ';']).next().unwrap_or("").trim();
+        if entry.is_empty() {
+            continue;
+        }
+        match IpNetwork::parse(entry) {
+            Some(network) => networks.push(network.canonical()),
+            None => invalid += 1,
+        }
+    }
+    (networks, invalid)
+}
+
+/// Name to resolve for `ip` on a DNS block list: the address reversed (by
+/// octet for IPv4, by nibble for IPv6) under `zone`
+pub fn dnsbl_query_name(ip: IpAddr, zone: &str) -> String {
+    let mut labels: Vec<String> = match ip {
+        IpAddr::V4(v4) => v4.octets().iter().rev().map(u8::to_string).collect(),
+        IpAddr::V6(v6) => v6
+            .octets()
+            .iter()
+            .rev()
+            .flat_map(|b| [b & 0x0F, b >> 4])
+            .map(|nibble| format!("{:x}", nibble))
+            .collect(),
+    };
+    labels.push(zone.trim_matches('.').to_string());
+    labels.join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_network() {
+        let net = IpNetwork::parse("10.0.0.0/8").unwrap();
+        assert!(net.contains("10.1.2.3".parse().unwrap()));
+        assert!(!net.contains("11.0.0.1".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+
+        let host = IpNetwork::parse("::1").unwrap();
+        assert!(host.contains("::1".parse().unwrap()));
+        assert!(IpNetwork::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert!(IpNetwork::parse("10.0.0.0/33").is_none());
+        assert!(IpNetwork::parse("proxy.local").is_none());
+
+        let sloppy = IpNetwork::parse("203.0.113.77/24").unwrap();
+        assert_eq!(sloppy.canonical().to_string(), "203.0.113.0/24");
+        assert_eq!(host.to_string(), "::1");
+        let mapped = IpNetwork::parse("::ffff:203.0.113.0/120").unwrap();
+        assert_eq!(mapped.normalize(true), IpNetwork::parse("203.0.113.0/24").unwrap());
+        assert_eq!(mapped.normalize(false), mapped);
+    }
+
+    #[test]
+    fn test_normalize_and_match() {
+        let mapped: IpAddr = "::ffff:203.0.113.7".parse().unwrap();
+        let v4: IpAddr = "203.0.113.7".parse().unwrap();
+        assert_eq!(normalize_ip(mapped, true), v4);
+        assert_eq!(normalize_ip(mapped, false), mapped);
+
+        assert!(ip_matches("203.0.113.7", mapped, true));
+        assert!(!ip_matches("203.0.113.7", mapped, false));
+        assert!(ip_matches(" 2001:DB8::1 ", "2001:db8:0:0:0:0:0:1".parse().unwrap(), true));
+        assert!(!ip_matches("0.0.0.0", v4, true));
+        assert!(!ip_matches("", v4, true));
+
+        assert_eq!(ip_text_forms(mapped, true), vec!["203.0.113.7", "::ffff:203.0.113.7"]);
+        assert_eq!(ip_text_forms("2001:DB8::1".parse().unwrap(), true), vec!["2001:db8::1"]);
+    }
+
+    #[test]
+    fn test_deny_lists() {
+        let text = "; Spamhaus DROP\n1.10.16.0/20 ; SBL256894\n\n# FireHOL\n203.0.113.77/24\n2001:db8::/32\nnot-an-address\n";
+        let (networks, invalid) = parse_network_list(text);
+        assert_eq!(invalid, 1);
+        let shown: Vec<String> = networks.iter().map(ToString::to_string).collect();
+        assert_eq!(shown, vec!["1.10.16.0/20", "203.0.113.0/24", "2001:db8::/32"]);
+
+        assert_eq!(dnsbl_query_name("203.0.113.7".parse().unwrap(), "zen.spamhaus.org."), "7.113.0.203.zen.spamhaus.org");
+        let v6 = dnsbl_query_name("2001:db8::1".parse().unwrap(), "dnsbl.example");
+        assert_eq!(v6, "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.dnsbl.example");
+    }
+}
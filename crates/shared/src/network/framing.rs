@@ -0,0 +1,156 @@
+// Length-prefixed frame helpers
+//
+// Auth and world packets are a fixed-size header announcing the length of the
+// body that follows. These helpers read such frames with a timeout and refuse
+// lengths above a caller-supplied bound, so a client can't make the server
+// allocate large buffers just by announcing them.
+
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+
+/// Read exactly `buf.len()` bytes, failing if that takes longer than `dur`
+///
+/// Bytes read before a timeout are lost; use [`FrameReader`] when the read
+/// must be resumable.
+pub async fn read_exact_timeout<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8], dur: Duration) -> Result<()> {
+    match timeout(dur, reader.read_exact(buf)).await {
+        Ok(result) => {
+            result?;
+            Ok(())
+        }
+        Err(_) => bail!("read timeout"),
+    }
+}
+
+/// Write all of `data`, failing if that takes longer than `dur`
+pub async fn write_all_timeout<W: AsyncWrite + Unpin>(writer: &mut W, data: &[u8], dur: Duration) -> Result<()> {
+    match timeout(dur, writer.write_all(data)).await {
+        Ok(result) => {
+            result?;
+            Ok(())
+        }
+        Err(_) => bail!("write timeout"),
+    }
+}
+
+/// Read a frame body of `len` bytes announced by a header
+///
+/// Fails without reading anything when `len` exceeds `max_len`.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, len: usize, max_len: usize, dur: Duration) -> Result<Vec<u8>> {
+    if len > max_len {
+        bail!("frame of {} bytes exceeds the {} byte limit", len, max_len);
+    }
+    let mut buf = vec![0u8; len];
+    read_exact_timeout(reader, &mut buf, dur).await?;
+    Ok(buf)
+}
+
+/// Resumable frame reader
+///
+/// Keeps the bytes of a partially received frame across calls, so a read that
+/// timed out or was cancelled (e.g. in a `select!`) can continue where it left
+/// off instead of desynchronising the stream.
+#[derive(Debug)]
+pub struct FrameReader {
+    buf: Vec<u8>,
+    filled: usize,
+    max_len: usize,
+}
+
+impl FrameReader {
+    /// Reader accepting frames of up to `max_len` bytes
+    pub fn new(max_len: usize) -> Self {
+        FrameReader {
+            buf: Vec::new(),
+            filled: 0,
+            max_len,
+        }
+    }
+
+    /// Start a new frame of `len` bytes, discarding any previous one
+    pub fn expect(&mut self, len: usize) -> Result<()> {
+        if len > self.max_len {
+            bail!("frame of {} bytes exceeds the {} byte limit", len, self.max_len);
+        }
+        self.buf.clear();
+        self.buf.resize(len, 0);
+        self.filled = 0;
+        Ok(())
+    }
+
+    /// Bytes still missing from the current frame
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.filled
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Read until the current frame is complete and return it
+    ///
+    /// Cancel safe: bytes received before the future is dropped are kept.
+    pub async fn read<R: AsyncRead + Unpin>(&mut self, reader: &mut R) -> Result<&[u8]> {
+        while self.filled < self.buf.len() {
+            let read = reader.read(&mut self.buf[self.filled..]).await?;
+            if read == 0 {
+                bail!("connection closed with {} of {} frame bytes received", self.filled, self.buf.len());
+            }
+            self.filled += read;
+        }
+        Ok(&self.buf)
+    }
+
+    /// [`FrameReader::read`] with a timeout; on timeout the partial frame is kept
+    pub async fn read_timeout<R: AsyncRead + Unpin>(&mut self, reader: &mut R, dur: Duration) -> Result<&[u8]> {
+        if timeout(dur, self.read(reader)).await.is_err() {
+            bail!("read timeout with {} of {} frame bytes received", self.filled, self.buf.len());
+        }
+        Ok(&self.buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_frame_bounds() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let mut data: &[u8] = &[1, 2, 3, 4, 5];
+            let frame = read_frame(&mut data, 3, 4, Duration::from_secs(1)).await.unwrap();
+            assert_eq!(frame, [1, 2, 3]);
+            assert!(read_frame(&mut data, 5, 4, Duration::from_secs(1)).await.is_err());
+            // Nothing was consumed by the rejected frame
+            assert_eq!(data, [4, 5]);
+            assert!(read_frame(&mut data, 3, 4, Duration::from_secs(1)).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_frame_reader_resumes_after_timeout() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let (mut client, mut server) = tokio::io::duplex(64);
+            let mut reader = FrameReader::new(8);
+            assert!(reader.expect(9).is_err());
+            reader.expect(6).unwrap();
+
+            client.write_all(&[1, 2, 3]).await.unwrap();
+            assert!(reader.read_timeout(&mut server, Duration::from_millis(50)).await.is_err());
+            assert_eq!(reader.remaining(), 3);
+
+            client.write_all(&[4, 5, 6, 7]).await.unwrap();
+            let frame = reader.read_timeout(&mut server, Duration::from_secs(1)).await.unwrap();
+            assert_eq!(frame, [1, 2, 3, 4, 5, 6]);
+            assert!(reader.is_complete());
+
+            // The byte after the frame is still in the stream
+            assert_eq!(server.read_u8().await.unwrap(), 7);
+        });
+    }
+}
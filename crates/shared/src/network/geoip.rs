@@ -0,0 +1,73 @@
+// GeoIP country lookup
+//
+// Resolves client addresses to ISO 3166-1 country codes ("DE", "US") with a
+// MaxMind GeoLite2/GeoIP2 Country or City database, for country bans and
+// accounts locked to a country. Needs the "geoip" feature; without it
+// opening a database fails and country checks stay off.
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Open country database
+pub struct GeoIp {
+    #[cfg(feature = "geoip")]
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    /// Load a .mmdb database into memory
+    #[cfg(feature = "geoip")]
+    pub fn open(path: &Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let reader = maxminddb::Reader::open_readfile(path)
+            .with_context(|| format!("Cannot open GeoIP database {}", path.display()))?;
+        Ok(GeoIp { reader })
+    }
+
+    #[cfg(not(feature = "geoip"))]
+    pub fn open(path: &Path) -> Result<Self> {
+        anyhow::bail!("Cannot open GeoIP database {}: this build lacks the \"geoip\" feature", path.display())
+    }
+
+    /// Upper-case country code of `ip`; None for private, reserved or unknown addresses
+    #[cfg(feature = "geoip")]
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record: maxminddb::geoip2::Country = self.reader.lookup(ip).ok()?;
+        record
+            .country
+            .or(record.registered_country)
+            .and_then(|country| country.iso_code)
+            .map(|code| code.to_ascii_uppercase())
+    }
+
+    #[cfg(not(feature = "geoip"))]
+    pub fn country(&self, _ip: IpAddr) -> Option<String> {
+        None
+    }
+}
+
+/// Parse a comma or space separated list of country codes ("CN, KP")
+pub fn parse_country_list(list: &str) -> HashSet<String> {
+    list.split([',', ' '])
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_country_list() {
+        let list = parse_country_list(" kp,CN  ru,");
+        assert_eq!(list.len(), 3);
+        assert!(list.contains("KP") && list.contains("CN") && list.contains("RU"));
+        assert!(parse_country_list("").is_empty());
+    }
+}
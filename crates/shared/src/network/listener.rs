@@ -0,0 +1,179 @@
+// Listener binding
+// Rust equivalent of the AsyncListener setup in the C++ core, extended to
+// several addresses so a server can listen on IPv4 and IPv6 at once.
+//
+// On Unix the listening sockets can also be handed to a re-executed process
+// (see `export_listeners`), so a restart never closes the port.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use anyhow::{Context, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+
+/// Pending connection queue length passed to listen()
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Environment variable listing the listener descriptors passed across exec()
+pub const LISTEN_FDS_ENV: &str = "MANGOS_LISTEN_FDS";
+
+/// Parse a BindIP style address list
+///
+/// Entries are separated by commas or whitespace. Each is an address
+/// ("0.0.0.0", "::", "[::1]") listening on `default_port`, or an address
+/// with its own port ("192.0.2.10:3725", "[2001:db8::1]:3725"). An empty
+/// list binds every IPv4 interface.
+pub fn parse_bind_addresses(list: &str, default_port: u16) -> Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = list
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<SocketAddr>()
+                .or_else(|_| entry.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().map(|ip| SocketAddr::new(ip, default_port)))
+                .with_context(|| format!("Invalid bind address '{}'", entry))
+        })
+        .collect::<Result<_>>()?;
+
+    if addresses.is_empty() {
+        return Ok(vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), default_port)]);
+    }
+    Ok(addresses)
+}
+
+/// Bind one listener per address
+///
+/// When IPv4 addresses are bound as well, IPv6 sockets are made v6-only so
+/// "0.0.0.0" and "::" can share a port. A lone IPv6 listener keeps the OS
+/// default, which usually also accepts IPv4 clients as v4-mapped addresses.
+pub fn bind_listeners(addresses: &[SocketAddr]) -> Result<Vec<TcpListener>> {
+    let v6_only = addresses.iter().any(SocketAddr::is_ipv4);
+    addresses
+        .iter()
+        .map(|addr| bind_listener(*addr, v6_only).with_context(|| format!("Cannot listen on {}", addr)))
+        .collect()
+}
+
+fn bind_listener(addr: SocketAddr, v6_only: bool) -> Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() && v6_only {
+        socket.set_only_v6(true)?;
+    }
+    // Allow a quick restart while old connections sit in TIME_WAIT (Windows
+    // SO_REUSEADDR would let another process steal the port instead)
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// Keep the listeners open across exec() and return the value for
+/// [`LISTEN_FDS_ENV`] describing them to the new process
+#[cfg(unix)]
+pub fn export_listeners(listeners: &[TcpListener]) -> Result<String> {
+    use std::os::fd::AsRawFd;
+
+    let fds = listeners
+        .iter()
+        .map(|listener| {
+            socket2::SockRef::from(listener).set_cloexec(false)?;
+            Ok(listener.as_raw_fd().to_string())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(fds.join(","))
+}
+
+/// Listeners passed on by the process that exec'd this one
+///
+/// Descriptors that are not listening TCP sockets are skipped. Must be called
+/// once, from within the tokio runtime.
+#[cfg(unix)]
+pub fn take_inherited_listeners() -> Vec<TcpListener> {
+    match std::env::var(LISTEN_FDS_ENV) {
+        Ok(list) => adopt_listeners(&list),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(unix)]
+fn adopt_listeners(list: &str) -> Vec<TcpListener> {
+    use std::os::fd::{FromRawFd, RawFd};
+
+    list.split(',')
+        .filter_map(|fd| fd.trim().parse::<RawFd>().ok())
+        .filter(|&fd| fd > 2)
+        .filter_map(|fd| {
+            // SAFETY: the descriptor was left open for us by the previous
+            // process and nothing else in this one owns it yet
+            let socket = unsafe { Socket::from_raw_fd(fd) };
+            let check = || -> Result<()> {
+                anyhow::ensure!(socket.r#type()? == Type::STREAM && socket.is_listener()?, "not a listening socket");
+                socket.set_cloexec(true)?;
+                socket.set_nonblocking(true)?;
+                Ok(())
+            };
+            match check().and_then(|()| Ok(TcpListener::from_std(socket.into())?)) {
+                Ok(listener) => Some(listener),
+                Err(e) => {
+                    tracing::warn!("Ignoring inherited descriptor {}: {:#}", fd, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bind_addresses() {
+        let addresses = parse_bind_addresses("0.0.0.0, [::] 192.0.2.10:3725 [2001:db8::1]:3726 ::1", 3724).unwrap();
+        let expected: Vec<SocketAddr> = ["0.0.0.0:3724", "[::]:3724", "192.0.2.10:3725", "[2001:db8::1]:3726", "[::1]:3724"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        assert_eq!(addresses, expected);
+
+        assert_eq!(parse_bind_addresses("", 3724).unwrap(), vec!["0.0.0.0:3724".parse().unwrap()]);
+        assert!(parse_bind_addresses("localhost", 3724).is_err());
+    }
+
+    #[test]
+    fn test_bind_listeners_on_both_families() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+        runtime.block_on(async {
+            let listeners = bind_listeners(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+            assert!(listeners[0].local_addr().unwrap().port() > 0);
+
+            // IPv6 may be disabled on the test host
+            if let Ok(listeners) = bind_listeners(&["[::1]:0".parse().unwrap()]) {
+                assert!(listeners[0].local_addr().unwrap().is_ipv6());
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listener_handover() {
+        use std::os::fd::IntoRawFd;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+        runtime.block_on(async {
+            let listeners = bind_listeners(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+            let addr = listeners[0].local_addr().unwrap();
+            let fds = export_listeners(&listeners).unwrap();
+
+            // What exec() would leave behind: the descriptor without its owner
+            let listener = listeners.into_iter().next().unwrap();
+            assert_eq!(listener.into_std().unwrap().into_raw_fd().to_string(), fds);
+
+            let adopted = adopt_listeners(&format!("{},1,junk", fds));
+            assert_eq!(adopted.len(), 1);
+            assert_eq!(adopted[0].local_addr().unwrap(), addr);
+        });
+    }
+}
@@ -6,6 +6,28 @@
 // The actual socket handling is done in the realmd crate since
 // it's protocol-specific.
 
+mod address;
+mod framing;
+mod geoip;
+mod listener;
+mod proxy_protocol;
+mod rate_limit;
+mod socket_options;
+mod throttle;
+mod tls;
+
+pub use address::{dnsbl_query_name, ip_matches, ip_text_forms, normalize_addr, normalize_ip, parse_network_list, IpNetwork};
+pub use framing::{read_exact_timeout, read_frame, write_all_timeout, FrameReader};
+pub use geoip::{parse_country_list, GeoIp};
+pub use listener::{bind_listeners, parse_bind_addresses, LISTEN_FDS_ENV};
+#[cfg(unix)]
+pub use listener::{export_listeners, take_inherited_listeners};
+pub use proxy_protocol::{read_proxy_header, ProxyProtocol};
+pub use rate_limit::IpRateLimiter;
+pub use socket_options::SocketOptions;
+pub use throttle::{ThrottledReader, TokenBucket};
+pub use tls::{TlsAcceptor, TlsSettings};
+
 /// Re-export tokio networking types for convenience
 pub use tokio::net::{TcpListener, TcpStream};
 pub use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -0,0 +1,224 @@
+// PROXY protocol (v1 text and v2 binary) header parsing
+//
+// Load balancers such as HAProxy, AWS NLB or GCP TCP proxies prepend a
+// header carrying the real client address to every connection they forward.
+// The header is only accepted from configured trusted proxies, otherwise any
+// client could claim an arbitrary address and dodge IP bans/locks.
+//
+// Spec: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::address::IpNetwork;
+use crate::config::Config;
+
+/// First 12 bytes of a v2 header
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// v1 headers are at most 107 bytes including the CRLF
+const V1_MAX_LENGTH: usize = 107;
+
+/// Bytes read before deciding between v1 and v2 ("PROXY " / first 6 signature bytes)
+const PREFIX_LENGTH: usize = 6;
+
+/// PROXY protocol settings for a listener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyProtocol {
+    /// Peers allowed to send a PROXY header
+    pub trusted: Vec<IpNetwork>,
+    /// How long a trusted peer has to send its header
+    pub timeout: Duration,
+}
+
+impl ProxyProtocol {
+    /// Read `ProxyProtocol.*` from the configuration; None when disabled
+    ///
+    /// `ProxyProtocol.TrustedProxies` is a comma/space separated list of
+    /// addresses and CIDR networks, `ProxyProtocol.Timeout` is in seconds.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.get_bool_default("ProxyProtocol.Enabled", false) {
+            return None;
+        }
+
+        let trusted = config
+            .get_string("ProxyProtocol.TrustedProxies")
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let network = IpNetwork::parse(entry);
+                if network.is_none() {
+                    tracing::warn!("Ignoring invalid ProxyProtocol.TrustedProxies entry '{}'", entry);
+                }
+                network
+            })
+            .collect();
+
+        Some(ProxyProtocol {
+            trusted,
+            timeout: Duration::from_secs(config.get_int_default("ProxyProtocol.Timeout", 5).max(1) as u64),
+        })
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.iter().any(|network| network.contains(ip))
+    }
+
+    /// Resolve the client address of a freshly accepted connection
+    ///
+    /// Connections from trusted proxies must start with a PROXY header, which
+    /// is consumed here; the header's source address is returned unless it is
+    /// a LOCAL/UNKNOWN header (proxy health checks). Other peers are returned
+    /// unchanged and their stream is not touched.
+    pub async fn resolve_peer<R: AsyncRead + Unpin>(&self, stream: &mut R, peer: SocketAddr) -> Result<SocketAddr> {
+        if !self.is_trusted(peer.ip()) {
+            return Ok(peer);
+        }
+        let source = tokio::time::timeout(self.timeout, read_proxy_header(stream))
+            .await
+            .context("Timed out waiting for PROXY header")??;
+        Ok(source.unwrap_or(peer))
+    }
+}
+
+/// Read and parse a v1 or v2 PROXY header from the start of `stream`
+///
+/// Returns the client (source) address, or None for LOCAL/UNKNOWN headers and
+/// non-TCP address families. Reads exactly the header and nothing more.
+pub async fn read_proxy_header<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<SocketAddr>> {
+    let mut prefix = [0u8; PREFIX_LENGTH];
+    stream.read_exact(&mut prefix).await?;
+
+    if &prefix == b"PROXY " {
+        let mut line = prefix.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() >= V1_MAX_LENGTH {
+                bail!("PROXY v1 header too long");
+            }
+            line.push(stream.read_u8().await?);
+        }
+        parse_v1(&line)
+    } else if prefix == V2_SIGNATURE[..PREFIX_LENGTH] {
+        let mut header = [0u8; 16];
+        header[..PREFIX_LENGTH].copy_from_slice(&prefix);
+        stream.read_exact(&mut header[PREFIX_LENGTH..]).await?;
+        if header[..12] != V2_SIGNATURE {
+            bail!("Invalid PROXY v2 signature");
+        }
+        let length = u16::from_be_bytes([header[14], header[15]]) as usize;
+        let mut addresses = vec![0u8; length];
+        stream.read_exact(&mut addresses).await?;
+        parse_v2(header[12], header[13], &addresses)
+    } else {
+        bail!("Connection from trusted proxy did not start with a PROXY header")
+    }
+}
+
+/// Parse "PROXY TCP4 <src> <dst> <sport> <dport>\r\n"
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line).context("PROXY v1 header is not ASCII")?;
+    let mut parts = line.trim_end_matches("\r\n").split(' ').skip(1);
+
+    match parts.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        other => bail!("Unsupported PROXY v1 protocol {:?}", other),
+    }
+
+    let (Some(source), Some(_destination), Some(source_port), Some(_destination_port), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        bail!("Malformed PROXY v1 header");
+    };
+    let ip: IpAddr = source.parse().context("Invalid PROXY v1 source address")?;
+    let port: u16 = source_port.parse().context("Invalid PROXY v1 source port")?;
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+/// Parse the address block of a v2 header
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> Result<Option<SocketAddr>> {
+    if version_command >> 4 != 2 {
+        bail!("Unsupported PROXY v2 version {}", version_command >> 4);
+    }
+    match version_command & 0x0F {
+        0x0 => return Ok(None), // LOCAL: connection made by the proxy itself
+        0x1 => {}
+        command => bail!("Unsupported PROXY v2 command {}", command),
+    }
+
+    match family {
+        // TCP over IPv4: src addr, dst addr, src port, dst port
+        0x11 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&addresses[0..4])?);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        // TCP over IPv6
+        0x21 if addresses.len() >= 36 => {
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&addresses[0..16])?);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::V6(ip), port)))
+        }
+        0x11 | 0x21 => bail!("Truncated PROXY v2 address block"),
+        // UNSPEC, UDP, unix sockets: keep the proxy address
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn test_read_v1_header() {
+        let mut data: &[u8] = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 3724\r\n\x00\x08";
+        let addr = block_on(read_proxy_header(&mut data)).unwrap();
+        assert_eq!(addr, Some("203.0.113.7:51234".parse().unwrap()));
+        // The client's first bytes are left in the stream
+        assert_eq!(data, b"\x00\x08");
+
+        let mut data: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(block_on(read_proxy_header(&mut data)).unwrap(), None);
+
+        let mut data: &[u8] = b"\x00\x08\x28\x00WoW\x00";
+        assert!(block_on(read_proxy_header(&mut data)).is_err());
+    }
+
+    #[test]
+    fn test_read_v2_header() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0x00, 0x0C]);
+        header.extend_from_slice(&[198, 51, 100, 20, 10, 0, 0, 1]);
+        header.extend_from_slice(&40000u16.to_be_bytes());
+        header.extend_from_slice(&3724u16.to_be_bytes());
+        header.push(0x00);
+
+        let mut data = header.as_slice();
+        let addr = block_on(read_proxy_header(&mut data)).unwrap();
+        assert_eq!(addr, Some("198.51.100.20:40000".parse().unwrap()));
+        assert_eq!(data, [0x00]);
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+        assert_eq!(block_on(read_proxy_header(&mut local.as_slice())).unwrap(), None);
+    }
+
+    #[test]
+    fn test_untrusted_peer_is_not_parsed() {
+        let proxy = ProxyProtocol {
+            trusted: vec![IpNetwork::parse("127.0.0.1").unwrap()],
+            timeout: Duration::from_secs(1),
+        };
+        let peer: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let mut data: &[u8] = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 3724\r\n";
+        assert_eq!(block_on(proxy.resolve_peer(&mut data, peer)).unwrap(), peer);
+        assert_eq!(data.len(), 44);
+    }
+}
@@ -0,0 +1,129 @@
+// Per-address attempt limiting
+//
+// Counts attempts per source IP over a sliding window, e.g. logon challenges
+// per minute. Unlike the database-backed WrongPass autoban this needs no
+// account and no failed password, so it also slows down bots spraying one
+// password over many accounts.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::config::Config;
+
+/// Addresses tracked before idle ones are swept out
+const SWEEP_THRESHOLD: usize = 4096;
+
+/// Sliding-window attempt counter keyed by IP address
+#[derive(Debug)]
+pub struct IpRateLimiter {
+    max_attempts: usize,
+    window: Duration,
+    tarpit: Duration,
+    attempts: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+impl IpRateLimiter {
+    /// Allow `max_attempts` per `window` from one address; 0 disables the limit
+    pub fn new(max_attempts: u32, window: Duration, tarpit: Duration) -> Self {
+        IpRateLimiter {
+            max_attempts: max_attempts as usize,
+            window,
+            tarpit,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Read `<prefix>.RateLimit` (attempts), `<prefix>.RateLimitWindow` (seconds)
+    /// and `<prefix>.RateLimitTarpit` (milliseconds)
+    pub fn from_config(config: &Config, prefix: &str) -> Self {
+        IpRateLimiter::new(
+            config.get_int_default(&format!("{}.RateLimit", prefix), 0).max(0) as u32,
+            Duration::from_secs(config.get_int_default(&format!("{}.RateLimitWindow", prefix), 60).max(1) as u64),
+            Duration::from_millis(config.get_int_default(&format!("{}.RateLimitTarpit", prefix), 2000).max(0) as u64),
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_attempts > 0
+    }
+
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// How long to stall a client that went over the limit
+    pub fn tarpit(&self) -> Duration {
+        self.tarpit
+    }
+
+    /// Record an attempt from `ip`; false when it is over the limit
+    ///
+    /// Rejected attempts count too, so a client has to actually back off
+    /// for the window to clear.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+
+        let mut attempts = self.attempts.lock();
+        if attempts.len() >= SWEEP_THRESHOLD {
+            let window = self.window;
+            attempts.retain(|_, times| times.back().is_some_and(|&t| now.saturating_duration_since(t) < window));
+        }
+
+        let times = attempts.entry(ip).or_default();
+        while times.front().is_some_and(|&t| now.saturating_duration_since(t) >= self.window) {
+            times.pop_front();
+        }
+        // Keep at most one entry past the limit, enough to stay over it
+        if times.len() > self.max_attempts {
+            times.pop_front();
+        }
+        times.push_back(now);
+        times.len() <= self.max_attempts
+    }
+
+    /// Number of addresses currently tracked
+    pub fn tracked(&self) -> usize {
+        self.attempts.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_rate_limiter() {
+        let limiter = IpRateLimiter::new(3, Duration::from_secs(60), Duration::ZERO);
+        let spammer: IpAddr = "192.0.2.1".parse().unwrap();
+        let player: IpAddr = "2001:db8::1".parse().unwrap();
+        let start = Instant::now();
+
+        assert!((0..3).all(|_| limiter.check_at(spammer, start)));
+        assert!(!limiter.check_at(spammer, start + Duration::from_secs(10)));
+        assert!(limiter.check_at(player, start + Duration::from_secs(10)));
+
+        assert!(!limiter.check_at(spammer, start + Duration::from_secs(30)));
+
+        // The first three attempts left the window, the rejected ones still count
+        assert!(limiter.check_at(spammer, start + Duration::from_secs(61)));
+        assert!(!limiter.check_at(spammer, start + Duration::from_secs(62)));
+        assert_eq!(limiter.tracked(), 2);
+
+        let disabled = IpRateLimiter::new(0, Duration::from_secs(60), Duration::ZERO);
+        assert!((0..100).all(|_| disabled.check(spammer)));
+        assert_eq!(disabled.tracked(), 0);
+    }
+}
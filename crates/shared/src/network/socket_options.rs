@@ -0,0 +1,119 @@
+// Socket options for accepted connections
+// Rust equivalent of the Network.TcpNodelay handling in the C++ WorldSocket
+//
+// Auth packets are tiny, so Nagle's algorithm combined with delayed ACKs on
+// some stacks adds up to ~200ms per round trip; TCP_NODELAY is on by default.
+// Keepalive detects clients that vanished without closing the connection.
+
+use std::io;
+use std::time::Duration;
+
+use socket2::{SockRef, TcpKeepalive};
+use tokio::net::TcpStream;
+
+use crate::config::Config;
+
+/// Options applied to every accepted socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Disable Nagle's algorithm
+    pub no_delay: bool,
+    /// Idle time before keepalive probes start; None disables keepalive
+    pub keepalive_time: Option<Duration>,
+    /// Time between keepalive probes; None keeps the OS default
+    pub keepalive_interval: Option<Duration>,
+    /// SO_LINGER timeout; None keeps the OS default
+    pub linger: Option<Duration>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            no_delay: true,
+            keepalive_time: None,
+            keepalive_interval: None,
+            linger: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Read `Network.*` socket settings from the configuration
+    ///
+    /// `Network.KeepAlive` and `Network.KeepAliveInterval` are in seconds
+    /// (0 = disabled / OS default), `Network.Linger` is in seconds with -1
+    /// keeping the OS default.
+    pub fn from_config(config: &Config) -> Self {
+        let seconds = |key: &str, default: i32| {
+            let value = config.get_int_default(key, default);
+            (value > 0).then(|| Duration::from_secs(value as u64))
+        };
+
+        SocketOptions {
+            no_delay: config.get_bool_default("Network.TcpNodelay", true),
+            keepalive_time: seconds("Network.KeepAlive", 0),
+            keepalive_interval: seconds("Network.KeepAliveInterval", 0),
+            linger: match config.get_int_default("Network.Linger", -1) {
+                value if value < 0 => None,
+                value => Some(Duration::from_secs(value as u64)),
+            },
+        }
+    }
+
+    /// Apply the options to an accepted connection
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.no_delay)?;
+
+        let socket = SockRef::from(stream);
+        if let Some(time) = self.keepalive_time {
+            let keepalive = TcpKeepalive::new().with_time(time);
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "windows"
+            ))]
+            let keepalive = match self.keepalive_interval {
+                Some(interval) => keepalive.with_interval(interval),
+                None => keepalive,
+            };
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+        if self.linger.is_some() {
+            socket.set_linger(self.linger)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_socket_options() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+            let (accepted, _) = listener.accept().await.unwrap();
+
+            let options = SocketOptions {
+                no_delay: true,
+                keepalive_time: Some(Duration::from_secs(60)),
+                keepalive_interval: Some(Duration::from_secs(10)),
+                linger: Some(Duration::from_secs(2)),
+            };
+            options.apply(&accepted).unwrap();
+
+            assert!(accepted.nodelay().unwrap());
+            let socket = SockRef::from(&accepted);
+            assert!(socket.keepalive().unwrap());
+            assert_eq!(socket.linger().unwrap(), Some(Duration::from_secs(2)));
+            drop(client);
+        });
+    }
+}
@@ -0,0 +1,183 @@
+// Read throttling
+//
+// Token-bucket rate limit for the read side of a connection. Used on
+// connections that have not authenticated yet, so a client streaming garbage
+// can't make the parser allocate and discard buffers at full line speed.
+// Writes pass through unthrottled.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// Token bucket refilled at `rate` bytes per second up to `burst` bytes
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket; `rate` must be non-zero
+    pub fn new(rate: u32, burst: u32) -> Self {
+        let burst = burst.max(1) as f64;
+        TokenBucket {
+            rate: rate.max(1) as f64,
+            burst,
+            tokens: burst,
+            refilled: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled = now;
+    }
+
+    /// Whole bytes available now
+    pub fn available(&mut self) -> usize {
+        self.refill(Instant::now());
+        self.tokens as usize
+    }
+
+    /// Time until at least one byte is available
+    pub fn time_until_available(&self) -> Duration {
+        Duration::from_secs_f64(((1.0 - self.tokens) / self.rate).max(0.0))
+    }
+
+    pub fn consume(&mut self, bytes: usize) {
+        self.tokens -= bytes as f64;
+    }
+}
+
+/// AsyncRead wrapper limiting the read rate with a [`TokenBucket`]
+pub struct ThrottledReader<R> {
+    inner: R,
+    bucket: Option<TokenBucket>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> ThrottledReader<R> {
+    /// Limit reads to `rate` bytes per second with bursts of up to `burst` bytes
+    pub fn new(inner: R, rate: u32, burst: u32) -> Self {
+        ThrottledReader {
+            inner,
+            bucket: Some(TokenBucket::new(rate, burst)),
+            sleep: None,
+        }
+    }
+
+    /// Wrap without a limit
+    pub fn unlimited(inner: R) -> Self {
+        ThrottledReader {
+            inner,
+            bucket: None,
+            sleep: None,
+        }
+    }
+
+    /// Lift the limit, e.g. once the client has authenticated
+    pub fn set_unlimited(&mut self) {
+        self.bucket = None;
+        self.sleep = None;
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.bucket.is_some()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ThrottledReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let Some(bucket) = this.bucket.as_mut() else {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        };
+
+        if let Some(sleep) = this.sleep.as_mut() {
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.sleep = None;
+        }
+
+        let available = bucket.available();
+        if available == 0 {
+            let mut sleep = Box::pin(tokio::time::sleep(bucket.time_until_available()));
+            // Registers the waker; a zero-length sleep can complete immediately
+            if sleep.as_mut().poll(cx).is_pending() {
+                this.sleep = Some(sleep);
+                return Poll::Pending;
+            }
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let limit = available.min(buf.remaining());
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(limit));
+        let result = Pin::new(&mut this.inner).poll_read(cx, &mut limited);
+        let read = limited.filled().len();
+        buf.advance(read);
+        bucket.consume(read);
+        result
+    }
+}
+
+impl<R: AsyncWrite + Unpin> AsyncWrite for ThrottledReader<R> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn test_throttled_reader_limits_rate() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let data = vec![0xAAu8; 3000];
+            let mut reader = ThrottledReader::new(data.as_slice(), 10_000, 1000);
+
+            let start = Instant::now();
+            let mut buf = vec![0u8; 3000];
+            reader.read_exact(&mut buf).await.unwrap();
+            // 1000 byte burst, then 2000 bytes at 10000 bytes/s
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(190), "{:?}", elapsed);
+            assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+            assert_eq!(buf, data);
+
+            reader.set_unlimited();
+            assert!(!reader.is_limited());
+        });
+    }
+}
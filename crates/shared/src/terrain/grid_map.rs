@@ -0,0 +1,564 @@
+// GridMap - Terrain of one grid, loaded from an extracted .map file
+// Rust equivalent of GridMap in GridMap.h/cpp
+//
+// A .map file (written by `extractors map-dbc`) covers one 533.33 yard grid:
+//   header       "MAPS", "s1.4" and offset/size of every section
+//   AREA         area flag per cell (16x16), or one flag for the grid
+//   MHGT         heights: 129x129 corner points (V9) then 128x128 centre
+//                points (V8), as f32 or packed into u16/u8 steps above the
+//                grid minimum, or nothing for a flat grid
+//   MLIQ         optional liquid: entry and type flags per cell (or one for
+//                the grid) and the levels of the wet rectangle
+//   holes        hole mask per cell (16x16)
+// Rows of every array run along the world X axis, columns along Y.
+
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::error::MangosError;
+use crate::geometry::grid_offset;
+
+use super::{INVALID_HEIGHT, MAP_RESOLUTION};
+
+const MAP_MAGIC: &[u8; 4] = b"MAPS";
+const MAP_VERSION_MAGIC: &[u8; 4] = b"s1.4";
+const MAP_AREA_MAGIC: &[u8; 4] = b"AREA";
+const MAP_HEIGHT_MAGIC: &[u8; 4] = b"MHGT";
+const MAP_LIQUID_MAGIC: &[u8; 4] = b"MLIQ";
+
+const MAP_AREA_NO_AREA: u16 = 0x0001;
+
+const MAP_HEIGHT_NO_HEIGHT: u32 = 0x0001;
+const MAP_HEIGHT_AS_INT16: u32 = 0x0002;
+const MAP_HEIGHT_AS_INT8: u32 = 0x0004;
+
+const MAP_LIQUID_NO_TYPE: u8 = 0x01;
+const MAP_LIQUID_NO_HEIGHT: u8 = 0x02;
+
+/// Liquid type flags per cell (GridMapLiquidData::type_flags)
+pub const MAP_LIQUID_TYPE_NO_WATER: u8 = 0x00;
+pub const MAP_LIQUID_TYPE_MAGMA: u8 = 0x01;
+pub const MAP_LIQUID_TYPE_OCEAN: u8 = 0x02;
+pub const MAP_LIQUID_TYPE_SLIME: u8 = 0x04;
+pub const MAP_LIQUID_TYPE_WATER: u8 = 0x08;
+pub const MAP_LIQUID_TYPE_DEEP_WATER: u8 = 0x10;
+pub const MAP_ALL_LIQUIDS: u8 = MAP_LIQUID_TYPE_WATER | MAP_LIQUID_TYPE_MAGMA | MAP_LIQUID_TYPE_OCEAN | MAP_LIQUID_TYPE_SLIME;
+
+/// Cells per grid side (area, liquid type and hole arrays)
+const CELLS: usize = 16;
+const V9_SIZE: usize = MAP_RESOLUTION + 1;
+
+const HOLETAB_H: [u16; 4] = [0x1111, 0x2222, 0x4444, 0x8888];
+const HOLETAB_V: [u16; 4] = [0x000F, 0x00F0, 0x0F00, 0xF000];
+
+/// Where a position is relative to the liquid at it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiquidStatus {
+    NoWater,
+    AboveWater,
+    WaterWalk,
+    InWater,
+    UnderWater,
+}
+
+/// The liquid found by GridMap::get_liquid_status
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LiquidData {
+    /// LiquidType.dbc entry
+    pub entry: u32,
+    /// MAP_LIQUID_TYPE_* flags
+    pub type_flags: u8,
+    pub level: f32,
+    /// Ground height under the liquid
+    pub depth_level: f32,
+}
+
+enum Heights {
+    Flat,
+    Float { v9: Box<[f32]>, v8: Box<[f32]> },
+    Int16 { v9: Box<[u16]>, v8: Box<[u16]>, multiplier: f32 },
+    Int8 { v9: Box<[u8]>, v8: Box<[u8]>, multiplier: f32 },
+}
+
+struct Liquid {
+    /// Entry and flags of the whole grid when there are no per cell arrays
+    entry: u16,
+    flags: u8,
+    entries: Option<Box<[u16]>>,
+    type_flags: Option<Box<[u8]>>,
+    /// Wet rectangle, in height points
+    off_x: usize,
+    off_y: usize,
+    width: usize,
+    height: usize,
+    level: f32,
+    levels: Option<Box<[f32]>>,
+}
+
+/// Terrain of one grid
+pub struct GridMap {
+    grid_area: u16,
+    area_map: Option<Box<[u16]>>,
+    grid_height: f32,
+    grid_max_height: f32,
+    heights: Heights,
+    liquid: Option<Liquid>,
+    holes: Option<Box<[u16]>>,
+}
+
+/// Bounds checked little-endian reader over the file
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn at(data: &'a [u8], pos: u32) -> Self {
+        Reader { data, pos: pos as usize }
+    }
+
+    fn bytes(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self.pos.checked_add(count).filter(|&end| end <= self.data.len());
+        let Some(end) = end else {
+            anyhow::bail!(MangosError::Data(format!("map file truncated at byte {}", self.pos)));
+        };
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn magic(&mut self, expected: &[u8; 4], section: &str) -> anyhow::Result<()> {
+        if self.bytes(4)? != expected {
+            anyhow::bail!(MangosError::Data(format!("{} section has a wrong magic", section)));
+        }
+        Ok(())
+    }
+
+    fn u8_array(&mut self, count: usize) -> anyhow::Result<Box<[u8]>> {
+        Ok(self.bytes(count)?.into())
+    }
+
+    fn u16_array(&mut self, count: usize) -> anyhow::Result<Box<[u16]>> {
+        let bytes = self.bytes(count * 2)?;
+        Ok(bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect())
+    }
+
+    fn f32_array(&mut self, count: usize) -> anyhow::Result<Box<[f32]>> {
+        let bytes = self.bytes(count * 4)?;
+        Ok(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+    }
+}
+
+impl GridMap {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+        Self::from_bytes(&data).with_context(|| format!("cannot load {}", path.display()))
+    }
+
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        let mut header = Reader::at(data, 0);
+        header.magic(MAP_MAGIC, "file")?;
+        if header.bytes(4)? != MAP_VERSION_MAGIC {
+            anyhow::bail!(MangosError::Data("map file is from another extractor version, extract the maps again".into()));
+        }
+        let area_offset = header.u32()?;
+        let _area_size = header.u32()?;
+        let height_offset = header.u32()?;
+        let _height_size = header.u32()?;
+        let liquid_offset = header.u32()?;
+        let liquid_size = header.u32()?;
+        let holes_offset = header.u32()?;
+        let holes_size = header.u32()?;
+
+        let mut map = GridMap {
+            grid_area: 0,
+            area_map: None,
+            grid_height: INVALID_HEIGHT,
+            grid_max_height: INVALID_HEIGHT,
+            heights: Heights::Flat,
+            liquid: None,
+            holes: None,
+        };
+
+        if area_offset != 0 {
+            let mut r = Reader::at(data, area_offset);
+            r.magic(MAP_AREA_MAGIC, "area")?;
+            let flags = r.u16()?;
+            map.grid_area = r.u16()?;
+            if flags & MAP_AREA_NO_AREA == 0 {
+                map.area_map = Some(r.u16_array(CELLS * CELLS)?);
+            }
+        }
+
+        if height_offset != 0 {
+            let mut r = Reader::at(data, height_offset);
+            r.magic(MAP_HEIGHT_MAGIC, "height")?;
+            let flags = r.u32()?;
+            map.grid_height = r.f32()?;
+            map.grid_max_height = r.f32()?;
+            let (v9, v8) = (V9_SIZE * V9_SIZE, MAP_RESOLUTION * MAP_RESOLUTION);
+            let range = map.grid_max_height - map.grid_height;
+            map.heights = if flags & MAP_HEIGHT_NO_HEIGHT != 0 {
+                Heights::Flat
+            } else if flags & MAP_HEIGHT_AS_INT16 != 0 {
+                Heights::Int16 { v9: r.u16_array(v9)?, v8: r.u16_array(v8)?, multiplier: range / 65535.0 }
+            } else if flags & MAP_HEIGHT_AS_INT8 != 0 {
+                Heights::Int8 { v9: r.u8_array(v9)?, v8: r.u8_array(v8)?, multiplier: range / 255.0 }
+            } else {
+                Heights::Float { v9: r.f32_array(v9)?, v8: r.f32_array(v8)? }
+            };
+        }
+
+        if liquid_offset != 0 && liquid_size != 0 {
+            let mut r = Reader::at(data, liquid_offset);
+            r.magic(MAP_LIQUID_MAGIC, "liquid")?;
+            let flags = r.u8()?;
+            let mut liquid = Liquid {
+                flags: r.u8()?,
+                entry: r.u16()?,
+                entries: None,
+                type_flags: None,
+                off_x: r.u8()? as usize,
+                off_y: r.u8()? as usize,
+                width: r.u8()? as usize,
+                height: r.u8()? as usize,
+                level: r.f32()?,
+                levels: None,
+            };
+            if flags & MAP_LIQUID_NO_TYPE == 0 {
+                liquid.entries = Some(r.u16_array(CELLS * CELLS)?);
+                liquid.type_flags = Some(r.u8_array(CELLS * CELLS)?);
+            }
+            if flags & MAP_LIQUID_NO_HEIGHT == 0 {
+                liquid.levels = Some(r.f32_array(liquid.width * liquid.height)?);
+            }
+            map.liquid = Some(liquid);
+        }
+
+        if holes_offset != 0 && holes_size as usize >= CELLS * CELLS * 2 {
+            map.holes = Some(Reader::at(data, holes_offset).u16_array(CELLS * CELLS)?);
+        }
+
+        Ok(map)
+    }
+
+    /// Lowest and highest terrain height in the grid
+    pub fn height_range(&self) -> (f32, f32) {
+        (self.grid_height, self.grid_max_height)
+    }
+
+    /// Area flag at a world position (an AreaTable.dbc index)
+    pub fn get_area(&self, x: f32, y: f32) -> u16 {
+        let Some(area_map) = &self.area_map else {
+            return self.grid_area;
+        };
+        let (lx, ly) = cell_of(x, y);
+        area_map[lx * CELLS + ly]
+    }
+
+    fn is_hole(&self, row: usize, col: usize) -> bool {
+        let Some(holes) = &self.holes else {
+            return false;
+        };
+        let hole = holes[(row / 8) * CELLS + col / 8];
+        let hole_row = row % 8 / 2;
+        let hole_col = col % 8 / 2;
+        hole & HOLETAB_H[hole_col] & HOLETAB_V[hole_row] != 0
+    }
+
+    /// Terrain height at a world position; INVALID_HEIGHT over a hole
+    pub fn get_height(&self, x: f32, y: f32) -> f32 {
+        if let Heights::Flat = self.heights {
+            return self.grid_height;
+        }
+
+        let (x, y) = grid_point(x, y);
+        let (x_int, y_int) = (x as usize, y as usize);
+        let (x, y) = (x - x_int as f32, y - y_int as f32);
+        let (x_int, y_int) = (x_int & (MAP_RESOLUTION - 1), y_int & (MAP_RESOLUTION - 1));
+        if self.is_hole(x_int, y_int) {
+            return INVALID_HEIGHT;
+        }
+
+        let point = (x, y, x_int, y_int);
+        match &self.heights {
+            Heights::Flat => self.grid_height,
+            Heights::Float { v9, v8 } => interpolate(point, |i| v9[i], |i| v8[i]),
+            Heights::Int16 { v9, v8, multiplier } => {
+                interpolate(point, |i| v9[i] as f32, |i| v8[i] as f32) * multiplier + self.grid_height
+            }
+            Heights::Int8 { v9, v8, multiplier } => {
+                interpolate(point, |i| v9[i] as f32, |i| v8[i] as f32) * multiplier + self.grid_height
+            }
+        }
+    }
+
+    /// Liquid level at a world position; INVALID_HEIGHT outside the liquid
+    pub fn get_liquid_level(&self, x: f32, y: f32) -> f32 {
+        let Some(liquid) = &self.liquid else {
+            return INVALID_HEIGHT;
+        };
+        let Some(levels) = &liquid.levels else {
+            return liquid.level;
+        };
+        let (x, y) = grid_point(x, y);
+        match liquid_index(liquid, x as usize & (MAP_RESOLUTION - 1), y as usize & (MAP_RESOLUTION - 1)) {
+            Some(index) => levels[index],
+            None => INVALID_HEIGHT,
+        }
+    }
+
+    /// MAP_LIQUID_TYPE_* flags of the cell at a world position
+    pub fn get_terrain_type(&self, x: f32, y: f32) -> u8 {
+        let Some(liquid) = &self.liquid else {
+            return MAP_LIQUID_TYPE_NO_WATER;
+        };
+        let Some(type_flags) = &liquid.type_flags else {
+            return liquid.flags;
+        };
+        let (lx, ly) = cell_of(x, y);
+        type_flags[lx * CELLS + ly]
+    }
+
+    /// Liquid at a world position of height `z`, limited to `req_type`
+    /// flags (0 for any liquid)
+    pub fn get_liquid_status(&self, x: f32, y: f32, z: f32, req_type: u8) -> (LiquidStatus, Option<LiquidData>) {
+        let Some(liquid) = &self.liquid else {
+            return (LiquidStatus::NoWater, None);
+        };
+        let (cx, cy) = grid_point(x, y);
+        let (x_int, y_int) = (cx as usize & (MAP_RESOLUTION - 1), cy as usize & (MAP_RESOLUTION - 1));
+        let cell = (x_int >> 3) * CELLS + (y_int >> 3);
+        let type_flags = liquid.type_flags.as_ref().map_or(liquid.flags, |flags| flags[cell]);
+        let entry = liquid.entries.as_ref().map_or(liquid.entry, |entries| entries[cell]);
+        if type_flags == MAP_LIQUID_TYPE_NO_WATER || (req_type != 0 && req_type & type_flags == 0) {
+            return (LiquidStatus::NoWater, None);
+        }
+
+        let Some(index) = liquid_index(liquid, x_int, y_int) else {
+            return (LiquidStatus::NoWater, None);
+        };
+        let level = liquid.levels.as_ref().map_or(liquid.level, |levels| levels[index]);
+        let ground = self.get_height(x, y);
+        if level < ground || z < ground - 2.0 {
+            return (LiquidStatus::NoWater, None);
+        }
+
+        let data = LiquidData { entry: entry as u32, type_flags, level, depth_level: ground };
+        let delta = level - z;
+        let status = if delta > 2.0 {
+            LiquidStatus::UnderWater
+        } else if delta > 0.0 {
+            LiquidStatus::InWater
+        } else if delta > -1.0 {
+            LiquidStatus::WaterWalk
+        } else {
+            LiquidStatus::AboveWater
+        };
+        (status, Some(data))
+    }
+}
+
+/// Height inside the square at (`x_int`, `y_int`), `x`/`y` being the
+/// fractions; the square is split into four triangles around its centre
+fn interpolate(
+    (x, y, x_int, y_int): (f32, f32, usize, usize),
+    v9: impl Fn(usize) -> f32,
+    v8: impl Fn(usize) -> f32,
+) -> f32 {
+    let h1 = || v9(x_int * V9_SIZE + y_int);
+    let h2 = || v9((x_int + 1) * V9_SIZE + y_int);
+    let h3 = || v9(x_int * V9_SIZE + y_int + 1);
+    let h4 = || v9((x_int + 1) * V9_SIZE + y_int + 1);
+    let h5 = 2.0 * v8(x_int * MAP_RESOLUTION + y_int);
+    let (a, b, c) = if x + y < 1.0 {
+        if x > y {
+            (h2() - h1(), h5 - h1() - h2(), h1())
+        } else {
+            (h5 - h1() - h3(), h3() - h1(), h1())
+        }
+    } else if x > y {
+        (h2() + h4() - h5, h4() - h2(), h5 - h4())
+    } else {
+        (h4() - h3(), h3() + h4() - h5, h5 - h4())
+    };
+    a * x + b * y + c
+}
+
+/// Position in height points from the grid's far corner (fraction kept)
+fn grid_point(x: f32, y: f32) -> (f32, f32) {
+    let res = MAP_RESOLUTION as f32;
+    (res * grid_offset(x), res * grid_offset(y))
+}
+
+/// Cell (16x16 per grid) at a world position
+fn cell_of(x: f32, y: f32) -> (usize, usize) {
+    let cx = CELLS as f32 * grid_offset(x);
+    let cy = CELLS as f32 * grid_offset(y);
+    (cx as usize & (CELLS - 1), cy as usize & (CELLS - 1))
+}
+
+/// Index into the liquid levels for a height point, if it is in the wet rectangle
+fn liquid_index(liquid: &Liquid, x_int: usize, y_int: usize) -> Option<usize> {
+    let lx = x_int.checked_sub(liquid.off_y).filter(|&lx| lx < liquid.height)?;
+    let ly = y_int.checked_sub(liquid.off_x).filter(|&ly| ly < liquid.width)?;
+    Some(lx * liquid.width + ly)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::geometry::SIZE_OF_GRIDS;
+
+    /// A .map file laid out like the extractor writes it
+    pub fn build_map(area: Option<&[u16]>, heights: Option<(&[f32], &[f32])>, liquid: Option<(u8, f32)>, holes: &[u16]) -> Vec<u8> {
+        let mut area_section = b"AREA".to_vec();
+        match area {
+            Some(flags) => {
+                area_section.extend_from_slice(&0u16.to_le_bytes());
+                area_section.extend_from_slice(&0u16.to_le_bytes());
+                flags.iter().for_each(|f| area_section.extend_from_slice(&f.to_le_bytes()));
+            }
+            None => {
+                area_section.extend_from_slice(&MAP_AREA_NO_AREA.to_le_bytes());
+                area_section.extend_from_slice(&12u16.to_le_bytes());
+            }
+        }
+
+        let mut height_section = b"MHGT".to_vec();
+        match heights {
+            Some((v9, v8)) => {
+                let min = v9.iter().chain(v8).copied().fold(f32::MAX, f32::min);
+                let max = v9.iter().chain(v8).copied().fold(f32::MIN, f32::max);
+                height_section.extend_from_slice(&0u32.to_le_bytes());
+                height_section.extend_from_slice(&min.to_le_bytes());
+                height_section.extend_from_slice(&max.to_le_bytes());
+                v9.iter().chain(v8).for_each(|h| height_section.extend_from_slice(&h.to_le_bytes()));
+            }
+            None => {
+                height_section.extend_from_slice(&MAP_HEIGHT_NO_HEIGHT.to_le_bytes());
+                height_section.extend_from_slice(&50.0f32.to_le_bytes());
+                height_section.extend_from_slice(&50.0f32.to_le_bytes());
+            }
+        }
+
+        let mut liquid_section = Vec::new();
+        if let Some((flags, level)) = liquid {
+            liquid_section.extend_from_slice(b"MLIQ");
+            liquid_section.extend_from_slice(&[MAP_LIQUID_NO_TYPE | MAP_LIQUID_NO_HEIGHT, flags]);
+            liquid_section.extend_from_slice(&2u16.to_le_bytes());
+            liquid_section.extend_from_slice(&[0, 0, 129, 129]);
+            liquid_section.extend_from_slice(&level.to_le_bytes());
+        }
+
+        let area_offset = 40u32;
+        let height_offset = area_offset + area_section.len() as u32;
+        let liquid_offset = if liquid_section.is_empty() { 0 } else { height_offset + height_section.len() as u32 };
+        let holes_offset = height_offset + height_section.len() as u32 + liquid_section.len() as u32;
+
+        let mut data = b"MAPSs1.4".to_vec();
+        for value in [
+            area_offset,
+            area_section.len() as u32,
+            height_offset,
+            height_section.len() as u32,
+            liquid_offset,
+            liquid_section.len() as u32,
+            holes_offset,
+            holes.len() as u32 * 2,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend(area_section);
+        data.extend(height_section);
+        data.extend(liquid_section);
+        holes.iter().for_each(|h| data.extend_from_slice(&h.to_le_bytes()));
+        data
+    }
+
+    /// A slope rising 1 yard per height point along the world X axis
+    pub fn slope() -> (Vec<f32>, Vec<f32>) {
+        let v9 = (0..V9_SIZE * V9_SIZE).map(|i| (i / V9_SIZE) as f32).collect();
+        let v8 = (0..MAP_RESOLUTION * MAP_RESOLUTION).map(|i| (i / MAP_RESOLUTION) as f32 + 0.5).collect();
+        (v9, v8)
+    }
+
+    /// World coordinate of height point `point` in grid 32 (which starts at 0)
+    fn world(point: f32) -> f32 {
+        -point * SIZE_OF_GRIDS / MAP_RESOLUTION as f32
+    }
+
+    #[test]
+    fn test_flat_grid() {
+        let map = GridMap::from_bytes(&build_map(None, None, None, &[0; 256])).unwrap();
+        assert_eq!(map.get_height(-100.0, -100.0), 50.0);
+        assert_eq!(map.get_area(-100.0, -100.0), 12);
+        assert_eq!(map.get_liquid_level(-100.0, -100.0), INVALID_HEIGHT);
+        assert_eq!(map.get_liquid_status(-100.0, -100.0, 50.0, 0).0, LiquidStatus::NoWater);
+    }
+
+    #[test]
+    fn test_interpolated_height() {
+        let (v9, v8) = slope();
+        let map = GridMap::from_bytes(&build_map(None, Some((&v9, &v8)), None, &[0; 256])).unwrap();
+        for point in [0.0, 10.25, 64.5, 127.75] {
+            let height = map.get_height(world(point), world(3.4));
+            assert!((height - point).abs() < 1e-3, "{} at {}", height, point);
+        }
+    }
+
+    #[test]
+    fn test_area_and_holes() {
+        let mut areas = [0u16; 256];
+        areas[16 + 2] = 41;
+        let mut holes = [0u16; 256];
+        holes[0] = 0x0001;
+        let (v9, v8) = slope();
+        let map = GridMap::from_bytes(&build_map(Some(&areas), Some((&v9, &v8)), None, &holes)).unwrap();
+
+        assert_eq!(map.get_area(world(8.5), world(16.5)), 41);
+        assert_eq!(map.get_area(world(0.5), world(0.5)), 0);
+        assert_eq!(map.get_height(world(0.5), world(0.5)), INVALID_HEIGHT);
+        assert_ne!(map.get_height(world(2.5), world(0.5)), INVALID_HEIGHT);
+    }
+
+    #[test]
+    fn test_liquid_status() {
+        let map = GridMap::from_bytes(&build_map(None, None, Some((MAP_LIQUID_TYPE_WATER, 55.0)), &[0; 256])).unwrap();
+        assert_eq!(map.get_liquid_level(-10.0, -10.0), 55.0);
+        assert_eq!(map.get_terrain_type(-10.0, -10.0), MAP_LIQUID_TYPE_WATER);
+
+        let (status, data) = map.get_liquid_status(-10.0, -10.0, 51.0, MAP_ALL_LIQUIDS);
+        assert_eq!(status, LiquidStatus::UnderWater);
+        assert_eq!(data.unwrap(), LiquidData { entry: 2, type_flags: MAP_LIQUID_TYPE_WATER, level: 55.0, depth_level: 50.0 });
+        assert_eq!(map.get_liquid_status(-10.0, -10.0, 54.0, 0).0, LiquidStatus::InWater);
+        assert_eq!(map.get_liquid_status(-10.0, -10.0, 55.5, 0).0, LiquidStatus::WaterWalk);
+        assert_eq!(map.get_liquid_status(-10.0, -10.0, 60.0, 0).0, LiquidStatus::AboveWater);
+        assert_eq!(map.get_liquid_status(-10.0, -10.0, 51.0, MAP_LIQUID_TYPE_MAGMA).0, LiquidStatus::NoWater);
+    }
+
+    #[test]
+    fn test_rejects_old_version() {
+        let mut data = build_map(None, None, None, &[]);
+        data[4..8].copy_from_slice(b"s1.3");
+        assert!(GridMap::from_bytes(&data).is_err());
+        assert!(GridMap::from_bytes(&data[..20]).is_err());
+    }
+}
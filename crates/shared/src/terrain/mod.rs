@@ -0,0 +1,160 @@
+// Terrain module - Runtime access to extracted map data
+// Rust equivalent of GridMap.h/cpp (GridMap and TerrainInfo)
+//
+// TerrainInfo serves the height, liquid and area queries of one map from the
+// .map files in <DataDir>/maps, loading each grid the first time a position
+// in it is asked about.
+
+mod grid_map;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::dbc::{AreaTableEntry, DbcStorage};
+use crate::geometry::world_to_grid;
+
+pub use grid_map::{
+    GridMap, LiquidData, LiquidStatus, MAP_ALL_LIQUIDS, MAP_LIQUID_TYPE_DEEP_WATER, MAP_LIQUID_TYPE_MAGMA,
+    MAP_LIQUID_TYPE_NO_WATER, MAP_LIQUID_TYPE_OCEAN, MAP_LIQUID_TYPE_SLIME, MAP_LIQUID_TYPE_WATER,
+};
+
+/// Height points per grid side
+pub const MAP_RESOLUTION: usize = 128;
+/// Height reported where there is no terrain (holes, missing grids)
+pub const INVALID_HEIGHT: f32 = -100000.0;
+
+/// File name of a grid, e.g. "0003248.map" for map 0, grid 32/48
+pub fn grid_file_name(map_id: u32, gx: u32, gy: u32) -> String {
+    format!("{:03}{:02}{:02}.map", map_id, gx, gy)
+}
+
+/// Loaded grids by grid coordinates; None for grids without a .map file
+type GridCache = HashMap<(u32, u32), Option<Arc<GridMap>>>;
+
+/// Terrain of one map, grids loaded on demand
+pub struct TerrainInfo {
+    map_id: u32,
+    maps_dir: PathBuf,
+    grids: RwLock<GridCache>,
+}
+
+impl TerrainInfo {
+    /// Terrain of `map_id` read from `<data_dir>/maps`
+    pub fn new(data_dir: &Path, map_id: u32) -> Self {
+        TerrainInfo { map_id, maps_dir: data_dir.join("maps"), grids: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn map_id(&self) -> u32 {
+        self.map_id
+    }
+
+    /// Grid holding a world position, loading it on first use
+    ///
+    /// A grid that fails to load is reported once and then treated as missing.
+    pub fn grid(&self, x: f32, y: f32) -> Option<Arc<GridMap>> {
+        let key = world_to_grid(x, y)?;
+        if let Some(grid) = self.grids.read().get(&key) {
+            return grid.clone();
+        }
+
+        let path = self.maps_dir.join(grid_file_name(self.map_id, key.0, key.1));
+        let grid = if path.exists() {
+            match GridMap::load(&path) {
+                Ok(grid) => Some(Arc::new(grid)),
+                Err(e) => {
+                    tracing::error!("{:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        self.grids.write().entry(key).or_insert(grid).clone()
+    }
+
+    /// Drop a loaded grid, e.g. once no player is near it
+    pub fn unload_grid(&self, gx: u32, gy: u32) {
+        self.grids.write().remove(&(gx, gy));
+    }
+
+    pub fn loaded_grids(&self) -> usize {
+        self.grids.read().values().filter(|grid| grid.is_some()).count()
+    }
+
+    /// Terrain height, INVALID_HEIGHT without map data or over a hole
+    pub fn get_height(&self, x: f32, y: f32) -> f32 {
+        self.grid(x, y).map_or(INVALID_HEIGHT, |grid| grid.get_height(x, y))
+    }
+
+    /// Liquid level, INVALID_HEIGHT where there is no liquid
+    pub fn get_liquid_level(&self, x: f32, y: f32) -> f32 {
+        self.grid(x, y).map_or(INVALID_HEIGHT, |grid| grid.get_liquid_level(x, y))
+    }
+
+    pub fn get_liquid_status(&self, x: f32, y: f32, z: f32, req_type: u8) -> (LiquidStatus, Option<LiquidData>) {
+        self.grid(x, y).map_or((LiquidStatus::NoWater, None), |grid| grid.get_liquid_status(x, y, z, req_type))
+    }
+
+    /// Area flag (AreaTable.dbc index) at a position, 0 without map data
+    pub fn get_area_flag(&self, x: f32, y: f32) -> u16 {
+        self.grid(x, y).map_or(0, |grid| grid.get_area(x, y))
+    }
+
+    /// AreaTable.dbc ID of the area at a position, 0 if unknown
+    pub fn get_area_id(&self, x: f32, y: f32, areas: &DbcStorage<AreaTableEntry>) -> u32 {
+        match self.get_area_flag(x, y) {
+            0 => 0,
+            flag => areas.lookup_entry(flag as u32).map_or(0, |area| area.id),
+        }
+    }
+
+    /// Zone holding the area at a position, 0 if unknown
+    pub fn get_zone_id(&self, x: f32, y: f32, areas: &DbcStorage<AreaTableEntry>) -> u32 {
+        match self.get_area_flag(x, y) {
+            0 => 0,
+            flag => areas.lookup_entry(flag as u32).map_or(0, |area| if area.zone != 0 { area.zone } else { area.id }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::SIZE_OF_GRIDS;
+    use grid_map::tests::{build_map, slope};
+
+    #[test]
+    fn test_grid_file_name() {
+        // Stormwind
+        let (gx, gy) = world_to_grid(-8913.0, 554.0).unwrap();
+        assert_eq!(grid_file_name(0, gx, gy), "0004830.map");
+    }
+
+    #[test]
+    fn test_terrain_loads_grids() {
+        let dir = std::env::temp_dir().join(format!("mangos_terrain_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("maps")).unwrap();
+        let (v9, v8) = slope();
+        std::fs::write(dir.join("maps").join(grid_file_name(1, 32, 32)), build_map(None, Some((&v9, &v8)), None, &[0; 256]))
+            .unwrap();
+        std::fs::write(dir.join("maps").join(grid_file_name(1, 32, 33)), b"MAPSs0.9").unwrap();
+
+        let terrain = TerrainInfo::new(&dir, 1);
+        let x = -10.0 * SIZE_OF_GRIDS / MAP_RESOLUTION as f32;
+        assert!((terrain.get_height(x, -5.0) - 10.0).abs() < 1e-3);
+        assert_eq!(terrain.get_area_flag(x, -5.0), 12);
+        assert_eq!(terrain.loaded_grids(), 1);
+
+        // Missing and broken grids have no terrain
+        assert_eq!(terrain.get_height(100.0, 100.0), INVALID_HEIGHT);
+        assert_eq!(terrain.get_height(-5.0, -600.0), INVALID_HEIGHT);
+        assert_eq!(terrain.loaded_grids(), 1);
+
+        terrain.unload_grid(32, 32);
+        assert_eq!(terrain.loaded_grids(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -10,6 +10,24 @@ use std::io::Cursor;
 pub struct ByteBuffer {
     data: Vec<u8>,
     read_pos: usize,
+    bits: BitCursor,
+}
+
+/// State of the bit reader/writer
+///
+/// Bits are packed most significant first. A byte written or read through the
+/// regular API ends the current bit byte, like FlushBits()/ResetBitReader()
+/// in later C++ cores.
+#[derive(Debug, Clone, Copy, Default)]
+struct BitCursor {
+    /// Index of the byte being filled by write_bit and the bits still free in it
+    write_index: usize,
+    write_free: u8,
+    /// Byte being consumed by read_bit, the bits left in it and the read
+    /// position right after it
+    read_value: u8,
+    read_left: u8,
+    read_end: usize,
 }
 
 impl Default for ByteBuffer {
@@ -24,6 +42,7 @@ impl ByteBuffer {
         ByteBuffer {
             data: Vec::new(),
             read_pos: 0,
+            bits: BitCursor::default(),
         }
     }
 
@@ -32,6 +51,16 @@ impl ByteBuffer {
         ByteBuffer {
             data: Vec::with_capacity(capacity),
             read_pos: 0,
+            bits: BitCursor::default(),
+        }
+    }
+
+    /// Wrap received packet data for reading, starting at position 0
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        ByteBuffer {
+            data,
+            read_pos: 0,
+            bits: BitCursor::default(),
         }
     }
 
@@ -50,6 +79,16 @@ impl ByteBuffer {
         self.read_pos
     }
 
+    /// Move the read position, clamped to the end of the buffer
+    pub fn set_read_pos(&mut self, pos: usize) {
+        self.read_pos = pos.min(self.data.len());
+    }
+
+    /// Number of bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.read_pos
+    }
+
     /// Get a pointer to the raw contents
     pub fn contents(&self) -> &[u8] {
         &self.data
@@ -64,6 +103,7 @@ impl ByteBuffer {
     pub fn clear(&mut self) {
         self.data.clear();
         self.read_pos = 0;
+        self.bits = BitCursor::default();
     }
 
     // ---- Write operations (append) ----
@@ -104,16 +144,81 @@ impl ByteBuffer {
         self.data.push(0); // null terminator
     }
 
+    /// Write a packed GUID: a mask byte followed by the non-zero bytes of the GUID
+    pub fn write_packed_guid(&mut self, guid: u64) {
+        let mask_pos = self.data.len();
+        self.data.push(0);
+        for (i, byte) in guid.to_le_bytes().into_iter().enumerate() {
+            if byte != 0 {
+                self.data[mask_pos] |= 1 << i;
+                self.data.push(byte);
+            }
+        }
+    }
+
+    /// Write a single bit
+    pub fn write_bit(&mut self, bit: bool) {
+        if self.bits.write_free == 0 || self.bits.write_index + 1 != self.data.len() {
+            self.bits.write_index = self.data.len();
+            self.bits.write_free = 8;
+            self.data.push(0);
+        }
+        self.bits.write_free -= 1;
+        if bit {
+            self.data[self.bits.write_index] |= 1 << self.bits.write_free;
+        }
+    }
+
+    /// Write the lowest `count` bits of `value`, most significant first
+    pub fn write_bits(&mut self, value: u32, count: u32) {
+        for i in (0..count.min(32)).rev() {
+            self.write_bit((value >> i) & 1 != 0);
+        }
+    }
+
+    /// Pad the current bit byte so the next bit starts a new byte
+    pub fn flush_bits(&mut self) {
+        self.bits.write_free = 0;
+    }
+
     // ---- Read operations ----
 
-    /// Read a u8
-    pub fn read_u8(&mut self) -> Result<u8, std::io::Error> {
-        if self.read_pos >= self.data.len() {
+    /// Check that `count` more bytes can be read
+    pub fn check_remaining(&self, count: usize) -> Result<(), std::io::Error> {
+        if count > self.remaining() {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::UnexpectedEof,
-                "ByteBuffer read past end",
+                format!(
+                    "ByteBuffer read of {} bytes at position {} past end (size {})",
+                    count,
+                    self.read_pos,
+                    self.data.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that exactly `count` bytes are left to read, for packets whose
+    /// size is fixed by their fields
+    pub fn check_exact(&self, count: usize) -> Result<(), std::io::Error> {
+        if count != self.remaining() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "ByteBuffer has {} bytes left at position {}, expected {}",
+                    self.remaining(),
+                    self.read_pos,
+                    count
+                ),
             ));
         }
+        Ok(())
+    }
+
+    /// Read a u8
+    pub fn read_u8(&mut self) -> Result<u8, std::io::Error> {
+        self.check_remaining(1)?;
         let val = self.data[self.read_pos];
         self.read_pos += 1;
         Ok(val)
@@ -121,12 +226,7 @@ impl ByteBuffer {
 
     /// Read a u16 (little-endian)
     pub fn read_u16(&mut self) -> Result<u16, std::io::Error> {
-        if self.read_pos + 2 > self.data.len() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "ByteBuffer read past end",
-            ));
-        }
+        self.check_remaining(2)?;
         let mut cursor = Cursor::new(&self.data[self.read_pos..]);
         let val = cursor.read_u16::<LittleEndian>()?;
         self.read_pos += 2;
@@ -135,12 +235,7 @@ impl ByteBuffer {
 
     /// Read a u32 (little-endian)
     pub fn read_u32(&mut self) -> Result<u32, std::io::Error> {
-        if self.read_pos + 4 > self.data.len() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "ByteBuffer read past end",
-            ));
-        }
+        self.check_remaining(4)?;
         let mut cursor = Cursor::new(&self.data[self.read_pos..]);
         let val = cursor.read_u32::<LittleEndian>()?;
         self.read_pos += 4;
@@ -149,12 +244,7 @@ impl ByteBuffer {
 
     /// Read a u64 (little-endian)
     pub fn read_u64(&mut self) -> Result<u64, std::io::Error> {
-        if self.read_pos + 8 > self.data.len() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "ByteBuffer read past end",
-            ));
-        }
+        self.check_remaining(8)?;
         let mut cursor = Cursor::new(&self.data[self.read_pos..]);
         let val = cursor.read_u64::<LittleEndian>()?;
         self.read_pos += 8;
@@ -163,12 +253,7 @@ impl ByteBuffer {
 
     /// Read an f32 (little-endian)
     pub fn read_f32(&mut self) -> Result<f32, std::io::Error> {
-        if self.read_pos + 4 > self.data.len() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "ByteBuffer read past end",
-            ));
-        }
+        self.check_remaining(4)?;
         let mut cursor = Cursor::new(&self.data[self.read_pos..]);
         let val = cursor.read_f32::<LittleEndian>()?;
         self.read_pos += 4;
@@ -188,25 +273,93 @@ impl ByteBuffer {
         Ok(s)
     }
 
-    /// Read N bytes into a slice
-    pub fn read_bytes(&mut self, count: usize) -> Result<Vec<u8>, std::io::Error> {
-        if self.read_pos + count > self.data.len() {
+    /// Read a null-terminated string, failing if the terminator is missing
+    pub fn read_cstring(&mut self) -> Result<String, std::io::Error> {
+        let Some(len) = self.data[self.read_pos..].iter().position(|&b| b == 0) else {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::UnexpectedEof,
-                "ByteBuffer read past end",
+                format!("ByteBuffer string at position {} is not null-terminated", self.read_pos),
             ));
-        }
+        };
+        let s = String::from_utf8_lossy(&self.data[self.read_pos..self.read_pos + len]).to_string();
+        self.read_pos += len + 1;
+        Ok(s)
+    }
+
+    /// Read a fixed-size byte array
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], std::io::Error> {
+        self.check_remaining(N)?;
+        let mut array = [0u8; N];
+        array.copy_from_slice(&self.data[self.read_pos..self.read_pos + N]);
+        self.read_pos += N;
+        Ok(array)
+    }
+
+    /// Read N bytes into a slice
+    pub fn read_bytes(&mut self, count: usize) -> Result<Vec<u8>, std::io::Error> {
+        self.check_remaining(count)?;
         let bytes = self.data[self.read_pos..self.read_pos + count].to_vec();
         self.read_pos += count;
         Ok(bytes)
     }
 
+    /// Read a packed GUID written by [`ByteBuffer::write_packed_guid`]
+    pub fn read_packed_guid(&mut self) -> Result<u64, std::io::Error> {
+        let start = self.read_pos;
+        let mask = self.read_u8()?;
+        self.check_remaining(mask.count_ones() as usize).inspect_err(|_| self.read_pos = start)?;
+        let mut bytes = [0u8; 8];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            if mask & (1 << i) != 0 {
+                *byte = self.read_u8()?;
+            }
+        }
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Read a single bit
+    pub fn read_bit(&mut self) -> Result<bool, std::io::Error> {
+        if self.bits.read_left == 0 || self.bits.read_end != self.read_pos {
+            self.bits.read_value = self.read_u8()?;
+            self.bits.read_left = 8;
+            self.bits.read_end = self.read_pos;
+        }
+        self.bits.read_left -= 1;
+        Ok((self.bits.read_value >> self.bits.read_left) & 1 != 0)
+    }
+
+    /// Read `count` bits, most significant first
+    pub fn read_bits(&mut self, count: u32) -> Result<u32, std::io::Error> {
+        let mut value = 0u32;
+        for _ in 0..count.min(32) {
+            value = (value << 1) | self.read_bit()? as u32;
+        }
+        Ok(value)
+    }
+
+    /// Drop the rest of the current bit byte so the next bit starts a new byte
+    pub fn reset_bit_reader(&mut self) {
+        self.bits.read_left = 0;
+    }
+
     /// Skip N bytes in the read position
     pub fn read_skip(&mut self, count: usize) {
         self.read_pos = (self.read_pos + count).min(self.data.len());
     }
 }
 
+impl From<Vec<u8>> for ByteBuffer {
+    fn from(data: Vec<u8>) -> Self {
+        ByteBuffer::from_vec(data)
+    }
+}
+
+impl From<&[u8]> for ByteBuffer {
+    fn from(data: &[u8]) -> Self {
+        ByteBuffer::from_vec(data.to_vec())
+    }
+}
+
 /// Implement the C++ << operator style for building packets
 /// Usage: buf << 0u8 << 0u16 << "hello"
 impl std::fmt::Display for ByteBuffer {
@@ -240,6 +393,75 @@ mod tests {
         assert_eq!(buf.read_string().unwrap(), "hello");
     }
 
+    #[test]
+    fn test_read_packet_fields() {
+        let mut buf = ByteBuffer::from(&[0x01, 0x34, 0x12, b'W', b'o', b'W', 0, 0xAA, 0xBB, b'x'][..]);
+        assert_eq!(buf.read_u8().unwrap(), 1);
+        assert_eq!(buf.read_u16().unwrap(), 0x1234);
+        assert_eq!(buf.read_cstring().unwrap(), "WoW");
+        assert_eq!(buf.read_array::<2>().unwrap(), [0xAA, 0xBB]);
+        assert_eq!(buf.remaining(), 1);
+
+        // Failed reads leave the position untouched
+        assert!(buf.read_u32().is_err());
+        assert!(buf.read_cstring().is_err());
+        assert_eq!(buf.read_pos(), 9);
+        assert_eq!(buf.read_bytes(1).unwrap(), b"x");
+        assert!(buf.read_u8().is_err());
+
+        buf.set_read_pos(3);
+        assert_eq!(buf.read_string().unwrap(), "WoW");
+    }
+
+    #[test]
+    fn test_length_checks() {
+        let mut buf = ByteBuffer::from(&[1, 2, 3][..]);
+        assert!(buf.check_remaining(3).is_ok());
+        assert!(buf.check_remaining(4).is_err());
+        assert!(buf.check_exact(3).is_ok());
+        assert!(buf.check_exact(2).is_err());
+        assert!(buf.check_exact(4).is_err());
+
+        buf.read_u8().unwrap();
+        assert!(buf.check_exact(2).is_ok());
+        assert!(buf.check_remaining(3).is_err());
+    }
+
+    #[test]
+    fn test_packed_guid() {
+        let mut buf = ByteBuffer::new();
+        buf.write_packed_guid(0x0000_0000_0000_0000);
+        buf.write_packed_guid(0xF130_0000_0000_1A2B);
+        assert_eq!(buf.contents(), &[0x00, 0xC3, 0x2B, 0x1A, 0x30, 0xF1]);
+        assert_eq!(buf.read_packed_guid().unwrap(), 0);
+        assert_eq!(buf.read_packed_guid().unwrap(), 0xF130_0000_0000_1A2B);
+
+        // Mask announcing more bytes than present
+        let mut buf = ByteBuffer::from(&[0x03, 0x01][..]);
+        assert!(buf.read_packed_guid().is_err());
+        assert_eq!(buf.read_pos(), 0);
+    }
+
+    #[test]
+    fn test_bit_stream() {
+        let mut buf = ByteBuffer::new();
+        buf.write_bit(true);
+        buf.write_bits(0b101, 3);
+        buf.write_u8(0xFF);
+        buf.write_bits(0x1FF, 9);
+        buf.flush_bits();
+        buf.write_bit(true);
+        assert_eq!(buf.contents(), &[0b1101_0000, 0xFF, 0xFF, 0b1000_0000, 0b1000_0000]);
+
+        assert!(buf.read_bit().unwrap());
+        assert_eq!(buf.read_bits(3).unwrap(), 0b101);
+        assert_eq!(buf.read_u8().unwrap(), 0xFF);
+        assert_eq!(buf.read_bits(9).unwrap(), 0x1FF);
+        buf.reset_bit_reader();
+        assert!(buf.read_bit().unwrap());
+        assert!(buf.read_bits(8).is_err());
+    }
+
     #[test]
     fn test_append_bytes() {
         let mut buf = ByteBuffer::new();
@@ -1,4 +1,8 @@
 // Utility module
 pub mod byte_buffer;
+pub mod object_guid;
+pub mod time;
 
 pub use byte_buffer::ByteBuffer;
+pub use object_guid::{HighGuid, ObjectGuid, PackedGuid, TypeId};
+pub use time::{local_datetime, secs_to_time_string, time_string_to_secs, unix_now};
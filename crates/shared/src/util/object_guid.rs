@@ -0,0 +1,329 @@
+// ObjectGuid - 64-bit identifier of every world object
+// Rust equivalent of ObjectGuid.h/cpp (TBC)
+//
+// The high 16 bits hold the HighGuid, the kind of object. Creatures, pets,
+// game objects and transports keep their template entry in bits 24..47 and a
+// 24-bit counter below it; every other kind has a 32-bit counter. Players
+// have HighGuid 0, so a character's guid is its characters.guid. On the wire
+// guids usually go packed: a mask byte followed by the non-zero bytes.
+
+use std::fmt;
+
+use super::ByteBuffer;
+
+/// Kind of object in the high 16 bits of a guid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum HighGuid {
+    /// Also containers, which share the value in TBC
+    Item = 0x4700,
+    Player = 0x0000,
+    GameObject = 0xF110,
+    Transport = 0xF120,
+    Unit = 0xF130,
+    Pet = 0xF140,
+    DynamicObject = 0xF100,
+    Corpse = 0xF101,
+    MoTransport = 0x1FC0,
+}
+
+impl HighGuid {
+    pub const CONTAINER: HighGuid = HighGuid::Item;
+
+    pub fn from_raw(raw: u16) -> Option<Self> {
+        Some(match raw {
+            0x4700 => HighGuid::Item,
+            0x0000 => HighGuid::Player,
+            0xF110 => HighGuid::GameObject,
+            0xF120 => HighGuid::Transport,
+            0xF130 => HighGuid::Unit,
+            0xF140 => HighGuid::Pet,
+            0xF100 => HighGuid::DynamicObject,
+            0xF101 => HighGuid::Corpse,
+            0x1FC0 => HighGuid::MoTransport,
+            _ => return None,
+        })
+    }
+
+    /// Whether guids of this kind carry an entry
+    pub fn has_entry(self) -> bool {
+        matches!(self, HighGuid::GameObject | HighGuid::Transport | HighGuid::Unit | HighGuid::Pet)
+    }
+
+    /// Largest counter a guid of this kind can hold
+    pub fn max_counter(self) -> u32 {
+        if self.has_entry() { 0x00FF_FFFF } else { u32::MAX }
+    }
+
+    /// Object type of guids of this kind
+    pub fn type_id(self) -> TypeId {
+        match self {
+            HighGuid::Item => TypeId::Item,
+            HighGuid::Unit | HighGuid::Pet => TypeId::Unit,
+            HighGuid::Player => TypeId::Player,
+            HighGuid::GameObject | HighGuid::Transport | HighGuid::MoTransport => TypeId::GameObject,
+            HighGuid::DynamicObject => TypeId::DynamicObject,
+            HighGuid::Corpse => TypeId::Corpse,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HighGuid::Item => "Item",
+            HighGuid::Player => "Player",
+            HighGuid::GameObject => "Gameobject",
+            HighGuid::Transport => "Transport",
+            HighGuid::Unit => "Creature",
+            HighGuid::Pet => "Pet",
+            HighGuid::DynamicObject => "DynObject",
+            HighGuid::Corpse => "Corpse",
+            HighGuid::MoTransport => "MoTransport",
+        }
+    }
+}
+
+/// Object type as sent in update packets (TypeID)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum TypeId {
+    Object = 0,
+    Item = 1,
+    Container = 2,
+    Unit = 3,
+    Player = 4,
+    GameObject = 5,
+    DynamicObject = 6,
+    Corpse = 7,
+}
+
+/// Identifier of a world object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ObjectGuid(u64);
+
+impl ObjectGuid {
+    pub const EMPTY: ObjectGuid = ObjectGuid(0);
+
+    pub const fn from_raw(raw: u64) -> Self {
+        ObjectGuid(raw)
+    }
+
+    /// Guid of a kind without entry; a zero counter gives the empty guid
+    pub fn new(high: HighGuid, counter: u32) -> Self {
+        Self::with_entry(high, 0, counter)
+    }
+
+    /// Guid with an entry, which is dropped for kinds that carry none
+    ///
+    /// The counter is cut to what the kind can hold, as in the C++ core;
+    /// callers allocating counters check max_counter first.
+    pub fn with_entry(high: HighGuid, entry: u32, counter: u32) -> Self {
+        if counter == 0 {
+            return Self::EMPTY;
+        }
+        let low = if high.has_entry() {
+            (u64::from(entry & 0x00FF_FFFF) << 24) | u64::from(counter & 0x00FF_FFFF)
+        } else {
+            u64::from(counter)
+        };
+        ObjectGuid((u64::from(high as u16) << 48) | low)
+    }
+
+    pub fn raw(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Raw high 16 bits, also for values no HighGuid matches
+    pub fn high_raw(self) -> u16 {
+        (self.0 >> 48) as u16
+    }
+
+    pub fn high(self) -> Option<HighGuid> {
+        HighGuid::from_raw(self.high_raw())
+    }
+
+    pub fn has_entry(self) -> bool {
+        self.high().is_some_and(HighGuid::has_entry)
+    }
+
+    /// Template entry, 0 for kinds without one
+    pub fn entry(self) -> u32 {
+        if self.has_entry() { ((self.0 >> 24) & 0x00FF_FFFF) as u32 } else { 0 }
+    }
+
+    pub fn counter(self) -> u32 {
+        if self.has_entry() { (self.0 & 0x00FF_FFFF) as u32 } else { self.0 as u32 }
+    }
+
+    pub fn type_id(self) -> TypeId {
+        self.high().map_or(TypeId::Object, HighGuid::type_id)
+    }
+
+    pub fn type_name(self) -> &'static str {
+        self.high().map_or("<unknown>", HighGuid::name)
+    }
+
+    pub fn is_player(self) -> bool {
+        !self.is_empty() && self.high() == Some(HighGuid::Player)
+    }
+
+    pub fn is_creature(self) -> bool {
+        self.high() == Some(HighGuid::Unit)
+    }
+
+    pub fn is_pet(self) -> bool {
+        self.high() == Some(HighGuid::Pet)
+    }
+
+    pub fn is_creature_or_pet(self) -> bool {
+        self.is_creature() || self.is_pet()
+    }
+
+    /// Creatures, pets and players
+    pub fn is_unit(self) -> bool {
+        self.is_creature_or_pet() || self.is_player()
+    }
+
+    pub fn is_item(self) -> bool {
+        self.high() == Some(HighGuid::Item)
+    }
+
+    pub fn is_game_object(self) -> bool {
+        self.high() == Some(HighGuid::GameObject)
+    }
+
+    pub fn is_dynamic_object(self) -> bool {
+        self.high() == Some(HighGuid::DynamicObject)
+    }
+
+    pub fn is_corpse(self) -> bool {
+        self.high() == Some(HighGuid::Corpse)
+    }
+
+    pub fn is_transport(self) -> bool {
+        self.high() == Some(HighGuid::Transport)
+    }
+
+    pub fn is_mo_transport(self) -> bool {
+        self.high() == Some(HighGuid::MoTransport)
+    }
+
+    /// The guid in packed form
+    pub fn pack(self) -> PackedGuid {
+        PackedGuid::new(self)
+    }
+
+    /// Write the guid packed
+    pub fn write_packed(self, buf: &mut ByteBuffer) {
+        buf.write_packed_guid(self.0);
+    }
+
+    /// Read a packed guid
+    pub fn read_packed(buf: &mut ByteBuffer) -> Result<Self, std::io::Error> {
+        buf.read_packed_guid().map(ObjectGuid)
+    }
+}
+
+impl From<u64> for ObjectGuid {
+    fn from(raw: u64) -> Self {
+        ObjectGuid(raw)
+    }
+}
+
+impl From<ObjectGuid> for u64 {
+    fn from(guid: ObjectGuid) -> Self {
+        guid.0
+    }
+}
+
+/// "Creature (Entry: 1234 Guid: 56)", as ObjectGuid::GetString in the logs
+impl fmt::Display for ObjectGuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.type_name())?;
+        if self.has_entry() {
+            write!(f, "{}: {} ", if self.is_pet() { "Petnumber" } else { "Entry" }, self.entry())?;
+        }
+        write!(f, "Guid: {})", self.counter())
+    }
+}
+
+/// A guid packed as mask byte and non-zero bytes, ready to be appended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackedGuid {
+    bytes: [u8; 9],
+    len: u8,
+}
+
+impl PackedGuid {
+    pub fn new(guid: ObjectGuid) -> Self {
+        let mut bytes = [0u8; 9];
+        let mut len = 1;
+        for (i, byte) in guid.raw().to_le_bytes().into_iter().enumerate() {
+            if byte != 0 {
+                bytes[0] |= 1 << i;
+                bytes[len] = byte;
+                len += 1;
+            }
+        }
+        PackedGuid { bytes, len: len as u8 }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    pub fn size(&self) -> usize {
+        self.len as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guid_layout() {
+        let creature = ObjectGuid::with_entry(HighGuid::Unit, 1234, 56);
+        assert_eq!(creature.raw(), 0xF130_0004_D200_0038);
+        assert_eq!((creature.high(), creature.entry(), creature.counter()), (Some(HighGuid::Unit), 1234, 56));
+        assert!(creature.is_creature() && creature.is_unit() && !creature.is_player());
+        assert_eq!(creature.type_id(), TypeId::Unit);
+        assert_eq!(creature.to_string(), "Creature (Entry: 1234 Guid: 56)");
+
+        let player = ObjectGuid::new(HighGuid::Player, 7);
+        assert_eq!(player.raw(), 7);
+        assert!(player.is_player());
+        assert_eq!((player.entry(), player.counter()), (0, 7));
+        assert_eq!(player.to_string(), "Player (Guid: 7)");
+
+        // Entries are only kept where the kind has one
+        let item = ObjectGuid::with_entry(HighGuid::Item, 999, 0x1234_5678);
+        assert_eq!(item.raw(), 0x4700_0000_1234_5678);
+        assert_eq!((item.entry(), item.counter(), item.type_id()), (0, 0x1234_5678, TypeId::Item));
+
+        assert_eq!(ObjectGuid::with_entry(HighGuid::Pet, 5, 0), ObjectGuid::EMPTY);
+        assert_eq!(ObjectGuid::with_entry(HighGuid::Pet, 5, 3).to_string(), "Pet (Petnumber: 5 Guid: 3)");
+        assert_eq!(HighGuid::Unit.max_counter(), 0x00FF_FFFF);
+        assert_eq!(HighGuid::Corpse.max_counter(), u32::MAX);
+
+        let unknown = ObjectGuid::from_raw(0xABCD_0000_0000_0001);
+        assert_eq!((unknown.high(), unknown.type_id(), unknown.counter()), (None, TypeId::Object, 1));
+        assert_eq!(unknown.to_string(), "<unknown> (Guid: 1)");
+    }
+
+    #[test]
+    fn test_packed_guid() {
+        let guid = ObjectGuid::with_entry(HighGuid::GameObject, 0x20, 0x0100);
+        let packed = guid.pack();
+        assert_eq!(packed.as_bytes(), &[0b1100_1010, 0x01, 0x20, 0x10, 0xF1]);
+        assert_eq!(ObjectGuid::EMPTY.pack().as_bytes(), &[0]);
+
+        let mut buf = ByteBuffer::new();
+        guid.write_packed(&mut buf);
+        assert_eq!(buf.contents(), packed.as_bytes());
+        assert_eq!(ObjectGuid::read_packed(&mut buf).unwrap(), guid);
+    }
+}
@@ -0,0 +1,82 @@
+// Time strings as used by chat and console commands
+// Rust equivalent of TimeStringToSecs / secsToTimeString in Util.cpp
+//
+// Also the current time for SQL statements, which take it from here rather
+// than from UNIX_TIMESTAMP() or NOW() so they run on every database backend.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::Local;
+
+use crate::{DAY, HOUR, MINUTE, WEEK};
+
+/// Seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Local time as "YYYY-MM-DD HH:MM:SS", the text form of a DATETIME column
+pub fn local_datetime() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Parse a duration like "1d12h30m" into seconds
+///
+/// Units are w, d, h, m and s; digits without a unit are ignored, as in the
+/// C++ core, so "90" is 0 and callers treat that as invalid.
+pub fn time_string_to_secs(text: &str) -> u64 {
+    let mut secs = 0u64;
+    let mut value = 0u64;
+    for c in text.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            value = value.saturating_mul(10).saturating_add(digit as u64);
+            continue;
+        }
+        let unit = match c {
+            'w' => WEEK,
+            'd' => DAY,
+            'h' => HOUR,
+            'm' => MINUTE,
+            's' => 1,
+            _ => 0,
+        };
+        secs = secs.saturating_add(value.saturating_mul(unit as u64));
+        value = 0;
+    }
+    secs
+}
+
+/// Format seconds like "1 Day(s) 2 Hour(s) 3 Minute(s) 4 Second(s)", leaving out zero parts
+pub fn secs_to_time_string(secs: u64) -> String {
+    let parts = [
+        (secs / DAY as u64, "Day(s)"),
+        (secs % DAY as u64 / HOUR as u64, "Hour(s)"),
+        (secs % HOUR as u64 / MINUTE as u64, "Minute(s)"),
+        (secs % MINUTE as u64, "Second(s)"),
+    ];
+    let text = parts
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{} {}", value, unit))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() { "0 Second(s)".to_string() } else { text }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_strings() {
+        assert_eq!(time_string_to_secs("1d12h30m"), 131400);
+        assert_eq!(time_string_to_secs("2w"), 1209600);
+        assert_eq!(time_string_to_secs("45s"), 45);
+        assert_eq!(time_string_to_secs("90"), 0);
+        assert_eq!(time_string_to_secs("-1"), 0);
+
+        assert_eq!(secs_to_time_string(131400), "1 Day(s) 12 Hour(s) 30 Minute(s)");
+        assert_eq!(secs_to_time_string(61), "1 Minute(s) 1 Second(s)");
+        assert_eq!(secs_to_time_string(0), "0 Second(s)");
+    }
+}
@@ -0,0 +1,307 @@
+// BIH - Bounding interval hierarchy over the primitives of a model or map
+// Rust equivalent of BIH.h/cpp (reading and ray traversal)
+//
+// The tree is the flat array of 3-word nodes written by the assembler:
+//   word 0    axis in bits 30-31 (3 = leaf), BVH2 flag in bit 29 and the
+//             child offset (leaves: first object slot) in bits 0-28
+//   word 1-2  interior nodes: left and right clip planes as f32 bits,
+//             BVH2 nodes: the bounds of their single child on the axis,
+//             leaves: object count
+// The children of an interior node are the nodes at offset and offset + 3.
+// The object array maps the leaf slots to primitive indices.
+
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::vector::{AaBox, Ray};
+use super::{read_u32_vec, read_vector3};
+
+/// Ray directions closer to 0 than this are treated as parallel to the axis
+const FUZZY_EPSILON: f32 = 0.00001;
+
+struct StackNode {
+    node: usize,
+    tnear: f32,
+    tfar: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bih {
+    bounds: AaBox,
+    tree: Vec<u32>,
+    objects: Vec<u32>,
+}
+
+impl Bih {
+    pub fn read_from<R: Read>(reader: &mut R) -> anyhow::Result<Self> {
+        let bounds = AaBox::new(read_vector3(reader)?, read_vector3(reader)?);
+        let tree_size = reader.read_u32::<LittleEndian>()?;
+        let tree = read_u32_vec(reader, tree_size)?;
+        let count = reader.read_u32::<LittleEndian>()?;
+        let objects = read_u32_vec(reader, count)?;
+        Ok(Bih { bounds, tree, objects })
+    }
+
+    pub fn bounds(&self) -> &AaBox {
+        &self.bounds
+    }
+
+    /// Number of primitives the tree was built over
+    pub fn prim_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    fn clip(&self, index: usize) -> Option<f32> {
+        self.tree.get(index).map(|&bits| f32::from_bits(bits))
+    }
+
+    /// Walk the leaves the ray passes within `max_dist`, front to back
+    ///
+    /// `callback(ray, primitive, max_dist, stop_at_first_hit)` tests one
+    /// primitive and shortens `max_dist` on a hit, which prunes the nodes
+    /// behind it. With `stop_at_first_hit` the walk ends at the first hit.
+    pub fn intersect_ray<F>(&self, ray: &Ray, max_dist: &mut f32, stop_at_first_hit: bool, mut callback: F)
+    where
+        F: FnMut(&Ray, u32, &mut f32, bool) -> bool,
+    {
+        let org = ray.origin;
+        let dir = ray.direction;
+        let inv_dir: [f32; 3] = std::array::from_fn(|axis| 1.0 / dir.axis(axis));
+
+        let mut interval_min = -1.0f32;
+        let mut interval_max = -1.0f32;
+        for (axis, &inv) in inv_dir.iter().enumerate() {
+            if dir.axis(axis).abs() <= FUZZY_EPSILON {
+                continue;
+            }
+            let mut t1 = (self.bounds.low.axis(axis) - org.axis(axis)) * inv;
+            let mut t2 = (self.bounds.high.axis(axis) - org.axis(axis)) * inv;
+            if t1 > t2 {
+                std::mem::swap(&mut t1, &mut t2);
+            }
+            if t1 > interval_min {
+                interval_min = t1;
+            }
+            if t2 < interval_max || interval_max < 0.0 {
+                interval_max = t2;
+            }
+            // The interval only shrinks on the other axes
+            if interval_max <= 0.0 || interval_min >= *max_dist {
+                return;
+            }
+        }
+        if interval_min > interval_max {
+            return;
+        }
+        interval_min = interval_min.max(0.0);
+        interval_max = interval_max.min(*max_dist);
+
+        // Word of the near/far clip plane and offset of the near/far child,
+        // from the sign of the direction
+        let offset_front: [usize; 3] = std::array::from_fn(|axis| (dir.axis(axis).to_bits() >> 31) as usize);
+        let offset_back: [usize; 3] = std::array::from_fn(|axis| offset_front[axis] ^ 1);
+        let offset_front3: [usize; 3] = std::array::from_fn(|axis| offset_front[axis] * 3);
+        let offset_back3: [usize; 3] = std::array::from_fn(|axis| offset_back[axis] * 3);
+
+        let mut stack: Vec<StackNode> = Vec::new();
+        let mut node = 0usize;
+        loop {
+            loop {
+                let Some(&tn) = self.tree.get(node) else {
+                    return;
+                };
+                let axis = (tn >> 30) as usize;
+                let bvh2 = tn & (1 << 29) != 0;
+                let offset = (tn & !(7 << 29)) as usize;
+
+                if axis == 3 {
+                    // Leaf, test its objects
+                    let count = self.tree.get(node + 1).copied().unwrap_or(0) as usize;
+                    let objects = self.objects.get(offset..offset.saturating_add(count)).unwrap_or(&[]);
+                    for &object in objects {
+                        let hit = callback(ray, object, max_dist, stop_at_first_hit);
+                        if stop_at_first_hit && hit {
+                            return;
+                        }
+                    }
+                    break;
+                }
+
+                let (Some(front), Some(back)) =
+                    (self.clip(node + 1 + offset_front[axis]), self.clip(node + 1 + offset_back[axis]))
+                else {
+                    return;
+                };
+                let tf = (front - org.axis(axis)) * inv_dir[axis];
+                let tb = (back - org.axis(axis)) * inv_dir[axis];
+
+                if bvh2 {
+                    // Single child, clip the interval to its bounds
+                    node = offset;
+                    if tf >= interval_min {
+                        interval_min = tf;
+                    }
+                    if tb <= interval_max {
+                        interval_max = tb;
+                    }
+                    if interval_min > interval_max {
+                        break;
+                    }
+                    continue;
+                }
+
+                // Ray passes between the clip zones
+                if tf < interval_min && tb > interval_max {
+                    break;
+                }
+                let back_node = offset + offset_back3[axis];
+                node = back_node;
+                // Far child only
+                if tf < interval_min {
+                    if tb >= interval_min {
+                        interval_min = tb;
+                    }
+                    continue;
+                }
+                node = offset + offset_front3[axis];
+                // Near child only
+                if tb > interval_max {
+                    if tf <= interval_max {
+                        interval_max = tf;
+                    }
+                    continue;
+                }
+                // Both, the far one after the near one
+                stack.push(StackNode {
+                    node: back_node,
+                    tnear: if tb >= interval_min { tb } else { interval_min },
+                    tfar: interval_max,
+                });
+                if tf <= interval_max {
+                    interval_max = tf;
+                }
+            }
+
+            loop {
+                let Some(entry) = stack.pop() else {
+                    return;
+                };
+                // Skip nodes behind the nearest hit so far
+                if *max_dist < entry.tnear {
+                    continue;
+                }
+                node = entry.node;
+                interval_min = entry.tnear;
+                interval_max = entry.tfar;
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::vmap::vector::Vector3;
+
+    /// Serialize a tree the way the assembler does
+    pub fn write_bih(out: &mut Vec<u8>, bounds: AaBox, tree: &[u32], objects: &[u32]) {
+        for v in [bounds.low, bounds.high] {
+            for c in [v.x, v.y, v.z] {
+                out.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+        for word in tree {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out.extend_from_slice(&(objects.len() as u32).to_le_bytes());
+        for object in objects {
+            out.extend_from_slice(&object.to_le_bytes());
+        }
+    }
+
+    /// One leaf holding primitives 0..count
+    pub fn leaf_bih(out: &mut Vec<u8>, bounds: AaBox, count: u32) {
+        write_bih(out, bounds, &[3 << 30, count, 0], &(0..count).collect::<Vec<_>>());
+    }
+
+    /// Boxes [0,1] and [5,6] on x, split by an interior node
+    fn two_boxes() -> Bih {
+        let tree = [
+            3, // axis x, children at 3 and 6
+            1.0f32.to_bits(),
+            5.0f32.to_bits(),
+            3 << 30,
+            1,
+            0,
+            (3 << 30) | 1,
+            1,
+            0,
+        ];
+        let mut bytes = Vec::new();
+        write_bih(
+            &mut bytes,
+            AaBox::new(Vector3::new(0.0, -1.0, -1.0), Vector3::new(6.0, 1.0, 1.0)),
+            &tree,
+            &[10, 20],
+        );
+        Bih::read_from(&mut bytes.as_slice()).unwrap()
+    }
+
+    fn visits(bih: &Bih, ray: Ray, max_dist: f32, hits: &[(u32, f32)]) -> Vec<u32> {
+        let mut visited = Vec::new();
+        let mut max_dist = max_dist;
+        bih.intersect_ray(&ray, &mut max_dist, false, |_, object, dist, _| {
+            visited.push(object);
+            match hits.iter().find(|(o, _)| *o == object) {
+                Some(&(_, at)) if at < *dist => {
+                    *dist = at;
+                    true
+                }
+                _ => false,
+            }
+        });
+        visited
+    }
+
+    #[test]
+    fn test_traversal_order() {
+        let bih = two_boxes();
+        assert_eq!(bih.prim_count(), 2);
+        let x = Vector3::new(1.0, 0.0, 0.0);
+
+        assert_eq!(visits(&bih, Ray::new(Vector3::new(-10.0, 0.0, 0.0), x), 100.0, &[]), [10, 20]);
+        assert_eq!(visits(&bih, Ray::new(Vector3::new(10.0, 0.0, 0.0), -x), 100.0, &[]), [20, 10]);
+
+        // A hit in the near box prunes the far one
+        assert_eq!(visits(&bih, Ray::new(Vector3::new(-10.0, 0.0, 0.0), x), 100.0, &[(10, 10.5)]), [10]);
+
+        // Too short, and between the boxes
+        assert_eq!(visits(&bih, Ray::new(Vector3::new(-10.0, 0.0, 0.0), x), 5.0, &[]), Vec::<u32>::new());
+        let y = Vector3::new(0.0, 1.0, 0.0);
+        assert_eq!(visits(&bih, Ray::new(Vector3::new(3.0, -5.0, 0.0), y), 100.0, &[]), Vec::<u32>::new());
+        assert_eq!(visits(&bih, Ray::new(Vector3::new(0.5, -5.0, 0.0), y), 100.0, &[]), [10]);
+    }
+
+    #[test]
+    fn test_stop_at_first_hit() {
+        let bih = two_boxes();
+        let mut visited = Vec::new();
+        let mut max_dist = 100.0;
+        let ray = Ray::new(Vector3::new(10.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0));
+        bih.intersect_ray(&ray, &mut max_dist, true, |_, object, _, _| {
+            visited.push(object);
+            true
+        });
+        assert_eq!(visited, [20]);
+    }
+
+    #[test]
+    fn test_truncated_tree() {
+        let mut bytes = Vec::new();
+        leaf_bih(&mut bytes, AaBox::default(), 3);
+        assert!(Bih::read_from(&mut &bytes[..bytes.len() - 4]).is_err());
+    }
+}
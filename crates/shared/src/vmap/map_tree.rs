@@ -0,0 +1,249 @@
+// StaticMapTree - The model spawns of one map and the BIH over them
+// Rust equivalent of MapTree.h/cpp
+//
+// <map>.vmtree holds "VMAP_7.0", a tiled flag, "NODE" with the BIH over the
+// bounds of every spawn of the map, then "GOBJ" and, on maps that are one
+// global WMO, the spawns of that WMO. Tiled maps list their spawns per tile
+// in <map>_<x>_<y>.vmtile: "VMAP_7.0", a count and the spawns. Each spawn is
+// followed by its index in the BIH; spawns crossing tiles are listed in each
+// of them and stay loaded until the last of those tiles is unloaded.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::error::MangosError;
+
+use super::bih::Bih;
+use super::model_instance::{ModelInstance, ModelSpawn};
+use super::vector::{Ray, Vector3};
+use super::{read_chunk, ModelCache, VMAP_MAGIC};
+
+pub fn tree_file_name(map_id: u32) -> String {
+    format!("{:03}.vmtree", map_id)
+}
+
+/// Tile file name, in the x/y order `extractors vmap-assemble` writes
+pub fn tile_file_name(map_id: u32, tile_x: u32, tile_y: u32) -> String {
+    format!("{:03}_{:02}_{:02}.vmtile", map_id, tile_x, tile_y)
+}
+
+fn pack_tile_id(tile_x: u32, tile_y: u32) -> u32 {
+    (tile_x << 16) | tile_y
+}
+
+/// Spawns of a .vmtree (after "GOBJ") or .vmtile, with their tree index
+fn read_spawns<R: Read>(reader: &mut R, limit: Option<u32>) -> anyhow::Result<Vec<(ModelSpawn, u32)>> {
+    let mut spawns = Vec::new();
+    while limit.is_none_or(|limit| spawns.len() < limit as usize) {
+        let Some(spawn) = ModelSpawn::read_from(reader)? else {
+            if limit.is_some() {
+                anyhow::bail!(MangosError::Data("spawn list truncated".into()));
+            }
+            break;
+        };
+        let node = reader.read_u32::<LittleEndian>()?;
+        spawns.push((spawn, node));
+    }
+    Ok(spawns)
+}
+
+pub struct StaticMapTree {
+    map_id: u32,
+    base_path: PathBuf,
+    is_tiled: bool,
+    tree: Bih,
+    /// Loaded spawns by tree index
+    tree_values: Vec<Option<ModelInstance>>,
+    /// Number of loaded tiles (or the global model) using each tree value
+    loaded_spawns: HashMap<u32, u32>,
+    /// Tree values listed by each loaded tile
+    loaded_tiles: HashMap<u32, Vec<u32>>,
+}
+
+impl StaticMapTree {
+    /// Read `<base_path>/<map>.vmtree`, loading the global model if any
+    pub fn init_map(base_path: &Path, map_id: u32, models: &ModelCache) -> anyhow::Result<Self> {
+        let path = base_path.join(tree_file_name(map_id));
+        let bytes = std::fs::read(&path).with_context(|| format!("cannot read {}", path.display()))?;
+        let mut reader = bytes.as_slice();
+
+        let (is_tiled, tree, spawns) = (|| {
+            read_chunk(&mut reader, VMAP_MAGIC)?;
+            let is_tiled = reader.read_u8()? != 0;
+            read_chunk(&mut reader, b"NODE")?;
+            let tree = Bih::read_from(&mut reader)?;
+            read_chunk(&mut reader, b"GOBJ")?;
+            let spawns = if is_tiled { Vec::new() } else { read_spawns(&mut reader, None)? };
+            anyhow::Ok((is_tiled, tree, spawns))
+        })()
+        .with_context(|| format!("cannot parse {}", path.display()))?;
+
+        let mut map_tree = StaticMapTree {
+            map_id,
+            base_path: base_path.to_path_buf(),
+            is_tiled,
+            tree_values: vec![None; tree.prim_count()],
+            tree,
+            loaded_spawns: HashMap::new(),
+            loaded_tiles: HashMap::new(),
+        };
+        // The global model stays loaded as long as the map
+        map_tree.acquire_spawns(spawns, models);
+        Ok(map_tree)
+    }
+
+    pub fn map_id(&self) -> u32 {
+        self.map_id
+    }
+
+    pub fn is_tiled(&self) -> bool {
+        self.is_tiled
+    }
+
+    pub fn num_loaded_tiles(&self) -> usize {
+        self.loaded_tiles.len()
+    }
+
+    pub fn has_tile(&self, tile_x: u32, tile_y: u32) -> bool {
+        self.loaded_tiles.contains_key(&pack_tile_id(tile_x, tile_y))
+    }
+
+    /// Place spawns in the tree, returning the tree indices now referenced
+    fn acquire_spawns(&mut self, spawns: Vec<(ModelSpawn, u32)>, models: &ModelCache) -> Vec<u32> {
+        let mut nodes = Vec::with_capacity(spawns.len());
+        for (spawn, node) in spawns {
+            if node as usize >= self.tree_values.len() {
+                tracing::debug!("Map {}: invalid tree element {}/{}", self.map_id, node, self.tree_values.len());
+                continue;
+            }
+            if let Some(count) = self.loaded_spawns.get_mut(&node) {
+                *count += 1;
+                nodes.push(node);
+                continue;
+            }
+            match models.acquire(&spawn.name) {
+                Ok(model) => {
+                    self.tree_values[node as usize] = Some(ModelInstance::new(spawn, model));
+                    self.loaded_spawns.insert(node, 1);
+                    nodes.push(node);
+                }
+                Err(e) => tracing::error!("Map {}: cannot load model of spawn {}: {:#}", self.map_id, spawn.id, e),
+            }
+        }
+        nodes
+    }
+
+    /// Load the spawns of a tile; a tile without a file has no spawns
+    ///
+    /// Maps without tiles accept every tile, so the caller can treat all
+    /// maps alike when it unloads the map after its last tile.
+    pub fn load_map_tile(&mut self, tile_x: u32, tile_y: u32, models: &ModelCache) -> anyhow::Result<()> {
+        let tile_id = pack_tile_id(tile_x, tile_y);
+        if self.loaded_tiles.contains_key(&tile_id) {
+            return Ok(());
+        }
+        if !self.is_tiled {
+            self.loaded_tiles.insert(tile_id, Vec::new());
+            return Ok(());
+        }
+
+        let path = self.base_path.join(tile_file_name(self.map_id, tile_x, tile_y));
+        let spawns = if path.exists() {
+            let bytes = std::fs::read(&path).with_context(|| format!("cannot read {}", path.display()))?;
+            let mut reader = bytes.as_slice();
+            (|| {
+                read_chunk(&mut reader, VMAP_MAGIC)?;
+                let count = reader.read_u32::<LittleEndian>()?;
+                read_spawns(&mut reader, Some(count))
+            })()
+            .with_context(|| format!("cannot parse {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+        let nodes = self.acquire_spawns(spawns, models);
+        self.loaded_tiles.insert(tile_id, nodes);
+        Ok(())
+    }
+
+    /// Release the spawns of a tile, dropping those no other tile uses
+    pub fn unload_map_tile(&mut self, tile_x: u32, tile_y: u32) {
+        let Some(nodes) = self.loaded_tiles.remove(&pack_tile_id(tile_x, tile_y)) else {
+            tracing::error!("Map {}: trying to unload non-loaded tile {},{}", self.map_id, tile_x, tile_y);
+            return;
+        };
+        for node in nodes {
+            if let Some(count) = self.loaded_spawns.get_mut(&node) {
+                *count -= 1;
+                if *count == 0 {
+                    self.loaded_spawns.remove(&node);
+                    self.tree_values[node as usize] = None;
+                }
+            }
+        }
+    }
+
+    /// Number of spawns currently loaded
+    pub fn loaded_spawns(&self) -> usize {
+        self.loaded_spawns.len()
+    }
+
+    /// Nearest hit within `max_dist`, shortening `max_dist`
+    fn get_intersection_time(&self, ray: &Ray, max_dist: &mut f32, stop_at_first_hit: bool) -> bool {
+        let mut hit = false;
+        self.tree.intersect_ray(ray, max_dist, stop_at_first_hit, |ray, entry, distance, stop| {
+            let result = self
+                .tree_values
+                .get(entry as usize)
+                .and_then(Option::as_ref)
+                .is_some_and(|instance| instance.intersect_ray(ray, distance, stop));
+            hit |= result;
+            result
+        });
+        hit
+    }
+
+    /// Whether no loaded model blocks the segment, in internal coordinates
+    pub fn is_in_line_of_sight(&self, pos1: Vector3, pos2: Vector3) -> bool {
+        let max_dist = (pos2 - pos1).length();
+        // Also keeps NaN out of the tree walk
+        if max_dist.is_nan() || max_dist < 1e-10 {
+            return true;
+        }
+        let ray = Ray::new(pos1, (pos2 - pos1) / max_dist);
+        let mut distance = max_dist;
+        !self.get_intersection_time(&ray, &mut distance, true)
+    }
+
+    /// First model hit from `pos1` towards `pos2`, moved by `modify_dist`
+    ///
+    /// A negative `modify_dist` pulls the point back towards `pos1`, but
+    /// never past it.
+    pub fn get_object_hit_pos(&self, pos1: Vector3, pos2: Vector3, modify_dist: f32) -> Option<Vector3> {
+        let max_dist = (pos2 - pos1).length();
+        if max_dist.is_nan() || max_dist < 1e-10 {
+            return None;
+        }
+        let dir = (pos2 - pos1) / max_dist;
+        let mut distance = max_dist;
+        if !self.get_intersection_time(&Ray::new(pos1, dir), &mut distance, false) {
+            return None;
+        }
+
+        let hit = pos1 + dir * distance;
+        if modify_dist < 0.0 && (hit - pos1).length() <= -modify_dist {
+            return Some(pos1);
+        }
+        Some(hit + dir * modify_dist)
+    }
+
+    /// Height of the first model surface below `pos` within `max_search_dist`
+    pub fn get_height(&self, pos: Vector3, max_search_dist: f32) -> Option<f32> {
+        let ray = Ray::new(pos, Vector3::new(0.0, 0.0, -1.0));
+        let mut distance = max_search_dist;
+        self.get_intersection_time(&ray, &mut distance, false).then_some(pos.z - distance)
+    }
+}
@@ -0,0 +1,399 @@
+// VMap module - Collision queries against the assembled model data
+// Rust equivalent of VMapManager2.h/cpp and VMapDefinitions.h
+//
+// VMapManager reads the output of `extractors vmap-assemble` from
+// <DataDir>/vmaps: per map a .vmtree with the BIH over its model spawns,
+// per tile a .vmtile with the spawns to load and one .vmo per model. Maps
+// are loaded tile by tile and answer line of sight, hit position and
+// height queries in world coordinates.
+
+mod bih;
+mod map_tree;
+mod model_instance;
+mod vector;
+mod world_model;
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use parking_lot::{Mutex, RwLock};
+
+use crate::error::MangosError;
+use crate::geometry::MAP_HALFSIZE;
+
+pub use bih::Bih;
+pub use map_tree::{tile_file_name, tree_file_name, StaticMapTree};
+pub use model_instance::{ModelInstance, ModelSpawn, MOD_HAS_BOUND, MOD_M2, MOD_WORLDSPAWN};
+pub use vector::{AaBox, Ray, Vector3};
+pub use world_model::{GroupModel, WmoLiquid, WorldModel};
+
+pub const VMAP_MAGIC: &[u8; 8] = b"VMAP_7.0";
+/// Heights at or below this are not real surfaces
+pub const VMAP_INVALID_HEIGHT: f32 = -100000.0;
+/// Height reported where no model is found
+pub const VMAP_INVALID_HEIGHT_VALUE: f32 = -200000.0;
+
+fn read_chunk<R: Read>(reader: &mut R, expected: &[u8]) -> anyhow::Result<()> {
+    let mut chunk = [0u8; 8];
+    let chunk = &mut chunk[..expected.len()];
+    reader.read_exact(chunk)?;
+    if chunk != expected {
+        anyhow::bail!(MangosError::Data(format!(
+            "expected chunk {}, found {}",
+            String::from_utf8_lossy(expected),
+            String::from_utf8_lossy(chunk)
+        )));
+    }
+    Ok(())
+}
+
+fn read_vector3<R: Read>(reader: &mut R) -> anyhow::Result<Vector3> {
+    Ok(Vector3::new(
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+    ))
+}
+
+/// `count` u32s; memory grows with the data actually read, not the count
+fn read_u32_vec<R: Read>(reader: &mut R, count: u32) -> anyhow::Result<Vec<u32>> {
+    let mut values = Vec::new();
+    for _ in 0..count {
+        values.push(reader.read_u32::<LittleEndian>()?);
+    }
+    Ok(values)
+}
+
+fn convert_position_to_internal_rep(x: f32, y: f32, z: f32) -> Vector3 {
+    // World x/y run the other way from the map centre
+    Vector3::new(MAP_HALFSIZE - x, MAP_HALFSIZE - y, z)
+}
+
+fn convert_position_to_mangos_rep(pos: Vector3) -> Vector3 {
+    Vector3::new(MAP_HALFSIZE - pos.x, MAP_HALFSIZE - pos.y, pos.z)
+}
+
+/// .vmo models shared by every map, kept while any spawn uses them
+pub struct ModelCache {
+    base_path: PathBuf,
+    models: Mutex<HashMap<String, Weak<WorldModel>>>,
+}
+
+impl ModelCache {
+    pub fn new(base_path: &Path) -> Self {
+        ModelCache { base_path: base_path.to_path_buf(), models: Mutex::new(HashMap::new()) }
+    }
+
+    /// The model `<name>.vmo`, loading it unless it is in use already
+    pub fn acquire(&self, name: &str) -> anyhow::Result<Arc<WorldModel>> {
+        let mut models = self.models.lock();
+        if let Some(model) = models.get(name).and_then(Weak::upgrade) {
+            return Ok(model);
+        }
+        let model = Arc::new(WorldModel::load(&self.base_path.join(format!("{}.vmo", name)))?);
+        models.retain(|_, model| model.strong_count() > 0);
+        models.insert(name.to_string(), Arc::downgrade(&model));
+        Ok(model)
+    }
+
+    /// Number of models in use
+    pub fn len(&self) -> usize {
+        self.models.lock().values().filter(|model| model.strong_count() > 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The vmaps of every map, loaded per tile
+pub struct VMapManager {
+    vmaps_dir: PathBuf,
+    models: ModelCache,
+    maps: RwLock<HashMap<u32, StaticMapTree>>,
+    enable_line_of_sight_calc: bool,
+    enable_height_calc: bool,
+}
+
+impl VMapManager {
+    /// Manager for the vmaps in `<data_dir>/vmaps`
+    pub fn new(data_dir: &Path) -> Self {
+        let vmaps_dir = data_dir.join("vmaps");
+        VMapManager {
+            models: ModelCache::new(&vmaps_dir),
+            vmaps_dir,
+            maps: RwLock::new(HashMap::new()),
+            enable_line_of_sight_calc: true,
+            enable_height_calc: true,
+        }
+    }
+
+    /// vmap.enableLOS; when off every position is in line of sight
+    pub fn set_enable_line_of_sight_calc(&mut self, enable: bool) {
+        self.enable_line_of_sight_calc = enable;
+    }
+
+    /// vmap.enableHeight; when off no model height is found
+    pub fn set_enable_height_calc(&mut self, enable: bool) {
+        self.enable_height_calc = enable;
+    }
+
+    pub fn is_line_of_sight_calc_enabled(&self) -> bool {
+        self.enable_line_of_sight_calc
+    }
+
+    pub fn is_height_calc_enabled(&self) -> bool {
+        self.enable_height_calc
+    }
+
+    /// Load a tile of a map, and the map itself on its first tile
+    pub fn load_map(&self, map_id: u32, tile_x: u32, tile_y: u32) -> anyhow::Result<()> {
+        let mut maps = self.maps.write();
+        let tree = match maps.entry(map_id) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(StaticMapTree::init_map(&self.vmaps_dir, map_id, &self.models)?)
+            }
+        };
+        tree.load_map_tile(tile_x, tile_y, &self.models)
+    }
+
+    /// Unload a tile, and the map with its last tile
+    pub fn unload_map_tile(&self, map_id: u32, tile_x: u32, tile_y: u32) {
+        let mut maps = self.maps.write();
+        if let Some(tree) = maps.get_mut(&map_id) {
+            tree.unload_map_tile(tile_x, tile_y);
+            if tree.num_loaded_tiles() == 0 {
+                maps.remove(&map_id);
+            }
+        }
+    }
+
+    pub fn unload_map(&self, map_id: u32) {
+        self.maps.write().remove(&map_id);
+    }
+
+    pub fn is_map_loaded(&self, map_id: u32) -> bool {
+        self.maps.read().contains_key(&map_id)
+    }
+
+    /// Number of models in use by the loaded maps
+    pub fn loaded_models(&self) -> usize {
+        self.models.len()
+    }
+
+    /// Whether no loaded model blocks the line between two positions
+    pub fn is_in_line_of_sight(&self, map_id: u32, from: Vector3, to: Vector3) -> bool {
+        if !self.enable_line_of_sight_calc {
+            return true;
+        }
+        let maps = self.maps.read();
+        let Some(tree) = maps.get(&map_id) else {
+            return true;
+        };
+        let pos1 = convert_position_to_internal_rep(from.x, from.y, from.z);
+        let pos2 = convert_position_to_internal_rep(to.x, to.y, to.z);
+        pos1 == pos2 || tree.is_in_line_of_sight(pos1, pos2)
+    }
+
+    /// Where the line from `from` to `to` first hits a model, moved along
+    /// the line by `modify_dist`; None if nothing is hit
+    pub fn get_object_hit_pos(&self, map_id: u32, from: Vector3, to: Vector3, modify_dist: f32) -> Option<Vector3> {
+        if !self.enable_line_of_sight_calc {
+            return None;
+        }
+        let maps = self.maps.read();
+        let tree = maps.get(&map_id)?;
+        let pos1 = convert_position_to_internal_rep(from.x, from.y, from.z);
+        let pos2 = convert_position_to_internal_rep(to.x, to.y, to.z);
+        tree.get_object_hit_pos(pos1, pos2, modify_dist).map(convert_position_to_mangos_rep)
+    }
+
+    /// Height of the first model surface at most `max_search_dist` below
+    /// the position, VMAP_INVALID_HEIGHT_VALUE if there is none
+    pub fn get_height(&self, map_id: u32, x: f32, y: f32, z: f32, max_search_dist: f32) -> f32 {
+        if !self.enable_height_calc {
+            return VMAP_INVALID_HEIGHT_VALUE;
+        }
+        let maps = self.maps.read();
+        maps.get(&map_id)
+            .and_then(|tree| tree.get_height(convert_position_to_internal_rep(x, y, z), max_search_dist))
+            .unwrap_or(VMAP_INVALID_HEIGHT_VALUE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bih::tests::{leaf_bih, write_bih};
+    use model_instance::tests::{wall_spawn, write_spawn};
+    use world_model::tests::{build_vmo, floor_and_wall};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mangos_vmap_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("vmaps")).unwrap();
+        std::fs::write(dir.join("vmaps").join("wall.wmo.vmo"), build_vmo(&floor_and_wall())).unwrap();
+        dir
+    }
+
+    fn vmtree(is_tiled: bool, spawns: &[ModelSpawn]) -> Vec<u8> {
+        let mut out = VMAP_MAGIC.to_vec();
+        out.push(is_tiled as u8);
+        out.extend_from_slice(b"NODE");
+        let bounds = spawns.iter().fold(spawns[0].bound.unwrap(), |bounds, spawn| {
+            let bound = spawn.bound.unwrap();
+            AaBox::new(bounds.low.min(bound.low), bounds.high.max(bound.high))
+        });
+        leaf_bih(&mut out, bounds, spawns.len() as u32);
+        out.extend_from_slice(b"GOBJ");
+        if !is_tiled {
+            for (node, spawn) in spawns.iter().enumerate() {
+                write_spawn(&mut out, spawn);
+                out.extend_from_slice(&(node as u32).to_le_bytes());
+            }
+        }
+        out
+    }
+
+    fn vmtile(spawns: &[(&ModelSpawn, u32)]) -> Vec<u8> {
+        let mut out = VMAP_MAGIC.to_vec();
+        out.extend_from_slice(&(spawns.len() as u32).to_le_bytes());
+        for (spawn, node) in spawns {
+            write_spawn(&mut out, spawn);
+            out.extend_from_slice(&node.to_le_bytes());
+        }
+        out
+    }
+
+    fn spawn_at(id: u32, x: f32, y: f32, z: f32) -> ModelSpawn {
+        wall_spawn(id, convert_position_to_internal_rep(x, y, z), 0.0, 1.0)
+    }
+
+    #[test]
+    fn test_line_of_sight_and_height() {
+        let dir = temp_dir("los");
+        let spawn = spawn_at(1, 100.0, 200.0, 50.0);
+        std::fs::write(dir.join("vmaps").join(tree_file_name(0)), vmtree(true, std::slice::from_ref(&spawn))).unwrap();
+        std::fs::write(dir.join("vmaps").join(tile_file_name(0, 32, 32)), vmtile(&[(&spawn, 0)])).unwrap();
+
+        let manager = VMapManager::new(&dir);
+        assert!(!manager.is_map_loaded(0));
+        manager.load_map(0, 32, 32).unwrap();
+        assert_eq!(manager.loaded_models(), 1);
+
+        // World x/y are mirrored in map space, the wall still splits x
+        let west = Vector3::new(95.0, 200.0, 52.0);
+        let east = Vector3::new(105.0, 200.0, 52.0);
+        let north = Vector3::new(95.0, 205.0, 52.0);
+        assert!(!manager.is_in_line_of_sight(0, west, east));
+        assert!(manager.is_in_line_of_sight(0, west, north));
+        assert!(manager.is_in_line_of_sight(0, west, west));
+        assert!(manager.is_in_line_of_sight(1, west, east));
+
+        let hit = manager.get_object_hit_pos(0, west, east, -1.0).unwrap();
+        assert!((hit.x - 99.0).abs() < 1e-2 && (hit.y - 200.0).abs() < 1e-2);
+        let hit = manager.get_object_hit_pos(0, west, east, -10.0).unwrap();
+        assert!((hit - west).length() < 1e-2);
+        assert_eq!(manager.get_object_hit_pos(0, west, north, 0.0), None);
+
+        assert!((manager.get_height(0, 103.0, 203.0, 60.0, 50.0) - 50.0).abs() < 1e-3);
+        assert_eq!(manager.get_height(0, 103.0, 203.0, 60.0, 5.0), VMAP_INVALID_HEIGHT_VALUE);
+        assert_eq!(manager.get_height(0, 150.0, 203.0, 60.0, 50.0), VMAP_INVALID_HEIGHT_VALUE);
+
+        manager.unload_map_tile(0, 32, 32);
+        assert!(!manager.is_map_loaded(0));
+        assert_eq!(manager.loaded_models(), 0);
+        assert!(manager.is_in_line_of_sight(0, west, east));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_spawns_shared_by_tiles() {
+        let dir = temp_dir("tiles");
+        let spawns = [spawn_at(1, 0.0, 0.0, 0.0), spawn_at(2, 0.0, 100.0, 0.0)];
+        std::fs::write(dir.join("vmaps").join(tree_file_name(1)), vmtree(true, &spawns)).unwrap();
+        std::fs::write(dir.join("vmaps").join(tile_file_name(1, 31, 31)), vmtile(&[(&spawns[0], 0)])).unwrap();
+        std::fs::write(
+            dir.join("vmaps").join(tile_file_name(1, 31, 32)),
+            vmtile(&[(&spawns[0], 0), (&spawns[1], 1)]),
+        )
+        .unwrap();
+
+        let models = ModelCache::new(&dir.join("vmaps"));
+        let mut tree = StaticMapTree::init_map(&dir.join("vmaps"), 1, &models).unwrap();
+        assert!(tree.is_tiled());
+        tree.load_map_tile(31, 31, &models).unwrap();
+        tree.load_map_tile(31, 32, &models).unwrap();
+        // No file, no spawns
+        tree.load_map_tile(40, 40, &models).unwrap();
+        assert_eq!(tree.num_loaded_tiles(), 3);
+        assert_eq!(tree.loaded_spawns(), 2);
+        assert_eq!(models.len(), 1);
+
+        let a = convert_position_to_internal_rep(-5.0, 100.0, 2.0);
+        let b = convert_position_to_internal_rep(5.0, 100.0, 2.0);
+        assert!(!tree.is_in_line_of_sight(a, b));
+
+        tree.unload_map_tile(31, 32);
+        assert_eq!(tree.loaded_spawns(), 1);
+        assert!(tree.is_in_line_of_sight(a, b));
+        tree.unload_map_tile(31, 31);
+        tree.unload_map_tile(40, 40);
+        assert_eq!(tree.loaded_spawns(), 0);
+        assert!(models.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_global_model_map() {
+        let dir = temp_dir("global");
+        let spawn = spawn_at(1, 10.0, 10.0, 0.0);
+        std::fs::write(dir.join("vmaps").join(tree_file_name(33)), vmtree(false, &[spawn])).unwrap();
+
+        let manager = VMapManager::new(&dir);
+        manager.load_map(33, 30, 30).unwrap();
+        manager.load_map(33, 31, 30).unwrap();
+        assert_eq!(manager.loaded_models(), 1);
+        assert!(!manager.is_in_line_of_sight(33, Vector3::new(5.0, 10.0, 1.0), Vector3::new(15.0, 10.0, 1.0)));
+
+        manager.unload_map_tile(33, 30, 30);
+        assert!(manager.is_map_loaded(33));
+        manager.unload_map_tile(33, 31, 30);
+        assert!(!manager.is_map_loaded(33));
+        assert_eq!(manager.loaded_models(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_broken_files() {
+        let dir = temp_dir("broken");
+        let manager = VMapManager::new(&dir);
+        assert!(manager.load_map(5, 1, 1).is_err());
+
+        let mut tree = VMAP_MAGIC.to_vec();
+        tree.push(1);
+        tree.extend_from_slice(b"NODE");
+        write_bih(&mut tree, AaBox::default(), &[3 << 30, 1, 0], &[0]);
+        std::fs::write(dir.join("vmaps").join(tree_file_name(5)), &tree[..tree.len() - 2]).unwrap();
+        assert!(manager.load_map(5, 1, 1).is_err());
+        assert!(!manager.is_map_loaded(5));
+
+        // A spawn of a missing model is skipped
+        tree.extend_from_slice(b"GOBJ");
+        std::fs::write(dir.join("vmaps").join(tree_file_name(5)), &tree).unwrap();
+        let mut spawn = spawn_at(1, 0.0, 0.0, 0.0);
+        spawn.name = "missing.wmo".into();
+        std::fs::write(dir.join("vmaps").join(tile_file_name(5, 1, 1)), vmtile(&[(&spawn, 0)])).unwrap();
+        manager.load_map(5, 1, 1).unwrap();
+        assert_eq!(manager.loaded_models(), 0);
+
+        // Truncated tile
+        let bytes = vmtile(&[(&spawn, 0)]);
+        std::fs::write(dir.join("vmaps").join(tile_file_name(5, 1, 2)), &bytes[..bytes.len() - 6]).unwrap();
+        assert!(manager.load_map(5, 1, 2).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+// ModelInstance - A model placed on a map
+// Rust equivalent of ModelInstance.h/cpp
+//
+// Spawns are stored in the .vmtree and .vmtile files as:
+//   flags u32 (MOD_*), ADT id u16, unique id u32, position, rotation in
+//   degrees, scale, bounds if MOD_HAS_BOUND, name length u32 and the name
+//   of the model file without ".vmo"
+// Positions and bounds are in the internal map space the map BIH uses.
+
+use std::io::Read;
+use std::sync::Arc;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::error::MangosError;
+
+use super::read_vector3;
+use super::vector::{AaBox, Ray, Vector3};
+use super::world_model::WorldModel;
+
+pub const MOD_M2: u32 = 1;
+pub const MOD_WORLDSPAWN: u32 = 1 << 1;
+pub const MOD_HAS_BOUND: u32 = 1 << 2;
+
+/// Longest model name accepted
+const MAX_NAME_LENGTH: usize = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSpawn {
+    pub flags: u32,
+    pub adt_id: u16,
+    pub id: u32,
+    pub pos: Vector3,
+    /// Rotation in degrees, y is the yaw
+    pub rot: Vector3,
+    pub scale: f32,
+    pub bound: Option<AaBox>,
+    pub name: String,
+}
+
+impl ModelSpawn {
+    /// Next spawn of a file, None at its end
+    pub fn read_from<R: Read>(reader: &mut R) -> anyhow::Result<Option<Self>> {
+        let flags = match reader.read_u32::<LittleEndian>() {
+            Ok(flags) => flags,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let adt_id = reader.read_u16::<LittleEndian>()?;
+        let id = reader.read_u32::<LittleEndian>()?;
+        let pos = read_vector3(reader)?;
+        let rot = read_vector3(reader)?;
+        let scale = reader.read_f32::<LittleEndian>()?;
+        let bound = if flags & MOD_HAS_BOUND != 0 {
+            Some(AaBox::new(read_vector3(reader)?, read_vector3(reader)?))
+        } else {
+            None
+        };
+
+        let name_length = reader.read_u32::<LittleEndian>()? as usize;
+        if name_length > MAX_NAME_LENGTH {
+            anyhow::bail!(MangosError::Data(format!("model name of spawn {} is {} bytes long", id, name_length)));
+        }
+        let mut name = vec![0u8; name_length];
+        reader.read_exact(&mut name)?;
+
+        Ok(Some(ModelSpawn {
+            flags,
+            adt_id,
+            id,
+            pos,
+            rot,
+            scale,
+            bound,
+            name: String::from_utf8_lossy(&name).into_owned(),
+        }))
+    }
+}
+
+/// Rotation matrix of G3D::Matrix3::fromEulerAnglesZYX
+fn rotation_from_euler_zyx(z: f32, y: f32, x: f32) -> [[f32; 3]; 3] {
+    let (sz, cz) = z.sin_cos();
+    let (sy, cy) = y.sin_cos();
+    let (sx, cx) = x.sin_cos();
+    [
+        [cy * cz, cz * sx * sy - cx * sz, cx * cz * sy + sx * sz],
+        [cy * sz, cx * cz + sx * sy * sz, -cz * sx + cx * sy * sz],
+        [-sy, cy * sx, cx * cy],
+    ]
+}
+
+fn transform(m: &[[f32; 3]; 3], v: Vector3) -> Vector3 {
+    Vector3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+/// A spawn with its model, answering queries in map space
+#[derive(Debug, Clone)]
+pub struct ModelInstance {
+    spawn: ModelSpawn,
+    /// Map space to model space rotation
+    inv_rot: [[f32; 3]; 3],
+    inv_scale: f32,
+    model: Arc<WorldModel>,
+}
+
+impl ModelInstance {
+    pub fn new(spawn: ModelSpawn, model: Arc<WorldModel>) -> Self {
+        let rot = rotation_from_euler_zyx(
+            spawn.rot.y.to_radians(),
+            spawn.rot.x.to_radians(),
+            spawn.rot.z.to_radians(),
+        );
+        // Rotations are orthonormal, the inverse is the transpose
+        let inv_rot = std::array::from_fn(|row| std::array::from_fn(|col| rot[col][row]));
+        let inv_scale = 1.0 / spawn.scale;
+        ModelInstance { spawn, inv_rot, inv_scale, model }
+    }
+
+    pub fn spawn(&self) -> &ModelSpawn {
+        &self.spawn
+    }
+
+    pub fn model(&self) -> &Arc<WorldModel> {
+        &self.model
+    }
+
+    /// Nearest hit within `max_dist` in map space, shortening `max_dist`
+    pub fn intersect_ray(&self, ray: &Ray, max_dist: &mut f32, stop_at_first_hit: bool) -> bool {
+        let Some(bound) = &self.spawn.bound else {
+            return false;
+        };
+        if ray.intersection_time(bound).is_none() {
+            return false;
+        }
+
+        // Model bounds are in model space
+        let origin = transform(&self.inv_rot, ray.origin - self.spawn.pos) * self.inv_scale;
+        let model_ray = Ray::new(origin, transform(&self.inv_rot, ray.direction));
+        let mut distance = *max_dist * self.inv_scale;
+        let hit = self.model.intersect_ray(&model_ray, &mut distance, stop_at_first_hit);
+        if hit {
+            *max_dist = distance * self.spawn.scale;
+        }
+        hit
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::vmap::world_model::tests::{bounds_of, build_vmo, floor_and_wall};
+
+    /// A spawn as the assembler writes it
+    pub fn write_spawn(out: &mut Vec<u8>, spawn: &ModelSpawn) {
+        out.extend_from_slice(&spawn.flags.to_le_bytes());
+        out.extend_from_slice(&spawn.adt_id.to_le_bytes());
+        out.extend_from_slice(&spawn.id.to_le_bytes());
+        for v in [spawn.pos, spawn.rot] {
+            for c in [v.x, v.y, v.z] {
+                out.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&spawn.scale.to_le_bytes());
+        if let Some(bound) = spawn.bound {
+            for v in [bound.low, bound.high] {
+                for c in [v.x, v.y, v.z] {
+                    out.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+        }
+        out.extend_from_slice(&(spawn.name.len() as u32).to_le_bytes());
+        out.extend_from_slice(spawn.name.as_bytes());
+    }
+
+    /// Spawn of the floor_and_wall model with bounds like the assembler's
+    pub fn wall_spawn(id: u32, pos: Vector3, yaw: f32, scale: f32) -> ModelSpawn {
+        let rot = rotation_from_euler_zyx(yaw.to_radians(), 0.0, 0.0);
+        let vertices: Vec<Vector3> = floor_and_wall()
+            .into_iter()
+            .flat_map(|(vertices, _)| vertices)
+            .map(|v| transform(&rot, v * scale) + pos)
+            .collect();
+        ModelSpawn {
+            flags: MOD_HAS_BOUND,
+            adt_id: 0,
+            id,
+            pos,
+            rot: Vector3::new(0.0, yaw, 0.0),
+            scale,
+            bound: Some(bounds_of(&vertices)),
+            name: "wall.wmo".into(),
+        }
+    }
+
+    fn wall_model() -> Arc<WorldModel> {
+        Arc::new(WorldModel::read_from(&mut build_vmo(&floor_and_wall()).as_slice()).unwrap())
+    }
+
+    #[test]
+    fn test_spawn_roundtrip() {
+        let spawn = wall_spawn(7, Vector3::new(1.0, 2.0, 3.0), 45.0, 1.5);
+        let mut bytes = Vec::new();
+        write_spawn(&mut bytes, &spawn);
+        let mut reader = bytes.as_slice();
+        assert_eq!(ModelSpawn::read_from(&mut reader).unwrap(), Some(spawn));
+        assert_eq!(ModelSpawn::read_from(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_instance_transform() {
+        let pos = Vector3::new(100.0, 100.0, 50.0);
+        let x = Vector3::new(1.0, 0.0, 0.0);
+        let y = Vector3::new(0.0, 1.0, 0.0);
+
+        let instance = ModelInstance::new(wall_spawn(1, pos, 0.0, 2.0), wall_model());
+        let mut dist = 100.0;
+        assert!(instance.intersect_ray(&Ray::new(pos + Vector3::new(-8.0, 0.0, 5.0), x), &mut dist, false));
+        assert!((dist - 8.0).abs() < 1e-3);
+        // Scaled to 40 yards wide
+        let mut dist = 100.0;
+        assert!(instance.intersect_ray(&Ray::new(pos + Vector3::new(-8.0, 15.0, 15.0), x), &mut dist, false));
+
+        // Turned by 90 degrees the wall runs along x
+        let instance = ModelInstance::new(wall_spawn(2, pos, 90.0, 1.0), wall_model());
+        let mut dist = 100.0;
+        assert!(!instance.intersect_ray(&Ray::new(pos + Vector3::new(-8.0, 1.0, 5.0), x), &mut dist, false));
+        assert!(instance.intersect_ray(&Ray::new(pos + Vector3::new(1.0, -8.0, 5.0), y), &mut dist, false));
+        assert!((dist - 8.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,162 @@
+// Vector3, AaBox and Ray - The few G3D types the vmap queries need
+// Rust equivalent of G3D::Vector3, G3D::AABox and G3D::Ray
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vector3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Vector3 { x, y, z }
+    }
+
+    /// Component by axis number, 0 = x, 1 = y, 2 = z
+    pub fn axis(&self, axis: usize) -> f32 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+
+    pub fn dot(&self, other: Vector3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: Vector3) -> Vector3 {
+        Vector3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn min(&self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z))
+    }
+
+    pub fn max(&self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Vector3;
+
+    fn sub(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vector3 {
+    type Output = Vector3;
+
+    fn mul(self, s: f32) -> Vector3 {
+        Vector3::new(self.x * s, self.y * s, self.z * s)
+    }
+}
+
+impl Div<f32> for Vector3 {
+    type Output = Vector3;
+
+    fn div(self, s: f32) -> Vector3 {
+        Vector3::new(self.x / s, self.y / s, self.z / s)
+    }
+}
+
+impl Neg for Vector3 {
+    type Output = Vector3;
+
+    fn neg(self) -> Vector3 {
+        Vector3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// Axis aligned box
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AaBox {
+    pub low: Vector3,
+    pub high: Vector3,
+}
+
+impl AaBox {
+    pub fn new(low: Vector3, high: Vector3) -> Self {
+        AaBox { low, high }
+    }
+}
+
+/// Half line from `origin` along the unit vector `direction`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3,
+    pub direction: Vector3,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3, direction: Vector3) -> Self {
+        Ray { origin, direction }
+    }
+
+    /// Distance at which the ray enters `bounds`, 0 from inside, None if it misses
+    pub fn intersection_time(&self, bounds: &AaBox) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+        for axis in 0..3 {
+            let origin = self.origin.axis(axis);
+            let dir = self.direction.axis(axis);
+            let (low, high) = (bounds.low.axis(axis), bounds.high.axis(axis));
+            if dir == 0.0 {
+                if origin < low || origin > high {
+                    return None;
+                }
+                continue;
+            }
+            let (t1, t2) = ((low - origin) / dir, (high - origin) / dir);
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+            if t_min > t_max {
+                return None;
+            }
+        }
+        Some(t_min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ray_box_intersection() {
+        let bounds = AaBox::new(Vector3::new(1.0, -1.0, -1.0), Vector3::new(3.0, 1.0, 1.0));
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersection_time(&bounds), Some(1.0));
+
+        // From inside
+        let ray = Ray::new(Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(ray.intersection_time(&bounds), Some(0.0));
+
+        // Pointing away, and parallel next to it
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0));
+        assert_eq!(ray.intersection_time(&bounds), None);
+        let ray = Ray::new(Vector3::new(0.0, 2.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(ray.intersection_time(&bounds), None);
+    }
+}
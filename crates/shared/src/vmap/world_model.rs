@@ -0,0 +1,373 @@
+// WorldModel - Collision geometry of one model (.vmo)
+// Rust equivalent of WorldModel.h/cpp
+//
+// A .vmo file (written by `extractors vmap-assemble`) holds the groups of a
+// WMO, or the single group of an M2:
+//   "VMAP_7.0", "WMOD", chunk size, root WMO id
+//   "GMOD"      group count, then per group its bounds, MOGP flags, WMO
+//               group id, "VERT" vertices, "TRIM" triangles, "MBIH" tree
+//               over the triangles and "LIQU" optional liquid; a group
+//               without vertices ends after "VERT"
+//   "GBIH"      tree over the group bounds
+// Everything is in model space, ModelInstance places it on the map.
+
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::Context;
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::error::MangosError;
+
+use super::bih::Bih;
+use super::vector::{AaBox, Ray, Vector3};
+use super::{read_chunk, read_vector3, VMAP_MAGIC};
+
+/// Liquid surface of a WMO group
+#[derive(Debug, Clone)]
+pub struct WmoLiquid {
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    /// Lower corner of the height grid
+    pub corner: Vector3,
+    pub liquid_type: u32,
+    /// (tiles_x + 1) * (tiles_y + 1) heights
+    pub heights: Vec<f32>,
+    /// tiles_x * tiles_y flags
+    pub flags: Vec<u8>,
+}
+
+impl WmoLiquid {
+    fn read_from<R: Read>(reader: &mut R) -> anyhow::Result<Self> {
+        let tiles_x = reader.read_u32::<LittleEndian>()?;
+        let tiles_y = reader.read_u32::<LittleEndian>()?;
+        let corner = read_vector3(reader)?;
+        let liquid_type = reader.read_u32::<LittleEndian>()?;
+
+        let height_count = (tiles_x as u64 + 1) * (tiles_y as u64 + 1);
+        let mut heights = Vec::new();
+        for _ in 0..height_count {
+            heights.push(reader.read_f32::<LittleEndian>()?);
+        }
+        let mut flags = Vec::new();
+        reader.take(tiles_x as u64 * tiles_y as u64).read_to_end(&mut flags)?;
+        if flags.len() as u64 != tiles_x as u64 * tiles_y as u64 {
+            anyhow::bail!(MangosError::Data("liquid flags truncated".into()));
+        }
+        Ok(WmoLiquid { tiles_x, tiles_y, corner, liquid_type, heights, flags })
+    }
+}
+
+/// One WMO group (or M2): triangle mesh with its tree
+#[derive(Debug, Clone)]
+pub struct GroupModel {
+    bound: AaBox,
+    mogp_flags: u32,
+    group_wmo_id: u32,
+    vertices: Vec<Vector3>,
+    triangles: Vec<[u32; 3]>,
+    mesh_tree: Bih,
+    liquid: Option<WmoLiquid>,
+}
+
+impl GroupModel {
+    fn read_from<R: Read>(reader: &mut R) -> anyhow::Result<Self> {
+        let bound = AaBox::new(read_vector3(reader)?, read_vector3(reader)?);
+        let mogp_flags = reader.read_u32::<LittleEndian>()?;
+        let group_wmo_id = reader.read_u32::<LittleEndian>()?;
+        let mut group = GroupModel {
+            bound,
+            mogp_flags,
+            group_wmo_id,
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+            mesh_tree: Bih::default(),
+            liquid: None,
+        };
+
+        read_chunk(reader, b"VERT")?;
+        let _chunk_size = reader.read_u32::<LittleEndian>()?;
+        let count = reader.read_u32::<LittleEndian>()?;
+        if count == 0 {
+            // Groups without collision geometry end here
+            return Ok(group);
+        }
+        for _ in 0..count {
+            group.vertices.push(read_vector3(reader)?);
+        }
+
+        read_chunk(reader, b"TRIM")?;
+        let _chunk_size = reader.read_u32::<LittleEndian>()?;
+        let count = reader.read_u32::<LittleEndian>()?;
+        for _ in 0..count {
+            let triangle = [
+                reader.read_u32::<LittleEndian>()?,
+                reader.read_u32::<LittleEndian>()?,
+                reader.read_u32::<LittleEndian>()?,
+            ];
+            if triangle.iter().any(|&idx| idx as usize >= group.vertices.len()) {
+                anyhow::bail!(MangosError::Data(format!(
+                    "triangle {:?} of group {} points past its {} vertices",
+                    triangle,
+                    group_wmo_id,
+                    group.vertices.len()
+                )));
+            }
+            group.triangles.push(triangle);
+        }
+
+        read_chunk(reader, b"MBIH")?;
+        group.mesh_tree = Bih::read_from(reader)?;
+
+        read_chunk(reader, b"LIQU")?;
+        if reader.read_u32::<LittleEndian>()? > 0 {
+            group.liquid = Some(WmoLiquid::read_from(reader)?);
+        }
+        Ok(group)
+    }
+
+    pub fn bound(&self) -> &AaBox {
+        &self.bound
+    }
+
+    pub fn mogp_flags(&self) -> u32 {
+        self.mogp_flags
+    }
+
+    pub fn group_wmo_id(&self) -> u32 {
+        self.group_wmo_id
+    }
+
+    pub fn liquid(&self) -> Option<&WmoLiquid> {
+        self.liquid.as_ref()
+    }
+
+    pub fn intersect_ray(&self, ray: &Ray, distance: &mut f32, stop_at_first_hit: bool) -> bool {
+        if self.triangles.is_empty() {
+            return false;
+        }
+        let mut hit = false;
+        self.mesh_tree.intersect_ray(ray, distance, stop_at_first_hit, |ray, entry, distance, _| {
+            let result = self
+                .triangles
+                .get(entry as usize)
+                .is_some_and(|triangle| intersect_triangle(triangle, &self.vertices, ray, distance));
+            hit |= result;
+            result
+        });
+        hit
+    }
+}
+
+/// Ray/triangle test (Moeller-Trumbore), shortens `distance` on a closer hit
+fn intersect_triangle(triangle: &[u32; 3], vertices: &[Vector3], ray: &Ray, distance: &mut f32) -> bool {
+    const EPS: f32 = 1e-5;
+
+    let p0 = vertices[triangle[0] as usize];
+    let e1 = vertices[triangle[1] as usize] - p0;
+    let e2 = vertices[triangle[2] as usize] - p0;
+    let p = ray.direction.cross(e2);
+    let a = e1.dot(p);
+    if a.abs() < EPS {
+        // Ray parallel to the triangle
+        return false;
+    }
+
+    let f = 1.0 / a;
+    let s = ray.origin - p0;
+    let q = s.cross(e1);
+    let u = f * s.dot(p);
+    if !(0.0..=1.0).contains(&u) {
+        return false;
+    }
+    let v = f * ray.direction.dot(q);
+    if v < 0.0 || u + v > 1.0 {
+        return false;
+    }
+
+    let t = f * e2.dot(q);
+    if t > 0.0 && t < *distance {
+        *distance = t;
+        return true;
+    }
+    false
+}
+
+/// A loaded .vmo model
+#[derive(Debug, Clone)]
+pub struct WorldModel {
+    root_wmo_id: u32,
+    group_models: Vec<GroupModel>,
+    group_tree: Bih,
+}
+
+impl WorldModel {
+    pub fn read_from<R: Read>(reader: &mut R) -> anyhow::Result<Self> {
+        read_chunk(reader, VMAP_MAGIC)?;
+        read_chunk(reader, b"WMOD")?;
+        let _chunk_size = reader.read_u32::<LittleEndian>()?;
+        let root_wmo_id = reader.read_u32::<LittleEndian>()?;
+        let mut model = WorldModel { root_wmo_id, group_models: Vec::new(), group_tree: Bih::default() };
+
+        // Models without groups end after the header
+        let mut chunk = [0u8; 4];
+        match reader.read_exact(&mut chunk) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(model),
+            result => result?,
+        }
+        if &chunk != b"GMOD" {
+            anyhow::bail!(MangosError::Data(format!("expected GMOD chunk, found {:?}", chunk)));
+        }
+        let count = reader.read_u32::<LittleEndian>()?;
+        for _ in 0..count {
+            model.group_models.push(GroupModel::read_from(reader)?);
+        }
+        read_chunk(reader, b"GBIH")?;
+        model.group_tree = Bih::read_from(reader)?;
+        Ok(model)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+        Self::read_from(&mut bytes.as_slice()).with_context(|| format!("cannot parse {}", path.display()))
+    }
+
+    pub fn root_wmo_id(&self) -> u32 {
+        self.root_wmo_id
+    }
+
+    pub fn group_models(&self) -> &[GroupModel] {
+        &self.group_models
+    }
+
+    /// Nearest hit within `distance` in model space, shortening `distance`
+    pub fn intersect_ray(&self, ray: &Ray, distance: &mut f32, stop_at_first_hit: bool) -> bool {
+        // A single group (every M2) needs no group tree
+        if let [group] = self.group_models.as_slice() {
+            return group.intersect_ray(ray, distance, stop_at_first_hit);
+        }
+        let mut hit = false;
+        self.group_tree.intersect_ray(ray, distance, stop_at_first_hit, |ray, entry, distance, stop| {
+            let result = self
+                .group_models
+                .get(entry as usize)
+                .is_some_and(|group| group.intersect_ray(ray, distance, stop));
+            hit |= result;
+            result
+        });
+        hit
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::vmap::bih::tests::{leaf_bih, write_bih};
+
+    fn push_vector(out: &mut Vec<u8>, v: Vector3) {
+        for c in [v.x, v.y, v.z] {
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+
+    /// Bounds of a vertex list
+    pub fn bounds_of(vertices: &[Vector3]) -> AaBox {
+        let mut bounds = AaBox::new(vertices[0], vertices[0]);
+        for v in vertices {
+            bounds = AaBox::new(bounds.low.min(*v), bounds.high.max(*v));
+        }
+        bounds
+    }
+
+    /// A .vmo file with one group per mesh, every tree a single leaf
+    pub fn build_vmo(meshes: &[(Vec<Vector3>, Vec<[u32; 3]>)]) -> Vec<u8> {
+        let mut out = VMAP_MAGIC.to_vec();
+        out.extend_from_slice(b"WMOD");
+        out.extend_from_slice(&8u32.to_le_bytes());
+        out.extend_from_slice(&77u32.to_le_bytes());
+        out.extend_from_slice(b"GMOD");
+        out.extend_from_slice(&(meshes.len() as u32).to_le_bytes());
+        for (group_id, (vertices, triangles)) in meshes.iter().enumerate() {
+            let bounds = bounds_of(vertices);
+            push_vector(&mut out, bounds.low);
+            push_vector(&mut out, bounds.high);
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&(group_id as u32).to_le_bytes());
+            out.extend_from_slice(b"VERT");
+            out.extend_from_slice(&(4 + 12 * vertices.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(vertices.len() as u32).to_le_bytes());
+            for v in vertices {
+                push_vector(&mut out, *v);
+            }
+            out.extend_from_slice(b"TRIM");
+            out.extend_from_slice(&(4 + 12 * triangles.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+            for idx in triangles.iter().flatten() {
+                out.extend_from_slice(&idx.to_le_bytes());
+            }
+            out.extend_from_slice(b"MBIH");
+            leaf_bih(&mut out, bounds, triangles.len() as u32);
+            out.extend_from_slice(b"LIQU");
+            out.extend_from_slice(&0u32.to_le_bytes());
+        }
+        out.extend_from_slice(b"GBIH");
+        let all: Vec<Vector3> = meshes.iter().flat_map(|(vertices, _)| vertices.iter().copied()).collect();
+        let groups: Vec<u32> = (0..meshes.len() as u32).collect();
+        write_bih(&mut out, bounds_of(&all), &[3 << 30, meshes.len() as u32, 0], &groups);
+        out
+    }
+
+    /// Floor at z = 0 and a wall in the plane x = 0, both 20 yards wide
+    pub fn floor_and_wall() -> Vec<(Vec<Vector3>, Vec<[u32; 3]>)> {
+        let floor = vec![
+            Vector3::new(-10.0, -10.0, 0.0),
+            Vector3::new(10.0, -10.0, 0.0),
+            Vector3::new(10.0, 10.0, 0.0),
+            Vector3::new(-10.0, 10.0, 0.0),
+        ];
+        let wall = vec![
+            Vector3::new(0.0, -10.0, 0.0),
+            Vector3::new(0.0, 10.0, 0.0),
+            Vector3::new(0.0, 10.0, 10.0),
+            Vector3::new(0.0, -10.0, 10.0),
+        ];
+        let quad = vec![[0, 1, 2], [0, 2, 3]];
+        vec![(floor, quad.clone()), (wall, quad)]
+    }
+
+    #[test]
+    fn test_model_intersection() {
+        let model = WorldModel::read_from(&mut build_vmo(&floor_and_wall()).as_slice()).unwrap();
+        assert_eq!(model.root_wmo_id(), 77);
+        assert_eq!(model.group_models().len(), 2);
+
+        // Through the wall
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 2.0), Vector3::new(1.0, 0.0, 0.0));
+        let mut distance = 100.0;
+        assert!(model.intersect_ray(&ray, &mut distance, false));
+        assert!((distance - 5.0).abs() < 1e-4);
+
+        // Down onto the floor, nearer than the wall
+        let ray = Ray::new(Vector3::new(3.0, 3.0, 4.0), Vector3::new(0.0, 0.0, -1.0));
+        let mut distance = 100.0;
+        assert!(model.intersect_ray(&ray, &mut distance, false));
+        assert!((distance - 4.0).abs() < 1e-4);
+
+        // Out of reach, and along the wall
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 2.0), Vector3::new(1.0, 0.0, 0.0));
+        let mut distance = 4.0;
+        assert!(!model.intersect_ray(&ray, &mut distance, false));
+        assert_eq!(distance, 4.0);
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 2.0), Vector3::new(0.0, 1.0, 0.0));
+        assert!(!model.intersect_ray(&ray, &mut 100.0, false));
+    }
+
+    #[test]
+    fn test_model_rejects_bad_triangles() {
+        let (vertices, _) = floor_and_wall().remove(0);
+        let bytes = build_vmo(&[(vertices, vec![[0, 1, 4]])]);
+        assert!(WorldModel::read_from(&mut bytes.as_slice()).is_err());
+        let bytes = build_vmo(&floor_and_wall());
+        assert!(WorldModel::read_from(&mut &bytes[..bytes.len() - 10]).is_err());
+    }
+}
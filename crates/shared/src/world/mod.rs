@@ -0,0 +1,15 @@
+// World module - World server packets and opcodes
+// Rust equivalent of WorldPacket.h and Opcodes.h/cpp (TBC 2.4.3)
+//
+// Shared so mangosd, packet tooling and tests build and read world packets
+// the same way. Socket handling and header encryption stay in mangosd.
+//
+// uptime keeps the realm's uptime row and realmlist population current.
+
+mod opcodes;
+mod uptime;
+mod world_packet;
+
+pub use opcodes::{opcode_name, Opcode};
+pub use world_packet::{parse_client_header, WorldPacket, CLIENT_HEADER_LEN, SERVER_HEADER_LEN};
+pub use uptime::{realm_population, UptimeReporter};
@@ -0,0 +1,125 @@
+// Opcodes - World packet opcodes (TBC 2.4.3)
+// Rust equivalent of the opcode enum and opcode table in Opcodes.h/cpp
+//
+// Only opcodes the Rust world server or its tooling use are listed; unknown
+// values are still carried as plain u16 by WorldPacket and named
+// "UNKNOWN_OPCODE" in logs.
+
+/// Defines the Opcode enum together with its value lookup and name table
+macro_rules! opcodes {
+    ($($name:ident = $value:literal,)+) => {
+        /// World packet opcodes
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u16)]
+        pub enum Opcode {
+            $($name = $value,)+
+        }
+
+        impl Opcode {
+            /// Every known opcode, in ascending order
+            pub const ALL: &'static [Opcode] = &[$(Opcode::$name,)+];
+
+            pub fn from_u16(val: u16) -> Option<Self> {
+                match val {
+                    $($value => Some(Opcode::$name),)+
+                    _ => None,
+                }
+            }
+
+            /// Name as used by the C++ opcode table, e.g. "CMSG_PING"
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Opcode::$name => stringify!($name),)+
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    MSG_NULL_ACTION = 0x000,
+    CMSG_CHAR_CREATE = 0x036,
+    CMSG_CHAR_ENUM = 0x037,
+    CMSG_CHAR_DELETE = 0x038,
+    SMSG_CHAR_CREATE = 0x03A,
+    SMSG_CHAR_ENUM = 0x03B,
+    SMSG_CHAR_DELETE = 0x03C,
+    CMSG_PLAYER_LOGIN = 0x03D,
+    SMSG_NEW_WORLD = 0x03E,
+    SMSG_TRANSFER_PENDING = 0x03F,
+    SMSG_CHARACTER_LOGIN_FAILED = 0x041,
+    SMSG_LOGIN_SETTIMESPEED = 0x042,
+    CMSG_PLAYER_LOGOUT = 0x04A,
+    CMSG_LOGOUT_REQUEST = 0x04B,
+    SMSG_LOGOUT_RESPONSE = 0x04C,
+    SMSG_LOGOUT_COMPLETE = 0x04D,
+    CMSG_LOGOUT_CANCEL = 0x04E,
+    SMSG_LOGOUT_CANCEL_ACK = 0x04F,
+    CMSG_NAME_QUERY = 0x050,
+    SMSG_NAME_QUERY_RESPONSE = 0x051,
+    CMSG_MESSAGECHAT = 0x095,
+    SMSG_MESSAGECHAT = 0x096,
+    SMSG_UPDATE_OBJECT = 0x0A9,
+    SMSG_TUTORIAL_FLAGS = 0x0FD,
+    SMSG_NOTIFICATION = 0x1CB,
+    CMSG_PING = 0x1DC,
+    SMSG_PONG = 0x1DD,
+    SMSG_AUTH_CHALLENGE = 0x1EC,
+    CMSG_AUTH_SESSION = 0x1ED,
+    SMSG_AUTH_RESPONSE = 0x1EE,
+    SMSG_COMPRESSED_UPDATE_OBJECT = 0x1F6,
+    SMSG_ACCOUNT_DATA_TIMES = 0x209,
+    CMSG_REQUEST_ACCOUNT_DATA = 0x20A,
+    CMSG_UPDATE_ACCOUNT_DATA = 0x20B,
+    SMSG_LOGIN_VERIFY_WORLD = 0x236,
+    SMSG_WARDEN_DATA = 0x2E6,
+    CMSG_WARDEN_DATA = 0x2E7,
+    SMSG_ADDON_INFO = 0x2EF,
+    SMSG_MOTD = 0x33D,
+    SMSG_REALM_SPLIT = 0x38B,
+    CMSG_REALM_SPLIT = 0x38C,
+    SMSG_TIME_SYNC_REQ = 0x390,
+    CMSG_TIME_SYNC_RESP = 0x391,
+    CMSG_KEEP_ALIVE = 0x407,
+}
+
+/// Name of a raw opcode value for logs and packet dumps
+pub fn opcode_name(opcode: u16) -> &'static str {
+    Opcode::from_u16(opcode).map_or("UNKNOWN_OPCODE", Opcode::name)
+}
+
+impl From<Opcode> for u16 {
+    fn from(opcode: Opcode) -> u16 {
+        opcode as u16
+    }
+}
+
+impl std::fmt::Display for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:#06x})", self.name(), *self as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_round_trip() {
+        for window in Opcode::ALL.windows(2) {
+            assert!((window[0] as u16) < (window[1] as u16), "{} listed out of order", window[1]);
+        }
+        for &opcode in Opcode::ALL {
+            assert_eq!(Opcode::from_u16(opcode as u16), Some(opcode));
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(Opcode::CMSG_AUTH_SESSION.name(), "CMSG_AUTH_SESSION");
+        assert_eq!(Opcode::SMSG_PONG.to_string(), "SMSG_PONG (0x01dd)");
+        assert_eq!(opcode_name(0x1EC), "SMSG_AUTH_CHALLENGE");
+        assert_eq!(opcode_name(0xFFFF), "UNKNOWN_OPCODE");
+    }
+}
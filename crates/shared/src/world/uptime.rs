@@ -0,0 +1,231 @@
+// UptimeReporter - Realm state written to the login database
+// Rust equivalent of the uptime and population updates in World.cpp
+//
+// At startup the world server adds its row to the uptime table (realm id,
+// start time) and clears REALM_FLAG_OFFLINE of its realmlist entry. Every
+// UpdateUptimeInterval it writes the seconds since the start and the most
+// players seen online into that row, and the population into realmlist:
+// online players over PlayerLimit, doubled, which the client shows as
+// low (< 1), medium or high (>= 2). On shutdown the realm is set offline.
+//
+// realmd reads starttime + uptime as the realm's heartbeat and a population
+// change as a sign of life, see RealmHeartbeatTimeout and RealmStaleTimeout.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{Local, TimeZone};
+use tokio::sync::watch;
+
+use crate::database::Database;
+use crate::log::LOG_TARGET_DB_ERROR;
+use crate::RealmFlags;
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Population value of realmlist for `online` players out of `player_limit`
+pub fn realm_population(online: u32, player_limit: u32) -> f32 {
+    if player_limit == 0 {
+        return 0.0;
+    }
+    online as f32 / player_limit as f32 * 2.0
+}
+
+/// Writes the uptime and population of one realm
+pub struct UptimeReporter {
+    db: Database,
+    realm_id: u32,
+    /// Unix time of the start, the second key of the uptime row
+    start_time: u64,
+    player_limit: u32,
+    online: AtomicU32,
+    max_online: AtomicU32,
+}
+
+impl UptimeReporter {
+    /// Add the uptime row of this start and show the realm online
+    pub async fn start(db: Database, realm_id: u32, player_limit: u32) -> anyhow::Result<Arc<Self>> {
+        let start_time = unix_now();
+        let start_string = Local
+            .timestamp_opt(start_time as i64, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d_%H-%M-%S").to_string())
+            .unwrap_or_default();
+        db.execute(&format!(
+            "INSERT INTO uptime (realmid, starttime, startstring, uptime, maxplayers) VALUES('{}', '{}', '{}', 0, 0)",
+            realm_id, start_time, start_string
+        ))
+        .await?;
+        db.execute(&format!(
+            "UPDATE realmlist SET realmflags = realmflags & ~{}, population = 0 WHERE id = '{}'",
+            RealmFlags::REALM_FLAG_OFFLINE, realm_id
+        ))
+        .await?;
+        tracing::info!("Realm {} set online, uptime recorded from {}", realm_id, start_string);
+
+        Ok(Arc::new(UptimeReporter {
+            db,
+            realm_id,
+            start_time,
+            player_limit,
+            online: AtomicU32::new(0),
+            max_online: AtomicU32::new(0),
+        }))
+    }
+
+    pub fn realm_id(&self) -> u32 {
+        self.realm_id
+    }
+
+    pub fn start_time(&self) -> u64 {
+        self.start_time
+    }
+
+    /// Seconds since the start
+    pub fn uptime(&self) -> u64 {
+        unix_now().saturating_sub(self.start_time)
+    }
+
+    /// A player entered the world
+    pub fn player_joined(&self) {
+        let online = self.online.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max_online.fetch_max(online, Ordering::Relaxed);
+    }
+
+    /// A player left the world
+    pub fn player_left(&self) {
+        let _ = self.online.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |online| online.checked_sub(1));
+    }
+
+    pub fn online_count(&self) -> u32 {
+        self.online.load(Ordering::Relaxed)
+    }
+
+    /// Most players online at once since the start
+    pub fn max_online_count(&self) -> u32 {
+        self.max_online.load(Ordering::Relaxed)
+    }
+
+    pub fn population(&self) -> f32 {
+        realm_population(self.online_count(), self.player_limit)
+    }
+
+    /// Write the uptime row and the realmlist population
+    pub async fn update(&self) -> anyhow::Result<()> {
+        self.db
+            .execute(&format!(
+                "UPDATE uptime SET uptime = '{}', maxplayers = '{}' WHERE realmid = '{}' AND starttime = '{}'",
+                self.uptime(),
+                self.max_online_count(),
+                self.realm_id,
+                self.start_time
+            ))
+            .await?;
+        let sql = format!("UPDATE realmlist SET population = '{}' WHERE id = '{}'", self.population(), self.realm_id);
+        self.db.execute(&sql).await?;
+        Ok(())
+    }
+
+    /// Write the final uptime and show the realm offline
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        self.update().await?;
+        self.db
+            .execute(&format!(
+                "UPDATE realmlist SET realmflags = realmflags | {} WHERE id = '{}'",
+                RealmFlags::REALM_FLAG_OFFLINE, self.realm_id
+            ))
+            .await?;
+        tracing::info!("Realm {} set offline after {}s", self.realm_id, self.uptime());
+        Ok(())
+    }
+
+    /// Update every `interval` until the stop signal, then shut down
+    pub fn spawn(self: Arc<Self>, interval: Duration, mut stop: watch::Receiver<bool>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick is immediate and start() already wrote the row
+            ticks.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = stop.wait_for(|stopped| *stopped) => break,
+                }
+                if let Err(e) = self.update().await {
+                    let realm_id = self.realm_id;
+                    tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot update uptime of realm {}: {:#}", realm_id, e);
+                }
+            }
+            if let Err(e) = self.shutdown().await {
+                tracing::error!(target: LOG_TARGET_DB_ERROR, "Cannot set realm {} offline: {:#}", self.realm_id, e);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::FieldExt;
+
+    #[test]
+    fn test_realm_population() {
+        assert_eq!(realm_population(0, 100), 0.0);
+        assert_eq!(realm_population(50, 100), 1.0);
+        assert_eq!(realm_population(150, 100), 3.0);
+        assert_eq!(realm_population(10, 0), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_uptime_rows() {
+        let dir = std::env::temp_dir().join(format!("mangos_uptime_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut db = Database::new("Login");
+        db.initialize(&format!("sqlite://{}?mode=rwc", dir.join("login.db").display())).await.unwrap();
+        db.execute(
+            "CREATE TABLE realmlist (id INTEGER PRIMARY KEY, realmflags INTEGER NOT NULL DEFAULT 2, \
+             population REAL NOT NULL DEFAULT 0)",
+        )
+        .await
+        .unwrap();
+        db.execute(
+            "CREATE TABLE uptime (realmid INTEGER NOT NULL, starttime INTEGER NOT NULL DEFAULT 0, \
+             startstring TEXT NOT NULL DEFAULT '', uptime INTEGER NOT NULL DEFAULT 0, \
+             maxplayers INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (realmid, starttime))",
+        )
+        .await
+        .unwrap();
+        db.execute("INSERT INTO realmlist (id, realmflags, population) VALUES (1, 0x42, 1.5)").await.unwrap();
+
+        let realm_state = |db: Database| async move {
+            let row = db.query_one("SELECT realmflags, population FROM realmlist WHERE id = 1").await.unwrap().unwrap();
+            (row.get_u8(0), row.get_f32(1))
+        };
+
+        let reporter = UptimeReporter::start(db.clone(), 1, 4).await.unwrap();
+        assert_eq!(realm_state(db.clone()).await, (0x40, 0.0));
+
+        reporter.player_joined();
+        reporter.player_joined();
+        reporter.player_joined();
+        reporter.player_left();
+        assert_eq!((reporter.online_count(), reporter.max_online_count()), (2, 3));
+        reporter.update().await.unwrap();
+        assert_eq!(realm_state(db.clone()).await, (0x40, 1.0));
+
+        let row = db.query_one("SELECT realmid, starttime, maxplayers FROM uptime").await.unwrap().unwrap();
+        assert_eq!((row.get_u32(0), row.get_u64(1), row.get_u32(2)), (1, reporter.start_time(), 3));
+
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let task = reporter.clone().spawn(Duration::from_secs(3600), stop_rx);
+        stop_tx.send_replace(true);
+        task.await.unwrap();
+        assert_eq!(realm_state(db.clone()).await, (0x42, 1.0));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,161 @@
+// WorldPacket - Opcode plus payload of a world server packet
+// Rust equivalent of WorldPacket.h
+//
+// Like the C++ class the packet is a ByteBuffer with an opcode attached; the
+// buffer API is reachable through Deref. Headers on the wire:
+//   server -> client: u16 size (big-endian, counting the opcode), u16 opcode
+//   client -> server: u16 size (big-endian, counting the opcode), u32 opcode
+// Header encryption is applied by the socket, not here.
+
+use std::ops::{Deref, DerefMut};
+
+use crate::util::ByteBuffer;
+
+use super::opcodes::{opcode_name, Opcode};
+
+/// Length of a header sent by the server
+pub const SERVER_HEADER_LEN: usize = 4;
+
+/// Length of a header sent by the client
+pub const CLIENT_HEADER_LEN: usize = 6;
+
+/// A world packet: opcode and payload
+#[derive(Debug, Clone)]
+pub struct WorldPacket {
+    opcode: u16,
+    buffer: ByteBuffer,
+}
+
+impl WorldPacket {
+    /// Empty packet to be filled through the ByteBuffer writers
+    pub fn new(opcode: impl Into<u16>) -> Self {
+        WorldPacket { opcode: opcode.into(), buffer: ByteBuffer::new() }
+    }
+
+    pub fn with_capacity(opcode: impl Into<u16>, capacity: usize) -> Self {
+        WorldPacket { opcode: opcode.into(), buffer: ByteBuffer::with_capacity(capacity) }
+    }
+
+    /// Wrap a received payload for reading
+    ///
+    /// Client opcodes travel as u32 but every valid one fits a u16; the
+    /// caller rejects larger values before building the packet.
+    pub fn from_parts(opcode: u16, body: Vec<u8>) -> Self {
+        WorldPacket { opcode, buffer: ByteBuffer::from_vec(body) }
+    }
+
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    pub fn set_opcode(&mut self, opcode: impl Into<u16>) {
+        self.opcode = opcode.into();
+    }
+
+    /// The opcode if it is in the opcode table
+    pub fn known_opcode(&self) -> Option<Opcode> {
+        Opcode::from_u16(self.opcode)
+    }
+
+    pub fn opcode_name(&self) -> &'static str {
+        opcode_name(self.opcode)
+    }
+
+    /// Header the server sends in front of this packet, before encryption
+    pub fn server_header(&self) -> std::io::Result<[u8; SERVER_HEADER_LEN]> {
+        let size = self.wire_size(2)?;
+        let mut header = [0u8; SERVER_HEADER_LEN];
+        header[..2].copy_from_slice(&size.to_be_bytes());
+        header[2..].copy_from_slice(&self.opcode.to_le_bytes());
+        Ok(header)
+    }
+
+    /// Header a client sends in front of this packet, before encryption
+    pub fn client_header(&self) -> std::io::Result<[u8; CLIENT_HEADER_LEN]> {
+        let size = self.wire_size(4)?;
+        let mut header = [0u8; CLIENT_HEADER_LEN];
+        header[..2].copy_from_slice(&size.to_be_bytes());
+        header[2..].copy_from_slice(&u32::from(self.opcode).to_le_bytes());
+        Ok(header)
+    }
+
+    fn wire_size(&self, opcode_len: usize) -> std::io::Result<u16> {
+        u16::try_from(self.buffer.size() + opcode_len).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} of {} bytes does not fit a packet header", self.opcode_name(), self.buffer.size()),
+            )
+        })
+    }
+}
+
+/// Split a decrypted client header into payload length and opcode
+pub fn parse_client_header(header: &[u8; CLIENT_HEADER_LEN]) -> std::io::Result<(usize, u32)> {
+    let size = u16::from_be_bytes([header[0], header[1]]) as usize;
+    let opcode = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+    if size < 4 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("client header announces {} bytes", size),
+        ));
+    }
+    Ok((size - 4, opcode))
+}
+
+impl Deref for WorldPacket {
+    type Target = ByteBuffer;
+
+    fn deref(&self) -> &ByteBuffer {
+        &self.buffer
+    }
+}
+
+impl DerefMut for WorldPacket {
+    fn deref_mut(&mut self) -> &mut ByteBuffer {
+        &mut self.buffer
+    }
+}
+
+impl std::fmt::Display for WorldPacket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:#06x}, {} bytes)", self.opcode_name(), self.opcode, self.buffer.size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_header() {
+        let mut packet = WorldPacket::new(Opcode::SMSG_AUTH_CHALLENGE);
+        packet.write_u32(0xDEADBEEF);
+        assert_eq!(packet.server_header().unwrap(), [0x00, 0x06, 0xEC, 0x01]);
+        assert_eq!(packet.contents(), &[0xEF, 0xBE, 0xAD, 0xDE]);
+        assert_eq!(packet.to_string(), "SMSG_AUTH_CHALLENGE (0x01ec, 4 bytes)");
+    }
+
+    #[test]
+    fn test_client_header_round_trip() {
+        let mut packet = WorldPacket::new(Opcode::CMSG_PING);
+        packet.write_u32(7);
+        packet.write_u32(50);
+        let header = packet.client_header().unwrap();
+        assert_eq!(header, [0x00, 0x0C, 0xDC, 0x01, 0x00, 0x00]);
+        assert_eq!(parse_client_header(&header).unwrap(), (8, Opcode::CMSG_PING as u32));
+
+        let mut received = WorldPacket::from_parts(Opcode::CMSG_PING as u16, packet.contents().to_vec());
+        assert_eq!(received.known_opcode(), Some(Opcode::CMSG_PING));
+        assert_eq!(received.read_u32().unwrap(), 7);
+        assert_eq!(received.read_u32().unwrap(), 50);
+    }
+
+    #[test]
+    fn test_invalid_sizes() {
+        assert!(parse_client_header(&[0x00, 0x03, 0xDC, 0x01, 0x00, 0x00]).is_err());
+
+        let mut packet = WorldPacket::new(Opcode::SMSG_UPDATE_OBJECT);
+        packet.append(&vec![0u8; u16::MAX as usize]);
+        assert!(packet.server_header().is_err());
+    }
+}
@@ -0,0 +1,296 @@
+// Client - A 2.4.3 or 3.3.5a game client's side of the login protocol
+//
+// Speaks the logon challenge, SRP6 proof, realm list and reconnect exchanges
+// over a real TCP connection and reports the result codes the server sent,
+// so tests can assert on refusals as well as on successful logins. Both
+// clients use the same packet formats; they differ in the version and build
+// they announce and the executable hash behind their version proof.
+
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use rand::RngCore;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use mangos_shared::auth::{BigNumber, SRP6Client, Sha1Hash};
+use mangos_shared::util::ByteBuffer;
+
+/// Result codes of the logon challenge and proof answers
+pub mod result {
+    pub const SUCCESS: u8 = 0x00;
+    pub const BANNED: u8 = 0x03;
+    pub const UNKNOWN_ACCOUNT: u8 = 0x04;
+    pub const INCORRECT_PASSWORD: u8 = 0x05;
+    pub const VERSION_INVALID: u8 = 0x09;
+    pub const SUSPENDED: u8 = 0x0C;
+    pub const NO_ACCESS: u8 = 0x0D;
+}
+
+const CMD_LOGON_CHALLENGE: u8 = 0x00;
+const CMD_LOGON_PROOF: u8 = 0x01;
+const CMD_RECONNECT_CHALLENGE: u8 = 0x02;
+const CMD_RECONNECT_PROOF: u8 = 0x03;
+const CMD_REALM_LIST: u8 = 0x10;
+
+/// Proof security flag announcing an authenticator code
+const SECURITY_FLAG_AUTHENTICATOR: u8 = 0x04;
+
+/// Version a client announces and proves
+#[derive(Debug, Clone, Copy)]
+pub struct ClientBuild {
+    pub version: [u8; 3],
+    pub build: u16,
+    /// Hash of the Windows executable, which the version proof is made over
+    pub windows_hash: [u8; 20],
+}
+
+impl ClientBuild {
+    /// 2.4.3 (8606), the client of this core
+    pub const TBC: ClientBuild = ClientBuild {
+        version: [2, 4, 3],
+        build: 8606,
+        windows_hash: [
+            0x31, 0x9A, 0xFA, 0xA3, 0xF2, 0x55, 0x96, 0x82, 0xF9, 0xFF,
+            0x65, 0x8B, 0xE0, 0x14, 0x56, 0x25, 0x5F, 0x45, 0x6F, 0xB1,
+        ],
+    };
+
+    /// 3.3.5a (12340)
+    pub const WOTLK: ClientBuild = ClientBuild {
+        version: [3, 3, 5],
+        build: 12340,
+        windows_hash: [
+            0xCD, 0xCB, 0xBD, 0x51, 0x88, 0x31, 0x5E, 0x6B, 0x4D, 0x19,
+            0x44, 0x9D, 0x49, 0x2D, 0xBC, 0xFA, 0xF1, 0x56, 0xA3, 0x47,
+        ],
+    };
+}
+
+/// How the server answered a login
+#[derive(Debug)]
+pub enum LoginOutcome {
+    /// The proof was accepted and the realm list received
+    Success(Session),
+    /// The logon challenge was answered with this result
+    ChallengeRefused(u8),
+    /// The logon proof was answered with this result
+    ProofRefused(u8),
+}
+
+impl LoginOutcome {
+    /// The session of a successful login; panics with the outcome otherwise
+    pub fn expect_success(self) -> Session {
+        match self {
+            LoginOutcome::Success(session) => session,
+            other => panic!("expected a successful login, got {:?}", other),
+        }
+    }
+}
+
+/// An account logged in with a session key
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Upper-cased account name
+    pub account: String,
+    pub session_key: BigNumber,
+    /// Names of the realms in the realm list, in packet order
+    pub realms: Vec<String>,
+    /// The same realms with their flags
+    pub realm_list: Vec<RealmEntry>,
+}
+
+/// A realm as listed to the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RealmEntry {
+    pub name: String,
+    pub flags: u8,
+    /// Build sent with REALM_FLAG_SPECIFYBUILD
+    pub build: Option<u16>,
+}
+
+impl RealmEntry {
+    pub fn is_offline(&self) -> bool {
+        self.flags & mangos_shared::RealmFlags::REALM_FLAG_OFFLINE != 0
+    }
+}
+
+/// Logon challenge (cmd 0x00) or reconnect challenge (0x02) for `account`
+fn challenge_packet(cmd: u8, client: ClientBuild, account: &str) -> ByteBuffer {
+    let mut body = ByteBuffer::new();
+    body.append(b"WoW\0");
+    body.append(&client.version);
+    body.write_u16(client.build);
+    body.append(b"68x\0"); // platform "x86", reversed
+    body.append(b"niW\0"); // os "Win"
+    body.append(b"SUne"); // locale "enUS"
+    body.write_u32(0); // timezone bias
+    body.append(&[127, 0, 0, 1]);
+    body.write_u8(account.len() as u8);
+    body.append(account.as_bytes());
+
+    let mut packet = ByteBuffer::new();
+    packet.write_u8(cmd);
+    packet.write_u8(8);
+    packet.write_u16(body.contents().len() as u16);
+    packet.append(body.contents());
+    packet
+}
+
+async fn read_bytes(stream: &mut TcpStream, len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).await.context("connection closed by the server")?;
+    Ok(buf)
+}
+
+/// Log in as `name` with `password` and fetch the realm list
+pub async fn login(addr: SocketAddr, name: &str, password: &str) -> anyhow::Result<LoginOutcome> {
+    login_as(addr, ClientBuild::TBC, name, password, None).await
+}
+
+/// Log in like [`login`], sending `code` as authenticator code when given,
+/// whether or not the server asked for one
+pub async fn login_with_authenticator(
+    addr: SocketAddr,
+    name: &str,
+    password: &str,
+    code: Option<&str>,
+) -> anyhow::Result<LoginOutcome> {
+    login_as(addr, ClientBuild::TBC, name, password, code).await
+}
+
+/// Log in with the version and executable hash of `client`
+pub async fn login_as(
+    addr: SocketAddr,
+    client: ClientBuild,
+    name: &str,
+    password: &str,
+    code: Option<&str>,
+) -> anyhow::Result<LoginOutcome> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let account = name.to_uppercase();
+
+    stream.write_all(challenge_packet(CMD_LOGON_CHALLENGE, client, &account).contents()).await?;
+    let header = read_bytes(&mut stream, 3).await?;
+    if header[2] != result::SUCCESS {
+        return Ok(LoginOutcome::ChallengeRefused(header[2]));
+    }
+    let b = read_bytes(&mut stream, 32).await?;
+    let g_len = read_bytes(&mut stream, 1).await?[0] as usize;
+    let g = read_bytes(&mut stream, g_len).await?;
+    let n_len = read_bytes(&mut stream, 1).await?[0] as usize;
+    let n = read_bytes(&mut stream, n_len).await?;
+    let salt = read_bytes(&mut stream, 32).await?;
+    let security_flags = read_bytes(&mut stream, 17).await?[16];
+    if security_flags == SECURITY_FLAG_AUTHENTICATOR {
+        read_bytes(&mut stream, 1).await?;
+    } else {
+        anyhow::ensure!(security_flags == 0, "account {} asks for security flags {:#04x}", account, security_flags);
+    }
+
+    let mut srp = SRP6Client::new(&account, password);
+    anyhow::ensure!(srp.process_challenge(&b, &g, &n, &salt), "invalid logon challenge");
+    let a = srp.get_client_public_ephemeral().as_byte_array(32);
+    let mut version_proof = Sha1Hash::new();
+    version_proof.update_data_bytes(&a);
+    version_proof.update_data_bytes(&client.windows_hash);
+    version_proof.finalize();
+    let mut proof = ByteBuffer::new();
+    proof.write_u8(CMD_LOGON_PROOF);
+    proof.append(&a);
+    proof.append(&srp.get_proof().as_byte_array(20));
+    proof.append(version_proof.get_digest());
+    proof.write_u8(0); // number of keys
+    match code {
+        Some(code) => {
+            proof.write_u8(SECURITY_FLAG_AUTHENTICATOR);
+            proof.write_u8(code.len() as u8);
+            proof.append(code.as_bytes());
+        }
+        None => proof.write_u8(0),
+    }
+    stream.write_all(proof.contents()).await?;
+
+    let header = read_bytes(&mut stream, 2).await?;
+    if header[1] != result::SUCCESS {
+        return Ok(LoginOutcome::ProofRefused(header[1]));
+    }
+    // M2, account flags, survey id, login flags
+    let answer = read_bytes(&mut stream, 30).await?;
+    anyhow::ensure!(srp.verify_server_proof(&answer[..20]), "server proof mismatch");
+
+    let realm_list = realm_list(&mut stream).await?;
+    Ok(LoginOutcome::Success(Session {
+        account,
+        session_key: srp.get_strong_session_key().clone(),
+        realms: realm_list.iter().map(|realm| realm.name.clone()).collect(),
+        realm_list,
+    }))
+}
+
+/// Reconnect with the session key of an earlier login; the realm list on
+/// success, None when the server refused or dropped the reconnect
+pub async fn reconnect(addr: SocketAddr, session: &Session) -> anyhow::Result<Option<Vec<String>>> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(challenge_packet(CMD_RECONNECT_CHALLENGE, ClientBuild::TBC, &session.account).contents()).await?;
+
+    let mut header = [0u8; 2];
+    if stream.read_exact(&mut header).await.is_err() || header[1] != result::SUCCESS {
+        return Ok(None);
+    }
+    let challenge = read_bytes(&mut stream, 32).await?;
+
+    let mut r1 = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut r1);
+    let mut client_random = BigNumber::new();
+    client_random.set_binary(&r1);
+    let mut server_random = BigNumber::new();
+    server_random.set_binary(&challenge[..16]);
+    let mut sha = Sha1Hash::new();
+    sha.initialize();
+    sha.update_data(&session.account);
+    sha.update_big_numbers(&[&client_random, &server_random, &session.session_key]);
+    sha.finalize();
+
+    let mut proof = ByteBuffer::new();
+    proof.write_u8(CMD_RECONNECT_PROOF);
+    proof.append(&r1);
+    proof.append(sha.get_digest());
+    proof.append(&[0u8; 20]); // client hash
+    proof.write_u8(0); // number of keys
+    stream.write_all(proof.contents()).await?;
+
+    let mut answer = [0u8; 4];
+    if stream.read_exact(&mut answer).await.is_err() || answer[1] != result::SUCCESS {
+        return Ok(None);
+    }
+    let realms = realm_list(&mut stream).await?;
+    Ok(Some(realms.into_iter().map(|realm| realm.name).collect()))
+}
+
+/// Request the realm list
+async fn realm_list(stream: &mut TcpStream) -> anyhow::Result<Vec<RealmEntry>> {
+    stream.write_all(&[CMD_REALM_LIST, 0, 0, 0, 0]).await?;
+    let header = read_bytes(stream, 3).await?;
+    anyhow::ensure!(header[0] == CMD_REALM_LIST, "expected a realm list, got command {:#04x}", header[0]);
+    let body = read_bytes(stream, u16::from_le_bytes([header[1], header[2]]) as usize).await?;
+
+    let mut pkt = ByteBuffer::from(body);
+    pkt.read_u32()?; // unused
+    let count = pkt.read_u16()?;
+    let mut realms = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (_icon, _lock, flags) = (pkt.read_u8()?, pkt.read_u8()?, pkt.read_u8()?);
+        let name = pkt.read_cstring()?;
+        pkt.read_cstring()?; // address
+        pkt.read_f32()?; // population
+        let _ = (pkt.read_u8()?, pkt.read_u8()?, pkt.read_u8()?); // characters, category, unknown
+        let mut build = None;
+        if flags & mangos_shared::RealmFlags::REALM_FLAG_SPECIFYBUILD != 0 {
+            let _ = (pkt.read_u8()?, pkt.read_u8()?, pkt.read_u8()?);
+            build = Some(pkt.read_u16()?);
+        }
+        realms.push(RealmEntry { name, flags, build });
+    }
+    Ok(realms)
+}
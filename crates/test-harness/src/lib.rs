@@ -0,0 +1,15 @@
+// CMaNGOS TBC - Integration test harness
+//
+// Boots the realmd binary against a temporary SQLite login database and
+// drives logins over real TCP connections, so the whole auth flow can run as
+// ordinary `cargo test` tests (see crates/realmd/tests).
+
+pub mod client;
+mod schema;
+mod server;
+
+pub use client::{
+    login, login_as, login_with_authenticator, reconnect, result, ClientBuild, LoginOutcome, RealmEntry, Session,
+};
+pub use schema::sqlite_schema;
+pub use server::{TestRealmd, TestRealmdBuilder};
@@ -0,0 +1,110 @@
+// Schema - SQLite version of the login database
+//
+// Built from the MySQL dump in resources/sql/realmd.sql at test time, so the
+// tests always run against the tables realmd ships with. Only what SQLite
+// cannot read is rewritten: AUTO_INCREMENT ids become INTEGER PRIMARY KEY
+// AUTOINCREMENT, column comments, plain indexes and table options are dropped
+// and UNIQUE KEY becomes a UNIQUE constraint. MySQL column types stay as they
+// are but for UNSIGNED, which SQLite cannot parse after a length; it picks
+// its type affinity from the names. Of the dumped rows only those of
+// SEED_TABLES are kept, accounts are created through `realmd account create`.
+
+/// Login database as shipped, for MySQL
+const MYSQL_SCHEMA: &str = include_str!("../../../resources/sql/realmd.sql");
+
+/// Tables whose dumped rows the tests start with
+const SEED_TABLES: [&str; 1] = ["realmlist"];
+
+/// Statements creating the login database on SQLite
+pub fn sqlite_schema() -> Vec<String> {
+    translate(MYSQL_SCHEMA)
+}
+
+fn translate(dump: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut lines = dump.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if let Some(rest) = line.strip_prefix("CREATE TABLE ") {
+            let table = rest.trim_end_matches('(').trim();
+            let body: Vec<&str> = lines.by_ref().take_while(|line| !line.starts_with(')')).collect();
+            statements.push(create_table(table, &body));
+        } else if line.starts_with("INSERT INTO ") && SEED_TABLES.iter().any(|table| table_name(line) == *table) {
+            let mut insert = line.to_string();
+            while !insert.ends_with(';') {
+                let Some(next) = lines.next() else { break };
+                insert.push('\n');
+                insert.push_str(next);
+            }
+            statements.push(insert.trim_end_matches(';').to_string());
+        }
+    }
+    statements
+}
+
+/// Table of an INSERT INTO statement, without quotes
+fn table_name(insert: &str) -> &str {
+    let rest = &insert["INSERT INTO ".len()..];
+    rest.split(|c: char| c.is_whitespace() || c == '(').next().unwrap_or_default().trim_matches('`')
+}
+
+fn create_table(table: &str, body: &[&str]) -> String {
+    let mut auto_increment = false;
+    let mut definitions = Vec::new();
+    for line in body {
+        let line = line.trim_end_matches(',');
+        // Column comments end the definition and may hold commas of their own
+        let line = line.find(" COMMENT '").map_or(line, |at| &line[..at]);
+        if line.starts_with("KEY ") || line.starts_with("INDEX ") || line.is_empty() {
+            continue;
+        }
+        if line.starts_with("PRIMARY KEY") {
+            if !auto_increment {
+                definitions.push(line.to_string());
+            }
+        } else if let Some(rest) = line.strip_prefix("UNIQUE KEY ") {
+            let columns = rest.find('(').map_or(rest, |at| &rest[at..]);
+            definitions.push(format!("UNIQUE {}", columns));
+        } else if line.contains("AUTO_INCREMENT") {
+            auto_increment = true;
+            let column = line.split_whitespace().next().unwrap_or_default();
+            definitions.push(format!("{} INTEGER PRIMARY KEY AUTOINCREMENT", column));
+        } else {
+            let line = line.replace(" unsigned", "").replace(" UNSIGNED", "");
+            definitions.push(line.replace("DEFAULT NOW()", "DEFAULT CURRENT_TIMESTAMP"));
+        }
+    }
+    format!("CREATE TABLE {} (\n  {}\n)", table, definitions.join(",\n  "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let dump = "DROP TABLE IF EXISTS `realmlist`;\n\
+            CREATE TABLE `realmlist` (\n\
+            \x20 `id` int(11) unsigned NOT NULL AUTO_INCREMENT,\n\
+            \x20 `name` varchar(32) NOT NULL DEFAULT '' COMMENT 'Shown, in the list',\n\
+            \x20 `flags` tinyint(3) unsigned NOT NULL DEFAULT '0',\n\
+            \x20 `joined` DATETIME NOT NULL DEFAULT NOW(),\n\
+            \x20 PRIMARY KEY (`id`),\n\
+            \x20 UNIQUE KEY `idx_name` (`name`),\n\
+            \x20 KEY `idx_joined` (`joined`)\n\
+            ) ENGINE=MyISAM AUTO_INCREMENT=2 DEFAULT CHARSET=utf8 COMMENT='Realm System';\n\
+            INSERT INTO `realmlist` VALUES\n(1,'MaNGOS','2006-04-25 10:18:56');\n\
+            INSERT INTO `account` VALUES (1,'ADMINISTRATOR');\n\
+            CREATE TABLE account_raf(\nreferrer INT UNSIGNED NOT NULL DEFAULT '0',\nPRIMARY KEY(referrer)\n);\n";
+        assert_eq!(
+            translate(dump),
+            [
+                "CREATE TABLE `realmlist` (\n  `id` INTEGER PRIMARY KEY AUTOINCREMENT,\n  \
+                 `name` varchar(32) NOT NULL DEFAULT '',\n  `flags` tinyint(3) NOT NULL DEFAULT '0',\n  \
+                 `joined` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,\n  \
+                 UNIQUE (`name`)\n)",
+                "INSERT INTO `realmlist` VALUES\n(1,'MaNGOS','2006-04-25 10:18:56')",
+                "CREATE TABLE account_raf (\n  referrer INT NOT NULL DEFAULT '0',\n  PRIMARY KEY(referrer)\n)",
+            ]
+        );
+    }
+}
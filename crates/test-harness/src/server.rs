@@ -0,0 +1,202 @@
+// TestRealmd - A realmd process against a throwaway SQLite login database
+//
+// Every TestRealmd gets its own directory under the system temp directory
+// holding the login database (created from resources/sql/realmd.sql), the config file
+// and realmd's output, listens on a free loopback port and is killed, and its
+// directory removed, when dropped.
+
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use mangos_shared::database::Database;
+use sqlx::any::AnyRow;
+
+use crate::schema;
+
+/// Longest realmd may take to start listening
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Numbers the directories of the servers of one test process
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Settings every test server uses; later entries override earlier ones
+const BASE_CONFIG: [(&str, &str); 4] = [
+    ("LogsDir", ""),
+    ("PidFile", ""),
+    ("BindIP", "127.0.0.1"),
+    ("LogLevel", "3"),
+];
+
+/// A running realmd
+pub struct TestRealmd {
+    binary: PathBuf,
+    dir: PathBuf,
+    config_path: PathBuf,
+    addr: SocketAddr,
+    db: Database,
+    child: Child,
+}
+
+/// Builds a TestRealmd with extra config settings
+pub struct TestRealmdBuilder {
+    binary: PathBuf,
+    config: Vec<(String, String)>,
+}
+
+impl TestRealmdBuilder {
+    /// Set a realmd.conf key, e.g. ("WrongPass.MaxCount", "3")
+    pub fn config(mut self, key: &str, value: &str) -> Self {
+        self.config.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Create the database and start realmd
+    pub async fn start(self) -> anyhow::Result<TestRealmd> {
+        let dir = std::env::temp_dir().join(format!(
+            "realmd-test-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).with_context(|| format!("cannot create {}", dir.display()))?;
+
+        let db_url = format!("sqlite://{}?mode=rwc", dir.join("login.db").display());
+        let mut db = Database::new("Login");
+        db.initialize(&db_url).await?;
+        for statement in schema::sqlite_schema() {
+            db.execute(&statement).await.with_context(|| format!("schema statement failed: {}", statement))?;
+        }
+
+        // Bound and released again, so a later test may take the port first;
+        // that shows up as a startup failure, not a wrong result
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+        let mut conf = String::from("[RealmdConf]\n");
+        let port_string = port.to_string();
+        let settings = BASE_CONFIG
+            .iter()
+            .map(|&(key, value)| (key, value))
+            .chain([("LoginDatabaseInfo", db_url.as_str()), ("RealmServerPort", port_string.as_str())])
+            .chain(self.config.iter().map(|(key, value)| (key.as_str(), value.as_str())));
+        for (key, value) in settings {
+            conf.push_str(&format!("{} = \"{}\"\n", key, value));
+        }
+        let config_path = dir.join("realmd.conf");
+        std::fs::write(&config_path, conf)?;
+
+        let log = std::fs::File::create(dir.join("realmd.log"))?;
+        let child = Command::new(&self.binary)
+            .arg("-c")
+            .arg(&config_path)
+            .current_dir(&dir)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()
+            .with_context(|| format!("cannot start {}", self.binary.display()))?;
+
+        let mut realmd = TestRealmd { binary: self.binary, dir, config_path, addr, db, child };
+        realmd.wait_until_listening().await?;
+        Ok(realmd)
+    }
+}
+
+impl TestRealmd {
+    /// A test server running the realmd binary at `binary`, usually
+    /// env!("CARGO_BIN_EXE_realmd")
+    pub fn builder(binary: impl AsRef<Path>) -> TestRealmdBuilder {
+        TestRealmdBuilder { binary: binary.as_ref().to_path_buf(), config: Vec::new() }
+    }
+
+    /// Start realmd with the default test settings
+    pub async fn start(binary: impl AsRef<Path>) -> anyhow::Result<TestRealmd> {
+        Self::builder(binary).start().await
+    }
+
+    async fn wait_until_listening(&mut self) -> anyhow::Result<()> {
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                anyhow::bail!("realmd exited with {} before listening:\n{}", status, self.output());
+            }
+            if tokio::net::TcpStream::connect(self.addr).await.is_ok() {
+                return Ok(());
+            }
+            if Instant::now() > deadline {
+                anyhow::bail!("realmd did not listen on {} within {:?}:\n{}", self.addr, STARTUP_TIMEOUT, self.output());
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The login database, to seed rows or check what realmd wrote
+    pub fn db(&self) -> &Database {
+        &self.db
+    }
+
+    /// First row of `sql`, waiting up to five seconds for one to appear; for
+    /// rows realmd writes after it answered the client, such as autobans
+    pub async fn wait_for_row(&self, sql: &str) -> anyhow::Result<AnyRow> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(row) = self.db.query_one(sql).await? {
+                return Ok(row);
+            }
+            anyhow::ensure!(Instant::now() < deadline, "no row for {}", sql);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    /// Everything realmd logged so far
+    pub fn output(&self) -> String {
+        std::fs::read_to_string(self.dir.join("realmd.log")).unwrap_or_default()
+    }
+
+    /// Run `realmd <args>` against the same config, e.g. `account create`;
+    /// its standard output, or an error with both outputs when it fails
+    pub fn command(&self, args: &[&str]) -> anyhow::Result<String> {
+        let output = Command::new(&self.binary)
+            .arg("-c")
+            .arg(&self.config_path)
+            .args(args)
+            .current_dir(&self.dir)
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        anyhow::ensure!(
+            output.status.success(),
+            "realmd {} failed with {}:\n{}{}",
+            args.join(" "),
+            output.status,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(stdout)
+    }
+
+    /// Create an account through `realmd account create`
+    pub fn create_account(&self, name: &str, password: &str) -> anyhow::Result<()> {
+        self.command(&["account", "create", name, password]).map(|_| ())
+    }
+}
+
+impl Drop for TestRealmd {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if std::thread::panicking() {
+            eprintln!("realmd output ({}):\n{}", self.dir.display(), self.output());
+        }
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -49,7 +49,7 @@ CREATE TABLE `account` (
   `s` longtext,
   `email` text,
   `joindate` DATETIME NOT NULL DEFAULT NOW(),
-  `lockedIp` varchar(30) NOT NULL DEFAULT '0.0.0.0',
+  `lockedIp` varchar(45) NOT NULL DEFAULT '0.0.0.0',
   `failed_logins` int(11) unsigned NOT NULL DEFAULT '0',
   `locked` tinyint(3) unsigned NOT NULL DEFAULT '0',
   `last_module` char(32) DEFAULT '',
@@ -62,6 +62,9 @@ CREATE TABLE `account` (
   `platform` VARCHAR(4) NOT NULL DEFAULT '0',
   `token` text,
   `flags` INT UNSIGNED NOT NULL DEFAULT '0',
+  `lock_country` varchar(2) NOT NULL DEFAULT '00' COMMENT 'ISO country code the account may log in from, 00 = any',
+  `totp_failures` int(11) unsigned NOT NULL DEFAULT '0' COMMENT 'Wrong authenticator codes since the last correct one',
+  `totp_locked_until` bigint(40) unsigned NOT NULL DEFAULT '0' COMMENT 'Authenticator codes refused until this time',
   PRIMARY KEY (`id`),
   UNIQUE KEY `idx_username` (`username`),
   KEY `idx_gmlevel` (`gmlevel`)
@@ -74,10 +77,10 @@ CREATE TABLE `account` (
 LOCK TABLES `account` WRITE;
 /*!40000 ALTER TABLE `account` DISABLE KEYS */;
 INSERT INTO `account` VALUES
-(1,'ADMINISTRATOR',3,'','312B99EEF1C0196BB73B79D114CE161C5D089319E6EF54FAA6117DAB8B672C14','8EB5DE915AA3D805FA7099CF61C0BB8A77990EA869078A0C5B9EEE55828F4505','','2006-04-25 10:18:56','127.0.0.1',0,0,'',0,0,0,0,'',0,0,NULL,0),
-(2,'GAMEMASTER',2,'','681F5A7D4DE26DBFD3060EE37E03B79FD154875FB18F44DBF843963F193FC1AC','8873CD861DEFBF124232D6A29E4884E34C73385304A8AC44175976B1003DCFD7','','2006-04-25 10:18:56','127.0.0.1',0,0,'',0,0,0,0,'',0,0,NULL,0),
-(3,'MODERATOR',1,'','2CA85C9853E44A6DCE09FC92EBDE57EF20975281EB7604326E25751AF8576859','AD68B088D7BCE5E4B734495A7A956F1D5DD1BAB61FB0FEE46C737D93EC166DF5','','2006-04-25 10:19:35','127.0.0.1',0,0,'',0,0,0,0,'',0,0,NULL,0),
-(4,'PLAYER',0,'','3738EC7E7C731FD431C716990C6D97CA5C1D50EF0DA7DE9819076DE1D03AA891','EBA23AF194D89B8061CA7FEBA06D336B1C38D8FBDABA76F2C51D45141362D881','','2006-04-25 10:19:35','127.0.0.1',0,0,'',0,0,0,0,'',0,0,NULL,0);
+(1,'ADMINISTRATOR',3,'','312B99EEF1C0196BB73B79D114CE161C5D089319E6EF54FAA6117DAB8B672C14','8EB5DE915AA3D805FA7099CF61C0BB8A77990EA869078A0C5B9EEE55828F4505','','2006-04-25 10:18:56','127.0.0.1',0,0,'',0,0,0,0,'',0,0,NULL,0,'00',0,0),
+(2,'GAMEMASTER',2,'','681F5A7D4DE26DBFD3060EE37E03B79FD154875FB18F44DBF843963F193FC1AC','8873CD861DEFBF124232D6A29E4884E34C73385304A8AC44175976B1003DCFD7','','2006-04-25 10:18:56','127.0.0.1',0,0,'',0,0,0,0,'',0,0,NULL,0,'00',0,0),
+(3,'MODERATOR',1,'','2CA85C9853E44A6DCE09FC92EBDE57EF20975281EB7604326E25751AF8576859','AD68B088D7BCE5E4B734495A7A956F1D5DD1BAB61FB0FEE46C737D93EC166DF5','','2006-04-25 10:19:35','127.0.0.1',0,0,'',0,0,0,0,'',0,0,NULL,0,'00',0,0),
+(4,'PLAYER',0,'','3738EC7E7C731FD431C716990C6D97CA5C1D50EF0DA7DE9819076DE1D03AA891','EBA23AF194D89B8061CA7FEBA06D336B1C38D8FBDABA76F2C51D45141362D881','','2006-04-25 10:19:35','127.0.0.1',0,0,'',0,0,0,0,'',0,0,NULL,0,'00',0,0);
 /*!40000 ALTER TABLE `account` ENABLE KEYS */;
 UNLOCK TABLES;
 
@@ -112,7 +115,7 @@ DROP TABLE IF EXISTS `account_logons`;
 CREATE TABLE `account_logons` (
 `id` INT PRIMARY KEY NOT NULL AUTO_INCREMENT,
 `accountId` INT UNSIGNED NOT NULL,
-`ip` varchar(30) NOT NULL,
+`ip` varchar(45) NOT NULL,
 `loginTime` timestamp NOT NULL,
 `loginSource` INT UNSIGNED NOT NULL
 );
@@ -130,7 +133,7 @@ PRIMARY KEY(referrer, referred)
 
 DROP TABLE IF EXISTS `ip_banned`;
 CREATE TABLE `ip_banned` (
-  `ip` varchar(32) NOT NULL DEFAULT '0.0.0.0',
+  `ip` varchar(45) NOT NULL DEFAULT '0.0.0.0',
   `banned_at` bigint(40) NOT NULL,
   `expires_at` bigint(40) NOT NULL,
   `banned_by` varchar(50) NOT NULL DEFAULT '[Console]',
@@ -177,7 +180,7 @@ DROP TABLE IF EXISTS `realmlist`;
 CREATE TABLE `realmlist` (
   `id` int(11) unsigned NOT NULL AUTO_INCREMENT,
   `name` varchar(32) NOT NULL DEFAULT '',
-  `address` varchar(32) NOT NULL DEFAULT '127.0.0.1',
+  `address` varchar(255) NOT NULL DEFAULT '127.0.0.1' COMMENT 'IP address or host name',
   `port` int(11) NOT NULL DEFAULT '8085',
   `icon` tinyint(3) unsigned NOT NULL DEFAULT '0',
   `realmflags` tinyint(3) unsigned NOT NULL DEFAULT '2' COMMENT 'Supported masks: 0x1 (invalid, not show in realm list), 0x2 (offline, set by mangosd), 0x4 (show version and build), 0x20 (new players), 0x40 (recommended)',
@@ -200,6 +203,47 @@ INSERT INTO `realmlist` VALUES
 /*!40000 ALTER TABLE `realmlist` ENABLE KEYS */;
 UNLOCK TABLES;
 
+--
+-- Table structure for table `realmbuilds`
+--
+
+DROP TABLE IF EXISTS `realmbuilds`;
+CREATE TABLE `realmbuilds` (
+  `realmid` int(11) unsigned NOT NULL DEFAULT '0' COMMENT '0 = accepted on every realm',
+  `build` smallint(5) unsigned NOT NULL,
+  `major_version` tinyint(3) unsigned NOT NULL DEFAULT '0' COMMENT '0 = use the version known for the build',
+  `minor_version` tinyint(3) unsigned NOT NULL DEFAULT '0',
+  `bugfix_version` tinyint(3) unsigned NOT NULL DEFAULT '0',
+  `hotfix_version` char(1) NOT NULL DEFAULT '',
+  `windows_hash` varchar(40) NOT NULL DEFAULT '' COMMENT 'Client version hash, 40 hex digits; empty = not checked',
+  `mac_hash` varchar(40) NOT NULL DEFAULT '',
+  PRIMARY KEY (`realmid`,`build`)
+) ENGINE=MyISAM DEFAULT CHARSET=utf8 ROW_FORMAT=DYNAMIC COMMENT='Client builds per realm';
+
+--
+-- Table structure for table `realmlist_locale`
+--
+
+DROP TABLE IF EXISTS `realmlist_locale`;
+CREATE TABLE `realmlist_locale` (
+  `realmid` int(11) unsigned NOT NULL,
+  `locale` char(4) NOT NULL COMMENT 'Client locale, e.g. deDE or frFR',
+  `name` varchar(32) NOT NULL DEFAULT '',
+  PRIMARY KEY (`realmid`,`locale`)
+) ENGINE=MyISAM DEFAULT CHARSET=utf8 ROW_FORMAT=DYNAMIC COMMENT='Translated realm names';
+
+--
+-- Table structure for table `realmlist_local`
+--
+
+DROP TABLE IF EXISTS `realmlist_local`;
+CREATE TABLE `realmlist_local` (
+  `realmid` int(11) unsigned NOT NULL,
+  `address` varchar(255) NOT NULL DEFAULT '' COMMENT 'IP address or host name given to clients from LocalNetworks',
+  `port` int(11) NOT NULL DEFAULT '0' COMMENT '0 = realmlist.port',
+  PRIMARY KEY (`realmid`)
+) ENGINE=MyISAM DEFAULT CHARSET=utf8 ROW_FORMAT=DYNAMIC COMMENT='LAN realm addresses';
+
 --
 -- Table structure for table `uptime`
 --
@@ -234,7 +278,7 @@ CREATE TABLE `system_fingerprint_usage` (
   `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
   `fingerprint` int(10) unsigned NOT NULL,
   `account` int(10) unsigned NOT NULL,
-  `ip` varchar(16) NOT NULL,
+  `ip` varchar(45) NOT NULL,
   `realm` int(10) unsigned NOT NULL,
   `time` datetime DEFAULT NULL,
   `architecture` varchar(16) DEFAULT NULL,
@@ -492,6 +536,144 @@ insert  into `antispam_blacklist`(`string`) values
 ('[www.y2IgoId.com>10g=2$/Power] L');
 
 
+--
+-- Table structure for table `account_gmlevel_audit`
+--
+
+DROP TABLE IF EXISTS `account_gmlevel_audit`;
+CREATE TABLE `account_gmlevel_audit` (
+  `id` int(11) unsigned NOT NULL AUTO_INCREMENT,
+  `account_id` int(11) unsigned NOT NULL,
+  `old_gmlevel` tinyint(3) unsigned NOT NULL DEFAULT '0',
+  `new_gmlevel` tinyint(3) unsigned NOT NULL DEFAULT '0',
+  `changed_by` varchar(50) NOT NULL,
+  `confirmed_by` varchar(50) NOT NULL DEFAULT '',
+  `source` varchar(8) NOT NULL DEFAULT '',
+  `changed_at` bigint(40) NOT NULL DEFAULT '0',
+  PRIMARY KEY (`id`),
+  KEY `idx_account` (`account_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='Audit trail of gmlevel changes';
+
+--
+-- Table structure for table `account_gmlevel_pending`
+--
+
+DROP TABLE IF EXISTS `account_gmlevel_pending`;
+CREATE TABLE `account_gmlevel_pending` (
+  `token` varchar(32) NOT NULL,
+  `account_id` int(11) unsigned NOT NULL,
+  `gmlevel` tinyint(3) unsigned NOT NULL DEFAULT '0',
+  `requested_by` varchar(50) NOT NULL,
+  `source` varchar(8) NOT NULL DEFAULT '',
+  `requested_at` bigint(40) NOT NULL DEFAULT '0',
+  `expires_at` bigint(40) NOT NULL DEFAULT '0',
+  PRIMARY KEY (`token`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='Administrator elevations awaiting second confirmation';
+
+--
+-- Table structure for table `account_matrix_card`
+--
+
+DROP TABLE IF EXISTS `account_matrix_card`;
+CREATE TABLE `account_matrix_card` (
+  `account_id` int(11) unsigned NOT NULL,
+  `width` tinyint(3) unsigned NOT NULL DEFAULT '8',
+  `height` tinyint(3) unsigned NOT NULL DEFAULT '10',
+  `digit_count` tinyint(3) unsigned NOT NULL DEFAULT '2',
+  `digits` text NOT NULL COMMENT 'All digits row by row, digit_count per cell',
+  PRIMARY KEY (`account_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='Matrix cards (security flag 0x02)';
+
+--
+-- Table structure for table `account_playtime`
+--
+
+DROP TABLE IF EXISTS `account_playtime`;
+CREATE TABLE `account_playtime` (
+  `account_id` int(11) unsigned NOT NULL,
+  `allowed_start` time NOT NULL DEFAULT '00:00:00' COMMENT 'Server local time',
+  `allowed_end` time NOT NULL DEFAULT '00:00:00' COMMENT 'Before allowed_start = next day; equal = whole day',
+  PRIMARY KEY (`account_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='Allowed login hours (parental control)';
+
+--
+-- Table structure for table `auth_log`
+--
+
+DROP TABLE IF EXISTS `auth_log`;
+CREATE TABLE `auth_log` (
+  `id` bigint(20) unsigned NOT NULL AUTO_INCREMENT,
+  `time` bigint(40) NOT NULL DEFAULT '0',
+  `stage` varchar(10) NOT NULL DEFAULT '',
+  `account` varchar(32) NOT NULL DEFAULT '',
+  `ip` varchar(45) NOT NULL DEFAULT '',
+  `build` smallint(5) unsigned NOT NULL DEFAULT '0',
+  `result` tinyint(3) unsigned NOT NULL DEFAULT '0',
+  `reason` varchar(32) NOT NULL DEFAULT '',
+  PRIMARY KEY (`id`),
+  KEY `idx_time` (`time`),
+  KEY `idx_account` (`account`),
+  KEY `idx_ip` (`ip`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='Authentication decisions';
+
+--
+-- Table structure for table `client_stats`
+--
+
+DROP TABLE IF EXISTS `client_stats`;
+CREATE TABLE `client_stats` (
+  `day` date NOT NULL,
+  `build` smallint(5) unsigned NOT NULL DEFAULT '0',
+  `os` varchar(4) NOT NULL DEFAULT '',
+  `platform` varchar(4) NOT NULL DEFAULT '',
+  `locale` varchar(4) NOT NULL DEFAULT '',
+  `logins` int(10) unsigned NOT NULL DEFAULT '0',
+  PRIMARY KEY (`day`,`build`,`os`,`platform`,`locale`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='Daily logins per client build, OS, platform and locale';
+
+--
+-- Table structure for table `realmd_counters`
+--
+
+DROP TABLE IF EXISTS `realmd_counters`;
+CREATE TABLE `realmd_counters` (
+  `scope` varchar(16) NOT NULL DEFAULT '',
+  `name` varchar(45) NOT NULL DEFAULT '',
+  `count` int(10) unsigned NOT NULL DEFAULT '0',
+  `first_time` bigint(20) NOT NULL DEFAULT '0',
+  `last_time` bigint(20) NOT NULL DEFAULT '0',
+  PRIMARY KEY (`scope`,`name`),
+  KEY `idx_last_time` (`last_time`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='Tarpit and rate limit counters shared by realmd instances';
+
+--
+-- Table structure for table `account_web_token`
+--
+
+DROP TABLE IF EXISTS `account_web_token`;
+CREATE TABLE `account_web_token` (
+  `token` varchar(128) NOT NULL,
+  `account_id` int(10) unsigned NOT NULL,
+  `ip` varchar(45) NOT NULL DEFAULT '',
+  `issued_at` bigint(20) NOT NULL DEFAULT '0',
+  `expires_at` bigint(20) NOT NULL DEFAULT '0',
+  PRIMARY KEY (`token`),
+  KEY `idx_account` (`account_id`),
+  KEY `idx_expires_at` (`expires_at`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='Web sign-in tokens issued at login';
+
+--
+-- Table structure for table `account_access`
+--
+
+DROP TABLE IF EXISTS `account_access`;
+CREATE TABLE `account_access` (
+  `id` int(10) unsigned NOT NULL,
+  `gmlevel` tinyint(3) unsigned NOT NULL,
+  `RealmID` int(11) NOT NULL DEFAULT '-1',
+  PRIMARY KEY (`id`,`RealmID`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8 COMMENT='GM levels of accounts on single realms';
+
 /*!40101 SET SQL_MODE=@OLD_SQL_MODE */;
 /*!40014 SET FOREIGN_KEY_CHECKS=@OLD_FOREIGN_KEY_CHECKS */;
 /*!40014 SET UNIQUE_CHECKS=@OLD_UNIQUE_CHECKS */;